@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS users (
+    id SERIAL PRIMARY KEY,
+    first_name TEXT NOT NULL,
+    last_name TEXT NOT NULL,
+    phone_number TEXT NOT NULL,
+    telegram_id BIGINT NOT NULL UNIQUE,
+    client_code TEXT NOT NULL UNIQUE
+);
@@ -0,0 +1,9 @@
+CREATE TABLE parcels (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    track_code TEXT NOT NULL,
+    label TEXT,
+    arrived BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, track_code)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE users ADD COLUMN username TEXT;
+ALTER TABLE users ADD COLUMN display_name TEXT;
+
+CREATE INDEX users_username_idx ON users (lower(username));
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN text_menu BOOLEAN NOT NULL DEFAULT FALSE;
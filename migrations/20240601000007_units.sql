@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN units TEXT NOT NULL DEFAULT 'metric';
@@ -0,0 +1,9 @@
+CREATE TABLE tickets (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    closed_at TIMESTAMPTZ
+);
+
+CREATE INDEX tickets_open_idx ON tickets (created_at) WHERE closed_at IS NULL;
@@ -0,0 +1,6 @@
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE users ADD COLUMN merged_into INTEGER REFERENCES users (id);
+
+-- A merged account keeps its telegram id, so only active accounts must be unique.
+ALTER TABLE users DROP CONSTRAINT users_telegram_id_key;
+CREATE UNIQUE INDEX users_telegram_id_active_idx ON users (telegram_id) WHERE deleted_at IS NULL;
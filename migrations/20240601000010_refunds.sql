@@ -0,0 +1,8 @@
+CREATE TABLE refunds (
+    id SERIAL PRIMARY KEY,
+    parcel_id INTEGER NOT NULL UNIQUE REFERENCES parcels (id) ON DELETE CASCADE,
+    ticket_id INTEGER NOT NULL REFERENCES tickets (id),
+    reason TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'requested',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+CREATE TABLE tutorials (
+    slug TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    text TEXT NOT NULL DEFAULT '',
+    position INTEGER NOT NULL DEFAULT 0
+);
+
+-- Texts used to come from HELP_<SLUG>, they are filled in from there on startup.
+INSERT INTO tutorials (slug, title, position) VALUES
+    ('1688', '1688', 1),
+    ('pinduoduo', 'Pinduoduo', 2),
+    ('poizon', 'Poizon', 3),
+    ('taobao', 'TaoBao', 4);
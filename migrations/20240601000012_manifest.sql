@@ -0,0 +1,6 @@
+ALTER TABLE parcels ADD COLUMN batch_code TEXT;
+ALTER TABLE parcels ADD COLUMN weight_kg REAL;
+ALTER TABLE parcels ADD COLUMN declared_value REAL;
+ALTER TABLE parcels ADD COLUMN declared_description TEXT;
+
+CREATE INDEX parcels_batch_code_idx ON parcels (batch_code);
@@ -0,0 +1,6 @@
+CREATE TABLE weight_disputes (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets (id),
+    parcel_id INTEGER NOT NULL REFERENCES parcels (id) ON DELETE CASCADE,
+    photo_ids TEXT[] NOT NULL DEFAULT '{}'
+);
@@ -0,0 +1,5 @@
+CREATE TABLE dialogues (
+    chat_id BIGINT PRIMARY KEY,
+    state TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,9 @@
+ALTER TABLE users ADD COLUMN city TEXT;
+
+CREATE TABLE broadcasts (
+    id SERIAL PRIMARY KEY,
+    filters TEXT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    sent_at TIMESTAMPTZ
+);
@@ -0,0 +1,9 @@
+CREATE TABLE staff_notes (
+    id SERIAL PRIMARY KEY,
+    author_id BIGINT NOT NULL,
+    author TEXT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX staff_notes_created_at_idx ON staff_notes (created_at);
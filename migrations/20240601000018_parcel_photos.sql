@@ -0,0 +1,3 @@
+ALTER TABLE parcels ADD COLUMN photo_id TEXT;
+
+ALTER TABLE outbox ADD COLUMN photo_id TEXT;
@@ -0,0 +1,14 @@
+CREATE TABLE canned_responses (
+    slug TEXT PRIMARY KEY,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE canned_response_uses (
+    id SERIAL PRIMARY KEY,
+    slug TEXT NOT NULL REFERENCES canned_responses (slug) ON DELETE CASCADE,
+    ticket_id INT NOT NULL,
+    operator_id BIGINT NOT NULL,
+    operator TEXT NOT NULL,
+    used_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,5 @@
+CREATE TABLE tariffs (
+    min_density REAL PRIMARY KEY,
+    price_per_kg REAL NOT NULL,
+    min_charge REAL NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE parcels ADD COLUMN arrived_at TIMESTAMPTZ;
+ALTER TABLE parcels ADD COLUMN delivered_at TIMESTAMPTZ;
+
+CREATE INDEX parcels_delivered_at_idx ON parcels (delivered_at) WHERE delivered_at IS NOT NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE parcels ADD COLUMN item_url TEXT;
+ALTER TABLE parcels ADD COLUMN item_title TEXT;
+ALTER TABLE parcels ADD COLUMN item_price TEXT;
+ALTER TABLE parcels ADD COLUMN item_image TEXT;
@@ -0,0 +1,25 @@
+CREATE TABLE event_counts (
+    month DATE NOT NULL,
+    kind TEXT NOT NULL,
+    events BIGINT NOT NULL,
+    users BIGINT NOT NULL,
+    PRIMARY KEY (month, kind)
+);
+
+CREATE TABLE tickets_archive (
+    id INTEGER PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    text TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    closed_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE retention_runs (
+    id SERIAL PRIMARY KEY,
+    dry_run BOOLEAN NOT NULL,
+    events BIGINT NOT NULL,
+    tickets BIGINT NOT NULL,
+    parcels BIGINT NOT NULL,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+CREATE TABLE quotes (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    width REAL NOT NULL,
+    length REAL NOT NULL,
+    height REAL NOT NULL,
+    weight_kg REAL NOT NULL,
+    price REAL,
+    tariff_version INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX quotes_telegram_id_idx ON quotes (telegram_id, created_at);
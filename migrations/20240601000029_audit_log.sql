@@ -0,0 +1,11 @@
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY,
+    admin_id BIGINT NOT NULL,
+    admin TEXT NOT NULL,
+    action TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    correlation_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
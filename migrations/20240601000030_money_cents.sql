@@ -0,0 +1,10 @@
+ALTER TABLE tariffs ALTER COLUMN price_per_kg TYPE BIGINT USING round(price_per_kg::numeric * 100);
+ALTER TABLE tariffs RENAME COLUMN price_per_kg TO price_per_kg_cents;
+ALTER TABLE tariffs ALTER COLUMN min_charge TYPE BIGINT USING round(min_charge::numeric * 100);
+ALTER TABLE tariffs RENAME COLUMN min_charge TO min_charge_cents;
+
+ALTER TABLE quotes ALTER COLUMN price TYPE BIGINT USING round(price::numeric * 100);
+ALTER TABLE quotes RENAME COLUMN price TO price_cents;
+
+ALTER TABLE parcels ALTER COLUMN declared_value TYPE BIGINT USING round(declared_value::numeric * 100);
+ALTER TABLE parcels RENAME COLUMN declared_value TO declared_value_cents;
@@ -0,0 +1 @@
+ALTER TABLE parcels ADD COLUMN shelf TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN reminders BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ADD COLUMN reengaged_at TIMESTAMPTZ;
@@ -0,0 +1,10 @@
+CREATE TABLE shelves (
+    code TEXT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO shelves (code) SELECT DISTINCT shelf FROM parcels WHERE shelf IS NOT NULL;
+
+ALTER TABLE parcels ADD CONSTRAINT parcels_shelf_fkey FOREIGN KEY (shelf) REFERENCES shelves (code);
+
+CREATE INDEX parcels_shelf_idx ON parcels (shelf) WHERE delivered_at IS NULL;
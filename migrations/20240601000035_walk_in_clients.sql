@@ -0,0 +1,2 @@
+-- Walk-in clients are registered by an operator and have no Telegram account.
+ALTER TABLE users ALTER COLUMN telegram_id DROP NOT NULL;
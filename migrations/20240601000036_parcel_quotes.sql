@@ -0,0 +1 @@
+ALTER TABLE parcels ADD COLUMN quote_id INTEGER REFERENCES quotes (id);
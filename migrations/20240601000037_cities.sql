@@ -0,0 +1,17 @@
+CREATE TABLE cities (
+    name TEXT PRIMARY KEY,
+    eta_min_days INTEGER NOT NULL,
+    eta_max_days INTEGER NOT NULL,
+    pickup_point TEXT NOT NULL,
+    opened_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX cities_name_idx ON cities (lower(name));
+
+CREATE TABLE city_tariffs (
+    city TEXT NOT NULL REFERENCES cities (name),
+    min_density REAL NOT NULL,
+    price_per_kg_cents BIGINT NOT NULL,
+    min_charge_cents BIGINT NOT NULL,
+    PRIMARY KEY (city, min_density)
+);
@@ -0,0 +1,11 @@
+ALTER TABLE parcels ADD COLUMN invoice_cents BIGINT, ADD COLUMN paid_cents BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE payments (
+    id SERIAL PRIMARY KEY,
+    parcel_id INTEGER NOT NULL REFERENCES parcels (id) ON DELETE CASCADE,
+    amount_cents BIGINT NOT NULL,
+    -- Telegram's charge id, so a repeated successful_payment update is not counted twice.
+    charge_id TEXT UNIQUE,
+    recorded_by BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
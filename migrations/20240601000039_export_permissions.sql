@@ -0,0 +1,15 @@
+CREATE TABLE roles (
+    telegram_id BIGINT NOT NULL,
+    permission TEXT NOT NULL,
+    granted_by BIGINT NOT NULL,
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (telegram_id, permission)
+);
+
+CREATE TABLE exports (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    row_count INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,8 @@
+-- Tracking services tried in order of position, the next one when a service fails or does not know
+-- the track code. While the table is empty only VENDOR_BASE_URL is asked.
+CREATE TABLE providers (
+    name TEXT PRIMARY KEY,
+    base_url TEXT NOT NULL,
+    position INTEGER NOT NULL UNIQUE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE
+);
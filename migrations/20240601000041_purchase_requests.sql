@@ -0,0 +1,15 @@
+CREATE TABLE purchase_requests (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    marketplace TEXT NOT NULL,
+    link TEXT NOT NULL,
+    options TEXT,
+    budget_cents BIGINT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'new',
+    quote_cents BIGINT,
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX purchase_requests_open_idx ON purchase_requests (created_at) WHERE status IN ('new', 'quoted');
@@ -0,0 +1,9 @@
+CREATE TABLE box_presets (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    width REAL NOT NULL,
+    length REAL NOT NULL,
+    height REAL NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (telegram_id, width, length, height)
+);
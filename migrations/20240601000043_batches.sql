@@ -0,0 +1,25 @@
+CREATE TABLE batches (
+    code TEXT PRIMARY KEY,
+    status TEXT NOT NULL DEFAULT 'forming',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Every status a batch went through, in order.
+CREATE TABLE batch_events (
+    id SERIAL PRIMARY KEY,
+    batch_code TEXT NOT NULL REFERENCES batches (code) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX batch_events_batch_code_idx ON batch_events (batch_code, created_at);
+
+-- Batches handed over before statuses existed are counted as arrived.
+INSERT INTO batches (code, status)
+SELECT batch_code, CASE WHEN bool_and(delivered_at IS NOT NULL) THEN 'arrived' ELSE 'forming' END
+FROM parcels
+WHERE batch_code IS NOT NULL
+GROUP BY batch_code;
+
+INSERT INTO batch_events (batch_code, status) SELECT code, status FROM batches;
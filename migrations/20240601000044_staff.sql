@@ -0,0 +1,15 @@
+-- Who may use admin commands, ADMIN_IDS only fill it while it is empty.
+CREATE TABLE staff (
+    telegram_id BIGINT PRIMARY KEY,
+    invited_by BIGINT,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE staff_invites (
+    code TEXT PRIMARY KEY,
+    created_by BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    redeemed_by BIGINT,
+    redeemed_at TIMESTAMPTZ
+);
@@ -0,0 +1,9 @@
+-- Idempotency keys of partner webhook posts, a retried post gets the first answer back.
+CREATE TABLE webhook_keys (
+    partner_id TEXT NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    -- NULL while the first post is being applied.
+    status SMALLINT,
+    claimed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (partner_id, idempotency_key)
+);
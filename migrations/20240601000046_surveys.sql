@@ -0,0 +1,28 @@
+-- Short surveys admins send to clients, a question without options takes a free-text answer.
+CREATE TABLE surveys (
+    id SERIAL PRIMARY KEY,
+    title TEXT NOT NULL,
+    created_by BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    sent_at TIMESTAMPTZ
+);
+
+CREATE TABLE survey_questions (
+    survey_id INTEGER NOT NULL REFERENCES surveys (id) ON DELETE CASCADE,
+    position SMALLINT NOT NULL,
+    text TEXT NOT NULL,
+    options TEXT[] NOT NULL DEFAULT '{}',
+    PRIMARY KEY (survey_id, position)
+);
+
+-- One answer per client and question, the number of the option or the text typed.
+CREATE TABLE survey_answers (
+    survey_id INTEGER NOT NULL,
+    position SMALLINT NOT NULL,
+    telegram_id BIGINT NOT NULL,
+    option SMALLINT,
+    text TEXT,
+    answered_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (survey_id, position, telegram_id),
+    FOREIGN KEY (survey_id, position) REFERENCES survey_questions (survey_id, position) ON DELETE CASCADE
+);
@@ -0,0 +1,9 @@
+-- Warehouse API answers the bot could not read, kept to see what changed in the API.
+CREATE TABLE vendor_errors (
+    id BIGSERIAL PRIMARY KEY,
+    source TEXT NOT NULL,
+    track_code TEXT NOT NULL,
+    error TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
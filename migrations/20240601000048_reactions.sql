@@ -0,0 +1,18 @@
+-- What a notice was about and the message it became, so that a reaction to it can be acted on.
+ALTER TABLE outbox ADD COLUMN subject TEXT;
+ALTER TABLE outbox ADD COLUMN track_code TEXT;
+ALTER TABLE outbox ADD COLUMN message_id INTEGER;
+
+CREATE INDEX outbox_message_idx ON outbox (telegram_id, message_id) WHERE message_id IS NOT NULL;
+
+-- Set when the client puts 👍 on the arrival notice.
+ALTER TABLE parcels ADD COLUMN arrival_acknowledged_at TIMESTAMPTZ;
+
+-- Stars a client gave the delivery of a parcel, the last rating counts.
+CREATE TABLE ratings (
+    telegram_id BIGINT NOT NULL,
+    track_code TEXT NOT NULL,
+    stars SMALLINT NOT NULL CHECK (stars BETWEEN 1 AND 5),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (telegram_id, track_code)
+);
@@ -0,0 +1,10 @@
+-- Clients who asked for a summary of their shipments at the start of each month.
+ALTER TABLE users ADD COLUMN monthly_summary BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Months a summary went into the outbox for, the job runs all day on the 1st and sends it once.
+CREATE TABLE monthly_summaries (
+    telegram_id BIGINT NOT NULL,
+    month DATE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (telegram_id, month)
+);
@@ -0,0 +1,12 @@
+-- An operator's change to a billed price, a discount or a penalty, always with a reason code.
+CREATE TABLE price_overrides (
+    id SERIAL PRIMARY KEY,
+    parcel_id INTEGER NOT NULL REFERENCES parcels (id) ON DELETE CASCADE,
+    original_cents BIGINT NOT NULL,
+    final_cents BIGINT NOT NULL,
+    reason TEXT NOT NULL,
+    operator_id BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX price_overrides_created_at_idx ON price_overrides (created_at);
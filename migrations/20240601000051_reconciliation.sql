@@ -0,0 +1,19 @@
+CREATE TABLE reconciliation_runs (
+    id SERIAL PRIMARY KEY,
+    checked INTEGER NOT NULL,
+    failed INTEGER NOT NULL,
+    repaired INTEGER NOT NULL,
+    ran_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Parcels whose saved status did not match the warehouse, `vendor_status` is the line the warehouse gave.
+CREATE TABLE reconciliation_discrepancies (
+    id SERIAL PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES reconciliation_runs(id) ON DELETE CASCADE,
+    track_code TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    vendor_status TEXT NOT NULL,
+    repaired BOOLEAN NOT NULL
+);
+
+CREATE INDEX reconciliation_discrepancies_run_id_idx ON reconciliation_discrepancies (run_id);
@@ -0,0 +1,8 @@
+-- Buttons under a tutorial: the marketplace app and its website, both optional.
+ALTER TABLE tutorials ADD COLUMN app_url TEXT;
+ALTER TABLE tutorials ADD COLUMN web_url TEXT;
+
+UPDATE tutorials SET web_url = 'https://www.1688.com' WHERE slug = '1688';
+UPDATE tutorials SET web_url = 'https://mobile.yangkeduo.com' WHERE slug = 'pinduoduo';
+UPDATE tutorials SET web_url = 'https://www.dewu.com' WHERE slug = 'poizon';
+UPDATE tutorials SET web_url = 'https://www.taobao.com' WHERE slug = 'taobao';
@@ -0,0 +1,18 @@
+-- Files clients sent with a ticket, they go with the ticket when retention archives it.
+CREATE TABLE attachments (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    file_id TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX attachments_ticket_id_idx ON attachments (ticket_id);
+
+-- Dispute photos move over, their sizes were not kept.
+INSERT INTO attachments (ticket_id, kind, file_id, mime_type, size_bytes)
+SELECT ticket_id, 'photo', unnest(photo_ids), 'image/jpeg', 0 FROM weight_disputes;
+
+ALTER TABLE weight_disputes DROP COLUMN photo_ids;
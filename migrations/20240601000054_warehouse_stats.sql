@@ -0,0 +1,19 @@
+-- When an operator first scanned the parcel at the warehouse.
+ALTER TABLE parcels ADD COLUMN scanned_at TIMESTAMPTZ;
+
+-- Daily intake in Bishkek days, a parcel counts on the day it was scanned or, unscanned, on the day it arrived.
+-- The stats job rebuilds the last days from parcels.
+CREATE TABLE warehouse_stats (
+    day DATE PRIMARY KEY,
+    parcels INTEGER NOT NULL,
+    scanned INTEGER NOT NULL,
+    weight_kg REAL NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Scans were not timed before, earlier days only have arrivals.
+INSERT INTO warehouse_stats (day, parcels, scanned, weight_kg)
+SELECT (arrived_at AT TIME ZONE 'Asia/Bishkek')::date, count(*), 0, COALESCE(sum(weight_kg), 0)
+FROM parcels
+WHERE arrived_at IS NOT NULL
+GROUP BY 1;
@@ -0,0 +1,6 @@
+-- Track codes from t.me/<bot>?start=track_<code> links, looked up once the user has registered.
+CREATE TABLE start_track_codes (
+    telegram_id BIGINT PRIMARY KEY,
+    track_code TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
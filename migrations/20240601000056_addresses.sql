@@ -0,0 +1,25 @@
+-- Home addresses users keep for courier delivery.
+CREATE TABLE addresses (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    city TEXT NOT NULL,
+    street TEXT NOT NULL,
+    entrance TEXT,
+    comment TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX addresses_user_id_idx ON addresses (user_id);
+
+-- Parcels operators sent out with a courier, `address` is the address as it was when the parcel was sent.
+CREATE TABLE deliveries (
+    id SERIAL PRIMARY KEY,
+    parcel_id INTEGER NOT NULL REFERENCES parcels (id) ON DELETE CASCADE,
+    address TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- A parcel is on its way with one courier at a time.
+CREATE UNIQUE INDEX deliveries_parcel_id_idx ON deliveries (parcel_id) WHERE status <> 'delivered';
@@ -0,0 +1,14 @@
+-- People who take parcels from the courier queue, they see their tasks with /tasks.
+CREATE TABLE couriers (
+    telegram_id BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    added_by BIGINT NOT NULL,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+ALTER TABLE deliveries
+    ADD COLUMN courier_id BIGINT REFERENCES couriers (telegram_id) ON DELETE SET NULL,
+    ADD COLUMN delivered_at TIMESTAMPTZ,
+    ADD COLUMN photo_id TEXT;
+
+CREATE INDEX deliveries_courier_id_idx ON deliveries (courier_id) WHERE status = 'assigned';
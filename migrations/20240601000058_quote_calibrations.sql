@@ -0,0 +1,16 @@
+-- Billed parcels whose attached quote was further off than the calibration threshold.
+-- The quote side is copied, so a later tariff change does not rewrite what the client was told.
+CREATE TABLE quote_calibrations (
+    id SERIAL PRIMARY KEY,
+    parcel_id INTEGER NOT NULL UNIQUE REFERENCES parcels (id) ON DELETE CASCADE,
+    quote_id INTEGER NOT NULL REFERENCES quotes (id),
+    tariff_version INTEGER NOT NULL,
+    quoted_cents BIGINT NOT NULL,
+    billed_cents BIGINT NOT NULL,
+    quoted_weight_kg REAL NOT NULL,
+    actual_weight_kg REAL,
+    density REAL NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX quote_calibrations_created_at_idx ON quote_calibrations (created_at);
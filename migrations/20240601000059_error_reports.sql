@@ -0,0 +1,12 @@
+-- A handler that failed, with what is needed to replay it: the dialogue state and the input, both without personal data.
+CREATE TABLE error_reports (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT,
+    correlation_id TEXT NOT NULL,
+    state TEXT,
+    input TEXT NOT NULL,
+    error TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX error_reports_created_at_idx ON error_reports (created_at);
@@ -0,0 +1,16 @@
+-- Someone registered from another Telegram account with the phone of an existing client.
+-- A phone confirmed by SMS moves the account right away, otherwise an operator decides.
+CREATE TABLE account_claims (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    telegram_id BIGINT NOT NULL,
+    username TEXT,
+    display_name TEXT,
+    approved BOOLEAN,
+    -- NULL for a claim confirmed by SMS.
+    decided_by BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    decided_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX account_claims_pending_idx ON account_claims (telegram_id) WHERE decided_at IS NULL;
@@ -0,0 +1,7 @@
+-- Users pick their city in the settings, the picker sends a city by id.
+ALTER TABLE cities ADD COLUMN id SERIAL UNIQUE;
+
+-- Until now the city was never asked, the latest delivery address is the best guess for users who have one.
+UPDATE users u SET city = a.city
+FROM (SELECT DISTINCT ON (user_id) user_id, city FROM addresses ORDER BY user_id, id DESC) a
+WHERE a.user_id = u.id AND u.city IS NULL;
@@ -0,0 +1,5 @@
+-- Phones are encrypted with a random nonce, a keyed hash lets a phone be looked up without decrypting every user.
+-- Filled by the bot at startup, since the key is not in the database.
+ALTER TABLE users ADD COLUMN phone_hash TEXT;
+
+CREATE INDEX users_phone_hash_idx ON users (phone_hash) WHERE deleted_at IS NULL;
@@ -0,0 +1,10 @@
+-- Verification codes sent, for the resend cooldown and the daily limits per phone and per Telegram account.
+CREATE TABLE sms_sends (
+    id SERIAL PRIMARY KEY,
+    telegram_id BIGINT NOT NULL,
+    phone_hash TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX sms_sends_phone_hash_idx ON sms_sends (phone_hash, sent_at);
+CREATE INDEX sms_sends_telegram_id_idx ON sms_sends (telegram_id, sent_at);
@@ -0,0 +1,10 @@
+-- Who a survey was sent to, only they may answer it.
+CREATE TABLE survey_recipients (
+    survey_id INTEGER NOT NULL REFERENCES surveys (id) ON DELETE CASCADE,
+    telegram_id BIGINT NOT NULL,
+    PRIMARY KEY (survey_id, telegram_id)
+);
+
+-- Surveys sent before recipients were kept: whoever has answered may finish.
+INSERT INTO survey_recipients (survey_id, telegram_id)
+SELECT DISTINCT survey_id, telegram_id FROM survey_answers;
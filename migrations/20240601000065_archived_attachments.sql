@@ -0,0 +1,2 @@
+-- Attachments outlive the ticket, an archived one keeps its id in tickets_archive and its files here.
+ALTER TABLE attachments DROP CONSTRAINT attachments_ticket_id_fkey;
@@ -0,0 +1,4 @@
+-- Client codes were numbered by counting users, two registrations at once got the same code.
+CREATE SEQUENCE client_code_numbers;
+
+SELECT setval('client_code_numbers', 200 + (SELECT COUNT(*) FROM users), false);
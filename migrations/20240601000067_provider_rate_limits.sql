@@ -0,0 +1,2 @@
+-- Every tracking service has its own request budget, fallbacks were asked as fast as the warehouse allowed.
+ALTER TABLE providers ADD COLUMN requests_per_second INTEGER NOT NULL DEFAULT 5 CHECK (requests_per_second > 0);
@@ -0,0 +1,148 @@
+use axum::{extract::{Path, State}, http::{HeaderMap, StatusCode}, routing::{get, post}, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{carrier::{self, Carrier}, config::Config, database::Db, models::{Units, User}, phone_policy::{self, PhoneDecision}, server, vendor};
+
+type ApiResult = Result<(StatusCode, Json<Value>), (StatusCode, Json<Value>)>;
+
+#[derive(Clone)]
+struct ApiState {
+    db: Db,
+    config: Config
+}
+
+/// A client signing up on the website, registered like a walk-in client without Telegram.
+#[derive(Deserialize)]
+struct NewClient {
+    first_name: String,
+    last_name: Option<String>,
+    phone_number: String
+}
+
+/// The tracking and tariffs the bot uses, for the company website and app, mounted only when API_KEYS is set.
+pub fn router(db: Db, config: Config) -> Router {
+    Router::new()
+        .route("/api/track/:code", get(track))
+        .route("/api/tariffs", get(tariffs))
+        .route("/api/clients", post(create_client))
+        .with_state(ApiState { db, config })
+}
+
+fn ok(body: Value) -> ApiResult {
+    Ok((StatusCode::OK, Json(body)))
+}
+
+fn error(status: StatusCode, message: &str) -> ApiResult {
+    Err((status, Json(json!({ "error": message }))))
+}
+
+fn authorize(headers: &HeaderMap, config: &Config) -> Result<(), (StatusCode, Json<Value>)> {
+    match server::authorized(headers, &config.api_keys) {
+        true => Ok(()),
+        false => {
+            log::warn!("Api: rejected a request without a valid key");
+
+            Err((StatusCode::UNAUTHORIZED, Json(json!({ "error": "unauthorized" }))))
+        }
+    }
+}
+
+/// The warehouse status of a track code, asked through the same chain of tracking services as the bot.
+async fn track(State(state): State<ApiState>, headers: HeaderMap, Path(code): Path<String>) -> ApiResult {
+    authorize(&headers, &state.config)?;
+
+    let track_code = match carrier::parse_track_code(&code) {
+        Some(track_code) => track_code,
+        None => return error(StatusCode::BAD_REQUEST, "invalid track code")
+    };
+
+    if let Some(carrier) = Carrier::detect(&track_code).filter(|carrier| !carrier.supported()) {
+        return ok(json!({ "track_code": track_code, "supported": false, "carrier": carrier.name() }));
+    }
+
+    let lookup = match vendor::lookup(&state.db, &vendor::chain(state.db.get_providers().await), &track_code).await {
+        Ok(lookup) => lookup,
+        Err(err) => {
+            log::error!("ERROR: Api could not track {}: {}", track_code, err);
+
+            return error(StatusCode::BAD_GATEWAY, "tracking services are unavailable");
+        }
+    };
+
+    let detail = state.config.status_translator.translate(&lookup.status.msg).await;
+
+    ok(json!({
+        "track_code": track_code,
+        "supported": true,
+        "at_warehouse": lookup.status.ready(),
+        "status": detail,
+        "source": lookup.source
+    }))
+}
+
+/// The price list by density bands, amounts in cents of USD.
+async fn tariffs(State(state): State<ApiState>, headers: HeaderMap) -> ApiResult {
+    authorize(&headers, &state.config)?;
+
+    let tariffs = state.db.get_tariffs().await;
+
+    ok(json!({
+        "version": state.db.get_tariff_version().await,
+        "tariffs": tariffs.iter().map(|tariff| json!({
+            "min_density": tariff.min_density,
+            "price_per_kg_cents": tariff.price_per_kg.cents(),
+            "min_charge_cents": tariff.min_charge.cents()
+        })).collect::<Vec<Value>>()
+    }))
+}
+
+/// Gives a website visitor a client code; numbers the phone policy would send to manual review are refused.
+///
+/// A phone that is registered already gets 409 without the code: the website does not verify phones, so handing
+/// the code out would tell anyone the client code of a number. A retried sign-up does not make a second client either.
+async fn create_client(State(state): State<ApiState>, headers: HeaderMap, Json(client): Json<NewClient>) -> ApiResult {
+    authorize(&headers, &state.config)?;
+
+    let first_name = client.first_name.trim();
+    let last_name = client.last_name.as_deref().map(str::trim).filter(|last_name| !last_name.is_empty());
+
+    if first_name.is_empty() || (state.config.require_last_name && last_name.is_none()) {
+        return error(StatusCode::UNPROCESSABLE_ENTITY, "first_name and last_name are required");
+    }
+
+    let phone_number = match phone_policy::normalize(&client.phone_number) {
+        Some(phone_number) => phone_number,
+        None => return error(StatusCode::UNPROCESSABLE_ENTITY, "invalid phone_number")
+    };
+
+    if !matches!(state.config.phone_policy.check(&phone_number), PhoneDecision::Allowed) {
+        return error(StatusCode::UNPROCESSABLE_ENTITY, "phone_number is not accepted");
+    }
+
+    let (client_code, created) = state.db.get_or_create_by_phone(User {
+        id: 0,
+        client_code: String::new(),
+        first_name: first_name.to_string(),
+        last_name: last_name.map(str::to_string),
+        phone_number,
+        telegram_id: None,
+        username: None,
+        display_name: None,
+        text_menu: false,
+        reminders: true,
+        monthly_summary: false,
+        units: Units::Metric,
+        city: None
+    }).await;
+
+    if !created {
+        log::info!("Api: phone already registered as {}", client_code);
+
+        return error(StatusCode::CONFLICT, "phone_number is already registered");
+    }
+
+    log::info!("Api: registered client {}", client_code);
+
+    Ok((StatusCode::CREATED, Json(json!({ "client_code": client_code }))))
+}
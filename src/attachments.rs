@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use teloxide::types::{Message, PhotoSize};
+
+use crate::config::env_or;
+
+// Pictures phones take and PDFs of invoices and receipts, anything else operators can not open on the spot.
+const ALLOWED_TYPES: &[&str] = &["image/jpeg", "image/png", "image/webp", "image/heic", "application/pdf"];
+
+// What Telegram makes of a photo is always a JPEG.
+const PHOTO_TYPE: &str = "image/jpeg";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AttachmentKind {
+    Photo,
+    Document
+}
+
+impl AttachmentKind {
+    pub const ALL: [AttachmentKind; 2] = [AttachmentKind::Photo, AttachmentKind::Document];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentKind::Photo => "photo",
+            AttachmentKind::Document => "document"
+        }
+    }
+}
+
+impl TryFrom<String> for AttachmentKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<AttachmentKind, String> {
+        AttachmentKind::ALL.into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("unknown attachment kind {}", value))
+    }
+}
+
+/// A photo or file a client sent with a ticket, operators get it by `file_id`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    pub file_id: String,
+    pub mime_type: String,
+    /// In bytes, 0 when Telegram did not tell.
+    pub size: u32
+}
+
+/// How many files a ticket takes and which ones.
+///
+/// Photos are sent to operators at the largest size Telegram keeps under ATTACHMENTS_PHOTO_KB,
+/// a phone camera shot is cut down that way without the bot downloading it.
+#[derive(Clone)]
+pub struct AttachmentPolicy {
+    pub max_per_ticket: usize,
+    pub max_bytes: u32,
+    pub photo_bytes: u32
+}
+
+impl AttachmentPolicy {
+    pub fn from_env() -> AttachmentPolicy {
+        AttachmentPolicy {
+            max_per_ticket: env_or("ATTACHMENTS_MAX", 10),
+            max_bytes: env_or::<u32>("ATTACHMENTS_MAX_MB", 10).checked_mul(1024 * 1024)
+                .expect("ERROR: ATTACHMENTS_MAX_MB does not fit in 4 GB"),
+            photo_bytes: env_or::<u32>("ATTACHMENTS_PHOTO_KB", 500).checked_mul(1024)
+                .expect("ERROR: ATTACHMENTS_PHOTO_KB does not fit in 4 GB")
+        }
+    }
+
+    /// The file of `msg` checked against the policy, `attached` is how many the ticket has already.
+    ///
+    /// None when nothing is attached, the error is the reply to the client.
+    pub fn accept(&self, msg: &Message, attached: usize) -> Option<Result<Attachment, String>> {
+        let attachment = match (msg.photo(), msg.document()) {
+            (Some(sizes), _) => self.photo(sizes)?,
+            (None, Some(document)) => Attachment {
+                kind: AttachmentKind::Document,
+                file_id: document.file.id.clone(),
+                mime_type: document.mime_type.as_ref().map_or(String::new(), |mime| mime.essence_str().to_string()),
+                size: document.file.size
+            },
+            (None, None) => return None
+        };
+
+        Some(self.check(attachment, attached))
+    }
+
+    fn photo(&self, sizes: &[PhotoSize]) -> Option<Attachment> {
+        // Sizes come smallest first, the smallest one goes when even it is too large.
+        let size = sizes.iter().rev().find(|size| size.file.size <= self.photo_bytes).or(sizes.first())?;
+
+        Some(Attachment {
+            kind: AttachmentKind::Photo,
+            file_id: size.file.id.clone(),
+            mime_type: PHOTO_TYPE.to_string(),
+            size: size.file.size
+        })
+    }
+
+    pub fn check(&self, attachment: Attachment, attached: usize) -> Result<Attachment, String> {
+        if attached >= self.max_per_ticket {
+            return Err(format!("Можно приложить не больше {} файлов", self.max_per_ticket));
+        }
+
+        if !ALLOWED_TYPES.contains(&attachment.mime_type.as_str()) {
+            return Err("Такие файлы не принимаются. Пришлите фото, картинку или PDF".to_string());
+        }
+
+        if attachment.size > self.max_bytes {
+            return Err(format!("Файл больше {} МБ, пришлите его как фото или уменьшите", self.max_bytes / 1024 / 1024));
+        }
+
+        Ok(attachment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::FileMeta;
+
+    use super::*;
+
+    fn policy() -> AttachmentPolicy {
+        AttachmentPolicy { max_per_ticket: 2, max_bytes: 1024 * 1024, photo_bytes: 200 * 1024 }
+    }
+
+    fn document(mime_type: &str, size: u32) -> Attachment {
+        Attachment { kind: AttachmentKind::Document, file_id: "doc".to_string(), mime_type: mime_type.to_string(), size }
+    }
+
+    #[test]
+    fn files_are_checked_before_operators_get_them() {
+        assert!(policy().check(document("application/pdf", 1000), 0).is_ok());
+        assert!(policy().check(document("application/pdf", 1000), 2).unwrap_err().contains("не больше 2 файлов"));
+        assert!(policy().check(document("application/zip", 1000), 0).is_err());
+        assert!(policy().check(document("image/png", 2 * 1024 * 1024), 0).unwrap_err().contains("больше 1 МБ"));
+    }
+
+    #[test]
+    fn large_photos_go_at_a_smaller_size() {
+        let size = |id: &str, kb: u32| PhotoSize {
+            file: FileMeta { id: id.to_string(), unique_id: id.to_string(), size: kb * 1024 },
+            width: 0,
+            height: 0
+        };
+
+        let photo = policy().photo(&[size("s", 10), size("m", 120), size("x", 900)]).unwrap();
+        assert_eq!((photo.file_id.as_str(), photo.mime_type.as_str()), ("m", "image/jpeg"));
+
+        assert_eq!(policy().photo(&[size("m", 300), size("x", 900)]).unwrap().file_id, "m");
+        assert!(policy().photo(&[]).is_none());
+    }
+}
@@ -0,0 +1,136 @@
+//! Emulates the 107kapro tracking API for local runs of the bot.
+//!
+//! `cargo run --bin mock_vendor -- fixtures/mock_vendor.json`, then start the bot with
+//! `VENDOR_BASE_URL=http://127.0.0.1:8090`. Fixtures map a track code to `ready`, `pending`
+//! or `error`, and `POST /fixtures` with the same JSON adds more while it runs. Track codes
+//! without a fixture are not found, unless MOCK_VENDOR_SEED is set: then each of them is
+//! ready or pending depending on the seed, the same way on every run.
+
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}, net::SocketAddr, sync::{Arc, RwLock}};
+
+use axum::{extract::{Query, State}, http::StatusCode, routing::{get, post}, Json, Router};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Fixture {
+    Ready,
+    Pending,
+    Error
+}
+
+#[derive(Clone)]
+struct MockState {
+    fixtures: Arc<RwLock<HashMap<String, Fixture>>>,
+    seed: Option<u64>
+}
+
+#[derive(Deserialize)]
+struct Search {
+    no: String
+}
+
+/// The body the real API answers with, only `code` "0000" means the parcel is at the warehouse.
+#[derive(Serialize)]
+struct ProductStatus {
+    code: &'static str,
+    msg: &'static str
+}
+
+impl MockState {
+    fn fixture(&self, track_code: &str) -> Option<Fixture> {
+        if let Some(fixture) = self.fixtures.read().unwrap().get(track_code) {
+            return Some(*fixture);
+        }
+
+        self.seed.map(|seed| seeded(seed, track_code))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let fixtures = match std::env::args().nth(1) {
+        Some(path) => {
+            let json = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("ERROR: Could not read {}", path));
+
+            serde_json::from_str(&json).unwrap_or_else(|_| panic!("ERROR: Could not parse {}", path))
+        },
+        None => HashMap::new()
+    };
+
+    let seed = std::env::var("MOCK_VENDOR_SEED").ok()
+        .map(|seed| seed.trim().parse().expect("ERROR: Could not parse MOCK_VENDOR_SEED"));
+
+    let address: SocketAddr = std::env::var("MOCK_VENDOR_ADDRESS").ok()
+        .map_or(SocketAddr::from(([127, 0, 0, 1], 8090)), |address| address.parse().expect("ERROR: Could not parse MOCK_VENDOR_ADDRESS"));
+
+    log::info!("Mock vendor on {} with {} fixtures, seed {:?}", address, fixtures.len(), seed);
+
+    let state = MockState { fixtures: Arc::new(RwLock::new(fixtures)), seed };
+
+    let router = Router::new()
+        .route("/index/index/search", get(search))
+        .route("/fixtures", post(add_fixtures))
+        .with_state(state);
+
+    axum::Server::bind(&address)
+        .serve(router.into_make_service())
+        .await
+        .expect("ERROR: Mock vendor failed");
+}
+
+async fn search(State(state): State<MockState>, Query(search): Query<Search>) -> Result<Json<ProductStatus>, StatusCode> {
+    let fixture = state.fixture(&search.no);
+
+    log::info!("Mock vendor: {} -> {:?}", search.no, fixture);
+
+    match fixture {
+        Some(Fixture::Ready) => Ok(Json(ProductStatus { code: "0000", msg: "已入库" })),
+        Some(Fixture::Pending) => Ok(Json(ProductStatus { code: "1001", msg: "未入库" })),
+        Some(Fixture::Error) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        None => Ok(Json(ProductStatus { code: "1002", msg: "单号不存在" }))
+    }
+}
+
+async fn add_fixtures(State(state): State<MockState>, Json(fixtures): Json<HashMap<String, Fixture>>) -> StatusCode {
+    log::info!("Mock vendor: {} fixtures added", fixtures.len());
+
+    state.fixtures.write().unwrap().extend(fixtures);
+
+    StatusCode::NO_CONTENT
+}
+
+// Roughly a third of the parcels are ready, so both notices show up in a short run.
+fn seeded(seed: u64, track_code: &str) -> Fixture {
+    let mut hasher = DefaultHasher::new();
+
+    (seed, track_code).hash(&mut hasher);
+
+    match hasher.finish() % 3 {
+        0 => Fixture::Ready,
+        _ => Fixture::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_win_over_the_seed() {
+        let state = MockState {
+            fixtures: Arc::new(RwLock::new(HashMap::from([("YT1".to_string(), Fixture::Error)]))),
+            seed: Some(7)
+        };
+
+        assert_eq!(state.fixture("YT1"), Some(Fixture::Error));
+        assert_eq!(state.fixture("YT2"), Some(seeded(7, "YT2")));
+        assert_eq!(state.fixture("YT2"), state.fixture("YT2"));
+
+        let unseeded = MockState { seed: None, ..state };
+
+        assert_eq!(unseeded.fixture("YT2"), None);
+    }
+}
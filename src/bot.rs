@@ -1,14 +1,62 @@
-use indoc::indoc;
-use teloxide::{dispatching::{dialogue::{self, Dialogue, GetChatId, InMemStorage}, Dispatcher, UpdateFilterExt}, payloads::{EditMessageTextSetters, SendMessageSetters}, requests::Requester, types::{CallbackQuery, ChatId, InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId, Update}, Bot};
-
-use crate::{database::Db, models::User, vendor::product_ready};
+use std::{ops::ControlFlow, sync::Arc, time::Duration};
+
+use dptree::{di::{DependencyMap, DependencySupplier}, Cont};
+use serde::{Deserialize, Serialize};
+use teloxide::{dispatching::{dialogue::{self, Dialogue, GetChatId, Storage}, Dispatcher, HandlerExt, UpdateFilterExt}, error_handlers::LoggingErrorHandler, update_listeners::{webhooks, Polling}, payloads::{AnswerCallbackQuerySetters, SendMessageSetters}, requests::Requester, types::{CallbackQuery, ChatId, ChatMemberUpdated, InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId, Update, UpdateKind}};
+
+use crate::{attachments::Attachment, capacity::IntakeStats, carrier::Carrier, config::{self, Config}, correlation, database::Db, events, experiments::WelcomeVariant, maintenance::Maintenance, models::{ErrorReport, EventKind, Quote, Units, User}, notifier::Notifier, onboarding::{Onboarding, OnboardingStep}, outbox::Relay, phone_policy::PhoneDecision, reconciliation::Reconciliation, reengagement::Reengagement, retention::Retention, retry::Bot, scheduler::PollScheduler, sender::SendQueue, server, shutdown, staff::Staff, summary::MonthlySummary, support::bishkek_now, vendor, watchdog::{self, Watchdog}};
+
+use self::{admin::AdminCommand, chat_lock::ChatLocks, confirm::Confirmations, dedup::RecentInputs, edits::LastInput, flow::Reply, help::UserCommand, render::Rendered, storage::PgStorage};
+
+mod addresses;
+mod admin;
+mod approvals;
+mod batches;
+mod canned;
+mod couriers;
+mod cities;
+mod chat_lock;
+mod claims;
+mod confirm;
+mod dedup;
+mod diagram;
+mod disputes;
+mod edits;
+mod error_reports;
+mod exports;
+mod flow;
+mod help;
+mod inline;
+mod parcels;
+mod payments;
+mod photos;
+mod purchases;
+mod quotes;
+mod reactions;
+mod render;
+mod scan;
+mod settings;
+mod shelves;
+mod staff;
+mod storage;
+mod support;
+mod surveys;
+mod text_menu;
+mod walk_in;
+
+// The same as teloxide's default long polling.
+const POLLING_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct BotService {
     bot: Bot,
-    db: Db
+    db: Db,
+    config: Config,
+    queue: SendQueue,
+    maintenance: Maintenance,
+    staff: Staff
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 enum BotState {
     #[default]
     Start,
@@ -19,8 +67,12 @@ enum BotState {
     },
     RegisterPhoneNumber {
         first_name: String,
-        last_name: String
+        last_name: Option<String>
+    },
+    RegisterPhoneCode {
+        draft: Box<PhoneDraft>
     },
+    AwaitingApproval,
     Profile {
         msg_id: MessageId
     },
@@ -30,9 +82,105 @@ enum BotState {
     ProductStatus {
         msg_id: MessageId
     },
+    TrackResult {
+        msg_id: MessageId,
+        track_code: String
+    },
+    Parcels {
+        msg_id: MessageId
+    },
+    ParcelLabel {
+        track_code: String
+    },
+    RefundReason {
+        msg_id: MessageId,
+        track_code: String
+    },
+    Settings {
+        msg_id: MessageId
+    },
+    Addresses {
+        msg_id: MessageId
+    },
+    AddressCity,
+    DeliveryProof {
+        delivery_id: i32
+    },
+    AddressStreet {
+        city: String
+    },
+    AddressEntrance {
+        city: String,
+        street: String
+    },
+    AddressComment {
+        draft: Box<AddressDraft>
+    },
+    Support {
+        msg_id: MessageId
+    },
+    SupportMessage,
+    SellerCheck {
+        msg_id: MessageId
+    },
+    PurchaseLink {
+        msg_id: MessageId
+    },
+    PurchaseOptions {
+        marketplace: String,
+        link: String
+    },
+    PurchaseBudget {
+        draft: Box<PurchaseDraft>
+    },
+    WeightDispute {
+        track_code: String,
+        comment: String,
+        attachments: Vec<Attachment>
+    },
+    TextMenu,
+    Scan {
+        batch_code: String,
+        matched: usize,
+        unmatched: Vec<String>
+    },
+    ParcelPhoto {
+        track_code: String
+    },
+    QuoteParcel {
+        quote_id: i32
+    },
+    CityName,
+    CityTariffs {
+        name: String
+    },
+    CityEta {
+        name: String,
+        tariffs: String
+    },
+    CityPickup {
+        name: String,
+        tariffs: String,
+        eta_days: (i32, i32)
+    },
+    CityAnnouncement {
+        draft: Box<CityDraft>
+    },
+    WalkInFirstName,
+    WalkInLastName {
+        first_name: String
+    },
+    WalkInPhoneNumber {
+        first_name: String,
+        last_name: Option<String>
+    },
     Tutorial {
         msg_id: MessageId
     },
+    SurveyAnswer {
+        survey_id: i32,
+        position: i16
+    },
     PriceWidth,
     PriceLength {
         width: f32
@@ -48,69 +196,512 @@ enum BotState {
     }
 }
 
+/// A city filled in by the /opencity steps, boxed in the state to keep the other states small.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct CityDraft {
+    name: String,
+    tariffs: String,
+    eta_days: (i32, i32),
+    pickup_point: String
+}
+
+/// A delivery address before its comment, the last step saves it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct AddressDraft {
+    city: String,
+    street: String,
+    entrance: Option<String>
+}
+
+/// A registration waiting for the code sent to its phone number by SMS.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct PhoneDraft {
+    first_name: String,
+    last_name: Option<String>,
+    phone_number: String,
+    // The code itself is not kept, dialogues are stored in the database.
+    code_hash: String,
+    attempts: u8
+}
+
+/// A purchase request before the budget step, boxed for the same reason.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct PurchaseDraft {
+    marketplace: String,
+    link: String,
+    options: Option<String>
+}
+
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
-type BotDialogue = Dialogue<BotState, InMemStorage<BotState>>;
+type BotDialogue = Dialogue<BotState, PgStorage>;
 
 impl BotService {
-    pub async fn new() -> BotService {
+    pub async fn new(db: Db) -> BotService {
         log::info!("Initializing BotService");
 
-        BotService {
-            bot: Bot::from_env(),
-            db: Db::new().await
-        }
+        let config = Config::from_env();
+        let bot = Bot::new(config.app_env.bot_token());
+        let queue = SendQueue::spawn(bot.clone(), db.clone());
+
+        let maintenance = Maintenance::load(&db, config.maintenance_message.clone()).await;
+        let staff = Staff::load(&db, &config.admin_ids).await;
+
+        config.support.load_migrations(&db).await;
+
+        // One scheduler for every vendor poll, so jobs running at once share the permits and the backoff.
+        let scheduler = PollScheduler::new();
+
+        Notifier::spawn(db.clone(), config.notify_interval, scheduler.clone());
+        Relay::spawn(db.clone(), queue.clone());
+        Retention::spawn(db.clone(), config.retention.clone());
+        Reengagement::spawn(db.clone(), config.reengagement.clone());
+        Onboarding::spawn(db.clone(), config.onboarding.clone());
+        MonthlySummary::spawn(db.clone());
+        Watchdog::spawn(bot.clone(), db.clone(), config.clone(), staff.clone());
+        Reconciliation::spawn(bot.clone(), db.clone(), config.clone(), staff.clone(), scheduler);
+        IntakeStats::spawn(db.clone());
+        events::subscribe_all(&db);
+
+        BotService { bot, db, config, queue, maintenance, staff }
     }
 
     pub async fn dispatch(&self) {
         log::info!("Starting dispatching messages");
         let bot = self.bot.clone();
 
+        let admin_handler = dptree::entry()
+            .filter_command::<AdminCommand>()
+            .filter(|msg: Message, staff: Staff| {
+                msg.from().is_some_and(|user| staff.contains(user.id.0 as i64))
+            })
+            .branch(dptree::filter(|cmd: AdminCommand| cmd.is_destructive()).endpoint(Self::handle_destructive_command))
+            .branch(dptree::filter(|cmd: AdminCommand| cmd.is_payment()).endpoint(Self::handle_payment_command))
+            .branch(dptree::filter(|cmd: AdminCommand| cmd.is_export()).endpoint(Self::handle_export_command))
+            .branch(dptree::filter(|cmd: AdminCommand| cmd.is_staff()).endpoint(Self::handle_staff_command))
+            .branch(dptree::filter(|cmd: AdminCommand| cmd.is_planning()).endpoint(Self::handle_planning_command))
+            .branch(dptree::endpoint(Self::handle_admin_command));
+
+        // Whatever an admin types while an action waits for its word answers it, in any dialogue state.
+        let confirm_handler = dptree::filter(|msg: Message, staff: Staff, confirmations: Confirmations| {
+                msg.from().is_some_and(|user| staff.contains(user.id.0 as i64) && confirmations.awaits(user.id.0 as i64))
+            })
+            .endpoint(Self::confirm_action);
+
+        let deep_link_handler = dptree::filter(|msg: Message| flow::start_track_code(msg.text()).is_some())
+            .endpoint(Self::start_with_track_code);
+
+        let user_command_handler = dptree::entry()
+            .filter_command::<UserCommand>()
+            .endpoint(Self::handle_user_command);
+
+
         let message_handler = Update::filter_message()
+            .filter(|msg: Message, state: BotState, recent: RecentInputs| {
+                let repeat = recent.is_repeat(msg.chat.id, msg.id, msg.text(), &state);
+
+                if repeat {
+                    log::info!("Bot: dropping a repeated message {} in chat {}", msg.id, msg.chat.id);
+                }
+
+                !repeat
+            })
+            .inspect(|msg: Message, state: BotState, last_input: LastInput| {
+                last_input.remember(msg.chat.id, msg.id, state)
+            })
+            .branch(admin_handler)
+            .branch(confirm_handler)
+            .branch(deep_link_handler)
+            .branch(user_command_handler)
             .branch(dptree::case![BotState::Start].endpoint(Self::start))
             .branch(dptree::case![BotState::RegisterFirstName].endpoint(Self::register_first_name))
             .branch(dptree::case![BotState::RegisterLastName { first_name }].endpoint(Self::register_last_name))
             .branch(dptree::case![BotState::RegisterPhoneNumber { first_name, last_name }].endpoint(Self::register_phone_number))
+            .branch(dptree::case![BotState::RegisterPhoneCode { draft }].endpoint(Self::register_phone_code))
+            .branch(dptree::case![BotState::AwaitingApproval].endpoint(Self::await_approval))
             .branch(dptree::case![BotState::ProductStatus { msg_id }].endpoint(Self::get_product_status))
+            .branch(dptree::case![BotState::ParcelLabel { track_code }].endpoint(Self::receive_parcel_label))
+            .branch(dptree::case![BotState::SupportMessage].endpoint(Self::receive_support_message))
+            .branch(dptree::case![BotState::AddressCity].endpoint(Self::receive_address_city))
+            .branch(dptree::case![BotState::DeliveryProof { delivery_id }].endpoint(Self::receive_delivery_proof))
+            .branch(dptree::case![BotState::AddressStreet { city }].endpoint(Self::receive_address_street))
+            .branch(dptree::case![BotState::AddressEntrance { city, street }].endpoint(Self::receive_address_entrance))
+            .branch(dptree::case![BotState::AddressComment { draft }].endpoint(Self::receive_address_comment))
+            .branch(dptree::case![BotState::SellerCheck { msg_id }].endpoint(Self::receive_seller_check))
+            .branch(dptree::case![BotState::PurchaseLink { msg_id }].endpoint(Self::receive_purchase_link))
+            .branch(dptree::case![BotState::PurchaseOptions { marketplace, link }].endpoint(Self::receive_purchase_options))
+            .branch(dptree::case![BotState::PurchaseBudget { draft }].endpoint(Self::receive_purchase_budget))
+            .branch(dptree::case![BotState::WeightDispute { track_code, comment, attachments }].endpoint(Self::receive_dispute_input))
+            .branch(dptree::case![BotState::SurveyAnswer { survey_id, position }].endpoint(Self::receive_survey_answer))
+            .branch(dptree::case![BotState::TextMenu].endpoint(Self::handle_text_menu))
+            .branch(dptree::case![BotState::Scan { batch_code, matched, unmatched }].endpoint(Self::receive_scan))
+            .branch(dptree::case![BotState::ParcelPhoto { track_code }].endpoint(Self::receive_parcel_photo))
+            .branch(dptree::case![BotState::QuoteParcel { quote_id }].endpoint(Self::receive_quote_parcel))
+            .branch(dptree::case![BotState::WalkInFirstName].endpoint(Self::receive_walk_in_first_name))
+            .branch(dptree::case![BotState::CityName].endpoint(Self::receive_city_name))
+            .branch(dptree::case![BotState::CityTariffs { name }].endpoint(Self::receive_city_tariffs))
+            .branch(dptree::case![BotState::CityEta { name, tariffs }].endpoint(Self::receive_city_eta))
+            .branch(dptree::case![BotState::CityPickup { name, tariffs, eta_days }].endpoint(Self::receive_city_pickup))
+            .branch(dptree::case![BotState::CityAnnouncement { draft }].endpoint(Self::receive_city_announcement))
+            .branch(dptree::case![BotState::WalkInLastName { first_name }].endpoint(Self::receive_walk_in_last_name))
+            .branch(dptree::case![BotState::WalkInPhoneNumber { first_name, last_name }].endpoint(Self::receive_walk_in_phone_number))
+            .branch(dptree::case![BotState::Profile { msg_id }].endpoint(Self::resume_text_menu))
+            .branch(dptree::case![BotState::PriceWidth].endpoint(Self::receive_width))
+            .branch(dptree::case![BotState::PriceLength { width }].endpoint(Self::receive_length))
+            .branch(dptree::case![BotState::PriceHeight { width, length }].endpoint(Self::receive_height))
+            .branch(dptree::case![BotState::PriceWeight { width, length, height }].endpoint(Self::receive_weight));
+
+        let admin_callback_handler = dptree::filter(|q: CallbackQuery, staff: Staff| staff.contains(q.from.id.0 as i64))
+            .branch(dptree::filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|data| data.starts_with("canned")))
+                .endpoint(Self::handle_canned_callback))
+            .branch(dptree::filter(|q: CallbackQuery| q.data.as_deref().is_some_and(|data| data.starts_with("courier:")))
+                .endpoint(Self::handle_courier_callback));
+
+        // The receipt with this button arrives outside of the dialogue, so it works in any state.
+        let dispute_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("dispute:"))
+            })
+            .endpoint(Self::handle_dispute_btn);
+
+        // Quote buttons stay under every quote in the chat, so they work in any state as well.
+        let quote_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| ["requote:", "save_quote:", "save_box:"].iter().any(|prefix| data.starts_with(prefix)))
+            })
+            .endpoint(Self::handle_quote_btn);
+
+        // Sent with an invoice through the outbox, so it works in any state as well.
+        let pay_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("pay:"))
+            })
+            .endpoint(Self::handle_pay_btn);
+
+        // Survey questions go out through the outbox as well.
+        let survey_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("survey:"))
+            })
+            .endpoint(Self::handle_survey_btn);
+
+        // The rating prompt answers a reaction, whatever the dialogue is doing.
+        let rating_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("rate:"))
+            })
+            .endpoint(Self::handle_rating_btn);
+
+        // The monthly summary arrives through the outbox as well.
+        let summary_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("summary:"))
+            })
+            .endpoint(Self::handle_summary_btn);
+
+        // Couriers get task buttons from /dispatch and /tasks, whatever the dialogue is doing.
+        let delivered_callback_handler = dptree::filter(|q: CallbackQuery| {
+                q.data.as_deref().is_some_and(|data| data.starts_with("delivered:"))
+            })
+            .endpoint(Self::handle_delivered_btn);
+
+        // Sent by the re-engagement job, so it works in any state too.
+        let reminders_callback_handler = dptree::filter(|q: CallbackQuery| q.data.as_deref() == Some("reminders_off"))
+            .endpoint(Self::handle_reminders_off);
+
+        // Replays a corrected typo in the input the bot has just rejected.
+        let edited_message_handler = Update::filter_edited_message()
+            .filter(|msg: Message, state: BotState, last_input: LastInput| {
+                last_input.awaits(msg.chat.id, msg.id, &state)
+            })
+            .branch(dptree::case![BotState::RegisterFirstName].endpoint(Self::register_first_name))
+            .branch(dptree::case![BotState::RegisterLastName { first_name }].endpoint(Self::register_last_name))
+            .branch(dptree::case![BotState::RegisterPhoneNumber { first_name, last_name }].endpoint(Self::register_phone_number))
             .branch(dptree::case![BotState::PriceWidth].endpoint(Self::receive_width))
             .branch(dptree::case![BotState::PriceLength { width }].endpoint(Self::receive_length))
             .branch(dptree::case![BotState::PriceHeight { width, length }].endpoint(Self::receive_height))
             .branch(dptree::case![BotState::PriceWeight { width, length, height }].endpoint(Self::receive_weight));
 
         let callback_handler = Update::filter_callback_query()
+            .branch(admin_callback_handler)
+            .branch(dispute_callback_handler)
+            .branch(quote_callback_handler)
+            .branch(pay_callback_handler)
+            .branch(survey_callback_handler)
+            .branch(rating_callback_handler)
+            .branch(summary_callback_handler)
+            .branch(reminders_callback_handler)
+            .branch(delivered_callback_handler)
             .branch(dptree::case![BotState::RegisterInit].endpoint(Self::init_register)) 
             .branch(dptree::case![BotState::Profile { msg_id }].endpoint(Self::send_profile))
             .branch(dptree::case![BotState::ProductStatus { msg_id }].endpoint(Self::send_profile))
+            .branch(dptree::case![BotState::TrackResult { msg_id, track_code }].endpoint(Self::handle_track_result))
+            .branch(dptree::case![BotState::Parcels { msg_id }].endpoint(Self::handle_parcels))
+            .branch(dptree::case![BotState::RefundReason { msg_id, track_code }].endpoint(Self::handle_refund_reason))
+            .branch(dptree::case![BotState::Settings { msg_id }].endpoint(Self::handle_settings))
+            .branch(dptree::case![BotState::Addresses { msg_id }].endpoint(Self::handle_addresses))
+            .branch(dptree::case![BotState::DeliveryProof { delivery_id }].endpoint(Self::handle_delivery_proof))
+            .branch(dptree::case![BotState::Support { msg_id }].endpoint(Self::handle_support))
+            .branch(dptree::case![BotState::SellerCheck { msg_id }].endpoint(Self::handle_seller_check))
+            .branch(dptree::case![BotState::PurchaseLink { msg_id }].endpoint(Self::handle_purchase_link))
+            .branch(dptree::case![BotState::PriceWidth].endpoint(Self::handle_box_preset))
+            .branch(dptree::case![BotState::WeightDispute { track_code, comment, attachments }].endpoint(Self::handle_dispute))
             .branch(dptree::case![BotState::ProfilePages { msg_id }].endpoint(Self::handle_pages))
             .branch(dptree::case![BotState::Tutorial { msg_id }].endpoint(Self::handle_tutorials));
 
 
-        let handler = dialogue::enter::<Update, InMemStorage<BotState>, BotState, _>()
-            .branch(message_handler)
-            .branch(callback_handler);
+        // Telegram sends MigrateToChatId to the old group and MigrateFromChatId to the new supergroup.
+        let migration_handler = Update::filter_message()
+            .filter(|msg: Message| msg.migrate_to_chat_id().is_some() || msg.migrate_from_chat_id().is_some())
+            .endpoint(Self::migrate_chat);
+
+        // A card payment lands in the chat whatever the dialogue is doing, and is recorded even in maintenance:
+        // Telegram has charged the card by then.
+        let payment_handler = Update::filter_message()
+            .filter(|msg: Message| msg.successful_payment().is_some())
+            .endpoint(Self::receive_payment);
+
+        let maintenance_handler = dptree::filter(|update: Update, staff: Staff, maintenance: Maintenance| {
+                maintenance.message().is_some() && !update.user().is_some_and(|user| staff.contains(user.id.0 as i64))
+            })
+            .branch(Update::filter_message().endpoint(Self::maintenance_message))
+            .branch(Update::filter_callback_query().endpoint(Self::maintenance_callback));
+
+        let my_chat_member_handler = Update::filter_my_chat_member().endpoint(Self::handle_my_chat_member);
+
+        // Inline queries come without a chat, so they are handled outside of the dialogue.
+        let inline_query_handler = Update::filter_inline_query().endpoint(Self::handle_inline_query);
+
+        // Telegram waits only ten seconds for the answer, and the query has no chat for the dialogue.
+        let pre_checkout_handler = Update::filter_pre_checkout_query().endpoint(Self::handle_pre_checkout);
+
+        let handler = dptree::from_fn(Self::with_correlation_id)
+            .inspect_async(Self::record_activity)
+            .map_async(ChatLocks::lock_update)
+            .branch(migration_handler)
+            .branch(my_chat_member_handler)
+            .branch(inline_query_handler)
+            .branch(pre_checkout_handler)
+            .branch(payment_handler)
+            .branch(maintenance_handler)
+            .branch(dialogue::enter::<Update, PgStorage, BotState, _>()
+                .branch(message_handler)
+                .branch(edited_message_handler)
+                .branch(callback_handler));
 
         
 
-        Dispatcher::builder(bot, handler)
+        let mut dispatcher = Dispatcher::builder(bot.clone(), handler)
             .dependencies(dptree::deps![
-                InMemStorage::<BotState>::new(),
-                self.db.clone()])
+                PgStorage::new(self.db.clone()),
+                LastInput::default(),
+                ChatLocks::default(),
+                RecentInputs::new(self.config.dedup_window),
+                Confirmations::default(),
+                Rendered::default(),
+                self.db.clone(),
+                self.config.clone(),
+                self.queue.clone(),
+                self.maintenance.clone(),
+                self.staff.clone()])
             .enable_ctrlc_handler()
-            .build()
-            .dispatch()
-            .await;
+            .build();
+
+        shutdown::on_terminate(dispatcher.shutdown_token());
+
+        let router = server::router(self.db.clone(), &self.config);
+        let address = self.config.http_address;
+
+        match &self.config.webhook {
+            Some(webhook) => {
+                log::info!("Receiving updates through webhook {}", webhook.url);
+                let options = webhooks::Options::new(address, webhook.url.clone())
+                    .secret_token(webhook.secret.clone());
+
+                let (listener, stop, webhook_router) = webhooks::axum_to_router(bot.clone(), options)
+                    .await.expect("ERROR: Could not set up the webhook");
+
+                crate::reactions::allow_on_webhook(&bot, webhook).await;
+                let (listener, reactions) = crate::reactions::split(listener);
+                tokio::spawn(Self::receive_reactions(bot, self.db.clone(), reactions));
+
+                tokio::spawn(server::serve(address, router.merge(webhook_router), stop));
+
+                dispatcher.dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the update listener")
+                ).await;
+            },
+            None => {
+                let polling = Polling::builder(bot.clone())
+                    .timeout(POLLING_TIMEOUT)
+                    .delete_webhook().await
+                    .build();
+
+                crate::reactions::allow_on_polling(&bot).await;
+                let (listener, reactions) = crate::reactions::split(polling);
+                tokio::spawn(Self::receive_reactions(bot, self.db.clone(), reactions));
+
+                tokio::spawn(server::serve(address, router, std::future::pending()));
+
+                dispatcher.dispatch_with_listener(
+                    listener,
+                    LoggingErrorHandler::with_custom_text("An error from the update listener")
+                ).await;
+            }
+        }
+    }
+
+    /// Keeps the failed update with a snapshot of the dialogue and sends it to the alert chats, so it can be replayed.
+    async fn report_error(bot: &Bot, update: &Update, error: &str, db: &Db, storage: &Arc<PgStorage>, alert_chats: Vec<ChatId>) {
+        // The state saved when the handler failed; handlers save the next state last, so it is usually the one the input came in.
+        let state = match update.chat() {
+            Some(chat) => storage.clone().get_dialogue(chat.id).await.unwrap_or_default(),
+            None => None
+        };
+
+        let report = ErrorReport {
+            telegram_id: update.user().map(|user| user.id.0 as i64),
+            correlation_id: correlation::current().map(|id| id.to_string()).unwrap_or_default(),
+            state: state.as_ref().map(error_reports::snapshot),
+            input: error_reports::describe_input(update),
+            error: error.to_string()
+        };
+
+        let text = error_reports::alert_text(db.save_error_report(&report).await, &report);
+
+        for chat_id in alert_chats {
+            if let Err(err) = bot.send_message(chat_id, &text).await {
+                log::error!("ERROR: Could not send an error report to {}: {}", chat_id, err);
+            }
+        }
+    }
+
+    /// Handles the update under a fresh correlation id and shows it to the user when a handler fails.
+    async fn with_correlation_id(deps: DependencyMap, cont: Cont<'static, DependencyMap, HandlerResult>) -> ControlFlow<HandlerResult, DependencyMap> {
+        let bot: Arc<Bot> = deps.get();
+        let update: Arc<Update> = deps.get();
+        let db: Arc<Db> = deps.get();
+        let config: Arc<Config> = deps.get();
+        let staff: Arc<Staff> = deps.get();
+        let storage: Arc<Arc<PgStorage>> = deps.get();
+
+        correlation::scope(async move {
+            match cont(deps).await {
+                ControlFlow::Break(Err(err)) => {
+                    log::error!("ERROR: Could not handle the update: {}", err);
+
+                    Self::report_error(&bot, &update, &err.to_string(), &db, &storage, config.alert_chats(&staff.ids())).await;
+
+                    if let (Some(chat), Some(id)) = (update.chat(), correlation::current()) {
+                        if let Err(err) = bot.send_message(chat.id, flow::error_text(&id.to_string())).await {
+                            log::error!("ERROR: Could not report the error to the user: {}", err);
+                        }
+                    }
+
+                    ControlFlow::Break(Ok(()))
+                },
+                flow => flow
+            }
+        }).await
+    }
+
+    async fn record_activity(update: Update, db: Db) {
+        watchdog::record_update();
+
+        // Blocking the bot is not activity, and would clear the mark it sets.
+        if let UpdateKind::MyChatMember(_) = update.kind {
+            return;
+        }
+
+        if let Some(user) = update.user() {
+            let telegram_id = user.id.0 as i64;
+
+            db.record_event(telegram_id, EventKind::Active).await;
+            db.update_telegram_profile(telegram_id, user.username.as_deref(), &user.full_name()).await;
+        }
     }
 
-    async fn start(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+    /// Follows users blocking and unblocking the bot, and the bot leaving groups.
+    async fn handle_my_chat_member(update: ChatMemberUpdated, db: Db, config: Config) -> HandlerResult {
+        let chat_id = update.chat.id;
+        let present = update.new_chat_member.is_present();
+
+        log::info!("Bot: my_chat_member in {}, present {}", chat_id, present);
+
+        if update.chat.is_private() {
+            match present {
+                true => db.mark_reachable(chat_id.0).await,
+                false => db.mark_unreachable(chat_id.0).await
+            }
+
+            return Ok(());
+        }
+
+        if present {
+            return Ok(());
+        }
+
+        db.remove_dialogue(chat_id.0).await;
+
+        if config.support.operator_chat() == Some(chat_id) {
+            log::error!("ERROR: The bot was removed from the operator chat {}, tickets reach no one", chat_id);
+        }
+
+        Ok(())
+    }
+
+    /// Moves the operator chat and the dialogue of a group that was upgraded to a supergroup.
+    async fn migrate_chat(msg: Message, db: Db, config: Config) -> HandlerResult {
+        let (from, to) = match msg.migrate_to_chat_id() {
+            Some(to) => (msg.chat.id, to),
+            // The supergroup's side of the same migration.
+            None => return Ok(())
+        };
+
+        log::info!("Bot: chat {} migrated to supergroup {}", from, to);
+
+        config.support.migrate(&db, from, to).await;
+
+        if let Some(state) = db.get_dialogue(from.0).await {
+            db.save_dialogue(to.0, &state).await;
+            db.remove_dialogue(from.0).await;
+        }
+
+        Ok(())
+    }
+
+    async fn maintenance_message(bot: Bot, msg: Message, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: maintenance_message");
+        if let Some(message) = maintenance.message() {
+            bot.send_message(msg.chat.id, message).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn maintenance_callback(bot: Bot, q: CallbackQuery, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: maintenance_callback");
+        if let Some(message) = maintenance.message() {
+            bot.answer_callback_query(q.id).text(message).show_alert(true).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn start(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config) -> HandlerResult {
         log::info!("Bot: start");
         let user_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
         
-        if db.check_user(user_id).await {
+        if let Some(user) = db.find_user(user_id).await {
+            // Users approved by an operator come back with /start, the link they registered from is still waiting.
+            if let Some(track_code) = db.take_start_track_code(user_id).await {
+                return Self::look_up_track_code(bot, dialogue, msg.chat.id, track_code, &db, &config).await;
+            }
+
+            if user.text_menu {
+                return Self::send_text_menu(bot, dialogue, msg.chat.id, &user).await;
+            }
+
             let markup = InlineKeyboardMarkup::new(
                 vec![vec![InlineKeyboardButton::callback("Продолжить", "continue_btn")]]
             );
 
-            let msg_id = bot.send_message(msg.chat.id, "С возвращением!").reply_markup(markup)
+            let msg_id = bot.send_message(msg.chat.id, config::banner("С возвращением!")).reply_markup(markup)
                 .await?.id;
 
             dialogue.update(BotState::Profile { msg_id }).await?;
@@ -118,19 +709,13 @@ impl BotService {
             return Ok(());
         }
 
-        let markup = InlineKeyboardMarkup::new(
-            vec![vec![InlineKeyboardButton::callback("Начать", "start_btn")]]
-        );
-
-        bot.send_message(msg.chat.id, indoc!(r#"
-        Добро пожаловать в MaxExpress! 😊
-                        
-        У нас Вы можете:
-                        
-        1) Отслеживать статус доставки 🚚
-        2) Получить свой клиентский код 💼
-        3) Узнать способы оплаты 💳 (по весу или по плотности)
-        "#)).reply_markup(markup).await?;
+        let variant = WelcomeVariant::assign(user_id, config.welcome_experiment);
+
+        bot.send_message(msg.chat.id, config::banner(flow::welcome_text(variant)))
+            .reply_markup(flow::welcome_markup(variant))
+            .await?;
+
+        db.record_event(user_id, EventKind::Welcome(variant)).await;
         
         dialogue.update(BotState::RegisterInit).await?;
 
@@ -154,35 +739,14 @@ impl BotService {
         Ok(())
     }
 
-    async fn register_first_name(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+    async fn register_first_name(bot: Bot, dialogue: BotDialogue, msg: Message, config: Config) -> HandlerResult {
         log::info!("Bot: register_first_name");
-        let first_name = match msg.text() {
-            Some(text) => {
-                text.to_string()
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите имя еще раз.
-                "#)).await?;
-
-                dialogue.update(BotState::RegisterFirstName)
-                    .await?;
-
-                return Ok(());
-            }
-        };
-
-        bot.send_message(msg.chat.id, r#"
-        Напишите Вашу фамилию.
-        "#).await?;
+        let reply = flow::register_first_name(msg.text(), config.require_last_name);
 
-        dialogue.update(BotState::RegisterLastName { first_name }).await?;
-
-        Ok(())
+        Self::send_reply(bot, dialogue, msg.chat.id, reply).await
     }
 
-    async fn register_last_name(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+    async fn register_last_name(bot: Bot, dialogue: BotDialogue, msg: Message, config: Config) -> HandlerResult {
         log::info!("Bot: register_last_name");
         let first_name = match dialogue.get()
             .await?
@@ -190,129 +754,154 @@ impl BotService {
                 BotState::RegisterLastName { first_name } => first_name,
                 _ => "".to_string()
         };
-        
-        let last_name = match msg.text() {
-            Some(text) => {
-                text.to_string()
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите фамилию еще раз.
-                "#)).await?;
-
-                dialogue.update(BotState::RegisterLastName { first_name }).await?;
-
-                return Ok(());
-            }
-        };
 
-        bot.send_message(msg.chat.id, indoc!(r#"
-        Напишите Ваш номер телефона
-        Пример: 996XXXXXXXXX.
-        "#)).await?;
+        let reply = flow::register_last_name(first_name, msg.text(), config.require_last_name);
 
-        dialogue.update(BotState::RegisterPhoneNumber { first_name, last_name }).await?;
-
-        Ok(())
+        Self::send_reply(bot, dialogue, msg.chat.id, reply).await
     }
 
-    async fn register_phone_number(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+    async fn register_phone_number(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config, queue: SendQueue) -> HandlerResult {
         log::info!("Bot: register_phone_number");
         let (first_name, last_name) = match dialogue.get()
             .await?.unwrap() {
                 BotState::RegisterPhoneNumber { first_name, last_name }
                     => (first_name, last_name),
-                _ => ("".to_string(), "".to_string())
+                _ => ("".to_string(), None)
         };
 
-        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let phone_number = match flow::register_phone_number(first_name.clone(), last_name.clone(), msg.text()) {
+            Ok(phone_number) => phone_number,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
 
-        let phone_number = match msg.text() {
-            Some(text) => {
-                text.to_string()
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите номер телефона еще раз.
-                Пример: 996XXXXXXXXX
-                "#)).await?;
+        let user = Self::registering_user(&msg, first_name, last_name, phone_number);
 
-                dialogue.update(BotState::RegisterPhoneNumber { first_name, last_name }).await?;
+        if config.phone_verification.enabled() {
+            let telegram_id = user.telegram_id.expect("ERROR: user is unknown");
 
-                return Ok(());
+            if !db.reserve_sms(telegram_id, &user.phone_number, &config.phone_verification.limits).await {
+                return Self::send_reply(bot, dialogue, msg.chat.id, flow::phone_code_limited(user.first_name, user.last_name)).await;
             }
+
+            let reply = match config.phone_verification.send_code(&user.phone_number).await {
+                Some(code_hash) => flow::phone_code_prompt(PhoneDraft {
+                    first_name: user.first_name,
+                    last_name: user.last_name,
+                    phone_number: user.phone_number,
+                    code_hash,
+                    attempts: 0
+                }),
+                None => flow::phone_code_not_sent(user.first_name, user.last_name)
+            };
+
+            return Self::send_reply(bot, dialogue, msg.chat.id, reply).await;
+        }
+
+        Self::finish_registration(bot, dialogue, msg.chat.id, db, config, queue, user).await
+    }
+
+    async fn register_phone_code(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: register_phone_code");
+        let draft = match dialogue.get().await?.unwrap() {
+            BotState::RegisterPhoneCode { draft } => *draft,
+            _ => return Ok(())
+        };
+
+        let draft = match flow::register_phone_code(draft, msg.text()) {
+            Ok(draft) => draft,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
         };
 
-        let user = User {
+        let user = Self::registering_user(&msg, draft.first_name, draft.last_name, draft.phone_number);
+
+        Self::finish_registration(bot, dialogue, msg.chat.id, db, config, queue, user).await
+    }
+
+    fn registering_user(msg: &Message, first_name: String, last_name: Option<String>, phone_number: String) -> User {
+        let from = msg.from().expect("ERROR: user is unknown");
+
+        User {
             id: 0,
             client_code: String::new(),
             first_name,
             last_name,
             phone_number,
-            telegram_id
-        };
+            telegram_id: Some(from.id.0 as i64),
+            username: from.username.clone(),
+            display_name: Some(from.full_name()),
+            text_menu: false,
+            reminders: true,
+            monthly_summary: false,
+            units: Units::Metric,
+            city: None
+        }
+    }
 
-        db.create_user(user).await;
+    /// Registers the user unless the phone policy denies the number or wants an operator to look at it.
+    async fn finish_registration(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, db: Db, config: Config, queue: SendQueue, user: User) -> HandlerResult {
+        // The owner of an account with this phone came from a new Telegram account, the phone policy let them in before.
+        if let Some(owner) = db.find_phone_owner(user.telegram_id, &user.phone_number).await {
+            let reply = Self::claim_account(&db, &config, &queue, &owner, &user).await;
 
-        let markup = InlineKeyboardMarkup::new(
-            vec![vec![InlineKeyboardButton::callback("Далее", "next")]]
-        );
+            return Self::send_reply(bot, dialogue, chat_id, reply).await;
+        }
 
-        let msg_id = bot.send_message(msg.chat.id, "Вы зарегистрированы!")
-            .reply_markup(markup)
-            .await?.id;
+        match config.phone_policy.check(&user.phone_number) {
+            PhoneDecision::Allowed => (),
+            PhoneDecision::Denied => {
+                let reply = flow::phone_denied(&config.phone_policy.allowed_text());
 
-        dialogue.update(BotState::Profile { msg_id }).await?;
+                return Self::send_reply(bot, dialogue, chat_id, reply).await;
+            },
+            PhoneDecision::Review => {
+                Self::request_approval(&db, &config, &queue, &user).await;
 
-        Ok(())
+                return Self::send_reply(bot, dialogue, chat_id, flow::approval_pending()).await;
+            }
+        }
+
+        let telegram_id = user.telegram_id.expect("ERROR: user is unknown");
+
+        db.get_or_create(telegram_id, user).await;
+
+        let track_code = db.take_start_track_code(telegram_id).await;
+
+        Self::send_reply(bot.clone(), dialogue.clone(), chat_id, flow::registered()).await?;
+
+        match track_code {
+            Some(track_code) => Self::look_up_track_code(bot, dialogue, chat_id, track_code, &db, &config).await,
+            None => Ok(())
+        }
     }
 
-    async fn send_profile(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+    async fn send_profile(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
         log::info!("Bot: send_profile");
-        let chat_id = q.chat_id().unwrap();
-        let telegram_id = q.from.id.0 as i64;
-        let user = db.get_user(telegram_id).await;
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::Profile { msg_id } | BotState::ProductStatus { msg_id } => Some(msg_id),
+            _ => None
+        };
 
-        let message = format!(
-        indoc!(r#"
-        Ваш профиль:
-                        
-        📃 Клиентский код: {}
-        👤 Имя: {}
-        👤 Фамилия: {}
-        📞 Номер тел: {}
-        "#), &user.client_code, &user.first_name, &user.last_name, &user.phone_number);
-
-        let markup = InlineKeyboardMarkup::new(
-            vec![
-                vec![InlineKeyboardButton::callback("Отслеживание товара", "locate_btn")],
-                vec![InlineKeyboardButton::callback("Высчитывание цены", "price_btn")],
-                vec![
-                    InlineKeyboardButton::callback("Код", "code_btn"),
-                    InlineKeyboardButton::callback("Адрес", "address_btn")
-                ],
-                vec![
-                    InlineKeyboardButton::callback("Тех. поддержка", "service_btn"),
-                    InlineKeyboardButton::callback("Инструкция", "tutorial_btn")
-                ]
-            ]
-        );
+        Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id).await
+    }
 
-        let mut msg_id = match dialogue.get().await?.unwrap() {
-            BotState::Profile { msg_id } => msg_id,
-            _ => MessageId(0)
-        };
+    /// The one way back to the profile screen: edits `msg_id` when there is one, sends the screen otherwise,
+    /// and leaves the dialogue waiting for a profile button.
+    async fn render_home(bot: &Bot, dialogue: &BotDialogue, db: &Db, rendered: &Rendered, telegram_id: i64, chat_id: ChatId, msg_id: Option<MessageId>) -> HandlerResult {
+        let user = db.get_user(telegram_id).await;
+        let message = flow::profile_text(&user);
+        let markup = flow::profile_markup();
 
-        msg_id = bot.edit_message_text(chat_id, msg_id, message).reply_markup(markup).await?.id;
+        let msg_id = match msg_id {
+            Some(msg_id) => rendered.edit(bot, chat_id, msg_id, message, Some(markup)).await?,
+            None => bot.send_message(chat_id, config::banner(message)).reply_markup(markup).await?.id
+        };
 
         dialogue.update(BotState::ProfilePages { msg_id }).await?;
 
         Ok(())
     }
 
-    async fn handle_pages(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+    async fn handle_pages(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, config: Config, rendered: Rendered) -> HandlerResult {
         log::info!("Bot: handle_pages");
         let msg_id = match dialogue.get_or_default().await? {
             BotState::ProfilePages { msg_id } => msg_id,
@@ -335,130 +924,149 @@ impl BotService {
 
         match cb.as_str() {
             "locate_btn" => {
-                Self::handle_locate_btn(bot, dialogue.clone(), q.clone().chat_id().unwrap(), msg_id).await?;
+                Self::handle_locate_btn(bot, dialogue.clone(), q.clone().chat_id().unwrap(), msg_id, &rendered).await?;
+            },
+            "parcels_btn" => {
+                Self::handle_parcels_btn(bot, dialogue.clone(), q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, db.clone(), &rendered).await?;
             },
             "price_btn" => {
-                Self::handle_price_btn(bot, dialogue.clone(), q.clone().chat_id().unwrap(), msg_id).await?;
+                Self::handle_price_btn(bot, dialogue.clone(), q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, db.clone(), &rendered).await?;
             },
             "code_btn" => {
-                Self::handle_code_btn(bot, q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, markup, db.clone()).await?;
+                Self::handle_code_btn(bot, q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, markup, db.clone(), &rendered).await?;
             },
             "address_btn" => {
-                Self::handle_address_btn(bot, q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, markup, db.clone()).await?;
+                Self::handle_address_btn(bot, q.from.id.0 as i64, q.clone().chat_id().unwrap(), msg_id, markup, db.clone(), &rendered).await?;
             },
             "service_btn" => {
-                Self::handle_service_btn(bot, q.chat_id().unwrap(), msg_id, markup).await?;
+                Self::handle_service_btn(bot, dialogue.clone(), q.chat_id().unwrap(), msg_id, config, &rendered).await?;
             },
             "tutorial_btn" => {
-                Self::handle_tutorial_btn(bot, dialogue.clone(), q.chat_id().unwrap(), msg_id).await?;
+                Self::handle_tutorial_btn(bot, dialogue.clone(), q.chat_id().unwrap(), msg_id, db.clone(), &rendered).await?;
+            },
+            "seller_check_btn" => {
+                Self::handle_seller_check_btn(bot, dialogue.clone(), q.chat_id().unwrap(), msg_id, &rendered).await?;
+            },
+            "purchase_btn" => {
+                Self::handle_purchase_btn(bot, dialogue.clone(), q.chat_id().unwrap(), msg_id, &rendered).await?;
+            },
+            "batch_btn" => {
+                Self::handle_batch_btn(bot, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id, markup, db.clone(), &rendered).await?;
+            },
+            "tariffs_btn" => {
+                Self::handle_tariffs_btn(bot, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id, markup, db.clone(), &rendered).await?;
+            },
+            "quotes_btn" => {
+                Self::handle_quotes_btn(bot, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id, markup, db.clone(), &rendered).await?;
+            },
+            "settings_btn" => {
+                Self::handle_settings_btn(bot, dialogue.clone(), q.from.id.0 as i64, q.chat_id().unwrap(), msg_id, db.clone(), &rendered).await?;
             },
             _ => {
-                Self::handle_invalid_query(bot, q.chat_id().unwrap(), msg_id, markup).await?;
+                Self::handle_invalid_query(bot, q.chat_id().unwrap(), msg_id, markup, &rendered).await?;
             }
         };
 
         Ok(())
     }
 
-    async fn handle_locate_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId) -> HandlerResult {
+    async fn handle_locate_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, rendered: &Rendered) -> HandlerResult {
         log::info!("Bot: handle_locate_btn");
         let message = "Введите трек-код товара";
         dialogue.update(BotState::ProductStatus { msg_id }).await?;
 
-        bot.edit_message_text(chat_id, msg_id, message).await?.id;
+        rendered.edit(&bot, chat_id, msg_id, message, None).await?;
 
         Ok(())
     }
 
-    async fn get_product_status(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
-        log::info!("Bot: get_product_status");
-        let markup = InlineKeyboardMarkup::new(
-            vec![vec![InlineKeyboardButton::callback("Назад", "back_btn")]]
-        );
+    async fn handle_seller_check_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_seller_check_btn");
+        rendered.edit(&bot, chat_id, msg_id, flow::SELLER_CHECK_PROMPT, None).await?;
 
-        let track_code = match msg.text() {
-            Some(text) => {
-                text.to_string()
-            },
-            None => {
-                let msg_id = bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите трек-код еще раз.
-                "#)).reply_markup(markup).await?.id;
+        dialogue.update(BotState::SellerCheck { msg_id }).await?;
 
-                dialogue.update(BotState::ProductStatus { msg_id })
-                    .await?;
+        Ok(())
+    }
 
-                return Ok(());
-            }
-        };
+    async fn receive_seller_check(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_seller_check");
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::seller_check(msg.text())).await
+    }
 
-        let message = if product_ready(track_code.as_str()).await {
-            "Товар уже на складе, ждет сортировки"
-        } else {
-            "Товара еще нет на складе"
+    async fn handle_seller_check(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_seller_check");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::SellerCheck { msg_id } => Some(msg_id),
+            _ => None
         };
 
-        let msg_id = bot.send_message(msg.chat.id, message).reply_markup(markup).await?.id;
+        Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id).await
+    }
 
-        dialogue.update(BotState::Profile { msg_id }).await?;
+    async fn get_product_status(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: get_product_status");
+        let track_code = match flow::track_code(msg.text()) {
+            Ok(track_code) => track_code,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
 
-        Ok(())
+        Self::look_up_track_code(bot, dialogue, msg.chat.id, track_code, &db, &config).await
     }
 
-    async fn handle_price_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId) -> HandlerResult {
-        log::info!("Bot: handle_price_btn");
-        let message = "Введите ширину коробки с товаром (см)";
+    /// Sends the warehouse status with the button to save the parcel.
+    async fn look_up_track_code(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, track_code: String, db: &Db, config: &Config) -> HandlerResult {
+        if let Some(carrier) = Carrier::detect(&track_code).filter(|carrier| !carrier.supported()) {
+            return Self::send_reply(bot, dialogue, chat_id, flow::unsupported_carrier(carrier)).await;
+        }
 
-        bot.edit_message_text(chat_id, msg_id, message).await?;
+        let lookup = vendor::lookup(db, &vendor::chain(db.get_providers().await), track_code.as_str()).await?;
+        let detail = config.status_translator.translate(&lookup.status.msg).await;
 
-        dialogue.update(BotState::PriceWidth).await?;
+        let reply = flow::product_status(track_code, lookup.status.ready(), detail.as_deref(), lookup.fallback.then_some(lookup.source.as_str()));
 
-        Ok(())
+        Self::send_reply(bot, dialogue, chat_id, reply).await
     }
 
-    async fn receive_width(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
-        log::info!("Bot: receive_width");
-        let width = match msg.text() {
-            Some(text) => {
-                match text.to_string().parse::<f32>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        bot.send_message(msg.chat.id, indoc!(r#"
-                        Неверный формат.
-                        Введите ширину еще раз.
-                        "#)).await?;
-
-                        dialogue.update(BotState::PriceWidth)
-                        .await?;
-
-                        return Ok(());
-                    }
-                }
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите ширину еще раз.
-                "#)).await?;
+    /// A `/start track_<code>` link in any dialogue state: registered users get the status right away,
+    /// new users register first and get it after that.
+    async fn start_with_track_code(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: start_with_track_code");
+        let user_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let track_code = flow::start_track_code(msg.text()).unwrap_or_default();
 
-                dialogue.update(BotState::PriceWidth)
-                    .await?;
+        if db.find_user(user_id).await.is_some() {
+            return Self::look_up_track_code(bot, dialogue, msg.chat.id, track_code, &db, &config).await;
+        }
 
-                return Ok(());
-            }
-        };
+        db.save_start_track_code(user_id, &track_code).await;
 
-        bot.send_message(msg.chat.id, r#"
-        Введите длину коробки с товаром (см)
-        "#).await?;
+        Self::start(bot, dialogue, msg, db, config).await
+    }
+
+    async fn handle_price_btn(bot: Bot, dialogue: BotDialogue, tg_id: i64, chat_id: ChatId, msg_id: MessageId, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_price_btn");
+        let reply = flow::price_prompt(db.get_user(tg_id).await.units, &db.get_box_presets(tg_id).await);
+
+        rendered.edit(&bot, chat_id, msg_id, reply.text, reply.markup).await?;
 
-        dialogue.update(BotState::PriceLength { width }).await?;
+        Self::send_box_diagram(&bot, &db, chat_id).await;
+
+        dialogue.update(reply.state).await?;
 
         Ok(())
     }
 
-    async fn receive_length(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+    async fn receive_width(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_width");
+        let units = Self::get_units(&msg, &db).await;
+
+        let reply = flow::price_width(units, msg.text());
+
+        Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+    }
+
+    async fn receive_length(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
         log::info!("Bot: receive_length");
         let width = match dialogue.get()
             .await?
@@ -466,45 +1074,15 @@ impl BotService {
                 BotState::PriceLength { width } => width,
                 _ => 0_f32
         };
-        
-        let length = match msg.text() {
-            Some(text) => {
-                match text.to_string().parse::<f32>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        bot.send_message(msg.chat.id, indoc!(r#"
-                        Неверный формат.
-                        Введите длину еще раз.
-                        "#)).await?;
-
-                        dialogue.update(BotState::PriceLength { width }).await?;
-
-                        return Ok(());
-                    }
-                }
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите длину еще раз.
-                "#)).await?;
 
-                dialogue.update(BotState::PriceLength { width }).await?;
+        let units = Self::get_units(&msg, &db).await;
 
-                return Ok(());
-            }
-        };
+        let reply = flow::price_length(units, width, msg.text());
 
-        bot.send_message(msg.chat.id, indoc!(r#"
-        Введите высоту коробки с товаром (см)
-        "#)).await?;
-
-        dialogue.update(BotState::PriceHeight { width, length }).await?;
-
-        Ok(())
+        Self::send_reply(bot, dialogue, msg.chat.id, reply).await
     }
 
-    async fn receive_height(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+    async fn receive_height(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
         log::info!("Bot: receive_height");
         let (width, length) = match dialogue.get()
             .await?.unwrap() {
@@ -513,42 +1091,14 @@ impl BotService {
                 _ => (0_f32, 0_f32)
         };
 
-        let height = match msg.text() {
-            Some(text) => {
-                match text.to_string().parse::<f32>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        bot.send_message(msg.chat.id, indoc!(r#"
-                        Неверный формат.
-                        Введите высоту еще раз
-                        "#)).await?;
-
-                        dialogue.update(BotState::PriceHeight { width, length }).await?;
-
-                        return Ok(());
-                    }
-                }
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите высоту еще раз
-                "#)).await?;
+        let units = Self::get_units(&msg, &db).await;
 
-                dialogue.update(BotState::PriceHeight { width, length }).await?;
+        let reply = flow::price_height(units, width, length, msg.text());
 
-                return Ok(());
-            }
-        };
-
-        bot.send_message(msg.chat.id, "Введите вес коробки с товаром (кг)").await?;
-
-        dialogue.update(BotState::PriceWeight { width, length, height }).await?;
-        
-        Ok(())
+        Self::send_reply(bot, dialogue, msg.chat.id, reply).await
     }
 
-    async fn receive_weight(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+    async fn receive_weight(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
         log::info!("Bot: receive_weight");
         let (width, length, height) = match dialogue.get()
             .await?.unwrap() {
@@ -557,159 +1107,146 @@ impl BotService {
                 _ => (0_f32, 0_f32, 0_f32)
         };
 
-        let weight = match msg.text() {
-            Some(text) => {
-                match text.to_string().parse::<f32>() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        bot.send_message(msg.chat.id, indoc!(r#"
-                        Неверный формат.
-                        Введите вес еще раз
-                        "#)).await?;
+        let units = Self::get_units(&msg, &db).await;
 
-                        dialogue.update(BotState::PriceWeight { width, length, height }).await?;
+        let weight_kg = match flow::price_weight(units, width, length, height, msg.text()) {
+            Ok(weight_kg) => weight_kg,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
 
-                        return Ok(());
-                    }
-                }
-            },
-            None => {
-                bot.send_message(msg.chat.id, indoc!(r#"
-                Неверный формат.
-                Введите вес еще раз
-                "#)).await?;
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let quote = Self::price_quote(&db, telegram_id, width, length, height, weight_kg).await;
 
-                dialogue.update(BotState::PriceWeight { width, length, height }).await?;
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::quote_reply(&quote)).await
+    }
 
-                return Ok(());
-            }
+    /// Prices a box with the tariffs of the user's city and saves the quote.
+    async fn price_quote(db: &Db, telegram_id: i64, width: f32, length: f32, height: f32, weight_kg: f32) -> Quote {
+        let mut quote = Quote {
+            id: 0,
+            telegram_id,
+            width,
+            length,
+            height,
+            weight_kg,
+            price: None,
+            tariff_version: db.get_tariff_version().await,
+            created_at: bishkek_now()
         };
 
-        let volume = width * length * height * 0.000001;
+        quote.price = flow::quote_price(&db.get_user_tariffs(telegram_id).await, quote.density(), weight_kg);
+        quote.id = db.save_quote(&quote).await;
 
-        let density = weight / volume;
+        quote
+    }
 
-        let message = if density >= 100_f32 {
-            format!("Плотность составляет: {} кг/м3.\nЦена товара высчитывается по весу", density)
-        } else {
-            format!("Плотность составляет: {} кг/м3.\nЦена товара высчитывается по плотности", density)
-        };
+    async fn get_units(msg: &Message, db: &Db) -> Units {
+        db.get_user(msg.from().expect("ERROR: user is unknown").id.0 as i64).await.units
+    }
 
-        let markup = InlineKeyboardMarkup::new(
-            vec![vec![InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")]]
-        );
-        
-        let msg_id = bot.send_message(msg.chat.id, message).reply_markup(markup).await?.id;
+    async fn send_reply(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, reply: Reply) -> HandlerResult {
+        let mut request = bot.send_message(chat_id, config::banner(reply.text));
 
-        dialogue.update(BotState::Profile { msg_id }).await?;
+        if let Some(markup) = reply.markup {
+            request = request.reply_markup(markup);
+        }
+
+        let msg_id = request.await?.id;
+
+        dialogue.update(reply.state.with_msg_id(msg_id)).await?;
 
         Ok(())
     }
 
-    async fn handle_code_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db) -> HandlerResult {
+    async fn handle_code_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db, rendered: &Rendered) -> HandlerResult {
         log::info!("Bot: handle_code_btn");
         let client_code = db.get_user(tg_id).await.client_code;
 
-        bot.edit_message_text(chat_id, msg_id, client_code).reply_markup(markup).await?;
+        rendered.edit(&bot, chat_id, msg_id, client_code, Some(markup)).await?;
 
         Ok(())
     }
 
-    async fn handle_address_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db) -> HandlerResult {
-        log::info!("Bot: handle_address_btn");
-        let client_code = db.get_user(tg_id).await.client_code;
+    async fn handle_tariffs_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_tariffs_btn");
+        let message = flow::tariffs_text(&db.get_user_tariffs(tg_id).await);
+
+        rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
 
-        let message = format!(indoc!(r#"
-        收件人：溴溴{}
-        电话：18160860859
-        地区：浙江省 金华市 义乌市 
-        详细地址：江东街道东苑路45号一楼左侧 7号仓库(溴溴){}
-        "#), client_code.as_str(), client_code.as_str());
+        Ok(())
+    }
+
+    async fn handle_quotes_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_quotes_btn");
+        let message = flow::quotes_text(&db.get_quotes(tg_id, flow::QUOTES_LIMIT).await);
 
-        bot.edit_message_text(chat_id, msg_id, message).reply_markup(markup).await?;
+        rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
 
         Ok(())
     }
 
-    async fn handle_service_btn(bot: Bot, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup) -> HandlerResult {
-        log::info!("Bot: handle_service_btn");
-        let message = indoc!(r#"
-        Контакты тех. поддержки:
-        +996706518003
-        "#);
+    async fn handle_address_btn(bot: Bot, tg_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_address_btn");
+        let user = db.get_user(tg_id).await;
+
+        let message = flow::address_text(&user.client_code, &user.first_name, user.last_name.as_deref());
 
-        bot.edit_message_text(chat_id, msg_id, message).reply_markup(markup).await?;
+        db.record_event(tg_id, EventKind::Onboarding(OnboardingStep::Address)).await;
+
+        rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
 
         Ok(())
     }
 
-    async fn handle_tutorial_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId) -> HandlerResult {
+    async fn handle_tutorial_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, db: Db, rendered: &Rendered) -> HandlerResult {
         log::info!("Bot: handle_tutorial_btn");
-        let markup = InlineKeyboardMarkup::new(
-            vec![
-            vec![
-                InlineKeyboardButton::callback("1688", "1688_btn"),
-                InlineKeyboardButton::callback("Pinduoduo", "pinduoduo_btn")
-            ],
-            vec![
-                InlineKeyboardButton::callback("Poizon", "poizon_btn"),
-                InlineKeyboardButton::callback("TaoBao", "taobao_btn")
-            ]
-            ]
-        );
+        let markup = flow::tutorials_markup(&db.get_tutorials().await);
 
         let message = "Выберите маркетплейс, инструкцию к которой вы бы хотели получить";
 
-        let msg_id = bot.edit_message_text(chat_id, msg_id, message)
-            .reply_markup(markup)
-            .await?.id;
+        let msg_id = rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
 
         dialogue.update(BotState::Tutorial { msg_id }).await?;
 
         Ok(())
     }
 
-    async fn handle_tutorials(bot: Bot, dialogue: BotDialogue, q: CallbackQuery) -> HandlerResult {
+    async fn handle_tutorials(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
         log::info!("Bot: handle_tutorials");
         let mut msg_id = match dialogue.get().await?.unwrap() {
             BotState::Tutorial { msg_id } => msg_id,
             _ => MessageId(0)
         };
 
-        let message = match q.clone().data.unwrap().as_str() {
-            "1688_btn" => format!(indoc!(r#"
-                    Инструкция к 1688:
-                    {}"#), std::env::var("HELP_1688")?),
-            "pinduoduo_btn" => format!(indoc!(r#"
-                    Инструкция к Pinduoduo:
-                    {}"#), std::env::var("HELP_PINDUODUO")?),
-            "poizon_btn" => format!(indoc!(r#"
-                    Инструкция к Poizon:
-                    {}"#), std::env::var("HELP_POIZON")?),
-            "taobao_btn" => format!(indoc!(r#"
-                    Инструкция к TaoBao:
-                    {}"#), std::env::var("HELP_TAOBAO")?),
-            _ => format!(indoc!("
-                    Инструкция к TaoBao:
-                    {}"), std::env::var("HELP_TAOBAO")?)
+        let slug = match q.data.as_deref().and_then(|data| data.strip_prefix("tutorial:")) {
+            Some(slug) => slug.to_string(),
+            None => return Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), Some(msg_id)).await
         };
 
-        let markup = InlineKeyboardMarkup::new(
-            vec![vec![InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")]]
-        );
+        let (message, markup) = match db.get_tutorial(&slug).await {
+            Some(tutorial) => {
+                db.record_event(q.from.id.0 as i64, EventKind::Onboarding(OnboardingStep::Rules)).await;
+
+                (flow::tutorial_text(&tutorial), flow::tutorial_markup(&tutorial))
+            },
+            None => (
+                "Инструкция не найдена".to_string(),
+                InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")]])
+            )
+        };
 
         let chat_id = q.clone().chat_id().unwrap();
 
-        msg_id = bot.edit_message_text(chat_id, msg_id, message).reply_markup(markup).await?.id;
+        msg_id = rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
 
         dialogue.update(BotState::Profile { msg_id }).await?;
 
         Ok(())
     }
 
-    async fn handle_invalid_query(bot: Bot, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup) -> HandlerResult {
+    async fn handle_invalid_query(bot: Bot, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, rendered: &Rendered) -> HandlerResult {
         log::info!("Bot: handle_invalid_query");
-        bot.edit_message_text(chat_id, msg_id, "Произошла ошибка").reply_markup(markup).await?;
+        rendered.edit(&bot, chat_id, msg_id, "Произошла ошибка", Some(markup)).await?;
         
         Ok(())
     }
@@ -0,0 +1,182 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{AnswerCallbackQuerySetters, SendMessageSetters}, requests::Requester, types::{CallbackQuery, ChatId, Message, MessageId}};
+
+use crate::{config::Config, database::Db, models::MAX_ADDRESSES, notifier, retry::Bot};
+
+use super::{flow, render::Rendered, AddressDraft, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn handle_addresses_btn(bot: Bot, dialogue: BotDialogue, tg_id: i64, chat_id: ChatId, msg_id: MessageId, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_addresses_btn");
+        let reply = flow::addresses(&db.get_addresses(tg_id).await);
+
+        rendered.edit(&bot, chat_id, msg_id, reply.text, reply.markup).await?;
+
+        dialogue.update(BotState::Addresses { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_addresses(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_addresses");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::Addresses { msg_id } => msg_id,
+            _ => MessageId(0)
+        };
+
+        let telegram_id = q.from.id.0 as i64;
+        let chat_id = q.chat_id().unwrap();
+        let data = q.data.clone().unwrap_or_default();
+
+        if data == "add_address_btn" {
+            rendered.edit(&bot, chat_id, msg_id, flow::ADDRESS_CITY_PROMPT, None).await?;
+
+            dialogue.update(BotState::AddressCity).await?;
+
+            return Ok(());
+        }
+
+        if let Some(address_id) = data.strip_prefix("delete_address:").and_then(|id| id.parse().ok()) {
+            db.delete_address(telegram_id, address_id).await;
+
+            return Self::handle_addresses_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await;
+        }
+
+        Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+    }
+
+    pub(super) async fn receive_address_city(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_address_city");
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::address_city(msg.text())).await
+    }
+
+    pub(super) async fn receive_address_street(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_address_street");
+        let city = match dialogue.get().await?.unwrap() {
+            BotState::AddressStreet { city } => city,
+            _ => String::new()
+        };
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::address_street(city, msg.text())).await
+    }
+
+    pub(super) async fn receive_address_entrance(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_address_entrance");
+        let (city, street) = match dialogue.get().await?.unwrap() {
+            BotState::AddressEntrance { city, street } => (city, street),
+            _ => (String::new(), String::new())
+        };
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::address_entrance(city, street, msg.text())).await
+    }
+
+    /// Saves the address and shows the address book again.
+    pub(super) async fn receive_address_comment(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_address_comment");
+        let draft = match dialogue.get().await?.unwrap() {
+            BotState::AddressComment { draft } => *draft,
+            _ => return Ok(())
+        };
+
+        let (AddressDraft { city, street, entrance }, comment) = match flow::address_comment(draft, msg.text()) {
+            Ok(address) => address,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
+
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        if !db.add_address(telegram_id, &city, &street, entrance.as_deref(), comment.as_deref()).await {
+            bot.send_message(msg.chat.id, format!("Можно сохранить не больше {} адресов, удалите ненужный", MAX_ADDRESSES)).await?;
+        } else if db.get_user(telegram_id).await.city.is_none() {
+            // The first address tells the city of users who never picked it in the settings.
+            db.set_city(telegram_id, &city).await;
+        }
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::addresses(&db.get_addresses(telegram_id).await)).await
+    }
+
+    /// Offers the addresses of the parcel owners, the operator picks where the courier goes.
+    pub(super) async fn courier(bot: Bot, msg: Message, track_code: String, db: Db) -> HandlerResult {
+        log::info!("Bot: courier");
+        let track_code = track_code.trim();
+
+        if track_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /courier <трек-код>").await?;
+
+            return Ok(());
+        }
+
+        let parcels = db.get_undelivered_parcels(track_code).await;
+
+        if parcels.is_empty() {
+            bot.send_message(msg.chat.id, format!("Посылка {} не найдена среди ожидающих выдачи", track_code)).await?;
+
+            return Ok(());
+        }
+
+        let mut options = Vec::new();
+
+        for parcel in &parcels {
+            let client_code = db.get_user(parcel.telegram_id).await.client_code;
+
+            for address in db.get_addresses(parcel.telegram_id).await {
+                options.push((parcel.id, client_code.clone(), address));
+            }
+        }
+
+        if options.is_empty() {
+            bot.send_message(msg.chat.id, format!("Владелец посылки {} не добавил адресов доставки", track_code)).await?;
+
+            return Ok(());
+        }
+
+        bot.send_message(msg.chat.id, format!("Куда доставить посылку {}?", track_code))
+            .reply_markup(flow::courier_markup(&options))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Puts the parcel into the courier queue and posts it to the courier chat.
+    pub(super) async fn handle_courier_callback(bot: Bot, q: CallbackQuery, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: handle_courier_callback");
+        let (parcel_id, address_id) = match flow::courier_choice(q.data.as_deref()) {
+            Some(choice) => choice,
+            None => {
+                bot.answer_callback_query(q.id).await?;
+
+                return Ok(());
+            }
+        };
+
+        let delivery = match db.queue_delivery(parcel_id, address_id, config.payments.require_before_delivery, notifier::courier_notice).await {
+            Some(delivery) => delivery,
+            None => {
+                bot.answer_callback_query(q.id).text("Посылка еще не на складе, не оплачена, уже у курьера, выдана или адрес удален").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.answer_callback_query(q.id).await?;
+
+        let mut message = format!("Посылка {} передана в доставку #{}", delivery.track_code, delivery.id);
+
+        match config.courier_chat {
+            Some(courier_chat) => {
+                let user = db.get_user(delivery.telegram_id).await;
+
+                bot.send_message(courier_chat, flow::courier_task_text(&delivery, &user)).await?;
+            },
+            None => {
+                log::warn!("COURIER_CHAT_ID is not set, delivery #{} waits in the queue", delivery.id);
+                message.push_str(", но COURIER_CHAT_ID не задан");
+            }
+        }
+
+        if let Some(msg) = q.message {
+            bot.edit_message_text(msg.chat.id, msg.id, message).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1301 @@
+use indoc::indoc;
+use teloxide::{macros::BotCommands, payloads::{SendDocumentSetters, SendMessageSetters}, requests::Requester, types::{ChatId, InputFile, Message, ParseMode}};
+
+use crate::{calibration, capacity, client_code, config::Config, database::Db, label, money::Money, segment::Segment, duplicates, maintenance::{self, Maintenance}, manifest, models::{Invoice, Notice, OverrideReason, PriceOverride, RefundStatus, User}, reconciliation, report, retention::{self, RetentionPolicy}, retry::Bot, sender::{Priority, SendQueue}, support::bishkek_now};
+
+const REPORT_WEEKS: i32 = 8;
+const OVERRIDE_DAYS: i32 = 30;
+// Two weeks, the trend of /capacity compares them.
+const CAPACITY_DAYS: i32 = 14;
+// A quarter, enough bills per tariff version to tell a bias from noise.
+const ACCURACY_DAYS: i32 = 90;
+const CALIBRATION_LIMIT: i64 = 10;
+const FIND_LIMIT: i64 = 10;
+const RETENTION_RUNS: i64 = 7;
+
+// Notes of the last two shifts go into the handover.
+const HANDOVER_HOURS: i32 = 24;
+
+use super::{confirm::{Confirmations, Destructive}, exports::ExportFile, flow, BotDialogue, BotService, HandlerResult};
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum AdminCommand {
+    Broadcast(String),
+    Preview(String),
+    Maintenance(String),
+    Resume(String),
+    Report(String),
+    Note(String),
+    Handover,
+    Stats,
+    Find(String),
+    Tickets,
+    Close(String),
+    Attachments(String),
+    Canned(String),
+    R(String),
+    Duplicates,
+    Merge(String),
+    Refund(String),
+    Tutorial(String),
+    DeleteTutorial(String),
+    TutorialLinks(String),
+    Tariff(String),
+    DeleteTariff(String),
+    Assign(String),
+    Batch(String),
+    Batches,
+    Manifest(String),
+    Label(String),
+    Shelve(String),
+    Shelf(String),
+    Courier(String),
+    Couriers(String),
+    Deliveries,
+    Dispatch(String),
+    Delivered(String),
+    Invoice(String),
+    Paid(String),
+    Adjust(String),
+    Overrides(String),
+    Purchases,
+    Purchase(String),
+    Export,
+    Grant(String),
+    Revoke(String),
+    Item(String),
+    Quote(String),
+    Retention(String),
+    Reconciliation,
+    Capacity(String),
+    Accuracy(String),
+    Scan(String),
+    Photo(String),
+    WalkIn,
+    OpenCity,
+    Approvals,
+    Claims,
+    AcceptClaim(String),
+    DenyClaim(String),
+    Approve(String),
+    Reject(String),
+    Invite,
+    Staff,
+    Dismiss(String),
+    Survey(String),
+    Question(String),
+    SendSurvey(String),
+    Results(String),
+    Done
+}
+
+impl AdminCommand {
+    pub(super) fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            AdminCommand::Broadcast(_) | AdminCommand::Merge(_) | AdminCommand::Tariff(_) | AdminCommand::DeleteTariff(_) | AdminCommand::Manifest(_)
+                | AdminCommand::SendSurvey(_)
+        )
+    }
+
+    pub(super) fn is_payment(&self) -> bool {
+        matches!(self, AdminCommand::Delivered(_) | AdminCommand::Invoice(_) | AdminCommand::Paid(_) | AdminCommand::Adjust(_))
+    }
+
+    pub(super) fn is_export(&self) -> bool {
+        matches!(self, AdminCommand::Export | AdminCommand::Grant(_) | AdminCommand::Revoke(_))
+    }
+
+    pub(super) fn is_planning(&self) -> bool {
+        matches!(self, AdminCommand::Capacity(_) | AdminCommand::Accuracy(_) | AdminCommand::Label(_))
+    }
+
+    pub(super) fn is_staff(&self) -> bool {
+        matches!(self, AdminCommand::Invite | AdminCommand::Staff | AdminCommand::Dismiss(_))
+    }
+}
+
+impl BotService {
+    pub(super) async fn handle_admin_command(bot: Bot, dialogue: BotDialogue, msg: Message, cmd: AdminCommand, db: Db, queue: SendQueue, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: handle_admin_command");
+        match cmd {
+            AdminCommand::Preview(client_code) => Self::preview(bot, msg, client_code, db).await,
+            AdminCommand::Maintenance(until) => Self::start_maintenance(bot, msg, until, db, maintenance).await,
+            AdminCommand::Resume(notice) => Self::resume(bot, msg, notice, db, queue, maintenance).await,
+            AdminCommand::Report(weeks) => Self::report(bot, msg, weeks, db).await,
+            AdminCommand::Overrides(days) => Self::overrides(bot, msg, days, db).await,
+            AdminCommand::Stats => Self::stats(bot, msg, db).await,
+            AdminCommand::Note(text) => Self::note(bot, msg, text, db).await,
+            AdminCommand::Handover => Self::handover(bot, msg, db).await,
+            AdminCommand::Find(search) => Self::find(bot, msg, search, db).await,
+            AdminCommand::Tickets => Self::tickets(bot, msg, db).await,
+            AdminCommand::Close(ticket_id) => Self::close_ticket(bot, msg, ticket_id, db).await,
+            AdminCommand::Attachments(ticket_id) => Self::attachments(bot, msg, ticket_id, db).await,
+            AdminCommand::Canned(args) => Self::canned(bot, msg, args, db).await,
+            AdminCommand::R(args) => Self::reply_canned(bot, msg, args, db, queue).await,
+            AdminCommand::Duplicates => Self::duplicates(bot, msg, db).await,
+            AdminCommand::Refund(args) => Self::refund(bot, msg, args, db).await,
+            AdminCommand::Tutorial(args) => Self::save_tutorial(bot, msg, args, db).await,
+            AdminCommand::DeleteTutorial(slug) => Self::delete_tutorial(bot, msg, slug, db).await,
+            AdminCommand::TutorialLinks(args) => Self::tutorial_links(bot, msg, args, db).await,
+            AdminCommand::Assign(args) => Self::assign(bot, msg, args, db).await,
+            AdminCommand::Batch(args) => Self::batch(bot, msg, args, db).await,
+            AdminCommand::Batches => Self::batches(bot, msg, db).await,
+            AdminCommand::Shelve(args) => Self::shelve(bot, msg, args, db).await,
+            AdminCommand::Shelf(shelf) => Self::shelf(bot, msg, shelf, db).await,
+            AdminCommand::Courier(track_code) => Self::courier(bot, msg, track_code, db).await,
+            AdminCommand::Couriers(args) => Self::couriers(bot, msg, args, db).await,
+            AdminCommand::Deliveries => Self::deliveries(bot, msg, db).await,
+            AdminCommand::Dispatch(args) => Self::dispatch_delivery(bot, msg, args, db).await,
+            AdminCommand::Item(track_code) => Self::item(bot, msg, track_code, db).await,
+            AdminCommand::Quote(id) => Self::quote(bot, msg, id, db).await,
+            AdminCommand::Purchases => Self::purchases(bot, msg, db).await,
+            AdminCommand::Purchase(args) => Self::purchase(bot, msg, args, db).await,
+            AdminCommand::Retention(action) => Self::retention(bot, msg, action, db).await,
+            AdminCommand::Reconciliation => Self::reconciliation(bot, msg, db).await,
+            AdminCommand::Scan(batch_code) => Self::start_scan(bot, dialogue, msg, batch_code, db).await,
+            AdminCommand::Photo(track_code) => Self::ask_parcel_photo(bot, dialogue, msg, track_code).await,
+            AdminCommand::WalkIn => Self::start_walk_in(bot, dialogue, msg).await,
+            AdminCommand::OpenCity => Self::start_open_city(bot, dialogue, msg).await,
+            AdminCommand::Approvals => Self::approvals(bot, msg, db).await,
+            AdminCommand::Claims => Self::claims(bot, msg, db).await,
+            AdminCommand::AcceptClaim(id) => Self::accept_claim(bot, msg, id, db, queue).await,
+            AdminCommand::DenyClaim(id) => Self::deny_claim(bot, msg, id, db, queue).await,
+            AdminCommand::Approve(telegram_id) => Self::approve(bot, msg, telegram_id, db, queue).await,
+            AdminCommand::Reject(telegram_id) => Self::reject(bot, msg, telegram_id, db, queue).await,
+            AdminCommand::Survey(args) => Self::survey(bot, msg, args, db).await,
+            AdminCommand::Question(args) => Self::survey_question(bot, msg, args, db).await,
+            AdminCommand::Results(survey_id) => Self::survey_results(bot, msg, survey_id, db).await,
+            AdminCommand::Done => Self::finish_scan(bot, dialogue, msg, queue).await,
+            AdminCommand::Broadcast(_) | AdminCommand::Merge(_) | AdminCommand::Tariff(_) | AdminCommand::DeleteTariff(_) | AdminCommand::Manifest(_)
+                | AdminCommand::SendSurvey(_)
+                => unreachable!("ERROR: Destructive commands go through handle_destructive_command"),
+            AdminCommand::Delivered(_) | AdminCommand::Invoice(_) | AdminCommand::Paid(_) | AdminCommand::Adjust(_)
+                => unreachable!("ERROR: Payment commands go through handle_payment_command"),
+            AdminCommand::Export | AdminCommand::Grant(_) | AdminCommand::Revoke(_)
+                => unreachable!("ERROR: Export commands go through handle_export_command"),
+            AdminCommand::Invite | AdminCommand::Staff | AdminCommand::Dismiss(_)
+                => unreachable!("ERROR: Staff commands go through handle_staff_command"),
+            AdminCommand::Capacity(_) | AdminCommand::Accuracy(_) | AdminCommand::Label(_)
+                => unreachable!("ERROR: Planning commands go through handle_planning_command")
+        }
+    }
+
+    /// Commands that follow the payment policy.
+    pub(super) async fn handle_payment_command(bot: Bot, msg: Message, cmd: AdminCommand, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: handle_payment_command");
+        match cmd {
+            AdminCommand::Delivered(batch_code) => Self::delivered(bot, msg, batch_code, db, config).await,
+            AdminCommand::Invoice(args) => Self::invoice(bot, msg, args, db, config).await,
+            AdminCommand::Paid(args) => Self::paid(bot, msg, args, db).await,
+            AdminCommand::Adjust(args) => Self::adjust(bot, msg, args, db, config).await,
+            _ => Ok(())
+        }
+    }
+
+    /// Commands that read their thresholds or the label font from the config.
+    pub(super) async fn handle_planning_command(bot: Bot, msg: Message, cmd: AdminCommand, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: handle_planning_command");
+        match cmd {
+            AdminCommand::Capacity(days) => Self::capacity(bot, msg, days, db, config).await,
+            AdminCommand::Accuracy(days) => Self::accuracy(bot, msg, days, db, config).await,
+            AdminCommand::Label(args) => Self::label(bot, msg, args, db, config).await,
+            _ => Ok(())
+        }
+    }
+
+    /// Commands that cannot be taken back, each one only asks for a confirmation word.
+    pub(super) async fn handle_destructive_command(bot: Bot, msg: Message, cmd: AdminCommand, db: Db, config: Config, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: handle_destructive_command");
+        match cmd {
+            AdminCommand::Broadcast(args) => Self::broadcast(bot, msg, args, db, confirmations).await,
+            AdminCommand::Merge(codes) => Self::merge(bot, msg, codes, db, confirmations).await,
+            AdminCommand::Tariff(args) => Self::save_tariff(bot, msg, args, db, confirmations).await,
+            AdminCommand::DeleteTariff(min_density) => Self::delete_tariff(bot, msg, min_density, db, confirmations).await,
+            AdminCommand::Manifest(batch_code) => Self::manifest(bot, msg, batch_code, db, config, confirmations).await,
+            AdminCommand::SendSurvey(args) => Self::send_survey(bot, msg, args, db, confirmations).await,
+            _ => Ok(())
+        }
+    }
+
+    /// Shows the audience size of a broadcast and asks to confirm it, see [`Self::run_confirmed`].
+    async fn broadcast(bot: Bot, msg: Message, args: String, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: broadcast");
+        let (segment, text) = match Segment::parse(&args) {
+            Ok((segment, text)) if !text.is_empty() => (segment, text),
+            Ok(_) => {
+                bot.send_message(msg.chat.id, indoc!("
+                Использование: /broadcast [фильтры] <текст>
+                Фильтры: after:ГГГГ-ММ-ДД, parcels:warehouse, city:<город>, inactive:<дней>
+                ")).await?;
+
+                return Ok(());
+            },
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let recipients = db.count_segment(&segment).await;
+        let broadcast_id = db.create_broadcast(&segment.to_args(), &text).await;
+
+        let summary = format!(
+            "Рассылка #{}\nПолучатели: {}\nКоличество: {}\n\n{}",
+            broadcast_id,
+            segment.describe(),
+            recipients,
+            text
+        );
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::Broadcast(broadcast_id)).await
+    }
+
+    /// Runs an action once the admin typed its confirmation word back.
+    pub(super) async fn run_confirmed(bot: Bot, msg: Message, action: Destructive, db: Db, config: Config, queue: SendQueue, confirmations: Confirmations) -> HandlerResult {
+        let message = match action {
+            // Taking the broadcast marks it sent, so it is never sent twice.
+            Destructive::Broadcast(broadcast_id) => match db.take_broadcast(broadcast_id).await {
+                Some((filters, text)) => {
+                    let segment = Segment::parse(&filters).map(|(segment, _)| segment)
+                        .expect("ERROR: Could not parse saved broadcast filters");
+
+                    Self::send_broadcast(&db, &queue, msg.chat.id, &segment, &text).await;
+
+                    format!("Рассылка #{} отправлена: {}", broadcast_id, segment.describe())
+                },
+                None => format!("Рассылка #{} уже отправлена", broadcast_id)
+            },
+            Destructive::SaveTariff(tariff) => {
+                db.save_tariff(&tariff).await;
+
+                flow::tariffs_text(&db.get_tariffs().await)
+            },
+            Destructive::DeleteTariff(min_density) => match db.delete_tariff(min_density).await {
+                true => format!("Тариф от {} кг/м3 удален", min_density),
+                false => format!("Тариф от {} кг/м3 не найден", min_density)
+            },
+            Destructive::Manifest(batch_code) => {
+                let rows = db.get_manifest(&batch_code).await;
+
+                let file = ExportFile {
+                    kind: "manifest",
+                    file_name: format!("manifest-{}.csv", batch_code),
+                    caption: format!("Манифест партии {}: {} посылок", batch_code, rows.len()),
+                    csv: manifest::render_csv(&rows),
+                    row_count: rows.len()
+                };
+
+                return Self::send_export(&bot, &msg, &db, &config, file).await;
+            },
+            Destructive::ExportClients => {
+                let file = Self::clients_export(&db).await;
+
+                return Self::send_export(&bot, &msg, &db, &config, file).await;
+            },
+            // The announcement is only a draft until its own confirmation.
+            Destructive::OpenCity(city) => match db.open_city(&city).await {
+                Some(broadcast_id) => {
+                    bot.send_message(msg.chat.id, format!("Город {} открыт", city.name)).await?;
+
+                    let (segment, _) = Segment::parse(&format!("city:{}", city.name)).expect("ERROR: City names are one word");
+
+                    let summary = format!(
+                        "Рассылка #{}\nПолучатели: {}\nКоличество: {}\n\n{}",
+                        broadcast_id,
+                        segment.describe(),
+                        db.count_segment(&segment).await,
+                        city.announcement
+                    );
+
+                    return Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::Broadcast(broadcast_id)).await;
+                },
+                None => format!("Город {} уже открыт", city.name)
+            },
+            Destructive::SendSurvey { survey_id, filters } => Self::send_confirmed_survey(&db, survey_id, &filters).await,
+            Destructive::Merge { survivor, duplicate } => match Self::merge_pair(&db, &survivor, &duplicate).await {
+                Ok((survivor, duplicate)) => match db.merge_users(&survivor, &duplicate).await {
+                    Ok(moved) => format!(
+                        "Аккаунт {} объединен с {}, перенесено посылок: {}",
+                        duplicate.client_code,
+                        survivor.client_code,
+                        moved
+                    ),
+                    Err(track_codes) => format!(
+                        "Аккаунты не объединены: у посылок {} дубликата есть оплаты, возвраты или доставки. Разберите их вручную",
+                        track_codes.join(", ")
+                    )
+                },
+                Err(message) => message
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Sends the files of a ticket again, those filed after hours were never forwarded.
+    async fn attachments(bot: Bot, msg: Message, ticket_id: String, db: Db) -> HandlerResult {
+        log::info!("Bot: attachments");
+        let ticket_id = match ticket_id.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(ticket_id) => ticket_id,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /attachments <номер обращения>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let attachments = db.get_attachments(ticket_id).await;
+
+        if attachments.is_empty() {
+            bot.send_message(msg.chat.id, format!("У обращения #{} нет вложений", ticket_id)).await?;
+
+            return Ok(());
+        }
+
+        Self::send_attachments(&bot, msg.chat.id, &attachments, &format!("Обращение #{}", ticket_id)).await
+    }
+
+    async fn send_broadcast(db: &Db, queue: &SendQueue, admin_chat: ChatId, segment: &Segment, text: &str) {
+        let telegram_ids = db.get_segment_ids(segment).await;
+
+        for telegram_id in &telegram_ids {
+            queue.push(Priority::Bulk, ChatId(*telegram_id), text.to_string());
+        }
+
+        queue.push(
+            Priority::Interactive,
+            admin_chat,
+            format!("Рассылка поставлена в очередь: {} получателей", telegram_ids.len())
+        );
+    }
+
+    async fn preview(bot: Bot, msg: Message, client_code: String, db: Db) -> HandlerResult {
+        log::info!("Bot: preview");
+        if client_code.trim().is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /preview <клиентский код>").await?;
+
+            return Ok(());
+        }
+
+        let user = match Self::find_client(&db, &client_code).await {
+            Ok(user) => user,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let screens = [
+            ("Профиль", flow::profile_text(&user)),
+            ("Адрес", flow::address_text(&user.client_code, &user.first_name, user.last_name.as_deref()))
+        ];
+
+        for (title, screen) in screens {
+            bot.send_message(msg.chat.id, format!("👁 {} глазами {}:\n\n{}", title, user.client_code, screen)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn start_maintenance(bot: Bot, msg: Message, until: String, db: Db, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: start_maintenance");
+        let until = match maintenance::parse_time(&until) {
+            Some(until) => until,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /maintenance HH:MM").await?;
+
+                return Ok(());
+            }
+        };
+
+        maintenance.enable(&db, until.clone()).await;
+
+        bot.send_message(msg.chat.id, format!("Режим техобслуживания включен до {}", until)).await?;
+
+        Ok(())
+    }
+
+    async fn resume(bot: Bot, msg: Message, notice: String, db: Db, queue: SendQueue, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: resume");
+        maintenance.disable(&db).await;
+
+        bot.send_message(msg.chat.id, "Режим техобслуживания выключен").await?;
+
+        if !notice.trim().is_empty() {
+            Self::send_broadcast(&db, &queue, msg.chat.id, &Segment::default(), notice.trim()).await;
+        }
+
+        Ok(())
+    }
+
+    async fn report(bot: Bot, msg: Message, weeks: String, db: Db) -> HandlerResult {
+        log::info!("Bot: report");
+        let weeks = match weeks.trim() {
+            "" => REPORT_WEEKS,
+            weeks => match weeks.parse::<i32>() {
+                Ok(weeks) if (1..=26).contains(&weeks) => weeks,
+                _ => {
+                    bot.send_message(msg.chat.id, "Использование: /report [кол-во недель, 1-26]").await?;
+
+                    return Ok(());
+                }
+            }
+        };
+
+        let cohorts = db.get_cohorts(weeks).await;
+        let activity = db.get_cohort_activity(weeks).await;
+
+        bot.send_message(msg.chat.id, format!(
+            "Когорты по неделям регистрации (удержание, посылок на пользователя):\n<pre>{}</pre>",
+            report::render_cohorts(&cohorts, &activity)
+        )).parse_mode(ParseMode::Html).await?;
+
+        Ok(())
+    }
+
+    async fn note(bot: Bot, msg: Message, text: String, db: Db) -> HandlerResult {
+        log::info!("Bot: note");
+        if text.trim().is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /note <текст заметки для следующей смены>").await?;
+
+            return Ok(());
+        }
+
+        let author = msg.from().expect("ERROR: user is unknown");
+
+        db.add_staff_note(author.id.0 as i64, &author.full_name(), text.trim()).await;
+
+        bot.send_message(msg.chat.id, "Заметка сохранена, она попадет в /handover").await?;
+
+        Ok(())
+    }
+
+    /// Everything the incoming shift has to pick up: notes, open tickets, disputes and parcels to batch.
+    async fn handover(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: handover");
+        let summary = report::render_handover(
+            &db.get_staff_notes(HANDOVER_HOURS).await,
+            &db.get_open_tickets().await,
+            &db.get_pending_disputes().await,
+            &db.get_unassigned_parcels().await
+        );
+
+        bot.send_message(msg.chat.id, summary).await?;
+
+        Ok(())
+    }
+
+    async fn stats(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: stats");
+        bot.send_message(msg.chat.id, format!(
+            "Эксперимент с приветствием, конверсия в регистрацию:\n<pre>{}</pre>\n\nПолучают сообщения: {}\nЗаблокировали бота: {}",
+            report::render_welcome(&db.get_welcome_results().await),
+            db.count_reachable().await,
+            db.count_unreachable().await
+        )).parse_mode(ParseMode::Html).await?;
+
+        Ok(())
+    }
+
+    async fn find(bot: Bot, msg: Message, search: String, db: Db) -> HandlerResult {
+        log::info!("Bot: find");
+        if search.trim().is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /find <@username, код или имя>").await?;
+
+            return Ok(());
+        }
+
+        let users = db.find_users(&search, FIND_LIMIT).await;
+
+        let message = if users.is_empty() {
+            "Никого не найдено".to_string()
+        } else {
+            users.iter().map(user_line).collect::<Vec<_>>().join("\n")
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    async fn tickets(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: tickets");
+        let tickets = db.get_open_tickets().await;
+
+        let message = if tickets.is_empty() {
+            "Открытых обращений нет".to_string()
+        } else {
+            tickets.iter()
+                .map(|ticket| format!(
+                    "#{} {} — {}:\n{}",
+                    ticket.id,
+                    ticket.created_at.format("%d.%m %H:%M"),
+                    ticket.client_code,
+                    ticket.text
+                ))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    async fn close_ticket(bot: Bot, msg: Message, ticket_id: String, db: Db) -> HandlerResult {
+        log::info!("Bot: close_ticket");
+        let ticket_id = match ticket_id.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(ticket_id) => ticket_id,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /close <номер обращения>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = if db.close_ticket(ticket_id).await {
+            format!("Обращение #{} закрыто", ticket_id)
+        } else {
+            format!("Открытое обращение #{} не найдено", ticket_id)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Moves a refund on and tells the client, a paid refund also closes its ticket.
+    async fn refund(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: refund");
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (ticket_id, status) = match args[..] {
+            [ticket_id, status] => (
+                ticket_id.trim_start_matches('#').parse::<i32>().ok(),
+                RefundStatus::try_from(status.to_lowercase()).ok()
+            ),
+            _ => (None, None)
+        };
+
+        let (ticket_id, status) = match (ticket_id, status) {
+            (Some(ticket_id), Some(status)) if status != RefundStatus::Requested => (ticket_id, status),
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /refund <номер обращения> <approved|paid>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let notice = |telegram_id, track_code: &str| Notice {
+            telegram_id,
+            text: format!("Возврат по заказу {} {}", track_code, status.title()),
+            markup: None,
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        if !db.set_refund_status(ticket_id, status, notice).await {
+            bot.send_message(msg.chat.id, format!("Возврат по обращению #{} не найден", ticket_id)).await?;
+
+            return Ok(());
+        }
+
+        if status == RefundStatus::Paid {
+            db.close_ticket(ticket_id).await;
+        }
+
+        bot.send_message(msg.chat.id, format!("Возврат по обращению #{} {}", ticket_id, status.title())).await?;
+
+        Ok(())
+    }
+
+    async fn save_tutorial(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: save_tutorial");
+        let tutorial = match flow::tutorial_from_command(&args) {
+            Some(tutorial) => tutorial,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /tutorial <slug латиницей> <название>\n<текст инструкции>").await?;
+
+                return Ok(());
+            }
+        };
+
+        db.save_tutorial(&tutorial).await;
+
+        bot.send_message(msg.chat.id, format!("Инструкция «{}» сохранена", tutorial.title)).await?;
+
+        Ok(())
+    }
+
+    async fn delete_tutorial(bot: Bot, msg: Message, slug: String, db: Db) -> HandlerResult {
+        log::info!("Bot: delete_tutorial");
+        let slug = slug.trim().to_lowercase();
+
+        let message = if db.delete_tutorial(&slug).await {
+            format!("Инструкция {} удалена", slug)
+        } else {
+            format!("Инструкция {} не найдена", slug)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    async fn tutorial_links(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: tutorial_links");
+        let (slug, app_url, web_url) = match flow::tutorial_links_from_command(&args) {
+            Some(links) => links,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /tutoriallinks <slug> <ссылка на приложение или -> <ссылка на сайт или ->").await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = if db.set_tutorial_links(&slug, app_url.as_deref(), web_url.as_deref()).await {
+            format!("Ссылки инструкции {} сохранены", slug)
+        } else {
+            format!("Инструкция {} не найдена", slug)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    async fn save_tariff(bot: Bot, msg: Message, args: String, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: save_tariff");
+        let tariff = match flow::tariff_from_command(&args) {
+            Some(tariff) => tariff,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /tariff <плотность от, кг/м3> <цена за кг, $> <минимум, $>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let summary = format!(
+            "Тариф от {} кг/м3: {} за кг, минимум {}. Цены в калькуляторе изменятся для всех клиентов",
+            tariff.min_density,
+            tariff.price_per_kg,
+            tariff.min_charge
+        );
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::SaveTariff(tariff)).await
+    }
+
+    async fn delete_tariff(bot: Bot, msg: Message, min_density: String, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: delete_tariff");
+        let min_density = match min_density.trim().replace(',', ".").parse::<f32>() {
+            Ok(min_density) => min_density,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /deletetariff <плотность от, кг/м3>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let summary = format!("Тариф от {} кг/м3 будет удален", min_density);
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::DeleteTariff(min_density)).await
+    }
+
+    /// Puts a weighed parcel into a batch and sends its owner the receipt with the weight.
+    async fn assign(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: assign");
+        let mut args = args.split_whitespace();
+        let (batch_code, track_code, weight_kg, declared_value) = match (args.next(), args.next(), flow::parse_dimension(args.next()), args.next().and_then(Money::parse)) {
+            (Some(batch_code), Some(track_code), Some(weight_kg), Some(declared_value))
+                => (batch_code.to_uppercase(), track_code.to_string(), weight_kg, declared_value),
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /assign <партия> <трек-код> <вес, кг> <стоимость, $> [описание]").await?;
+
+                return Ok(());
+            }
+        };
+
+        let description = args.collect::<Vec<_>>().join(" ");
+        let description = Some(description.as_str()).filter(|description| !description.is_empty());
+
+        let receipt = |parcel_id, telegram_id| Notice {
+            telegram_id,
+            text: flow::weighed_text(&track_code, weight_kg),
+            markup: Some(flow::weighed_markup(parcel_id)),
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        db.create_batch(&batch_code).await;
+
+        let message = if db.assign_parcel(&track_code, &batch_code, weight_kg, declared_value, description, receipt).await == 0 {
+            format!("Посылка {} не найдена среди сохраненных", track_code)
+        } else {
+            format!("Посылка {} добавлена в партию {}", track_code, batch_code)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Records the handover of a batch, its transit time feeds the delivery estimate in arrival notices.
+    ///
+    /// When payment is required before delivery, parcels not paid in full stay in the batch and are listed.
+    async fn delivered(bot: Bot, msg: Message, batch_code: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: delivered");
+        let batch_code = batch_code.trim().to_uppercase();
+
+        if batch_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /delivered <партия>").await?;
+
+            return Ok(());
+        }
+
+        let (delivered, held) = db.mark_batch_delivered(&batch_code, config.payments.require_before_delivery).await;
+
+        bot.send_message(msg.chat.id, flow::delivered_text(&batch_code, delivered, &held)).await?;
+
+        Ok(())
+    }
+
+    /// Bills every client who saved the parcel, with a «Оплатить» button when Telegram Payments are set up.
+    async fn invoice(bot: Bot, msg: Message, args: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: invoice");
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (track_code, amount) = match args[..] {
+            [track_code, amount] => match Money::parse(amount) {
+                Some(amount) if amount > Money::default() => (track_code, amount),
+                _ => ("", Money::default())
+            },
+            _ => ("", Money::default())
+        };
+
+        if track_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /invoice <трек-код> <сумма, $>").await?;
+
+            return Ok(());
+        }
+
+        let online = config.payments.provider_token.is_some();
+
+        let notice = |invoice: &Invoice| Notice {
+            telegram_id: invoice.telegram_id,
+            text: flow::invoice_text(invoice, online),
+            markup: online.then(|| flow::invoice_markup(invoice.parcel_id)),
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        let message = match db.set_invoice(track_code, amount, &config.calibration, notice).await {
+            0 => format!("Посылка {} не найдена среди сохраненных", track_code),
+            _ => format!("Счет на {} за посылку {} отправлен клиенту", amount, track_code)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Records a payment taken at the pickup point or by transfer.
+    ///
+    /// A track code saved by several clients needs the client code to tell whose invoice was paid.
+    async fn paid(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: paid");
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (track_code, amount, client_code) = match args[..] {
+            [track_code, amount] => (track_code, Money::parse(amount), None),
+            [track_code, amount, client_code] => (track_code, Money::parse(amount), Some(client_code)),
+            _ => ("", None, None)
+        };
+
+        let amount = match amount {
+            Some(amount) if amount > Money::default() => amount,
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /paid <трек-код> <сумма, $> [код клиента]").await?;
+
+                return Ok(());
+            }
+        };
+
+        let invoice = match Self::find_invoice(&db, track_code, client_code, &format!("/paid {} {}", track_code, amount.amount())).await {
+            Ok(invoice) => invoice,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        if amount > invoice.due() {
+            bot.send_message(msg.chat.id, format!("Оплата {} больше остатка {} по счету за посылку {}", amount, invoice.due(), invoice.track_code)).await?;
+
+            return Ok(());
+        }
+
+        let recorded_by = msg.from().map(|user| user.id.0 as i64);
+
+        let notice = |invoice: &Invoice| Notice {
+            telegram_id: invoice.telegram_id,
+            text: flow::payment_text(invoice),
+            markup: None,
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        let message = match db.record_payment(invoice.parcel_id, amount, None, recorded_by, notice).await {
+            Some(invoice) => format!(
+                "Оплата {} за посылку {} клиента {} записана, счет {}",
+                amount, invoice.track_code, invoice.client_code, invoice.status().title()
+            ),
+            None => format!("Счет за посылку {} не найден или остаток по нему уже меньше {}", track_code, amount)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// The invoice of one client for the track code, or what to tell the operator.
+    ///
+    /// `retry` is the command to repeat with the client code when several clients saved the parcel.
+    async fn find_invoice(db: &Db, track_code: &str, client_code: Option<&str>, retry: &str) -> Result<Invoice, String> {
+        let mut invoices: Vec<Invoice> = db.get_invoices(track_code).await.into_iter()
+            .filter(|invoice| match client_code {
+                Some(code) => invoice.client_code.eq_ignore_ascii_case(code),
+                None => true
+            })
+            .collect();
+
+        match invoices.len() {
+            1 => Ok(invoices.remove(0)),
+            0 => Err(format!("Счет за посылку {} не выставлен, сначала отправьте /invoice", track_code)),
+            _ => {
+                let codes: Vec<&str> = invoices.iter().map(|invoice| invoice.client_code.as_str()).collect();
+
+                Err(format!(
+                    "Посылку {} сохранили несколько клиентов: {}. Укажите код клиента: {} <код клиента>",
+                    track_code, codes.join(", "), retry
+                ))
+            }
+        }
+    }
+
+    /// `/adjust <трек-код> <итоговая сумма, $> <причина> [код клиента]`, a discount or a penalty on a billed parcel.
+    async fn adjust(bot: Bot, msg: Message, args: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: adjust");
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (track_code, final_price, reason, client_code) = match args[..] {
+            [track_code, amount, reason] => (track_code, Money::parse(amount), OverrideReason::try_from(reason.to_lowercase()).ok(), None),
+            [track_code, amount, reason, client_code] => (track_code, Money::parse(amount), OverrideReason::try_from(reason.to_lowercase()).ok(), Some(client_code)),
+            _ => ("", None, None, None)
+        };
+
+        let (final_price, reason) = match (final_price, reason) {
+            (Some(final_price), Some(reason)) if final_price > Money::default() => (final_price, reason),
+            _ => {
+                bot.send_message(msg.chat.id, flow::adjust_usage()).await?;
+
+                return Ok(());
+            }
+        };
+
+        let retry = format!("/adjust {} {} {}", track_code, final_price.amount(), reason.as_str());
+
+        let invoice = match Self::find_invoice(&db, track_code, client_code, &retry).await {
+            Ok(invoice) if invoice.amount == final_price => {
+                bot.send_message(msg.chat.id, format!("Счет за посылку {} уже {}", track_code, final_price)).await?;
+
+                return Ok(());
+            },
+            Ok(invoice) => invoice,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let change = PriceOverride { original: invoice.amount, final_price, reason };
+        let operator = msg.from().expect("ERROR: user is unknown");
+        let online = config.payments.provider_token.is_some();
+
+        let notice = |invoice: &Invoice| Notice {
+            telegram_id: invoice.telegram_id,
+            text: flow::override_text(invoice, &change),
+            markup: (online && invoice.due() > Money::default()).then(|| flow::invoice_markup(invoice.parcel_id)),
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        let message = match db.override_price(invoice.parcel_id, &change, operator.id.0 as i64, notice).await {
+            Some(invoice) => format!(
+                "Счет за посылку {} клиента {}: было {}, стало {} ({}), клиент получил новую квитанцию",
+                invoice.track_code, invoice.client_code, change.original, change.final_price, reason.as_str()
+            ),
+            None => format!("Счет за посылку {} изменился, проверьте его и повторите команду", track_code)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// `/overrides [дней]`, price overrides per operator.
+    async fn overrides(bot: Bot, msg: Message, days: String, db: Db) -> HandlerResult {
+        log::info!("Bot: overrides");
+        let days = match days.trim() {
+            "" => OVERRIDE_DAYS,
+            days => match days.parse::<i32>() {
+                Ok(days) if (1..=365).contains(&days) => days,
+                _ => {
+                    bot.send_message(msg.chat.id, "Использование: /overrides [кол-во дней, 1-365]").await?;
+
+                    return Ok(());
+                }
+            }
+        };
+
+        bot.send_message(msg.chat.id, format!(
+            "Изменения цен за {} дн. по операторам:\n<pre>{}</pre>",
+            days,
+            report::render_overrides(&db.get_override_totals(days).await)
+        )).parse_mode(ParseMode::Html).await?;
+
+        Ok(())
+    }
+
+    /// Shows what the marketplace links clients saved with a parcel point to.
+    async fn item(bot: Bot, msg: Message, track_code: String, db: Db) -> HandlerResult {
+        log::info!("Bot: item");
+        let track_code = track_code.trim();
+
+        if track_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /item <трек-код>").await?;
+
+            return Ok(());
+        }
+
+        let items = db.get_parcel_items(track_code).await;
+
+        bot.send_message(msg.chat.id, flow::items_text(track_code, &items)).await?;
+
+        Ok(())
+    }
+
+    /// Shows a quote a client refers to when paying, with the price list version it came from.
+    async fn quote(bot: Bot, msg: Message, id: String, db: Db) -> HandlerResult {
+        log::info!("Bot: quote");
+        let quote = match id.trim().trim_start_matches(['№', '#']).parse() {
+            Ok(id) => db.get_quote(id).await,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /quote <номер расчета>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let quote = match quote {
+            Some(quote) => quote,
+            None => {
+                bot.send_message(msg.chat.id, "Расчет не найден").await?;
+
+                return Ok(());
+            }
+        };
+
+        let client = match db.check_user(quote.telegram_id).await {
+            true => db.get_user(quote.telegram_id).await.client_code,
+            false => format!("не зарегистрирован (id {})", quote.telegram_id)
+        };
+
+        bot.send_message(msg.chat.id, format!("{}\n\nКлиент: {}\nВерсия тарифов: {}", flow::quote_text(&quote), client, quote.tariff_version)).await?;
+
+        Ok(())
+    }
+
+    /// Shows the retention policy with its latest runs, `preview` counts what a run would purge now.
+    async fn retention(bot: Bot, msg: Message, action: String, db: Db) -> HandlerResult {
+        log::info!("Bot: retention");
+        // The policy is only read from the environment, the same way the daily job got it.
+        let policy = RetentionPolicy::from_env();
+
+        match action.trim() {
+            "" => {},
+            "preview" => {
+                db.apply_retention(&policy, true).await;
+            },
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /retention [preview]").await?;
+
+                return Ok(());
+            }
+        }
+
+        let runs = db.get_retention_runs(RETENTION_RUNS).await;
+
+        bot.send_message(msg.chat.id, retention::render_report(&policy, &runs)).await?;
+
+        Ok(())
+    }
+
+    /// The last nightly reconciliation with the parcels that did not match the warehouse.
+    async fn reconciliation(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: reconciliation");
+        let text = match db.get_last_reconciliation().await {
+            Some(run) => reconciliation::render_report(&run, &db.get_discrepancies(run.id).await),
+            None => "Сверка со складом еще не запускалась".to_string()
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+
+    /// Shows daily warehouse intake so that extra trucks are booked before the busy days.
+    async fn capacity(bot: Bot, msg: Message, days: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: capacity");
+        let days = match days.trim() {
+            "" => CAPACITY_DAYS,
+            days => match days.parse::<i32>() {
+                Ok(days) if (1..=90).contains(&days) => days,
+                _ => {
+                    bot.send_message(msg.chat.id, "Использование: /capacity [кол-во дней, 1-90]").await?;
+
+                    return Ok(());
+                }
+            }
+        };
+
+        bot.send_message(msg.chat.id, format!(
+            "Приемка склада за {} дн.:\n<pre>{}</pre>",
+            days,
+            capacity::render_capacity(&db.get_warehouse_stats(days).await, &config.capacity, bishkek_now().date())
+        )).parse_mode(ParseMode::Html).await?;
+
+        Ok(())
+    }
+
+    /// Shows how far bills were from the calculator's quotes, for tuning the tariffs and density bands.
+    async fn accuracy(bot: Bot, msg: Message, days: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: accuracy");
+        let days = match days.trim() {
+            "" => ACCURACY_DAYS,
+            days => match days.parse::<i32>() {
+                Ok(days) if (1..=365).contains(&days) => days,
+                _ => {
+                    bot.send_message(msg.chat.id, "Использование: /accuracy [кол-во дней, 1-365]").await?;
+
+                    return Ok(());
+                }
+            }
+        };
+
+        let text = calibration::render_accuracy(
+            &db.get_quote_accuracy(days).await,
+            &db.get_calibrations(CALIBRATION_LIMIT).await,
+            &config.calibration
+        );
+
+        bot.send_message(msg.chat.id, format!("Точность калькулятора за {} дн.:\n<pre>{}</pre>", days, text))
+            .parse_mode(ParseMode::Html).await?;
+
+        Ok(())
+    }
+
+    /// Sends the customs manifest of a batch as a CSV document for the broker.
+    async fn manifest(bot: Bot, msg: Message, batch_code: String, db: Db, config: Config, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: manifest");
+        if !Self::check_export_permission(&bot, &msg, &db, &config).await? {
+            return Ok(());
+        }
+
+        let batch_code = batch_code.trim().to_uppercase();
+
+        if batch_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /manifest <партия>").await?;
+
+            return Ok(());
+        }
+
+        let rows = db.get_manifest(&batch_code).await;
+
+        if rows.is_empty() {
+            bot.send_message(msg.chat.id, format!("В партии {} нет посылок", batch_code)).await?;
+
+            return Ok(());
+        }
+
+        let summary = format!(
+            "Манифест партии {}: {} посылок с именами и телефонами клиентов",
+            batch_code,
+            rows.len()
+        );
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::Manifest(batch_code)).await
+    }
+
+    /// Sends the sticker of a parcel as a PDF for the thermal printer, shelving it like /shelve when a shelf is given.
+    async fn label(bot: Bot, msg: Message, args: String, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: label");
+        let mut args = args.split_whitespace();
+
+        let (track_code, shelf) = match (args.next(), args.next()) {
+            (Some(track_code), shelf) => (track_code.to_string(), shelf.and_then(flow::shelf_code)),
+            (None, _) => {
+                bot.send_message(msg.chat.id, "Использование: /label <трек-код> [полка]").await?;
+
+                return Ok(());
+            }
+        };
+
+        if let Some(shelf) = &shelf {
+            Self::put_on_shelf(&db, &track_code, shelf).await;
+        }
+
+        let labels = db.get_warehouse_labels(&track_code).await;
+
+        if labels.is_empty() {
+            bot.send_message(msg.chat.id, format!("Посылка {} не найдена среди сохраненных", track_code)).await?;
+
+            return Ok(());
+        }
+
+        let pdf = label::render_pdf(&labels, config.label_font.as_deref().map(|font| font.as_slice()));
+
+        let document = InputFile::memory(pdf).file_name(format!("label-{}.pdf", track_code));
+
+        bot.send_document(msg.chat.id, document)
+            .caption(format!("Этикетка посылки {}: {} шт.", track_code, labels.len()))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn duplicates(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: duplicates");
+        let users = db.get_users().await;
+        let duplicates = duplicates::find_duplicates(&users);
+
+        if duplicates.is_empty() {
+            bot.send_message(msg.chat.id, "Дубликатов не найдено").await?;
+
+            return Ok(());
+        }
+
+        let message = duplicates.iter()
+            .map(|duplicates| {
+                let reason = if duplicates.same_phone { "Один телефон" } else { "Похожие имена" };
+                let lines = duplicates.users.iter().map(|user| user_line(user)).collect::<Vec<_>>().join("\n");
+
+                format!(
+                    "{}:\n{}\n/merge {} {}",
+                    reason,
+                    lines,
+                    duplicates.users[0].client_code,
+                    duplicates.users[1].client_code
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Shows both accounts and asks to confirm, the merge itself happens in [`Self::run_confirmed`].
+    async fn merge(bot: Bot, msg: Message, codes: String, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: merge");
+        let (survivor, duplicate) = match codes.split_whitespace().collect::<Vec<_>>()[..] {
+            [survivor, duplicate] => (survivor.to_string(), duplicate.to_string()),
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /merge <код остающегося> <код дубликата>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let (survivor, duplicate) = match Self::merge_pair(&db, &survivor, &duplicate).await {
+            Ok(pair) => pair,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let parcels = match duplicate.telegram_id {
+            Some(telegram_id) => db.get_parcels(telegram_id).await.len(),
+            None => 0
+        };
+
+        let summary = format!(
+            "Остается:\n{}\n\nБудет удален:\n{}\n\nПосылок к переносу: {}",
+            user_line(&survivor),
+            user_line(&duplicate),
+            parcels
+        );
+
+        let action = Destructive::Merge { survivor: survivor.client_code, duplicate: duplicate.client_code };
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, action).await
+    }
+
+    async fn merge_pair(db: &Db, survivor: &str, duplicate: &str) -> Result<(User, User), String> {
+        let survivor = Self::find_client(db, survivor).await?;
+        let duplicate = Self::find_client(db, duplicate).await?;
+
+        if survivor.id == duplicate.id {
+            return Err("Укажите два разных аккаунта".to_string());
+        }
+
+        // Parcels are followed from Telegram, a walk-in account cannot take them over.
+        if survivor.telegram_id.is_none() && duplicate.telegram_id.is_some() {
+            return Err("Оставьте аккаунт с Telegram, клиент без Telegram не получит уведомления".to_string());
+        }
+
+        Ok((survivor, duplicate))
+    }
+
+    /// Checks a typed client code before the lookup and suggests codes one digit away when it is not found.
+    async fn find_client(db: &Db, input: &str) -> Result<User, String> {
+        let client_code = client_code::parse(input).map_err(|err| err.to_string())?;
+
+        if let Some(user) = db.find_user_by_client_code(&client_code).await {
+            return Ok(user);
+        }
+
+        let suggestions = db.existing_client_codes(&client_code::neighbours(&client_code)).await;
+
+        if suggestions.is_empty() {
+            Err(format!("Клиент {} не найден", client_code))
+        } else {
+            Err(format!("Клиент {} не найден. Возможно, имелся в виду: {}", client_code, suggestions.join(", ")))
+        }
+    }
+}
+
+fn user_line(user: &User) -> String {
+    let contact = match (user.telegram_id, &user.username) {
+        (None, _) => "без Telegram".to_string(),
+        (Some(_), Some(username)) => format!("@{}", username),
+        (Some(_), None) => "без @username".to_string()
+    };
+
+    format!("{} — {}, {}, 📞 {}", user.client_code, user.full_name(), contact, user.phone_number)
+}
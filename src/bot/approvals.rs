@@ -0,0 +1,130 @@
+use teloxide::{requests::Requester, types::{ChatId, Message}};
+
+use crate::{config::Config, database::Db, models::{full_name, RegistrationRequest, Units, User}, retry::Bot, sender::{Priority, SendQueue}};
+
+use super::{BotService, HandlerResult};
+
+impl BotService {
+    /// Sends a registration from an unlisted country to the operators, see [`Self::approve`].
+    pub(super) async fn request_approval(db: &Db, config: &Config, queue: &SendQueue, user: &User) {
+        db.request_registration(user).await;
+
+        let telegram_id = user.telegram_id.expect("ERROR: Registration requests come from Telegram");
+
+        match config.support.operator_chat() {
+            Some(operator_chat) => queue.push(
+                Priority::Interactive,
+                operator_chat,
+                format!("🛂 Заявка на регистрацию:\n\n{}\n\n/approve {} или /reject {}", request_text(user), telegram_id, telegram_id)
+            ),
+            None => log::warn!("SUPPORT_CHAT_ID is not set, registration of {} waits in /approvals", telegram_id)
+        }
+    }
+
+    pub(super) async fn await_approval(bot: Bot, msg: Message) -> HandlerResult {
+        log::info!("Bot: await_approval");
+        bot.send_message(msg.chat.id, "Ваша заявка на регистрацию ещё на проверке. Мы сообщим о решении в этом чате.").await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn approvals(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: approvals");
+        let requests = db.get_registration_requests().await;
+
+        let text = match requests.is_empty() {
+            true => "Заявок на регистрацию нет".to_string(),
+            false => requests.iter()
+                .map(|request| format!("{}\n/approve {} /reject {}", request_text(&user_from(request)), request.telegram_id, request.telegram_id))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn approve(bot: Bot, msg: Message, telegram_id: String, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: approve");
+        let request = match Self::take_request(&db, &telegram_id, "/approve").await {
+            Ok(request) => request,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        db.get_or_create(request.telegram_id, user_from(&request)).await;
+
+        // The next message of the user starts over and lands in the profile.
+        db.remove_dialogue(request.telegram_id).await;
+
+        queue.push(
+            Priority::Interactive,
+            ChatId(request.telegram_id),
+            "✅ Заявка на регистрацию одобрена! Отправьте /start, чтобы открыть личный кабинет.".to_string()
+        );
+
+        bot.send_message(msg.chat.id, format!("Регистрация {} одобрена", full_name(&request.first_name, request.last_name.as_deref()))).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn reject(bot: Bot, msg: Message, telegram_id: String, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: reject");
+        let request = match Self::take_request(&db, &telegram_id, "/reject").await {
+            Ok(request) => request,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        db.remove_dialogue(request.telegram_id).await;
+
+        queue.push(
+            Priority::Interactive,
+            ChatId(request.telegram_id),
+            "К сожалению, заявка на регистрацию отклонена. Если это ошибка, напишите в поддержку.".to_string()
+        );
+
+        bot.send_message(msg.chat.id, format!("Регистрация {} отклонена", full_name(&request.first_name, request.last_name.as_deref()))).await?;
+
+        Ok(())
+    }
+
+    async fn take_request(db: &Db, input: &str, command: &str) -> Result<RegistrationRequest, String> {
+        let telegram_id = input.trim().parse::<i64>()
+            .map_err(|_| format!("Использование: {} <telegram id>", command))?;
+
+        db.take_registration_request(telegram_id).await
+            .ok_or(format!("Заявка {} не найдена или уже рассмотрена", telegram_id))
+    }
+}
+
+fn user_from(request: &RegistrationRequest) -> User {
+    User {
+        id: 0,
+        client_code: String::new(),
+        first_name: request.first_name.clone(),
+        last_name: request.last_name.clone(),
+        phone_number: request.phone_number.clone(),
+        telegram_id: Some(request.telegram_id),
+        username: request.username.clone(),
+        display_name: request.display_name.clone(),
+        text_menu: false,
+        reminders: true,
+        monthly_summary: false,
+        units: Units::Metric,
+        city: None
+    }
+}
+
+fn request_text(user: &User) -> String {
+    let username = user.username.as_ref().map_or(String::new(), |username| format!(" (@{})", username));
+
+    format!("{}{}\nТелефон: +{}", user.full_name(), username, user.phone_number)
+}
@@ -0,0 +1,89 @@
+use teloxide::{requests::Requester, types::{ChatId, InlineKeyboardMarkup, Message, MessageId}};
+
+use crate::{database::Db, models::{BatchStatus, Notice}, retry::Bot};
+
+use super::{flow, render::Rendered, BotService, HandlerResult};
+
+impl BotService {
+    /// `/batch <партия> [loaded|departed|customs|arrived]`, opens the batch or moves it on.
+    ///
+    /// A batch only moves forward, every move is sent to the clients with parcels in it through the outbox.
+    pub(super) async fn batch(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: batch");
+        let mut words = args.split_whitespace();
+
+        let (batch_code, status) = match (words.next(), words.next().map(|status| BatchStatus::try_from(status.to_lowercase()))) {
+            (Some(batch_code), None) => (batch_code.to_uppercase(), None),
+            (Some(batch_code), Some(Ok(status))) => (batch_code.to_uppercase(), Some(status)),
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /batch <партия> [loaded|departed|customs|arrived]").await?;
+
+                return Ok(());
+            }
+        };
+
+        let status = match status {
+            Some(status) => status,
+            None => {
+                let message = match db.create_batch(&batch_code).await {
+                    true => format!("Партия {} создана, добавляйте посылки через /assign или /scan", batch_code),
+                    false => flow::batch_text(&db.get_batch(&batch_code).await.expect("ERROR: Could not get an existing batch"))
+                };
+
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match db.get_batch(&batch_code).await {
+            None => format!("Партия {} не найдена, создайте ее: /batch {}", batch_code, batch_code),
+            Some(batch) if status <= batch.status => format!("Партия {} уже {}", batch_code, batch.status.title()),
+            Some(batch) => {
+                let notice = |telegram_id, track_codes: &[String]| Notice {
+                    telegram_id,
+                    text: flow::batch_status_text(&batch_code, status, track_codes),
+                    markup: None,
+                    photo_id: None,
+                    digest: None,
+                    subject: None
+                };
+
+                match db.advance_batch(&batch_code, batch.status, status, notice).await {
+                    Some(notified) => format!("Партия {}: {}, уведомлено клиентов: {}", batch_code, status.title(), notified),
+                    None => format!("Статус партии {} только что изменился, проверьте его: /batch {}", batch_code, batch_code)
+                }
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn batches(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: batches");
+        bot.send_message(msg.chat.id, flow::batches_text(&db.get_open_batches().await)).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_batch_btn(bot: Bot, telegram_id: i64, chat_id: ChatId, msg_id: MessageId, markup: InlineKeyboardMarkup, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_batch_btn");
+        rendered.edit(&bot, chat_id, msg_id, Self::batch_progress(&db, telegram_id).await, Some(markup)).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn batch_progress(db: &Db, telegram_id: i64) -> String {
+        let mut batches = Vec::new();
+
+        for batch in db.get_user_batches(telegram_id).await {
+            let events = db.get_batch_events(&batch.code).await;
+
+            batches.push((batch, events));
+        }
+
+        flow::batch_progress_text(&batches)
+    }
+}
@@ -0,0 +1,133 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::SendMessageSetters, requests::Requester, types::{CallbackQuery, ChatId, Message}};
+
+use crate::{database::Db, retry::Bot, sender::{Priority, SendQueue}};
+
+use super::{flow, BotService, HandlerResult};
+
+impl BotService {
+    /// Lists canned responses with their usage, or saves one with `/canned <slug> <text>`.
+    pub(super) async fn canned(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: canned");
+        if args.trim().is_empty() {
+            let responses = db.get_canned_responses().await;
+            let usage = db.get_canned_usage().await;
+
+            let message = if responses.is_empty() {
+                "Шаблонов ответов нет. Добавить: /canned <slug латиницей> <текст>, {name} заменяется именем клиента".to_string()
+            } else {
+                responses.iter()
+                    .map(|response| {
+                        let uses = usage.iter()
+                            .filter(|usage| usage.slug == response.slug)
+                            .map(|usage| format!("{} {}", usage.operator, usage.uses))
+                            .collect::<Vec<_>>();
+
+                        let uses = match uses.is_empty() {
+                            true => "не использовался".to_string(),
+                            false => format!("использовали: {}", uses.join(", "))
+                        };
+
+                        format!("{} — {}\n{}", response.slug, response.text, uses)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            };
+
+            bot.send_message(msg.chat.id, message).await?;
+
+            return Ok(());
+        }
+
+        let (slug, text) = match flow::canned_from_command(&args) {
+            Some(canned) => canned,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /canned <slug латиницей> <текст>").await?;
+
+                return Ok(());
+            }
+        };
+
+        db.save_canned_response(&slug, &text).await;
+
+        bot.send_message(msg.chat.id, format!("Шаблон {} сохранен, отправить: /r <номер обращения> {}", slug, slug)).await?;
+
+        Ok(())
+    }
+
+    /// Sends a canned response to the client of a ticket, without a slug shows a picker of all templates.
+    pub(super) async fn reply_canned(bot: Bot, msg: Message, args: String, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: reply_canned");
+        let replied = msg.reply_to_message().and_then(|replied| replied.text());
+
+        let (ticket_id, slug) = match flow::canned_target(&args, replied) {
+            Some(target) => target,
+            None => {
+                bot.send_message(msg.chat.id, "Использование: /r <номер обращения> [шаблон] или ответом на уведомление об обращении").await?;
+
+                return Ok(());
+            }
+        };
+
+        let slug = match slug {
+            Some(slug) => slug,
+            None => {
+                let responses = db.get_canned_responses().await;
+
+                if responses.is_empty() {
+                    bot.send_message(msg.chat.id, "Шаблонов ответов нет, добавьте их через /canned").await?;
+                } else {
+                    bot.send_message(msg.chat.id, format!("Ответ на обращение #{}:", ticket_id))
+                        .reply_markup(flow::canned_markup(ticket_id, &responses))
+                        .await?;
+                }
+
+                return Ok(());
+            }
+        };
+
+        let operator = msg.from().expect("ERROR: user is unknown");
+        let message = Self::send_canned(&db, &queue, ticket_id, &slug, operator.id.0 as i64, &operator.full_name()).await;
+
+        bot.send_message(msg.chat.id, message.unwrap_or_else(|message| message)).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_canned_callback(bot: Bot, q: CallbackQuery, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: handle_canned_callback");
+        let chat_id = q.chat_id().unwrap();
+        let msg_id = q.message.as_ref().expect("ERROR: callback has no message").id;
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        let target = q.data.as_deref()
+            .and_then(|data| data.strip_prefix("canned:"))
+            .and_then(|target| target.split_once(':'))
+            .and_then(|(ticket_id, slug)| Some((ticket_id.parse::<i32>().ok()?, slug)));
+
+        let message = match target {
+            None => "Ответ отменен".to_string(),
+            Some((ticket_id, slug)) => Self::send_canned(&db, &queue, ticket_id, slug, q.from.id.0 as i64, &q.from.full_name()).await
+                .unwrap_or_else(|message| message)
+        };
+
+        bot.edit_message_text(chat_id, msg_id, message).await?;
+
+        Ok(())
+    }
+
+    async fn send_canned(db: &Db, queue: &SendQueue, ticket_id: i32, slug: &str, operator_id: i64, operator: &str) -> Result<String, String> {
+        let template = db.get_canned_response(slug).await
+            .ok_or(format!("Шаблон {} не найден, список: /canned", slug))?;
+        let user = db.get_ticket_user(ticket_id).await
+            .ok_or(format!("Обращение #{} не найдено", ticket_id))?;
+
+        let telegram_id = user.telegram_id
+            .ok_or(format!("У клиента {} нет Telegram", user.client_code))?;
+
+        queue.push(Priority::Interactive, ChatId(telegram_id), flow::fill_canned(&template, &user));
+        db.record_canned_use(slug, ticket_id, operator_id, operator).await;
+
+        Ok(format!("Ответ {} отправлен клиенту {} по обращению #{}", slug, user.client_code, ticket_id))
+    }
+}
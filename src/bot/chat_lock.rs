@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use teloxide::types::{ChatId, Update};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// One async mutex per chat, held for the whole handler run of an update.
+///
+/// The dispatcher already queues updates of a known chat, but callbacks from
+/// inline messages carry no chat and run concurrently; those are keyed by the
+/// user instead, which is the chat id of their private chat with the bot.
+#[derive(Clone, Default)]
+pub(super) struct ChatLocks(Arc<DashMap<ChatId, Arc<Mutex<()>>>>);
+
+/// Released when the update is handled, the map entry goes away with the last waiter.
+pub(super) struct ChatGuard {
+    locks: ChatLocks,
+    chat_id: ChatId,
+    guard: Option<OwnedMutexGuard<()>>
+}
+
+impl ChatLocks {
+    pub(super) async fn lock(&self, chat_id: ChatId) -> ChatGuard {
+        let mutex = self.0.entry(chat_id).or_default().clone();
+
+        ChatGuard { locks: self.clone(), chat_id, guard: Some(mutex.lock_owned().await) }
+    }
+
+    pub(super) async fn lock_update(update: Update, locks: ChatLocks) -> Option<ChatGuard> {
+        let chat_id = update.chat().map(|chat| chat.id)
+            .or_else(|| update.user().map(|user| ChatId(user.id.0 as i64)))?;
+
+        Some(locks.lock(chat_id).await)
+    }
+}
+
+impl Drop for ChatGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+
+        // Nobody else holds the mutex once the map keeps the only reference.
+        self.locks.0.remove_if(&self.chat_id, |_, mutex| Arc::strong_count(mutex) == 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn same_chat_waits_for_the_running_update() {
+        let locks = ChatLocks::default();
+        let guard = locks.lock(ChatId(1)).await;
+
+        let waiting = tokio::spawn({
+            let locks = locks.clone();
+            async move { locks.lock(ChatId(1)).await; }
+        });
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        // Other chats are not held up.
+        locks.lock(ChatId(2)).await;
+
+        drop(guard);
+        waiting.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn released_locks_are_removed() {
+        let locks = ChatLocks::default();
+
+        drop(locks.lock(ChatId(1)).await);
+
+        assert!(locks.0.is_empty());
+    }
+}
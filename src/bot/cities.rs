@@ -0,0 +1,141 @@
+use indoc::indoc;
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{database::Db, models::NewCity, retry::Bot};
+
+use super::{confirm::{Confirmations, Destructive}, flow, BotDialogue, BotService, BotState, CityDraft, HandlerResult};
+
+impl BotService {
+    /// Opens a destination city step by step, nothing is saved until the last step is confirmed.
+    pub(super) async fn start_open_city(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: start_open_city");
+        bot.send_message(msg.chat.id, "Открытие нового города.\nВведите название города одним словом, например Кара-Балта.").await?;
+
+        dialogue.update(BotState::CityName).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_city_name(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_city_name");
+        let name = match flow::city_name(msg.text()) {
+            Some(name) => name,
+            None => {
+                bot.send_message(msg.chat.id, "Введите название города одним словом.").await?;
+
+                return Ok(());
+            }
+        };
+
+        if db.get_planned_etas().await.iter().any(|(city, _, _)| city.to_lowercase() == name.to_lowercase()) {
+            bot.send_message(msg.chat.id, format!("Город {} уже открыт. Введите другое название.", name)).await?;
+
+            return Ok(());
+        }
+
+        bot.send_message(msg.chat.id, indoc!("
+        Введите тарифы города, по одной полосе плотности в строке:
+        <плотность от, кг/м3> <цена за кг, $> <минимум, $>
+        Например:
+        0 3 10
+        200 2,5 10
+        ")).await?;
+
+        dialogue.update(BotState::CityTariffs { name }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_city_tariffs(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_city_tariffs");
+        let name = match dialogue.get().await?.unwrap() {
+            BotState::CityTariffs { name } => name,
+            _ => return Ok(())
+        };
+
+        if flow::city_tariffs(msg.text()).is_none() {
+            bot.send_message(msg.chat.id, "Неверный формат. В каждой строке: плотность, цена за кг и минимум, плотности не повторяются.").await?;
+
+            return Ok(());
+        }
+
+        bot.send_message(msg.chat.id, "Введите срок доставки от склада до города в днях, например 10-14.").await?;
+
+        dialogue.update(BotState::CityEta { name, tariffs: msg.text().unwrap_or_default().to_string() }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_city_eta(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_city_eta");
+        let (name, tariffs) = match dialogue.get().await?.unwrap() {
+            BotState::CityEta { name, tariffs } => (name, tariffs),
+            _ => return Ok(())
+        };
+
+        let eta_days = match flow::city_eta(msg.text()) {
+            Some(eta_days) => eta_days,
+            None => {
+                bot.send_message(msg.chat.id, "Неверный формат. Введите наименьший и наибольший срок, например 10-14.").await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.send_message(msg.chat.id, "Введите адрес пункта выдачи в городе.").await?;
+
+        dialogue.update(BotState::CityPickup { name, tariffs, eta_days }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_city_pickup(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_city_pickup");
+        let (name, tariffs, eta_days) = match dialogue.get().await?.unwrap() {
+            BotState::CityPickup { name, tariffs, eta_days } => (name, tariffs, eta_days),
+            _ => return Ok(())
+        };
+
+        let pickup_point = match msg.text().map(str::trim) {
+            Some(pickup_point) if !pickup_point.is_empty() => pickup_point.to_string(),
+            _ => {
+                bot.send_message(msg.chat.id, "Введите адрес пункта выдачи текстом.").await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.send_message(msg.chat.id, "Введите текст объявления для клиентов города или отправьте «-», чтобы взять готовый.").await?;
+
+        dialogue.update(BotState::CityAnnouncement { draft: Box::new(CityDraft { name, tariffs, eta_days, pickup_point }) }).await?;
+
+        Ok(())
+    }
+
+    /// Ends the dialogue with a confirmation, see [`Self::run_confirmed`].
+    pub(super) async fn receive_city_announcement(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: receive_city_announcement");
+        let CityDraft { name, tariffs, eta_days, pickup_point } = match dialogue.get().await?.unwrap() {
+            BotState::CityAnnouncement { draft } => *draft,
+            _ => return Ok(())
+        };
+
+        let tariffs = flow::city_tariffs(Some(&tariffs)).expect("ERROR: City tariffs were checked at their step");
+
+        let announcement = match msg.text().map(str::trim) {
+            Some("-") => flow::city_announcement(&name, &tariffs, eta_days, &pickup_point),
+            Some(text) if !text.is_empty() => text.to_string(),
+            _ => {
+                bot.send_message(msg.chat.id, "Введите текст объявления или «-».").await?;
+
+                return Ok(());
+            }
+        };
+
+        let city = NewCity { name, tariffs, eta_days, pickup_point, announcement };
+
+        dialogue.exit().await?;
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, flow::city_summary(&city), Destructive::OpenCity(city)).await
+    }
+}
@@ -0,0 +1,132 @@
+use teloxide::{requests::Requester, types::{ChatId, Message}};
+
+use crate::{config::Config, database::Db, models::{full_name, AccountClaim, User}, notifier, retry::Bot, sender::{Priority, SendQueue}};
+
+use super::{flow::{self, Reply}, BotService, HandlerResult};
+
+impl BotService {
+    /// Asks an operator to move the account of `owner` to the registering user with /acceptclaim.
+    ///
+    /// A confirmed phone is not enough on its own, whoever gets a recycled number would take over the account.
+    pub(super) async fn claim_account(db: &Db, config: &Config, queue: &SendQueue, owner: &User, user: &User) -> Reply {
+        let claim = db.request_account_claim(owner.id, user).await;
+
+        match config.support.operator_chat() {
+            Some(operator_chat) => queue.push(
+                Priority::Interactive,
+                operator_chat,
+                format!("🔁 Запрос на перенос аккаунта:\n\n{}\n\n/acceptclaim {} или /denyclaim {}", claim_text(&claim), claim.id, claim.id)
+            ),
+            None => log::warn!("SUPPORT_CHAT_ID is not set, account claim {} waits in /claims", claim.id)
+        }
+
+        flow::claim_pending()
+    }
+
+    pub(super) async fn claims(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: claims");
+        let claims = db.get_account_claims().await;
+
+        let text = match claims.is_empty() {
+            true => "Запросов на перенос аккаунта нет".to_string(),
+            false => claims.iter()
+                .map(|claim| format!("{}\n/acceptclaim {} /denyclaim {}", claim_text(claim), claim.id, claim.id))
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn accept_claim(bot: Bot, msg: Message, id: String, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: accept_claim");
+        let id = match id.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(id) => id,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /acceptclaim <номер запроса>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let operator_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        let claim = match db.approve_account_claim(id, operator_id, notifier::account_moved_notice).await {
+            Some(claim) => claim,
+            None => {
+                bot.send_message(msg.chat.id, format!(
+                    "Запрос {} не найден, уже рассмотрен, или новый аккаунт Telegram успел зарегистрироваться — проверьте /claims",
+                    id
+                )).await?;
+
+                return Ok(());
+            }
+        };
+
+        // The new account is still stuck in registration, its next message opens the profile it took over.
+        db.remove_dialogue(claim.telegram_id).await;
+
+        queue.push(
+            Priority::Interactive,
+            ChatId(claim.telegram_id),
+            format!("✅ Аккаунт {} с посылками и историей перенесен в этот Telegram. Отправьте /start, чтобы открыть личный кабинет.", claim.client_code)
+        );
+
+        bot.send_message(msg.chat.id, format!("Аккаунт {} перенесен на {}", claim.client_code, claim.telegram_id)).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn deny_claim(bot: Bot, msg: Message, id: String, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: deny_claim");
+        let id = match id.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(id) => id,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /denyclaim <номер запроса>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let operator_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        let claim = match db.reject_account_claim(id, operator_id).await {
+            Some(claim) => claim,
+            None => {
+                bot.send_message(msg.chat.id, format!("Запрос {} не найден или уже рассмотрен", id)).await?;
+
+                return Ok(());
+            }
+        };
+
+        db.remove_dialogue(claim.telegram_id).await;
+
+        queue.push(
+            Priority::Interactive,
+            ChatId(claim.telegram_id),
+            "К сожалению, перенос аккаунта отклонен. Если это ошибка, напишите в поддержку.".to_string()
+        );
+
+        bot.send_message(msg.chat.id, format!("Перенос аккаунта {} отклонен", claim.client_code)).await?;
+
+        Ok(())
+    }
+}
+
+fn claim_text(claim: &AccountClaim) -> String {
+    let username = claim.username.as_ref().map_or(String::new(), |username| format!(" (@{})", username));
+    let old = claim.old_telegram_id.map_or("клиент стойки, без Telegram".to_string(), |telegram_id| telegram_id.to_string());
+
+    format!(
+        "#{} {} {}\nСейчас: {}\nНовый аккаунт: {}{}{}",
+        claim.id,
+        claim.client_code,
+        full_name(&claim.first_name, claim.last_name.as_deref()),
+        old,
+        claim.telegram_id,
+        claim.display_name.as_ref().map_or(String::new(), |name| format!(" {}", name)),
+        username
+    )
+}
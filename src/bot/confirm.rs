@@ -0,0 +1,197 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{config::Config, database::Db, models::{NewCity, Tariff}, retry::Bot, sender::SendQueue};
+
+use super::{BotService, HandlerResult};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+const WORDS: [&str; 16] = [
+    "арбуз", "ветер", "гроза", "дрова", "ежевика", "жираф", "замок", "изюм",
+    "кактус", "лимон", "малина", "облако", "пингвин", "ракета", "сирень", "тюльпан"
+];
+
+/// An admin action that cannot be taken back, run only after its confirmation word is typed.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Destructive {
+    Broadcast(i32),
+    SaveTariff(Tariff),
+    DeleteTariff(f32),
+    Manifest(String),
+    ExportClients,
+    OpenCity(NewCity),
+    Merge {
+        survivor: String,
+        duplicate: String
+    },
+    SendSurvey {
+        survey_id: i32,
+        filters: String
+    }
+}
+
+impl Destructive {
+    /// How the action is written to the audit trail.
+    pub(super) fn describe(&self) -> String {
+        match self {
+            Destructive::Broadcast(id) => format!("broadcast #{}", id),
+            Destructive::SaveTariff(tariff) => format!(
+                "tariff {} {} {}",
+                tariff.min_density,
+                tariff.price_per_kg.amount(),
+                tariff.min_charge.amount()
+            ),
+            Destructive::DeleteTariff(min_density) => format!("deletetariff {}", min_density),
+            Destructive::Manifest(batch_code) => format!("manifest {}", batch_code),
+            Destructive::ExportClients => "export".to_string(),
+            Destructive::OpenCity(city) => format!("opencity {}", city.name),
+            Destructive::Merge { survivor, duplicate } => format!("merge {} {}", survivor, duplicate),
+            Destructive::SendSurvey { survey_id, filters } => format!("sendsurvey {} {}", survey_id, filters)
+        }
+    }
+}
+
+/// What became of a typed confirmation word, the pending action is dropped in every case.
+#[derive(Debug, PartialEq)]
+pub(super) enum Answer {
+    Confirmed(Destructive),
+    WrongWord(Destructive),
+    Expired(Destructive)
+}
+
+struct Pending {
+    action: Destructive,
+    word: String,
+    asked_at: Instant
+}
+
+/// The one action every admin is asked to confirm, keyed by the admin rather than the chat
+/// so that other operators talking in a shared chat do not cancel it.
+#[derive(Clone, Default)]
+pub(super) struct Confirmations(Arc<Mutex<HashMap<i64, Pending>>>);
+
+impl Confirmations {
+    /// Replaces whatever the admin was asked before, returns the word to type back.
+    pub(super) fn ask(&self, admin_id: i64, action: Destructive) -> String {
+        let word = WORDS[OsRng.next_u32() as usize % WORDS.len()].to_string();
+
+        self.insert(admin_id, action, word.clone(), Instant::now());
+
+        word
+    }
+
+    fn insert(&self, admin_id: i64, action: Destructive, word: String, asked_at: Instant) {
+        self.0.lock().unwrap().insert(admin_id, Pending { action, word, asked_at });
+    }
+
+    pub(super) fn awaits(&self, admin_id: i64) -> bool {
+        self.0.lock().unwrap().contains_key(&admin_id)
+    }
+
+    pub(super) fn answer(&self, admin_id: i64, text: &str, now: Instant) -> Option<Answer> {
+        let pending = self.0.lock().unwrap().remove(&admin_id)?;
+
+        Some(if now.duration_since(pending.asked_at) > TIMEOUT {
+            Answer::Expired(pending.action)
+        } else if text.trim().to_lowercase() == pending.word {
+            Answer::Confirmed(pending.action)
+        } else {
+            Answer::WrongWord(pending.action)
+        })
+    }
+}
+
+impl BotService {
+    /// Shows what is about to happen and asks for a random word, see [`Self::confirm_action`].
+    pub(super) async fn ask_confirmation(bot: &Bot, msg: &Message, db: &Db, confirmations: &Confirmations, summary: String, action: Destructive) -> HandlerResult {
+        let admin = msg.from().expect("ERROR: user is unknown");
+        let admin_id = admin.id.0 as i64;
+
+        db.record_audit(admin_id, &admin.full_name(), &action.describe(), "requested").await;
+
+        let word = confirmations.ask(admin_id, action);
+
+        bot.send_message(msg.chat.id, format!(
+            "{}\n\nЧтобы подтвердить, отправьте слово «{}» в течение {} секунд",
+            summary,
+            word,
+            TIMEOUT.as_secs()
+        )).await?;
+
+        Ok(())
+    }
+
+    /// Any message of an admin with a pending action answers it, a wrong word cancels the action.
+    pub(super) async fn confirm_action(bot: Bot, msg: Message, db: Db, config: Config, queue: SendQueue, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: confirm_action");
+        let admin = msg.from().expect("ERROR: user is unknown");
+        let admin_id = admin.id.0 as i64;
+
+        let answer = match confirmations.answer(admin_id, msg.text().unwrap_or_default(), Instant::now()) {
+            Some(answer) => answer,
+            None => return Ok(())
+        };
+
+        let (action, outcome) = match &answer {
+            Answer::Confirmed(action) => (action, "confirmed"),
+            Answer::WrongWord(action) => (action, "wrong_word"),
+            Answer::Expired(action) => (action, "expired")
+        };
+
+        log::info!("Admin {} {} {}", admin_id, outcome, action.describe());
+        db.record_audit(admin_id, &admin.full_name(), &action.describe(), outcome).await;
+
+        match answer {
+            Answer::Confirmed(action) => Self::run_confirmed(bot, msg, action, db, config, queue, confirmations).await,
+            Answer::WrongWord(_) => {
+                bot.send_message(msg.chat.id, "Слово не совпало, действие отменено").await?;
+
+                Ok(())
+            },
+            Answer::Expired(_) => {
+                bot.send_message(msg.chat.id, "Время на подтверждение истекло, действие отменено").await?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(word: &str, asked_at: Instant) -> Confirmations {
+        let confirmations = Confirmations::default();
+
+        confirmations.insert(1, Destructive::Manifest("KG-01".to_string()), word.to_string(), asked_at);
+
+        confirmations
+    }
+
+    #[test]
+    fn matching_word_confirms_once() {
+        let now = Instant::now();
+        let confirmations = pending("лимон", now);
+
+        assert!(confirmations.awaits(1));
+        assert!(!confirmations.awaits(2));
+
+        assert_eq!(
+            confirmations.answer(1, " Лимон ", now + Duration::from_secs(10)),
+            Some(Answer::Confirmed(Destructive::Manifest("KG-01".to_string())))
+        );
+        assert_eq!(confirmations.answer(1, "лимон", now), None);
+    }
+
+    #[test]
+    fn wrong_or_late_word_cancels() {
+        let now = Instant::now();
+
+        assert!(matches!(pending("лимон", now).answer(1, "малина", now), Some(Answer::WrongWord(_))));
+        assert!(matches!(pending("лимон", now).answer(1, "лимон", now + Duration::from_secs(61)), Some(Answer::Expired(_))));
+    }
+}
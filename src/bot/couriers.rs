@@ -0,0 +1,179 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::SendMessageSetters, requests::Requester, types::{CallbackQuery, ChatId, Message}};
+
+use crate::{config::Config, database::Db, models::DeliveryClosing, notifier, retry::Bot};
+
+use super::{flow, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    /// `/couriers`, `/couriers add <telegram id> <имя>` and `/couriers remove <telegram id>`.
+    pub(super) async fn couriers(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: couriers");
+        let admin_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let mut args = args.split_whitespace();
+
+        let message = match (args.next(), args.next().and_then(|id| id.parse::<i64>().ok())) {
+            (None, _) => flow::couriers_text(&db.get_couriers().await),
+            (Some("add"), Some(telegram_id)) => {
+                let name = args.collect::<Vec<_>>().join(" ");
+
+                if name.is_empty() {
+                    "Использование: /couriers add <telegram id> <имя>".to_string()
+                } else {
+                    db.add_courier(telegram_id, &name, admin_id).await;
+
+                    format!("{} теперь курьер, задания он увидит по команде /tasks", name)
+                }
+            },
+            (Some("remove"), Some(telegram_id)) => match db.remove_courier(telegram_id).await {
+                true => format!("{} больше не курьер, его доставки вернулись в очередь", telegram_id),
+                false => format!("{} не курьер", telegram_id)
+            },
+            _ => "Использование: /couriers [add <telegram id> <имя> | remove <telegram id>]".to_string()
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn deliveries(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: deliveries");
+        bot.send_message(msg.chat.id, flow::deliveries_text(&db.get_open_deliveries(None).await)).await?;
+
+        Ok(())
+    }
+
+    /// `/dispatch <номер доставки> <telegram id курьера>`, the courier gets the task with the client's contacts.
+    pub(super) async fn dispatch_delivery(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: dispatch");
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (delivery_id, courier_id) = match args[..] {
+            [delivery_id, courier_id] => match (delivery_id.trim_start_matches('#').parse::<i32>(), courier_id.parse::<i64>()) {
+                (Ok(delivery_id), Ok(courier_id)) => (delivery_id, courier_id),
+                _ => (0, 0)
+            },
+            _ => (0, 0)
+        };
+
+        if delivery_id == 0 {
+            bot.send_message(msg.chat.id, "Использование: /dispatch <номер доставки> <telegram id курьера>").await?;
+
+            return Ok(());
+        }
+
+        let delivery = match db.assign_delivery(delivery_id, courier_id).await {
+            Some(delivery) => delivery,
+            None => {
+                bot.send_message(msg.chat.id, format!("Открытая доставка #{} или курьер {} не найдены", delivery_id, courier_id)).await?;
+
+                return Ok(());
+            }
+        };
+
+        let user = db.get_user(delivery.telegram_id).await;
+
+        let message = match bot.send_message(ChatId(courier_id), flow::courier_task_text(&delivery, &user))
+            .reply_markup(flow::delivered_markup(delivery.id))
+            .await {
+            Ok(_) => format!("Доставка #{} передана курьеру", delivery.id),
+            Err(err) => {
+                log::warn!("Could not send delivery #{} to courier {}: {}", delivery.id, courier_id, err);
+
+                format!("Доставка #{} назначена, но курьеру не удалось написать: пусть откроет бота и отправит /tasks", delivery.id)
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// The courier's open deliveries, each with its «Доставлено» button.
+    pub(super) async fn tasks(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: tasks");
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        if !db.is_courier(telegram_id).await {
+            bot.send_message(msg.chat.id, "Команда доступна только курьерам").await?;
+
+            return Ok(());
+        }
+
+        let tasks = db.get_open_deliveries(Some(telegram_id)).await;
+        let mut request = bot.send_message(msg.chat.id, flow::courier_tasks_text(&tasks));
+
+        if !tasks.is_empty() {
+            request = request.reply_markup(flow::courier_tasks_markup(&tasks));
+        }
+
+        request.await?;
+
+        Ok(())
+    }
+
+    /// Task buttons stay in the courier's chat, so they work in any state.
+    pub(super) async fn handle_delivered_btn(bot: Bot, dialogue: BotDialogue, q: CallbackQuery) -> HandlerResult {
+        log::info!("Bot: handle_delivered_btn");
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        match flow::delivered_choice(q.data.as_deref()) {
+            Some(delivery_id) => Self::send_reply(bot, dialogue, q.chat_id().unwrap(), flow::delivery_proof_prompt(delivery_id)).await,
+            None => Ok(())
+        }
+    }
+
+    pub(super) async fn receive_delivery_proof(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: receive_delivery_proof");
+        let delivery_id = match dialogue.get().await?.unwrap() {
+            BotState::DeliveryProof { delivery_id } => delivery_id,
+            _ => return Ok(())
+        };
+
+        // Telegram sends several sizes of a photo, the last one is the largest.
+        let photo_id = match msg.photo().and_then(|sizes| sizes.last()) {
+            Some(size) => size.file.id.clone(),
+            None => return Self::send_reply(bot, dialogue, msg.chat.id, flow::delivery_proof_prompt(delivery_id)).await
+        };
+
+        let courier_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        dialogue.exit().await?;
+
+        Self::complete_delivery(bot, msg.chat.id, delivery_id, courier_id, Some(photo_id), db, &config).await
+    }
+
+    pub(super) async fn handle_delivery_proof(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: handle_delivery_proof");
+        let delivery_id = match dialogue.get().await?.unwrap() {
+            BotState::DeliveryProof { delivery_id } => delivery_id,
+            _ => return Ok(())
+        };
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        dialogue.exit().await?;
+
+        if q.data.as_deref() != Some("delivered_without_photo") {
+            return Ok(());
+        }
+
+        Self::complete_delivery(bot, q.chat_id().unwrap(), delivery_id, q.from.id.0 as i64, None, db, &config).await
+    }
+
+    async fn complete_delivery(bot: Bot, chat_id: ChatId, delivery_id: i32, courier_id: i64, photo_id: Option<String>, db: Db, config: &Config) -> HandlerResult {
+        let closing = db.complete_delivery(delivery_id, courier_id, photo_id.as_deref(), config.payments.require_before_delivery, |delivery| {
+            notifier::delivered_notice(delivery, photo_id.as_deref())
+        }).await;
+
+        let message = match closing {
+            DeliveryClosing::Closed(delivery) => format!("Доставка #{} закрыта, клиент получил уведомление", delivery.id),
+            DeliveryClosing::Unpaid => format!("Посылка доставки #{} не оплачена, не отдавайте ее клиенту до оплаты", delivery_id),
+            DeliveryClosing::NotFound => format!("Доставка #{} не найдена среди Ваших заданий", delivery_id)
+        };
+
+        bot.send_message(chat_id, message).await?;
+
+        Ok(())
+    }
+}
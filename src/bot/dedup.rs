@@ -0,0 +1,96 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use teloxide::types::{ChatId, MessageId};
+
+use super::BotState;
+
+// Telegram delivers an update again soon after it got no answer, an input older than that is not coming back.
+const REDELIVERY: Duration = Duration::from_secs(10 * 60);
+
+struct Seen {
+    msg_id: MessageId,
+    text: Option<String>,
+    state: BotState,
+    at: Instant
+}
+
+/// The last input of every chat, so the same text sent twice is handled once.
+///
+/// Covers a message Telegram delivers again, which keeps its id, and a user double-sending a track code:
+/// the same text in the same dialogue state within `window` is dropped. A zero window turns off the latter.
+/// Chats quiet for longer than both `window` and [`REDELIVERY`] are dropped when a new chat comes in.
+#[derive(Clone)]
+pub(super) struct RecentInputs {
+    window: Duration,
+    seen: Arc<Mutex<HashMap<ChatId, Seen>>>
+}
+
+impl RecentInputs {
+    pub(super) fn new(window: Duration) -> RecentInputs {
+        RecentInputs { window, seen: Arc::default() }
+    }
+
+    /// Remembers the input unless it repeats the previous one.
+    pub(super) fn is_repeat(&self, chat_id: ChatId, msg_id: MessageId, text: Option<&str>, state: &BotState) -> bool {
+        self.is_repeat_at(chat_id, msg_id, text, state, Instant::now())
+    }
+
+    fn is_repeat_at(&self, chat_id: ChatId, msg_id: MessageId, text: Option<&str>, state: &BotState, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+
+        let repeat = seen.get(&chat_id).is_some_and(|last| {
+            last.msg_id == msg_id
+                || (text.is_some() && last.text.as_deref() == text && last.state == *state && now.duration_since(last.at) < self.window)
+        });
+
+        if !repeat && seen.insert(chat_id, Seen { msg_id, text: text.map(str::to_string), state: state.clone(), at: now }).is_none() {
+            let keep = self.window.max(REDELIVERY);
+
+            seen.retain(|_, last| now.duration_since(last.at) < keep);
+        }
+
+        repeat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_text_in_same_state_is_dropped_within_window() {
+        let recent = RecentInputs::new(Duration::from_secs(5));
+        let state = BotState::ProductStatus { msg_id: MessageId(1) };
+        let now = Instant::now();
+
+        assert!(!recent.is_repeat_at(ChatId(1), MessageId(10), Some("YT1"), &state, now));
+        assert!(recent.is_repeat_at(ChatId(1), MessageId(11), Some("YT1"), &state, now + Duration::from_secs(1)));
+        assert!(!recent.is_repeat_at(ChatId(2), MessageId(11), Some("YT1"), &state, now + Duration::from_secs(1)));
+        assert!(!recent.is_repeat_at(ChatId(1), MessageId(12), Some("YT1"), &BotState::PriceWidth, now + Duration::from_secs(1)));
+        assert!(!recent.is_repeat_at(ChatId(1), MessageId(13), Some("YT1"), &BotState::PriceWidth, now + Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn redelivered_message_is_dropped_in_any_state() {
+        let recent = RecentInputs::new(Duration::ZERO);
+        let now = Instant::now();
+
+        assert!(!recent.is_repeat_at(ChatId(1), MessageId(10), None, &BotState::Start, now));
+        assert!(recent.is_repeat_at(ChatId(1), MessageId(10), None, &BotState::RegisterFirstName, now));
+        assert!(!recent.is_repeat_at(ChatId(1), MessageId(11), None, &BotState::RegisterFirstName, now));
+    }
+
+    #[test]
+    fn quiet_chats_are_forgotten() {
+        let recent = RecentInputs::new(Duration::from_secs(5));
+        let now = Instant::now();
+
+        recent.is_repeat_at(ChatId(1), MessageId(10), Some("YT1"), &BotState::Start, now);
+        recent.is_repeat_at(ChatId(2), MessageId(20), Some("YT2"), &BotState::Start, now + Duration::from_secs(60));
+        assert_eq!(recent.seen.lock().unwrap().len(), 2);
+
+        recent.is_repeat_at(ChatId(3), MessageId(30), Some("YT3"), &BotState::Start, now + REDELIVERY);
+        assert!(!recent.seen.lock().unwrap().contains_key(&ChatId(1)));
+        assert!(recent.seen.lock().unwrap().contains_key(&ChatId(2)));
+    }
+}
@@ -0,0 +1,36 @@
+use teloxide::{payloads::SendPhotoSetters, requests::Requester, types::{ChatId, InputFile}};
+
+use crate::{database::Db, retry::Bot};
+
+use super::{flow, BotService};
+
+// The box with its sides lettered, shown before the first price prompt.
+const BOX_DIAGRAM: &[u8] = include_bytes!("../../assets/box_dimensions.png");
+
+const FILE_ID_KEY: &str = "box_diagram_file_id";
+
+impl BotService {
+    /// Uploads the picture once and sends Telegram's file id after that.
+    ///
+    /// The picture only helps, so a failure is logged and the price flow goes on without it.
+    pub(super) async fn send_box_diagram(bot: &Bot, db: &Db, chat_id: ChatId) {
+        if let Some(file_id) = db.get_setting(FILE_ID_KEY).await {
+            match bot.send_photo(chat_id, InputFile::file_id(file_id)).caption(flow::BOX_DIAGRAM_CAPTION).await {
+                Ok(_) => return,
+                // File ids belong to the bot token, after a new token the picture is uploaded again.
+                Err(err) => log::warn!("Could not send the cached box diagram, uploading it again: {}", err)
+            }
+        }
+
+        let photo = InputFile::memory(BOX_DIAGRAM).file_name("box_dimensions.png");
+
+        match bot.send_photo(chat_id, photo).caption(flow::BOX_DIAGRAM_CAPTION).await {
+            Ok(msg) => {
+                if let Some(size) = msg.photo().and_then(|sizes| sizes.last()) {
+                    db.set_setting(FILE_ID_KEY, &size.file.id).await;
+                }
+            },
+            Err(err) => log::warn!("Could not send the box diagram: {}", err)
+        }
+    }
+}
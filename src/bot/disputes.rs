@@ -0,0 +1,99 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::AnswerCallbackQuerySetters, requests::Requester, types::{CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId}};
+
+use crate::{config::Config, database::Db, retry::Bot, sender::SendQueue};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn handle_dispute_btn(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_dispute_btn");
+        let parcel_id = q.data.as_deref().and_then(|data| data.strip_prefix("dispute:")).and_then(|id| id.parse::<i32>().ok());
+        let telegram_id = q.from.id.0 as i64;
+
+        // Callback data comes from the client, so the parcel must be theirs and weighed.
+        let track_code = match parcel_id {
+            Some(parcel_id) => db.get_parcels(telegram_id).await.into_iter()
+                .find(|parcel| parcel.id == parcel_id)
+                .map(|parcel| parcel.track_code),
+            None => None
+        };
+
+        let track_code = match track_code {
+            Some(track_code) if db.get_shipment(telegram_id, &track_code).await.is_some() => track_code,
+            _ => {
+                bot.answer_callback_query(q.id).text("Посылка не найдена").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        Self::send_reply(bot, dialogue, q.chat_id().unwrap(), flow::dispute_prompt(&track_code)).await
+    }
+
+    pub(super) async fn receive_dispute_input(bot: Bot, dialogue: BotDialogue, msg: Message, config: Config) -> HandlerResult {
+        log::info!("Bot: receive_dispute_input");
+        let (track_code, comment, attachments) = match dialogue.get().await?.unwrap() {
+            BotState::WeightDispute { track_code, comment, attachments } => (track_code, comment, attachments),
+            _ => (String::new(), String::new(), Vec::new())
+        };
+
+        let attachment = config.attachments.accept(&msg, attachments.len());
+        let text = msg.text().or(msg.caption());
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::dispute_input(track_code, comment, attachments, text, attachment)).await
+    }
+
+    /// Opens the dispute ticket and forwards the files to the operator chat.
+    pub(super) async fn handle_dispute(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, config: Config, queue: SendQueue, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_dispute");
+        let (track_code, comment, attachments) = match dialogue.get().await?.unwrap() {
+            BotState::WeightDispute { track_code, comment, attachments } => (track_code, comment, attachments),
+            _ => (String::new(), String::new(), Vec::new())
+        };
+
+        // Every input is answered with a new prompt, the buttons pressed are on the latest one.
+        let msg_id = q.message.as_ref().map_or(MessageId(0), |msg| msg.id);
+
+        if q.data.as_deref() != Some("dispute_send_btn") {
+            return Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), Some(msg_id)).await;
+        }
+
+        if comment.is_empty() && attachments.is_empty() {
+            bot.answer_callback_query(q.id).text("Добавьте описание или фото").show_alert(true).await?;
+
+            return Ok(());
+        }
+
+        let telegram_id = q.from.id.0 as i64;
+
+        let shipment = match db.get_shipment(telegram_id, &track_code).await {
+            Some(shipment) => shipment,
+            None => {
+                bot.answer_callback_query(q.id).text("Посылка не найдена").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        let text = flow::dispute_ticket_text(&shipment, &comment, attachments.len());
+        let ticket_id = db.open_weight_dispute(telegram_id, &track_code, &text, &attachments).await;
+
+        Self::notify_operators(&db, &config, &queue, telegram_id, ticket_id, &text).await;
+
+        if let Some(operator_chat) = config.support.operator_chat() {
+            Self::send_attachments(&bot, operator_chat, &attachments, &format!("Обращение #{}, посылка {}", ticket_id, track_code)).await?;
+        }
+
+        let markup = InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")
+        ]]);
+
+        rendered.edit(&bot, q.chat_id().unwrap(), msg_id, flow::dispute_opened_text(ticket_id, &track_code), Some(markup)).await?;
+
+        dialogue.update(BotState::Profile { msg_id }).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use teloxide::types::{ChatId, MessageId};
+
+use super::BotState;
+
+// A typo is fixed within minutes, an older rejected input is not waited on anymore.
+const TTL: Duration = Duration::from_secs(60 * 60);
+
+struct Input {
+    msg_id: MessageId,
+    state: BotState,
+    received_at: Instant
+}
+
+/// The last message of every chat together with the state it was received in.
+///
+/// An edit is only replayed when it targets that message and the state did not move
+/// since, i.e. the bot rejected the input and is still waiting for it. Chats quiet for
+/// longer than [`TTL`] are dropped when a new chat comes in, so the map stays small.
+#[derive(Clone, Default)]
+pub(super) struct LastInput(Arc<Mutex<HashMap<ChatId, Input>>>);
+
+impl LastInput {
+    pub(super) fn remember(&self, chat_id: ChatId, msg_id: MessageId, state: BotState) {
+        let mut inputs = self.0.lock().unwrap();
+
+        if inputs.insert(chat_id, Input { msg_id, state, received_at: Instant::now() }).is_none() {
+            inputs.retain(|_, input| input.received_at.elapsed() < TTL);
+        }
+    }
+
+    pub(super) fn awaits(&self, chat_id: ChatId, msg_id: MessageId, state: &BotState) -> bool {
+        self.0.lock().unwrap()
+            .get(&chat_id)
+            .is_some_and(|last| last.msg_id == msg_id && last.state == *state && last.received_at.elapsed() < TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_of_rejected_input_is_replayed() {
+        let last = LastInput::default();
+        let state = BotState::RegisterPhoneNumber { first_name: "Айбек".to_string(), last_name: Some("Осмонов".to_string()) };
+
+        last.remember(ChatId(1), MessageId(10), state.clone());
+
+        assert!(last.awaits(ChatId(1), MessageId(10), &state));
+        assert!(!last.awaits(ChatId(1), MessageId(9), &state));
+        assert!(!last.awaits(ChatId(2), MessageId(10), &state));
+    }
+
+    #[test]
+    fn edit_of_accepted_input_is_ignored() {
+        let last = LastInput::default();
+
+        // The last name was accepted, so the dialogue moved on to the phone number.
+        last.remember(ChatId(1), MessageId(10), BotState::RegisterLastName { first_name: "Айбек".to_string() });
+
+        let state = BotState::RegisterPhoneNumber { first_name: "Айбек".to_string(), last_name: Some("Осмонов".to_string()) };
+
+        assert!(!last.awaits(ChatId(1), MessageId(10), &state));
+    }
+
+    #[test]
+    fn quiet_chats_are_forgotten() {
+        let last = LastInput::default();
+        let state = BotState::RegisterLastName { first_name: "Айбек".to_string() };
+
+        last.0.lock().unwrap().insert(ChatId(1), Input { msg_id: MessageId(10), state: state.clone(), received_at: Instant::now() - TTL });
+        assert!(!last.awaits(ChatId(1), MessageId(10), &state));
+
+        last.remember(ChatId(2), MessageId(20), state);
+        assert!(!last.0.lock().unwrap().contains_key(&ChatId(1)));
+    }
+}
@@ -0,0 +1,106 @@
+use serde_json::Value;
+use teloxide::types::{MediaKind, Message, MessageKind, Update, UpdateKind};
+
+use crate::models::ErrorReport;
+
+use super::BotState;
+
+/// The staff alert about a failed update, the code is the one the user was shown.
+pub(super) fn alert_text(id: i32, report: &ErrorReport) -> String {
+    format!(
+        "⚠️ Ошибка #{} (код {}) у {}\nСостояние: {}\nВвод: {}\n{}",
+        id,
+        report.correlation_id,
+        report.telegram_id.map_or("неизвестного пользователя".to_string(), |id| id.to_string()),
+        report.state.as_deref().unwrap_or("нет"),
+        report.input,
+        report.error
+    )
+}
+
+/// The dialogue state with every text replaced by its length: names, phones and addresses stay out of the report,
+/// the variant and its ids, numbers and flags are enough to replay it.
+pub(super) fn snapshot(state: &BotState) -> String {
+    match serde_json::to_value(state).expect("ERROR: Could not serialize a dialogue state") {
+        // A state without fields is its name.
+        Value::String(name) => name,
+        value => mask(value).to_string()
+    }
+}
+
+fn mask(value: Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(format!("<{} симв.>", text.chars().count())),
+        Value::Array(values) => Value::Array(values.into_iter().map(mask).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, value)| (key, mask(value))).collect()),
+        value => value
+    }
+}
+
+/// What the user sent, commands and button data as they are, typed text only by its length.
+pub(super) fn describe_input(update: &Update) -> String {
+    match &update.kind {
+        UpdateKind::Message(msg) => describe_message(msg),
+        UpdateKind::EditedMessage(msg) => format!("правка: {}", describe_message(msg)),
+        UpdateKind::CallbackQuery(q) => format!("кнопка {}", q.data.as_deref().unwrap_or("без данных")),
+        _ => "другое обновление".to_string()
+    }
+}
+
+fn describe_message(msg: &Message) -> String {
+    let media = match &msg.kind {
+        MessageKind::Common(common) => &common.media_kind,
+        _ => return "служебное сообщение".to_string()
+    };
+
+    match media {
+        // Only the command itself, its arguments may be a phone or a name.
+        MediaKind::Text(text) if text.text.starts_with('/') => {
+            let command = text.text.split_whitespace().next().unwrap_or_default();
+
+            match text.text.len() > command.len() {
+                true => format!("команда {} с аргументами", command),
+                false => format!("команда {}", command)
+            }
+        },
+        MediaKind::Text(text) => format!("текст, {} симв.", text.text.chars().count()),
+        MediaKind::Photo(_) => "фото".to_string(),
+        MediaKind::Document(_) => "документ".to_string(),
+        MediaKind::Video(_) => "видео".to_string(),
+        MediaKind::Voice(_) => "голосовое".to_string(),
+        MediaKind::Contact(_) => "контакт".to_string(),
+        MediaKind::Location(_) => "геопозиция".to_string(),
+        MediaKind::Sticker(_) => "стикер".to_string(),
+        _ => "другое сообщение".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_keep_the_shape_but_not_the_text() {
+        let state = BotState::RegisterPhoneNumber { first_name: "Айбек".to_string(), last_name: Some("Осмонов".to_string()) };
+        let snapshot = snapshot(&state);
+
+        assert!(snapshot.starts_with("{\"RegisterPhoneNumber\":"), "{}", snapshot);
+        assert!(snapshot.contains("\"first_name\":\"<5 симв.>\""));
+        assert!(snapshot.contains("\"last_name\":\"<7 симв.>\""));
+        assert!(!snapshot.contains("Айбек"));
+
+        assert_eq!(super::snapshot(&BotState::DeliveryProof { delivery_id: 7 }), "{\"DeliveryProof\":{\"delivery_id\":7}}");
+        assert_eq!(super::snapshot(&BotState::AddressCity), "AddressCity");
+
+        let report = ErrorReport {
+            telegram_id: Some(42),
+            correlation_id: "0000abcd".to_string(),
+            state: Some(snapshot),
+            input: "текст, 12 симв.".to_string(),
+            error: "A timeout".to_string()
+        };
+        let text = alert_text(3, &report);
+        assert!(text.starts_with("⚠️ Ошибка #3 (код 0000abcd) у 42\nСостояние: {\"RegisterPhoneNumber\""));
+        assert!(text.ends_with("\nВвод: текст, 12 симв.\nA timeout"));
+    }
+}
@@ -0,0 +1,132 @@
+use teloxide::{payloads::SendDocumentSetters, requests::Requester, types::{InputFile, Message}};
+
+use crate::{client_list, config::Config, database::Db, manifest, models::Permission, retry::Bot, support::bishkek_now};
+
+use super::{admin::AdminCommand, confirm::{Confirmations, Destructive}, BotService, HandlerResult};
+
+/// A CSV file with clients' personal data on its way to an admin.
+pub(super) struct ExportFile {
+    pub(super) kind: &'static str,
+    pub(super) file_name: String,
+    pub(super) caption: String,
+    pub(super) csv: String,
+    pub(super) row_count: usize
+}
+
+impl BotService {
+    /// Commands that hand out personal data or the right to it.
+    pub(super) async fn handle_export_command(bot: Bot, msg: Message, cmd: AdminCommand, db: Db, config: Config, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: handle_export_command");
+        match cmd {
+            AdminCommand::Export => Self::export_clients(bot, msg, db, config, confirmations).await,
+            AdminCommand::Grant(args) => Self::change_permission(bot, msg, args, db, config, true).await,
+            AdminCommand::Revoke(args) => Self::change_permission(bot, msg, args, db, config, false).await,
+            _ => Ok(())
+        }
+    }
+
+    /// Tells the admin off when they have no export permission, the owner always has it.
+    pub(super) async fn check_export_permission(bot: &Bot, msg: &Message, db: &Db, config: &Config) -> Result<bool, teloxide::RequestError> {
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        if config.is_owner(telegram_id) || db.has_permission(telegram_id, Permission::Export).await {
+            return Ok(true);
+        }
+
+        bot.send_message(msg.chat.id, "Выгрузка персональных данных доступна только с разрешением export, его выдает владелец бота.").await?;
+
+        Ok(false)
+    }
+
+    /// Logs the export before the file leaves, and watermarks it when EXPORT_WATERMARK is on.
+    pub(super) async fn send_export(bot: &Bot, msg: &Message, db: &Db, config: &Config, file: ExportFile) -> HandlerResult {
+        let admin_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let export_id = db.log_export(admin_id, file.kind, file.row_count).await;
+
+        log::info!("Bot: export #{} of {} rows of {} by {}", export_id, file.row_count, file.kind, admin_id);
+
+        let csv = match config.export_watermark {
+            true => manifest::watermark(
+                file.csv,
+                &format!("Выгрузка №{}, администратор {}, {}", export_id, admin_id, bishkek_now().format("%d.%m.%Y %H:%M"))
+            ),
+            false => file.csv
+        };
+
+        bot.send_document(msg.chat.id, InputFile::memory(csv.into_bytes()).file_name(file.file_name))
+            .caption(format!("{}\nВыгрузка №{}", file.caption, export_id))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Asks to confirm the export first like the manifest, both carry the phones of every client in them.
+    async fn export_clients(bot: Bot, msg: Message, db: Db, config: Config, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: export_clients");
+        if !Self::check_export_permission(&bot, &msg, &db, &config).await? {
+            return Ok(());
+        }
+
+        let summary = "Выгрузка всех клиентов с именами и телефонами".to_string();
+
+        Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, Destructive::ExportClients).await
+    }
+
+    pub(super) async fn clients_export(db: &Db) -> ExportFile {
+        let users = db.get_users().await;
+
+        ExportFile {
+            kind: "clients",
+            file_name: format!("clients-{}.csv", bishkek_now().format("%Y-%m-%d")),
+            caption: format!("Клиенты: {}", users.len()),
+            csv: client_list::render_csv(&users),
+            row_count: users.len()
+        }
+    }
+
+    /// `/grant <telegram id> export` and `/revoke`, only the owner may run them.
+    async fn change_permission(bot: Bot, msg: Message, args: String, db: Db, config: Config, grant: bool) -> HandlerResult {
+        log::info!("Bot: change_permission");
+        let owner_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        if !config.is_owner(owner_id) {
+            bot.send_message(msg.chat.id, "Разрешения выдает только владелец бота (OWNER_ID).").await?;
+
+            return Ok(());
+        }
+
+        let args: Vec<&str> = args.split_whitespace().collect();
+
+        let (telegram_id, permission) = match args[..] {
+            [telegram_id, permission] => (telegram_id.parse::<i64>().ok(), Permission::try_from(permission.to_lowercase()).ok()),
+            _ => (None, None)
+        };
+
+        let (telegram_id, permission) = match (telegram_id, permission) {
+            (Some(telegram_id), Some(permission)) => (telegram_id, permission),
+            _ => {
+                let command = if grant { "grant" } else { "revoke" };
+
+                bot.send_message(msg.chat.id, format!("Использование: /{} <telegram id администратора> export", command)).await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = if grant {
+            match db.grant_permission(telegram_id, permission, owner_id).await {
+                true => format!("Разрешение {} выдано {}", permission.as_str(), telegram_id),
+                false => format!("У {} уже есть разрешение {}", telegram_id, permission.as_str())
+            }
+        } else {
+            match db.revoke_permission(telegram_id, permission).await {
+                true => format!("Разрешение {} отозвано у {}", permission.as_str(), telegram_id),
+                false => format!("У {} нет разрешения {}", telegram_id, permission.as_str())
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+}
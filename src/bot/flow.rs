@@ -0,0 +1,3000 @@
+use chrono::NaiveDateTime;
+use indoc::indoc;
+use reqwest::Url;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId};
+
+use crate::{attachments::Attachment, carrier::{self, Carrier}, catalog::{self, Item}, china_address, experiments::WelcomeVariant, models::{Address, Batch, BatchEvent, BatchStatus, BoxPreset, CannedResponse, Courier, CourierDelivery, DeliveryTask, Invoice, InvoiceStatus, NewCity, OverrideReason, Parcel, ParcelItem, PriceOverride, PurchaseRequest, PurchaseStatus, Quote, Shipment, Survey, SurveyAnswer, SurveyQuestion, Tariff, Tutorial, Units, User, WarehouseLabel, full_name, MAX_ADDRESSES}, money::Money, phone_policy, sms, translit};
+
+use super::{AddressDraft, BotState, PhoneDraft};
+
+// Density (kg/m3) from which the price is calculated by weight.
+const DENSITY_THRESHOLD: f32 = 100_f32;
+
+const MAX_ADDRESS_PART: usize = 100;
+
+// Centimeters or kilograms as well as inches or pounds, nothing shipped comes close.
+const MAX_DIMENSION: f32 = 1000_f32;
+
+const MAX_LABEL_LENGTH: usize = 64;
+
+const MAX_SLUG_LENGTH: usize = 32;
+
+const MAX_PURCHASE_OPTIONS_LENGTH: usize = 200;
+
+pub(super) const QUOTES_LIMIT: i64 = 10;
+
+pub(super) const MAX_BOX_PRESETS: i64 = 5;
+
+pub(super) const MAX_SURVEY_QUESTIONS: usize = 5;
+
+const MAX_SURVEY_OPTIONS: usize = 6;
+
+const MAX_SURVEY_ANSWER_LENGTH: usize = 1000;
+
+// Typed answers shown in the results, the rest are only counted.
+const SURVEY_RESULT_TEXTS: usize = 10;
+
+const REFUND_REASONS: &[&str] = &[
+    "Передумал(а) покупать",
+    "Продавец не отправил товар",
+    "Товар поврежден или не тот",
+    "Другое"
+];
+
+// Profile menu as (label, callback data), one inner slice per keyboard row.
+const MENU: &[&[(&str, &str)]] = &[
+    &[("Отслеживание товара", "locate_btn")],
+    &[("Мои посылки", "parcels_btn")],
+    &[("Высчитывание цены", "price_btn")],
+    &[("Код", "code_btn"), ("Адрес", "address_btn")],
+    &[("Тех. поддержка", "service_btn"), ("Инструкция", "tutorial_btn")],
+    &[("Проверка адреса продавца", "seller_check_btn"), ("Тарифы", "tariffs_btn")],
+    &[("Мои расчёты", "quotes_btn"), ("Настройки", "settings_btn")],
+    &[("Выкуп товара", "purchase_btn"), ("Где моя партия?", "batch_btn")]
+];
+
+// In the text menu the settings entry is replaced by the way back to buttons.
+const TEXT_MENU_EXIT: (&str, &str) = ("Вернуть меню с кнопками", "buttons_btn");
+
+pub(super) const TEXT_MENU_HINT: &str = "Отправьте 0, чтобы вернуться в меню.";
+
+/// What a handler should answer with and which state the dialogue moves to.
+///
+/// States carrying a `msg_id` hold a placeholder until the reply is sent,
+/// see [`BotState::with_msg_id`].
+#[derive(Debug, PartialEq)]
+pub(super) struct Reply {
+    pub text: String,
+    pub markup: Option<InlineKeyboardMarkup>,
+    pub state: BotState
+}
+
+impl Reply {
+    fn new(text: impl Into<String>, state: BotState) -> Reply {
+        Reply { text: text.into(), markup: None, state }
+    }
+
+    fn with_markup(mut self, markup: InlineKeyboardMarkup) -> Reply {
+        self.markup = Some(markup);
+        self
+    }
+}
+
+impl BotState {
+    /// Fills the message id of states that point at the last sent message.
+    pub(super) fn with_msg_id(self, msg_id: MessageId) -> BotState {
+        match self {
+            BotState::Profile { .. } => BotState::Profile { msg_id },
+            BotState::ProfilePages { .. } => BotState::ProfilePages { msg_id },
+            BotState::ProductStatus { .. } => BotState::ProductStatus { msg_id },
+            BotState::Tutorial { .. } => BotState::Tutorial { msg_id },
+            BotState::TrackResult { track_code, .. } => BotState::TrackResult { msg_id, track_code },
+            BotState::Parcels { .. } => BotState::Parcels { msg_id },
+            BotState::Settings { .. } => BotState::Settings { msg_id },
+            BotState::Addresses { .. } => BotState::Addresses { msg_id },
+            BotState::Support { .. } => BotState::Support { msg_id },
+            BotState::SellerCheck { .. } => BotState::SellerCheck { msg_id },
+            BotState::PurchaseLink { .. } => BotState::PurchaseLink { msg_id },
+            BotState::RefundReason { track_code, .. } => BotState::RefundReason { msg_id, track_code },
+            state => state
+        }
+    }
+}
+
+fn back_markup(text: &str) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(text, "back_btn")]])
+}
+
+fn placeholder() -> MessageId {
+    MessageId(0)
+}
+
+pub(super) fn profile_text(user: &User) -> String {
+    format!(
+    indoc!(r#"
+    Ваш профиль:
+
+    📃 Клиентский код: {}
+    👤 Имя: {}
+    {}📞 Номер тел: {}
+    "#),
+    &user.client_code,
+    &user.first_name,
+    user.last_name.as_ref().map_or(String::new(), |last_name| format!("👤 Фамилия: {}\n", last_name)),
+    &user.phone_number)
+}
+
+pub(super) fn welcome_text(variant: WelcomeVariant) -> &'static str {
+    match variant {
+        WelcomeVariant::A => indoc!(r#"
+        Добро пожаловать в MaxExpress! 😊
+                        
+        У нас Вы можете:
+                        
+        1) Отслеживать статус доставки 🚚
+        2) Получить свой клиентский код 💼
+        3) Узнать способы оплаты 💳 (по весу или по плотности)
+        "#),
+        WelcomeVariant::B => indoc!("
+        MaxExpress — доставка товаров из Китая 🇨🇳
+
+        Регистрация займет минуту: имя, фамилия и телефон.
+        После нее Вы сразу получите клиентский код и адрес склада для продавцов.
+        ")
+    }
+}
+
+pub(super) fn welcome_markup(variant: WelcomeVariant) -> InlineKeyboardMarkup {
+    let label = match variant {
+        WelcomeVariant::A => "Начать",
+        WelcomeVariant::B => "🚀 Получить клиентский код"
+    };
+
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(label, "start_btn")]])
+}
+
+/// The address book under the settings, each address with its delete button.
+pub(super) fn addresses(addresses: &[Address]) -> Reply {
+    let text = match addresses.is_empty() {
+        true => "🏠 Адреса доставки\n\nАдресов пока нет. Добавьте адрес, и оператор сможет отправить посылку курьером.".to_string(),
+        false => format!(
+            "🏠 Адреса доставки\n\n{}",
+            addresses.iter().enumerate().map(|(i, address)| format!("{}. {}", i + 1, address.line())).collect::<Vec<_>>().join("\n")
+        )
+    };
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = (1..=addresses.len()).zip(addresses)
+        .map(|(number, address)| vec![InlineKeyboardButton::callback(format!("🗑 Удалить адрес {}", number), format!("delete_address:{}", address.id))])
+        .collect();
+
+    if addresses.len() < MAX_ADDRESSES {
+        rows.push(vec![InlineKeyboardButton::callback("Добавить адрес", "add_address_btn")]);
+    }
+
+    rows.push(vec![InlineKeyboardButton::callback("Назад", "back_btn")]);
+
+    Reply::new(text, BotState::Addresses { msg_id: placeholder() }).with_markup(InlineKeyboardMarkup::new(rows))
+}
+
+pub(super) const ADDRESS_CITY_PROMPT: &str = "Напишите город или село доставки.";
+
+fn address_part(text: Option<&str>) -> Option<String> {
+    text.map(str::trim)
+        .filter(|text| !text.is_empty() && text.chars().count() <= MAX_ADDRESS_PART)
+        .map(str::to_string)
+}
+
+/// «-» skips the optional parts of an address.
+fn optional_address_part(text: Option<&str>) -> Option<Option<String>> {
+    match text.map(str::trim) {
+        Some("-") => Some(None),
+        text => address_part(text).map(Some)
+    }
+}
+
+pub(super) fn address_city(text: Option<&str>) -> Reply {
+    match address_part(text) {
+        Some(city) => Reply::new("Напишите улицу, дом и квартиру.", BotState::AddressStreet { city }),
+        None => Reply::new(format!("Неверный формат.\n{}", ADDRESS_CITY_PROMPT), BotState::AddressCity)
+    }
+}
+
+pub(super) fn address_street(city: String, text: Option<&str>) -> Reply {
+    match address_part(text) {
+        Some(street) => Reply::new(
+            "Напишите номер подъезда или отправьте «-», если его нет.",
+            BotState::AddressEntrance { city, street }
+        ),
+        None => Reply::new("Неверный формат.\nНапишите улицу, дом и квартиру.", BotState::AddressStreet { city })
+    }
+}
+
+pub(super) fn address_entrance(city: String, street: String, text: Option<&str>) -> Reply {
+    match optional_address_part(text) {
+        Some(entrance) => Reply::new(
+            "Добавьте комментарий для курьера, например этаж или ориентир, или отправьте «-».",
+            BotState::AddressComment { draft: Box::new(AddressDraft { city, street, entrance }) }
+        ),
+        None => Reply::new("Неверный формат.\nНапишите номер подъезда или «-».", BotState::AddressEntrance { city, street })
+    }
+}
+
+/// The comment of the address to save, or the reply asking for it again.
+pub(super) fn address_comment(draft: AddressDraft, text: Option<&str>) -> Result<(AddressDraft, Option<String>), Reply> {
+    match optional_address_part(text) {
+        Some(comment) => Ok((draft, comment)),
+        None => Err(Reply::new("Неверный формат.\nНапишите комментарий или «-».", BotState::AddressComment { draft: Box::new(draft) }))
+    }
+}
+
+/// The addresses of the owners of a parcel for the operator who sends it with a courier.
+pub(super) fn courier_markup(options: &[(i32, String, Address)]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(options.iter().map(|(parcel_id, client_code, address)| vec![
+        InlineKeyboardButton::callback(format!("{}: {}", client_code, address.line()), format!("courier:{}:{}", parcel_id, address.id))
+    ]))
+}
+
+/// The parcel and address ids of a courier button.
+pub(super) fn courier_choice(data: Option<&str>) -> Option<(i32, i32)> {
+    let (parcel_id, address_id) = data?.strip_prefix("courier:")?.split_once(':')?;
+
+    Some((parcel_id.parse().ok()?, address_id.parse().ok()?))
+}
+
+/// What the courier chat gets for a parcel to deliver.
+pub(super) fn courier_task_text(delivery: &CourierDelivery, user: &User) -> String {
+    format!(
+        "🚚 Доставка #{}\nПосылка: {}\nКлиент: {} {}\nТелефон: +{}\nАдрес: {}",
+        delivery.id,
+        delivery.track_code,
+        user.client_code,
+        user.first_name,
+        user.phone_number,
+        delivery.address
+    )
+}
+
+pub(super) fn delivered_markup(delivery_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback("✅ Доставлено", format!("delivered:{}", delivery_id))]])
+}
+
+pub(super) fn delivered_choice(data: Option<&str>) -> Option<i32> {
+    data?.strip_prefix("delivered:")?.parse().ok()
+}
+
+pub(super) fn delivery_proof_prompt(delivery_id: i32) -> Reply {
+    Reply::new(
+        format!("Доставка #{}. Пришлите фото вручения или нажмите «Без фото»", delivery_id),
+        BotState::DeliveryProof { delivery_id }
+    ).with_markup(InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback("Без фото", "delivered_without_photo")],
+        vec![InlineKeyboardButton::callback("Отмена", "back_btn")]
+    ]))
+}
+
+pub(super) fn courier_tasks_text(tasks: &[DeliveryTask]) -> String {
+    if tasks.is_empty() {
+        return "Заданий нет".to_string();
+    }
+
+    let lines: Vec<String> = tasks.iter()
+        .map(|task| format!("#{} {}\n{}", task.id, task.track_code, task.address))
+        .collect();
+
+    format!("Ваши доставки ({}):\n\n{}", tasks.len(), lines.join("\n\n"))
+}
+
+pub(super) fn courier_tasks_markup(tasks: &[DeliveryTask]) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(tasks.iter().map(|task| vec![
+        InlineKeyboardButton::callback(format!("✅ #{} доставлено", task.id), format!("delivered:{}", task.id))
+    ]))
+}
+
+/// Open deliveries for /deliveries, the queued ones wait for /dispatch.
+pub(super) fn deliveries_text(tasks: &[DeliveryTask]) -> String {
+    if tasks.is_empty() {
+        return "Открытых доставок нет".to_string();
+    }
+
+    let lines: Vec<String> = tasks.iter()
+        .map(|task| format!(
+            "#{} {}: {}{}\n{}",
+            task.id,
+            task.track_code,
+            task.status.title(),
+            task.courier.as_ref().map_or(String::new(), |courier| format!(" ({})", courier)),
+            task.address
+        ))
+        .collect();
+
+    format!("Открытые доставки ({}):\n\n{}\n\nНазначить курьера: /dispatch <номер> <telegram id курьера>", tasks.len(), lines.join("\n\n"))
+}
+
+pub(super) fn couriers_text(couriers: &[Courier]) -> String {
+    if couriers.is_empty() {
+        return "Курьеров нет. Добавить: /couriers add <telegram id> <имя>".to_string();
+    }
+
+    let lines: Vec<String> = couriers.iter()
+        .map(|courier| format!("{} — {}, в работе: {}", courier.telegram_id, courier.name, courier.open_tasks))
+        .collect();
+
+    format!("Курьеры ({}):\n{}", couriers.len(), lines.join("\n"))
+}
+
+pub(super) fn profile_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(MENU.iter().map(|row| {
+        row.iter()
+            .map(|(label, data)| InlineKeyboardButton::callback(*label, *data))
+            .collect::<Vec<_>>()
+    }))
+}
+
+fn text_menu_items() -> Vec<(&'static str, &'static str)> {
+    MENU.iter()
+        .flat_map(|row| row.iter().copied())
+        .filter(|(_, data)| *data != "settings_btn")
+        .chain([TEXT_MENU_EXIT])
+        .collect()
+}
+
+pub(super) fn text_menu_text(user: &User) -> String {
+    let items: Vec<String> = text_menu_items().iter()
+        .enumerate()
+        .map(|(i, (label, _))| format!("{} — {}", i + 1, label))
+        .collect();
+
+    format!("{}\n{}\n\nОтправьте номер пункта.", profile_text(user), items.join("\n"))
+}
+
+/// Maps the number sent in the text menu to the callback data of the same button.
+pub(super) fn text_menu_choice(text: Option<&str>) -> Option<&'static str> {
+    let number: usize = text?.trim().parse().ok()?;
+
+    text_menu_items().get(number.checked_sub(1)?).map(|(_, data)| *data)
+}
+
+pub(super) fn with_text_menu_hint(text: &str) -> String {
+    format!("{}\n\n{}", text.trim_end(), TEXT_MENU_HINT)
+}
+
+pub(super) fn service_text(online: bool, schedule: &str) -> String {
+    let status = if online { "🟢 Операторы на связи" } else { "🔴 Операторы сейчас не в сети" };
+
+    format!(indoc!("
+    Контакты тех. поддержки:
+    +996706518003
+
+    {}
+    Часы работы: {}
+    "), status, schedule)
+}
+
+pub(super) fn service_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback("Написать оператору", "support_write_btn")],
+        vec![InlineKeyboardButton::callback("Назад", "back_btn")]
+    ])
+}
+
+pub(super) const SUPPORT_PROMPT: &str = "Опишите Ваш вопрос одним сообщением, к нему можно приложить фото или файл";
+
+/// Returns the text of the support request with its file, or the reply asking for it again.
+///
+/// `attachment` is the file of the message as the attachment policy judged it.
+pub(super) fn support_message(text: Option<&str>, attachment: Option<Result<Attachment, String>>) -> Result<(String, Option<Attachment>), Reply> {
+    let attachment = match attachment {
+        Some(Err(reason)) => return Err(Reply::new(reason, BotState::SupportMessage)),
+        Some(Ok(attachment)) => Some(attachment),
+        None => None
+    };
+
+    let text = match (text.map(str::trim).filter(|text| !text.is_empty()), &attachment) {
+        (Some(text), None) => text.to_string(),
+        (Some(text), Some(_)) => format!("{}\n\n📎 Файл приложен", text),
+        (None, Some(_)) => "📎 Файл без описания".to_string(),
+        (None, None) => return Err(Reply::new(
+            indoc!("
+            Неверный формат.
+            Опишите вопрос текстом.
+            "),
+            BotState::SupportMessage
+        ))
+    };
+
+    Ok((text, attachment))
+}
+
+/// `next_opening` is None when the ticket went straight to an operator.
+pub(super) fn support_received(ticket_id: i32, next_opening: Option<NaiveDateTime>) -> Reply {
+    let text = match next_opening {
+        None => format!("Обращение #{} передано оператору, скоро с Вами свяжутся", ticket_id),
+        Some(opening) => format!(
+            indoc!("
+            Обращение #{} принято.
+            Сейчас нерабочее время, оператор ответит после {} {}.
+            "),
+            ticket_id,
+            opening.format("%H:%M"),
+            opening.format("%d.%m")
+        )
+    };
+
+    Reply::new(text, BotState::Profile { msg_id: placeholder() })
+        .with_markup(back_markup("Вернуться в личный кабинет"))
+}
+
+pub(super) fn settings_text(user: &User) -> String {
+    format!(indoc!("
+    ⚙️ Настройки
+
+    Текстовое меню: {}
+    В текстовом меню пункты приходят списком, а выбираются отправкой номера.
+
+    Единицы измерения: {}, {}
+
+    Город: {}
+    По городу бот считает сроки доставки и присылает новости о Вашем городе.
+
+    Напоминания: {}
+
+    Итоги месяца: {}
+    Первого числа бот присылает, сколько посылок Вы получили за прошлый месяц и сколько оплатили.
+    "),
+    if user.text_menu { "включено" } else { "выключено" },
+    user.units.length_unit(),
+    user.units.weight_unit(),
+    user.city.as_deref().unwrap_or("не выбран"),
+    if user.reminders { "включены" } else { "выключены" },
+    if user.monthly_summary { "включены" } else { "выключены" })
+}
+
+pub(super) fn settings_markup(user: &User) -> InlineKeyboardMarkup {
+    let units = match user.units {
+        Units::Metric => "Перейти на дюймы и фунты",
+        Units::Imperial => "Перейти на сантиметры и килограммы"
+    };
+
+    let reminders = match user.reminders {
+        true => "Не напоминать о боте",
+        false => "Напоминать о боте"
+    };
+
+    let monthly_summary = match user.monthly_summary {
+        true => "Не присылать итоги месяца",
+        false => "Присылать итоги месяца"
+    };
+
+    InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback("Включить текстовое меню", "text_menu_btn")],
+        vec![InlineKeyboardButton::callback(units, "units_btn")],
+        vec![InlineKeyboardButton::callback("Выбрать город", "city_btn")],
+        vec![InlineKeyboardButton::callback(reminders, "reminders_btn")],
+        vec![InlineKeyboardButton::callback(monthly_summary, "summary_btn")],
+        vec![InlineKeyboardButton::callback("Адреса доставки", "addresses_btn")],
+        vec![InlineKeyboardButton::callback("Скачать мои данные", "export_btn")],
+        vec![InlineKeyboardButton::callback("Назад", "back_btn")]
+    ])
+}
+
+pub(super) const CITY_PROMPT: &str = "Выберите Ваш город";
+
+/// Cities open for delivery as (id, name), the picked one arrives as `city:<id>`.
+pub(super) fn city_markup(cities: &[(i32, String)]) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = cities.iter()
+        .map(|(id, name)| vec![InlineKeyboardButton::callback(name.clone(), format!("city:{}", id))])
+        .collect();
+
+    rows.push(vec![InlineKeyboardButton::callback("Назад", "city_back_btn")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Sellers that ask for a recipient name get it in Latin letters next to the client code.
+pub(super) fn address_text(client_code: &str, first_name: &str, last_name: Option<&str>) -> String {
+    let name = translit::latin(&full_name(first_name, last_name));
+
+    format!(indoc!(r#"
+    收件人：溴溴{} {}
+    电话：18160860859
+    地区：浙江省 金华市 义乌市 
+    详细地址：江东街道东苑路45号一楼左侧 7号仓库(溴溴){}
+    "#), client_code, name, client_code)
+}
+
+/// What a client forwards to a seller or a buying agent, in Chinese so it needs no translation.
+pub(super) fn seller_message(client_code: &str, first_name: &str, last_name: Option<&str>) -> String {
+    format!(indoc!("
+    您好！请把货发到以下地址：
+
+    {}
+    注意事项：
+    1. 收件人和详细地址里的客户代码 {} 必须写上，否则仓库无法认领包裹。
+    2. 请不要使用到付。
+    3. 发货后请把快递单号发给我。
+    谢谢！
+    "), address_text(client_code, first_name, last_name), client_code)
+}
+
+pub(super) fn register_first_name(text: Option<&str>, last_name_required: bool) -> Reply {
+    let prompt = match last_name_required {
+        true => "Напишите Вашу фамилию.",
+        false => "Напишите Вашу фамилию или отправьте «-», чтобы пропустить."
+    };
+
+    match text {
+        Some(first_name) => Reply::new(prompt, BotState::RegisterLastName { first_name: first_name.to_string() }),
+        None => Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите имя еще раз.
+            "),
+            BotState::RegisterFirstName
+        )
+    }
+}
+
+/// A last name answered with «-» or «пропустить».
+pub(super) fn skips_last_name(text: &str) -> bool {
+    let text = text.trim();
+
+    text == "-" || text.to_lowercase() == "пропустить"
+}
+
+/// The last name may be skipped with «-» or «пропустить» unless it is required.
+pub(super) fn register_last_name(first_name: String, text: Option<&str>, last_name_required: bool) -> Reply {
+    let last_name = match text.map(str::trim) {
+        Some(text) if skips_last_name(text) => (!last_name_required).then_some(None),
+        Some(text) => Some(Some(text.to_string())),
+        None => None
+    };
+
+    match last_name {
+        Some(last_name) => Reply::new(
+            indoc!("
+            Напишите Ваш номер телефона
+            Пример: 996XXXXXXXXX.
+            "),
+            BotState::RegisterPhoneNumber { first_name, last_name }
+        ),
+        None => Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите фамилию еще раз.
+            "),
+            BotState::RegisterLastName { first_name }
+        )
+    }
+}
+
+/// Returns the phone number to register, or the reply asking for it again.
+pub(super) fn register_phone_number(first_name: String, last_name: Option<String>, text: Option<&str>) -> Result<String, Reply> {
+    match text.and_then(phone_policy::normalize) {
+        Some(phone_number) => Ok(phone_number),
+        None => Err(Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите номер телефона еще раз.
+            Пример: 996XXXXXXXXX
+            "),
+            BotState::RegisterPhoneNumber { first_name, last_name }
+        ))
+    }
+}
+
+pub(super) fn phone_code_prompt(draft: PhoneDraft) -> Reply {
+    Reply::new(
+        format!("Мы отправили SMS с кодом на номер +{}.\nВведите код из сообщения.", draft.phone_number),
+        BotState::RegisterPhoneCode { draft: Box::new(draft) }
+    )
+}
+
+pub(super) fn phone_code_not_sent(first_name: String, last_name: Option<String>) -> Reply {
+    Reply::new(
+        indoc!("
+        Не удалось отправить SMS на этот номер.
+        Проверьте номер и введите его еще раз.
+        Пример: 996XXXXXXXXX
+        "),
+        BotState::RegisterPhoneNumber { first_name, last_name }
+    )
+}
+
+pub(super) fn phone_code_limited(first_name: String, last_name: Option<String>) -> Reply {
+    Reply::new(
+        indoc!("
+        Код на этот номер уже отправлялся недавно или слишком много раз за сутки.
+        Подождите и введите номер еще раз.
+        Пример: 996XXXXXXXXX
+        "),
+        BotState::RegisterPhoneNumber { first_name, last_name }
+    )
+}
+
+/// Returns the registration once the code matches, or the reply asking for it again.
+///
+/// After [`sms::MAX_CODE_ATTEMPTS`] wrong codes the number is asked again, so a new code goes out.
+pub(super) fn register_phone_code(mut draft: PhoneDraft, text: Option<&str>) -> Result<PhoneDraft, Reply> {
+    if text.is_some_and(|text| sms::matches(&draft.code_hash, text)) {
+        return Ok(draft);
+    }
+
+    draft.attempts += 1;
+
+    if draft.attempts >= sms::MAX_CODE_ATTEMPTS {
+        return Err(Reply::new(
+            indoc!("
+            Слишком много неверных попыток.
+            Введите номер телефона еще раз, мы отправим новый код.
+            "),
+            BotState::RegisterPhoneNumber { first_name: draft.first_name, last_name: draft.last_name }
+        ));
+    }
+
+    Err(Reply::new(
+        format!("Неверный код, осталось попыток: {}.\nВведите код из SMS еще раз.", sms::MAX_CODE_ATTEMPTS - draft.attempts),
+        BotState::RegisterPhoneCode { draft: Box::new(draft) }
+    ))
+}
+
+/// What the operator prints for a walk-in client: the client code and the warehouse address to order to.
+pub(super) fn walk_in_text(client_code: &str, first_name: &str, last_name: Option<&str>) -> String {
+    format!(
+        "Клиент {} зарегистрирован, код {}.\n\nАдрес склада для заказов:\n\n{}",
+        full_name(first_name, last_name),
+        client_code,
+        address_text(client_code, first_name, last_name)
+    )
+}
+
+pub(super) fn phone_denied(allowed: &str) -> Reply {
+    Reply::new(
+        format!(
+            "К сожалению, мы не можем зарегистрировать номер этой страны.\nРегистрация открыта для номеров {}.\nЧтобы начать заново, отправьте /start.",
+            allowed
+        ),
+        BotState::Start
+    )
+}
+
+pub(super) fn approval_pending() -> Reply {
+    Reply::new(
+        indoc!("
+        Номера этой страны мы регистрируем после проверки.
+        Заявка отправлена оператору, мы сообщим о решении в этом чате.
+        "),
+        BotState::AwaitingApproval
+    )
+}
+
+pub(super) fn claim_pending() -> Reply {
+    Reply::new(
+        indoc!("
+        Этот номер уже зарегистрирован с другого аккаунта Telegram.
+        Мы отправили оператору запрос на перенос аккаунта: код клиента и посылки перейдут сюда.
+        Мы сообщим о решении в этом чате.
+        "),
+        BotState::AwaitingApproval
+    )
+}
+
+pub(super) fn error_text(correlation_id: &str) -> String {
+    format!(
+        "Что-то пошло не так, попробуйте еще раз.\nЕсли ошибка повторится, напишите в тех. поддержку и укажите код ошибки: {}",
+        correlation_id
+    )
+}
+
+pub(super) fn registered() -> Reply {
+    Reply::new("Вы зарегистрированы!", BotState::Profile { msg_id: placeholder() })
+        .with_markup(InlineKeyboardMarkup::new(
+            vec![vec![InlineKeyboardButton::callback("Далее", "next")]]
+        ))
+}
+
+/// The track code of a `/start track_<code>` deep link from a receipt or the website.
+///
+/// Telegram passes only letters, digits, `_` and `-` in the start parameter, the code itself is letters and digits.
+pub(super) fn start_track_code(text: Option<&str>) -> Option<String> {
+    let payload = text?.strip_prefix("/start")?.trim();
+
+    carrier::parse_track_code(payload.strip_prefix("track_")?).map(|track_code| track_code.to_uppercase())
+}
+
+/// Returns the track code to look up, or the reply asking for it again.
+pub(super) fn track_code(text: Option<&str>) -> Result<String, Reply> {
+    match text.and_then(carrier::parse_track_code) {
+        Some(track_code) => Ok(track_code),
+        None => Err(Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите трек-код еще раз.
+            "),
+            BotState::ProductStatus { msg_id: placeholder() }
+        ).with_markup(back_markup("Назад")))
+    }
+}
+
+/// `detail` is the warehouse status line in Russian, when it says more than the status itself.
+/// `fallback` names the tracking service that answered when the first one could not.
+pub(super) fn product_status(track_code: String, ready: bool, detail: Option<&str>, fallback: Option<&str>) -> Reply {
+    let status = if ready {
+        "Товар уже на складе, ждет сортировки"
+    } else {
+        "Товара еще нет на складе"
+    };
+
+    let mut text = match Carrier::detect(&track_code) {
+        Some(carrier) => format!("Перевозчик: {}\n{}", carrier.name(), status),
+        None => status.to_string()
+    };
+
+    if let Some(detail) = detail {
+        text.push_str(&format!("\nСклад: {}", detail));
+    }
+
+    if let Some(source) = fallback {
+        text.push_str(&format!("\nИсточник: {}", source));
+    }
+
+    Reply::new(text, BotState::TrackResult { msg_id: placeholder(), track_code })
+        .with_markup(InlineKeyboardMarkup::new(vec![
+            vec![InlineKeyboardButton::callback("Сохранить посылку", "save_parcel_btn")],
+            vec![InlineKeyboardButton::callback("Назад", "back_btn")]
+        ]))
+}
+
+/// Shown instead of the warehouse status, which would always say the parcel is not there yet.
+pub(super) fn unsupported_carrier(carrier: Carrier) -> Reply {
+    Reply::new(
+        format!(indoc!("
+        Это трек-код {}.
+        Этот перевозчик не передает посылки на наш склад, поэтому отследить ее здесь не получится.
+        Попросите продавца отправить товар другой службой доставки.
+        "), carrier.name()),
+        BotState::Profile { msg_id: placeholder() }
+    ).with_markup(back_markup("Назад"))
+}
+
+pub(super) fn parcel_label_prompt() -> &'static str {
+    indoc!("
+    Напишите подпись для посылки, например «кроссовки для брата»,
+    или вставьте ссылку на товар с Taobao, 1688, Pinduoduo или Poizon.
+    Отправьте «-», чтобы сохранить без подписи.
+    ")
+}
+
+/// The item title as the parcel label, cut to the label length.
+pub(super) fn item_label(item: &Item) -> Option<String> {
+    item.title.as_ref().map(|title| title.chars().take(MAX_LABEL_LENGTH).collect::<String>().trim().to_string())
+}
+
+/// Shown with the item picture so the client sees the link points to what they ordered.
+pub(super) fn item_caption(item: &Item) -> String {
+    let mut caption = item.title.clone().unwrap_or_else(|| "Товар по ссылке".to_string());
+
+    if let Some(price) = &item.price {
+        caption.push_str(&format!("\nЦена: {}", price));
+    }
+
+    caption.push_str("\n\nЭто ваш товар? Если нет, сохраните посылку заново с другой ссылкой или подписью.");
+    caption
+}
+
+/// Returns the label to store (`None` when skipped), or the reply asking for it again.
+pub(super) fn parcel_label(track_code: String, text: Option<&str>) -> Result<Option<String>, Reply> {
+    let label = match text.map(str::trim) {
+        Some("-") => return Ok(None),
+        Some(label) if !label.is_empty() && label.chars().count() <= MAX_LABEL_LENGTH => label,
+        _ => return Err(Reply::new(
+            format!("Подпись должна быть до {} символов.\n{}", MAX_LABEL_LENGTH, parcel_label_prompt()),
+            BotState::ParcelLabel { track_code }
+        ))
+    };
+
+    Ok(Some(label.to_string()))
+}
+
+pub(super) fn items_text(track_code: &str, items: &[ParcelItem]) -> String {
+    if items.is_empty() {
+        return format!("К посылке {} не сохранены ссылки на товар", track_code);
+    }
+
+    let lines: Vec<String> = items.iter()
+        .map(|item| format!(
+            "{}: {}\nЦена: {}\n{}",
+            item.client_code,
+            item.title.as_deref().unwrap_or("название не найдено"),
+            item.price.as_deref().unwrap_or("—"),
+            item.url
+        ))
+        .collect();
+
+    format!("Товар посылки {}\n\n{}", track_code, lines.join("\n\n"))
+}
+
+pub(super) fn parcel_saved(track_code: &str) -> Reply {
+    Reply::new(format!("Посылка {} сохранена", track_code), BotState::Profile { msg_id: placeholder() })
+        .with_markup(back_markup("Вернуться в личный кабинет"))
+}
+
+pub(super) fn parcel_line(track_code: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{} — {}", track_code, label),
+        None => track_code.to_string()
+    }
+}
+
+pub(super) fn parcels_text(parcels: &[Parcel]) -> String {
+    if parcels.is_empty() {
+        return indoc!("
+        У вас пока нет сохраненных посылок.
+        Сохраните трек-код после отслеживания товара.
+        ").to_string();
+    }
+
+    let lines: Vec<String> = parcels.iter()
+        .enumerate()
+        .map(|(i, parcel)| {
+            let mut line = format!(
+                "{}. {} {}",
+                i + 1,
+                parcel_line(&parcel.track_code, parcel.label.as_deref()),
+                if parcel.arrived { "✅" } else { "⏳" }
+            );
+
+            if let Some(quote_id) = parcel.quote_id {
+                line.push_str(&format!("\n    Расчет №{}", quote_id));
+            }
+
+            match parcel.refund {
+                Some(refund) => format!("{}\n    Заказ отменен, возврат {}", line, refund.title()),
+                None => line
+            }
+        })
+        .collect();
+
+    format!(
+        "Ваши посылки:\n\n{}\n\nНажмите ✏️, чтобы изменить подпись, или ❌, чтобы отменить заказ и запросить возврат.",
+        lines.join("\n")
+    )
+}
+
+/// What a button under the parcel list asks for, `label` or `cancel`, and the id of the parcel.
+pub(super) fn parcel_callback(data: Option<&str>) -> Option<(&'static str, i32)> {
+    let (action, parcel_id) = data?.split_once(':')?;
+    let action = ["label", "cancel"].into_iter().find(|known| *known == action)?;
+
+    Some((action, parcel_id.parse().ok()?))
+}
+
+pub(super) fn parcels_markup(parcels: &[Parcel]) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = parcels.iter()
+        .map(|parcel| {
+            let mut row = vec![InlineKeyboardButton::callback(
+                format!("✏️ {}", parcel.track_code),
+                format!("label:{}", parcel.id)
+            )];
+
+            if parcel.refund.is_none() {
+                row.push(InlineKeyboardButton::callback("❌ Отменить заказ", format!("cancel:{}", parcel.id)));
+            }
+
+            row
+        })
+        .collect();
+
+    rows.push(vec![InlineKeyboardButton::callback("Назад", "back_btn")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub(super) fn tutorials_markup(tutorials: &[Tutorial]) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = tutorials.chunks(2)
+        .map(|row| row.iter()
+            .map(|tutorial| InlineKeyboardButton::callback(&tutorial.title, format!("tutorial:{}", tutorial.slug)))
+            .collect())
+        .collect();
+
+    rows.push(vec![InlineKeyboardButton::callback("Назад", "back_btn")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub(super) fn tutorial_text(tutorial: &Tutorial) -> String {
+    format!("Инструкция к {}:\n{}", tutorial.title, tutorial.text)
+}
+
+/// Parses `/tutorial <slug> <title>` with the text on the following lines.
+pub(super) fn tutorial_from_command(args: &str) -> Option<Tutorial> {
+    let (header, text) = args.trim().split_once('\n')?;
+    let (slug, title) = header.trim().split_once(' ')?;
+
+    let slug = slug.to_lowercase();
+
+    if !is_valid_slug(&slug) || title.trim().is_empty() || text.trim().is_empty() {
+        return None;
+    }
+
+    Some(Tutorial { slug, title: title.trim().to_string(), text: text.trim().to_string(), app_url: None, web_url: None })
+}
+
+/// Buttons under an opened tutorial: the app and the website when they are set, then the way back.
+pub(super) fn tutorial_markup(tutorial: &Tutorial) -> InlineKeyboardMarkup {
+    let links: Vec<InlineKeyboardButton> = [("📱 Открыть приложение", &tutorial.app_url), ("🌐 Открыть сайт", &tutorial.web_url)].into_iter()
+        // Links are checked when they are set, one that still does not parse is left out.
+        .filter_map(|(text, url)| Some(InlineKeyboardButton::url(text, Url::parse(url.as_deref()?).ok()?)))
+        .collect();
+
+    let mut rows = Vec::new();
+
+    if !links.is_empty() {
+        rows.push(links);
+    }
+
+    rows.push(vec![InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Parses `/tutoriallinks <slug> <app link> <website link>`, `-` leaves a button out.
+///
+/// Telegram opens only http(s) links from buttons, an app is reached through the marketplace's universal link.
+pub(super) fn tutorial_links_from_command(args: &str) -> Option<(String, Option<String>, Option<String>)> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+
+    let (slug, app_url, web_url) = match parts.as_slice() {
+        [slug, app_url, web_url] => (slug.to_lowercase(), tutorial_link(app_url)?, tutorial_link(web_url)?),
+        _ => return None
+    };
+
+    Some((slug, app_url, web_url))
+}
+
+fn tutorial_link(arg: &str) -> Option<Option<String>> {
+    if arg == "-" {
+        return Some(None);
+    }
+
+    let url = Url::parse(arg).ok()?;
+
+    match url.scheme() {
+        "https" | "http" => Some(Some(url.to_string())),
+        _ => None
+    }
+}
+
+/// Parses `/canned <slug> <text>`, the text may span several lines.
+pub(super) fn canned_from_command(args: &str) -> Option<(String, String)> {
+    let (slug, text) = args.trim().split_once(char::is_whitespace)?;
+    let slug = slug.to_lowercase();
+
+    if !is_valid_slug(&slug) || text.trim().is_empty() {
+        return None;
+    }
+
+    Some((slug, text.trim().to_string()))
+}
+
+/// Parses `/r [#ticket] [slug]`, taking the ticket from the notification the command replies to when it is omitted.
+pub(super) fn canned_target(args: &str, replied: Option<&str>) -> Option<(i32, Option<String>)> {
+    let ticket_number = |word: &str| word.strip_prefix('#').unwrap_or(word).parse::<i32>().ok();
+    let mut args = args.split_whitespace().peekable();
+
+    let ticket_id = match args.peek().and_then(|word| ticket_number(word)) {
+        Some(ticket_id) => {
+            args.next();
+
+            ticket_id
+        },
+        None => replied?
+            .split_once("Обращение #")
+            .and_then(|(_, rest)| rest.split(|c: char| !c.is_ascii_digit()).next())
+            .and_then(ticket_number)?
+    };
+
+    Some((ticket_id, args.next().map(str::to_lowercase)))
+}
+
+pub(super) fn canned_markup(ticket_id: i32, responses: &[CannedResponse]) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = responses
+        .chunks(2)
+        .map(|row| row.iter()
+            .map(|response| InlineKeyboardButton::callback(response.slug.clone(), format!("canned:{}:{}", ticket_id, response.slug)))
+            .collect())
+        .collect();
+
+    rows.push(vec![InlineKeyboardButton::callback("Отмена", "canned_cancel")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub(super) fn fill_canned(template: &str, user: &User) -> String {
+    template.replace("{name}", &user.first_name)
+}
+
+// Slugs end up in callback data, which Telegram limits to 64 bytes.
+fn is_valid_slug(slug: &str) -> bool {
+    slug.len() <= MAX_SLUG_LENGTH && slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Shelf where parcels of a client go, so that all of them end up in one place.
+///
+/// Hundreds of the client number pick the rack letter and tens the section.
+pub(super) fn suggest_shelf(client_code: &str) -> String {
+    let number: u32 = client_code.chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse().unwrap_or(0);
+
+    let rack = (b'A' + (number / 100 % 26) as u8) as char;
+
+    format!("{}-{}", rack, number % 100 / 10 + 1)
+}
+
+/// `b 3` and `B3` are the same shelf, codes are kept upper case without spaces.
+pub(super) fn shelf_code(text: &str) -> Option<String> {
+    let code: String = text.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+
+    (!code.is_empty()).then_some(code)
+}
+
+pub(super) fn shelf_text(shelf: &str, parcels: &[WarehouseLabel]) -> String {
+    if parcels.is_empty() {
+        return format!("Полка {} пуста", shelf);
+    }
+
+    let lines: Vec<String> = parcels.iter()
+        .map(|parcel| format!("{} → {} {}", parcel.track_code, parcel.client_code, full_name(&parcel.first_name, parcel.last_name.as_deref())))
+        .collect();
+
+    format!("Полка {}, посылок: {}\n\n{}", shelf, parcels.len(), lines.join("\n"))
+}
+
+/// Shelves in the order they stand in the warehouse: `B2` comes before `B10`.
+pub(super) fn shelves_text(shelves: &[(String, i64)]) -> String {
+    if shelves.is_empty() {
+        return "Полок пока нет, они появляются с /shelve".to_string();
+    }
+
+    let mut shelves = shelves.to_vec();
+
+    shelves.sort_by_key(|(code, _)| shelf_order(code));
+
+    let lines: Vec<String> = shelves.iter()
+        .map(|(code, parcels)| format!("{}: {}", code, parcels))
+        .collect();
+
+    format!("Посылок на полках:\n{}", lines.join("\n"))
+}
+
+// Letters compare as text and numbers as numbers.
+fn shelf_order(code: &str) -> Vec<(String, u32)> {
+    let mut parts = Vec::new();
+    let mut letters = String::new();
+    let mut digits = String::new();
+
+    for c in code.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        if !digits.is_empty() {
+            parts.push((std::mem::take(&mut letters), digits.parse().unwrap_or(u32::MAX)));
+            digits.clear();
+        }
+
+        letters.push(c);
+    }
+
+    parts.push((letters, digits.parse().unwrap_or(0)));
+    parts
+}
+
+pub(super) fn scanned_line(track_code: &str, client_codes: &[String]) -> String {
+    match client_codes {
+        [] => format!("❌ {} — не найден", track_code),
+        client_codes => client_codes.iter()
+            .map(|client_code| format!("✅ {} → {}, полка {}", track_code, client_code, suggest_shelf(client_code)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+pub(super) fn scan_summary(batch_code: &str, matched: usize, unmatched: &[String]) -> String {
+    let mut summary = format!(
+        "Сканирование партии {} завершено.\nНайдено: {}\nНе найдено: {}",
+        batch_code,
+        matched,
+        unmatched.len()
+    );
+
+    if !unmatched.is_empty() {
+        summary.push_str(&format!("\n\n{}", unmatched.join("\n")));
+    }
+
+    summary
+}
+
+pub(super) fn refund_reason_text(track_code: &str) -> String {
+    format!("Почему Вы хотите отменить заказ {}?", track_code)
+}
+
+pub(super) fn refund_reason_markup() -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = REFUND_REASONS.iter()
+        .enumerate()
+        .map(|(i, reason)| vec![InlineKeyboardButton::callback(*reason, format!("reason:{}", i))])
+        .collect();
+
+    rows.push(vec![InlineKeyboardButton::callback("Назад", "back_btn")]);
+
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub(super) fn refund_reason(data: Option<&str>) -> Option<&'static str> {
+    let index: usize = data?.strip_prefix("reason:")?.parse().ok()?;
+
+    REFUND_REASONS.get(index).copied()
+}
+
+pub(super) fn refund_requested_text(ticket_id: i32, track_code: &str) -> String {
+    format!(indoc!("
+    Заявка на отмену заказа {} и возврат средств принята, обращение #{}.
+    Статус возврата виден в разделе «Мои посылки».
+    "), track_code, ticket_id)
+}
+
+pub(super) fn weighed_text(track_code: &str, weight_kg: f32) -> String {
+    format!(indoc!("
+    🧾 Посылка {} взвешена на складе: {:.2} кг.
+    Если Вы не согласны с весом, нажмите «Оспорить вес».
+    "), track_code, weight_kg)
+}
+
+pub(super) fn weighed_markup(parcel_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("⚖️ Оспорить вес", format!("dispute:{}", parcel_id))
+    ]])
+}
+
+/// `online` when Telegram Payments are set up, otherwise the client pays at the pickup point.
+pub(super) fn invoice_text(invoice: &Invoice, online: bool) -> String {
+    let how = match online {
+        true => "Оплатить можно кнопкой ниже или на пункте выдачи.",
+        false => "Оплатить можно на пункте выдачи."
+    };
+
+    format!("💳 Счет за доставку посылки {}: {}, к оплате {}.\n{}", invoice.track_code, invoice.amount, invoice.due(), how)
+}
+
+pub(super) fn adjust_usage() -> String {
+    let reasons: Vec<&str> = OverrideReason::ALL.iter().map(OverrideReason::as_str).collect();
+
+    format!("Использование: /adjust <трек-код> <итоговая сумма, $> <причина> [код клиента]\nПричины: {}", reasons.join(", "))
+}
+
+/// The receipt after an operator changed the price, with the difference and its reason.
+pub(super) fn override_text(invoice: &Invoice, change: &PriceOverride) -> String {
+    let delta = change.delta();
+
+    let kind = match delta < Money::default() {
+        true => "скидка",
+        false => "надбавка"
+    };
+
+    format!(
+        "🧾 Стоимость доставки посылки {} изменена: было {}, стало {} ({} {}).\nПричина: {}.\nК оплате {}.",
+        invoice.track_code, change.original, change.final_price, kind, Money::from_cents(delta.cents().abs()), change.reason.title(), invoice.due()
+    )
+}
+
+pub(super) fn invoice_markup(parcel_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("💳 Оплатить", format!("pay:{}", parcel_id))
+    ]])
+}
+
+pub(super) fn payment_text(invoice: &Invoice) -> String {
+    match invoice.status() {
+        InvoiceStatus::Paid => format!("✅ Доставка посылки {} оплачена: {}.", invoice.track_code, invoice.paid),
+        _ => format!(
+            "Оплата по посылке {} получена: оплачено {} из {}, осталось {}.",
+            invoice.track_code, invoice.paid, invoice.amount, invoice.due()
+        )
+    }
+}
+
+/// `held` are the parcels not handed over because they are not paid in full.
+pub(super) fn delivered_text(batch_code: &str, delivered: u64, held: &[String]) -> String {
+    let text = format!("Партия {} доставлена, посылок: {}", batch_code, delivered);
+
+    match held.is_empty() {
+        true => text,
+        false => format!("{}\nНе выданы без оплаты ({}): {}", text, held.len(), held.join(", "))
+    }
+}
+
+fn dispute_markup() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback("Отправить", "dispute_send_btn")],
+        vec![InlineKeyboardButton::callback("Отмена", "back_btn")]
+    ])
+}
+
+pub(super) fn dispute_prompt(track_code: &str) -> Reply {
+    Reply::new(
+        format!(indoc!("
+        Спор по весу посылки {}.
+        Опишите, с чем Вы не согласны, и пришлите фото весов или посылки.
+        Когда закончите, нажмите «Отправить».
+        "), track_code),
+        BotState::WeightDispute { track_code: track_code.to_string(), comment: String::new(), attachments: Vec::new() }
+    ).with_markup(dispute_markup())
+}
+
+/// Collects the comment and files of a weight dispute until it is sent.
+///
+/// A message whose file the attachment policy turned down is left out whole, caption included.
+pub(super) fn dispute_input(track_code: String, mut comment: String, mut attachments: Vec<Attachment>, text: Option<&str>, attachment: Option<Result<Attachment, String>>) -> Reply {
+    let text = text.map(str::trim).filter(|text| !text.is_empty());
+
+    let attachment = match attachment {
+        Some(Err(reason)) => return Reply::new(
+            format!("{}. Пришлите другой файл или нажмите «Отправить»", reason),
+            BotState::WeightDispute { track_code, comment, attachments }
+        ).with_markup(dispute_markup()),
+        Some(Ok(attachment)) => Some(attachment),
+        None => None
+    };
+
+    if text.is_none() && attachment.is_none() {
+        return Reply::new(
+            "Пришлите текст, фото или файл",
+            BotState::WeightDispute { track_code, comment, attachments }
+        ).with_markup(dispute_markup());
+    }
+
+    if let Some(text) = text {
+        if !comment.is_empty() {
+            comment.push('\n');
+        }
+
+        comment.push_str(text);
+    }
+
+    attachments.extend(attachment);
+
+    Reply::new(
+        format!("Добавлено. Файлов: {}. Пришлите еще или нажмите «Отправить»", attachments.len()),
+        BotState::WeightDispute { track_code, comment, attachments }
+    ).with_markup(dispute_markup())
+}
+
+/// Ticket text for operators: the client's comment next to what the warehouse recorded.
+pub(super) fn dispute_ticket_text(shipment: &Shipment, comment: &str, attachment_count: usize) -> String {
+    let weight = shipment.weight_kg.map_or("—".to_string(), |weight_kg| format!("{:.2} кг", weight_kg));
+
+    format!(indoc!("
+    Спор по весу посылки {}.
+    Партия: {}
+    Вес: {}
+    Объявленная стоимость: {}
+    Описание: {}
+    Файлов: {}
+
+    Комментарий клиента: {}"),
+        shipment.track_code,
+        shipment.batch_code.as_deref().unwrap_or("—"),
+        weight,
+        shipment.declared_value.map_or("—".to_string(), |value| value.to_string()),
+        shipment.description.as_deref().unwrap_or("—"),
+        attachment_count,
+        if comment.is_empty() { "—" } else { comment }
+    )
+}
+
+pub(super) fn dispute_opened_text(ticket_id: i32, track_code: &str) -> String {
+    format!(indoc!("
+    Спор по весу посылки {} передан операторам, обращение #{}.
+    Мы перепроверим вес и ответим Вам здесь.
+    "), track_code, ticket_id)
+}
+
+pub(super) const SELLER_CHECK_PROMPT: &str = indoc!("
+Вставьте адрес и телефон, которые продавец указал для доставки на склад.
+Бот проверит, что адрес написан полностью, а номер похож на китайский мобильный.
+");
+
+/// Passed addresses finish the check, otherwise the user can paste a corrected one.
+pub(super) fn seller_check(text: Option<&str>) -> Reply {
+    let text = match text {
+        Some(text) => text,
+        None => return Reply::new("Пришлите адрес текстом", BotState::SellerCheck { msg_id: placeholder() })
+            .with_markup(back_markup("Назад"))
+    };
+
+    let (phone, issues) = china_address::check(text);
+
+    if issues.is_empty() {
+        let phone = phone.unwrap_or_default();
+
+        return Reply::new(
+            format!("✅ Адрес заполнен правильно, телефон получателя: {}", phone),
+            BotState::Profile { msg_id: placeholder() }
+        ).with_markup(back_markup("Вернуться в личный кабинет"));
+    }
+
+    let issues: Vec<String> = issues.iter().map(|issue| format!("• {}", issue)).collect();
+
+    Reply::new(
+        format!("⚠️ Попросите продавца исправить адрес:\n{}\n\nМожно прислать исправленный адрес.", issues.join("\n")),
+        BotState::SellerCheck { msg_id: placeholder() }
+    ).with_markup(back_markup("Назад"))
+}
+
+pub(super) const PURCHASE_PROMPT: &str = indoc!("
+Выкуп товара: мы закажем товар за Вас и доставим вместе с остальными посылками.
+Пришлите ссылку на товар с Taobao, 1688, Pinduoduo, Poizon или другого китайского магазина.
+");
+
+/// A marketplace link moves on to the size and color, anything else is asked again.
+pub(super) fn purchase_link(text: Option<&str>) -> Reply {
+    let url = match text.and_then(catalog::marketplace_url) {
+        Some(url) => url,
+        None => return Reply::new(
+            "Не нашли ссылку на товар. Скопируйте ее в приложении магазина кнопкой «Поделиться» и пришлите сюда.",
+            BotState::PurchaseLink { msg_id: placeholder() }
+        ).with_markup(back_markup("Назад"))
+    };
+
+    let marketplace = catalog::marketplace(&url).unwrap_or_default().to_string();
+
+    Reply::new(
+        "Укажите размер, цвет и количество, например «42, черный, 1 шт.», или «-», если выбирать нечего.",
+        BotState::PurchaseOptions { marketplace, link: url.to_string() }
+    )
+}
+
+/// The size, color and quantity as typed, `None` for «-».
+pub(super) fn purchase_options(text: Option<&str>) -> Result<Option<String>, &'static str> {
+    match text.map(str::trim) {
+        Some("-") => Ok(None),
+        Some(text) if !text.is_empty() && text.chars().count() <= MAX_PURCHASE_OPTIONS_LENGTH => Ok(Some(text.to_string())),
+        _ => Err("Опишите размер, цвет и количество одним сообщением до 200 символов, или отправьте «-».")
+    }
+}
+
+pub(super) const PURCHASE_BUDGET_PROMPT: &str = "Сколько Вы готовы потратить на товар вместе с доставкой по Китаю? Отправьте сумму в долларах, например 25.";
+
+pub(super) fn purchase_budget(text: Option<&str>) -> Option<Money> {
+    Money::parse(text?.trim()).filter(|budget| *budget > Money::default())
+}
+
+pub(super) fn purchase_received(id: i32) -> Reply {
+    Reply::new(
+        format!("Заявка на выкуп №{} принята. Оператор проверит товар и пришлет цену в этот чат.", id),
+        BotState::Profile { msg_id: placeholder() }
+    ).with_markup(back_markup("Вернуться в личный кабинет"))
+}
+
+fn purchase_summary(request: &PurchaseRequest) -> String {
+    format!(
+        "№{} {} ({}), бюджет {}\n{}\nРазмер, цвет: {}",
+        request.id,
+        request.client_code,
+        request.marketplace,
+        request.budget,
+        request.link,
+        request.options.as_deref().unwrap_or("—")
+    )
+}
+
+/// The operators' ping about a new request, with the command that answers it.
+pub(super) fn purchase_operator_text(request: &PurchaseRequest) -> String {
+    format!(
+        "🛍 Новая заявка на выкуп\n{}\n\nПредложить цену: /purchase {} quoted <сумма> [комментарий]",
+        purchase_summary(request), request.id
+    )
+}
+
+/// The open requests by marketplace for /purchases.
+pub(super) fn purchase_queue_text(requests: &[PurchaseRequest]) -> String {
+    if requests.is_empty() {
+        return "Открытых заявок на выкуп нет".to_string();
+    }
+
+    let mut text = format!("Заявки на выкуп: {}", requests.len());
+    let mut marketplace = "";
+
+    for request in requests {
+        if request.marketplace != marketplace {
+            marketplace = &request.marketplace;
+            text.push_str(&format!("\n\n{}", marketplace));
+        }
+
+        text.push_str(&format!("\n\n{} — {}", purchase_summary(request), request.status.title()));
+
+        if let Some(quote) = request.quote {
+            text.push_str(&format!(", цена {}", quote));
+        }
+    }
+
+    text
+}
+
+/// What the client hears when an operator moves the request on.
+pub(super) fn purchase_text(request: &PurchaseRequest) -> String {
+    let text = match request.status {
+        PurchaseStatus::Quoted => format!(
+            "💬 Цена выкупа по заявке №{}: {} (Ваш бюджет {}).\nЕсли согласны, напишите в тех. поддержку, и мы оформим заказ.",
+            request.id, request.quote.unwrap_or_default(), request.budget
+        ),
+        PurchaseStatus::Purchased => format!(
+            "✅ Товар по заявке №{} выкуплен. Трек-код появится в «Мои посылки», когда продавец отправит заказ.",
+            request.id
+        ),
+        PurchaseStatus::Declined => format!("❌ Заявку на выкуп №{} выполнить не получится.", request.id),
+        PurchaseStatus::New => format!("Заявка на выкуп №{} ждет оператора.", request.id)
+    };
+
+    match &request.comment {
+        Some(comment) => format!("{}\nКомментарий оператора: {}", text, comment),
+        None => text
+    }
+}
+
+pub(super) fn batch_text(batch: &Batch) -> String {
+    format!(
+        "Партия {}: {}\nПосылок: {}\nОбновлено: {}",
+        batch.code, batch.status.title(), batch.parcels, batch.updated_at.format("%d.%m.%Y %H:%M")
+    )
+}
+
+pub(super) fn batches_text(batches: &[Batch]) -> String {
+    if batches.is_empty() {
+        return "Партий в пути нет".to_string();
+    }
+
+    let lines: Vec<String> = batches.iter()
+        .map(|batch| format!("{} — {}, посылок: {}", batch.code, batch.status.title(), batch.parcels))
+        .collect();
+
+    format!("Партии в пути: {}\n\n{}", batches.len(), lines.join("\n"))
+}
+
+/// What the clients with parcels in the batch hear when it moves on.
+pub(super) fn batch_status_text(batch_code: &str, status: BatchStatus, track_codes: &[String]) -> String {
+    let text = match status {
+        BatchStatus::Forming => format!("📦 Посылки собираются в партию {}.", batch_code),
+        BatchStatus::Loaded => format!("📦 Партия {} загружена и скоро отправится.", batch_code),
+        BatchStatus::Departed => format!("🚚 Партия {} выехала со склада в Китае.", batch_code),
+        BatchStatus::Customs => format!("🛃 Партия {} проходит таможню.", batch_code),
+        BatchStatus::Arrived => format!("🏁 Партия {} прибыла в Кыргызстан, скоро посылки будут на пункте выдачи.", batch_code)
+    };
+
+    format!("{}\nВаши посылки в ней: {}", text, track_codes.join(", "))
+}
+
+/// The way of each batch with the user's parcels, for «Где моя партия?».
+pub(super) fn batch_progress_text(batches: &[(Batch, Vec<BatchEvent>)]) -> String {
+    if batches.is_empty() {
+        return "Ваших посылок в партиях пока нет. Партия появится здесь, когда посылку отправят со склада в Китае.".to_string();
+    }
+
+    batches.iter()
+        .map(|(batch, events)| batch_timeline(batch, events))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn batch_timeline(batch: &Batch, events: &[BatchEvent]) -> String {
+    let bar: String = BatchStatus::ALL.iter()
+        .map(|status| if *status <= batch.status { "🟩" } else { "⬜" })
+        .collect();
+
+    let mut text = format!("🚚 Партия {}\n{} {}\n", batch.code, bar, batch.status.title());
+
+    for status in BatchStatus::ALL {
+        // A skipped status has no event of its own, it is still passed.
+        let reached = events.iter().rev().find(|event| event.status == status);
+
+        let line = match (status <= batch.status, reached) {
+            (true, Some(event)) => format!("\n✅ {} — {}", status.title(), event.created_at.format("%d.%m %H:%M")),
+            (true, None) => format!("\n✅ {}", status.title()),
+            (false, _) => format!("\n▫️ {}", status.title())
+        };
+
+        text.push_str(&line);
+    }
+
+    text.push_str(&format!("\n\nОбновлено: {}", batch.updated_at.format("%d.%m.%Y %H:%M")));
+    text
+}
+
+/// `<номер опроса> <вопрос> | вариант | вариант`, a question without options takes a typed answer.
+pub(super) fn parse_survey_question(args: &str) -> Result<(i32, String, Vec<String>), String> {
+    let usage = || format!(
+        "Использование: /question <номер опроса> <вопрос> | вариант | вариант\nБез вариантов клиент отвечает текстом, вариантов не больше {}.",
+        MAX_SURVEY_OPTIONS
+    );
+
+    let (survey_id, rest) = args.trim().split_once(' ').ok_or_else(usage)?;
+    let survey_id = survey_id.trim_start_matches('#').parse::<i32>().map_err(|_| usage())?;
+
+    let mut parts = rest.split('|').map(str::trim);
+    let text = parts.next().filter(|text| !text.is_empty()).ok_or_else(usage)?.to_string();
+    let options: Vec<String> = parts.filter(|option| !option.is_empty()).map(str::to_string).collect();
+
+    match options.len() {
+        1 => Err("Нужно хотя бы два варианта, или ни одного для ответа текстом.".to_string()),
+        n if n > MAX_SURVEY_OPTIONS => Err(usage()),
+        _ => Ok((survey_id, text, options))
+    }
+}
+
+/// The survey as an admin builds it.
+pub(super) fn survey_text(survey: &Survey) -> String {
+    let mut text = format!("Опрос #{} «{}»", survey.id, survey.title);
+
+    for question in &survey.questions {
+        let answer = match question.options.is_empty() {
+            true => "ответ текстом".to_string(),
+            false => question.options.join(" / ")
+        };
+
+        text.push_str(&format!("\n{}. {} — {}", question.position, question.text, answer));
+    }
+
+    let next = match (survey.sent, survey.questions.len()) {
+        (true, _) => format!("Опрос отправлен, результаты: /results {}", survey.id),
+        (false, 0) => format!("Добавьте вопросы: /question {} <вопрос> | вариант | вариант", survey.id),
+        (false, n) if n < MAX_SURVEY_QUESTIONS => format!(
+            "Еще вопрос: /question {} ...\nОтправить клиентам: /sendsurvey {} [фильтры]",
+            survey.id, survey.id
+        ),
+        (false, _) => format!("Отправить клиентам: /sendsurvey {} [фильтры]", survey.id)
+    };
+
+    format!("{}\n\n{}", text, next)
+}
+
+/// A question as the client sees it, with a button per option or one to type the answer.
+/// A question is a message of its own, it comes in any dialogue state and leaves the state as it is.
+pub(super) fn survey_question(survey: &Survey, question: &SurveyQuestion) -> (String, InlineKeyboardMarkup) {
+    let text = format!(
+        "📋 Опрос «{}», вопрос {} из {}\n\n{}",
+        survey.title, question.position, survey.questions.len(), question.text
+    );
+
+    let buttons = match question.options.is_empty() {
+        true => vec![vec![InlineKeyboardButton::callback("✏️ Ответить", format!("survey:{}:{}:text", survey.id, question.position))]],
+        false => question.options.iter()
+            .enumerate()
+            .map(|(i, option)| vec![InlineKeyboardButton::callback(option, format!("survey:{}:{}:{}", survey.id, question.position, i))])
+            .collect()
+    };
+
+    (text, InlineKeyboardMarkup::new(buttons))
+}
+
+/// The survey, question and option of a pressed survey button, no option for a typed answer.
+pub(super) fn survey_callback(data: Option<&str>) -> Option<(i32, i16, Option<i16>)> {
+    let mut parts = data?.strip_prefix("survey:")?.split(':');
+
+    let survey_id = parts.next()?.parse().ok()?;
+    let position = parts.next()?.parse().ok()?;
+
+    let option = match parts.next()? {
+        "text" => None,
+        option => Some(option.parse().ok()?)
+    };
+
+    Some((survey_id, position, option))
+}
+
+pub(super) fn survey_answer_prompt(survey_id: i32, question: &SurveyQuestion) -> Reply {
+    Reply::new(
+        format!("{}\n\nНапишите ответ одним сообщением, до {} символов.", question.text, MAX_SURVEY_ANSWER_LENGTH),
+        BotState::SurveyAnswer { survey_id, position: question.position }
+    )
+}
+
+pub(super) fn survey_answer(survey_id: i32, position: i16, text: Option<&str>) -> Result<String, Reply> {
+    match text.map(str::trim) {
+        Some(text) if !text.is_empty() && text.chars().count() <= MAX_SURVEY_ANSWER_LENGTH => Ok(text.to_string()),
+        _ => Err(Reply::new(
+            format!("Отправьте ответ текстом, до {} символов, или /cancel", MAX_SURVEY_ANSWER_LENGTH),
+            BotState::SurveyAnswer { survey_id, position }
+        ))
+    }
+}
+
+pub(super) const SURVEY_FINISHED: &str = "Спасибо за ответы! Они помогают нам работать лучше 🙏";
+
+pub(super) fn survey_finished() -> Reply {
+    Reply::new(SURVEY_FINISHED, BotState::Profile { msg_id: placeholder() })
+        .with_markup(back_markup("Вернуться в личный кабинет"))
+}
+
+/// Answers counted per option, typed answers listed newest first.
+pub(super) fn survey_results_text(survey: &Survey, answers: &[SurveyAnswer]) -> String {
+    let mut text = format!("Результаты опроса #{} «{}»", survey.id, survey.title);
+
+    for question in &survey.questions {
+        let answers: Vec<&SurveyAnswer> = answers.iter().filter(|answer| answer.position == question.position).collect();
+
+        text.push_str(&format!("\n\n{}. {} (ответов: {})", question.position, question.text, answers.len()));
+
+        if question.options.is_empty() {
+            for answer in answers.iter().rev().take(SURVEY_RESULT_TEXTS) {
+                text.push_str(&format!("\n— {}", answer.text.as_deref().unwrap_or_default()));
+            }
+
+            if answers.len() > SURVEY_RESULT_TEXTS {
+                text.push_str(&format!("\n… и еще {}", answers.len() - SURVEY_RESULT_TEXTS));
+            }
+
+            continue;
+        }
+
+        for (i, option) in question.options.iter().enumerate() {
+            let count = answers.iter().filter(|answer| answer.option == Some(i as i16)).count();
+            let percent = match answers.len() {
+                0 => 0,
+                total => count * 100 / total
+            };
+
+            text.push_str(&format!("\n▫️ {} — {} ({}%)", option, count, percent));
+        }
+    }
+
+    text
+}
+
+/// What a client's reaction to a notice of the bot asks for.
+#[derive(Debug, PartialEq)]
+pub(super) enum ReactionAction {
+    Acknowledge,
+    Rate
+}
+
+/// 👍 acknowledges a notice, ❤️ opens the rating; Telegram sends the heart without the variation selector.
+pub(super) fn reaction_action(emoji: &str) -> Option<ReactionAction> {
+    match emoji.trim_end_matches('\u{fe0f}') {
+        "👍" => Some(ReactionAction::Acknowledge),
+        "❤" => Some(ReactionAction::Rate),
+        _ => None
+    }
+}
+
+pub(super) fn rating_prompt(track_code: &str) -> (String, InlineKeyboardMarkup) {
+    let buttons = (1..=5)
+        .map(|stars| InlineKeyboardButton::callback("⭐".repeat(stars), format!("rate:{}:{}", track_code, stars)))
+        .map(|button| vec![button])
+        .collect::<Vec<_>>();
+
+    (format!("Как вам доставка посылки {}? Оцените от 1 до 5 звезд.", track_code), InlineKeyboardMarkup::new(buttons))
+}
+
+pub(super) fn rating_callback(data: Option<&str>) -> Option<(String, i16)> {
+    let (track_code, stars) = data?.strip_prefix("rate:")?.rsplit_once(':')?;
+
+    match stars.parse() {
+        Ok(stars) if (1..=5).contains(&stars) && !track_code.is_empty() => Some((track_code.to_string(), stars)),
+        _ => None
+    }
+}
+
+/// A positive size or weight up to [`MAX_DIMENSION`], `nan` and `inf` parse as floats too.
+pub(super) fn parse_dimension(text: Option<&str>) -> Option<f32> {
+    text?.trim().replace(',', ".").parse::<f32>().ok()
+        .filter(|value| value.is_finite() && *value > 0.0 && *value <= MAX_DIMENSION)
+}
+
+/// The price list by density bands, each band lasting until the next one starts.
+pub(super) fn tariffs_text(tariffs: &[Tariff]) -> String {
+    if tariffs.is_empty() {
+        return "Тарифы пока не опубликованы, уточните стоимость у тех. поддержки.".to_string();
+    }
+
+    let bands: Vec<String> = tariffs.iter()
+        .enumerate()
+        .map(|(i, tariff)| {
+            let band = match tariffs.get(i + 1) {
+                Some(next) => format!("{}–{} кг/м3", tariff.min_density, next.min_density),
+                None => format!("от {} кг/м3", tariff.min_density)
+            };
+
+            format!("▫️ {}: {}/кг, минимум {}", band, tariff.price_per_kg, tariff.min_charge)
+        })
+        .collect();
+
+    format!(
+        "Тарифы на доставку\n\n{}\n\nПлотность — вес посылки в кг, деленный на ее объем в м3. Посчитать ее можно в разделе «Высчитывание цены».",
+        bands.join("\n")
+    )
+}
+
+/// Parses `/tariff <density from> <price per kg> <minimum charge>`.
+pub(super) fn tariff_from_command(args: &str) -> Option<Tariff> {
+    match args.split_whitespace().collect::<Vec<_>>()[..] {
+        [min_density, price_per_kg, min_charge] => Some(Tariff {
+            min_density: min_density.replace(',', ".").parse::<f32>().ok().filter(|density| *density >= 0.0)?,
+            price_per_kg: Money::parse(price_per_kg)?,
+            min_charge: Money::parse(min_charge)?
+        }),
+        _ => None
+    }
+}
+
+/// One word, so that the `city:` filter of the announcement broadcast can name it.
+pub(super) fn city_name(text: Option<&str>) -> Option<String> {
+    let name = text?.trim();
+
+    (!name.is_empty() && !name.contains(char::is_whitespace) && name.chars().count() <= 50).then(|| name.to_string())
+}
+
+/// One `/tariff` line per density band, sorted by density. None when any line is wrong or bands repeat.
+pub(super) fn city_tariffs(text: Option<&str>) -> Option<Vec<Tariff>> {
+    let lines: Vec<&str> = text?.lines().filter(|line| !line.trim().is_empty()).collect();
+
+    let mut tariffs = lines.iter()
+        .map(|line| tariff_from_command(line))
+        .collect::<Option<Vec<_>>>()?;
+
+    tariffs.sort_by(|a, b| a.min_density.total_cmp(&b.min_density));
+    tariffs.dedup_by(|a, b| a.min_density == b.min_density);
+
+    (!tariffs.is_empty() && tariffs.len() == lines.len()).then_some(tariffs)
+}
+
+/// Fewest and most days in transit, as «10 14» or «10-14».
+pub(super) fn city_eta(text: Option<&str>) -> Option<(i32, i32)> {
+    let (from, to) = text?.trim().split_once(|c: char| c == '-' || c == '–' || c.is_whitespace())?;
+    let (from, to) = (from.trim().parse::<i32>().ok()?, to.trim().parse::<i32>().ok()?);
+
+    (from > 0 && from <= to).then_some((from, to))
+}
+
+/// The broadcast draft for a new city, used when the admin does not write their own.
+pub(super) fn city_announcement(name: &str, tariffs: &[Tariff], eta_days: (i32, i32), pickup_point: &str) -> String {
+    format!(
+        "🎉 Открыли доставку в {}!\n\nСрок доставки от склада: {}–{} дней.\nПункт выдачи: {}\n\n{}",
+        name,
+        eta_days.0,
+        eta_days.1,
+        pickup_point,
+        tariffs_text(tariffs)
+    )
+}
+
+/// Everything about a city an admin confirms before it goes live.
+pub(super) fn city_summary(city: &NewCity) -> String {
+    format!(
+        "Открытие города {}\nСрок доставки: {}–{} дней\nПункт выдачи: {}\n\n{}\n\nЧерновик рассылки:\n\n{}",
+        city.name,
+        city.eta_days.0,
+        city.eta_days.1,
+        city.pickup_point,
+        tariffs_text(&city.tariffs),
+        city.announcement
+    )
+}
+
+pub(super) const BOX_DIAGRAM_CAPTION: &str = indoc!("
+Как измерить коробку:
+Д — длина, длинная сторона дна
+Ш — ширина, короткая сторона дна
+В — высота, от дна до крышки
+Измеряйте снаружи, вместе с упаковкой.
+");
+
+/// A typical value given as (metric, imperial), in the units the user types in.
+fn unit_example<'a>(units: Units, example: (&'a str, &'a str)) -> &'a str {
+    match units {
+        Units::Metric => example.0,
+        Units::Imperial => example.1
+    }
+}
+
+/// The prompt for one side of the box, pointing at its letter on the box picture.
+fn dimension_prompt(units: Units, side: &str, hint: &str, example: (&str, &str)) -> String {
+    format!("Введите {} коробки с товаром ({}) — {}.\nНапример: {}", side, units.length_unit(), hint, unit_example(units, example))
+}
+
+/// Asks for the width, saved boxes are offered as buttons under the prompt.
+pub(super) fn price_prompt(units: Units, presets: &[BoxPreset]) -> Reply {
+    let text = dimension_prompt(units, "ширину", "короткую сторону дна, «Ш» на картинке", ("40", "16"));
+
+    if presets.is_empty() {
+        return Reply::new(text, BotState::PriceWidth);
+    }
+
+    let buttons = presets.iter()
+        .map(|preset| vec![InlineKeyboardButton::callback(format!("{} см", preset.title()), format!("box:{}", preset.id))])
+        .collect::<Vec<_>>();
+
+    Reply::new(format!("{}\n\nИли выберите сохраненную коробку:", text), BotState::PriceWidth)
+        .with_markup(InlineKeyboardMarkup::new(buttons))
+}
+
+fn weight_prompt(units: Units) -> String {
+    format!(
+        "Введите вес коробки с товаром ({}) вместе с упаковкой.\nНапример: {}",
+        units.weight_unit(), unit_example(units, ("12,5", "27,5"))
+    )
+}
+
+/// A saved box skips the sides, only the weight is asked.
+pub(super) fn box_preset_picked(units: Units, preset: &BoxPreset) -> Reply {
+    Reply::new(
+        format!("{} см.\n{}", preset.title(), weight_prompt(units)),
+        BotState::PriceWeight { width: preset.width, length: preset.length, height: preset.height }
+    )
+}
+
+/// Dimensions are kept in centimeters and kilograms whatever units the user types in.
+pub(super) fn price_width(units: Units, text: Option<&str>) -> Reply {
+    match parse_dimension(text) {
+        Some(width) => Reply::new(
+            dimension_prompt(units, "длину", "длинную сторону дна, «Д» на картинке", ("60", "24")),
+            BotState::PriceLength { width: units.to_centimeters(width) }
+        ),
+        None => Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите ширину еще раз.
+            "),
+            BotState::PriceWidth
+        )
+    }
+}
+
+pub(super) fn price_length(units: Units, width: f32, text: Option<&str>) -> Reply {
+    match parse_dimension(text) {
+        Some(length) => Reply::new(
+            dimension_prompt(units, "высоту", "от дна до крышки, «В» на картинке", ("35", "14")),
+            BotState::PriceHeight { width, length: units.to_centimeters(length) }
+        ),
+        None => Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите длину еще раз.
+            "),
+            BotState::PriceLength { width }
+        )
+    }
+}
+
+pub(super) fn price_height(units: Units, width: f32, length: f32, text: Option<&str>) -> Reply {
+    match parse_dimension(text) {
+        Some(height) => Reply::new(
+            weight_prompt(units),
+            BotState::PriceWeight { width, length, height: units.to_centimeters(height) }
+        ),
+        None => Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите высоту еще раз
+            "),
+            BotState::PriceHeight { width, length }
+        )
+    }
+}
+
+/// Returns the weight in kilograms, or the reply asking for it again.
+pub(super) fn price_weight(units: Units, width: f32, length: f32, height: f32, text: Option<&str>) -> Result<f32, Reply> {
+    match parse_dimension(text) {
+        Some(weight) => Ok(units.to_kilograms(weight)),
+        None => Err(Reply::new(
+            indoc!("
+            Неверный формат.
+            Введите вес еще раз
+            "),
+            BotState::PriceWeight { width, length, height }
+        ))
+    }
+}
+
+/// Prices a box by the band its density falls in, anything lighter than the first band goes by the first one.
+pub(super) fn quote_price(tariffs: &[Tariff], density: f32, weight_kg: f32) -> Option<Money> {
+    let tariff = tariffs.iter().rev().find(|tariff| tariff.min_density <= density).or(tariffs.first())?;
+
+    Some(tariff.price_per_kg.times(weight_kg)?.max(tariff.min_charge))
+}
+
+pub(super) fn quote_text(quote: &Quote) -> String {
+    let density = quote.density();
+
+    let basis = if density >= DENSITY_THRESHOLD { "по весу" } else { "по плотности" };
+
+    let price = match quote.price {
+        Some(price) => format!("Стоимость доставки: {}", price),
+        None => "Тарифы пока не опубликованы, уточните стоимость у тех. поддержки.".to_string()
+    };
+
+    format!(
+        "Расчет №{} от {}\nКоробка {}×{}×{} см, {} кг\nПлотность составляет: {:.1} кг/м3.\nЦена товара высчитывается {}\n{}",
+        quote.id,
+        quote.created_at.format("%d.%m.%Y"),
+        quote.width,
+        quote.length,
+        quote.height,
+        quote.weight_kg,
+        density,
+        basis,
+        price
+    )
+}
+
+/// The buttons carry the quote id, so they still work on an old quote further up the chat.
+pub(super) fn quote_reply(quote: &Quote) -> Reply {
+    Reply::new(
+        format!("{}\n\nНазовите номер расчета оператору при оплате.", quote_text(quote)),
+        BotState::Profile { msg_id: placeholder() }
+    ).with_markup(InlineKeyboardMarkup::new(vec![
+        vec![
+            InlineKeyboardButton::callback("Пересчитать", format!("requote:{}", quote.id)),
+            InlineKeyboardButton::callback("Сохранить", format!("save_quote:{}", quote.id))
+        ],
+        vec![InlineKeyboardButton::callback("Запомнить размеры", format!("save_box:{}", quote.id))],
+        vec![InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")]
+    ]))
+}
+
+pub(super) fn quote_parcel_prompt(quote_id: i32) -> String {
+    format!("Введите трек-код заказа, к которому относится расчет №{}", quote_id)
+}
+
+/// Returns the track code to attach the quote to, or the reply asking for it again.
+///
+/// Attaching saves the parcel, so track codes of carriers the warehouse does not receive are refused like in the lookup.
+pub(super) fn quote_parcel(quote_id: i32, text: Option<&str>) -> Result<String, Reply> {
+    let track_code = match text.and_then(carrier::parse_track_code) {
+        Some(track_code) => track_code,
+        None => return Err(Reply::new(
+            format!("Неверный формат.\n{}", quote_parcel_prompt(quote_id)),
+            BotState::QuoteParcel { quote_id }
+        ))
+    };
+
+    match Carrier::detect(&track_code).filter(|carrier| !carrier.supported()) {
+        Some(carrier) => Err(Reply::new(
+            format!("Это трек-код {}, этот перевозчик не передает посылки на наш склад.\n{}", carrier.name(), quote_parcel_prompt(quote_id)),
+            BotState::QuoteParcel { quote_id }
+        )),
+        None => Ok(track_code)
+    }
+}
+
+pub(super) fn quote_attached(quote_id: i32, track_code: &str) -> Reply {
+    Reply::new(format!("Расчет №{} сохранен для заказа {}", quote_id, track_code), BotState::Profile { msg_id: placeholder() })
+        .with_markup(back_markup("Вернуться в личный кабинет"))
+}
+
+pub(super) fn quotes_text(quotes: &[Quote]) -> String {
+    if quotes.is_empty() {
+        return "Вы еще ничего не рассчитывали. Посчитать стоимость можно в разделе «Высчитывание цены».".to_string();
+    }
+
+    let quotes: Vec<String> = quotes.iter().map(quote_text).collect();
+
+    format!("Мои расчёты\n\n{}", quotes.join("\n\n"))
+}
+
+/// The hint for /help, depending on what the bot waits for in the current step.
+pub(super) fn help_text(state: &BotState, units: Units) -> String {
+    let length = match units {
+        Units::Metric => "в сантиметрах",
+        Units::Imperial => "в дюймах"
+    };
+
+    let weight = match units {
+        Units::Metric => "в килограммах",
+        Units::Imperial => "в фунтах"
+    };
+
+    match state {
+        BotState::Start => indoc!("
+        Бот помогает заказывать товары из Китая и следить за посылками.
+        Отправьте /start, чтобы открыть личный кабинет.
+        Во время ввода данных /cancel прерывает текущее действие.
+        ").to_string(),
+        BotState::RegisterInit => "Вы начинаете регистрацию — нажмите кнопку под приветствием.".to_string(),
+        BotState::RegisterFirstName => "Вы сейчас вводите имя — отправьте его текстом, или /cancel".to_string(),
+        BotState::RegisterLastName { .. } => "Вы сейчас вводите фамилию — отправьте ее текстом или «-», чтобы пропустить, или /cancel".to_string(),
+        BotState::RegisterPhoneNumber { .. } => "Вы сейчас вводите номер телефона — отправьте его в формате 996XXXXXXXXX, или /cancel".to_string(),
+        BotState::RegisterPhoneCode { .. } => "Вы сейчас подтверждаете номер — отправьте код из SMS, или /cancel".to_string(),
+        BotState::AwaitingApproval => "Ваша заявка на регистрацию на проверке — мы сообщим о решении в этом чате.".to_string(),
+        BotState::ProductStatus { .. } => "Вы сейчас вводите трек-код — отправьте его текстом, или /cancel".to_string(),
+        BotState::ParcelLabel { track_code } => format!(
+            "Вы сейчас подписываете посылку {} — отправьте подпись до {} символов или «-», чтобы пропустить, или /cancel",
+            track_code, MAX_LABEL_LENGTH
+        ),
+        BotState::AddressCity | BotState::AddressStreet { .. } | BotState::AddressEntrance { .. } | BotState::AddressComment { .. } =>
+            "Вы сейчас добавляете адрес доставки — ответьте на последний вопрос бота, или /cancel".to_string(),
+        BotState::DeliveryProof { delivery_id } => format!(
+            "Вы закрываете доставку #{} — пришлите фото вручения или нажмите «Без фото», или /cancel",
+            delivery_id
+        ),
+        BotState::SupportMessage => "Вы сейчас пишете в поддержку — отправьте вопрос одним сообщением, или /cancel".to_string(),
+        BotState::SellerCheck { .. } => "Вы сейчас проверяете адрес продавца — вставьте адрес и телефон текстом, или /cancel".to_string(),
+        BotState::PurchaseLink { .. } => "Вы оформляете выкуп товара — пришлите ссылку на товар, или /cancel".to_string(),
+        BotState::PurchaseOptions { .. } => "Вы оформляете выкуп товара — отправьте размер, цвет и количество или «-», или /cancel".to_string(),
+        BotState::PurchaseBudget { .. } => "Вы оформляете выкуп товара — отправьте бюджет в долларах, или /cancel".to_string(),
+        BotState::WeightDispute { track_code, .. } => format!(
+            "Вы сейчас оспариваете вес посылки {} — опишите проблему, пришлите фото и нажмите «Отправить», или /cancel",
+            track_code
+        ),
+        BotState::Scan { batch_code, .. } => format!(
+            "Вы сканируете партию {} — отправляйте трек-коды, для завершения /done, или /cancel",
+            batch_code
+        ),
+        BotState::ParcelPhoto { track_code } => format!("Вы прикрепляете фото к посылке {} — пришлите фото, или /cancel", track_code),
+        BotState::QuoteParcel { quote_id } => format!("Вы сейчас сохраняете расчет №{} — отправьте трек-код заказа, или /cancel", quote_id),
+        BotState::WalkInFirstName => "Вы регистрируете клиента у стойки — отправьте его имя, или /cancel".to_string(),
+        BotState::WalkInLastName { .. } => "Вы регистрируете клиента у стойки — отправьте его фамилию или «-», или /cancel".to_string(),
+        BotState::WalkInPhoneNumber { .. } => "Вы регистрируете клиента у стойки — отправьте его номер телефона, или /cancel".to_string(),
+        BotState::CityName => "Вы открываете новый город — отправьте его название одним словом, или /cancel".to_string(),
+        BotState::CityTariffs { name } => format!(
+            "Вы открываете город {} — отправьте тарифы, по строке «плотность цена минимум» на полосу, или /cancel",
+            name
+        ),
+        BotState::CityEta { name, .. } => format!("Вы открываете город {} — отправьте срок доставки в днях, например 10-14, или /cancel", name),
+        BotState::CityPickup { name, .. } => format!("Вы открываете город {} — отправьте адрес пункта выдачи, или /cancel", name),
+        BotState::CityAnnouncement { draft } => format!(
+            "Вы открываете город {} — отправьте текст объявления или «-», чтобы взять готовый, или /cancel",
+            draft.name
+        ),
+        BotState::SurveyAnswer { .. } => "Вы сейчас отвечаете на опрос — напишите ответ одним сообщением, или /cancel".to_string(),
+        BotState::TextMenu => "Отправьте номер пункта меню или /start, чтобы показать меню еще раз.".to_string(),
+        BotState::PriceWidth => format!("Вы сейчас вводите ширину коробки — отправьте число {}, или /cancel", length),
+        BotState::PriceLength { .. } => format!("Вы сейчас вводите длину коробки — отправьте число {}, или /cancel", length),
+        BotState::PriceHeight { .. } => format!("Вы сейчас вводите высоту коробки — отправьте число {}, или /cancel", length),
+        BotState::PriceWeight { .. } => format!("Вы сейчас вводите вес коробки — отправьте число {}, или /cancel", weight),
+        BotState::Profile { .. } | BotState::ProfilePages { .. } | BotState::TrackResult { .. } | BotState::Parcels { .. }
+            | BotState::RefundReason { .. } | BotState::Settings { .. } | BotState::Addresses { .. } | BotState::Support { .. } | BotState::Tutorial { .. } =>
+            "Выберите действие кнопками под последним сообщением бота или отправьте /start, чтобы открыть меню.".to_string()
+    }
+}
+
+/// The answer to /cancel, `None` when the bot waits for nothing to cancel.
+pub(super) fn cancel_text(state: &BotState) -> Option<&'static str> {
+    match state {
+        BotState::RegisterFirstName | BotState::RegisterLastName { .. } | BotState::RegisterPhoneNumber { .. } | BotState::RegisterPhoneCode { .. } =>
+            Some("Регистрация отменена. Отправьте /start, чтобы начать заново."),
+        BotState::ProductStatus { .. } | BotState::ParcelLabel { .. } | BotState::SupportMessage | BotState::SellerCheck { .. }
+            | BotState::WeightDispute { .. } | BotState::Scan { .. } | BotState::ParcelPhoto { .. } | BotState::QuoteParcel { .. } | BotState::WalkInFirstName
+            | BotState::WalkInLastName { .. } | BotState::WalkInPhoneNumber { .. } | BotState::CityName | BotState::CityTariffs { .. }
+            | BotState::CityEta { .. } | BotState::CityPickup { .. } | BotState::CityAnnouncement { .. } | BotState::PriceWidth
+            | BotState::PriceLength { .. } | BotState::PriceHeight { .. } | BotState::PriceWeight { .. } | BotState::PurchaseLink { .. }
+            | BotState::PurchaseOptions { .. } | BotState::PurchaseBudget { .. } | BotState::SurveyAnswer { .. } | BotState::AddressCity
+            | BotState::AddressStreet { .. } | BotState::AddressEntrance { .. } | BotState::AddressComment { .. } | BotState::DeliveryProof { .. } =>
+            Some("Действие отменено. Отправьте /start, чтобы открыть меню."),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::InlineKeyboardButtonKind;
+
+    use crate::{attachments::AttachmentKind, models::{DeliveryStatus, RefundStatus}};
+
+    use super::*;
+
+    #[test]
+    fn claim_waits_for_an_operator() {
+        assert_eq!(claim_pending().state, BotState::AwaitingApproval);
+        assert_eq!(cancel_text(&claim_pending().state), None);
+    }
+
+    #[test]
+    fn text_menu_numbers_follow_buttons() {
+        assert_eq!(text_menu_choice(Some("1")), Some("locate_btn"));
+        assert_eq!(text_menu_choice(Some(" 4 ")), Some("code_btn"));
+        assert_eq!(text_menu_choice(Some("8")), Some("seller_check_btn"));
+        assert_eq!(text_menu_choice(Some("9")), Some("tariffs_btn"));
+        assert_eq!(text_menu_choice(Some("10")), Some("quotes_btn"));
+        assert_eq!(text_menu_choice(Some("11")), Some("purchase_btn"));
+        assert_eq!(text_menu_choice(Some("12")), Some("batch_btn"));
+        assert_eq!(text_menu_choice(Some("13")), Some("buttons_btn"));
+    }
+
+    #[test]
+    fn text_menu_ignores_unknown_numbers() {
+        assert_eq!(text_menu_choice(Some("0")), None);
+        assert_eq!(text_menu_choice(Some("14")), None);
+        assert_eq!(text_menu_choice(Some("профиль")), None);
+        assert_eq!(text_menu_choice(None), None);
+    }
+
+    #[test]
+    fn address_contains_client_code_twice() {
+        let address = address_text("MX205", "Айбек", Some("Осмонов"));
+
+        assert_eq!(address.matches("MX205").count(), 2);
+        assert!(address.contains("收件人：溴溴MX205 Aybek Osmonov\n"));
+    }
+
+    #[test]
+    fn seller_message_carries_address_and_code() {
+        let text = seller_message("MX205", "Айбек", None);
+
+        assert!(text.contains(&address_text("MX205", "Айбек", None)));
+        assert!(text.contains("客户代码 MX205"));
+    }
+
+    #[test]
+    fn walk_in_gets_code_and_address() {
+        let text = walk_in_text("MX231", "Айбек", None);
+
+        assert!(text.starts_with("Клиент Айбек зарегистрирован, код MX231."));
+        assert!(text.contains("收件人：溴溴MX231 Aybek\n"));
+    }
+
+    #[test]
+    fn first_name_moves_to_last_name() {
+        let reply = register_first_name(Some("Азамат"), false);
+
+        assert_eq!(reply.state, BotState::RegisterLastName { first_name: "Азамат".to_string() });
+        assert!(reply.text.contains("пропустить"));
+        assert!(!register_first_name(Some("Азамат"), true).text.contains("пропустить"));
+    }
+
+    #[test]
+    fn missing_first_name_is_asked_again() {
+        let reply = register_first_name(None, false);
+
+        assert_eq!(reply.state, BotState::RegisterFirstName);
+        assert!(reply.text.starts_with("Неверный формат."));
+    }
+
+    #[test]
+    fn last_name_keeps_first_name() {
+        let reply = register_last_name("Азамат".to_string(), Some("Осмонов"), false);
+
+        assert_eq!(reply.state, BotState::RegisterPhoneNumber {
+            first_name: "Азамат".to_string(),
+            last_name: Some("Осмонов".to_string())
+        });
+    }
+
+    #[test]
+    fn last_name_is_skipped_unless_required() {
+        let reply = register_last_name("Азамат".to_string(), Some(" Пропустить "), false);
+
+        assert_eq!(reply.state, BotState::RegisterPhoneNumber { first_name: "Азамат".to_string(), last_name: None });
+
+        let reply = register_last_name("Азамат".to_string(), Some("-"), true);
+
+        assert_eq!(reply.state, BotState::RegisterLastName { first_name: "Азамат".to_string() });
+    }
+
+    #[test]
+    fn phone_number_is_normalized() {
+        let phone_number = register_phone_number("Азамат".to_string(), None, Some("+996 555 123 456"));
+
+        assert_eq!(phone_number.ok().as_deref(), Some("996555123456"));
+        assert!(register_phone_number("Азамат".to_string(), None, Some("Осмонов")).is_err());
+    }
+
+    #[test]
+    fn missing_phone_number_keeps_names() {
+        let reply = register_phone_number("Азамат".to_string(), Some("Осмонов".to_string()), None).unwrap_err();
+
+        assert_eq!(reply.state, BotState::RegisterPhoneNumber {
+            first_name: "Азамат".to_string(),
+            last_name: Some("Осмонов".to_string())
+        });
+    }
+
+    #[test]
+    fn empty_support_message_is_asked_again() {
+        assert_eq!(support_message(Some("  "), None).unwrap_err().state, BotState::SupportMessage);
+        assert_eq!(support_message(None, None).unwrap_err().state, BotState::SupportMessage);
+        assert_eq!(support_message(Some(" Где посылка? "), None), Ok(("Где посылка?".to_string(), None)));
+    }
+
+    #[test]
+    fn support_message_may_carry_a_file() {
+        let (text, file) = support_message(None, Some(Ok(attachment("scan")))).unwrap();
+        assert_eq!(text, "📎 Файл без описания");
+        assert_eq!(file, Some(attachment("scan")));
+
+        let reply = support_message(Some("Вот чек"), Some(Err("Такие файлы не принимаются".to_string()))).unwrap_err();
+        assert_eq!(reply.text, "Такие файлы не принимаются");
+        assert_eq!(reply.state, BotState::SupportMessage);
+    }
+
+    #[test]
+    fn off_hours_ticket_promises_next_opening() {
+        let opening = chrono::NaiveDate::from_ymd_opt(2024, 3, 11).unwrap().and_hms_opt(9, 0, 0).unwrap();
+
+        assert!(support_received(7, Some(opening)).text.contains("после 09:00 11.03"));
+        assert!(support_received(7, None).text.contains("передано оператору"));
+    }
+
+    #[test]
+    fn registered_leads_to_profile() {
+        let reply = registered();
+
+        assert_eq!(reply.state.with_msg_id(MessageId(42)), BotState::Profile { msg_id: MessageId(42) });
+        assert!(reply.markup.is_some());
+    }
+
+    #[test]
+    fn missing_track_code_stays_in_product_status() {
+        let reply = track_code(None).unwrap_err();
+
+        assert_eq!(reply.state, BotState::ProductStatus { msg_id: placeholder() });
+    }
+
+    #[test]
+    fn product_status_reports_warehouse_arrival() {
+        assert_eq!(product_status("YT1".to_string(), true, None, None).text, "Товар уже на складе, ждет сортировки");
+        assert_eq!(product_status("YT1".to_string(), false, None, None).text, "Товара еще нет на складе");
+    }
+
+    #[test]
+    fn product_status_names_the_carrier() {
+        assert_eq!(
+            product_status("SF1234567890123".to_string(), false, None, None).text,
+            "Перевозчик: SF Express (顺丰)\nТовара еще нет на складе"
+        );
+    }
+
+    #[test]
+    fn product_status_shows_the_warehouse_detail() {
+        assert_eq!(
+            product_status("YT1".to_string(), false, Some("проблема с посылкой, обратитесь в поддержку"), None).text,
+            "Товара еще нет на складе\nСклад: проблема с посылкой, обратитесь в поддержку"
+        );
+    }
+
+    #[test]
+    fn product_status_names_the_fallback_source() {
+        assert_eq!(
+            product_status("YT1".to_string(), true, None, Some("kapro-mirror")).text,
+            "Товар уже на складе, ждет сортировки\nИсточник: kapro-mirror"
+        );
+    }
+
+    #[test]
+    fn unsupported_carrier_leads_back_to_profile() {
+        let reply = unsupported_carrier(Carrier::Jd);
+
+        assert!(reply.text.contains("JD Logistics"));
+        assert_eq!(reply.state, BotState::Profile { msg_id: placeholder() });
+    }
+
+    #[test]
+    fn product_status_remembers_track_code() {
+        let reply = product_status("YT1".to_string(), true, None, None);
+
+        assert_eq!(
+            reply.state.with_msg_id(MessageId(7)),
+            BotState::TrackResult { msg_id: MessageId(7), track_code: "YT1".to_string() }
+        );
+    }
+
+    #[test]
+    fn address_is_asked_step_by_step() {
+        let reply = address_city(Some(" Бишкек "));
+        assert_eq!(reply.state, BotState::AddressStreet { city: "Бишкек".to_string() });
+
+        assert_eq!(address_street("Бишкек".to_string(), Some("  ")).state, BotState::AddressStreet { city: "Бишкек".to_string() });
+
+        let reply = address_entrance("Бишкек".to_string(), "Киевская 95, кв 4".to_string(), Some("-"));
+        let draft = match reply.state {
+            BotState::AddressComment { draft } => *draft,
+            state => panic!("unexpected state {:?}", state)
+        };
+        assert_eq!(draft.entrance, None);
+
+        let (draft, comment) = address_comment(draft, Some("Домофон 4")).unwrap();
+        let address = Address { id: 3, city: draft.city, street: draft.street, entrance: draft.entrance, comment };
+        assert_eq!(address.line(), "Бишкек, Киевская 95, кв 4 (Домофон 4)");
+
+        let markup = courier_markup(&[(12, "MX-101".to_string(), address)]);
+        let data = match &markup.inline_keyboard[0][0].kind {
+            InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {:?}", kind)
+        };
+        assert_eq!(courier_choice(Some(&data)), Some((12, 3)));
+    }
+
+    #[test]
+    fn couriers_close_deliveries_from_their_tasks() {
+        let task = |id, status, courier: Option<&str>| DeliveryTask {
+            id,
+            track_code: format!("YT{}", id),
+            address: "Бишкек, Киевская 95".to_string(),
+            status,
+            courier: courier.map(str::to_string)
+        };
+
+        let text = deliveries_text(&[task(1, DeliveryStatus::Queued, None), task(2, DeliveryStatus::Assigned, Some("Бакыт"))]);
+        assert!(text.contains("#1 YT1: ждет курьера\n"));
+        assert!(text.contains("#2 YT2: у курьера (Бакыт)\n"));
+
+        let markup = courier_tasks_markup(&[task(7, DeliveryStatus::Assigned, None)]);
+        let data = match &markup.inline_keyboard[0][0].kind {
+            InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {:?}", kind)
+        };
+        assert_eq!(delivered_choice(Some(&data)), Some(7));
+        assert_eq!(delivery_proof_prompt(7).state, BotState::DeliveryProof { delivery_id: 7 });
+        assert_eq!(delivered_choice(Some("delivered:x")), None);
+    }
+
+    #[test]
+    fn address_book_is_limited() {
+        let address = |id| Address { id, city: "Ош".to_string(), street: "Ленина 1".to_string(), entrance: Some("2".to_string()), comment: None };
+        let full: Vec<Address> = (1..=MAX_ADDRESSES as i32).map(address).collect();
+
+        let reply = addresses(&full);
+        assert!(reply.text.contains("5. Ош, Ленина 1, подъезд 2"));
+        assert!(!reply.markup.unwrap().inline_keyboard.iter().flatten().any(|button| button.text == "Добавить адрес"));
+        assert!(addresses(&[]).markup.unwrap().inline_keyboard.iter().flatten().any(|button| button.text == "Добавить адрес"));
+    }
+
+    #[test]
+    fn start_links_carry_a_track_code() {
+        assert_eq!(start_track_code(Some("/start track_yt123456789")), Some("YT123456789".to_string()));
+        assert_eq!(start_track_code(Some("/start")), None);
+        assert_eq!(start_track_code(Some("/start ref_42")), None);
+        assert_eq!(start_track_code(Some("/start track_")), None);
+        assert_eq!(start_track_code(Some("/start track_YT-1")), None);
+        assert_eq!(start_track_code(Some("track_YT1")), None);
+    }
+
+    fn parcel(track_code: &str, refund: Option<RefundStatus>) -> Parcel {
+        Parcel { id: 1, track_code: track_code.to_string(), label: None, arrived: false, refund, quote_id: None }
+    }
+
+    #[test]
+    fn cancelled_orders_show_refund_status() {
+        let parcels = [parcel("YT1", None), parcel("YT2", Some(RefundStatus::Approved))];
+
+        let text = parcels_text(&parcels);
+
+        assert!(text.contains("2. YT2 ⏳\n    Заказ отменен, возврат одобрен"));
+        assert_eq!(text.matches("Заказ отменен").count(), 1);
+    }
+
+    #[test]
+    fn cancel_button_is_hidden_once_refund_is_requested() {
+        let markup = parcels_markup(&[parcel("YT1", None), parcel("YT2", Some(RefundStatus::Requested))]);
+
+        assert_eq!(markup.inline_keyboard[0].len(), 2);
+        assert_eq!(markup.inline_keyboard[1].len(), 1);
+    }
+
+    #[test]
+    fn refund_reason_is_picked_by_index() {
+        assert_eq!(refund_reason(Some("reason:0")), Some("Передумал(а) покупать"));
+        assert_eq!(refund_reason(Some("reason:99")), None);
+        assert_eq!(refund_reason(Some("back_btn")), None);
+    }
+
+    #[test]
+    fn tutorial_command_takes_slug_title_and_text() {
+        let tutorial = tutorial_from_command("Alibaba Alibaba.com\nЗарегистрируйтесь\nи укажите адрес").unwrap();
+
+        assert_eq!(tutorial.slug, "alibaba");
+        assert_eq!(tutorial.title, "Alibaba.com");
+        assert_eq!(tutorial.text, "Зарегистрируйтесь\nи укажите адрес");
+    }
+
+    #[test]
+    fn tutorial_command_rejects_bad_slugs_and_missing_text() {
+        assert!(tutorial_from_command("alibaba Alibaba").is_none());
+        assert!(tutorial_from_command("али Alibaba\nтекст").is_none());
+        assert!(tutorial_from_command("alibaba:1 Alibaba\nтекст").is_none());
+    }
+
+    #[test]
+    fn tutorial_links_become_url_buttons() {
+        assert_eq!(
+            tutorial_links_from_command("Taobao https://m.tb.cn -"),
+            Some(("taobao".to_string(), Some("https://m.tb.cn/".to_string()), None))
+        );
+        assert!(tutorial_links_from_command("taobao taobao://home -").is_none());
+        assert!(tutorial_links_from_command("taobao https://m.tb.cn").is_none());
+
+        let mut tutorial = Tutorial {
+            slug: "taobao".to_string(),
+            title: "TaoBao".to_string(),
+            text: "Текст".to_string(),
+            app_url: None,
+            web_url: Some("https://www.taobao.com".to_string())
+        };
+
+        assert_eq!(tutorial_markup(&tutorial).inline_keyboard.iter().map(Vec::len).collect::<Vec<_>>(), vec![1, 1]);
+
+        tutorial.web_url = None;
+        assert_eq!(tutorial_markup(&tutorial).inline_keyboard.iter().map(Vec::len).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn canned_command_takes_slug_and_text() {
+        assert_eq!(
+            canned_from_command("Greeting Здравствуйте, {name}!\nЧем можем помочь?"),
+            Some(("greeting".to_string(), "Здравствуйте, {name}!\nЧем можем помочь?".to_string()))
+        );
+        assert!(canned_from_command("greeting").is_none());
+        assert!(canned_from_command("привет Здравствуйте").is_none());
+    }
+
+    #[test]
+    fn canned_target_falls_back_to_the_replied_ticket() {
+        let notification = "🆕 Обращение #42 от MX201 (@azamat):\n\nГде посылка?";
+
+        assert_eq!(canned_target("#7 greeting", Some(notification)), Some((7, Some("greeting".to_string()))));
+        assert_eq!(canned_target("Customs_Delay", Some(notification)), Some((42, Some("customs_delay".to_string()))));
+        assert_eq!(canned_target("", Some(notification)), Some((42, None)));
+        assert_eq!(canned_target("greeting", None), None);
+    }
+
+    #[test]
+    fn tutorials_are_laid_out_two_per_row() {
+        let tutorial = |slug: &str| Tutorial { slug: slug.to_string(), title: slug.to_string(), text: String::new(), app_url: None, web_url: None };
+
+        let markup = tutorials_markup(&[tutorial("1688"), tutorial("poizon"), tutorial("alibaba")]);
+
+        assert_eq!(markup.inline_keyboard.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn shelves_sort_naturally() {
+        let shelves = vec![("B10".to_string(), 1), ("B2".to_string(), 0), ("A-3".to_string(), 4), ("B".to_string(), 2)];
+
+        assert_eq!(shelves_text(&shelves), "Посылок на полках:\nA-3: 4\nB: 2\nB2: 0\nB10: 1");
+        assert_eq!(shelf_code(" b 3 "), Some("B3".to_string()));
+        assert_eq!(shelf_code("  "), None);
+    }
+
+    #[test]
+    fn shelf_depends_on_client_number() {
+        assert_eq!(suggest_shelf("MX201"), "C-1");
+        assert_eq!(suggest_shelf("MX201H"), "C-1");
+        assert_eq!(suggest_shelf("MX299"), "C-10");
+        assert_eq!(suggest_shelf("MX1234"), "M-4");
+        assert_eq!(suggest_shelf("MX"), "A-1");
+    }
+
+    #[test]
+    fn scanned_code_lists_every_owner() {
+        assert_eq!(scanned_line("YT1", &[]), "❌ YT1 — не найден");
+        assert_eq!(
+            scanned_line("YT1", &["MX201".to_string(), "MX315".to_string()]),
+            "✅ YT1 → MX201, полка C-1\n✅ YT1 → MX315, полка D-2"
+        );
+    }
+
+    #[test]
+    fn scan_summary_lists_unmatched_codes() {
+        let summary = scan_summary("B12", 3, &["YT1".to_string(), "SF2".to_string()]);
+
+        assert!(summary.contains("Найдено: 3\nНе найдено: 2"));
+        assert!(summary.ends_with("YT1\nSF2"));
+        assert!(!scan_summary("B12", 3, &[]).ends_with('\n'));
+    }
+
+    #[test]
+    fn dash_skips_parcel_label() {
+        assert_eq!(parcel_label("YT1".to_string(), Some(" - ")), Ok(None));
+        assert_eq!(
+            parcel_label("YT1".to_string(), Some("кроссовки для брата")),
+            Ok(Some("кроссовки для брата".to_string()))
+        );
+    }
+
+    #[test]
+    fn overlong_parcel_label_is_asked_again() {
+        let reply = parcel_label("YT1".to_string(), Some(&"а".repeat(MAX_LABEL_LENGTH + 1))).unwrap_err();
+
+        assert_eq!(reply.state, BotState::ParcelLabel { track_code: "YT1".to_string() });
+    }
+
+    #[test]
+    fn item_title_is_cut_to_a_label() {
+        let item = Item { title: Some(format!("{} кроссовки", "а".repeat(MAX_LABEL_LENGTH - 1))), ..Item::default() };
+
+        assert_eq!(item_label(&item).map(|label| label.chars().count()), Some(MAX_LABEL_LENGTH - 1));
+        assert_eq!(item_label(&Item::default()), None);
+    }
+
+    #[test]
+    fn invalid_width_is_asked_again() {
+        assert_eq!(price_width(Units::Metric, Some("широкая")).state, BotState::PriceWidth);
+        assert_eq!(price_width(Units::Metric, None).state, BotState::PriceWidth);
+    }
+
+    #[test]
+    fn dimensions_accumulate_across_steps() {
+        assert_eq!(price_width(Units::Metric, Some("40")).state, BotState::PriceLength { width: 40.0 });
+        assert_eq!(
+            price_length(Units::Metric, 40.0, Some("60")).state,
+            BotState::PriceHeight { width: 40.0, length: 60.0 }
+        );
+        assert_eq!(
+            price_height(Units::Metric, 40.0, 60.0, Some("50")).state,
+            BotState::PriceWeight { width: 40.0, length: 60.0, height: 50.0 }
+        );
+    }
+
+    #[test]
+    fn decimal_comma_is_accepted() {
+        assert_eq!(price_width(Units::Metric, Some("40,5")).state, BotState::PriceLength { width: 40.5 });
+    }
+
+    #[test]
+    fn sizes_and_weights_stay_in_range() {
+        for text in ["nan", "inf", "-inf", "0", "-5", "1e30", "1001"] {
+            assert!(price_weight(Units::Metric, 40.0, 60.0, 50.0, Some(text)).is_err(), "{}", text);
+        }
+
+        assert_eq!(price_weight(Units::Metric, 40.0, 60.0, 50.0, Some("1000")), Ok(1000.0));
+    }
+
+    #[test]
+    fn imperial_input_is_stored_in_centimeters() {
+        let reply = price_width(Units::Imperial, Some("10"));
+
+        assert_eq!(reply.state, BotState::PriceLength { width: 25.4 });
+        assert!(reply.text.starts_with("Введите длину коробки с товаром (дюймы)"));
+        assert!(price_height(Units::Imperial, 1.0, 1.0, Some("1")).text.contains("(фунты)"));
+    }
+
+    #[test]
+    fn price_examples_follow_units() {
+        assert!(price_prompt(Units::Metric, &[]).text.ends_with("Например: 40"));
+        assert!(price_prompt(Units::Imperial, &[]).text.ends_with("Например: 16"));
+        assert!(price_width(Units::Metric, Some("40")).text.contains("«Д» на картинке"));
+        assert!(price_height(Units::Imperial, 1.0, 1.0, Some("1")).text.ends_with("Например: 27,5"));
+    }
+
+    #[test]
+    fn tariff_bands_end_where_the_next_begins() {
+        let tariffs = [
+            Tariff { min_density: 0.0, price_per_kg: Money::from_cents(450), min_charge: Money::from_cents(500) },
+            Tariff { min_density: 100.0, price_per_kg: Money::from_cents(320), min_charge: Money::from_cents(500) },
+            Tariff { min_density: 200.0, price_per_kg: Money::from_cents(280), min_charge: Money::from_cents(300) }
+        ];
+
+        let text = tariffs_text(&tariffs);
+
+        assert!(text.contains("▫️ 0–100 кг/м3: 4,50 $/кг, минимум 5,00 $"));
+        assert!(text.contains("▫️ от 200 кг/м3: 2,80 $/кг, минимум 3,00 $"));
+        assert!(tariffs_text(&[]).contains("не опубликованы"));
+    }
+
+    #[test]
+    fn tariff_command_takes_three_numbers() {
+        assert_eq!(tariff_from_command("100 3,2 5"), Some(Tariff { min_density: 100.0, price_per_kg: Money::from_cents(320), min_charge: Money::from_cents(500) }));
+        assert_eq!(tariff_from_command("100 3,2"), None);
+        assert_eq!(tariff_from_command("100 -1 5"), None);
+        assert_eq!(tariff_from_command("100 3,255 5"), None);
+    }
+
+    fn quote(weight_kg: f32, price: Option<Money>) -> Quote {
+        Quote {
+            id: 7,
+            telegram_id: 1,
+            width: 100.0,
+            length: 100.0,
+            height: 100.0,
+            weight_kg,
+            price,
+            tariff_version: 3,
+            created_at: NaiveDateTime::default()
+        }
+    }
+
+    #[test]
+    fn dense_box_is_priced_by_weight() {
+        let text = quote_text(&quote(150.0, Some(Money::from_cents(42000))));
+
+        assert!(text.contains("по весу"));
+        assert!(text.contains("Стоимость доставки: 420,00 $"));
+        assert!(quote_reply(&quote(150.0, None)).text.starts_with("Расчет №7"));
+    }
+
+    #[test]
+    fn quote_buttons_carry_the_quote_id() {
+        let markup = quote_reply(&quote(150.0, None)).markup.unwrap();
+
+        let data: Vec<_> = markup.inline_keyboard[0].iter().map(|button| match &button.kind {
+            InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            _ => String::new()
+        }).collect();
+
+        assert_eq!(data, vec!["requote:7", "save_quote:7"]);
+    }
+
+    #[test]
+    fn quote_is_saved_under_a_single_track_code() {
+        assert_eq!(quote_parcel(7, Some(" YT1 ")).unwrap(), "YT1");
+        assert_eq!(quote_parcel(7, Some("YT1 YT2")).unwrap_err().state, BotState::QuoteParcel { quote_id: 7 });
+        assert_eq!(quote_parcel(7, None).unwrap_err().state, BotState::QuoteParcel { quote_id: 7 });
+        assert_eq!(quote_parcel(7, Some("../admin")).unwrap_err().state, BotState::QuoteParcel { quote_id: 7 });
+        assert!(quote_parcel(7, Some("JDV012345678901")).unwrap_err().text.contains("JD Logistics"));
+    }
+
+    #[test]
+    fn light_box_is_priced_by_density() {
+        assert!(quote_text(&quote(50.0, None)).contains("по плотности"));
+    }
+
+    #[test]
+    fn imperial_weight_is_converted_before_density() {
+        // 330 lb in a 1 m3 box is about 150 kg.
+        let weight_kg = price_weight(Units::Imperial, 100.0, 100.0, 100.0, Some("330")).unwrap();
+
+        assert!(quote_text(&quote(weight_kg, None)).contains("по весу"));
+        assert_eq!(price_weight(Units::Metric, 1.0, 1.0, 1.0, Some("тяжелая")).unwrap_err().state, BotState::PriceWeight { width: 1.0, length: 1.0, height: 1.0 });
+    }
+
+    #[test]
+    fn quote_takes_the_band_of_its_density() {
+        let tariffs = [
+            Tariff { min_density: 100.0, price_per_kg: Money::from_cents(320), min_charge: Money::from_cents(500) },
+            Tariff { min_density: 200.0, price_per_kg: Money::from_cents(280), min_charge: Money::from_cents(300) }
+        ];
+
+        assert_eq!(quote_price(&tariffs, 150.0, 10.0), Some(Money::from_cents(3200)));
+        assert_eq!(quote_price(&tariffs, 250.0, 10.3), Some(Money::from_cents(2884)));
+        assert_eq!(quote_price(&tariffs, 50.0, 1.0), Some(Money::from_cents(500)));
+        assert_eq!(quote_price(&[], 150.0, 10.0), None);
+    }
+
+    fn attachment(file_id: &str) -> Attachment {
+        Attachment { kind: AttachmentKind::Photo, file_id: file_id.to_string(), mime_type: "image/jpeg".to_string(), size: 1000 }
+    }
+
+    #[test]
+    fn dispute_collects_comment_and_photos() {
+        let reply = dispute_input("YT1".to_string(), String::new(), Vec::new(), Some("Весы показывали 2 кг"), None);
+        let reply = match reply.state {
+            BotState::WeightDispute { track_code, comment, attachments } => dispute_input(track_code, comment, attachments, None, Some(Ok(attachment("photo1")))),
+            state => panic!("unexpected state {:?}", state)
+        };
+
+        assert_eq!(reply.state, BotState::WeightDispute {
+            track_code: "YT1".to_string(),
+            comment: "Весы показывали 2 кг".to_string(),
+            attachments: vec![attachment("photo1")]
+        });
+    }
+
+    #[test]
+    fn rejected_files_leave_the_dispute_as_it_was() {
+        let attachments = vec![attachment("photo"); 2];
+        let reply = dispute_input("YT1".to_string(), String::new(), attachments, Some("еще"), Some(Err("Можно приложить не больше 2 файлов".to_string())));
+
+        assert!(reply.text.starts_with("Можно приложить не больше 2 файлов"));
+        assert!(matches!(reply.state, BotState::WeightDispute { comment, attachments, .. } if comment.is_empty() && attachments.len() == 2));
+    }
+
+    #[test]
+    fn dispute_ticket_carries_shipment_data() {
+        let shipment = Shipment {
+            track_code: "YT1".to_string(),
+            batch_code: Some("B12".to_string()),
+            weight_kg: Some(2.5),
+            declared_value: None,
+            description: Some("Кроссовки".to_string())
+        };
+
+        let text = dispute_ticket_text(&shipment, "", 2);
+
+        assert!(text.contains("Партия: B12"));
+        assert!(text.contains("Вес: 2.50 кг"));
+        assert!(text.contains("Объявленная стоимость: —"));
+        assert!(text.contains("Файлов: 2"));
+    }
+
+    #[test]
+    fn seller_check_waits_for_a_corrected_address() {
+        let reply = seller_check(Some("广东省白云区 13812345678"));
+
+        assert!(reply.text.contains("Не указан город"));
+        assert_eq!(reply.state, BotState::SellerCheck { msg_id: placeholder() });
+
+        let reply = seller_check(Some("广东省广州市白云区 13812345678"));
+
+        assert_eq!(reply.state, BotState::Profile { msg_id: placeholder() });
+    }
+
+    #[test]
+    fn city_name_is_one_word() {
+        assert_eq!(city_name(Some(" Ош ")).as_deref(), Some("Ош"));
+        assert_eq!(city_name(Some("Кара-Балта")).as_deref(), Some("Кара-Балта"));
+        assert_eq!(city_name(Some("Кара Балта")), None);
+        assert_eq!(city_name(Some("  ")), None);
+    }
+
+    #[test]
+    fn city_tariffs_take_a_band_per_line() {
+        let tariffs = city_tariffs(Some("200 2,5 10\n100 3 10\n")).unwrap();
+
+        assert_eq!(tariffs.iter().map(|tariff| tariff.min_density).collect::<Vec<_>>(), vec![100.0, 200.0]);
+        assert_eq!(city_tariffs(Some("100 3 10\nдорого")), None);
+        assert_eq!(city_tariffs(Some("100 3 10\n100 4 10")), None);
+        assert_eq!(city_tariffs(Some("")), None);
+    }
+
+    #[test]
+    fn city_eta_is_a_range_of_days() {
+        assert_eq!(city_eta(Some("10-14")), Some((10, 14)));
+        assert_eq!(city_eta(Some("10 14")), Some((10, 14)));
+        assert_eq!(city_eta(Some("14-10")), None);
+        assert_eq!(city_eta(Some("10")), None);
+    }
+
+    #[test]
+    fn help_follows_the_current_step() {
+        assert_eq!(
+            help_text(&BotState::PriceWidth, Units::Metric),
+            "Вы сейчас вводите ширину коробки — отправьте число в сантиметрах, или /cancel"
+        );
+        assert!(help_text(&BotState::PriceWeight { width: 1.0, length: 1.0, height: 1.0 }, Units::Imperial).contains("в фунтах"));
+        assert!(help_text(&BotState::ParcelLabel { track_code: "YT1".to_string() }, Units::Metric).contains("YT1"));
+        assert!(help_text(&BotState::Start, Units::Metric).contains("/start"));
+    }
+
+    #[test]
+    fn cancel_only_stops_input() {
+        assert!(cancel_text(&BotState::RegisterFirstName).unwrap().contains("Регистрация"));
+        assert!(cancel_text(&BotState::PriceHeight { width: 1.0, length: 1.0 }).is_some());
+        assert_eq!(cancel_text(&BotState::Profile { msg_id: placeholder() }), None);
+        assert_eq!(cancel_text(&BotState::AwaitingApproval), None);
+    }
+
+    #[test]
+    fn payment_shows_what_is_left() {
+        let mut invoice = Invoice {
+            parcel_id: 1,
+            track_code: "YT1".to_string(),
+            client_code: "MX1".to_string(),
+            telegram_id: 1,
+            amount: Money::from_cents(1500),
+            paid: Money::from_cents(500)
+        };
+
+        assert_eq!(payment_text(&invoice), "Оплата по посылке YT1 получена: оплачено 5,00 $ из 15,00 $, осталось 10,00 $.");
+
+        invoice.paid = Money::from_cents(1500);
+        assert_eq!(payment_text(&invoice), "✅ Доставка посылки YT1 оплачена: 15,00 $.");
+        assert!(invoice_text(&invoice, false).ends_with("на пункте выдачи."));
+    }
+
+    #[test]
+    fn delivered_lists_unpaid_parcels() {
+        assert_eq!(delivered_text("B1", 3, &[]), "Партия B1 доставлена, посылок: 3");
+        assert_eq!(
+            delivered_text("B1", 1, &["YT2".to_string(), "YT3".to_string()]),
+            "Партия B1 доставлена, посылок: 1\nНе выданы без оплаты (2): YT2, YT3"
+        );
+    }
+
+    #[test]
+    fn purchase_link_is_read_from_a_share_text() {
+        let reply = purchase_link(Some("Кроссовки https://example.com/item/42"));
+        assert!(matches!(reply.state, BotState::PurchaseLink { .. }));
+
+        let reply = purchase_link(Some("Кроссовки https://item.taobao.com/item.htm?id=42 смотри"));
+        assert_eq!(reply.state, BotState::PurchaseOptions {
+            marketplace: "taobao.com".to_string(),
+            link: "https://item.taobao.com/item.htm?id=42".to_string()
+        });
+    }
+
+    #[test]
+    fn purchase_options_and_budget_are_checked() {
+        assert_eq!(purchase_options(Some(" - ")), Ok(None));
+        assert_eq!(purchase_options(Some("42, черный")), Ok(Some("42, черный".to_string())));
+        assert!(purchase_options(Some(&"x".repeat(201))).is_err());
+        assert!(purchase_options(None).is_err());
+
+        assert_eq!(purchase_budget(Some("25,5")), Some(Money::from_cents(2550)));
+        assert_eq!(purchase_budget(Some("0")), None);
+        assert_eq!(purchase_budget(Some("много")), None);
+    }
+
+    #[test]
+    fn purchase_queue_groups_by_marketplace() {
+        let request = |id, marketplace: &str| PurchaseRequest {
+            id,
+            client_code: "MX205".to_string(),
+            telegram_id: 1,
+            marketplace: marketplace.to_string(),
+            link: "https://example.com".to_string(),
+            options: None,
+            budget: Money::from_cents(2500),
+            status: PurchaseStatus::New,
+            quote: None,
+            comment: None
+        };
+
+        let text = purchase_queue_text(&[request(1, "1688.com"), request(3, "1688.com"), request(2, "taobao.com")]);
+
+        assert_eq!(text.matches("1688.com").count(), 3);
+        assert!(text.find("№3").unwrap() < text.find("\n\ntaobao.com").unwrap());
+
+        let mut quoted = request(1, "taobao.com");
+        quoted.status = PurchaseStatus::Quoted;
+        quoted.quote = Some(Money::from_cents(2300));
+        quoted.comment = Some("доставка по Китаю бесплатная".to_string());
+
+        let text = purchase_text(&quoted);
+        assert!(text.contains(&Money::from_cents(2300).to_string()));
+        assert!(text.ends_with("Комментарий оператора: доставка по Китаю бесплатная"));
+    }
+
+    #[test]
+    fn saved_boxes_skip_to_the_weight() {
+        let preset = BoxPreset { id: 3, width: 40.0, length: 60.0, height: 35.0 };
+
+        let reply = price_prompt(Units::Metric, std::slice::from_ref(&preset));
+        assert_eq!(reply.state, BotState::PriceWidth);
+        assert!(matches!(
+            &reply.markup.unwrap().inline_keyboard[0][0].kind,
+            InlineKeyboardButtonKind::CallbackData(data) if data == "box:3"
+        ));
+        assert!(price_prompt(Units::Metric, &[]).markup.is_none());
+
+        let reply = box_preset_picked(Units::Imperial, &preset);
+        assert_eq!(reply.state, BotState::PriceWeight { width: 40.0, length: 60.0, height: 35.0 });
+        assert!(reply.text.starts_with("Коробка 40×60×35 см.\nВведите вес коробки с товаром (фунты)"));
+    }
+
+    #[test]
+    fn batch_notice_lists_the_clients_parcels() {
+        let text = batch_status_text("KG-07", BatchStatus::Customs, &["YT1".to_string(), "YT2".to_string()]);
+
+        assert!(text.starts_with("🛃 Партия KG-07 проходит таможню."));
+        assert!(text.ends_with("Ваши посылки в ней: YT1, YT2"));
+    }
+
+    #[test]
+    fn batch_progress_marks_passed_statuses() {
+        let at = |day| chrono::NaiveDate::from_ymd_opt(2024, 10, day).unwrap().and_hms_opt(9, 30, 0).unwrap();
+
+        let batch = Batch { code: "KG-07".to_string(), status: BatchStatus::Departed, parcels: 12, updated_at: at(4) };
+        let events = vec![
+            BatchEvent { status: BatchStatus::Forming, created_at: at(1) },
+            BatchEvent { status: BatchStatus::Departed, created_at: at(4) }
+        ];
+
+        let text = batch_progress_text(&[(batch, events)]);
+
+        assert!(text.starts_with("🚚 Партия KG-07\n🟩🟩🟩⬜⬜ в пути\n"));
+        assert!(text.contains("✅ формируется на складе — 01.10 09:30\n✅ загружена\n✅ в пути — 04.10 09:30\n▫️ на таможне"));
+        assert!(text.ends_with("Обновлено: 04.10.2024 09:30"));
+    }
+
+    #[test]
+    fn phone_is_asked_again_after_wrong_codes() {
+        let draft = PhoneDraft {
+            first_name: "Айбек".to_string(),
+            last_name: None,
+            phone_number: "996700123456".to_string(),
+            code_hash: sms::hash_code("0427", "5eed"),
+            attempts: 0
+        };
+
+        assert_eq!(register_phone_code(draft.clone(), Some("04 27")).unwrap(), draft);
+
+        let mut draft = draft;
+
+        for attempts in 1..sms::MAX_CODE_ATTEMPTS {
+            let reply = register_phone_code(draft, Some("1111")).unwrap_err();
+
+            draft = match reply.state {
+                BotState::RegisterPhoneCode { draft } => *draft,
+                state => panic!("unexpected state {:?}", state)
+            };
+            assert_eq!(draft.attempts, attempts);
+        }
+
+        let reply = register_phone_code(draft, None).unwrap_err();
+        assert_eq!(reply.state, BotState::RegisterPhoneNumber { first_name: "Айбек".to_string(), last_name: None });
+    }
+
+    #[test]
+    fn survey_questions_take_options_or_text() {
+        assert_eq!(
+            parse_survey_question("3 Как доставка? | Отлично | Плохо").unwrap(),
+            (3, "Как доставка?".to_string(), vec!["Отлично".to_string(), "Плохо".to_string()])
+        );
+        assert_eq!(parse_survey_question("#3 Что улучшить?").unwrap(), (3, "Что улучшить?".to_string(), Vec::new()));
+
+        assert!(parse_survey_question("3 Как доставка? | Отлично").is_err());
+        assert!(parse_survey_question("Как доставка?").is_err());
+        assert!(parse_survey_question("3 | a | b").is_err());
+    }
+
+    #[test]
+    fn survey_results_count_options_and_list_texts() {
+        let survey = Survey {
+            id: 3,
+            title: "Качество".to_string(),
+            sent: true,
+            questions: vec![
+                SurveyQuestion { position: 1, text: "Как доставка?".to_string(), options: vec!["Отлично".to_string(), "Плохо".to_string()] },
+                SurveyQuestion { position: 2, text: "Что улучшить?".to_string(), options: Vec::new() }
+            ]
+        };
+        let answer = |position, option, text: Option<&str>| SurveyAnswer { position, option, text: text.map(str::to_string) };
+
+        let text = survey_results_text(&survey, &[
+            answer(1, Some(0), None),
+            answer(1, Some(0), None),
+            answer(1, Some(1), None),
+            answer(2, None, Some("Быстрее")),
+            answer(2, None, Some("Дешевле"))
+        ]);
+
+        assert!(text.contains("1. Как доставка? (ответов: 3)\n▫️ Отлично — 2 (66%)\n▫️ Плохо — 1 (33%)"));
+        assert!(text.contains("2. Что улучшить? (ответов: 2)\n— Дешевле\n— Быстрее"));
+
+        assert_eq!(survey_callback(Some("survey:3:1:0")), Some((3, 1, Some(0))));
+        assert_eq!(survey_callback(Some("survey:3:2:text")), Some((3, 2, None)));
+        assert_eq!(survey_callback(Some("survey:3:2")), None);
+    }
+
+    #[test]
+    fn parcel_buttons_carry_ids() {
+        let markup = parcels_markup(&[parcel(&"Y".repeat(60), None)]);
+        let data = match &markup.inline_keyboard[0][1].kind {
+            InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            kind => panic!("unexpected button {:?}", kind)
+        };
+        assert_eq!(parcel_callback(Some(&data)), Some(("cancel", 1)));
+        assert_eq!(parcel_callback(Some("label:1")), Some(("label", 1)));
+        assert_eq!(parcel_callback(Some("delete:1")), None);
+        assert_eq!(parcel_callback(Some("label:YT123")), None);
+    }
+
+    #[test]
+    fn reactions_pick_an_action_and_ratings_parse() {
+        assert_eq!(reaction_action("👍"), Some(ReactionAction::Acknowledge));
+        assert_eq!(reaction_action("❤"), Some(ReactionAction::Rate));
+        assert_eq!(reaction_action("❤️"), Some(ReactionAction::Rate));
+        assert_eq!(reaction_action("🔥"), None);
+
+        let (_, markup) = rating_prompt("YT123");
+        assert_eq!(markup.inline_keyboard.len(), 5);
+
+        assert_eq!(rating_callback(Some("rate:YT123:5")), Some(("YT123".to_string(), 5)));
+        assert_eq!(rating_callback(Some("rate:YT123:6")), None);
+        assert_eq!(rating_callback(Some("rate::3")), None);
+        assert_eq!(rating_callback(Some("survey:3:1:0")), None);
+    }
+
+    #[test]
+    fn override_receipt_shows_the_difference() {
+        let invoice = Invoice {
+            parcel_id: 1,
+            track_code: "YT123".to_string(),
+            client_code: "MX201".to_string(),
+            telegram_id: 7,
+            amount: Money::from_cents(1500),
+            paid: Money::from_cents(500)
+        };
+        let change = PriceOverride { original: Money::from_cents(2000), final_price: Money::from_cents(1500), reason: OverrideReason::Delay };
+
+        let text = override_text(&invoice, &change);
+
+        assert!(text.contains("было 20,00 $, стало 15,00 $ (скидка 5,00 $)"));
+        assert!(text.contains("Причина: задержка доставки"));
+        assert!(text.contains("К оплате 10,00 $"));
+
+        let penalty = PriceOverride { final_price: Money::from_cents(2500), ..change };
+        assert!(override_text(&invoice, &penalty).contains("(надбавка 5,00 $)"));
+    }
+}
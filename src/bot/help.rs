@@ -0,0 +1,60 @@
+use teloxide::{macros::BotCommands, requests::Requester, types::Message};
+
+use crate::{database::Db, models::Units, retry::Bot, staff::Staff};
+
+use super::{flow, BotDialogue, BotService, BotState, HandlerResult};
+
+/// Commands every user may send in any step of a dialogue.
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum UserCommand {
+    Help,
+    Cancel,
+    Tasks,
+    Join(String)
+}
+
+impl BotService {
+    pub(super) async fn handle_user_command(bot: Bot, dialogue: BotDialogue, msg: Message, cmd: UserCommand, state: BotState, db: Db, staff: Staff) -> HandlerResult {
+        log::info!("Bot: handle_user_command");
+        match cmd {
+            UserCommand::Help => Self::help(bot, msg, state, db).await,
+            UserCommand::Cancel => Self::cancel(bot, dialogue, msg, state).await,
+            UserCommand::Tasks => Self::tasks(bot, msg, db).await,
+            UserCommand::Join(code) => Self::join_staff(bot, msg, code, db, staff).await
+        }
+    }
+
+    async fn help(bot: Bot, msg: Message, state: BotState, db: Db) -> HandlerResult {
+        log::info!("Bot: help");
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        // Users who are still registering have no saved units yet.
+        let units = match db.check_user(telegram_id).await {
+            true => db.get_user(telegram_id).await.units,
+            false => Units::Metric
+        };
+
+        bot.send_message(msg.chat.id, flow::help_text(&state, units)).await?;
+
+        Ok(())
+    }
+
+    async fn cancel(bot: Bot, dialogue: BotDialogue, msg: Message, state: BotState) -> HandlerResult {
+        log::info!("Bot: cancel");
+        let text = match flow::cancel_text(&state) {
+            Some(text) => text,
+            None => {
+                bot.send_message(msg.chat.id, "Сейчас нечего отменять. Отправьте /help, чтобы узнать, что делать дальше.").await?;
+
+                return Ok(());
+            }
+        };
+
+        dialogue.exit().await?;
+
+        bot.send_message(msg.chat.id, text).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use teloxide::{payloads::AnswerInlineQuerySetters, requests::Requester, types::{InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent, InputMessageContentText}};
+
+use crate::{database::Db, retry::Bot};
+
+use super::{flow, BotService, HandlerResult};
+
+impl BotService {
+    /// Offers the warehouse address in Chinese to drop into a chat with a seller, typed as `@bot` in any chat.
+    ///
+    /// Inline mode has to be turned on for the bot in @BotFather.
+    pub(super) async fn handle_inline_query(bot: Bot, q: InlineQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_inline_query");
+        let telegram_id = q.from.id.0 as i64;
+
+        if !db.check_user(telegram_id).await {
+            bot.answer_inline_query(q.id, Vec::<InlineQueryResult>::new())
+                .is_personal(true)
+                .cache_time(0)
+                .switch_pm_text("Зарегистрируйтесь, чтобы получить адрес")
+                .switch_pm_parameter("inline")
+                .await?;
+
+            return Ok(());
+        }
+
+        let user = db.get_user(telegram_id).await;
+
+        let article = |id: &str, title: &str, description: &str, text: String| InlineQueryResult::Article(
+            InlineQueryResultArticle::new(id, title, InputMessageContent::Text(InputMessageContentText::new(text)))
+                .description(description)
+        );
+
+        let results = vec![
+            article(
+                "seller",
+                "Сообщение продавцу",
+                "Адрес склада и правила отправки на китайском",
+                flow::seller_message(&user.client_code, &user.first_name, user.last_name.as_deref())
+            ),
+            article(
+                "address",
+                "Только адрес склада",
+                "Адрес с Вашим клиентским кодом",
+                flow::address_text(&user.client_code, &user.first_name, user.last_name.as_deref())
+            )
+        ];
+
+        // The address carries the client code, so the answer must not be cached for other users.
+        bot.answer_inline_query(q.id, results).is_personal(true).cache_time(0).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use reqwest::Url;
+use teloxide::{dispatching::dialogue::GetChatId, payloads::SendPhotoSetters, requests::Requester, types::{CallbackQuery, ChatId, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, Message, MessageId}};
+
+use crate::{catalog, config::Config, database::Db, retry::Bot, sender::SendQueue};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn handle_parcels_btn(bot: Bot, dialogue: BotDialogue, tg_id: i64, chat_id: ChatId, msg_id: MessageId, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_parcels_btn");
+        let parcels = db.get_parcels(tg_id).await;
+
+        rendered.edit(&bot, chat_id, msg_id, flow::parcels_text(&parcels), Some(flow::parcels_markup(&parcels))).await?;
+
+        dialogue.update(BotState::Parcels { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_parcels(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_parcels");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::Parcels { msg_id } => msg_id,
+            _ => MessageId(0)
+        };
+
+        let telegram_id = q.from.id.0 as i64;
+
+        // Buttons carry the parcel id, a track code may not fit the 64 bytes of callback data.
+        let parcel = match flow::parcel_callback(q.data.as_deref()) {
+            Some((action, parcel_id)) => db.get_parcels(telegram_id).await.into_iter()
+                .find(|parcel| parcel.id == parcel_id)
+                .map(|parcel| (action, parcel.track_code)),
+            None => None
+        };
+
+        match parcel {
+            Some(("label", track_code)) => {
+                return Self::ask_parcel_label(bot, dialogue, q.chat_id().unwrap(), msg_id, track_code, &rendered).await;
+            },
+            Some(("cancel", track_code)) => {
+                rendered.edit(&bot, q.chat_id().unwrap(), msg_id, flow::refund_reason_text(&track_code), Some(flow::refund_reason_markup())).await?;
+
+                dialogue.update(BotState::RefundReason { msg_id, track_code }).await?;
+
+                return Ok(());
+            },
+            _ => {}
+        }
+
+        Self::render_home(&bot, &dialogue, &db, &rendered, telegram_id, q.chat_id().unwrap(), Some(msg_id)).await
+    }
+
+    /// Cancels the order with the picked reason: opens a refund ticket and lets operators know.
+    pub(super) async fn handle_refund_reason(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, config: Config, queue: SendQueue, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_refund_reason");
+        let (msg_id, track_code) = match dialogue.get().await?.unwrap() {
+            BotState::RefundReason { msg_id, track_code } => (msg_id, track_code),
+            _ => (MessageId(0), String::new())
+        };
+
+        let chat_id = q.chat_id().unwrap();
+        let telegram_id = q.from.id.0 as i64;
+
+        let reason = match flow::refund_reason(q.data.as_deref()) {
+            Some(reason) => reason,
+            None => return Self::handle_parcels_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+        };
+
+        let text = format!("Отмена заказа {}, возврат средств. Причина: {}", track_code, reason);
+
+        let message = match db.request_refund(telegram_id, &track_code, reason, &text).await {
+            Some(ticket_id) => {
+                Self::notify_operators(&db, &config, &queue, telegram_id, ticket_id, &text).await;
+
+                flow::refund_requested_text(ticket_id, &track_code)
+            },
+            None => format!("Возврат по заказу {} уже запрошен", track_code)
+        };
+
+        let markup = InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("Вернуться в личный кабинет", "back_btn")
+        ]]);
+
+        rendered.edit(&bot, chat_id, msg_id, message, Some(markup)).await?;
+
+        dialogue.update(BotState::Profile { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_track_result(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_track_result");
+        let (msg_id, track_code) = match dialogue.get().await?.unwrap() {
+            BotState::TrackResult { msg_id, track_code } => (msg_id, track_code),
+            _ => (MessageId(0), String::new())
+        };
+
+        if q.data.as_deref() == Some("save_parcel_btn") {
+            return Self::ask_parcel_label(bot, dialogue, q.chat_id().unwrap(), msg_id, track_code, &rendered).await;
+        }
+
+        Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), Some(msg_id)).await
+    }
+
+    async fn ask_parcel_label(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, track_code: String, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: ask_parcel_label");
+        rendered.edit(&bot, chat_id, msg_id, flow::parcel_label_prompt(), None).await?;
+
+        dialogue.update(BotState::ParcelLabel { track_code }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_parcel_label(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_parcel_label");
+        let track_code = match dialogue.get().await?.unwrap() {
+            BotState::ParcelLabel { track_code } => track_code,
+            _ => String::new()
+        };
+
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        if let Some(url) = msg.text().and_then(catalog::marketplace_url) {
+            let item = catalog::fetch(&url).await;
+
+            db.save_parcel(telegram_id, &track_code, flow::item_label(&item)).await;
+            db.save_parcel_item(telegram_id, &track_code, &item).await;
+
+            if let Some(image) = item.image.as_ref().and_then(|image| Url::parse(image).ok()) {
+                // Telegram downloads the picture itself, a marketplace CDN may refuse it.
+                if let Err(err) = bot.send_photo(msg.chat.id, InputFile::url(image)).caption(flow::item_caption(&item)).await {
+                    log::warn!("Bot: could not send the picture of {}: {}", item.url, err);
+                }
+            }
+
+            return Self::send_reply(bot, dialogue, msg.chat.id, flow::parcel_saved(&track_code)).await;
+        }
+
+        let label = match flow::parcel_label(track_code.clone(), msg.text()) {
+            Ok(label) => label,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
+
+        db.save_parcel(telegram_id, &track_code, label).await;
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::parcel_saved(&track_code)).await
+    }
+}
@@ -0,0 +1,119 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{AnswerCallbackQuerySetters, AnswerPreCheckoutQuerySetters}, requests::Requester, types::{CallbackQuery, LabeledPrice, Message, PreCheckoutQuery}};
+
+use crate::{config::Config, database::Db, maintenance::Maintenance, models::{Invoice, Notice}, money::Money, payments, retry::Bot};
+
+use super::{flow, BotService, HandlerResult};
+
+impl BotService {
+    /// «Оплатить» under an invoice sends a Telegram invoice for what is left to pay.
+    pub(super) async fn handle_pay_btn(bot: Bot, q: CallbackQuery, db: Db, config: Config) -> HandlerResult {
+        log::info!("Bot: handle_pay_btn");
+        let parcel_id = q.data.as_deref().and_then(|data| data.strip_prefix("pay:")).and_then(|id| id.parse().ok());
+
+        // Callback data comes from the client, so the invoice must be theirs.
+        let invoice = match parcel_id {
+            Some(parcel_id) => db.get_invoice(parcel_id).await.filter(|invoice| invoice.telegram_id == q.from.id.0 as i64),
+            None => None
+        };
+
+        let (invoice, provider_token) = match (invoice, config.payments.provider_token) {
+            (Some(invoice), Some(provider_token)) if invoice.due() > Money::default() => (invoice, provider_token),
+            (Some(_), Some(_)) => {
+                bot.answer_callback_query(q.id).text("Счет уже оплачен").show_alert(true).await?;
+
+                return Ok(());
+            },
+            _ => {
+                bot.answer_callback_query(q.id).text("Счет не найден").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        let amount = match payments::telegram_amount(invoice.due()) {
+            Some(amount) => amount,
+            None => {
+                bot.answer_callback_query(q.id).text("Счет слишком большой для оплаты картой, оплатите его в пункте выдачи").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        bot.send_invoice(
+            q.chat_id().unwrap(),
+            format!("Доставка {}", invoice.track_code),
+            format!("Оплата доставки посылки {} из Китая", invoice.track_code),
+            payments::payload(invoice.parcel_id),
+            provider_token,
+            payments::CURRENCY,
+            vec![LabeledPrice::new("Доставка", amount)]
+        ).await?;
+
+        Ok(())
+    }
+
+    /// Telegram asks before charging the card, a changed or already paid invoice is declined.
+    pub(super) async fn handle_pre_checkout(bot: Bot, q: PreCheckoutQuery, db: Db, maintenance: Maintenance) -> HandlerResult {
+        log::info!("Bot: handle_pre_checkout");
+        // Nothing is charged while the bot is down for maintenance, the client pays once it is back.
+        if maintenance.message().is_some() {
+            bot.answer_pre_checkout_query(q.id, false)
+                .error_message("Бот на техническом обслуживании, попробуйте оплатить позже.")
+                .await?;
+
+            return Ok(());
+        }
+
+        let invoice = match payments::parcel_id(&q.invoice_payload) {
+            Some(parcel_id) => db.get_invoice(parcel_id).await,
+            None => None
+        };
+
+        let valid = invoice.is_some_and(|invoice| {
+            invoice.telegram_id == q.from.id.0 as i64 && invoice.due().cents() == i64::from(q.total_amount)
+        });
+
+        match valid {
+            true => bot.answer_pre_checkout_query(q.id, true).await?,
+            false => bot.answer_pre_checkout_query(q.id, false)
+                .error_message("Счет уже оплачен или изменился. Откройте его заново из последнего сообщения.")
+                .await?
+        };
+
+        Ok(())
+    }
+
+    /// Records a card payment, the client hears about it through the outbox like from `/paid`.
+    pub(super) async fn receive_payment(msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_payment");
+        let payment = msg.successful_payment().expect("ERROR: Message has no payment");
+
+        let parcel_id = match payments::parcel_id(&payment.invoice_payload) {
+            Some(parcel_id) => parcel_id,
+            None => {
+                log::error!("ERROR: Payment {} has an unknown payload {}", payment.telegram_payment_charge_id, payment.invoice_payload);
+
+                return Ok(());
+            }
+        };
+
+        let notice = |invoice: &Invoice| Notice {
+            telegram_id: invoice.telegram_id,
+            text: flow::payment_text(invoice),
+            markup: None,
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        let amount = Money::from_cents(i64::from(payment.total_amount));
+
+        if db.record_payment(parcel_id, amount, Some(&payment.telegram_payment_charge_id), None, notice).await.is_none() {
+            log::warn!("Payment {} is already recorded or its invoice is gone", payment.telegram_payment_charge_id);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{database::Db, notifier::photo_notice, retry::Bot};
+
+use super::{BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn ask_parcel_photo(bot: Bot, dialogue: BotDialogue, msg: Message, track_code: String) -> HandlerResult {
+        log::info!("Bot: ask_parcel_photo");
+        let track_code = track_code.trim().to_string();
+
+        if track_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /photo <трек-код>, затем фото посылки").await?;
+
+            return Ok(());
+        }
+
+        bot.send_message(msg.chat.id, format!("Пришлите фото посылки {}", track_code)).await?;
+
+        dialogue.update(BotState::ParcelPhoto { track_code }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_parcel_photo(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_parcel_photo");
+        let track_code = match dialogue.get().await?.unwrap() {
+            BotState::ParcelPhoto { track_code } => track_code,
+            _ => return Ok(())
+        };
+
+        // The last size is the largest one.
+        let photo_id = match msg.photo().and_then(|sizes| sizes.last()) {
+            Some(size) => size.file.id.clone(),
+            None => {
+                bot.send_message(msg.chat.id, format!("Фото не получено, привязка к {} отменена", track_code)).await?;
+
+                dialogue.exit().await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match db.set_parcel_photo(&track_code, &photo_id, photo_notice).await {
+            0 => format!("Посылка {} не найдена среди сохраненных", track_code),
+            _ => format!("Фото посылки {} сохранено, владелец увидит его в уведомлении", track_code)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        dialogue.exit().await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use teloxide::{dispatching::dialogue::GetChatId, requests::Requester, types::{CallbackQuery, ChatId, Message, MessageId}};
+
+use crate::{config::Config, database::Db, money::Money, models::{Notice, PurchaseRequest, PurchaseStatus}, retry::Bot, sender::{Priority, SendQueue}};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult, PurchaseDraft};
+
+impl BotService {
+    pub(super) async fn handle_purchase_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_purchase_btn");
+        rendered.edit(&bot, chat_id, msg_id, flow::PURCHASE_PROMPT, None).await?;
+
+        dialogue.update(BotState::PurchaseLink { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_purchase_link(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_purchase_link");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::PurchaseLink { msg_id } => Some(msg_id),
+            _ => None
+        };
+
+        Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), msg_id).await
+    }
+
+    pub(super) async fn receive_purchase_link(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_purchase_link");
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::purchase_link(msg.text())).await
+    }
+
+    pub(super) async fn receive_purchase_options(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: receive_purchase_options");
+        let (marketplace, link) = match dialogue.get().await?.unwrap() {
+            BotState::PurchaseOptions { marketplace, link } => (marketplace, link),
+            _ => return Ok(())
+        };
+
+        let options = match flow::purchase_options(msg.text()) {
+            Ok(options) => options,
+            Err(text) => {
+                bot.send_message(msg.chat.id, text).await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.send_message(msg.chat.id, flow::PURCHASE_BUDGET_PROMPT).await?;
+
+        dialogue.update(BotState::PurchaseBudget { draft: Box::new(PurchaseDraft { marketplace, link, options }) }).await?;
+
+        Ok(())
+    }
+
+    /// Files the request and pings operators, the answer comes back through the outbox.
+    pub(super) async fn receive_purchase_budget(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: receive_purchase_budget");
+        let draft = match dialogue.get().await?.unwrap() {
+            BotState::PurchaseBudget { draft } => draft,
+            _ => return Ok(())
+        };
+
+        let budget = match flow::purchase_budget(msg.text()) {
+            Some(budget) => budget,
+            None => {
+                bot.send_message(msg.chat.id, "Отправьте сумму в долларах числом, например 25 или 12,5.").await?;
+
+                return Ok(());
+            }
+        };
+
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let id = db.create_purchase_request(telegram_id, &draft.marketplace, &draft.link, draft.options.as_deref(), budget).await;
+
+        match (config.support.operator_chat(), db.get_purchase_request(id).await) {
+            (Some(operator_chat), Some(request)) => queue.push(Priority::Interactive, operator_chat, flow::purchase_operator_text(&request)),
+            _ => log::warn!("SUPPORT_CHAT_ID is not set, purchase request #{} waits in /purchases", id)
+        }
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::purchase_received(id)).await
+    }
+
+    pub(super) async fn purchases(bot: Bot, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: purchases");
+        let requests = db.get_open_purchase_requests().await;
+
+        bot.send_message(msg.chat.id, flow::purchase_queue_text(&requests)).await?;
+
+        Ok(())
+    }
+
+    /// `/purchase <номер> <quoted|purchased|declined> [сумма] [комментарий]`, a quote needs the amount.
+    pub(super) async fn purchase(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: purchase");
+        let usage = "Использование: /purchase <номер> <quoted|purchased|declined> [сумма, $] [комментарий]\nСумма нужна для quoted.";
+
+        let mut words = args.split_whitespace();
+
+        let id = words.next().and_then(|id| id.trim_start_matches('#').parse::<i32>().ok());
+        let status = words.next().and_then(|status| PurchaseStatus::try_from(status.to_lowercase()).ok());
+
+        let (id, status) = match (id, status) {
+            (Some(id), Some(status)) if status != PurchaseStatus::New => (id, status),
+            _ => {
+                bot.send_message(msg.chat.id, usage).await?;
+
+                return Ok(());
+            }
+        };
+
+        let mut rest: Vec<&str> = words.collect();
+
+        let quote = match rest.first().and_then(|amount| Money::parse(amount)) {
+            Some(amount) => {
+                rest.remove(0);
+
+                Some(amount)
+            },
+            None => None
+        };
+
+        if status == PurchaseStatus::Quoted && quote.is_none() {
+            bot.send_message(msg.chat.id, usage).await?;
+
+            return Ok(());
+        }
+
+        let comment = rest.join(" ");
+        let comment = Some(comment.as_str()).filter(|comment| !comment.is_empty());
+
+        let notice = |request: &PurchaseRequest| Notice {
+            telegram_id: request.telegram_id,
+            text: flow::purchase_text(request),
+            markup: None,
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        let message = match db.update_purchase_request(id, status, quote, comment, notice).await {
+            Some(request) => format!("Заявка №{} клиента {}: {}", request.id, request.client_code, request.status.title()),
+            None => format!("Открытая заявка на выкуп №{} не найдена", id)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::AnswerCallbackQuerySetters, requests::Requester, types::{CallbackQuery, Message}};
+
+use crate::{database::Db, retry::Bot};
+
+use super::{flow, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    /// «Пересчитать» prices the same box with the current tariffs, «Сохранить» asks which order the quote is for,
+    /// «Запомнить размеры» saves the box for the next calculation.
+    pub(super) async fn handle_quote_btn(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_quote_btn");
+        let data = q.data.clone().unwrap_or_default();
+        let telegram_id = q.from.id.0 as i64;
+
+        let (action, id) = data.split_once(':').unwrap_or_default();
+
+        // Callback data comes from the client, so the quote must be theirs.
+        let quote = match id.parse().ok() {
+            Some(id) => db.get_quote(id).await.filter(|quote| quote.telegram_id == telegram_id),
+            None => None
+        };
+
+        let quote = match quote {
+            Some(quote) => quote,
+            None => {
+                bot.answer_callback_query(q.id).text("Расчет не найден").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        if action == "save_box" {
+            let text = match db.save_box_preset(telegram_id, quote.width, quote.length, quote.height, flow::MAX_BOX_PRESETS).await {
+                true => "Размеры сохранены, в следующий раз выберите коробку кнопкой",
+                false => "Эта коробка уже сохранена"
+            };
+
+            bot.answer_callback_query(q.id).text(text).await?;
+
+            return Ok(());
+        }
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        let chat_id = q.chat_id().unwrap();
+
+        if action == "requote" {
+            let quote = Self::price_quote(&db, telegram_id, quote.width, quote.length, quote.height, quote.weight_kg).await;
+
+            return Self::send_reply(bot, dialogue, chat_id, flow::quote_reply(&quote)).await;
+        }
+
+        bot.send_message(chat_id, flow::quote_parcel_prompt(quote.id)).await?;
+
+        dialogue.update(BotState::QuoteParcel { quote_id: quote.id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_quote_parcel(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_quote_parcel");
+        let quote_id = match dialogue.get().await?.unwrap() {
+            BotState::QuoteParcel { quote_id } => quote_id,
+            _ => 0
+        };
+
+        let track_code = match flow::quote_parcel(quote_id, msg.text()) {
+            Ok(track_code) => track_code,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
+
+        db.attach_quote(msg.from().expect("ERROR: user is unknown").id.0 as i64, &track_code, quote_id).await;
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::quote_attached(quote_id, &track_code)).await
+    }
+
+    /// A saved box under the width prompt, the calculator goes straight to the weight.
+    pub(super) async fn handle_box_preset(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_box_preset");
+        let telegram_id = q.from.id.0 as i64;
+
+        let preset = match q.data.as_deref().and_then(|data| data.strip_prefix("box:")).and_then(|id| id.parse().ok()) {
+            Some(id) => db.get_box_preset(telegram_id, id).await,
+            None => None
+        };
+
+        let preset = match preset {
+            Some(preset) => preset,
+            None => {
+                bot.answer_callback_query(q.id).text("Коробка не найдена, введите размеры").show_alert(true).await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        let units = db.get_user(telegram_id).await.units;
+
+        Self::send_reply(bot, dialogue, q.chat_id().unwrap(), flow::box_preset_picked(units, &preset)).await
+    }
+}
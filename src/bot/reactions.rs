@@ -0,0 +1,72 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{AnswerCallbackQuerySetters, EditMessageTextSetters, SendMessageSetters}, requests::Requester, types::CallbackQuery};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{database::Db, models::Subject, reactions::Reaction, retry::Bot};
+
+use super::{flow::{self, ReactionAction}, BotService, HandlerResult};
+
+impl BotService {
+    /// Reactions come past the dispatcher, see [`crate::reactions::split`], and are handled one by one.
+    pub(super) async fn receive_reactions(bot: Bot, db: Db, mut reactions: UnboundedReceiver<Reaction>) {
+        while let Some(reaction) = reactions.recv().await {
+            if let Err(err) = Self::handle_reaction(&bot, &db, reaction).await {
+                log::error!("ERROR: Could not handle a reaction: {}", err);
+            }
+        }
+    }
+
+    /// 👍 on an arrival notice acknowledges it, ❤️ on a delivered notice asks to rate the delivery.
+    async fn handle_reaction(bot: &Bot, db: &Db, reaction: Reaction) -> HandlerResult {
+        log::info!("Bot: handle_reaction");
+        let actions: Vec<ReactionAction> = reaction.emoji.iter().filter_map(|emoji| flow::reaction_action(emoji)).collect();
+
+        if actions.is_empty() {
+            return Ok(());
+        }
+
+        for subject in db.get_reacted_subjects(reaction.telegram_id, reaction.message_id.0).await {
+            match subject {
+                Subject::Arrival(track_code) if actions.contains(&ReactionAction::Acknowledge) => {
+                    db.acknowledge_arrival(reaction.telegram_id, &track_code).await;
+                },
+                Subject::Receipt(track_code) if actions.contains(&ReactionAction::Rate) => {
+                    let (text, markup) = flow::rating_prompt(&track_code);
+
+                    bot.send_message(reaction.chat_id, text).reply_markup(markup).await?;
+                },
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A star button under the rating prompt, it works in any dialogue state.
+    pub(super) async fn handle_rating_btn(bot: Bot, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_rating_btn");
+        let (track_code, stars) = match flow::rating_callback(q.data.as_deref()) {
+            Some(rating) => rating,
+            None => {
+                bot.answer_callback_query(q.id).await?;
+
+                return Ok(());
+            }
+        };
+
+        if !db.save_rating(q.from.id.0 as i64, &track_code, stars).await {
+            bot.answer_callback_query(q.id).text("Оценить можно только свою посылку").show_alert(true).await?;
+
+            return Ok(());
+        }
+
+        bot.answer_callback_query(q.id.clone()).text("Спасибо за оценку!").await?;
+
+        if let (Some(chat_id), Some(message)) = (q.chat_id(), &q.message) {
+            let text = format!("Посылка {}: ваша оценка {}", track_code, "⭐".repeat(stars as usize));
+
+            bot.edit_message_text(chat_id, message.id, text).reply_markup(Default::default()).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}, sync::Arc, time::{Duration, Instant}};
+
+use dashmap::DashMap;
+use crate::{config, retry::Bot};
+
+use teloxide::{payloads::EditMessageTextSetters, requests::Requester, types::{ChatId, InlineKeyboardMarkup, MessageId}, ApiError, RequestError};
+
+// A chat idle this long is forgotten, its next edit simply goes to Telegram.
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The menu message of every chat with a fingerprint of the text and buttons it shows.
+///
+/// Users pressing the same button again would make Telegram answer "message is not modified",
+/// such edits are skipped. Every edit of a menu message goes through here, otherwise the
+/// fingerprint no longer matches what the chat shows. Chats idle for longer than [`TTL`] are
+/// dropped when a new chat comes in.
+#[derive(Clone, Default)]
+pub(super) struct Rendered(Arc<DashMap<ChatId, (MessageId, u64, Instant)>>);
+
+impl Rendered {
+    /// Edits the message unless it already shows this content, returns the message id.
+    pub(super) async fn edit(&self, bot: &Bot, chat_id: ChatId, msg_id: MessageId, text: impl Into<String>, markup: Option<InlineKeyboardMarkup>) -> Result<MessageId, RequestError> {
+        let text = config::banner(text);
+        let fingerprint = fingerprint(&text, markup.as_ref());
+
+        if !self.changes(chat_id, msg_id, fingerprint) {
+            return Ok(msg_id);
+        }
+
+        let mut request = bot.edit_message_text(chat_id, msg_id, text);
+
+        if let Some(markup) = markup {
+            request = request.reply_markup(markup);
+        }
+
+        match request.await {
+            Ok(msg) => {
+                self.remember(chat_id, msg.id, fingerprint);
+
+                Ok(msg.id)
+            },
+            // Nothing is remembered after a restart, Telegram still knows the message did not change.
+            Err(RequestError::Api(ApiError::MessageNotModified)) => {
+                self.remember(chat_id, msg_id, fingerprint);
+
+                Ok(msg_id)
+            },
+            Err(err) => {
+                self.0.remove(&chat_id);
+
+                Err(err)
+            }
+        }
+    }
+
+    fn changes(&self, chat_id: ChatId, msg_id: MessageId, fingerprint: u64) -> bool {
+        !self.0.get(&chat_id).is_some_and(|shown| (shown.0, shown.1) == (msg_id, fingerprint))
+    }
+
+    fn remember(&self, chat_id: ChatId, msg_id: MessageId, fingerprint: u64) {
+        if self.0.insert(chat_id, (msg_id, fingerprint, Instant::now())).is_none() {
+            self.0.retain(|_, (_, _, shown_at)| shown_at.elapsed() < TTL);
+        }
+    }
+}
+
+fn fingerprint(text: &str, markup: Option<&InlineKeyboardMarkup>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    text.hash(&mut hasher);
+    markup.map(|markup| serde_json::to_string(markup).unwrap_or_default()).hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::InlineKeyboardButton;
+
+    use super::*;
+
+    #[test]
+    fn only_the_shown_content_is_skipped() {
+        let rendered = Rendered::default();
+        let markup = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback("Назад", "back_btn")]]);
+        let shown = fingerprint("Профиль", Some(&markup));
+
+        rendered.remember(ChatId(1), MessageId(10), shown);
+
+        assert!(!rendered.changes(ChatId(1), MessageId(10), shown));
+        assert!(rendered.changes(ChatId(1), MessageId(10), fingerprint("Профиль", None)));
+        assert!(rendered.changes(ChatId(1), MessageId(11), shown));
+        assert!(rendered.changes(ChatId(2), MessageId(10), shown));
+    }
+
+    #[test]
+    fn idle_chats_are_forgotten() {
+        let rendered = Rendered::default();
+
+        rendered.0.insert(ChatId(1), (MessageId(10), 1, Instant::now() - TTL));
+        rendered.remember(ChatId(2), MessageId(20), 2);
+
+        assert!(!rendered.0.contains_key(&ChatId(1)));
+        assert!(rendered.0.contains_key(&ChatId(2)));
+    }
+}
@@ -0,0 +1,72 @@
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{database::Db, retry::Bot, sender::{Priority, SendQueue}};
+
+use super::{flow, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn start_scan(bot: Bot, dialogue: BotDialogue, msg: Message, batch_code: String, db: Db) -> HandlerResult {
+        log::info!("Bot: start_scan");
+        let batch_code = batch_code.trim().to_uppercase();
+
+        if batch_code.is_empty() {
+            bot.send_message(msg.chat.id, "Использование: /scan <партия>").await?;
+
+            return Ok(());
+        }
+
+        db.create_batch(&batch_code).await;
+
+        bot.send_message(msg.chat.id, format!(
+            "Режим сканирования партии {}. Сканируйте трек-коды, для завершения отправьте /done",
+            batch_code
+        )).await?;
+
+        dialogue.update(BotState::Scan { batch_code, matched: 0, unmatched: Vec::new() }).await?;
+
+        Ok(())
+    }
+
+    /// Scanners type codes as fast consecutive messages, sometimes several per message.
+    pub(super) async fn receive_scan(dialogue: BotDialogue, msg: Message, db: Db, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: receive_scan");
+        let (batch_code, mut matched, mut unmatched) = match dialogue.get().await?.unwrap() {
+            BotState::Scan { batch_code, matched, unmatched } => (batch_code, matched, unmatched),
+            _ => return Ok(())
+        };
+
+        for track_code in msg.text().unwrap_or_default().split_whitespace() {
+            let client_codes = db.scan_parcel(track_code, &batch_code).await;
+
+            if client_codes.is_empty() {
+                unmatched.push(track_code.to_string());
+            } else {
+                matched += 1;
+            }
+
+            // Queued so that a burst of scans stays within Telegram limits.
+            queue.push(Priority::Interactive, msg.chat.id, flow::scanned_line(track_code, &client_codes));
+        }
+
+        dialogue.update(BotState::Scan { batch_code, matched, unmatched }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn finish_scan(bot: Bot, dialogue: BotDialogue, msg: Message, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: finish_scan");
+        match dialogue.get().await?.unwrap_or_default() {
+            BotState::Scan { batch_code, matched, unmatched } => {
+                // Through the queue as well, so that it comes after the last scanned code.
+                queue.push(Priority::Interactive, msg.chat.id, flow::scan_summary(&batch_code, matched, &unmatched));
+
+                dialogue.exit().await?;
+            },
+            _ => {
+                bot.send_message(msg.chat.id, "Режим сканирования не запущен").await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{AnswerCallbackQuerySetters, SendDocumentSetters}, requests::Requester, types::{CallbackQuery, ChatId, InputFile, MessageId}};
+
+use crate::{database::Db, export::UserData, models::Units, retry::Bot, summary};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn handle_settings_btn(bot: Bot, dialogue: BotDialogue, tg_id: i64, chat_id: ChatId, msg_id: MessageId, db: Db, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_settings_btn");
+        let user = db.get_user(tg_id).await;
+
+        rendered.edit(&bot, chat_id, msg_id, flow::settings_text(&user), Some(flow::settings_markup(&user))).await?;
+
+        dialogue.update(BotState::Settings { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_settings(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_settings");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::Settings { msg_id } => msg_id,
+            _ => MessageId(0)
+        };
+
+        let telegram_id = q.from.id.0 as i64;
+        let chat_id = q.chat_id().unwrap();
+
+        match q.data.as_deref() {
+            Some("text_menu_btn") => {
+                db.set_text_menu(telegram_id, true).await;
+
+                rendered.edit(&bot, chat_id, msg_id, "Текстовое меню включено", None).await?;
+
+                let user = db.get_user(telegram_id).await;
+
+                Self::send_text_menu(bot, dialogue, chat_id, &user).await
+            },
+            Some("units_btn") => {
+                let units = match db.get_user(telegram_id).await.units {
+                    Units::Metric => Units::Imperial,
+                    Units::Imperial => Units::Metric
+                };
+
+                db.set_units(telegram_id, units).await;
+
+                Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+            },
+            Some("export_btn") => {
+                let data = UserData {
+                    user: db.get_user(telegram_id).await,
+                    parcels: db.get_parcels(telegram_id).await,
+                    shipments: db.get_shipments(telegram_id).await,
+                    quotes: db.get_quotes(telegram_id, i64::MAX).await,
+                    tickets: db.get_user_tickets(telegram_id).await
+                };
+
+                let document = InputFile::memory(data.to_json()).file_name(format!("max-express-{}.json", data.user.client_code));
+
+                bot.send_document(chat_id, document)
+                    .caption("Ваши данные: профиль, посылки, отправки, расчеты и обращения")
+                    .await?;
+
+                Ok(())
+            },
+            Some("addresses_btn") => Self::handle_addresses_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await,
+            Some("reminders_btn") => {
+                let reminders = db.get_user(telegram_id).await.reminders;
+
+                db.set_reminders(telegram_id, !reminders).await;
+
+                Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+            },
+            Some("city_btn") => {
+                let cities = db.get_cities().await;
+
+                rendered.edit(&bot, chat_id, msg_id, flow::CITY_PROMPT, Some(flow::city_markup(&cities))).await?;
+
+                Ok(())
+            },
+            Some(data) if data.starts_with("city:") => {
+                let id = data.trim_start_matches("city:").parse::<i32>().ok();
+
+                if let Some((_, city)) = db.get_cities().await.into_iter().find(|(city_id, _)| Some(*city_id) == id) {
+                    db.set_city(telegram_id, &city).await;
+                }
+
+                Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+            },
+            Some("city_back_btn") => Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await,
+            Some("summary_btn") => {
+                let monthly_summary = db.get_user(telegram_id).await.monthly_summary;
+
+                db.set_monthly_summary(telegram_id, !monthly_summary).await;
+
+                Self::handle_settings_btn(bot, dialogue, telegram_id, chat_id, msg_id, db, &rendered).await
+            },
+            _ => {
+                Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), Some(msg_id)).await
+            }
+        }
+    }
+
+    /// Opts out of the re-engagement reminders right from one of them.
+    pub(super) async fn handle_reminders_off(bot: Bot, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_reminders_off");
+        db.set_reminders(q.from.id.0 as i64, false).await;
+
+        bot.answer_callback_query(q.id).text("Напоминаний больше не будет, включить их можно в настройках").show_alert(true).await?;
+
+        if let Some(msg) = q.message {
+            bot.edit_message_reply_markup(msg.chat.id, msg.id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// The receipt list under a monthly summary, a CSV of the parcels delivered that month.
+    pub(super) async fn handle_summary_btn(bot: Bot, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_summary_btn");
+        let month = match summary::parse_callback(q.data.as_deref()) {
+            Some(month) => month,
+            None => {
+                bot.answer_callback_query(q.id).await?;
+
+                return Ok(());
+            }
+        };
+
+        let receipts = db.get_monthly_receipts(q.from.id.0 as i64, month).await;
+
+        if receipts.is_empty() {
+            bot.answer_callback_query(q.id).text("За этот месяц квитанций нет").show_alert(true).await?;
+
+            return Ok(());
+        }
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        let document = InputFile::memory(summary::render_csv(&receipts).into_bytes()).file_name(summary::file_name(month));
+
+        bot.send_document(q.from.id, document).caption("Квитанции за месяц").await?;
+
+        Ok(())
+    }
+}
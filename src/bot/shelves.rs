@@ -0,0 +1,54 @@
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{database::Db, models::PendingParcel, notifier::pickup_notice, retry::Bot};
+
+use super::{flow, BotService, HandlerResult};
+
+impl BotService {
+    /// Puts a parcel on a shelf of the pickup point and tells its owner it is ready.
+    pub(super) async fn shelve(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: shelve");
+        let mut args = args.split_whitespace();
+
+        let (track_code, shelf) = match (args.next(), flow::shelf_code(&args.collect::<String>())) {
+            (Some(track_code), Some(shelf)) => (track_code.to_string(), shelf),
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /shelve <трек-код> <полка>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match Self::put_on_shelf(&db, &track_code, &shelf).await {
+            0 => format!("Посылка {} не найдена среди ожидающих выдачи", track_code),
+            _ => format!("Посылка {} на полке {}, владелец получит уведомление", track_code, shelf)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Shelves the undelivered parcels under a track code and tells their owners where to pick them up.
+    pub(super) async fn put_on_shelf(db: &Db, track_code: &str, shelf: &str) -> u64 {
+        let pickup_points = db.get_pickup_points().await;
+        let pickup_point = |parcel: &PendingParcel| parcel.city.as_ref().and_then(|city| pickup_points.iter()
+            .find(|(name, _)| name.to_lowercase() == city.to_lowercase())
+            .map(|(_, pickup_point)| pickup_point.as_str()));
+
+        db.shelve_parcel(track_code, shelf, |parcel| pickup_notice(parcel, shelf, pickup_point(parcel))).await
+    }
+
+    /// Lists what waits on a shelf, or every shelf with its number of parcels.
+    pub(super) async fn shelf(bot: Bot, msg: Message, shelf: String, db: Db) -> HandlerResult {
+        log::info!("Bot: shelf");
+        let message = match flow::shelf_code(&shelf) {
+            Some(shelf) => flow::shelf_text(&shelf, &db.get_shelf(&shelf).await),
+            None => flow::shelves_text(&db.get_shelves().await)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{config::Config, database::Db, retry::Bot, staff::{Staff, INVITE_HOURS}};
+
+use super::{admin::AdminCommand, BotService, HandlerResult};
+
+impl BotService {
+    /// Commands that change who may run admin commands, each one goes to the audit trail.
+    pub(super) async fn handle_staff_command(bot: Bot, msg: Message, cmd: AdminCommand, db: Db, config: Config, staff: Staff) -> HandlerResult {
+        log::info!("Bot: handle_staff_command");
+        match cmd {
+            AdminCommand::Invite => Self::invite_staff(bot, msg, db, staff).await,
+            AdminCommand::Staff => Self::list_staff(bot, msg, staff).await,
+            AdminCommand::Dismiss(telegram_id) => Self::dismiss_staff(bot, msg, telegram_id, db, config, staff).await,
+            _ => Ok(())
+        }
+    }
+
+    async fn invite_staff(bot: Bot, msg: Message, db: Db, staff: Staff) -> HandlerResult {
+        log::info!("Bot: invite_staff");
+        let admin = msg.from().expect("ERROR: user is unknown");
+        let code = staff.invite(&db, admin.id.0 as i64).await;
+
+        db.record_audit(admin.id.0 as i64, &admin.full_name(), "invite staff", "issued").await;
+
+        bot.send_message(msg.chat.id, format!(
+            "Код приглашения: {}\nНовый сотрудник отправляет боту /join {}\nКод действует {} ч и подходит один раз.",
+            code, code, INVITE_HOURS
+        )).await?;
+
+        Ok(())
+    }
+
+    async fn list_staff(bot: Bot, msg: Message, staff: Staff) -> HandlerResult {
+        log::info!("Bot: list_staff");
+        let ids: Vec<String> = staff.ids().iter().map(i64::to_string).collect();
+
+        bot.send_message(msg.chat.id, format!("Сотрудники ({}):\n{}", ids.len(), ids.join("\n"))).await?;
+
+        Ok(())
+    }
+
+    /// `/dismiss <telegram id>`, only the owner may run it and cannot dismiss themselves.
+    async fn dismiss_staff(bot: Bot, msg: Message, telegram_id: String, db: Db, config: Config, staff: Staff) -> HandlerResult {
+        log::info!("Bot: dismiss_staff");
+        let owner = msg.from().expect("ERROR: user is unknown");
+
+        if !config.is_owner(owner.id.0 as i64) {
+            bot.send_message(msg.chat.id, "Сотрудников увольняет только владелец бота (OWNER_ID).").await?;
+
+            return Ok(());
+        }
+
+        let telegram_id = match telegram_id.trim().parse::<i64>() {
+            Ok(telegram_id) if telegram_id != owner.id.0 as i64 => telegram_id,
+            _ => {
+                bot.send_message(msg.chat.id, "Использование: /dismiss <telegram id сотрудника>").await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match staff.dismiss(&db, telegram_id).await {
+            true => {
+                db.record_audit(owner.id.0 as i64, &owner.full_name(), &format!("dismiss staff {}", telegram_id), "done").await;
+
+                format!("{} больше не сотрудник, команды и разрешения отозваны", telegram_id)
+            },
+            false => format!("{} не сотрудник", telegram_id)
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// `/join <код>` from a new operator.
+    pub(super) async fn join_staff(bot: Bot, msg: Message, code: String, db: Db, staff: Staff) -> HandlerResult {
+        log::info!("Bot: join_staff");
+        let user = msg.from().expect("ERROR: user is unknown");
+        let telegram_id = user.id.0 as i64;
+
+        if staff.contains(telegram_id) {
+            bot.send_message(msg.chat.id, "Вы уже сотрудник.").await?;
+
+            return Ok(());
+        }
+
+        let message = match staff.join(&db, &code, telegram_id).await {
+            Some(invited_by) => {
+                db.record_audit(telegram_id, &user.full_name(), &format!("join staff, invited by {}", invited_by), "done").await;
+
+                "Вы добавлены в сотрудники, команды администратора доступны."
+            },
+            None => "Код недействителен: он неверный, устарел или уже использован."
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use std::{convert::Infallible, future::Future, pin::Pin, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use teloxide::{dispatching::dialogue::Storage, types::ChatId};
+
+use crate::database::Db;
+
+use super::BotState;
+
+/// Bumped whenever a change to `BotState` breaks the JSON of states saved by older releases.
+const STATE_VERSION: u32 = 2;
+
+type StorageFuture<T> = Pin<Box<dyn Future<Output = Result<T, Infallible>> + Send>>;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    state: Value
+}
+
+/// Dialogue states kept in Postgres, so that a restart does not drop users out of their flows.
+pub(super) struct PgStorage {
+    db: Db
+}
+
+impl PgStorage {
+    pub(super) fn new(db: Db) -> Arc<PgStorage> {
+        Arc::new(PgStorage { db })
+    }
+}
+
+impl Storage<BotState> for PgStorage {
+    type Error = Infallible;
+
+    fn remove_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<()> {
+        Box::pin(async move {
+            self.db.remove_dialogue(chat_id.0).await;
+
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(self: Arc<Self>, chat_id: ChatId, dialogue: BotState) -> StorageFuture<()> {
+        Box::pin(async move {
+            self.db.save_dialogue(chat_id.0, &encode(&dialogue)).await;
+
+            Ok(())
+        })
+    }
+
+    /// A state that can not be read any more starts the user over instead of failing every update.
+    fn get_dialogue(self: Arc<Self>, chat_id: ChatId) -> StorageFuture<Option<BotState>> {
+        Box::pin(async move {
+            let state = self.db.get_dialogue(chat_id.0).await.map(|json| {
+                decode(&json).unwrap_or_else(|| {
+                    log::warn!("Could not read the dialogue state of {}, starting over", chat_id);
+
+                    BotState::default()
+                })
+            });
+
+            Ok(state)
+        })
+    }
+}
+
+fn encode(state: &BotState) -> String {
+    let state = serde_json::to_value(state).expect("ERROR: Could not serialize a dialogue state");
+
+    serde_json::to_string(&Envelope { version: STATE_VERSION, state }).expect("ERROR: Could not serialize a dialogue state")
+}
+
+fn decode(json: &str) -> Option<BotState> {
+    let envelope: Envelope = serde_json::from_str(json).ok()?;
+
+    serde_json::from_value(migrate(envelope.version, envelope.state)?).ok()
+}
+
+/// Upgrades the JSON of a state saved by an older release, one version at a time.
+///
+/// States of versions without an upgrade step are dropped.
+fn migrate(version: u32, state: Value) -> Option<Value> {
+    match version {
+        STATE_VERSION => Some(state),
+        1 => migrate(2, photos_to_attachments(state)),
+        _ => None
+    }
+}
+
+/// Version 2 keeps the files of a weight dispute as attachments instead of bare photo ids.
+fn photos_to_attachments(mut state: Value) -> Value {
+    if let Some(dispute) = state.get_mut("WeightDispute").and_then(Value::as_object_mut) {
+        let photos = dispute.remove("photos").unwrap_or_default();
+        let attachments: Vec<Value> = photos.as_array().into_iter().flatten()
+            .map(|file_id| json!({ "kind": "Photo", "file_id": file_id, "mime_type": "image/jpeg", "size": 0 }))
+            .collect();
+
+        dispute.insert("attachments".to_string(), Value::Array(attachments));
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::MessageId;
+
+    use crate::attachments::{Attachment, AttachmentKind};
+
+    use super::*;
+
+    #[test]
+    fn saved_state_is_restored() {
+        let state = BotState::TrackResult { msg_id: MessageId(5), track_code: "YT1".to_string() };
+
+        assert_eq!(decode(&encode(&state)), Some(state));
+    }
+
+    #[test]
+    fn dispute_photos_of_version_1_become_attachments() {
+        let saved = json!({ "version": 1, "state": { "WeightDispute": { "track_code": "YT1", "comment": "", "photos": ["p1"] } } }).to_string();
+
+        assert_eq!(decode(&saved), Some(BotState::WeightDispute {
+            track_code: "YT1".to_string(),
+            comment: String::new(),
+            attachments: vec![Attachment { kind: AttachmentKind::Photo, file_id: "p1".to_string(), mime_type: "image/jpeg".to_string(), size: 0 }]
+        }));
+        assert_eq!(decode(&json!({ "version": 1, "state": "Start" }).to_string()), Some(BotState::Start));
+    }
+
+    #[test]
+    fn unreadable_states_are_dropped() {
+        let unknown_version = json!({ "version": STATE_VERSION + 1, "state": "Start" }).to_string();
+        let removed_variant = json!({ "version": STATE_VERSION, "state": { "Checkout": { "step": 2 } } }).to_string();
+
+        assert_eq!(decode(&unknown_version), None);
+        assert_eq!(decode(&removed_variant), None);
+        assert_eq!(decode("\"Start\""), None);
+    }
+}
@@ -0,0 +1,100 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{SendDocumentSetters, SendPhotoSetters}, requests::Requester, types::{CallbackQuery, ChatId, InputFile, Message, MessageId}};
+
+use crate::{attachments::{Attachment, AttachmentKind}, config::Config, database::Db, retry::Bot, sender::{Priority, SendQueue}, support::bishkek_now};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn handle_service_btn(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, msg_id: MessageId, config: Config, rendered: &Rendered) -> HandlerResult {
+        log::info!("Bot: handle_service_btn");
+        let desk = &config.support;
+        let message = flow::service_text(desk.is_open(bishkek_now()), &desk.schedule_text());
+
+        rendered.edit(&bot, chat_id, msg_id, message, Some(flow::service_markup())).await?;
+
+        dialogue.update(BotState::Support { msg_id }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_support(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_support");
+        let msg_id = match dialogue.get().await?.unwrap() {
+            BotState::Support { msg_id } => msg_id,
+            _ => MessageId(0)
+        };
+
+        if q.data.as_deref() == Some("support_write_btn") {
+            rendered.edit(&bot, q.chat_id().unwrap(), msg_id, flow::SUPPORT_PROMPT, None).await?;
+
+            dialogue.update(BotState::SupportMessage).await?;
+
+            return Ok(());
+        }
+
+        Self::render_home(&bot, &dialogue, &db, &rendered, q.from.id.0 as i64, q.chat_id().unwrap(), Some(msg_id)).await
+    }
+
+    /// Files the message as a ticket and pings operators right away during working hours.
+    pub(super) async fn receive_support_message(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config, queue: SendQueue) -> HandlerResult {
+        log::info!("Bot: receive_support_message");
+        let (text, attachment) = match flow::support_message(msg.text().or(msg.caption()), config.attachments.accept(&msg, 0)) {
+            Ok(message) => message,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
+
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let ticket_id = db.create_ticket(telegram_id, &text, attachment.as_slice()).await;
+
+        let desk = &config.support;
+        let now = bishkek_now();
+
+        let next_opening = if desk.is_open(now) {
+            Self::notify_operators(&db, &config, &queue, telegram_id, ticket_id, &text).await;
+
+            if let Some(operator_chat) = desk.operator_chat() {
+                Self::send_attachments(&bot, operator_chat, attachment.as_slice(), &format!("Обращение #{}", ticket_id)).await?;
+            }
+
+            None
+        } else {
+            Some(desk.next_opening(now))
+        };
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::support_received(ticket_id, next_opening)).await
+    }
+
+    /// Sends stored files of a ticket by `file_id`, Telegram does not upload them again.
+    pub(super) async fn send_attachments(bot: &Bot, chat_id: ChatId, attachments: &[Attachment], caption: &str) -> HandlerResult {
+        for attachment in attachments {
+            let file = InputFile::file_id(attachment.file_id.clone());
+
+            match attachment.kind {
+                AttachmentKind::Photo => bot.send_photo(chat_id, file).caption(caption).await?,
+                AttachmentKind::Document => bot.send_document(chat_id, file).caption(caption).await?
+            };
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn notify_operators(db: &Db, config: &Config, queue: &SendQueue, telegram_id: i64, ticket_id: i32, text: &str) {
+        let operator_chat = match config.support.operator_chat() {
+            Some(operator_chat) => operator_chat,
+            None => {
+                log::warn!("SUPPORT_CHAT_ID is not set, ticket #{} waits in the queue", ticket_id);
+
+                return;
+            }
+        };
+
+        let user = db.get_user(telegram_id).await;
+        let username = user.username.map_or(String::new(), |username| format!(" (@{})", username));
+
+        queue.push(
+            Priority::Interactive,
+            operator_chat,
+            format!("🆕 Обращение #{} от {}{}:\n\n{}\n\nОтветить шаблоном: /r {}", ticket_id, user.client_code, username, text, ticket_id)
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use teloxide::{dispatching::dialogue::GetChatId, payloads::{AnswerCallbackQuerySetters, EditMessageTextSetters, SendMessageSetters}, requests::Requester, types::{CallbackQuery, ChatId, Message}};
+
+use crate::{database::Db, models::{Notice, Survey}, notifier, retry::Bot, segment::Segment};
+
+use super::{confirm::{Confirmations, Destructive}, flow, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    /// `/survey <название>` starts a survey, `/survey <номер>` shows one.
+    pub(super) async fn survey(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: survey");
+        let args = args.trim();
+
+        let message = match args.trim_start_matches('#').parse::<i32>() {
+            _ if args.is_empty() => "Использование: /survey <название> — новый опрос, /survey <номер> — показать опрос".to_string(),
+            Ok(survey_id) => match db.get_survey(survey_id).await {
+                Some(survey) => flow::survey_text(&survey),
+                None => format!("Опрос #{} не найден", survey_id)
+            },
+            Err(_) => {
+                let admin_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+                let survey_id = db.create_survey(args, admin_id).await;
+
+                flow::survey_text(&db.get_survey(survey_id).await.expect("ERROR: Could not get a new survey"))
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// `/question <номер опроса> <вопрос> | вариант | вариант`, only until the survey is sent.
+    pub(super) async fn survey_question(bot: Bot, msg: Message, args: String, db: Db) -> HandlerResult {
+        log::info!("Bot: survey_question");
+        let (survey_id, text, options) = match flow::parse_survey_question(&args) {
+            Ok(question) => question,
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match db.get_survey(survey_id).await {
+            None => format!("Опрос #{} не найден", survey_id),
+            Some(survey) if survey.sent => format!("Опрос #{} уже отправлен, вопросы в нем не меняются", survey_id),
+            Some(survey) if survey.questions.len() >= flow::MAX_SURVEY_QUESTIONS => {
+                format!("В опросе уже {} вопросов, больше добавить нельзя", flow::MAX_SURVEY_QUESTIONS)
+            },
+            Some(_) => {
+                db.add_survey_question(survey_id, &text, &options).await;
+
+                flow::survey_text(&db.get_survey(survey_id).await.expect("ERROR: Could not get a survey"))
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// `/sendsurvey <номер> [фильтры]`, shows the audience and asks to confirm like a broadcast.
+    pub(super) async fn send_survey(bot: Bot, msg: Message, args: String, db: Db, confirmations: Confirmations) -> HandlerResult {
+        log::info!("Bot: send_survey");
+        let args = args.trim();
+        let (survey_id, filters) = args.split_once(' ').unwrap_or((args, ""));
+
+        let survey = match survey_id.trim_start_matches('#').parse::<i32>() {
+            Ok(survey_id) => db.get_survey(survey_id).await,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Использование: /sendsurvey <номер опроса> [фильтры как у /broadcast]").await?;
+
+                return Ok(());
+            }
+        };
+
+        let segment = match Segment::parse(filters) {
+            Ok((segment, rest)) if rest.is_empty() => segment,
+            Ok(_) => {
+                bot.send_message(msg.chat.id, "Фильтры: after:ГГГГ-ММ-ДД, parcels:warehouse, city:<город>, inactive:<дней>").await?;
+
+                return Ok(());
+            },
+            Err(message) => {
+                bot.send_message(msg.chat.id, message).await?;
+
+                return Ok(());
+            }
+        };
+
+        let message = match survey {
+            None => "Опрос не найден".to_string(),
+            Some(survey) if survey.sent => format!("Опрос #{} уже отправлен", survey.id),
+            Some(survey) if survey.questions.is_empty() => format!("В опросе #{} нет вопросов", survey.id),
+            Some(survey) => {
+                let summary = format!(
+                    "{}\n\nПолучатели: {}\nКоличество: {}",
+                    flow::survey_text(&survey),
+                    segment.describe(),
+                    db.count_segment(&segment).await
+                );
+
+                let action = Destructive::SendSurvey { survey_id: survey.id, filters: segment.to_args() };
+
+                return Self::ask_confirmation(&bot, &msg, &db, &confirmations, summary, action).await;
+            }
+        };
+
+        bot.send_message(msg.chat.id, message).await?;
+
+        Ok(())
+    }
+
+    /// Puts the first question into the outbox of every recipient, returns what to tell the admin.
+    pub(super) async fn send_confirmed_survey(db: &Db, survey_id: i32, filters: &str) -> String {
+        let survey = match db.get_survey(survey_id).await {
+            Some(survey) => survey,
+            None => return format!("Опрос #{} не найден", survey_id)
+        };
+
+        let segment = Segment::parse(filters).map(|(segment, _)| segment)
+            .expect("ERROR: Could not parse saved survey filters");
+
+        let (text, markup) = flow::survey_question(&survey, &survey.questions[0]);
+
+        let notice = |telegram_id| Notice {
+            telegram_id,
+            text: text.clone(),
+            markup: Some(markup.clone()),
+            photo_id: None,
+            digest: None,
+            subject: None
+        };
+
+        match db.send_survey(survey_id, &db.get_segment_ids(&segment).await, notice).await {
+            Some(recipients) => format!("Опрос #{} отправлен: {}, получателей: {}", survey_id, segment.describe(), recipients),
+            None => format!("Опрос #{} уже отправлен", survey_id)
+        }
+    }
+
+    /// `/results <номер опроса>`, in several messages when there are many typed answers.
+    pub(super) async fn survey_results(bot: Bot, msg: Message, survey_id: String, db: Db) -> HandlerResult {
+        log::info!("Bot: survey_results");
+        let message = match survey_id.trim().trim_start_matches('#').parse::<i32>() {
+            Ok(survey_id) => match db.get_survey(survey_id).await {
+                Some(survey) => flow::survey_results_text(&survey, &db.get_survey_answers(survey_id).await),
+                None => format!("Опрос #{} не найден", survey_id)
+            },
+            Err(_) => "Использование: /results <номер опроса>".to_string()
+        };
+
+        for message in notifier::split_message(&message) {
+            bot.send_message(msg.chat.id, message).await?;
+        }
+
+        Ok(())
+    }
+
+    /// A survey button, it arrives through the outbox and so works in any state.
+    pub(super) async fn handle_survey_btn(bot: Bot, dialogue: BotDialogue, q: CallbackQuery, db: Db) -> HandlerResult {
+        log::info!("Bot: handle_survey_btn");
+        let chat_id = q.chat_id().unwrap();
+        let telegram_id = q.from.id.0 as i64;
+
+        let found = match flow::survey_callback(q.data.as_deref()) {
+            Some((survey_id, position, option)) => db.get_survey(survey_id).await.and_then(|survey| {
+                let question = survey.questions.iter().find(|question| question.position == position).cloned()?;
+
+                Some((survey, question, option))
+            }),
+            None => None
+        };
+
+        let (survey, question, option) = match found {
+            Some(found) if db.is_survey_recipient(found.0.id, telegram_id).await => found,
+            _ => return Self::survey_gone(bot, q).await
+        };
+
+        let option = match option {
+            Some(option) => option,
+            None => {
+                bot.answer_callback_query(q.id).await?;
+
+                return Self::send_reply(bot, dialogue, chat_id, flow::survey_answer_prompt(survey.id, &question)).await;
+            }
+        };
+
+        let picked = match question.options.get(option as usize) {
+            Some(picked) => picked.clone(),
+            None => return Self::survey_gone(bot, q).await
+        };
+
+        if !db.save_survey_answer(survey.id, question.position, telegram_id, Some(option), None).await {
+            bot.answer_callback_query(q.id).text("Вы уже ответили на этот вопрос").await?;
+
+            return Ok(());
+        }
+
+        bot.answer_callback_query(q.id.clone()).await?;
+
+        // The buttons go, the answer stays next to the question.
+        if let Some(message) = &q.message {
+            let text = format!("{}\n\nВаш ответ: {}", message.text().unwrap_or_default(), picked);
+
+            bot.edit_message_text(chat_id, message.id, text).reply_markup(Default::default()).await?;
+        }
+
+        // The client may be in the middle of something else, so the dialogue stays where it is.
+        if !Self::send_next_survey_question(&bot, chat_id, &survey, question.position).await? {
+            bot.send_message(chat_id, flow::SURVEY_FINISHED).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_survey_answer(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_survey_answer");
+        let (survey_id, position) = match dialogue.get().await?.unwrap() {
+            BotState::SurveyAnswer { survey_id, position } => (survey_id, position),
+            _ => (0, 0)
+        };
+
+        let text = match flow::survey_answer(survey_id, position, msg.text()) {
+            Ok(text) => text,
+            Err(reply) => return Self::send_reply(bot, dialogue, msg.chat.id, reply).await
+        };
+
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+
+        let survey = match db.get_survey(survey_id).await {
+            Some(survey) => survey,
+            None => return Self::send_reply(bot, dialogue, msg.chat.id, flow::survey_finished()).await
+        };
+
+        if !db.save_survey_answer(survey_id, position, telegram_id, None, Some(&text)).await {
+            bot.send_message(msg.chat.id, "Вы уже ответили на этот вопрос").await?;
+        }
+
+        if Self::send_next_survey_question(&bot, msg.chat.id, &survey, position).await? {
+            // The answer is typed, the next question comes with buttons in any state.
+            dialogue.exit().await?;
+
+            return Ok(());
+        }
+
+        Self::send_reply(bot, dialogue, msg.chat.id, flow::survey_finished()).await
+    }
+
+    /// Sends the question after `position`, false when that was the last one.
+    async fn send_next_survey_question(bot: &Bot, chat_id: ChatId, survey: &Survey, position: i16) -> Result<bool, teloxide::RequestError> {
+        let next = match survey.questions.iter().find(|question| question.position == position + 1) {
+            Some(next) => next,
+            None => return Ok(false)
+        };
+
+        let (text, markup) = flow::survey_question(survey, next);
+
+        bot.send_message(chat_id, text).reply_markup(markup).await?;
+
+        Ok(true)
+    }
+
+    async fn survey_gone(bot: Bot, q: CallbackQuery) -> HandlerResult {
+        bot.answer_callback_query(q.id).text("Опрос не найден").show_alert(true).await?;
+
+        Ok(())
+    }
+}
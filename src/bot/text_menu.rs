@@ -0,0 +1,95 @@
+use teloxide::{requests::Requester, types::{ChatId, Message}};
+
+use crate::{config::Config, database::Db, models::{EventKind, User}, onboarding::OnboardingStep, retry::Bot, support::bishkek_now};
+
+use super::{flow, render::Rendered, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    pub(super) async fn send_text_menu(bot: Bot, dialogue: BotDialogue, chat_id: ChatId, user: &User) -> HandlerResult {
+        log::info!("Bot: send_text_menu");
+        bot.send_message(chat_id, flow::text_menu_text(user)).await?;
+
+        dialogue.update(BotState::TextMenu).await?;
+
+        Ok(())
+    }
+
+    /// Brings a text menu user back to the menu after a flow has finished.
+    pub(super) async fn resume_text_menu(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        let user = db.get_user(msg.from().expect("ERROR: user is unknown").id.0 as i64).await;
+
+        if user.text_menu {
+            return Self::send_text_menu(bot, dialogue, msg.chat.id, &user).await;
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_text_menu(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db, config: Config, rendered: Rendered) -> HandlerResult {
+        log::info!("Bot: handle_text_menu");
+        let chat_id = msg.chat.id;
+        let telegram_id = msg.from().expect("ERROR: user is unknown").id.0 as i64;
+        let user = db.get_user(telegram_id).await;
+
+        let page = match flow::text_menu_choice(msg.text()) {
+            Some("locate_btn") => {
+                let msg_id = bot.send_message(chat_id, "Введите трек-код товара").await?.id;
+
+                dialogue.update(BotState::ProductStatus { msg_id }).await?;
+
+                return Ok(());
+            },
+            Some("seller_check_btn") => {
+                let msg_id = bot.send_message(chat_id, flow::SELLER_CHECK_PROMPT).await?.id;
+
+                dialogue.update(BotState::SellerCheck { msg_id }).await?;
+
+                return Ok(());
+            },
+            Some("purchase_btn") => {
+                let msg_id = bot.send_message(chat_id, flow::PURCHASE_PROMPT).await?.id;
+
+                dialogue.update(BotState::PurchaseLink { msg_id }).await?;
+
+                return Ok(());
+            },
+            Some("price_btn") => {
+                Self::send_box_diagram(&bot, &db, chat_id).await;
+
+                let reply = flow::price_prompt(user.units, &db.get_box_presets(telegram_id).await);
+
+                return Self::send_reply(bot, dialogue, chat_id, reply).await;
+            },
+            Some("buttons_btn") => {
+                db.set_text_menu(telegram_id, false).await;
+
+                return Self::render_home(&bot, &dialogue, &db, &rendered, telegram_id, chat_id, None).await;
+            },
+            Some("parcels_btn") => flow::parcels_text(&db.get_parcels(telegram_id).await),
+            Some("code_btn") => user.client_code.clone(),
+            Some("address_btn") => {
+                db.record_event(telegram_id, EventKind::Onboarding(OnboardingStep::Address)).await;
+
+                flow::address_text(&user.client_code, &user.first_name, user.last_name.as_deref())
+            },
+            Some("batch_btn") => Self::batch_progress(&db, telegram_id).await,
+            Some("tariffs_btn") => flow::tariffs_text(&db.get_user_tariffs(telegram_id).await),
+            Some("quotes_btn") => flow::quotes_text(&db.get_quotes(telegram_id, flow::QUOTES_LIMIT).await),
+            Some("service_btn") => flow::service_text(config.support.is_open(bishkek_now()), &config.support.schedule_text()),
+            // Marketplaces listed at once, since the text menu has no tutorial picker.
+            Some("tutorial_btn") => {
+                db.record_event(telegram_id, EventKind::Onboarding(OnboardingStep::Rules)).await;
+
+                db.get_tutorials().await.iter()
+                    .map(flow::tutorial_text)
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            },
+            _ => return Self::send_text_menu(bot, dialogue, chat_id, &user).await
+        };
+
+        bot.send_message(chat_id, flow::with_text_menu_hint(&page)).await?;
+
+        Ok(())
+    }
+}
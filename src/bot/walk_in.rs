@@ -0,0 +1,104 @@
+use teloxide::{requests::Requester, types::Message};
+
+use crate::{config::Config, database::Db, models::{Units, User}, phone_policy, retry::Bot};
+
+use super::{flow, BotDialogue, BotService, BotState, HandlerResult};
+
+impl BotService {
+    /// Registers a client who came to the counter without Telegram, one question per message.
+    pub(super) async fn start_walk_in(bot: Bot, dialogue: BotDialogue, msg: Message) -> HandlerResult {
+        log::info!("Bot: start_walk_in");
+        bot.send_message(msg.chat.id, "Регистрация клиента без Telegram.\nВведите имя клиента.").await?;
+
+        dialogue.update(BotState::WalkInFirstName).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_walk_in_first_name(bot: Bot, dialogue: BotDialogue, msg: Message, config: Config) -> HandlerResult {
+        log::info!("Bot: receive_walk_in_first_name");
+        let first_name = match msg.text().map(str::trim) {
+            Some(first_name) if !first_name.is_empty() => first_name.to_string(),
+            _ => {
+                bot.send_message(msg.chat.id, "Введите имя клиента текстом.").await?;
+
+                return Ok(());
+            }
+        };
+
+        let prompt = match config.require_last_name {
+            true => "Введите фамилию клиента.",
+            false => "Введите фамилию клиента или отправьте «-», чтобы пропустить."
+        };
+
+        bot.send_message(msg.chat.id, prompt).await?;
+
+        dialogue.update(BotState::WalkInLastName { first_name }).await?;
+
+        Ok(())
+    }
+
+    pub(super) async fn receive_walk_in_last_name(bot: Bot, dialogue: BotDialogue, msg: Message, config: Config) -> HandlerResult {
+        log::info!("Bot: receive_walk_in_last_name");
+        let first_name = match dialogue.get().await?.unwrap() {
+            BotState::WalkInLastName { first_name } => first_name,
+            _ => return Ok(())
+        };
+
+        let last_name = match msg.text().map(str::trim) {
+            Some(text) if flow::skips_last_name(text) && !config.require_last_name => None,
+            Some(text) if !text.is_empty() && !flow::skips_last_name(text) => Some(text.to_string()),
+            _ => {
+                bot.send_message(msg.chat.id, "Введите фамилию клиента текстом.").await?;
+
+                return Ok(());
+            }
+        };
+
+        bot.send_message(msg.chat.id, "Введите номер телефона клиента.\nПример: 996XXXXXXXXX").await?;
+
+        dialogue.update(BotState::WalkInPhoneNumber { first_name, last_name }).await?;
+
+        Ok(())
+    }
+
+    /// The operator sees the client in person, so the phone policy and approvals do not apply.
+    pub(super) async fn receive_walk_in_phone_number(bot: Bot, dialogue: BotDialogue, msg: Message, db: Db) -> HandlerResult {
+        log::info!("Bot: receive_walk_in_phone_number");
+        let (first_name, last_name) = match dialogue.get().await?.unwrap() {
+            BotState::WalkInPhoneNumber { first_name, last_name } => (first_name, last_name),
+            _ => return Ok(())
+        };
+
+        let phone_number = match msg.text().and_then(phone_policy::normalize) {
+            Some(phone_number) => phone_number,
+            None => {
+                bot.send_message(msg.chat.id, "Неверный формат.\nВведите номер телефона еще раз.\nПример: 996XXXXXXXXX").await?;
+
+                return Ok(());
+            }
+        };
+
+        let client_code = db.create_user(User {
+            id: 0,
+            client_code: String::new(),
+            first_name: first_name.clone(),
+            last_name: last_name.clone(),
+            phone_number,
+            telegram_id: None,
+            username: None,
+            display_name: None,
+            text_menu: false,
+            reminders: true,
+            monthly_summary: false,
+            units: Units::Metric,
+            city: None
+        }).await;
+
+        bot.send_message(msg.chat.id, flow::walk_in_text(&client_code, &first_name, last_name.as_deref())).await?;
+
+        dialogue.exit().await?;
+
+        Ok(())
+    }
+}
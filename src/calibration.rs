@@ -0,0 +1,110 @@
+use crate::{models::{Calibration, QuoteAccuracy}, money::Money};
+
+/// How far a bill may be from the client's quote before it is kept as a calibration record for /accuracy.
+#[derive(Clone)]
+pub struct CalibrationPolicy {
+    pub threshold_percent: f32
+}
+
+impl CalibrationPolicy {
+    pub fn from_env() -> CalibrationPolicy {
+        CalibrationPolicy {
+            threshold_percent: crate::config::env_or("CALIBRATION_THRESHOLD_PERCENT", 15.0)
+        }
+    }
+
+    pub fn is_off(&self, quoted: Money, billed: Money) -> bool {
+        matches!(delta_percent(quoted, billed), Some(delta) if delta.abs() > self.threshold_percent)
+    }
+}
+
+/// How much the bill is over (+) or under (-) the quote, None for a free quote.
+pub fn delta_percent(quoted: Money, billed: Money) -> Option<f32> {
+    if quoted.cents() <= 0 {
+        return None;
+    }
+
+    Some((billed.cents() - quoted.cents()) as f32 * 100.0 / quoted.cents() as f32)
+}
+
+/// The /accuracy report: quote-vs-bill error per tariff version and the latest parcels over the threshold.
+pub fn render_accuracy(rows: &[QuoteAccuracy], calibrations: &[Calibration], policy: &CalibrationPolicy) -> String {
+    if rows.is_empty() {
+        return "Нет счетов по посылкам с расчетом за выбранный период".to_string();
+    }
+
+    let mut lines = vec![format!("{:<7} {:>6} {:>7} {:>7} {:>7} {:>6}", "Тарифы", "Счета", "Ошибка", "Сдвиг", "Вес, кг", "Мимо")];
+
+    for row in rows {
+        lines.push(format!(
+            "v{:<6} {:>6} {:>6.1}% {:>+6.1}% {:>7} {:>6}",
+            row.tariff_version,
+            row.billed,
+            row.mean_error_percent,
+            row.bias_percent,
+            row.weight_bias_kg.map_or("—".to_string(), |kg| format!("{:+.2}", kg)),
+            row.calibrations
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(format!("Ошибка — среднее отклонение счета от расчета, сдвиг — в какую сторону. «Мимо» — дальше {:.0}%.", policy.threshold_percent));
+
+    if !calibrations.is_empty() {
+        lines.push(String::new());
+        lines.push("Последние расхождения:".to_string());
+    }
+
+    for calibration in calibrations {
+        lines.push(format!(
+            "{}: {} → {} ({:+.0}%), вес {:.2} → {} кг, плотность {:.0} кг/м³",
+            calibration.track_code,
+            calibration.quoted,
+            calibration.billed,
+            delta_percent(calibration.quoted, calibration.billed).unwrap_or_default(),
+            calibration.quoted_weight_kg,
+            calibration.actual_weight_kg.map_or("?".to_string(), |kg| format!("{:.2}", kg)),
+            calibration.density
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bills_far_from_the_quote_are_reported() {
+        let policy = CalibrationPolicy { threshold_percent: 15.0 };
+        let money = Money::from_cents;
+
+        assert!(!policy.is_off(money(1000), money(1150)));
+        assert!(policy.is_off(money(1000), money(1151)));
+        assert!(policy.is_off(money(1000), money(800)));
+        assert!(!policy.is_off(money(0), money(800)));
+
+        let rows = vec![QuoteAccuracy {
+            tariff_version: 3,
+            billed: 12,
+            mean_error_percent: 8.25,
+            bias_percent: -4.0,
+            weight_bias_kg: Some(0.3),
+            calibrations: 2
+        }];
+        let calibrations = vec![Calibration {
+            track_code: "YT1".to_string(),
+            quoted: money(1000),
+            billed: money(1300),
+            quoted_weight_kg: 2.0,
+            actual_weight_kg: Some(2.6),
+            density: 150.0
+        }];
+
+        let text = render_accuracy(&rows, &calibrations, &policy);
+        assert!(text.contains("v3          12    8.2%   -4.0%   +0.30      2"), "{}", text);
+        assert!(text.contains("YT1: 10,00 $ → 13,00 $ (+30%), вес 2.00 → 2.60 кг, плотность 150 кг/м³"));
+        assert!(text.contains("дальше 15%"));
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+
+use crate::{config::env_or, database::Db, models::WarehouseDay};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Scans and weights are still corrected a day or two after intake, older days are left as they are.
+const REFRESH_DAYS: i32 = 3;
+
+// The trend compares the average of the last week with the week before it.
+const TREND_DAYS: usize = 7;
+
+/// How much a day of intake the warehouse and one truck handle, busier days are flagged in /capacity.
+#[derive(Clone)]
+pub struct CapacityPolicy {
+    pub max_parcels: i32,
+    pub max_weight_kg: f32
+}
+
+impl CapacityPolicy {
+    pub fn from_env() -> CapacityPolicy {
+        CapacityPolicy {
+            max_parcels: env_or("CAPACITY_MAX_PARCELS", 300),
+            max_weight_kg: env_or("CAPACITY_MAX_KG", 1500.0)
+        }
+    }
+
+    pub fn is_exceeded(&self, day: &WarehouseDay) -> bool {
+        day.parcels > self.max_parcels || day.weight_kg > self.max_weight_kg
+    }
+}
+
+/// Keeps `warehouse_stats` up to date with the parcels operators scan and the arrivals of the last days.
+pub struct IntakeStats {
+    db: Db
+}
+
+impl IntakeStats {
+    pub fn spawn(db: Db) {
+        log::info!("Starting the intake stats job");
+        tokio::spawn(IntakeStats { db }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let days = self.db.refresh_warehouse_stats(REFRESH_DAYS).await;
+            log::info!("Intake stats: {} days refreshed", days);
+        }
+    }
+}
+
+/// The intake table of /capacity, oldest day first, with the weekly trend and the days over the limits.
+pub fn render_capacity(days: &[WarehouseDay], policy: &CapacityPolicy, today: NaiveDate) -> String {
+    if days.is_empty() {
+        return "Нет данных о приемке за выбранный период".to_string();
+    }
+
+    let mut lines = vec![format!("{:<6} {:>6} {:>6} {:>8}", "День", "Посыл", "Скан", "Вес, кг")];
+
+    for day in days {
+        lines.push(format!(
+            "{:<6} {:>6} {:>6} {:>8.1}{}",
+            day.day.format("%d.%m"),
+            day.parcels,
+            day.scanned,
+            day.weight_kg,
+            if policy.is_exceeded(day) { " ⚠️" } else { "" }
+        ));
+    }
+
+    lines.push(String::new());
+
+    let week = average(days, today, 0);
+    let previous = average(days, today, 1);
+
+    lines.push(format!("Среднее за {} дн.: {:.0} посылок, {:.1} кг{}", TREND_DAYS, week.0, week.1, trend(week.1, previous.1)));
+
+    let exceeded = days.iter().filter(|day| policy.is_exceeded(day)).count();
+
+    lines.push(format!(
+        "Дней сверх нормы ({} посылок или {:.0} кг): {}",
+        policy.max_parcels,
+        policy.max_weight_kg,
+        exceeded
+    ));
+
+    lines.join("\n")
+}
+
+/// Average parcels and weight a day over the `weeks_back`-th week before `today`, today itself is not over yet.
+fn average(days: &[WarehouseDay], today: NaiveDate, weeks_back: i64) -> (f32, f32) {
+    let end = today - chrono::Duration::days(weeks_back * TREND_DAYS as i64);
+    let start = end - chrono::Duration::days(TREND_DAYS as i64);
+
+    // Days without intake have no row and count as zero.
+    let (parcels, weight_kg) = days.iter()
+        .filter(|day| day.day >= start && day.day < end)
+        .fold((0, 0.0), |(parcels, weight_kg), day| (parcels + day.parcels, weight_kg + day.weight_kg));
+
+    (parcels as f32 / TREND_DAYS as f32, weight_kg / TREND_DAYS as f32)
+}
+
+fn trend(current: f32, previous: f32) -> String {
+    if previous <= 0.0 {
+        return String::new();
+    }
+
+    format!(" ({:+.0}% к прошлой неделе)", (current - previous) * 100.0 / previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: NaiveDate, parcels: i32, weight_kg: f32) -> WarehouseDay {
+        WarehouseDay { day: date, parcels, scanned: parcels, weight_kg }
+    }
+
+    #[test]
+    fn busy_days_are_flagged_with_the_weekly_trend() {
+        let policy = CapacityPolicy { max_parcels: 100, max_weight_kg: 500.0 };
+        let today = NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        let date = |d: u32| NaiveDate::from_ymd_opt(2024, 6, d).unwrap();
+
+        let days = vec![day(date(1), 70, 140.0), day(date(8), 80, 700.0), day(date(10), 120, 210.0), day(date(15), 500, 900.0)];
+        let text = render_capacity(&days, &policy, today);
+
+        assert!(text.contains("08.06      80     80    700.0 ⚠️"));
+        assert!(text.contains("10.06     120    120    210.0 ⚠️"));
+        assert!(!text.contains("01.06      70     70    140.0 ⚠️"));
+        // June 8 to 14 against June 1 to 7, today is left out.
+        assert!(text.contains("Среднее за 7 дн.: 29 посылок, 130.0 кг (+550% к прошлой неделе)"));
+        assert!(text.contains("Дней сверх нормы (100 посылок или 500 кг): 3"));
+
+        assert_eq!(render_capacity(&[], &policy, today), "Нет данных о приемке за выбранный период");
+    }
+}
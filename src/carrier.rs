@@ -0,0 +1,106 @@
+/// Longer input is not a track code of any carrier, it is pasted text.
+pub const MAX_TRACK_CODE_LENGTH: usize = 40;
+
+/// The trimmed track code, None unless it is letters and digits of a plausible length.
+///
+/// Codes of carriers [`Carrier::detect`] does not know pass too, only the shape is checked.
+pub fn parse_track_code(text: &str) -> Option<String> {
+    let track_code = text.trim();
+
+    match !track_code.is_empty() && track_code.len() <= MAX_TRACK_CODE_LENGTH && track_code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        true => Some(track_code.to_string()),
+        false => None
+    }
+}
+
+/// Chinese carriers recognized by their track code format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Carrier {
+    Yto,
+    SfExpress,
+    Jd,
+    Zto
+}
+
+impl Carrier {
+    pub fn detect(track_code: &str) -> Option<Carrier> {
+        let track_code = track_code.trim().to_uppercase();
+        let all_digits = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+
+        if let Some(digits) = track_code.strip_prefix("YT") {
+            return (all_digits(digits) && digits.len() >= 10).then_some(Carrier::Yto);
+        }
+
+        if let Some(digits) = track_code.strip_prefix("SF") {
+            return (all_digits(digits) && digits.len() >= 10).then_some(Carrier::SfExpress);
+        }
+
+        // JDV, JDX, JDAZ...: a few letters for the service, then digits.
+        if let Some(rest) = track_code.strip_prefix("JD") {
+            let digits = rest.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+
+            return (all_digits(digits) && digits.len() >= 9).then_some(Carrier::Jd);
+        }
+
+        if track_code.starts_with("78") && track_code.len() == 14 && all_digits(&track_code) {
+            return Some(Carrier::Zto);
+        }
+
+        None
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Carrier::Yto => "YTO Express (圆通)",
+            Carrier::SfExpress => "SF Express (顺丰)",
+            Carrier::Jd => "JD Logistics (京东)",
+            Carrier::Zto => "ZTO Express (中通)"
+        }
+    }
+
+    /// Whether parcels of this carrier reach our warehouse and show up in tracking.
+    ///
+    /// JD delivers with its own couriers and does not hand parcels over to the warehouse.
+    pub fn supported(&self) -> bool {
+        !matches!(self, Carrier::Jd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_formats_are_detected() {
+        assert_eq!(Carrier::detect("YT7412345678901"), Some(Carrier::Yto));
+        assert_eq!(Carrier::detect(" sf1234567890123 "), Some(Carrier::SfExpress));
+        assert_eq!(Carrier::detect("JDV012345678901"), Some(Carrier::Jd));
+        assert_eq!(Carrier::detect("JD0123456789"), Some(Carrier::Jd));
+        assert_eq!(Carrier::detect("78123456789012"), Some(Carrier::Zto));
+    }
+
+    #[test]
+    fn unknown_formats_are_not_guessed() {
+        assert_eq!(Carrier::detect("YT1"), None);
+        assert_eq!(Carrier::detect("SFABC1234567890"), None);
+        assert_eq!(Carrier::detect("7812345678901"), None);
+        assert_eq!(Carrier::detect("4312345678901"), None);
+        assert_eq!(Carrier::detect(""), None);
+    }
+
+    #[test]
+    fn track_codes_are_letters_and_digits() {
+        assert_eq!(parse_track_code(" YT7412345678901 "), Some("YT7412345678901".to_string()));
+        assert_eq!(parse_track_code("LP00123"), Some("LP00123".to_string()));
+        assert_eq!(parse_track_code(""), None);
+        assert_eq!(parse_track_code("YT74 123"), None);
+        assert_eq!(parse_track_code("../admin"), None);
+        assert_eq!(parse_track_code(&"1".repeat(MAX_TRACK_CODE_LENGTH + 1)), None);
+    }
+
+    #[test]
+    fn jd_is_not_tracked_at_the_warehouse() {
+        assert!(!Carrier::Jd.supported());
+        assert!(Carrier::SfExpress.supported());
+    }
+}
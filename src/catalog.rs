@@ -0,0 +1,203 @@
+use std::time::Duration;
+
+use reqwest::{redirect, Url};
+
+use crate::vendor::VendorResult;
+
+// Marketplaces clients order from, with their short-link domains. Other links are not fetched.
+const MARKETPLACES: &[&str] = &[
+    "taobao.com", "tb.cn", "tmall.com", "1688.com", "jd.com", "3.cn", "yangkeduo.com", "pinduoduo.com",
+    "dewu.com", "poizon.com", "aliexpress.com", "aliexpress.ru", "alibaba.com"
+];
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Product pages put their meta tags in the head, the rest of the page is not needed.
+const MAX_PAGE_BYTES: usize = 512 * 1024;
+
+/// What a marketplace page says about the item, as far as it could be read.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Item {
+    pub url: String,
+    pub title: Option<String>,
+    pub price: Option<String>,
+    pub image: Option<String>
+}
+
+/// The first marketplace link in a message, share texts from the apps carry one among other words.
+pub fn marketplace_url(text: &str) -> Option<Url> {
+    text.split_whitespace()
+        .filter_map(|word| word.find("http").map(|start| &word[start..]))
+        .filter_map(|word| Url::parse(word).ok())
+        .find(is_marketplace)
+}
+
+/// Reads the title, price and picture of an item, an unreadable page leaves them empty.
+pub async fn fetch(url: &Url) -> Item {
+    let mut item = Item { url: url.to_string(), ..Item::default() };
+
+    match fetch_page(url).await {
+        Ok(html) => {
+            let (title, price, image) = parse(&html);
+
+            item.title = title;
+            item.price = price;
+            item.image = image;
+        },
+        Err(err) => log::warn!("Catalog: could not fetch {}: {}", url, err)
+    }
+
+    item
+}
+
+async fn fetch_page(url: &Url) -> VendorResult<String> {
+    // Redirects of short links are followed only as long as they stay on marketplaces.
+    let client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() < 5 && is_marketplace(attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        }))
+        .build()?;
+
+    let mut response = client.get(url.clone()).send().await?.error_for_status()?;
+    let mut page = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        page.extend_from_slice(&chunk);
+
+        if page.len() >= MAX_PAGE_BYTES {
+            break;
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&page).into_owned())
+}
+
+/// The marketplace domain a link points to, e.g. `taobao.com` for `m.intl.taobao.com`.
+pub fn marketplace(url: &Url) -> Option<&'static str> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let host = url.host_str()?;
+
+    MARKETPLACES.iter().copied().find(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+}
+
+fn is_marketplace(url: &Url) -> bool {
+    marketplace(url).is_some()
+}
+
+/// Title, price with currency and picture from the Open Graph and product meta tags.
+fn parse(html: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let metas: Vec<(String, String)> = html.match_indices("<meta")
+        .filter_map(|(start, _)| {
+            let tag = &html[start..start + html[start..].find('>')?];
+            let key = attribute(tag, "property").or(attribute(tag, "name")).or(attribute(tag, "itemprop"))?;
+
+            Some((key.to_lowercase(), decode(attribute(tag, "content")?)))
+        })
+        .collect();
+
+    let meta = |keys: &[&str]| keys.iter()
+        .find_map(|key| metas.iter().find(|(name, _)| name == key))
+        .map(|(_, content)| content.trim().to_string())
+        .filter(|content| !content.is_empty());
+
+    let title = meta(&["og:title", "twitter:title"]).or_else(|| {
+        let start = html.find("<title")?;
+        let start = start + html[start..].find('>')? + 1;
+        let end = start + html[start..].find("</title>")?;
+
+        Some(decode(html[start..end].trim())).filter(|title| !title.is_empty())
+    });
+
+    let price = meta(&["product:price:amount", "og:price:amount", "price"]).map(|amount| {
+        match meta(&["product:price:currency", "og:price:currency", "pricecurrency"]) {
+            Some(currency) => format!("{} {}", amount, currency),
+            None => amount
+        }
+    });
+
+    let image = meta(&["og:image", "twitter:image"]).map(|image| match image.starts_with("//") {
+        true => format!("https:{}", image),
+        false => image
+    });
+
+    (title, price, image)
+}
+
+/// Attribute names are ASCII, so they are matched case-insensitively in place:
+/// lowercasing the whole tag can change the byte length of the values and shift the offsets.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{}=", name);
+
+    let start = tag.as_bytes().windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| window.eq_ignore_ascii_case(pattern.as_bytes()))
+        .map(|(i, _)| i)
+        .find(|i| tag[..*i].ends_with(char::is_whitespace))?
+        + pattern.len();
+
+    let quote = tag[start..].chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &tag[start + 1..];
+
+    Some(&value[..value.find(quote)?])
+}
+
+fn decode(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_are_found_in_share_texts() {
+        let url = marketplace_url("【淘宝】https://m.tb.cn/h.5Xyz?tk=abc 「男士运动鞋」点击链接直接打开").unwrap();
+
+        assert_eq!(url.host_str(), Some("m.tb.cn"));
+        assert!(marketplace_url("https://item.taobao.com/item.htm?id=1").is_some());
+        assert!(marketplace_url("https://example.com/taobao.com").is_none());
+        assert!(marketplace_url("кроссовки для брата").is_none());
+        assert_eq!(marketplace(&Url::parse("https://m.intl.taobao.com/item?id=1").unwrap()), Some("taobao.com"));
+    }
+
+    #[test]
+    fn open_graph_tags_are_read() {
+        let html = r#"<html><head><title>Fallback</title>
+            <meta property="og:title" content="Nike Air Force 1 &amp; box">
+            <meta property="product:price:amount" content="399.00" />
+            <meta property="product:price:currency" content="CNY">
+            <meta name='og:image' content='//img.alicdn.com/shoe.jpg'>
+            </head></html>"#;
+
+        assert_eq!(parse(html), (
+            Some("Nike Air Force 1 & box".to_string()),
+            Some("399.00 CNY".to_string()),
+            Some("https://img.alicdn.com/shoe.jpg".to_string())
+        ));
+    }
+
+    #[test]
+    fn attributes_keep_their_offsets_when_lowercasing_changes_lengths() {
+        let tag = r#"meta CONTENT="İstanbul Ⱥ" Property="og:title""#;
+
+        assert_eq!(attribute(tag, "property"), Some("og:title"));
+        assert_eq!(attribute(tag, "content"), Some("İstanbul Ⱥ"));
+    }
+
+    #[test]
+    fn title_tag_is_the_fallback() {
+        assert_eq!(parse("<title> 商品详情 </title><meta charset=\"utf-8\">"), (Some("商品详情".to_string()), None, None));
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+
+// Mainland provinces, autonomous regions and municipalities, as they start a full address.
+const PROVINCES: &[&str] = &[
+    "河北", "山西", "辽宁", "吉林", "黑龙江", "江苏", "浙江", "安徽", "福建", "江西", "山东",
+    "河南", "湖北", "湖南", "广东", "海南", "四川", "贵州", "云南", "陕西", "甘肃", "青海",
+    "台湾", "内蒙古", "广西", "西藏", "宁夏", "新疆"
+];
+
+// Municipalities are a province and a city at once.
+const MUNICIPALITIES: &[&str] = &["北京", "上海", "天津", "重庆"];
+
+// Whole suffixes that close a name, longer ones first so 自治区 is not read as a district 区.
+const PROVINCE_SUFFIXES: &[&str] = &["壮族自治区", "回族自治区", "维吾尔自治区", "自治区", "省", "市"];
+
+// A bare 州 is part of names like 广州 and 杭州, only 自治州 closes a prefecture.
+const CITY_SUFFIXES: &[&str] = &["自治州", "地区", "市", "盟"];
+
+const DISTRICT_SUFFIXES: &[&str] = &["区", "县", "旗", "市"];
+
+#[derive(Debug, PartialEq)]
+pub enum AddressIssue {
+    NoPhone,
+    NotMobile(String),
+    NotChinese,
+    NoProvince,
+    NoCity,
+    NoDistrict,
+    WrongOrder
+}
+
+impl fmt::Display for AddressIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressIssue::NoPhone => write!(f, "Не найден номер телефона получателя"),
+            AddressIssue::NotMobile(phone) => write!(f, "Номер {} не похож на китайский мобильный: нужно 11 цифр, первая 1", phone),
+            AddressIssue::NotChinese => write!(f, "Адрес должен быть написан иероглифами"),
+            AddressIssue::NoProvince => write!(f, "Не указана провинция (省) или город центрального подчинения"),
+            AddressIssue::NoCity => write!(f, "Не указан город (市)"),
+            AddressIssue::NoDistrict => write!(f, "Не указан район (区 или 县)"),
+            AddressIssue::WrongOrder => write!(f, "Части адреса идут не по порядку: сначала провинция, затем город и район")
+        }
+    }
+}
+
+/// Checks an address with a phone number the way a Chinese seller fills in a waybill.
+///
+/// Returns the normalized mobile number when it is found and valid.
+pub fn check(text: &str) -> (Option<String>, Vec<AddressIssue>) {
+    let mut issues = Vec::new();
+
+    let phone = match find_phone(text) {
+        Some(phone) if is_mobile(&phone) => Some(phone),
+        Some(phone) => {
+            issues.push(AddressIssue::NotMobile(phone));
+
+            None
+        },
+        None => {
+            issues.push(AddressIssue::NoPhone);
+
+            None
+        }
+    };
+
+    issues.extend(check_address(text));
+
+    (phone, issues)
+}
+
+// The longest run of digits, spaces and hyphens, without the +86 country code.
+fn find_phone(text: &str) -> Option<String> {
+    let phone = text.split(|c: char| !(c.is_ascii_digit() || c == ' ' || c == '-' || c == '+'))
+        .map(|part| part.chars().filter(|c| c.is_ascii_digit()).collect::<String>())
+        .max_by_key(|digits| digits.len())
+        .filter(|digits| digits.len() >= 7)?;
+
+    let phone = phone.strip_prefix("0086")
+        .or_else(|| phone.strip_prefix("86").filter(|rest| rest.len() == 11))
+        .map_or(phone.clone(), str::to_string);
+
+    Some(phone)
+}
+
+fn is_mobile(phone: &str) -> bool {
+    phone.len() == 11 && phone.starts_with('1') && matches!(phone.as_bytes()[1], b'3'..=b'9')
+}
+
+fn check_address(text: &str) -> Vec<AddressIssue> {
+    if !text.chars().any(|c| ('\u{4e00}'..='\u{9fff}').contains(&c)) {
+        return vec![AddressIssue::NotChinese];
+    }
+
+    let municipality = MUNICIPALITIES.iter().find_map(|name| text.find(name).map(|start| (start, name.len())));
+    let province = PROVINCES.iter().find_map(|name| text.find(name).map(|start| (start, name.len()))).or(municipality);
+
+    let (province, name_length) = match province {
+        Some(province) => province,
+        None => return vec![AddressIssue::NoProvince]
+    };
+
+    let after_name = &text[province + name_length..];
+    let after_province = PROVINCE_SUFFIXES.iter()
+        .find_map(|suffix| after_name.strip_prefix(suffix))
+        .unwrap_or(after_name);
+
+    let after_city = match municipality {
+        Some(_) => Some(after_province),
+        None => after_suffix(after_province, CITY_SUFFIXES)
+    };
+
+    let after_city = match after_city {
+        Some(after_city) => after_city,
+        None => return vec![AddressIssue::NoCity]
+    };
+
+    match after_suffix(after_city, DISTRICT_SUFFIXES) {
+        Some(_) => Vec::new(),
+        None if after_suffix(&text[..province], DISTRICT_SUFFIXES).is_some() => vec![AddressIssue::WrongOrder],
+        None => vec![AddressIssue::NoDistrict]
+    }
+}
+
+// The text after the first name closed by one of the suffixes, a suffix alone is not a name.
+fn after_suffix<'a>(text: &'a str, suffixes: &[&str]) -> Option<&'a str> {
+    text.char_indices()
+        .skip(1)
+        .find_map(|(i, _)| suffixes.iter().find_map(|suffix| text[i..].strip_prefix(suffix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_waybill_passes() {
+        let (phone, issues) = check("广东省广州市白云区太和镇 123号 仓库 +86 138-1234-5678");
+
+        assert_eq!(phone.as_deref(), Some("13812345678"));
+        assert!(issues.is_empty());
+
+        let (_, issues) = check("上海市浦东新区张江路 1号 13912345678");
+
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn phone_must_be_a_mobile() {
+        assert_eq!(check("广东省广州市白云区").1, vec![AddressIssue::NoPhone]);
+        assert_eq!(check("广东省广州市白云区 020-12345678").1, vec![AddressIssue::NotMobile("02012345678".to_string())]);
+        assert_eq!(check("广东省广州市白云区 1381234567").1, vec![AddressIssue::NotMobile("1381234567".to_string())]);
+    }
+
+    #[test]
+    fn address_structure_is_checked() {
+        assert_eq!(check("Guangzhou 13812345678").1, vec![AddressIssue::NotChinese]);
+        assert_eq!(check("白云区太和镇 13812345678").1, vec![AddressIssue::NoProvince]);
+        assert_eq!(check("广东省白云区 13812345678").1, vec![AddressIssue::NoCity]);
+        assert_eq!(check("广东省广州市太和镇 13812345678").1, vec![AddressIssue::NoDistrict]);
+        assert_eq!(check("白云区 广东省广州市 13812345678").1, vec![AddressIssue::WrongOrder]);
+    }
+
+    #[test]
+    fn only_whole_suffixes_count() {
+        assert!(check("广西壮族自治区南宁市青秀区 13812345678").1.is_empty());
+        assert!(check("吉林省延边朝鲜族自治州延吉市 13812345678").1.is_empty());
+        assert_eq!(check("广东省广州白云区 13812345678").1, vec![AddressIssue::NoCity]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+const PREFIX: &str = "MX";
+
+// Check letters, without I, O and Q that are easy to confuse with digits.
+const CHECK_LETTERS: &[u8; 23] = b"ABCDEFGHJKLMNPRSTUVWXYZ";
+
+#[derive(Debug, PartialEq)]
+pub enum ClientCodeError {
+    Format,
+    Checksum
+}
+
+impl fmt::Display for ClientCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientCodeError::Format => write!(f, "Клиентский код должен выглядеть как MX201H"),
+            ClientCodeError::Checksum => write!(f, "В клиентском коде опечатка: контрольная буква не сходится")
+        }
+    }
+}
+
+/// Builds a client code from its number, e.g. `MX201` plus the check letter.
+pub fn generate(number: i64) -> String {
+    let digits = number.to_string();
+
+    format!("{}{}{}", PREFIX, digits, check_letter(&digits))
+}
+
+/// Normalizes a typed client code and checks it before it is looked up.
+///
+/// Codes issued before check letters were introduced have none and are accepted as they are.
+pub fn parse(input: &str) -> Result<String, ClientCodeError> {
+    let code: String = input.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(latin_lookalike)
+        .collect::<String>()
+        .to_uppercase();
+
+    let rest = code.strip_prefix(PREFIX).ok_or(ClientCodeError::Format)?;
+    let digits = rest.trim_end_matches(|c: char| c.is_ascii_uppercase());
+    let letter = &rest[digits.len()..];
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) || letter.len() > 1 {
+        return Err(ClientCodeError::Format);
+    }
+
+    if let Some(letter) = letter.chars().next() {
+        if letter != check_letter(digits) {
+            return Err(ClientCodeError::Checksum);
+        }
+    }
+
+    Ok(code)
+}
+
+/// Valid codes one mistyped digit away, to suggest when a code is not found.
+///
+/// A code typed without its check letter also suggests the same number with one.
+pub fn neighbours(code: &str) -> Vec<String> {
+    let digits = code.trim_start_matches(PREFIX).trim_end_matches(|c: char| c.is_ascii_uppercase());
+    let checked = code.len() > PREFIX.len() + digits.len();
+
+    let mut neighbours = Vec::new();
+
+    if !checked {
+        neighbours.push(format!("{}{}{}", PREFIX, digits, check_letter(digits)));
+    }
+
+    for (i, original) in digits.char_indices() {
+        for digit in '0'..='9' {
+            if digit == original {
+                continue;
+            }
+
+            let mut candidate = digits.to_string();
+            candidate.replace_range(i..i + 1, &digit.to_string());
+
+            neighbours.push(if checked {
+                format!("{}{}{}", PREFIX, candidate, check_letter(&candidate))
+            } else {
+                format!("{}{}", PREFIX, candidate)
+            });
+        }
+    }
+
+    neighbours
+}
+
+// Weighted sum modulo a prime catches any single wrong digit and swapped neighbours.
+fn check_letter(digits: &str) -> char {
+    let sum: u32 = digits.chars()
+        .rev()
+        .enumerate()
+        .map(|(i, digit)| digit.to_digit(10).unwrap_or(0) * (i as u32 + 1))
+        .sum();
+
+    CHECK_LETTERS[(sum % CHECK_LETTERS.len() as u32) as usize] as char
+}
+
+// Cyrillic М and Х look the same as the Latin prefix on most keyboards.
+fn latin_lookalike(c: char) -> char {
+    match c {
+        'М' | 'м' => 'M',
+        'Х' | 'х' => 'X',
+        c => c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_codes_pass_validation() {
+        for number in [200, 201, 999, 12345] {
+            let code = generate(number);
+
+            assert_eq!(parse(&code), Ok(code.clone()));
+        }
+    }
+
+    #[test]
+    fn typed_codes_are_normalized() {
+        let code = generate(201);
+
+        assert_eq!(parse(&format!(" {} ", code.to_lowercase())), Ok(code.clone()));
+        assert_eq!(parse(&code.replace("MX", "МХ")), Ok(code.clone()));
+        assert_eq!(parse("mx-201"), Ok("MX201".to_string()));
+    }
+
+    #[test]
+    fn wrong_digit_or_swap_breaks_the_checksum() {
+        let letter = generate(2014).chars().last().unwrap();
+
+        assert_eq!(parse(&format!("MX2015{}", letter)), Err(ClientCodeError::Checksum));
+        assert_eq!(parse(&format!("MX2104{}", letter)), Err(ClientCodeError::Checksum));
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        assert_eq!(parse("201"), Err(ClientCodeError::Format));
+        assert_eq!(parse("MX"), Err(ClientCodeError::Format));
+        assert_eq!(parse("MX20A1"), Err(ClientCodeError::Format));
+        assert_eq!(parse("MX201AB"), Err(ClientCodeError::Format));
+    }
+
+    #[test]
+    fn neighbours_differ_by_one_digit() {
+        let legacy = neighbours("MX201");
+
+        assert_eq!(legacy.len(), 28);
+        assert_eq!(legacy[0], "MX201H");
+        assert!(legacy.contains(&"MX211".to_string()));
+        assert!(legacy.contains(&"MX301".to_string()));
+
+        let code = generate(201);
+        assert!(neighbours(&code).iter().all(|neighbour| parse(neighbour).is_ok()));
+        assert!(neighbours(&code).contains(&generate(202)));
+    }
+}
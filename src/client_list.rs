@@ -0,0 +1,47 @@
+use crate::{manifest::escape, models::User};
+
+const HEADER: [&str; 5] = ["Client code", "First name", "Last name", "Phone", "Username"];
+
+/// Renders the clients with their contacts as CSV, for `/export`.
+///
+/// Starts with a BOM like the manifest, the names are mostly Cyrillic.
+pub fn render_csv(users: &[User]) -> String {
+    let mut lines = vec![HEADER.iter().map(|cell| escape(cell)).collect::<Vec<_>>().join(",")];
+
+    for user in users {
+        lines.push([
+            escape(&user.client_code),
+            escape(&user.first_name),
+            escape(user.last_name.as_deref().unwrap_or_default()),
+            escape(&user.phone_number),
+            user.username.as_deref().map_or(String::new(), |username| escape(&format!("@{}", username)))
+        ].join(","));
+    }
+
+    format!("\u{feff}{}\r\n", lines.join("\r\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn rows_follow_the_header() {
+        let user = User {
+            id: 1,
+            first_name: "Айбек".to_string(),
+            last_name: None,
+            client_code: "MX201".to_string(),
+            username: Some("aibek".to_string()),
+            ..fixtures::user(7)
+        };
+
+        let csv = render_csv(&[user]);
+        let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().collect();
+
+        assert_eq!(lines[0], "Client code,First name,Last name,Phone,Username");
+        assert_eq!(lines[1], "MX201,Айбек,,996555123456,@aibek");
+    }
+}
@@ -0,0 +1,225 @@
+use std::{net::SocketAddr, str::FromStr, sync::{Arc, OnceLock}, time::Duration};
+
+use reqwest::Url;
+use teloxide::types::ChatId;
+
+use crate::{attachments::AttachmentPolicy, calibration::CalibrationPolicy, capacity::CapacityPolicy, label, onboarding::OnboardingPolicy, payments::PaymentPolicy, phone_policy::PhonePolicy, reengagement::ReengagementPolicy, retention::RetentionPolicy, sms::PhoneVerification, support::SupportDesk, translation::StatusTranslator, watchdog::WatchdogPolicy};
+
+const VENDOR_BASE_URL: &str = "http://www.107kapro.cn";
+
+const STAGING_BANNER: &str = "🧪 ТЕСТОВЫЙ БОТ";
+
+static APP_ENV: OnceLock<AppEnv> = OnceLock::new();
+
+/// The environment profile picked by APP_ENV, production when unset.
+///
+/// Staging reads its token, database and vendor URL from `STAGING_*` variables so that
+/// a staging run never talks to real clients or writes into the production database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AppEnv {
+    Production,
+    Staging
+}
+
+impl AppEnv {
+    /// Read once per process, after `.env` is loaded.
+    pub fn current() -> AppEnv {
+        *APP_ENV.get_or_init(|| match env_opt("APP_ENV").as_deref() {
+            None | Some("production") => AppEnv::Production,
+            Some("staging") => AppEnv::Staging,
+            Some(_) => panic!("ERROR: Could not parse APP_ENV, expected production or staging")
+        })
+    }
+
+    pub fn bot_token(self) -> String {
+        self.required("TELOXIDE_TOKEN")
+    }
+
+    pub fn postgres_db(self) -> String {
+        self.required("POSTGRES_DB")
+    }
+
+    /// A read replica for reports, staging reads STAGING_REPORTS_DATABASE_URL so it never reports on production.
+    pub fn reports_database_url(self) -> Option<String> {
+        self.var("REPORTS_DATABASE_URL")
+    }
+
+    /// A test token from @BotFather in staging, so staging never charges real cards.
+    pub fn payment_provider_token(self) -> Option<String> {
+        self.var("PAYMENT_PROVIDER_TOKEN")
+    }
+
+    pub fn vendor_base_url(self) -> String {
+        self.var("VENDOR_BASE_URL").unwrap_or(VENDOR_BASE_URL.to_string())
+    }
+
+    /// Used unless RUST_LOG is set, staging logs the bot's own debug lines.
+    pub fn log_filter(self) -> &'static str {
+        match self {
+            AppEnv::Production => "error",
+            AppEnv::Staging => "info,max_express_bot=debug"
+        }
+    }
+
+    /// Marks a message sent from staging, so a tester's screenshot is never taken for the real bot.
+    pub fn banner(self, text: String) -> String {
+        match self {
+            AppEnv::Production => text,
+            AppEnv::Staging => format!("{}\n\n{}", STAGING_BANNER, text)
+        }
+    }
+
+    fn var(self, name: &str) -> Option<String> {
+        match self {
+            AppEnv::Production => env_opt(name),
+            AppEnv::Staging => env_opt(&format!("STAGING_{}", name))
+        }
+    }
+
+    // No fallback to the production value, a missing staging variable is a mistake.
+    fn required(self, name: &str) -> String {
+        let name = match self {
+            AppEnv::Production => name.to_string(),
+            AppEnv::Staging => format!("STAGING_{}", name)
+        };
+
+        env_opt(&name).unwrap_or_else(|| panic!("ERROR: Could not get {}", name))
+    }
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub app_env: AppEnv,
+    /// The first staff members, only read while the staff table is empty.
+    pub admin_ids: Vec<i64>,
+    owner_id: Option<i64>,
+    pub export_watermark: bool,
+    pub notify_interval: Duration,
+    pub dedup_window: Duration,
+    pub maintenance_message: String,
+    pub http_address: SocketAddr,
+    pub dashboard_token: Option<String>,
+    pub api_keys: Vec<String>,
+    pub webhook: Option<Webhook>,
+    pub support: SupportDesk,
+    pub attachments: AttachmentPolicy,
+    pub welcome_experiment: bool,
+    pub phone_policy: PhonePolicy,
+    pub retention: RetentionPolicy,
+    pub reengagement: ReengagementPolicy,
+    pub onboarding: OnboardingPolicy,
+    pub require_last_name: bool,
+    pub phone_verification: PhoneVerification,
+    pub status_translator: StatusTranslator,
+    pub watchdog: WatchdogPolicy,
+    pub payments: PaymentPolicy,
+    pub capacity: CapacityPolicy,
+    pub calibration: CalibrationPolicy,
+    /// Read once at startup, see [`label::load_font`].
+    pub label_font: Option<Arc<Vec<u8>>>,
+    pub courier_chat: Option<ChatId>,
+    alert_chat: Option<ChatId>
+}
+
+/// Receiving updates through a webhook instead of long polling.
+#[derive(Clone)]
+pub struct Webhook {
+    pub url: Url,
+    pub secret: String
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let admin_ids = std::env::var("ADMIN_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| id.trim().parse().expect("ERROR: Could not parse ADMIN_IDS"))
+            .collect();
+
+        Config {
+            app_env: AppEnv::current(),
+            admin_ids,
+            owner_id: env_opt("OWNER_ID").map(|id| id.parse().expect("ERROR: Could not parse OWNER_ID")),
+            export_watermark: env_or("EXPORT_WATERMARK", false),
+            notify_interval: Duration::from_secs(env_or("NOTIFY_INTERVAL_MINUTES", 30) * 60),
+            dedup_window: Duration::from_secs(env_or("DEDUP_WINDOW_SECONDS", 5)),
+            maintenance_message: env_or(
+                "MAINTENANCE_MESSAGE",
+                "🛠 Бот на техобслуживании до {until}. Пожалуйста, попробуйте позже.".to_string()
+            ),
+            http_address: env_or("HTTP_ADDRESS", SocketAddr::from(([0, 0, 0, 0], 8080))),
+            dashboard_token: env_opt("DASHBOARD_TOKEN"),
+            api_keys: env_opt("API_KEYS").unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .collect(),
+            webhook: env_opt("WEBHOOK_URL").map(|url| Webhook {
+                url: url.parse().expect("ERROR: Could not parse WEBHOOK_URL"),
+                secret: env_opt("WEBHOOK_SECRET").expect("ERROR: Could not get WEBHOOK_SECRET")
+            }),
+            support: SupportDesk::from_env(),
+            attachments: AttachmentPolicy::from_env(),
+            welcome_experiment: env_or("WELCOME_EXPERIMENT", false),
+            phone_policy: PhonePolicy::from_env(),
+            retention: RetentionPolicy::from_env(),
+            reengagement: ReengagementPolicy::from_env(),
+            onboarding: OnboardingPolicy::from_env(),
+            require_last_name: env_or("REQUIRE_LAST_NAME", false),
+            phone_verification: PhoneVerification::from_env(),
+            status_translator: StatusTranslator::from_env(),
+            watchdog: WatchdogPolicy::from_env(),
+            payments: PaymentPolicy::from_env(),
+            capacity: CapacityPolicy::from_env(),
+            calibration: CalibrationPolicy::from_env(),
+            label_font: label::load_font().map(Arc::new),
+            courier_chat: env_opt("COURIER_CHAT_ID").map(|id| ChatId(id.parse().expect("ERROR: Could not parse COURIER_CHAT_ID"))),
+            alert_chat: env_opt("ALERT_CHAT_ID").map(|id| ChatId(id.parse().expect("ERROR: Could not parse ALERT_CHAT_ID")))
+        }
+    }
+
+    /// The owner grants admins their permissions and holds all of them.
+    pub fn is_owner(&self, telegram_id: i64) -> bool {
+        self.owner_id == Some(telegram_id)
+    }
+
+    /// Where watchdog alerts and error reports go: ALERT_CHAT_ID, or every staff member in private when it is not set.
+    pub fn alert_chats(&self, staff: &[i64]) -> Vec<ChatId> {
+        match self.alert_chat {
+            Some(chat_id) => vec![chat_id],
+            None => staff.iter().map(|id| ChatId(*id)).collect()
+        }
+    }
+}
+
+/// Reads an optional variable, treating an empty value as unset.
+pub fn env_opt(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env_opt(name) {
+        Some(value) => value.parse().unwrap_or_else(|_| panic!("ERROR: Could not parse {}", name)),
+        None => default
+    }
+}
+
+/// Puts the staging banner on top of a message, see [`AppEnv::banner`].
+pub fn banner(text: impl Into<String>) -> String {
+    AppEnv::current().banner(text.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_staging_messages_carry_the_banner() {
+        assert_eq!(AppEnv::Production.banner("Профиль".to_string()), "Профиль");
+        assert_eq!(AppEnv::Staging.banner("Профиль".to_string()), "🧪 ТЕСТОВЫЙ БОТ\n\nПрофиль");
+    }
+}
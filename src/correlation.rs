@@ -0,0 +1,49 @@
+use std::{fmt, future::Future};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+tokio::task_local! {
+    static CURRENT: CorrelationId;
+}
+
+/// Tags everything done for one update or one background job, so a complaint leads to its log lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CorrelationId(u32);
+
+impl CorrelationId {
+    pub fn new() -> CorrelationId {
+        CorrelationId(OsRng.next_u32())
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+/// Runs `future` with a fresh id, log lines and outgoing requests inside it carry the id.
+pub async fn scope<F: Future>(future: F) -> F::Output {
+    CURRENT.scope(CorrelationId::new(), future).await
+}
+
+/// The id of the update or job being handled, None outside of [`scope`].
+pub fn current() -> Option<CorrelationId> {
+    CURRENT.try_with(|id| *id).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn id_is_visible_only_inside_the_scope() {
+        assert_eq!(current(), None);
+
+        let inside = scope(async { current() }).await;
+
+        assert!(inside.is_some());
+        assert_eq!(inside.unwrap().to_string().len(), 8);
+        assert_eq!(current(), None);
+    }
+}
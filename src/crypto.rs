@@ -0,0 +1,138 @@
+use std::fmt;
+
+use aes_gcm::{aead::{Aead, AeadCore, KeyInit, OsRng}, Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Marks values written by this module, so plaintext rows can be told apart.
+pub const PREFIX: &str = "enc:v1:";
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub enum DecryptError {
+    Encoding,
+    Truncated,
+    Tampered
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecryptError::Encoding => write!(f, "the stored phone number is not valid base64"),
+            DecryptError::Truncated => write!(f, "the stored phone number is shorter than its nonce"),
+            DecryptError::Tampered => write!(f, "the stored phone number does not decrypt with this key")
+        }
+    }
+}
+
+/// Encrypts phone numbers at rest with AES-256-GCM.
+#[derive(Clone)]
+pub struct PhoneCipher {
+    cipher: Aes256Gcm,
+    // Derived from the encryption key, so the index changes together with it.
+    index_key: Vec<u8>
+}
+
+impl PhoneCipher {
+    /// Reads the base64-encoded 32-byte key from `PHONE_ENCRYPTION_KEY`.
+    pub fn from_env() -> PhoneCipher {
+        let key = std::env::var("PHONE_ENCRYPTION_KEY").expect("ERROR: Could not get PHONE_ENCRYPTION_KEY");
+
+        PhoneCipher::new(&STANDARD.decode(key.trim()).expect("ERROR: PHONE_ENCRYPTION_KEY is not valid base64"))
+    }
+
+    fn new(key: &[u8]) -> PhoneCipher {
+        assert_eq!(key.len(), 32, "ERROR: PHONE_ENCRYPTION_KEY must be 32 bytes long");
+
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(b"phone-index");
+
+        PhoneCipher {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            index_key: mac.finalize().into_bytes().to_vec()
+        }
+    }
+
+    /// A keyed hash of a normalized phone number, equal phones get equal hashes so they can be looked up by an index.
+    pub fn blind_index(&self, phone_key: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.index_key).expect("HMAC accepts keys of any length");
+        mac.update(phone_key.as_bytes());
+
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_bytes())
+            .expect("ERROR: Could not encrypt a phone number");
+
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+
+        format!("{}{}", PREFIX, STANDARD.encode(payload))
+    }
+
+    /// Decrypts a stored value; rows not migrated yet are returned as is.
+    pub fn decrypt(&self, stored: &str) -> Result<String, DecryptError> {
+        let encoded = match stored.strip_prefix(PREFIX) {
+            Some(encoded) => encoded,
+            None => return Ok(stored.to_string())
+        };
+
+        let payload = STANDARD.decode(encoded).map_err(|_| DecryptError::Encoding)?;
+
+        if payload.len() < NONCE_LENGTH {
+            return Err(DecryptError::Truncated);
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| DecryptError::Tampered)?;
+
+        String::from_utf8(plaintext).map_err(|_| DecryptError::Tampered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> PhoneCipher {
+        PhoneCipher::new(&[7; 32])
+    }
+
+    #[test]
+    fn encrypted_phone_round_trips() {
+        let stored = cipher().encrypt("996700123456");
+
+        assert!(stored.starts_with(PREFIX));
+        assert!(!stored.contains("996700123456"));
+        assert_eq!(cipher().decrypt(&stored), Ok("996700123456".to_string()));
+    }
+
+    #[test]
+    fn same_phone_gets_different_ciphertexts() {
+        assert_ne!(cipher().encrypt("996700123456"), cipher().encrypt("996700123456"));
+    }
+
+    #[test]
+    fn blind_index_is_stable_per_key() {
+        assert_eq!(cipher().blind_index("700123456"), cipher().blind_index("700123456"));
+        assert_ne!(cipher().blind_index("700123456"), cipher().blind_index("700123457"));
+        assert_ne!(cipher().blind_index("700123456"), PhoneCipher::new(&[8; 32]).blind_index("700123456"));
+    }
+
+    #[test]
+    fn plaintext_rows_are_passed_through() {
+        assert_eq!(cipher().decrypt("996700123456"), Ok("996700123456".to_string()));
+    }
+
+    #[test]
+    fn corrupted_values_are_errors() {
+        assert_eq!(cipher().decrypt("enc:v1:???"), Err(DecryptError::Encoding));
+        assert_eq!(cipher().decrypt("enc:v1:AAAA"), Err(DecryptError::Truncated));
+        assert_eq!(cipher().decrypt(&PhoneCipher::new(&[8; 32]).encrypt("996700123456")), Err(DecryptError::Tampered));
+    }
+}
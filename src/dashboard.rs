@@ -0,0 +1,93 @@
+use axum::{extract::State, http::{HeaderMap, StatusCode}, response::Html, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::{database::Db, server};
+
+// The page holds no data, it asks for the token and reads the API below with it.
+const PAGE: &str = include_str!("../assets/dashboard.html");
+
+const RECENT_REGISTRATIONS: i64 = 20;
+
+#[derive(Clone)]
+struct DashboardState {
+    db: Db,
+    token: String
+}
+
+impl DashboardState {
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        match server::authorized(headers, std::slice::from_ref(&self.token)) {
+            true => Ok(()),
+            false => {
+                log::warn!("Dashboard: rejected a request without a valid token");
+
+                Err(StatusCode::UNAUTHORIZED)
+            }
+        }
+    }
+}
+
+/// Managers' view of the bot, mounted only when DASHBOARD_TOKEN is set.
+pub fn router(db: Db, token: String) -> Router {
+    Router::new()
+        .route("/dashboard", get(page))
+        .route("/dashboard/api/stats", get(stats))
+        .route("/dashboard/api/registrations", get(registrations))
+        .route("/dashboard/api/tickets", get(tickets))
+        .with_state(DashboardState { db, token })
+}
+
+async fn page() -> Html<&'static str> {
+    Html(PAGE)
+}
+
+async fn stats(State(state): State<DashboardState>, headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+    state.authorize(&headers)?;
+
+    let db = &state.db;
+
+    Ok(Json(json!({
+        "reachable": db.count_reachable().await,
+        "unreachable": db.count_unreachable().await,
+        "open_tickets": db.get_open_tickets().await.len(),
+        "pending_registrations": db.get_registration_requests().await.len(),
+        "open_purchases": db.get_open_purchase_requests().await.len()
+    })))
+}
+
+/// The latest clients and the registrations waiting for /approve, without phone numbers.
+async fn registrations(State(state): State<DashboardState>, headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+    state.authorize(&headers)?;
+
+    let recent = state.db.get_recent_registrations(RECENT_REGISTRATIONS).await;
+    let pending = state.db.get_registration_requests().await;
+
+    Ok(Json(json!({
+        "recent": recent.iter().map(|user| json!({
+            "client_code": user.client_code,
+            "first_name": user.first_name,
+            "last_name": user.last_name,
+            "username": user.username,
+            "created_at": user.created_at.format("%d.%m.%Y %H:%M").to_string()
+        })).collect::<Vec<Value>>(),
+        "pending": pending.iter().map(|request| json!({
+            "telegram_id": request.telegram_id,
+            "first_name": request.first_name,
+            "last_name": request.last_name,
+            "username": request.username
+        })).collect::<Vec<Value>>()
+    })))
+}
+
+async fn tickets(State(state): State<DashboardState>, headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+    state.authorize(&headers)?;
+
+    let tickets = state.db.get_open_tickets().await;
+
+    Ok(Json(json!(tickets.iter().map(|ticket| json!({
+        "id": ticket.id,
+        "client_code": ticket.client_code,
+        "text": ticket.text,
+        "created_at": ticket.created_at.format("%d.%m.%Y %H:%M").to_string()
+    })).collect::<Vec<Value>>())))
+}
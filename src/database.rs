@@ -1,64 +1,674 @@
-use sqlx::postgres::PgConnectOptions;
-use sqlx::{query_as, query_scalar, PgPool};
+use std::sync::Mutex;
 
-use sqlx::query;
-use crate::models::User;
+use chrono::NaiveDate;
+
+use crate::attachments::Attachment;
+use crate::calibration::CalibrationPolicy;
+use crate::catalog::Item;
+use crate::events::{DomainEvent, EventBus};
+use crate::money::Money;
+use crate::retention::RetentionPolicy;
+use crate::segment::Segment;
+use crate::sms::SmsLimits;
+use crate::vendor::Source;
+use self::memory::MemoryStore;
+use self::postgres::PgStore;
+use crate::models::{AccountClaim, ActiveTrackCode, Address, Batch, BatchEvent, BatchStatus, BoxPreset, Calibration, CannedResponse, CannedUsage, Cohort, CohortActivity, Courier, CourierDelivery, DeliveryClosing, DeliveryTask, Discrepancy, ErrorReport, EventKind, ExperimentResult, IdleUser, Invoice, ManifestRow, MonthlyReceipt, MonthlySpending, NewCity, Notice, OnboardingUser, OutboxMessage, OverrideTotals, Parcel, ParcelItem, PendingParcel, Permission, PriceOverride, PurchaseRequest, PurchaseStatus, Quote, QuoteAccuracy, RefundStatus, Registration, ReconciliationRun, RegistrationRequest, RetentionRun, Shipment, StaffNote, Subject, Survey, SurveyAnswer, Tariff, Ticket, Tutorial, Units, User, WarehouseDay, WarehouseLabel, WebhookClaim};
+
+mod memory;
+mod postgres;
+
+const TARIFF_VERSION_KEY: &str = "tariff_version";
 
 #[derive(Clone)]
 pub struct Db {
-    pool: PgPool
+    store: Backend,
+    events: EventBus
+}
+
+/// Where the data is kept, Postgres unless the bot runs with `--no-db`.
+#[derive(Clone)]
+enum Backend {
+    Postgres(Box<PgStore>),
+    Memory(MemoryStore)
 }
 
 impl Db {
     pub async fn new() -> Db {
-        let pg_user = std::env::var("POSTGRES_USER").expect("ERROR: Could not get POSTGRES_USER");
-        let pg_password = std::env::var("POSTGRES_PASSWORD").expect("ERROR: Could not get POSTGRES_PASSWORD");
-        let pg_host = std::env::var("POSTGRES_HOST").expect("ERROR: Could not get POSTGRES_HOST");
-        let pg_port = std::env::var("POSTGRES_PORT").expect("ERROR: Could not get POSTGRES_PORT");
-        let pg_db = std::env::var("POSTGRES_DB").expect("ERROR: Could not get POSTGRES_DB");
-
-        let opt = PgConnectOptions::new()
-            .host(&pg_host)
-            .port(pg_port.parse().expect("ERROR: Could not parse port string into u16"))
-            .database(&pg_db)
-            .username(&pg_user)
-            .password(&pg_password);
-
-        Db {
-            pool: PgPool::connect_with(opt).await.expect("ERROR: Could not connect the database")
+        Db { store: Backend::Postgres(Box::new(PgStore::connect().await)), events: EventBus::new() }
+    }
+
+    /// Keeps everything in memory instead of Postgres, for running the bot with only a bot token.
+    pub fn in_memory() -> Db {
+        log::warn!("Running without a database, all data is lost on restart");
+
+        Db { store: Backend::Memory(MemoryStore::default()), events: EventBus::new() }
+    }
+
+    /// Where the changes below are announced once committed.
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Returns the client code the new user got.
+    pub async fn create_user(&self, new_user: User) -> String {
+        let telegram_id = new_user.telegram_id;
+        let client_code = self.insert_user(new_user).await;
+
+        self.events.publish(DomainEvent::UserRegistered { telegram_id, client_code: client_code.clone() });
+
+        client_code
+    }
+
+    /// Registers a client unless an active user has the same phone, for sign-ups without Telegram.
+    ///
+    /// Returns the client code and whether it is new, a repeated sign-up gets the code it got the first time.
+    pub async fn get_or_create_by_phone(&self, new_user: User) -> (String, bool) {
+        let telegram_id = new_user.telegram_id;
+        let (client_code, created) = self.upsert_by_phone(new_user).await;
+
+        if created {
+            self.events.publish(DomainEvent::UserRegistered { telegram_id, client_code: client_code.clone() });
         }
+
+        (client_code, created)
     }
 
-    pub async fn create_user(&self, mut new_user: User) {
-        let count: i64 = query_scalar!("SELECT COUNT(*) AS user_count FROM users;")
-            .fetch_all(&self.pool)
-            .await.expect("ERROR: Could not get user count")[0].unwrap();
+    /// Registers `profile` under `telegram_id` unless an active user has it already, in one statement.
+    ///
+    /// Returns the stored user and whether it was created now, two registrations at once end with one user.
+    pub async fn get_or_create(&self, telegram_id: i64, mut profile: User) -> (User, bool) {
+        profile.telegram_id = Some(telegram_id);
+
+        let (user, created) = self.upsert_user(profile).await;
+
+        if created {
+            self.events.publish(DomainEvent::UserRegistered { telegram_id: Some(telegram_id), client_code: user.client_code.clone() });
+        }
 
-        let client_code: String = "MX".to_string() + &(200 + count).to_string();
+        (user, created)
+    }
 
-        new_user.client_code = client_code;
+    pub async fn mark_parcel_arrived(&self, parcel_id: i32, notice: &Notice) {
+        self.update_parcel_arrived(parcel_id, notice).await;
 
-        query("INSERT INTO users (first_name, last_name, phone_number, telegram_id, client_code)
-            VALUES ($1, $2, $3, $4, $5);")
-            .bind(new_user.first_name)
-            .bind(new_user.last_name)
-            .bind(new_user.phone_number)
-            .bind(new_user.telegram_id)
-            .bind(new_user.client_code)
-            .fetch_all(&self.pool)
-            .await.expect("ERROR: Could not create a user");
+        self.events.publish(DomainEvent::ParcelArrived { parcel_id, telegram_id: notice.telegram_id });
     }
 
-    pub async fn get_user(&self, telegram_id: i64) -> User {
-        query_as::<_, User>("SELECT * FROM users WHERE telegram_id = $1;")
-            .bind(telegram_id)
-            .fetch_all(&self.pool)
-            .await.expect("ERROR: Could not get user")[0].clone()
+    /// Marks every saved parcel with this track code as arrived and returns the ones that changed.
+    /// Returns the number of parcels that have just arrived.
+    pub async fn mark_track_code_arrived(&self, track_code: &str, notice: impl Fn(&PendingParcel) -> Notice) -> usize {
+        let arrived = Mutex::new(Vec::new());
+
+        let count = self.update_track_code_arrived(track_code, |parcel| {
+            arrived.lock().unwrap().push(DomainEvent::ParcelArrived { parcel_id: parcel.id, telegram_id: parcel.telegram_id });
+            notice(parcel)
+        }).await;
+
+        arrived.into_inner().unwrap().into_iter().for_each(|event| self.events.publish(event));
+
+        count
     }
 
-    pub async fn check_user(&self, telegram_id: i64) -> bool {
-        query_scalar!("SELECT EXISTS (SELECT 1 FROM users WHERE telegram_id = $1);", telegram_id)
-            .fetch_all(&self.pool)
-            .await.expect("ERROR: Could check the user")[0].expect("ERROR: Could not check the user")
+    pub async fn get_tariff_version(&self) -> i32 {
+        self.get_setting(TARIFF_VERSION_KEY).await
+            .and_then(|version| version.parse().ok())
+            .unwrap_or(0)
     }
-}
\ No newline at end of file
+
+    /// Bills every client who saved this track code and sends them the invoice, returns how many were billed.
+    ///
+    /// A bill far from the quote the client attached is kept as a calibration record in the same transaction.
+    pub async fn set_invoice(&self, track_code: &str, amount: Money, calibration: &CalibrationPolicy, notice: impl Fn(&Invoice) -> Notice) -> usize {
+        let billed = Mutex::new(Vec::new());
+
+        let count = self.update_invoices(track_code, amount, calibration, |invoice| {
+            billed.lock().unwrap().push(DomainEvent::ShipmentBilled { parcel_id: invoice.parcel_id, telegram_id: invoice.telegram_id, amount: invoice.amount });
+            notice(invoice)
+        }).await;
+
+        billed.into_inner().unwrap().into_iter().for_each(|event| self.events.publish(event));
+
+        count
+    }
+}
+
+/// Declares the [`Store`] methods and a method of [`Db`] for each that calls the store it runs on.
+///
+/// Both stores implement the whole trait, so a method the memory store lacks does not compile.
+macro_rules! store {
+    ($($(#[$doc:meta])* $vis:vis async fn $name:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;)*) => {
+        /// What the bot keeps, in Postgres or in memory.
+        trait Store {
+            $(async fn $name(&self $(, $arg: $ty)*) $(-> $ret)?;)*
+        }
+
+        impl Db {
+            $(
+                $(#[$doc])*
+                $vis async fn $name(&self $(, $arg: $ty)*) $(-> $ret)? {
+                    match &self.store {
+                        Backend::Postgres(store) => store.$name($($arg),*).await,
+                        Backend::Memory(store) => store.$name($($arg),*).await
+                    }
+                }
+            )*
+        }
+    };
+}
+
+store! {
+    /// Whether the database answers, for the watchdog. Unlike other methods it does not panic.
+    pub async fn ping(&self) -> Result<(), sqlx::Error>;
+
+    async fn insert_user(&self, new_user: User) -> String;
+
+    async fn upsert_by_phone(&self, new_user: User) -> (String, bool);
+
+    async fn upsert_user(&self, profile: User) -> (User, bool);
+
+    pub async fn find_user(&self, telegram_id: i64) -> Option<User>;
+
+    pub async fn get_user(&self, telegram_id: i64) -> User;
+
+    pub async fn find_user_by_client_code(&self, client_code: &str) -> Option<User>;
+
+    /// Which of these client codes belong to active users.
+    pub async fn existing_client_codes(&self, client_codes: &[String]) -> Vec<String>;
+
+    /// Keeps the Telegram @username and display name of a registered user up to date.
+    /// Also clears the unreachable mark, since a user who sends updates has unblocked the bot.
+    pub async fn update_telegram_profile(&self, telegram_id: i64, username: Option<&str>, display_name: &str);
+
+    /// Marks a user who blocked the bot, their notifications wait until they come back.
+    pub async fn mark_unreachable(&self, telegram_id: i64);
+
+    /// Clears the unreachable mark of a user who unblocked the bot.
+    pub async fn mark_reachable(&self, telegram_id: i64);
+
+    pub async fn count_reachable(&self) -> i64;
+
+    pub async fn count_unreachable(&self) -> i64;
+
+    pub async fn set_text_menu(&self, telegram_id: i64, text_menu: bool);
+
+    pub async fn set_reminders(&self, telegram_id: i64, reminders: bool);
+
+    pub async fn set_monthly_summary(&self, telegram_id: i64, monthly_summary: bool);
+
+    pub async fn set_units(&self, telegram_id: i64, units: Units);
+
+    pub async fn set_city(&self, telegram_id: i64, city: &str);
+
+    /// Looks users up by @username, client code or name.
+    pub async fn find_users(&self, search: &str, limit: i64) -> Vec<User>;
+
+    /// Every active user with the phone number decrypted, for duplicate detection.
+    pub async fn get_users(&self) -> Vec<User>;
+
+    /// Moves parcels, addresses and purchase requests of `duplicate` to `survivor` and soft-deletes `duplicate`,
+    /// returns the moved parcel count.
+    ///
+    /// Parcels both accounts saved stay with the survivor only. When the duplicate's copy has payments, a refund,
+    /// a dispute or a delivery, nothing is merged and the track codes of those parcels come back instead.
+    pub async fn merge_users(&self, survivor: &User, duplicate: &User) -> Result<u64, Vec<String>>;
+
+    /// The active user with this phone other than `telegram_id`, found by the phone hash.
+    pub async fn find_phone_owner(&self, telegram_id: Option<i64>, phone_number: &str) -> Option<User>;
+
+    /// Records a verification code about to go out, false when the phone or the user is over `limits`.
+    pub async fn reserve_sms(&self, telegram_id: i64, phone_number: &str, limits: &SmsLimits) -> bool;
+
+    /// Tutorials with a text, in menu order.
+    pub async fn get_tutorials(&self) -> Vec<Tutorial>;
+
+    pub async fn get_tutorial(&self, slug: &str) -> Option<Tutorial>;
+
+    /// Adds a tutorial to the end of the menu or replaces the one with the same slug, its links stay.
+    pub async fn save_tutorial(&self, tutorial: &Tutorial);
+
+    /// Sets the app and website buttons of a tutorial, `None` removes one. False when there is no such tutorial.
+    pub async fn set_tutorial_links(&self, slug: &str, app_url: Option<&str>, web_url: Option<&str>) -> bool;
+
+    pub async fn delete_tutorial(&self, slug: &str) -> bool;
+
+    pub async fn check_user(&self, telegram_id: i64) -> bool;
+
+    pub async fn get_segment_ids(&self, segment: &Segment) -> Vec<i64>;
+
+    pub async fn count_segment(&self, segment: &Segment) -> i64;
+
+    /// Keeps a broadcast until an admin confirms it, returns its id.
+    pub async fn create_broadcast(&self, filters: &str, text: &str) -> i32;
+
+    /// Marks a broadcast sent and returns its filters and text, None if it was already sent.
+    pub async fn take_broadcast(&self, id: i32) -> Option<(String, String)>;
+
+    pub async fn create_survey(&self, title: &str, created_by: i64) -> i32;
+
+    pub async fn get_survey(&self, id: i32) -> Option<Survey>;
+
+    /// Adds a question after the last one, returns its position.
+    pub async fn add_survey_question(&self, survey_id: i32, text: &str, options: &[String]) -> i16;
+
+    /// Marks the survey sent and puts its first question into the outbox of every recipient.
+    ///
+    /// Returns the number of recipients, None if the survey was already sent.
+    pub async fn send_survey(&self, survey_id: i32, telegram_ids: &[i64], notice: impl Fn(i64) -> Notice) -> Option<usize>;
+
+    /// Whether the survey was sent to this client, nobody else may answer it.
+    pub async fn is_survey_recipient(&self, survey_id: i32, telegram_id: i64) -> bool;
+
+    /// Saves the answer, false when the client has already answered this question or did not get the survey.
+    pub async fn save_survey_answer(&self, survey_id: i32, position: i16, telegram_id: i64, option: Option<i16>, text: Option<&str>) -> bool;
+
+    pub async fn get_survey_answers(&self, survey_id: i32) -> Vec<SurveyAnswer>;
+
+    pub async fn save_parcel(&self, telegram_id: i64, track_code: &str, label: Option<String>);
+
+    /// Links a price quote to a parcel, saving the parcel when the user has not saved it yet.
+    pub async fn attach_quote(&self, telegram_id: i64, track_code: &str, quote_id: i32);
+
+    /// Stores what the marketplace page of a parcel's item says.
+    pub async fn save_parcel_item(&self, telegram_id: i64, track_code: &str, item: &Item);
+
+    /// Items clients saved under a track code.
+    pub async fn get_parcel_items(&self, track_code: &str) -> Vec<ParcelItem>;
+
+    pub async fn get_parcels(&self, telegram_id: i64) -> Vec<Parcel>;
+
+    pub async fn get_pending_parcels(&self) -> Vec<PendingParcel>;
+
+    async fn update_parcel_arrived(&self, parcel_id: i32, notice: &Notice);
+
+    /// Attaches a warehouse photo to saved parcels with this track code, returns how many were found.
+    ///
+    /// Owners of parcels that have already arrived get the photo right away, the others with the arrival notice.
+    pub async fn set_parcel_photo(&self, track_code: &str, photo_id: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64;
+
+    /// Puts saved parcels with this track code into an outbound batch and sends their owners the receipt.
+    ///
+    /// Returns the number of parcels assigned.
+    pub async fn assign_parcel(&self, track_code: &str, batch_code: &str, weight_kg: f32, declared_value: Money, description: Option<&str>, receipt: impl Fn(i32, i64) -> Notice) -> usize;
+
+    pub async fn get_shipment(&self, telegram_id: i64, track_code: &str) -> Option<Shipment>;
+
+    /// Shipping details of every parcel of a user, for the export of their data.
+    pub async fn get_shipments(&self, telegram_id: i64) -> Vec<Shipment>;
+
+    /// Opens a ticket disputing the billed weight of a parcel, returns the ticket id.
+    pub async fn open_weight_dispute(&self, telegram_id: i64, track_code: &str, text: &str, attachments: &[Attachment]) -> i32;
+
+    /// Puts scanned parcels into a batch, returns client codes of their owners.
+    pub async fn scan_parcel(&self, track_code: &str, batch_code: &str) -> Vec<String>;
+
+    pub async fn get_manifest(&self, batch_code: &str) -> Vec<ManifestRow>;
+
+    /// Labels of the parcels saved under a track code.
+    pub async fn get_warehouse_labels(&self, track_code: &str) -> Vec<WarehouseLabel>;
+
+    /// Puts the parcels with this track code on a shelf, their owners are told where to pick them up.
+    pub async fn shelve_parcel(&self, track_code: &str, shelf: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64;
+
+    /// Parcels with this track code that are not handed over yet, for /courier.
+    pub async fn get_undelivered_parcels(&self, track_code: &str) -> Vec<PendingParcel>;
+
+    pub async fn get_addresses(&self, telegram_id: i64) -> Vec<Address>;
+
+    /// Adds an address unless the user has [`MAX_ADDRESSES`] already, false then.
+    pub async fn add_address(&self, telegram_id: i64, city: &str, street: &str, entrance: Option<&str>, comment: Option<&str>) -> bool;
+
+    pub async fn delete_address(&self, telegram_id: i64, address_id: i32) -> bool;
+
+    /// Puts the parcel into the courier queue with one of its owner's addresses and tells the owner.
+    ///
+    /// None when the address is not the owner's, the parcel has not arrived, is handed over, already with a courier
+    /// or, with `require_payment`, not paid in full.
+    pub async fn queue_delivery(&self, parcel_id: i32, address_id: i32, require_payment: bool, notice: impl Fn(&CourierDelivery) -> Notice) -> Option<CourierDelivery>;
+
+    pub async fn get_couriers(&self) -> Vec<Courier>;
+
+    /// Adds a courier or renames one.
+    pub async fn add_courier(&self, telegram_id: i64, name: &str, added_by: i64);
+
+    /// Removes a courier, the parcels they still had go back to the queue.
+    pub async fn remove_courier(&self, telegram_id: i64) -> bool;
+
+    pub async fn is_courier(&self, telegram_id: i64) -> bool;
+
+    /// Deliveries that are not done, of one courier or of everybody.
+    pub async fn get_open_deliveries(&self, courier_id: Option<i64>) -> Vec<DeliveryTask>;
+
+    /// Gives a delivery that is not done to a courier, another courier's one is handed over.
+    pub async fn assign_delivery(&self, delivery_id: i32, courier_id: i64) -> Option<CourierDelivery>;
+
+    /// Closes a delivery of this courier, hands the parcel over and tells its owner.
+    ///
+    /// With `require_payment` an unpaid parcel is not handed over, the delivery stays open.
+    pub async fn complete_delivery(&self, delivery_id: i32, courier_id: i64, photo_id: Option<&str>, require_payment: bool, notice: impl Fn(&CourierDelivery) -> Notice) -> DeliveryClosing;
+
+    /// Parcels waiting on a shelf to be picked up.
+    pub async fn get_shelf(&self, shelf: &str) -> Vec<WarehouseLabel>;
+
+    /// Every shelf with the number of parcels waiting on it.
+    pub async fn get_shelves(&self) -> Vec<(String, i64)>;
+
+    pub async fn get_setting(&self, key: &str) -> Option<String>;
+
+    pub async fn set_setting(&self, key: &str, value: &str);
+
+    pub async fn delete_setting(&self, key: &str);
+
+    async fn update_track_code_arrived(&self, track_code: &str, notice: impl Fn(&PendingParcel) -> Notice) -> usize;
+
+    /// Puts a purchase request into the operators' queue, returns its number.
+    pub async fn create_purchase_request(&self, telegram_id: i64, marketplace: &str, link: &str, options: Option<&str>, budget: Money) -> i32;
+
+    /// New and quoted requests, grouped by marketplace and oldest first within each.
+    pub async fn get_open_purchase_requests(&self) -> Vec<PurchaseRequest>;
+
+    pub async fn get_purchase_request(&self, id: i32) -> Option<PurchaseRequest>;
+
+    /// Moves an open request on and tells the client; None when it is already purchased or declined.
+    ///
+    /// `quote` and `comment` are kept from before when not given.
+    pub async fn update_purchase_request(&self, id: i32, status: PurchaseStatus, quote: Option<Money>, comment: Option<&str>, notice: impl FnOnce(&PurchaseRequest) -> Notice) -> Option<PurchaseRequest>;
+
+    /// Enabled tracking services in the order they are tried.
+    pub async fn get_providers(&self) -> Vec<Source>;
+
+    /// Keeps a warehouse answer that could not be read, with the error it gave.
+    pub async fn record_vendor_error(&self, source: &str, track_code: &str, error: &str, body: &str);
+
+    /// Users with no parcels and no activity for `idle_days`, who allow reminders and got none for `cooldown_days`.
+    pub async fn get_idle_users(&self, idle_days: i32, cooldown_days: i32, limit: i64) -> Vec<IdleUser>;
+
+    /// Writes the reminder to the outbox and remembers when it was sent, in one transaction.
+    pub async fn reengage(&self, notice: &Notice);
+
+    /// Users registered in the last `window_days`, with the onboarding steps they did and when the checklist last went.
+    pub async fn get_onboarding_users(&self, window_days: i32) -> Vec<OnboardingUser>;
+
+    /// Writes the checklist to the outbox and records that it was sent, in one transaction.
+    pub async fn send_onboarding(&self, notice: &Notice);
+
+    /// Opted-in clients with parcels delivered in `month` whose summary for it has not been sent.
+    ///
+    /// Payments count by the day they were made, months follow Bishkek time.
+    pub async fn get_monthly_spending(&self, month: NaiveDate, limit: i64) -> Vec<MonthlySpending>;
+
+    /// Writes the summary to the outbox unless one for this month was written already, in one transaction.
+    pub async fn send_monthly_summary(&self, month: NaiveDate, notice: &Notice);
+
+    /// Parcels of the client delivered in `month`, in the order they were handed over.
+    pub async fn get_monthly_receipts(&self, telegram_id: i64, month: NaiveDate) -> Vec<MonthlyReceipt>;
+
+    /// Undelivered messages in the order they were written.
+    ///
+    /// Messages that failed too often are skipped, as well as those to users who blocked the bot.
+    /// Digest messages of a user wait until none has been written for `window`, so they go out together.
+    pub async fn get_outbox(&self, limit: i64, max_attempts: i32, window: std::time::Duration) -> Vec<OutboxMessage>;
+
+    /// `message_id` is the message in the client's chat, reactions to it are matched back to the notice.
+    pub async fn mark_outbox_sent(&self, id: i64, message_id: i32);
+
+    /// What the notices that became this message were about, a digest may cover several parcels.
+    pub async fn get_reacted_subjects(&self, telegram_id: i64, message_id: i32) -> Vec<Subject>;
+
+    /// The client saw the arrival notice, only the first acknowledgement is kept.
+    pub async fn acknowledge_arrival(&self, telegram_id: i64, track_code: &str);
+
+    /// A later rating of the same parcel replaces the earlier one, false when the user does not own the parcel.
+    pub async fn save_rating(&self, telegram_id: i64, track_code: &str, stars: i16) -> bool;
+
+    pub async fn mark_outbox_failed(&self, id: i64);
+
+    pub async fn get_partner_secret(&self, partner_id: &str) -> Option<String>;
+
+    /// Claims the idempotency key for this post unless an earlier post with it got there first.
+    ///
+    /// A claim left unanswered for a minute, a post that crashed halfway, may be taken over.
+    pub async fn claim_webhook_key(&self, partner_id: &str, key: &str) -> WebhookClaim;
+
+    /// Stores the answer given to the post that claimed the key.
+    pub async fn finish_webhook_key(&self, partner_id: &str, key: &str, status: u16);
+
+    pub async fn create_ticket(&self, telegram_id: i64, text: &str, attachments: &[Attachment]) -> i32;
+
+    pub async fn get_attachments(&self, ticket_id: i32) -> Vec<Attachment>;
+
+    /// Opens a ticket and a refund for a saved parcel in one go, returns the ticket id.
+    ///
+    /// Returns None when a refund for this parcel was already requested.
+    pub async fn request_refund(&self, telegram_id: i64, track_code: &str, reason: &str, text: &str) -> Option<i32>;
+
+    /// Moves the refund opened by this ticket on, returns the client's telegram id and track code.
+    /// Returns false when there is no refund for this ticket.
+    pub async fn set_refund_status(&self, ticket_id: i32, status: RefundStatus, notice: impl FnOnce(i64, &str) -> Notice) -> bool;
+
+    pub async fn get_open_tickets(&self) -> Vec<Ticket>;
+
+    /// Every ticket of a user, open or closed.
+    pub async fn get_user_tickets(&self, telegram_id: i64) -> Vec<Ticket>;
+
+    /// Returns false when there is no open ticket with this id.
+    pub async fn close_ticket(&self, ticket_id: i32) -> bool;
+
+    pub async fn record_event(&self, telegram_id: i64, kind: EventKind);
+
+    /// Writes a step of a destructive admin action: requested, confirmed, wrong_word or expired.
+    pub async fn record_audit(&self, admin_id: i64, admin: &str, action: &str, outcome: &str);
+
+    pub async fn get_cohorts(&self, weeks: i32) -> Vec<Cohort>;
+
+    pub async fn get_cohort_activity(&self, weeks: i32) -> Vec<CohortActivity>;
+
+    /// Registration completion of users who saw each welcome variant.
+    pub async fn get_welcome_results(&self) -> Vec<ExperimentResult>;
+
+    pub async fn get_dialogue(&self, chat_id: i64) -> Option<String>;
+
+    pub async fn save_dialogue(&self, chat_id: i64, state: &str);
+
+    pub async fn remove_dialogue(&self, chat_id: i64);
+
+    /// Keeps the track code of a deep link until the user has registered, a newer link replaces it.
+    pub async fn save_start_track_code(&self, telegram_id: i64, track_code: &str);
+
+    pub async fn take_start_track_code(&self, telegram_id: i64) -> Option<String>;
+
+    pub async fn add_staff_note(&self, author_id: i64, author: &str, text: &str);
+
+    pub async fn get_staff_notes(&self, hours: i32) -> Vec<StaffNote>;
+
+    /// Weight disputes with an open ticket, as (ticket id, track code).
+    pub async fn get_pending_disputes(&self) -> Vec<(i32, String)>;
+
+    /// Parcels at the warehouse not put into a batch yet, as (track code, client code).
+    pub async fn get_unassigned_parcels(&self) -> Vec<(String, String)>;
+
+    /// Saves a registration for manual approval, replacing an earlier request of the same user.
+    pub async fn request_registration(&self, user: &User);
+
+    /// The latest clients to get a code, newest first.
+    pub async fn get_recent_registrations(&self, limit: i64) -> Vec<Registration>;
+
+    pub async fn get_registration_requests(&self) -> Vec<RegistrationRequest>;
+
+    /// Marks a pending request decided, so approving it twice does not register the user twice.
+    pub async fn take_registration_request(&self, telegram_id: i64) -> Option<RegistrationRequest>;
+
+    /// Asks an operator to move `user_id` to the Telegram account of `profile`, a repeated claim replaces the pending one.
+    pub async fn request_account_claim(&self, user_id: i32, profile: &User) -> AccountClaim;
+
+    /// Claims waiting for an operator, oldest first.
+    pub async fn get_account_claims(&self) -> Vec<AccountClaim>;
+
+    /// Approves a pending claim and moves the account, None when it is decided already or the move is no longer possible.
+    pub async fn approve_account_claim(&self, id: i32, operator_id: i64, notice: impl FnOnce(i64, &AccountClaim) -> Notice) -> Option<AccountClaim>;
+
+    pub async fn reject_account_claim(&self, id: i32, operator_id: i64) -> Option<AccountClaim>;
+
+    pub async fn save_canned_response(&self, slug: &str, text: &str);
+
+    pub async fn get_canned_responses(&self) -> Vec<CannedResponse>;
+
+    pub async fn get_canned_response(&self, slug: &str) -> Option<String>;
+
+    pub async fn record_canned_use(&self, slug: &str, ticket_id: i32, operator_id: i64, operator: &str);
+
+    /// Uses per template and operator, grouped by the operator id so a renamed operator is counted once.
+    pub async fn get_canned_usage(&self) -> Vec<CannedUsage>;
+
+    /// The client who opened a ticket.
+    pub async fn get_ticket_user(&self, ticket_id: i32) -> Option<User>;
+
+    pub async fn get_tariffs(&self) -> Vec<Tariff>;
+
+    /// Tariffs of the city the user picked, or the general tariffs when the city has none of its own.
+    pub async fn get_user_tariffs(&self, telegram_id: i64) -> Vec<Tariff>;
+
+    /// Adds a density band or replaces the one starting at the same density.
+    pub async fn save_tariff(&self, tariff: &Tariff);
+
+    pub async fn delete_tariff(&self, min_density: f32) -> bool;
+
+    /// Stores a quote the calculator gave, returns its id.
+    pub async fn save_quote(&self, quote: &Quote) -> i32;
+
+    /// The latest quotes of a user, newest first.
+    pub async fn get_quotes(&self, telegram_id: i64, limit: i64) -> Vec<Quote>;
+
+    pub async fn get_quote(&self, id: i32) -> Option<Quote>;
+
+    /// Billed parcels with a priced quote from the last `days`, per tariff version.
+    pub async fn get_quote_accuracy(&self, days: i32) -> Vec<QuoteAccuracy>;
+
+    /// The latest bills over the calibration threshold, newest first.
+    pub async fn get_calibrations(&self, limit: i64) -> Vec<Calibration>;
+
+    /// Remembers the sides of a quoted box, false when the same box is saved already.
+    ///
+    /// Only the latest `limit` presets of a user are kept.
+    pub async fn save_box_preset(&self, telegram_id: i64, width: f32, length: f32, height: f32, limit: i64) -> bool;
+
+    /// The saved boxes of a user, newest first.
+    pub async fn get_box_presets(&self, telegram_id: i64) -> Vec<BoxPreset>;
+
+    /// A saved box, only when it belongs to the user.
+    pub async fn get_box_preset(&self, telegram_id: i64, id: i32) -> Option<BoxPreset>;
+
+    /// Marks the parcels of a batch handed over to clients, returns how many there were.
+    ///
+    /// With `require_payment` only the parcels paid in full are handed over, the track codes of the rest are returned.
+    pub async fn mark_batch_delivered(&self, batch_code: &str, require_payment: bool) -> (u64, Vec<String>);
+
+    /// Opens a batch for parcels to be assigned to, false when it exists already.
+    pub async fn create_batch(&self, code: &str) -> bool;
+
+    pub async fn get_batch(&self, code: &str) -> Option<Batch>;
+
+    /// Batches that have not arrived yet, oldest first.
+    pub async fn get_open_batches(&self) -> Vec<Batch>;
+
+    /// Batches with parcels of the user not handed over yet, oldest first.
+    pub async fn get_user_batches(&self, telegram_id: i64) -> Vec<Batch>;
+
+    /// The statuses a batch went through, in order.
+    pub async fn get_batch_events(&self, code: &str) -> Vec<BatchEvent>;
+
+    /// Moves a batch from `from` to `status` and tells every client with parcels in it, returns how many were told.
+    ///
+    /// None when the batch is no longer at `from`: someone else moved it since it was read, and nobody is told twice.
+    /// `notice` gets the telegram id of a client and the track codes of their parcels in the batch.
+    pub async fn advance_batch(&self, code: &str, from: BatchStatus, status: BatchStatus, notice: impl Fn(i64, &[String]) -> Notice) -> Option<usize>;
+
+    pub async fn has_permission(&self, telegram_id: i64, permission: Permission) -> bool;
+
+    /// Returns false when the admin already had it.
+    pub async fn grant_permission(&self, telegram_id: i64, permission: Permission, granted_by: i64) -> bool;
+
+    /// Returns false when the admin did not have it.
+    pub async fn revoke_permission(&self, telegram_id: i64, permission: Permission) -> bool;
+
+    pub async fn get_staff(&self) -> Vec<i64>;
+
+    /// Makes these users the first staff members, false when the staff table has members already.
+    pub async fn seed_staff(&self, telegram_ids: &[i64]) -> bool;
+
+    pub async fn create_staff_invite(&self, code: &str, created_by: i64, valid_hours: i32);
+
+    /// Uses up the invite and adds the user to staff, returns who invited them.
+    ///
+    /// None when the code is unknown, expired or redeemed already.
+    pub async fn redeem_staff_invite(&self, code: &str, telegram_id: i64) -> Option<i64>;
+
+    /// Removes the user from staff along with their permissions, false when they were not staff.
+    pub async fn remove_staff(&self, telegram_id: i64) -> bool;
+
+    /// Keeps a failed update for replaying it later, returns the report number.
+    pub async fn save_error_report(&self, report: &ErrorReport) -> i32;
+
+    /// Records who downloaded personal data and how much of it, returns the export number.
+    pub async fn log_export(&self, telegram_id: i64, kind: &str, row_count: usize) -> i32;
+
+    async fn update_invoices(&self, track_code: &str, amount: Money, calibration: &CalibrationPolicy, notice: impl Fn(&Invoice) -> Notice) -> usize;
+
+    pub async fn get_invoice(&self, parcel_id: i32) -> Option<Invoice>;
+
+    /// Invoices of every client who saved this track code.
+    pub async fn get_invoices(&self, track_code: &str) -> Vec<Invoice>;
+
+    /// Adds a payment to the invoice of a parcel and tells the client, returns the invoice after it.
+    ///
+    /// None when the parcel has no invoice, when Telegram reports a charge that is already recorded, or when a payment
+    /// recorded by hand is more than is due. A card charge Telegram already took is kept whatever the invoice says now.
+    pub async fn record_payment(&self, parcel_id: i32, amount: Money, charge_id: Option<&str>, recorded_by: Option<i64>, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice>;
+
+    /// Changes the billed price of a parcel and keeps what it was, the client gets the new receipt.
+    ///
+    /// None when the parcel has no invoice.
+    pub async fn override_price(&self, parcel_id: i32, change: &PriceOverride, operator_id: i64, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice>;
+
+    /// Overrides of the last `days` per operator, the busiest first.
+    pub async fn get_override_totals(&self, days: i32) -> Vec<OverrideTotals>;
+
+    /// Opens a city with its tariffs and the announcement draft all at once, returns the draft broadcast id.
+    ///
+    /// None when a city of this name is already open.
+    pub async fn open_city(&self, city: &NewCity) -> Option<i32>;
+
+    /// Open cities users can pick in the settings, as (id, name).
+    pub async fn get_cities(&self) -> Vec<(i32, String)>;
+
+    /// Pickup points of the open cities, as (city, pickup point).
+    pub async fn get_pickup_points(&self) -> Vec<(String, String)>;
+
+    /// Days in transit admins planned for the open cities, as (city, fewest, most).
+    pub async fn get_planned_etas(&self) -> Vec<(String, i32, i32)>;
+
+    /// Destination city and days from the warehouse to the client of parcels delivered lately.
+    pub async fn get_delivery_history(&self, days: i32) -> Vec<(Option<String>, i32)>;
+
+    /// Applies the retention policy in one transaction, a dry run only counts the rows.
+    pub async fn apply_retention(&self, policy: &RetentionPolicy, dry_run: bool) -> RetentionRun;
+
+    pub async fn get_retention_runs(&self, limit: i64) -> Vec<RetentionRun>;
+
+    /// Track codes of active users' parcels that are not delivered yet, with whether their saved copies have arrived
+    /// and whether one of them is in a batch that has left the warehouse.
+    pub async fn get_active_track_codes(&self) -> Vec<ActiveTrackCode>;
+
+    /// Records a reconciliation pass with what it found, in one transaction.
+    pub async fn save_reconciliation(&self, checked: i32, failed: i32, discrepancies: &[Discrepancy]);
+
+    pub async fn get_last_reconciliation(&self) -> Option<ReconciliationRun>;
+
+    pub async fn get_discrepancies(&self, run_id: i32) -> Vec<Discrepancy>;
+
+    /// Rebuilds the intake of the last `days` Bishkek days and today from parcels, returns how many days had any.
+    pub async fn refresh_warehouse_stats(&self, days: i32) -> u64;
+
+    /// Intake of the last `days` Bishkek days and today, oldest first.
+    pub async fn get_warehouse_stats(&self, days: i32) -> Vec<WarehouseDay>;
+}
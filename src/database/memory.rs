@@ -0,0 +1,2819 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, sync::{Arc, Mutex, MutexGuard}};
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+use crate::{attachments::Attachment, calibration::CalibrationPolicy, catalog::Item, client_code, duplicates, money::Money, retention::RetentionPolicy, models::{AccountClaim, ActiveTrackCode, Address, Batch, BatchEvent, BatchStatus, BoxPreset, Calibration, CannedResponse, CannedUsage, Cohort, CohortActivity, Courier, CourierDelivery, DeliveryClosing, DeliveryTask, Discrepancy, ErrorReport, EventKind, ExperimentResult, IdleUser, Invoice, ManifestRow, MonthlyReceipt, MonthlySpending, NewCity, Notice, OnboardingUser, OutboxMessage, OverrideTotals, Parcel, ParcelItem, PendingParcel, Permission, PriceOverride, PurchaseRequest, PurchaseStatus, Quote, QuoteAccuracy, ReconciliationRun, RefundStatus, Registration, RegistrationRequest, RetentionRun, Shipment, StaffNote, Subject, Survey, SurveyAnswer, SurveyQuestion, Tariff, Ticket, Tutorial, Units, User, WarehouseDay, WarehouseLabel, WebhookClaim, MAX_ADDRESSES}, segment::{Filter, Segment}, sms::SmsLimits, support::bishkek_now, vendor::Source};
+
+use super::Store;
+
+/// Tables of the `--no-db` mode, lost on restart.
+///
+/// Covers what a contributor clicks through with only a bot token: registration, parcels, tickets and
+/// the admin commands around them. Reports read the events table and stay empty.
+///
+/// Not kept here, their methods answer empty and drop writes: tracking providers and vendor errors, couriers and
+/// their deliveries, the delivery history behind ETAs, warehouse intake stats, quote accuracy, reminders,
+/// onboarding and monthly summaries, partner webhooks, the audit log, retention and reconciliation runs.
+#[derive(Default)]
+pub struct Memory {
+    users: Vec<User>,
+    registered_at: Vec<NaiveDateTime>,
+    deleted: HashSet<i32>,
+    blocked: HashSet<i64>,
+    last_active: HashMap<i64, NaiveDateTime>,
+    parcels: Vec<MemoryParcel>,
+    tickets: Vec<MemoryTicket>,
+    disputes: Vec<(i32, i32)>,
+    outbox: Vec<MemoryOutbox>,
+    settings: HashMap<String, String>,
+    dialogues: HashMap<i64, String>,
+    start_track_codes: HashMap<i64, String>,
+    addresses: Vec<(i64, Address)>,
+    tutorials: Vec<Tutorial>,
+    tariffs: Vec<Tariff>,
+    quotes: Vec<Quote>,
+    broadcasts: Vec<(String, String, bool)>,
+    staff_notes: Vec<StaffNote>,
+    registrations: Vec<(RegistrationRequest, bool)>,
+    claims: Vec<(AccountClaim, bool)>,
+    canned: BTreeMap<String, String>,
+    canned_uses: Vec<(String, i64, String)>,
+    shelves: BTreeSet<String>,
+    cities: Vec<NewCity>,
+    charges: HashSet<String>,
+    roles: HashSet<(i64, &'static str)>,
+    exports: Vec<(i64, String, usize)>,
+    error_reports: Vec<ErrorReport>,
+    purchases: Vec<(i32, PurchaseRequest)>,
+    box_presets: Vec<(i64, BoxPreset)>,
+    batches: Vec<MemoryBatch>,
+    batch_events: Vec<(String, BatchStatus, NaiveDateTime)>,
+    staff: Vec<i64>,
+    staff_invites: Vec<MemoryInvite>,
+    surveys: Vec<Survey>,
+    survey_answers: Vec<(i32, i64, SurveyAnswer)>,
+    survey_recipients: HashSet<(i32, i64)>,
+    ratings: HashMap<(i64, String), i16>,
+    overrides: Vec<(i64, PriceOverride, NaiveDateTime)>,
+    sms_sends: Vec<(i64, String, NaiveDateTime)>
+}
+
+struct MemoryParcel {
+    id: i32,
+    user_id: i32,
+    track_code: String,
+    label: Option<String>,
+    arrived: bool,
+    acknowledged: bool,
+    delivered: bool,
+    photo_id: Option<String>,
+    batch_code: Option<String>,
+    weight_kg: Option<f32>,
+    declared_value: Option<Money>,
+    description: Option<String>,
+    refund: Option<(i32, RefundStatus)>,
+    item: Option<Item>,
+    shelf: Option<String>,
+    quote_id: Option<i32>,
+    invoice: Option<Money>,
+    paid: Money
+}
+
+struct MemoryBatch {
+    code: String,
+    status: BatchStatus,
+    updated_at: NaiveDateTime
+}
+
+struct MemoryInvite {
+    code: String,
+    created_by: i64,
+    expires_at: NaiveDateTime,
+    redeemed: bool
+}
+
+struct MemoryTicket {
+    id: i32,
+    telegram_id: i64,
+    text: String,
+    created_at: NaiveDateTime,
+    closed: bool,
+    attachments: Vec<Attachment>
+}
+
+struct MemoryOutbox {
+    id: i64,
+    notice: Notice,
+    attempts: i32,
+    sent: bool,
+    message_id: Option<i32>,
+    created_at: NaiveDateTime
+}
+
+impl Memory {
+    pub fn create_user(&mut self, mut new_user: User) -> String {
+        new_user.id = self.users.len() as i32 + 1;
+        new_user.client_code = client_code::generate(200 + self.users.len() as i64);
+
+        let client_code = new_user.client_code.clone();
+        self.users.push(new_user);
+        self.registered_at.push(bishkek_now());
+
+        client_code
+    }
+
+    pub fn get_or_create_by_phone(&mut self, new_user: User) -> (String, bool) {
+        if let Some(user) = self.active_users().find(|user| duplicates::same_phone(&user.phone_number, &new_user.phone_number)) {
+            return (user.client_code.clone(), false);
+        }
+
+        (self.create_user(new_user), true)
+    }
+
+    pub fn get_or_create(&mut self, profile: User) -> (User, bool) {
+        if let Some(user) = profile.telegram_id.and_then(|telegram_id| self.user(telegram_id)) {
+            return (user.clone(), false);
+        }
+
+        let client_code = self.create_user(profile);
+
+        (self.active_users().find(|user| user.client_code == client_code).cloned().unwrap(), true)
+    }
+
+    pub fn find_user(&self, telegram_id: i64) -> Option<User> {
+        self.user(telegram_id).cloned()
+    }
+
+    pub fn get_user(&self, telegram_id: i64) -> User {
+        self.user(telegram_id).cloned().expect("ERROR: Could not get user")
+    }
+
+    pub fn find_user_by_client_code(&self, client_code: &str) -> Option<User> {
+        self.active_users().find(|user| user.client_code == client_code).cloned()
+    }
+
+    pub fn existing_client_codes(&self, client_codes: &[String]) -> Vec<String> {
+        let mut existing: Vec<String> = self.active_users()
+            .filter(|user| client_codes.contains(&user.client_code))
+            .map(|user| user.client_code.clone())
+            .collect();
+
+        existing.sort();
+        existing
+    }
+
+    pub fn update_telegram_profile(&mut self, telegram_id: i64, username: Option<&str>, display_name: &str) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.username = username.map(str::to_string);
+            user.display_name = Some(display_name.to_string());
+
+            self.blocked.remove(&telegram_id);
+        }
+    }
+
+    pub fn mark_unreachable(&mut self, telegram_id: i64) {
+        self.blocked.insert(telegram_id);
+    }
+
+    pub fn mark_reachable(&mut self, telegram_id: i64) {
+        self.blocked.remove(&telegram_id);
+    }
+
+    pub fn count_unreachable(&self) -> i64 {
+        self.active_users().filter(|user| user.telegram_id.is_some_and(|id| self.blocked.contains(&id))).count() as i64
+    }
+
+    pub fn count_reachable(&self) -> i64 {
+        self.active_users().filter(|user| user.telegram_id.is_some_and(|id| !self.blocked.contains(&id))).count() as i64
+    }
+
+    pub fn set_text_menu(&mut self, telegram_id: i64, text_menu: bool) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.text_menu = text_menu;
+        }
+    }
+
+    pub fn set_reminders(&mut self, telegram_id: i64, reminders: bool) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.reminders = reminders;
+        }
+    }
+
+    pub fn set_monthly_summary(&mut self, telegram_id: i64, monthly_summary: bool) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.monthly_summary = monthly_summary;
+        }
+    }
+
+    pub fn set_units(&mut self, telegram_id: i64, units: Units) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.units = units;
+        }
+    }
+
+    pub fn set_city(&mut self, telegram_id: i64, city: &str) {
+        if let Some(user) = self.user_mut(telegram_id) {
+            user.city = Some(city.to_owned());
+        }
+    }
+
+    pub fn find_users(&self, search: &str, limit: i64) -> Vec<User> {
+        let search = search.trim().trim_start_matches('@').to_lowercase();
+        let contains = |field: &str| field.to_lowercase().contains(&search);
+
+        self.active_users()
+            .filter(|user| user.username.as_deref().is_some_and(|username| username.to_lowercase() == search)
+                || user.client_code.to_lowercase() == search
+                || contains(&user.first_name)
+                || user.last_name.as_deref().is_some_and(contains)
+                || user.display_name.as_deref().is_some_and(contains))
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_users(&self) -> Vec<User> {
+        self.active_users().cloned().collect()
+    }
+
+    pub fn merge_users(&mut self, survivor: &User, duplicate: &User) -> Result<u64, Vec<String>> {
+        let kept: HashSet<String> = self.parcels.iter()
+            .filter(|parcel| parcel.user_id == survivor.id)
+            .map(|parcel| parcel.track_code.clone())
+            .collect();
+
+        let mut kept_history: Vec<String> = self.parcels.iter()
+            .filter(|parcel| parcel.user_id == duplicate.id && kept.contains(&parcel.track_code))
+            .filter(|parcel| parcel.paid.cents() > 0
+                || parcel.refund.is_some()
+                || self.disputes.iter().any(|(_, parcel_id)| *parcel_id == parcel.id))
+            .map(|parcel| parcel.track_code.clone())
+            .collect();
+
+        if !kept_history.is_empty() {
+            kept_history.sort();
+
+            return Err(kept_history);
+        }
+
+        self.parcels.retain(|parcel| parcel.user_id != duplicate.id || !kept.contains(&parcel.track_code));
+
+        let mut moved = 0;
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.user_id == duplicate.id) {
+            parcel.user_id = survivor.id;
+            moved += 1;
+        }
+
+        if let (Some(from), Some(to)) = (duplicate.telegram_id, survivor.telegram_id) {
+            for (owner, _) in self.addresses.iter_mut().filter(|(owner, _)| *owner == from) {
+                *owner = to;
+            }
+        }
+
+        for (user_id, request) in self.purchases.iter_mut().filter(|(user_id, _)| *user_id == duplicate.id) {
+            *user_id = survivor.id;
+            request.client_code = survivor.client_code.clone();
+            request.telegram_id = survivor.telegram_id.unwrap_or(request.telegram_id);
+        }
+
+        self.deleted.insert(duplicate.id);
+
+        Ok(moved)
+    }
+
+    pub fn get_tutorials(&self) -> Vec<Tutorial> {
+        self.tutorials.clone()
+    }
+
+    pub fn get_tutorial(&self, slug: &str) -> Option<Tutorial> {
+        self.tutorials.iter().find(|tutorial| tutorial.slug == slug).cloned()
+    }
+
+    pub fn save_tutorial(&mut self, tutorial: &Tutorial) {
+        match self.tutorials.iter_mut().find(|saved| saved.slug == tutorial.slug) {
+            Some(saved) => {
+                saved.title = tutorial.title.clone();
+                saved.text = tutorial.text.clone();
+            },
+            None => self.tutorials.push(tutorial.clone())
+        }
+    }
+
+    pub fn set_tutorial_links(&mut self, slug: &str, app_url: Option<&str>, web_url: Option<&str>) -> bool {
+        match self.tutorials.iter_mut().find(|tutorial| tutorial.slug == slug) {
+            Some(tutorial) => {
+                tutorial.app_url = app_url.map(str::to_string);
+                tutorial.web_url = web_url.map(str::to_string);
+
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn delete_tutorial(&mut self, slug: &str) -> bool {
+        let count = self.tutorials.len();
+        self.tutorials.retain(|tutorial| tutorial.slug != slug);
+
+        self.tutorials.len() < count
+    }
+
+    pub fn check_user(&self, telegram_id: i64) -> bool {
+        self.user(telegram_id).is_some()
+    }
+
+    /// Activity is not recorded here, so everyone counts as inactive.
+    pub fn get_segment_ids(&self, segment: &Segment) -> Vec<i64> {
+        self.users.iter()
+            .zip(&self.registered_at)
+            .filter(|(user, _)| !self.deleted.contains(&user.id))
+            .filter(|(user, registered_at)| segment.filters.iter().all(|filter| match filter {
+                Filter::RegisteredAfter(date) => registered_at.date() >= *date,
+                Filter::AtWarehouse => self.parcels.iter().any(|parcel| parcel.user_id == user.id && parcel.arrived && parcel.batch_code.is_none()),
+                Filter::City(city) => user.city.as_ref().is_some_and(|own| own.to_lowercase() == city.to_lowercase()),
+                Filter::InactiveDays(days) => match user.telegram_id.and_then(|telegram_id| self.last_active.get(&telegram_id)) {
+                    Some(active) => *active <= bishkek_now() - Duration::days(*days as i64),
+                    None => true
+                }
+            }))
+            .filter_map(|(user, _)| user.telegram_id)
+            .filter(|telegram_id| !self.blocked.contains(telegram_id))
+            .collect()
+    }
+
+    pub fn create_broadcast(&mut self, filters: &str, text: &str) -> i32 {
+        self.broadcasts.push((filters.to_string(), text.to_string(), false));
+
+        self.broadcasts.len() as i32
+    }
+
+    pub fn take_broadcast(&mut self, id: i32) -> Option<(String, String)> {
+        let (filters, text, sent) = self.broadcasts.get_mut(id as usize - 1)?;
+
+        if *sent {
+            return None;
+        }
+
+        *sent = true;
+
+        Some((filters.clone(), text.clone()))
+    }
+
+    pub fn create_survey(&mut self, title: &str) -> i32 {
+        let id = self.surveys.len() as i32 + 1;
+
+        self.surveys.push(Survey { id, title: title.to_string(), sent: false, questions: Vec::new() });
+
+        id
+    }
+
+    pub fn get_survey(&self, id: i32) -> Option<Survey> {
+        self.surveys.iter().find(|survey| survey.id == id).cloned()
+    }
+
+    pub fn add_survey_question(&mut self, survey_id: i32, text: &str, options: &[String]) -> i16 {
+        let survey = self.surveys.iter_mut().find(|survey| survey.id == survey_id).expect("ERROR: Could not add a survey question");
+        let position = survey.questions.len() as i16 + 1;
+
+        survey.questions.push(SurveyQuestion { position, text: text.to_string(), options: options.to_vec() });
+
+        position
+    }
+
+    pub fn send_survey(&mut self, survey_id: i32, telegram_ids: &[i64], notice: impl Fn(i64) -> Notice) -> Option<usize> {
+        let survey = self.surveys.iter_mut().find(|survey| survey.id == survey_id && !survey.sent)?;
+
+        survey.sent = true;
+
+        for telegram_id in telegram_ids {
+            self.survey_recipients.insert((survey_id, *telegram_id));
+            self.enqueue(&notice(*telegram_id));
+        }
+
+        Some(telegram_ids.len())
+    }
+
+    pub fn is_survey_recipient(&self, survey_id: i32, telegram_id: i64) -> bool {
+        self.survey_recipients.contains(&(survey_id, telegram_id))
+    }
+
+    pub fn save_survey_answer(&mut self, survey_id: i32, position: i16, telegram_id: i64, option: Option<i16>, text: Option<&str>) -> bool {
+        if !self.is_survey_recipient(survey_id, telegram_id) {
+            return false;
+        }
+
+        let answered = self.survey_answers.iter()
+            .any(|(id, answered_by, answer)| *id == survey_id && *answered_by == telegram_id && answer.position == position);
+
+        if answered {
+            return false;
+        }
+
+        self.survey_answers.push((survey_id, telegram_id, SurveyAnswer { position, option, text: text.map(str::to_string) }));
+
+        true
+    }
+
+    pub fn get_survey_answers(&self, survey_id: i32) -> Vec<SurveyAnswer> {
+        self.survey_answers.iter()
+            .filter(|(id, _, _)| *id == survey_id)
+            .map(|(_, _, answer)| answer.clone())
+            .collect()
+    }
+
+    pub fn save_parcel(&mut self, telegram_id: i64, track_code: &str, label: Option<String>) {
+        let user_id = match self.user(telegram_id) {
+            Some(user) => user.id,
+            None => return
+        };
+
+        if let Some(parcel) = self.parcels.iter_mut().find(|parcel| parcel.user_id == user_id && parcel.track_code == track_code) {
+            parcel.label = label;
+
+            return;
+        }
+
+        self.parcels.push(MemoryParcel {
+            id: self.parcels.len() as i32 + 1,
+            user_id,
+            track_code: track_code.to_string(),
+            label,
+            arrived: false,
+            acknowledged: false,
+            delivered: false,
+            photo_id: None,
+            batch_code: None,
+            weight_kg: None,
+            declared_value: None,
+            description: None,
+            refund: None,
+            item: None,
+            shelf: None,
+            quote_id: None,
+            invoice: None,
+            paid: Money::default()
+        });
+    }
+
+    pub fn attach_quote(&mut self, telegram_id: i64, track_code: &str, quote_id: i32) {
+        let user_id = match self.user(telegram_id) {
+            Some(user) => user.id,
+            None => return
+        };
+
+        if !self.parcels.iter().any(|parcel| parcel.user_id == user_id && parcel.track_code == track_code) {
+            self.save_parcel(telegram_id, track_code, None);
+        }
+
+        if let Some(parcel) = self.parcels.iter_mut().find(|parcel| parcel.user_id == user_id && parcel.track_code == track_code) {
+            parcel.quote_id = Some(quote_id);
+        }
+    }
+
+    pub fn save_parcel_item(&mut self, telegram_id: i64, track_code: &str, item: &Item) {
+        let user_id = match self.user(telegram_id) {
+            Some(user) => user.id,
+            None => return
+        };
+
+        if let Some(parcel) = self.parcels.iter_mut().find(|parcel| parcel.user_id == user_id && parcel.track_code == track_code) {
+            parcel.item = Some(item.clone());
+        }
+    }
+
+    pub fn get_parcel_items(&self, track_code: &str) -> Vec<ParcelItem> {
+        self.parcels.iter()
+            .filter(|parcel| parcel.track_code == track_code)
+            .filter_map(|parcel| {
+                let item = parcel.item.clone()?;
+
+                Some(ParcelItem {
+                    client_code: self.users[parcel.user_id as usize - 1].client_code.clone(),
+                    url: item.url,
+                    title: item.title,
+                    price: item.price
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_parcels(&self, telegram_id: i64) -> Vec<Parcel> {
+        self.user_parcels(telegram_id)
+            .map(|parcel| Parcel {
+                id: parcel.id,
+                track_code: parcel.track_code.clone(),
+                label: parcel.label.clone(),
+                arrived: parcel.arrived,
+                refund: parcel.refund.map(|(_, status)| status),
+                quote_id: parcel.quote_id
+            })
+            .collect()
+    }
+
+    pub fn get_pending_parcels(&self) -> Vec<PendingParcel> {
+        self.parcels.iter()
+            .filter(|parcel| !parcel.arrived)
+            .map(|parcel| self.pending(parcel))
+            .collect()
+    }
+
+    pub fn mark_parcel_arrived(&mut self, parcel_id: i32, notice: &Notice) {
+        if let Some(parcel) = self.parcels.iter_mut().find(|parcel| parcel.id == parcel_id) {
+            parcel.arrived = true;
+        }
+
+        self.enqueue(notice);
+    }
+
+    pub fn set_parcel_photo(&mut self, track_code: &str, photo_id: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        let mut arrived = Vec::new();
+        let mut updated = 0;
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code.eq_ignore_ascii_case(track_code)) {
+            parcel.photo_id = Some(photo_id.to_string());
+            updated += 1;
+
+            if parcel.arrived {
+                arrived.push(parcel.id);
+            }
+        }
+
+        for parcel_id in arrived {
+            let parcel = self.pending(self.parcels.iter().find(|parcel| parcel.id == parcel_id).unwrap());
+
+            self.enqueue(&notice(&parcel));
+        }
+
+        updated
+    }
+
+    pub fn assign_parcel(&mut self, track_code: &str, batch_code: &str, weight_kg: f32, declared_value: Money, description: Option<&str>, receipt: impl Fn(i32, i64) -> Notice) -> usize {
+        let mut owners = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code == track_code) {
+            parcel.batch_code = Some(batch_code.to_string());
+            parcel.weight_kg = Some(weight_kg);
+            parcel.declared_value = Some(declared_value);
+            parcel.description = description.map(str::to_string).or(parcel.description.take());
+
+            owners.push((parcel.id, parcel.user_id));
+        }
+
+        for (parcel_id, user_id) in &owners {
+            let telegram_id = self.owner(*user_id);
+
+            self.enqueue(&receipt(*parcel_id, telegram_id));
+        }
+
+        owners.len()
+    }
+
+    pub fn get_shipment(&self, telegram_id: i64, track_code: &str) -> Option<Shipment> {
+        self.user_parcels(telegram_id)
+            .find(|parcel| parcel.track_code == track_code)
+            .map(shipment)
+    }
+
+    pub fn get_shipments(&self, telegram_id: i64) -> Vec<Shipment> {
+        self.user_parcels(telegram_id).map(shipment).collect()
+    }
+
+    pub fn open_weight_dispute(&mut self, telegram_id: i64, track_code: &str, text: &str, attachments: &[Attachment]) -> i32 {
+        let ticket_id = self.create_ticket(telegram_id, text, attachments);
+
+        let parcel_id = self.user_parcels(telegram_id).find(|parcel| parcel.track_code == track_code).map(|parcel| parcel.id);
+
+        if let Some(parcel_id) = parcel_id {
+            self.disputes.push((ticket_id, parcel_id));
+        }
+
+        ticket_id
+    }
+
+    pub fn scan_parcel(&mut self, track_code: &str, batch_code: &str) -> Vec<String> {
+        let mut owners = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code.eq_ignore_ascii_case(track_code)) {
+            parcel.batch_code = Some(batch_code.to_string());
+            owners.push(self.users[parcel.user_id as usize - 1].client_code.clone());
+        }
+
+        owners
+    }
+
+    pub fn get_manifest(&self, batch_code: &str) -> Vec<ManifestRow> {
+        let mut rows: Vec<ManifestRow> = self.parcels.iter()
+            .filter(|parcel| parcel.batch_code.as_deref() == Some(batch_code))
+            .map(|parcel| ManifestRow {
+                track_code: parcel.track_code.clone(),
+                client_code: self.users[parcel.user_id as usize - 1].client_code.clone(),
+                description: parcel.description.clone().or(parcel.label.clone()),
+                declared_value: parcel.declared_value,
+                weight_kg: parcel.weight_kg
+            })
+            .collect();
+
+        rows.sort_by(|a, b| (&a.client_code, &a.track_code).cmp(&(&b.client_code, &b.track_code)));
+        rows
+    }
+
+    pub fn get_warehouse_labels(&self, track_code: &str) -> Vec<WarehouseLabel> {
+        let mut labels = Vec::new();
+
+        for parcel in self.parcels.iter().filter(|parcel| parcel.track_code == track_code) {
+            if self.deleted.contains(&parcel.user_id) {
+                continue;
+            }
+
+            let user = &self.users[parcel.user_id as usize - 1];
+
+            labels.push(WarehouseLabel {
+                track_code: parcel.track_code.clone(),
+                client_code: user.client_code.clone(),
+                first_name: user.first_name.clone(),
+                last_name: user.last_name.clone(),
+                city: user.city.clone(),
+                shelf: parcel.shelf.clone()
+            });
+        }
+
+        labels
+    }
+
+    pub fn shelve_parcel(&mut self, track_code: &str, shelf: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        self.shelves.insert(shelf.to_string());
+
+        let mut shelved = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code.eq_ignore_ascii_case(track_code) && !parcel.delivered) {
+            if self.deleted.contains(&parcel.user_id) {
+                continue;
+            }
+
+            parcel.shelf = Some(shelf.to_string());
+            shelved.push(parcel.id);
+        }
+
+        for parcel_id in &shelved {
+            let parcel = self.pending(self.parcels.iter().find(|parcel| parcel.id == *parcel_id).unwrap());
+
+            self.enqueue(&notice(&parcel));
+        }
+
+        shelved.len() as u64
+    }
+
+    pub fn get_shelf(&self, shelf: &str) -> Vec<WarehouseLabel> {
+        let mut labels: Vec<WarehouseLabel> = self.parcels.iter()
+            .filter(|parcel| parcel.shelf.as_deref() == Some(shelf) && !parcel.delivered)
+            .map(|parcel| {
+                let user = &self.users[parcel.user_id as usize - 1];
+
+                WarehouseLabel {
+                    track_code: parcel.track_code.clone(),
+                    client_code: user.client_code.clone(),
+                    first_name: user.first_name.clone(),
+                    last_name: user.last_name.clone(),
+                    city: None,
+                    shelf: parcel.shelf.clone()
+                }
+            })
+            .collect();
+
+        labels.sort_by(|a, b| (&a.client_code, &a.track_code).cmp(&(&b.client_code, &b.track_code)));
+        labels
+    }
+
+    pub fn get_shelves(&self) -> Vec<(String, i64)> {
+        self.shelves.iter()
+            .map(|shelf| {
+                let parcels = self.parcels.iter().filter(|parcel| parcel.shelf.as_ref() == Some(shelf) && !parcel.delivered).count();
+
+                (shelf.clone(), parcels as i64)
+            })
+            .collect()
+    }
+
+    pub fn get_setting(&self, key: &str) -> Option<String> {
+        self.settings.get(key).cloned()
+    }
+
+    pub fn set_setting(&mut self, key: &str, value: &str) {
+        self.settings.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn delete_setting(&mut self, key: &str) {
+        self.settings.remove(key);
+    }
+
+    pub fn mark_track_code_arrived(&mut self, track_code: &str, notice: impl Fn(&PendingParcel) -> Notice) -> usize {
+        let mut arrived = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code == track_code && !parcel.arrived) {
+            parcel.arrived = true;
+            arrived.push(parcel.id);
+        }
+
+        for parcel_id in &arrived {
+            let parcel = self.pending(self.parcels.iter().find(|parcel| parcel.id == *parcel_id).unwrap());
+
+            self.enqueue(&notice(&parcel));
+        }
+
+        arrived.len()
+    }
+
+    pub fn enqueue(&mut self, notice: &Notice) {
+        self.outbox.push(MemoryOutbox {
+            id: self.outbox.len() as i64 + 1,
+            notice: Notice {
+                telegram_id: notice.telegram_id,
+                text: notice.text.clone(),
+                markup: notice.markup.clone(),
+                photo_id: notice.photo_id.clone(),
+                digest: notice.digest.clone(),
+                subject: notice.subject.clone()
+            },
+            attempts: 0,
+            sent: false,
+            message_id: None,
+            created_at: bishkek_now()
+        });
+    }
+
+    pub fn get_outbox(&self, limit: i64, max_attempts: i32, window: Duration) -> Vec<OutboxMessage> {
+        let pending = || self.outbox.iter().filter(|message| !message.sent && message.attempts < max_attempts);
+
+        let collecting: HashSet<i64> = pending()
+            .filter(|message| message.notice.digest.is_some() && message.created_at > bishkek_now() - window)
+            .map(|message| message.notice.telegram_id)
+            .collect();
+
+        pending()
+            .filter(|message| !self.blocked.contains(&message.notice.telegram_id))
+            .filter(|message| message.notice.digest.is_none() || !collecting.contains(&message.notice.telegram_id))
+            .take(limit as usize)
+            .map(|message| OutboxMessage {
+                id: message.id,
+                telegram_id: message.notice.telegram_id,
+                text: message.notice.text.clone(),
+                markup: message.notice.markup.as_ref()
+                    .map(|markup| serde_json::to_string(markup).expect("ERROR: Could not serialize a keyboard")),
+                photo_id: message.notice.photo_id.clone(),
+                digest: message.notice.digest.clone()
+            })
+            .collect()
+    }
+
+    pub fn mark_outbox_sent(&mut self, id: i64, message_id: i32) {
+        if let Some(message) = self.outbox.get_mut(id as usize - 1) {
+            message.sent = true;
+            message.message_id = Some(message_id);
+        }
+    }
+
+    pub fn get_reacted_subjects(&self, telegram_id: i64, message_id: i32) -> Vec<Subject> {
+        self.outbox.iter()
+            .filter(|message| message.notice.telegram_id == telegram_id && message.message_id == Some(message_id))
+            .filter_map(|message| message.notice.subject.clone())
+            .collect()
+    }
+
+    pub fn acknowledge_arrival(&mut self, telegram_id: i64, track_code: &str) {
+        let user_id = self.user(telegram_id).map(|user| user.id);
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| Some(parcel.user_id) == user_id && parcel.track_code == track_code) {
+            parcel.acknowledged = true;
+        }
+    }
+
+    pub fn save_rating(&mut self, telegram_id: i64, track_code: &str, stars: i16) -> bool {
+        let user_id = self.user(telegram_id).map(|user| user.id);
+
+        if !self.parcels.iter().any(|parcel| Some(parcel.user_id) == user_id && parcel.track_code == track_code) {
+            return false;
+        }
+
+        self.ratings.insert((telegram_id, track_code.to_string()), stars);
+
+        true
+    }
+
+    pub fn mark_outbox_failed(&mut self, id: i64) {
+        if let Some(message) = self.outbox.get_mut(id as usize - 1) {
+            message.attempts += 1;
+        }
+    }
+
+    pub fn create_ticket(&mut self, telegram_id: i64, text: &str, attachments: &[Attachment]) -> i32 {
+        let id = self.tickets.len() as i32 + 1;
+
+        self.tickets.push(MemoryTicket {
+            id,
+            telegram_id,
+            text: text.to_string(),
+            created_at: bishkek_now(),
+            closed: false,
+            attachments: attachments.to_vec()
+        });
+
+        id
+    }
+
+    pub fn get_attachments(&self, ticket_id: i32) -> Vec<Attachment> {
+        self.tickets.iter()
+            .find(|ticket| ticket.id == ticket_id)
+            .map(|ticket| ticket.attachments.clone())
+            .unwrap_or_default()
+    }
+
+    /// Only activity is kept, for the `inactive:` segment.
+    pub fn record_event(&mut self, telegram_id: i64, kind: EventKind) {
+        if let EventKind::Active = kind {
+            self.last_active.insert(telegram_id, bishkek_now());
+        }
+    }
+
+    pub fn request_refund(&mut self, telegram_id: i64, track_code: &str, text: &str) -> Option<i32> {
+        let user_id = self.user(telegram_id)?.id;
+        let parcel = self.parcels.iter().position(|parcel| parcel.user_id == user_id && parcel.track_code == track_code)?;
+
+        if self.parcels[parcel].refund.is_some() {
+            return None;
+        }
+
+        let ticket_id = self.create_ticket(telegram_id, text, &[]);
+        self.parcels[parcel].refund = Some((ticket_id, RefundStatus::Requested));
+
+        Some(ticket_id)
+    }
+
+    pub fn set_refund_status(&mut self, ticket_id: i32, status: RefundStatus, notice: impl FnOnce(i64, &str) -> Notice) -> bool {
+        let parcel = match self.parcels.iter_mut().find(|parcel| parcel.refund.is_some_and(|(id, _)| id == ticket_id)) {
+            Some(parcel) => parcel,
+            None => return false
+        };
+
+        parcel.refund = Some((ticket_id, status));
+
+        let user_id = parcel.user_id;
+        let track_code = parcel.track_code.clone();
+        let notice = notice(self.owner(user_id), &track_code);
+
+        self.enqueue(&notice);
+
+        true
+    }
+
+    pub fn get_open_tickets(&self) -> Vec<Ticket> {
+        self.tickets.iter()
+            .filter(|ticket| !ticket.closed)
+            .filter_map(|ticket| Some(Ticket {
+                id: ticket.id,
+                client_code: self.user(ticket.telegram_id)?.client_code.clone(),
+                text: ticket.text.clone(),
+                created_at: ticket.created_at
+            }))
+            .collect()
+    }
+
+    pub fn get_user_tickets(&self, telegram_id: i64) -> Vec<Ticket> {
+        self.tickets.iter()
+            .filter(|ticket| ticket.telegram_id == telegram_id)
+            .filter_map(|ticket| Some(Ticket {
+                id: ticket.id,
+                client_code: self.user(ticket.telegram_id)?.client_code.clone(),
+                text: ticket.text.clone(),
+                created_at: ticket.created_at
+            }))
+            .collect()
+    }
+
+    pub fn close_ticket(&mut self, ticket_id: i32) -> bool {
+        match self.tickets.iter_mut().find(|ticket| ticket.id == ticket_id && !ticket.closed) {
+            Some(ticket) => {
+                ticket.closed = true;
+
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn get_dialogue(&self, chat_id: i64) -> Option<String> {
+        self.dialogues.get(&chat_id).cloned()
+    }
+
+    pub fn save_dialogue(&mut self, chat_id: i64, state: &str) {
+        self.dialogues.insert(chat_id, state.to_string());
+    }
+
+    pub fn remove_dialogue(&mut self, chat_id: i64) {
+        self.dialogues.remove(&chat_id);
+    }
+
+    pub fn get_addresses(&self, telegram_id: i64) -> Vec<Address> {
+        self.addresses.iter()
+            .filter(|(owner, _)| *owner == telegram_id)
+            .map(|(_, address)| address.clone())
+            .collect()
+    }
+
+    pub fn add_address(&mut self, telegram_id: i64, city: &str, street: &str, entrance: Option<&str>, comment: Option<&str>) -> bool {
+        if self.user(telegram_id).is_none() || self.get_addresses(telegram_id).len() >= MAX_ADDRESSES {
+            return false;
+        }
+
+        let id = self.addresses.iter().map(|(_, address)| address.id).max().unwrap_or(0) + 1;
+
+        self.addresses.push((telegram_id, Address {
+            id,
+            city: city.to_string(),
+            street: street.to_string(),
+            entrance: entrance.map(str::to_string),
+            comment: comment.map(str::to_string)
+        }));
+
+        true
+    }
+
+    pub fn delete_address(&mut self, telegram_id: i64, address_id: i32) -> bool {
+        let before = self.addresses.len();
+
+        self.addresses.retain(|(owner, address)| !(*owner == telegram_id && address.id == address_id));
+
+        self.addresses.len() < before
+    }
+
+    pub fn save_start_track_code(&mut self, telegram_id: i64, track_code: &str) {
+        self.start_track_codes.insert(telegram_id, track_code.to_string());
+    }
+
+    pub fn take_start_track_code(&mut self, telegram_id: i64) -> Option<String> {
+        self.start_track_codes.remove(&telegram_id)
+    }
+
+    pub fn add_staff_note(&mut self, author: &str, text: &str) {
+        self.staff_notes.push(StaffNote { author: author.to_string(), text: text.to_string(), created_at: bishkek_now() });
+    }
+
+    pub fn get_staff_notes(&self, hours: i32) -> Vec<StaffNote> {
+        let since = bishkek_now() - Duration::hours(hours as i64);
+
+        self.staff_notes.iter().filter(|note| note.created_at > since).cloned().collect()
+    }
+
+    pub fn get_pending_disputes(&self) -> Vec<(i32, String)> {
+        self.disputes.iter()
+            .filter(|(ticket_id, _)| self.tickets.iter().any(|ticket| ticket.id == *ticket_id && !ticket.closed))
+            .filter_map(|(ticket_id, parcel_id)| {
+                let parcel = self.parcels.iter().find(|parcel| parcel.id == *parcel_id)?;
+
+                Some((*ticket_id, parcel.track_code.clone()))
+            })
+            .collect()
+    }
+
+    pub fn get_unassigned_parcels(&self) -> Vec<(String, String)> {
+        self.parcels.iter()
+            .filter(|parcel| parcel.arrived && parcel.batch_code.is_none())
+            .map(|parcel| (parcel.track_code.clone(), self.users[parcel.user_id as usize - 1].client_code.clone()))
+            .collect()
+    }
+
+    pub fn request_registration(&mut self, user: &User) {
+        let telegram_id = user.telegram_id.expect("ERROR: Registration requests come from Telegram");
+
+        self.registrations.retain(|(request, _)| request.telegram_id != telegram_id);
+
+        self.registrations.push((RegistrationRequest {
+            telegram_id,
+            first_name: user.first_name.clone(),
+            last_name: user.last_name.clone(),
+            phone_number: user.phone_number.clone(),
+            username: user.username.clone(),
+            display_name: user.display_name.clone()
+        }, false));
+    }
+
+    pub fn find_phone_owner(&self, telegram_id: Option<i64>, phone_number: &str) -> Option<User> {
+        self.active_users()
+            .find(|user| user.telegram_id != telegram_id && duplicates::same_phone(&user.phone_number, phone_number))
+            .cloned()
+    }
+
+    pub fn reserve_sms(&mut self, telegram_id: i64, phone_number: &str, limits: &SmsLimits) -> bool {
+        let now = bishkek_now();
+        let phone_key = duplicates::phone_key(phone_number);
+
+        let recent = |since: NaiveDateTime| self.sms_sends.iter()
+            .filter(move |(_, _, sent_at)| *sent_at > since);
+
+        let cooling_down = recent(now - Duration::seconds(limits.cooldown_seconds.into()))
+            .any(|(sender, phone, _)| *sender == telegram_id || *phone == phone_key);
+        let to_phone = recent(now - Duration::days(1)).filter(|(_, phone, _)| *phone == phone_key).count() as i64;
+        let by_user = recent(now - Duration::days(1)).filter(|(sender, _, _)| *sender == telegram_id).count() as i64;
+
+        if cooling_down || to_phone >= limits.daily_per_phone || by_user >= limits.daily_per_user {
+            return false;
+        }
+
+        self.sms_sends.push((telegram_id, phone_key, now));
+
+        true
+    }
+
+    pub fn request_account_claim(&mut self, user_id: i32, profile: &User) -> AccountClaim {
+        let telegram_id = profile.telegram_id.expect("ERROR: Claims come from Telegram");
+        let owner = &self.users[user_id as usize - 1];
+
+        let claim = AccountClaim {
+            id: self.claims.len() as i32 + 1,
+            user_id,
+            client_code: owner.client_code.clone(),
+            first_name: owner.first_name.clone(),
+            last_name: owner.last_name.clone(),
+            old_telegram_id: owner.telegram_id,
+            telegram_id,
+            username: profile.username.clone(),
+            display_name: profile.display_name.clone()
+        };
+
+        // A repeated claim replaces the pending one.
+        for (_, decided) in self.claims.iter_mut().filter(|(pending, _)| pending.telegram_id == telegram_id) {
+            *decided = true;
+        }
+
+        self.claims.push((claim.clone(), false));
+
+        claim
+    }
+
+    pub fn get_account_claims(&self) -> Vec<AccountClaim> {
+        self.claims.iter()
+            .filter(|(claim, decided)| !decided && !self.deleted.contains(&claim.user_id))
+            .map(|(claim, _)| claim.clone())
+            .collect()
+    }
+
+    /// Moves only the user, the history tables of the Postgres version are not kept here.
+    pub fn approve_account_claim(&mut self, id: i32, notice: impl FnOnce(i64, &AccountClaim) -> Notice) -> Option<AccountClaim> {
+        let claim = self.take_claim(id)?;
+
+        if self.deleted.contains(&claim.user_id) || self.user(claim.telegram_id).is_some() {
+            return None;
+        }
+
+        let user = &mut self.users[claim.user_id as usize - 1];
+        user.telegram_id = Some(claim.telegram_id);
+        user.username = claim.username.clone();
+        user.display_name = claim.display_name.clone();
+
+        if let Some(old_telegram_id) = claim.old_telegram_id {
+            self.dialogues.remove(&old_telegram_id);
+            self.enqueue(&notice(old_telegram_id, &claim));
+        }
+
+        Some(claim)
+    }
+
+    pub fn reject_account_claim(&mut self, id: i32) -> Option<AccountClaim> {
+        self.take_claim(id)
+    }
+
+    fn take_claim(&mut self, id: i32) -> Option<AccountClaim> {
+        let (claim, decided) = self.claims.iter_mut().find(|(claim, decided)| claim.id == id && !decided)?;
+        *decided = true;
+
+        Some(claim.clone())
+    }
+
+    pub fn get_registration_requests(&self) -> Vec<RegistrationRequest> {
+        self.registrations.iter()
+            .filter(|(_, decided)| !decided)
+            .map(|(request, _)| request.clone())
+            .collect()
+    }
+
+    pub fn get_recent_registrations(&self, limit: i64) -> Vec<Registration> {
+        self.users.iter()
+            .zip(&self.registered_at)
+            .rev()
+            .filter(|(user, _)| !self.deleted.contains(&user.id))
+            .take(limit as usize)
+            .map(|(user, created_at)| Registration {
+                client_code: user.client_code.clone(),
+                first_name: user.first_name.clone(),
+                last_name: user.last_name.clone(),
+                username: user.username.clone(),
+                created_at: *created_at
+            })
+            .collect()
+    }
+
+    pub fn take_registration_request(&mut self, telegram_id: i64) -> Option<RegistrationRequest> {
+        let (request, decided) = self.registrations.iter_mut()
+            .find(|(request, decided)| request.telegram_id == telegram_id && !decided)?;
+
+        *decided = true;
+
+        Some(request.clone())
+    }
+
+    pub fn save_canned_response(&mut self, slug: &str, text: &str) {
+        self.canned.insert(slug.to_string(), text.to_string());
+    }
+
+    pub fn get_canned_responses(&self) -> Vec<CannedResponse> {
+        self.canned.iter()
+            .map(|(slug, text)| CannedResponse { slug: slug.clone(), text: text.clone() })
+            .collect()
+    }
+
+    pub fn get_canned_response(&self, slug: &str) -> Option<String> {
+        self.canned.get(slug).cloned()
+    }
+
+    pub fn record_canned_use(&mut self, slug: &str, operator_id: i64, operator: &str) {
+        self.canned_uses.push((slug.to_string(), operator_id, operator.to_string()));
+    }
+
+    pub fn get_canned_usage(&self) -> Vec<CannedUsage> {
+        let mut counts: BTreeMap<(&str, i64), (&str, i64)> = BTreeMap::new();
+
+        for (slug, operator_id, operator) in &self.canned_uses {
+            counts.entry((slug, *operator_id)).or_insert((operator, 0)).1 += 1;
+        }
+
+        counts.into_iter()
+            .map(|((slug, _), (operator, uses))| CannedUsage { slug: slug.to_string(), operator: operator.to_string(), uses })
+            .collect()
+    }
+
+    pub fn get_ticket_user(&self, ticket_id: i32) -> Option<User> {
+        let ticket = self.tickets.iter().find(|ticket| ticket.id == ticket_id)?;
+
+        self.user(ticket.telegram_id).cloned()
+    }
+
+    pub fn mark_batch_delivered(&mut self, batch_code: &str, require_payment: bool) -> (u64, Vec<String>) {
+        let mut delivered = 0;
+        let mut held = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.batch_code.as_deref() == Some(batch_code) && !parcel.delivered) {
+            let paid = parcel.invoice.is_some_and(|invoice| parcel.paid >= invoice);
+
+            if require_payment && !paid {
+                held.push(parcel.track_code.clone());
+                continue;
+            }
+
+            parcel.delivered = true;
+            delivered += 1;
+        }
+
+        held.sort();
+
+        (delivered, held)
+    }
+
+    pub fn create_batch(&mut self, code: &str) -> bool {
+        if self.batches.iter().any(|batch| batch.code == code) {
+            return false;
+        }
+
+        self.batches.push(MemoryBatch { code: code.to_string(), status: BatchStatus::Forming, updated_at: bishkek_now() });
+        self.batch_events.push((code.to_string(), BatchStatus::Forming, bishkek_now()));
+
+        true
+    }
+
+    pub fn get_batch(&self, code: &str) -> Option<Batch> {
+        self.batches.iter().find(|batch| batch.code == code).map(|batch| self.batch(batch))
+    }
+
+    pub fn get_open_batches(&self) -> Vec<Batch> {
+        self.batches.iter()
+            .filter(|batch| batch.status != BatchStatus::Arrived)
+            .map(|batch| self.batch(batch))
+            .collect()
+    }
+
+    pub fn get_user_batches(&self, telegram_id: i64) -> Vec<Batch> {
+        let codes: HashSet<&str> = self.user_parcels(telegram_id)
+            .filter(|parcel| !parcel.delivered)
+            .filter_map(|parcel| parcel.batch_code.as_deref())
+            .collect();
+
+        self.batches.iter()
+            .filter(|batch| codes.contains(batch.code.as_str()))
+            .map(|batch| self.batch(batch))
+            .collect()
+    }
+
+    pub fn get_batch_events(&self, code: &str) -> Vec<BatchEvent> {
+        self.batch_events.iter()
+            .filter(|(batch_code, _, _)| batch_code == code)
+            .map(|(_, status, created_at)| BatchEvent { status: *status, created_at: *created_at })
+            .collect()
+    }
+
+    pub fn advance_batch(&mut self, code: &str, from: BatchStatus, status: BatchStatus, notice: impl Fn(i64, &[String]) -> Notice) -> Option<usize> {
+        let batch = self.batches.iter_mut().find(|batch| batch.code == code && batch.status == from)?;
+
+        batch.status = status;
+        batch.updated_at = bishkek_now();
+
+        self.batch_events.push((code.to_string(), status, bishkek_now()));
+
+        let mut owners: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+
+        for parcel in self.parcels.iter().filter(|parcel| parcel.batch_code.as_deref() == Some(code) && !parcel.delivered) {
+            if self.deleted.contains(&parcel.user_id) {
+                continue;
+            }
+
+            owners.entry(self.owner(parcel.user_id)).or_default().push(parcel.track_code.clone());
+        }
+
+        for (telegram_id, track_codes) in &mut owners {
+            track_codes.sort();
+
+            self.enqueue(&notice(*telegram_id, track_codes));
+        }
+
+        Some(owners.len())
+    }
+
+    pub fn has_permission(&self, telegram_id: i64, permission: Permission) -> bool {
+        self.roles.contains(&(telegram_id, permission.as_str()))
+    }
+
+    pub fn grant_permission(&mut self, telegram_id: i64, permission: Permission) -> bool {
+        self.roles.insert((telegram_id, permission.as_str()))
+    }
+
+    pub fn revoke_permission(&mut self, telegram_id: i64, permission: Permission) -> bool {
+        self.roles.remove(&(telegram_id, permission.as_str()))
+    }
+
+    pub fn get_staff(&self) -> Vec<i64> {
+        self.staff.clone()
+    }
+
+    pub fn seed_staff(&mut self, telegram_ids: &[i64]) -> bool {
+        if !self.staff.is_empty() || telegram_ids.is_empty() {
+            return false;
+        }
+
+        self.staff = telegram_ids.to_vec();
+
+        true
+    }
+
+    pub fn create_staff_invite(&mut self, code: &str, created_by: i64, valid_hours: i32) {
+        self.staff_invites.push(MemoryInvite {
+            code: code.to_string(),
+            created_by,
+            expires_at: bishkek_now() + Duration::hours(valid_hours as i64),
+            redeemed: false
+        });
+    }
+
+    pub fn redeem_staff_invite(&mut self, code: &str, telegram_id: i64) -> Option<i64> {
+        let invite = self.staff_invites.iter_mut()
+            .find(|invite| invite.code == code && !invite.redeemed && invite.expires_at > bishkek_now())?;
+
+        invite.redeemed = true;
+
+        if !self.staff.contains(&telegram_id) {
+            self.staff.push(telegram_id);
+        }
+
+        Some(invite.created_by)
+    }
+
+    pub fn remove_staff(&mut self, telegram_id: i64) -> bool {
+        self.roles.retain(|(id, _)| *id != telegram_id);
+
+        let count = self.staff.len();
+        self.staff.retain(|id| *id != telegram_id);
+
+        self.staff.len() < count
+    }
+
+    pub fn log_export(&mut self, telegram_id: i64, kind: &str, row_count: usize) -> i32 {
+        self.exports.push((telegram_id, kind.to_string(), row_count));
+
+        self.exports.len() as i32
+    }
+
+    pub fn save_error_report(&mut self, report: &ErrorReport) -> i32 {
+        self.error_reports.push(report.clone());
+
+        self.error_reports.len() as i32
+    }
+
+    pub fn set_invoice(&mut self, track_code: &str, amount: Money, notice: impl Fn(&Invoice) -> Notice) -> usize {
+        let deleted = &self.deleted;
+        let mut billed = Vec::new();
+
+        for parcel in self.parcels.iter_mut().filter(|parcel| parcel.track_code == track_code && !deleted.contains(&parcel.user_id)) {
+            parcel.invoice = Some(amount);
+            billed.push(parcel.id);
+        }
+
+        for parcel_id in &billed {
+            let invoice = self.get_invoice(*parcel_id).expect("ERROR: A billed parcel has no invoice");
+
+            self.enqueue(&notice(&invoice));
+        }
+
+        billed.len()
+    }
+
+    pub fn get_invoice(&self, parcel_id: i32) -> Option<Invoice> {
+        self.parcels.iter()
+            .find(|parcel| parcel.id == parcel_id && !self.deleted.contains(&parcel.user_id))
+            .and_then(|parcel| self.invoice(parcel))
+    }
+
+    pub fn get_invoices(&self, track_code: &str) -> Vec<Invoice> {
+        let mut invoices: Vec<Invoice> = self.parcels.iter()
+            .filter(|parcel| parcel.track_code == track_code && !self.deleted.contains(&parcel.user_id))
+            .filter_map(|parcel| self.invoice(parcel))
+            .collect();
+
+        invoices.sort_by(|a, b| a.client_code.cmp(&b.client_code));
+
+        invoices
+    }
+
+    pub fn record_payment(&mut self, parcel_id: i32, amount: Money, charge_id: Option<&str>, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        let parcel = self.parcels.iter_mut().find(|parcel| parcel.id == parcel_id && parcel.invoice.is_some())?;
+
+        if charge_id.is_none() && parcel.paid.cents() + amount.cents() > parcel.invoice?.cents() {
+            return None;
+        }
+
+        if let Some(charge_id) = charge_id {
+            if !self.charges.insert(charge_id.to_string()) {
+                return None;
+            }
+        }
+
+        parcel.paid = Money::from_cents(parcel.paid.cents() + amount.cents());
+
+        let invoice = self.get_invoice(parcel_id)?;
+
+        self.enqueue(&notice(&invoice));
+
+        Some(invoice)
+    }
+
+    pub fn override_price(&mut self, parcel_id: i32, change: &PriceOverride, operator_id: i64, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        let parcel = self.parcels.iter_mut().find(|parcel| parcel.id == parcel_id && parcel.invoice == Some(change.original))?;
+
+        parcel.invoice = Some(change.final_price);
+        self.overrides.push((operator_id, change.clone(), bishkek_now()));
+
+        let invoice = self.get_invoice(parcel_id)?;
+
+        self.enqueue(&notice(&invoice));
+
+        Some(invoice)
+    }
+
+    pub fn get_override_totals(&self, days: i32) -> Vec<OverrideTotals> {
+        let since = bishkek_now() - Duration::days(days as i64);
+        let mut totals: Vec<OverrideTotals> = Vec::new();
+
+        for (operator_id, change, _) in self.overrides.iter().filter(|(_, _, created_at)| *created_at >= since) {
+            let index = match totals.iter().position(|total| total.operator_id == *operator_id) {
+                Some(index) => index,
+                None => {
+                    totals.push(OverrideTotals { operator_id: *operator_id, overrides: 0, discounts: Money::default(), penalties: Money::default() });
+                    totals.len() - 1
+                }
+            };
+
+            let total = &mut totals[index];
+            let delta = change.delta().cents();
+
+            total.overrides += 1;
+            total.discounts = Money::from_cents(total.discounts.cents() + (-delta).max(0));
+            total.penalties = Money::from_cents(total.penalties.cents() + delta.max(0));
+        }
+
+        totals.sort_by(|a, b| b.overrides.cmp(&a.overrides).then(a.operator_id.cmp(&b.operator_id)));
+        totals
+    }
+
+    pub fn create_purchase_request(&mut self, telegram_id: i64, marketplace: &str, link: &str, options: Option<&str>, budget: Money) -> i32 {
+        let user = self.user(telegram_id).cloned().expect("ERROR: Could not create a purchase request");
+        let id = self.purchases.len() as i32 + 1;
+
+        self.purchases.push((user.id, PurchaseRequest {
+            id,
+            client_code: user.client_code,
+            telegram_id,
+            marketplace: marketplace.to_string(),
+            link: link.to_string(),
+            options: options.map(str::to_string),
+            budget,
+            status: PurchaseStatus::New,
+            quote: None,
+            comment: None
+        }));
+
+        id
+    }
+
+    pub fn get_open_purchase_requests(&self) -> Vec<PurchaseRequest> {
+        let mut requests: Vec<PurchaseRequest> = self.purchases.iter()
+            .filter(|(user_id, request)| request.status.is_open() && !self.deleted.contains(user_id))
+            .map(|(_, request)| request.clone())
+            .collect();
+
+        requests.sort_by(|a, b| a.marketplace.cmp(&b.marketplace));
+
+        requests
+    }
+
+    pub fn get_purchase_request(&self, id: i32) -> Option<PurchaseRequest> {
+        self.purchases.iter()
+            .find(|(user_id, request)| request.id == id && !self.deleted.contains(user_id))
+            .map(|(_, request)| request.clone())
+    }
+
+    pub fn update_purchase_request(&mut self, id: i32, status: PurchaseStatus, quote: Option<Money>, comment: Option<&str>, notice: impl FnOnce(&PurchaseRequest) -> Notice) -> Option<PurchaseRequest> {
+        let (_, request) = self.purchases.iter_mut().find(|(_, request)| request.id == id && request.status.is_open())?;
+
+        request.status = status;
+        request.quote = quote.or(request.quote);
+        request.comment = comment.map(str::to_string).or(request.comment.take());
+
+        let request = request.clone();
+
+        self.enqueue(&notice(&request));
+
+        Some(request)
+    }
+
+    pub fn get_tariffs(&self) -> Vec<Tariff> {
+        self.tariffs.clone()
+    }
+
+    pub fn save_tariff(&mut self, tariff: &Tariff) {
+        self.tariffs.retain(|saved| saved.min_density != tariff.min_density);
+        self.tariffs.push(tariff.clone());
+        self.tariffs.sort_by(|a, b| a.min_density.total_cmp(&b.min_density));
+        self.bump_tariff_version();
+    }
+
+    pub fn open_city(&mut self, city: &NewCity) -> Option<i32> {
+        if self.cities.iter().any(|open| open.name.to_lowercase() == city.name.to_lowercase()) {
+            return None;
+        }
+
+        self.cities.push(city.clone());
+
+        Some(self.create_broadcast(&format!("city:{}", city.name), &city.announcement))
+    }
+
+    pub fn get_cities(&self) -> Vec<(i32, String)> {
+        let mut cities: Vec<(i32, String)> = self.cities.iter().enumerate().map(|(i, city)| (i as i32 + 1, city.name.clone())).collect();
+        cities.sort_by(|a, b| a.1.cmp(&b.1));
+        cities
+    }
+
+    pub fn get_user_tariffs(&self, telegram_id: i64) -> Vec<Tariff> {
+        let city = self.users.iter()
+            .find(|user| user.telegram_id == Some(telegram_id))
+            .and_then(|user| user.city.as_ref())
+            .map(|city| city.to_lowercase());
+
+        let mut tariffs = self.cities.iter()
+            .find(|opened| Some(opened.name.to_lowercase()) == city)
+            .map(|opened| opened.tariffs.clone())
+            .unwrap_or_default();
+
+        if tariffs.is_empty() {
+            return self.get_tariffs();
+        }
+
+        tariffs.sort_by(|a, b| a.min_density.total_cmp(&b.min_density));
+        tariffs
+    }
+
+    pub fn get_pickup_points(&self) -> Vec<(String, String)> {
+        self.cities.iter().map(|city| (city.name.clone(), city.pickup_point.clone())).collect()
+    }
+
+    pub fn get_planned_etas(&self) -> Vec<(String, i32, i32)> {
+        self.cities.iter().map(|city| (city.name.clone(), city.eta_days.0, city.eta_days.1)).collect()
+    }
+
+    pub fn delete_tariff(&mut self, min_density: f32) -> bool {
+        let count = self.tariffs.len();
+        self.tariffs.retain(|tariff| tariff.min_density != min_density);
+
+        let deleted = self.tariffs.len() < count;
+
+        if deleted {
+            self.bump_tariff_version();
+        }
+
+        deleted
+    }
+
+    fn bump_tariff_version(&mut self) {
+        let version = self.settings.get(super::TARIFF_VERSION_KEY).and_then(|version| version.parse::<i32>().ok()).unwrap_or(0);
+
+        self.settings.insert(super::TARIFF_VERSION_KEY.to_string(), (version + 1).to_string());
+    }
+
+    pub fn save_quote(&mut self, quote: &Quote) -> i32 {
+        let id = self.quotes.len() as i32 + 1;
+
+        self.quotes.push(Quote { id, created_at: bishkek_now(), ..quote.clone() });
+
+        id
+    }
+
+    pub fn get_quotes(&self, telegram_id: i64, limit: i64) -> Vec<Quote> {
+        self.quotes.iter()
+            .rev()
+            .filter(|quote| quote.telegram_id == telegram_id)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_quote(&self, id: i32) -> Option<Quote> {
+        self.quotes.iter().find(|quote| quote.id == id).cloned()
+    }
+
+    pub fn save_box_preset(&mut self, telegram_id: i64, width: f32, length: f32, height: f32, limit: i64) -> bool {
+        let saved = !self.box_presets.iter().any(|(owner, preset)| {
+            *owner == telegram_id && (preset.width, preset.length, preset.height) == (width, length, height)
+        });
+
+        if saved {
+            let id = self.box_presets.iter().map(|(_, preset)| preset.id).max().unwrap_or_default() + 1;
+
+            self.box_presets.push((telegram_id, BoxPreset { id, width, length, height }));
+        }
+
+        let kept: Vec<i32> = self.get_box_presets(telegram_id).iter().take(limit as usize).map(|preset| preset.id).collect();
+
+        self.box_presets.retain(|(owner, preset)| *owner != telegram_id || kept.contains(&preset.id));
+
+        saved
+    }
+
+    pub fn get_box_presets(&self, telegram_id: i64) -> Vec<BoxPreset> {
+        self.box_presets.iter()
+            .rev()
+            .filter(|(owner, _)| *owner == telegram_id)
+            .map(|(_, preset)| preset.clone())
+            .collect()
+    }
+
+    pub fn get_box_preset(&self, telegram_id: i64, id: i32) -> Option<BoxPreset> {
+        self.get_box_presets(telegram_id).into_iter().find(|preset| preset.id == id)
+    }
+
+    fn active_users(&self) -> impl Iterator<Item = &User> {
+        self.users.iter().filter(|user| !self.deleted.contains(&user.id))
+    }
+
+    fn user(&self, telegram_id: i64) -> Option<&User> {
+        self.active_users().find(|user| user.telegram_id == Some(telegram_id))
+    }
+
+    fn user_mut(&mut self, telegram_id: i64) -> Option<&mut User> {
+        let deleted = &self.deleted;
+
+        self.users.iter_mut().find(|user| user.telegram_id == Some(telegram_id) && !deleted.contains(&user.id))
+    }
+
+    fn user_parcels(&self, telegram_id: i64) -> impl Iterator<Item = &MemoryParcel> {
+        let user_id = self.user(telegram_id).map(|user| user.id);
+
+        self.parcels.iter().filter(move |parcel| Some(parcel.user_id) == user_id)
+    }
+
+    // Parcels are saved from Telegram, so their owners always have a telegram id.
+    fn owner(&self, user_id: i32) -> i64 {
+        self.users[user_id as usize - 1].telegram_id.expect("ERROR: A parcel owner has no telegram id")
+    }
+
+    fn batch(&self, batch: &MemoryBatch) -> Batch {
+        Batch {
+            code: batch.code.clone(),
+            status: batch.status,
+            parcels: self.parcels.iter().filter(|parcel| parcel.batch_code.as_deref() == Some(&batch.code) && !parcel.delivered).count() as i64,
+            updated_at: batch.updated_at
+        }
+    }
+
+    fn invoice(&self, parcel: &MemoryParcel) -> Option<Invoice> {
+        Some(Invoice {
+            parcel_id: parcel.id,
+            track_code: parcel.track_code.clone(),
+            client_code: self.users[parcel.user_id as usize - 1].client_code.clone(),
+            telegram_id: self.owner(parcel.user_id),
+            amount: parcel.invoice?,
+            paid: parcel.paid
+        })
+    }
+
+    fn pending(&self, parcel: &MemoryParcel) -> PendingParcel {
+        PendingParcel {
+            id: parcel.id,
+            track_code: parcel.track_code.clone(),
+            label: parcel.label.clone(),
+            telegram_id: self.owner(parcel.user_id),
+            photo_id: parcel.photo_id.clone(),
+            city: None
+        }
+    }
+}
+
+fn shipment(parcel: &MemoryParcel) -> Shipment {
+    Shipment {
+        track_code: parcel.track_code.clone(),
+        batch_code: parcel.batch_code.clone(),
+        weight_kg: parcel.weight_kg,
+        declared_value: parcel.declared_value,
+        description: parcel.description.clone().or(parcel.label.clone())
+    }
+}
+
+/// [`Memory`] behind the lock handlers share.
+#[derive(Clone, Default)]
+pub(super) struct MemoryStore(Arc<Mutex<Memory>>);
+
+impl MemoryStore {
+    fn lock(&self) -> MutexGuard<'_, Memory> {
+        self.0.lock().expect("ERROR: The in-memory store is poisoned")
+    }
+}
+
+impl Store for MemoryStore {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn insert_user(&self, new_user: User) -> String {
+        self.lock().create_user(new_user)
+    }
+
+    async fn upsert_by_phone(&self, new_user: User) -> (String, bool) {
+        self.lock().get_or_create_by_phone(new_user)
+    }
+
+    async fn upsert_user(&self, profile: User) -> (User, bool) {
+        self.lock().get_or_create(profile)
+    }
+
+    async fn find_user(&self, telegram_id: i64) -> Option<User> {
+        self.lock().find_user(telegram_id)
+    }
+
+    async fn get_user(&self, telegram_id: i64) -> User {
+        self.lock().get_user(telegram_id)
+    }
+
+    async fn find_user_by_client_code(&self, client_code: &str) -> Option<User> {
+        self.lock().find_user_by_client_code(client_code)
+    }
+
+    async fn existing_client_codes(&self, client_codes: &[String]) -> Vec<String> {
+        self.lock().existing_client_codes(client_codes)
+    }
+
+    async fn update_telegram_profile(&self, telegram_id: i64, username: Option<&str>, display_name: &str) {
+        self.lock().update_telegram_profile(telegram_id, username, display_name)
+    }
+
+    async fn mark_unreachable(&self, telegram_id: i64) {
+        self.lock().mark_unreachable(telegram_id)
+    }
+
+    async fn mark_reachable(&self, telegram_id: i64) {
+        self.lock().mark_reachable(telegram_id)
+    }
+
+    async fn count_reachable(&self) -> i64 {
+        self.lock().count_reachable()
+    }
+
+    async fn count_unreachable(&self) -> i64 {
+        self.lock().count_unreachable()
+    }
+
+    async fn set_text_menu(&self, telegram_id: i64, text_menu: bool) {
+        self.lock().set_text_menu(telegram_id, text_menu)
+    }
+
+    async fn set_reminders(&self, telegram_id: i64, reminders: bool) {
+        self.lock().set_reminders(telegram_id, reminders)
+    }
+
+    async fn set_monthly_summary(&self, telegram_id: i64, monthly_summary: bool) {
+        self.lock().set_monthly_summary(telegram_id, monthly_summary)
+    }
+
+    async fn set_units(&self, telegram_id: i64, units: Units) {
+        self.lock().set_units(telegram_id, units)
+    }
+
+    async fn set_city(&self, telegram_id: i64, city: &str) {
+        self.lock().set_city(telegram_id, city)
+    }
+
+    async fn find_users(&self, search: &str, limit: i64) -> Vec<User> {
+        self.lock().find_users(search, limit)
+    }
+
+    async fn get_users(&self) -> Vec<User> {
+        self.lock().get_users()
+    }
+
+    async fn merge_users(&self, survivor: &User, duplicate: &User) -> Result<u64, Vec<String>> {
+        self.lock().merge_users(survivor, duplicate)
+    }
+
+    async fn find_phone_owner(&self, telegram_id: Option<i64>, phone_number: &str) -> Option<User> {
+        self.lock().find_phone_owner(telegram_id, phone_number)
+    }
+
+    async fn reserve_sms(&self, telegram_id: i64, phone_number: &str, limits: &SmsLimits) -> bool {
+        self.lock().reserve_sms(telegram_id, phone_number, limits)
+    }
+
+    async fn get_tutorials(&self) -> Vec<Tutorial> {
+        self.lock().get_tutorials()
+    }
+
+    async fn get_tutorial(&self, slug: &str) -> Option<Tutorial> {
+        self.lock().get_tutorial(slug)
+    }
+
+    async fn save_tutorial(&self, tutorial: &Tutorial) {
+        self.lock().save_tutorial(tutorial)
+    }
+
+    async fn set_tutorial_links(&self, slug: &str, app_url: Option<&str>, web_url: Option<&str>) -> bool {
+        self.lock().set_tutorial_links(slug, app_url, web_url)
+    }
+
+    async fn delete_tutorial(&self, slug: &str) -> bool {
+        self.lock().delete_tutorial(slug)
+    }
+
+    async fn check_user(&self, telegram_id: i64) -> bool {
+        self.lock().check_user(telegram_id)
+    }
+
+    async fn get_segment_ids(&self, segment: &Segment) -> Vec<i64> {
+        self.lock().get_segment_ids(segment)
+    }
+
+    async fn count_segment(&self, segment: &Segment) -> i64 {
+        self.lock().get_segment_ids(segment).len() as i64
+    }
+
+    async fn create_broadcast(&self, filters: &str, text: &str) -> i32 {
+        self.lock().create_broadcast(filters, text)
+    }
+
+    async fn take_broadcast(&self, id: i32) -> Option<(String, String)> {
+        self.lock().take_broadcast(id)
+    }
+
+    async fn create_survey(&self, title: &str, _created_by: i64) -> i32 {
+        self.lock().create_survey(title)
+    }
+
+    async fn get_survey(&self, id: i32) -> Option<Survey> {
+        self.lock().get_survey(id)
+    }
+
+    async fn add_survey_question(&self, survey_id: i32, text: &str, options: &[String]) -> i16 {
+        self.lock().add_survey_question(survey_id, text, options)
+    }
+
+    async fn send_survey(&self, survey_id: i32, telegram_ids: &[i64], notice: impl Fn(i64) -> Notice) -> Option<usize> {
+        self.lock().send_survey(survey_id, telegram_ids, notice)
+    }
+
+    async fn is_survey_recipient(&self, survey_id: i32, telegram_id: i64) -> bool {
+        self.lock().is_survey_recipient(survey_id, telegram_id)
+    }
+
+    async fn save_survey_answer(&self, survey_id: i32, position: i16, telegram_id: i64, option: Option<i16>, text: Option<&str>) -> bool {
+        self.lock().save_survey_answer(survey_id, position, telegram_id, option, text)
+    }
+
+    async fn get_survey_answers(&self, survey_id: i32) -> Vec<SurveyAnswer> {
+        self.lock().get_survey_answers(survey_id)
+    }
+
+    async fn save_parcel(&self, telegram_id: i64, track_code: &str, label: Option<String>) {
+        self.lock().save_parcel(telegram_id, track_code, label)
+    }
+
+    async fn attach_quote(&self, telegram_id: i64, track_code: &str, quote_id: i32) {
+        self.lock().attach_quote(telegram_id, track_code, quote_id)
+    }
+
+    async fn save_parcel_item(&self, telegram_id: i64, track_code: &str, item: &Item) {
+        self.lock().save_parcel_item(telegram_id, track_code, item)
+    }
+
+    async fn get_parcel_items(&self, track_code: &str) -> Vec<ParcelItem> {
+        self.lock().get_parcel_items(track_code)
+    }
+
+    async fn get_parcels(&self, telegram_id: i64) -> Vec<Parcel> {
+        self.lock().get_parcels(telegram_id)
+    }
+
+    async fn get_pending_parcels(&self) -> Vec<PendingParcel> {
+        self.lock().get_pending_parcels()
+    }
+
+    async fn update_parcel_arrived(&self, parcel_id: i32, notice: &Notice) {
+        self.lock().mark_parcel_arrived(parcel_id, notice)
+    }
+
+    async fn set_parcel_photo(&self, track_code: &str, photo_id: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        self.lock().set_parcel_photo(track_code, photo_id, notice)
+    }
+
+    async fn assign_parcel(&self, track_code: &str, batch_code: &str, weight_kg: f32, declared_value: Money, description: Option<&str>, receipt: impl Fn(i32, i64) -> Notice) -> usize {
+        self.lock().assign_parcel(track_code, batch_code, weight_kg, declared_value, description, receipt)
+    }
+
+    async fn get_shipment(&self, telegram_id: i64, track_code: &str) -> Option<Shipment> {
+        self.lock().get_shipment(telegram_id, track_code)
+    }
+
+    async fn get_shipments(&self, telegram_id: i64) -> Vec<Shipment> {
+        self.lock().get_shipments(telegram_id)
+    }
+
+    async fn open_weight_dispute(&self, telegram_id: i64, track_code: &str, text: &str, attachments: &[Attachment]) -> i32 {
+        self.lock().open_weight_dispute(telegram_id, track_code, text, attachments)
+    }
+
+    async fn scan_parcel(&self, track_code: &str, batch_code: &str) -> Vec<String> {
+        self.lock().scan_parcel(track_code, batch_code)
+    }
+
+    async fn get_manifest(&self, batch_code: &str) -> Vec<ManifestRow> {
+        self.lock().get_manifest(batch_code)
+    }
+
+    async fn get_warehouse_labels(&self, track_code: &str) -> Vec<WarehouseLabel> {
+        self.lock().get_warehouse_labels(track_code)
+    }
+
+    async fn shelve_parcel(&self, track_code: &str, shelf: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        self.lock().shelve_parcel(track_code, shelf, notice)
+    }
+
+    async fn get_undelivered_parcels(&self, _track_code: &str) -> Vec<PendingParcel> {
+        Vec::new()
+    }
+
+    async fn get_addresses(&self, telegram_id: i64) -> Vec<Address> {
+        self.lock().get_addresses(telegram_id)
+    }
+
+    async fn add_address(&self, telegram_id: i64, city: &str, street: &str, entrance: Option<&str>, comment: Option<&str>) -> bool {
+        self.lock().add_address(telegram_id, city, street, entrance, comment)
+    }
+
+    async fn delete_address(&self, telegram_id: i64, address_id: i32) -> bool {
+        self.lock().delete_address(telegram_id, address_id)
+    }
+
+    async fn queue_delivery(&self, _parcel_id: i32, _address_id: i32, _require_payment: bool, _notice: impl Fn(&CourierDelivery) -> Notice) -> Option<CourierDelivery> {
+        None
+    }
+
+    async fn get_couriers(&self) -> Vec<Courier> {
+        Vec::new()
+    }
+
+    async fn add_courier(&self, _telegram_id: i64, _name: &str, _added_by: i64) {}
+
+    async fn remove_courier(&self, _telegram_id: i64) -> bool {
+        false
+    }
+
+    async fn is_courier(&self, _telegram_id: i64) -> bool {
+        false
+    }
+
+    async fn get_open_deliveries(&self, _courier_id: Option<i64>) -> Vec<DeliveryTask> {
+        Vec::new()
+    }
+
+    async fn assign_delivery(&self, _delivery_id: i32, _courier_id: i64) -> Option<CourierDelivery> {
+        None
+    }
+
+    async fn complete_delivery(&self, _delivery_id: i32, _courier_id: i64, _photo_id: Option<&str>, _require_payment: bool, _notice: impl Fn(&CourierDelivery) -> Notice) -> DeliveryClosing {
+        DeliveryClosing::NotFound
+    }
+
+    async fn get_shelf(&self, shelf: &str) -> Vec<WarehouseLabel> {
+        self.lock().get_shelf(shelf)
+    }
+
+    async fn get_shelves(&self) -> Vec<(String, i64)> {
+        self.lock().get_shelves()
+    }
+
+    async fn get_setting(&self, key: &str) -> Option<String> {
+        self.lock().get_setting(key)
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) {
+        self.lock().set_setting(key, value)
+    }
+
+    async fn delete_setting(&self, key: &str) {
+        self.lock().delete_setting(key)
+    }
+
+    async fn update_track_code_arrived(&self, track_code: &str, notice: impl Fn(&PendingParcel) -> Notice) -> usize {
+        self.lock().mark_track_code_arrived(track_code, notice)
+    }
+
+    async fn create_purchase_request(&self, telegram_id: i64, marketplace: &str, link: &str, options: Option<&str>, budget: Money) -> i32 {
+        self.lock().create_purchase_request(telegram_id, marketplace, link, options, budget)
+    }
+
+    async fn get_open_purchase_requests(&self) -> Vec<PurchaseRequest> {
+        self.lock().get_open_purchase_requests()
+    }
+
+    async fn get_purchase_request(&self, id: i32) -> Option<PurchaseRequest> {
+        self.lock().get_purchase_request(id)
+    }
+
+    async fn update_purchase_request(&self, id: i32, status: PurchaseStatus, quote: Option<Money>, comment: Option<&str>, notice: impl FnOnce(&PurchaseRequest) -> Notice) -> Option<PurchaseRequest> {
+        self.lock().update_purchase_request(id, status, quote, comment, notice)
+    }
+
+    async fn get_providers(&self) -> Vec<Source> {
+        Vec::new()
+    }
+
+    async fn record_vendor_error(&self, _source: &str, _track_code: &str, _error: &str, _body: &str) {}
+
+    async fn get_idle_users(&self, _idle_days: i32, _cooldown_days: i32, _limit: i64) -> Vec<IdleUser> {
+        Vec::new()
+    }
+
+    async fn reengage(&self, _notice: &Notice) {}
+
+    async fn get_onboarding_users(&self, _window_days: i32) -> Vec<OnboardingUser> {
+        Vec::new()
+    }
+
+    async fn send_onboarding(&self, _notice: &Notice) {}
+
+    async fn get_monthly_spending(&self, _month: NaiveDate, _limit: i64) -> Vec<MonthlySpending> {
+        Vec::new()
+    }
+
+    async fn send_monthly_summary(&self, _month: NaiveDate, _notice: &Notice) {}
+
+    async fn get_monthly_receipts(&self, _telegram_id: i64, _month: NaiveDate) -> Vec<MonthlyReceipt> {
+        Vec::new()
+    }
+
+    async fn get_outbox(&self, limit: i64, max_attempts: i32, window: std::time::Duration) -> Vec<OutboxMessage> {
+        self.lock().get_outbox(limit, max_attempts, chrono::Duration::from_std(window).expect("ERROR: Digest window is too long"))
+    }
+
+    async fn mark_outbox_sent(&self, id: i64, message_id: i32) {
+        self.lock().mark_outbox_sent(id, message_id)
+    }
+
+    async fn get_reacted_subjects(&self, telegram_id: i64, message_id: i32) -> Vec<Subject> {
+        self.lock().get_reacted_subjects(telegram_id, message_id)
+    }
+
+    async fn acknowledge_arrival(&self, telegram_id: i64, track_code: &str) {
+        self.lock().acknowledge_arrival(telegram_id, track_code)
+    }
+
+    async fn save_rating(&self, telegram_id: i64, track_code: &str, stars: i16) -> bool {
+        self.lock().save_rating(telegram_id, track_code, stars)
+    }
+
+    async fn mark_outbox_failed(&self, id: i64) {
+        self.lock().mark_outbox_failed(id)
+    }
+
+    async fn get_partner_secret(&self, _partner_id: &str) -> Option<String> {
+        None
+    }
+
+    async fn claim_webhook_key(&self, _partner_id: &str, _key: &str) -> WebhookClaim {
+        WebhookClaim::New
+    }
+
+    async fn finish_webhook_key(&self, _partner_id: &str, _key: &str, _status: u16) {}
+
+    async fn create_ticket(&self, telegram_id: i64, text: &str, attachments: &[Attachment]) -> i32 {
+        self.lock().create_ticket(telegram_id, text, attachments)
+    }
+
+    async fn get_attachments(&self, ticket_id: i32) -> Vec<Attachment> {
+        self.lock().get_attachments(ticket_id)
+    }
+
+    async fn request_refund(&self, telegram_id: i64, track_code: &str, _reason: &str, text: &str) -> Option<i32> {
+        self.lock().request_refund(telegram_id, track_code, text)
+    }
+
+    async fn set_refund_status(&self, ticket_id: i32, status: RefundStatus, notice: impl FnOnce(i64, &str) -> Notice) -> bool {
+        self.lock().set_refund_status(ticket_id, status, notice)
+    }
+
+    async fn get_open_tickets(&self) -> Vec<Ticket> {
+        self.lock().get_open_tickets()
+    }
+
+    async fn get_user_tickets(&self, telegram_id: i64) -> Vec<Ticket> {
+        self.lock().get_user_tickets(telegram_id)
+    }
+
+    async fn close_ticket(&self, ticket_id: i32) -> bool {
+        self.lock().close_ticket(ticket_id)
+    }
+
+    async fn record_event(&self, telegram_id: i64, kind: EventKind) {
+        self.lock().record_event(telegram_id, kind)
+    }
+
+    async fn record_audit(&self, _admin_id: i64, _admin: &str, _action: &str, _outcome: &str) {}
+
+    async fn get_cohorts(&self, _weeks: i32) -> Vec<Cohort> {
+        Vec::new()
+    }
+
+    async fn get_cohort_activity(&self, _weeks: i32) -> Vec<CohortActivity> {
+        Vec::new()
+    }
+
+    async fn get_welcome_results(&self) -> Vec<ExperimentResult> {
+        Vec::new()
+    }
+
+    async fn get_dialogue(&self, chat_id: i64) -> Option<String> {
+        self.lock().get_dialogue(chat_id)
+    }
+
+    async fn save_dialogue(&self, chat_id: i64, state: &str) {
+        self.lock().save_dialogue(chat_id, state)
+    }
+
+    async fn remove_dialogue(&self, chat_id: i64) {
+        self.lock().remove_dialogue(chat_id)
+    }
+
+    async fn save_start_track_code(&self, telegram_id: i64, track_code: &str) {
+        self.lock().save_start_track_code(telegram_id, track_code)
+    }
+
+    async fn take_start_track_code(&self, telegram_id: i64) -> Option<String> {
+        self.lock().take_start_track_code(telegram_id)
+    }
+
+    async fn add_staff_note(&self, _author_id: i64, author: &str, text: &str) {
+        self.lock().add_staff_note(author, text)
+    }
+
+    async fn get_staff_notes(&self, hours: i32) -> Vec<StaffNote> {
+        self.lock().get_staff_notes(hours)
+    }
+
+    async fn get_pending_disputes(&self) -> Vec<(i32, String)> {
+        self.lock().get_pending_disputes()
+    }
+
+    async fn get_unassigned_parcels(&self) -> Vec<(String, String)> {
+        self.lock().get_unassigned_parcels()
+    }
+
+    async fn request_registration(&self, user: &User) {
+        self.lock().request_registration(user)
+    }
+
+    async fn get_recent_registrations(&self, limit: i64) -> Vec<Registration> {
+        self.lock().get_recent_registrations(limit)
+    }
+
+    async fn get_registration_requests(&self) -> Vec<RegistrationRequest> {
+        self.lock().get_registration_requests()
+    }
+
+    async fn take_registration_request(&self, telegram_id: i64) -> Option<RegistrationRequest> {
+        self.lock().take_registration_request(telegram_id)
+    }
+
+    async fn request_account_claim(&self, user_id: i32, profile: &User) -> AccountClaim {
+        self.lock().request_account_claim(user_id, profile)
+    }
+
+    async fn get_account_claims(&self) -> Vec<AccountClaim> {
+        self.lock().get_account_claims()
+    }
+
+    async fn approve_account_claim(&self, id: i32, _operator_id: i64, notice: impl FnOnce(i64, &AccountClaim) -> Notice) -> Option<AccountClaim> {
+        self.lock().approve_account_claim(id, notice)
+    }
+
+    async fn reject_account_claim(&self, id: i32, _operator_id: i64) -> Option<AccountClaim> {
+        self.lock().reject_account_claim(id)
+    }
+
+    async fn save_canned_response(&self, slug: &str, text: &str) {
+        self.lock().save_canned_response(slug, text)
+    }
+
+    async fn get_canned_responses(&self) -> Vec<CannedResponse> {
+        self.lock().get_canned_responses()
+    }
+
+    async fn get_canned_response(&self, slug: &str) -> Option<String> {
+        self.lock().get_canned_response(slug)
+    }
+
+    async fn record_canned_use(&self, slug: &str, _ticket_id: i32, operator_id: i64, operator: &str) {
+        self.lock().record_canned_use(slug, operator_id, operator)
+    }
+
+    async fn get_canned_usage(&self) -> Vec<CannedUsage> {
+        self.lock().get_canned_usage()
+    }
+
+    async fn get_ticket_user(&self, ticket_id: i32) -> Option<User> {
+        self.lock().get_ticket_user(ticket_id)
+    }
+
+    async fn get_tariffs(&self) -> Vec<Tariff> {
+        self.lock().get_tariffs()
+    }
+
+    async fn get_user_tariffs(&self, telegram_id: i64) -> Vec<Tariff> {
+        self.lock().get_user_tariffs(telegram_id)
+    }
+
+    async fn save_tariff(&self, tariff: &Tariff) {
+        self.lock().save_tariff(tariff)
+    }
+
+    async fn delete_tariff(&self, min_density: f32) -> bool {
+        self.lock().delete_tariff(min_density)
+    }
+
+    async fn save_quote(&self, quote: &Quote) -> i32 {
+        self.lock().save_quote(quote)
+    }
+
+    async fn get_quotes(&self, telegram_id: i64, limit: i64) -> Vec<Quote> {
+        self.lock().get_quotes(telegram_id, limit)
+    }
+
+    async fn get_quote(&self, id: i32) -> Option<Quote> {
+        self.lock().get_quote(id)
+    }
+
+    async fn get_quote_accuracy(&self, _days: i32) -> Vec<QuoteAccuracy> {
+        Vec::new()
+    }
+
+    async fn get_calibrations(&self, _limit: i64) -> Vec<Calibration> {
+        Vec::new()
+    }
+
+    async fn save_box_preset(&self, telegram_id: i64, width: f32, length: f32, height: f32, limit: i64) -> bool {
+        self.lock().save_box_preset(telegram_id, width, length, height, limit)
+    }
+
+    async fn get_box_presets(&self, telegram_id: i64) -> Vec<BoxPreset> {
+        self.lock().get_box_presets(telegram_id)
+    }
+
+    async fn get_box_preset(&self, telegram_id: i64, id: i32) -> Option<BoxPreset> {
+        self.lock().get_box_preset(telegram_id, id)
+    }
+
+    async fn mark_batch_delivered(&self, batch_code: &str, require_payment: bool) -> (u64, Vec<String>) {
+        self.lock().mark_batch_delivered(batch_code, require_payment)
+    }
+
+    async fn create_batch(&self, code: &str) -> bool {
+        self.lock().create_batch(code)
+    }
+
+    async fn get_batch(&self, code: &str) -> Option<Batch> {
+        self.lock().get_batch(code)
+    }
+
+    async fn get_open_batches(&self) -> Vec<Batch> {
+        self.lock().get_open_batches()
+    }
+
+    async fn get_user_batches(&self, telegram_id: i64) -> Vec<Batch> {
+        self.lock().get_user_batches(telegram_id)
+    }
+
+    async fn get_batch_events(&self, code: &str) -> Vec<BatchEvent> {
+        self.lock().get_batch_events(code)
+    }
+
+    async fn advance_batch(&self, code: &str, from: BatchStatus, status: BatchStatus, notice: impl Fn(i64, &[String]) -> Notice) -> Option<usize> {
+        self.lock().advance_batch(code, from, status, notice)
+    }
+
+    async fn has_permission(&self, telegram_id: i64, permission: Permission) -> bool {
+        self.lock().has_permission(telegram_id, permission)
+    }
+
+    async fn grant_permission(&self, telegram_id: i64, permission: Permission, _granted_by: i64) -> bool {
+        self.lock().grant_permission(telegram_id, permission)
+    }
+
+    async fn revoke_permission(&self, telegram_id: i64, permission: Permission) -> bool {
+        self.lock().revoke_permission(telegram_id, permission)
+    }
+
+    async fn get_staff(&self) -> Vec<i64> {
+        self.lock().get_staff()
+    }
+
+    async fn seed_staff(&self, telegram_ids: &[i64]) -> bool {
+        self.lock().seed_staff(telegram_ids)
+    }
+
+    async fn create_staff_invite(&self, code: &str, created_by: i64, valid_hours: i32) {
+        self.lock().create_staff_invite(code, created_by, valid_hours)
+    }
+
+    async fn redeem_staff_invite(&self, code: &str, telegram_id: i64) -> Option<i64> {
+        self.lock().redeem_staff_invite(code, telegram_id)
+    }
+
+    async fn remove_staff(&self, telegram_id: i64) -> bool {
+        self.lock().remove_staff(telegram_id)
+    }
+
+    async fn save_error_report(&self, report: &ErrorReport) -> i32 {
+        self.lock().save_error_report(report)
+    }
+
+    async fn log_export(&self, telegram_id: i64, kind: &str, row_count: usize) -> i32 {
+        self.lock().log_export(telegram_id, kind, row_count)
+    }
+
+    async fn update_invoices(&self, track_code: &str, amount: Money, _calibration: &CalibrationPolicy, notice: impl Fn(&Invoice) -> Notice) -> usize {
+        self.lock().set_invoice(track_code, amount, notice)
+    }
+
+    async fn get_invoice(&self, parcel_id: i32) -> Option<Invoice> {
+        self.lock().get_invoice(parcel_id)
+    }
+
+    async fn get_invoices(&self, track_code: &str) -> Vec<Invoice> {
+        self.lock().get_invoices(track_code)
+    }
+
+    async fn record_payment(&self, parcel_id: i32, amount: Money, charge_id: Option<&str>, _recorded_by: Option<i64>, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        self.lock().record_payment(parcel_id, amount, charge_id, notice)
+    }
+
+    async fn override_price(&self, parcel_id: i32, change: &PriceOverride, operator_id: i64, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        self.lock().override_price(parcel_id, change, operator_id, notice)
+    }
+
+    async fn get_override_totals(&self, days: i32) -> Vec<OverrideTotals> {
+        self.lock().get_override_totals(days)
+    }
+
+    async fn open_city(&self, city: &NewCity) -> Option<i32> {
+        self.lock().open_city(city)
+    }
+
+    async fn get_cities(&self) -> Vec<(i32, String)> {
+        self.lock().get_cities()
+    }
+
+    async fn get_pickup_points(&self) -> Vec<(String, String)> {
+        self.lock().get_pickup_points()
+    }
+
+    async fn get_planned_etas(&self) -> Vec<(String, i32, i32)> {
+        self.lock().get_planned_etas()
+    }
+
+    async fn get_delivery_history(&self, _days: i32) -> Vec<(Option<String>, i32)> {
+        Vec::new()
+    }
+
+    async fn apply_retention(&self, _policy: &RetentionPolicy, dry_run: bool) -> RetentionRun {
+        RetentionRun { dry_run, events: 0, tickets: 0, parcels: 0, ran_at: bishkek_now() }
+    }
+
+    async fn get_retention_runs(&self, _limit: i64) -> Vec<RetentionRun> {
+        Vec::new()
+    }
+
+    async fn get_active_track_codes(&self) -> Vec<ActiveTrackCode> {
+        Vec::new()
+    }
+
+    async fn save_reconciliation(&self, _checked: i32, _failed: i32, _discrepancies: &[Discrepancy]) {}
+
+    async fn get_last_reconciliation(&self) -> Option<ReconciliationRun> {
+        None
+    }
+
+    async fn get_discrepancies(&self, _run_id: i32) -> Vec<Discrepancy> {
+        Vec::new()
+    }
+
+    async fn refresh_warehouse_stats(&self, _days: i32) -> u64 {
+        0
+    }
+
+    async fn get_warehouse_stats(&self, _days: i32) -> Vec<WarehouseDay> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{attachments::AttachmentKind, fixtures::{self, user}, models::{InvoiceStatus, OverrideReason}};
+
+    use super::*;
+
+    fn notice(parcel: &PendingParcel) -> Notice {
+        fixtures::notice(parcel.telegram_id, parcel.track_code.clone())
+    }
+
+    #[test]
+    fn users_get_sequential_client_codes() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+
+        assert_eq!(memory.get_user(2).client_code, client_code::generate(201));
+        assert_eq!(memory.find_user_by_client_code(&client_code::generate(200)).and_then(|user| user.telegram_id), Some(1));
+    }
+
+    #[test]
+    fn a_repeated_sign_up_gets_the_same_client_code() {
+        let mut memory = Memory::default();
+        let walk_in = User { telegram_id: None, ..user(0) };
+
+        let (client_code, created) = memory.get_or_create_by_phone(walk_in.clone());
+        assert!(created);
+
+        assert_eq!(memory.get_or_create_by_phone(User { phone_number: "0555 123 456".to_string(), ..walk_in.clone() }), (client_code, false));
+        assert!(memory.get_or_create_by_phone(User { phone_number: "996700000001".to_string(), ..walk_in }).1);
+    }
+
+    #[test]
+    fn claimed_account_moves_with_its_parcels() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.save_parcel(1, "YT1", None);
+
+        let mut profile = user(2);
+        profile.username = Some("new_account".to_string());
+
+        let claim = memory.request_account_claim(1, &profile);
+        assert_eq!(claim.old_telegram_id, Some(1));
+        assert_eq!(memory.get_account_claims(), vec![claim.clone()]);
+
+        let notice = |telegram_id, claim: &AccountClaim| fixtures::notice(telegram_id, claim.client_code.clone());
+        assert_eq!(memory.approve_account_claim(claim.id, notice), Some(claim.clone()));
+
+        assert!(memory.find_user(1).is_none());
+        let moved = memory.get_user(2);
+        assert_eq!((moved.client_code, moved.username.as_deref()), (claim.client_code.clone(), Some("new_account")));
+        assert_eq!(memory.get_parcels(2).len(), 1);
+
+        // Decided claims are gone from the queue and can not be approved twice.
+        assert!(memory.get_account_claims().is_empty());
+        assert_eq!(memory.approve_account_claim(claim.id, notice), None);
+    }
+
+    #[test]
+    fn a_merge_moves_addresses_and_purchase_requests() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+        memory.save_parcel(2, "YT1", None);
+        memory.add_address(2, "Бишкек", "Киевская 1", None, None);
+        let request_id = memory.create_purchase_request(2, "Taobao", "https://item.taobao.com/1", None, Money::from_cents(100000));
+
+        let (survivor, duplicate) = (memory.get_user(1), memory.get_user(2));
+
+        assert_eq!(memory.merge_users(&survivor, &duplicate), Ok(1));
+        assert_eq!(memory.get_addresses(1).len(), 1);
+        assert!(memory.get_addresses(2).is_empty());
+
+        let request = memory.get_purchase_request(request_id).unwrap();
+        assert_eq!((request.telegram_id, request.client_code), (1, survivor.client_code));
+        assert_eq!(memory.get_open_purchase_requests().len(), 1);
+    }
+
+    #[test]
+    fn a_merge_keeps_a_paid_duplicate_parcel() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+        memory.save_parcel(1, "YT1", None);
+        memory.save_parcel(2, "YT1", None);
+        memory.save_parcel(2, "YT2", None);
+
+        let (survivor, duplicate) = (memory.get_user(1), memory.get_user(2));
+
+        memory.set_invoice("YT1", Money::from_cents(50000), |invoice| fixtures::notice(invoice.telegram_id, invoice.track_code.clone()));
+        let paid = memory.get_invoices("YT1").into_iter().find(|invoice| invoice.telegram_id == 2).unwrap();
+        memory.record_payment(paid.parcel_id, Money::from_cents(50000), Some("charge"), |invoice| fixtures::notice(invoice.telegram_id, invoice.track_code.clone()));
+
+        assert_eq!(memory.merge_users(&survivor, &duplicate), Err(vec!["YT1".to_string()]));
+        assert_eq!(memory.get_parcels(2).len(), 2);
+        assert!(memory.get_invoice(paid.parcel_id).is_some());
+    }
+
+    #[test]
+    fn registering_twice_keeps_the_first_user() {
+        let mut memory = Memory::default();
+
+        let (first, created) = memory.get_or_create(user(1));
+        assert!(created);
+
+        let (second, created) = memory.get_or_create(User { first_name: "Бакыт".to_string(), ..user(1) });
+        assert!(!created);
+        assert_eq!(second.client_code, first.client_code);
+        assert_eq!(second.first_name, "Азамат");
+
+        assert!(memory.find_user(2).is_none());
+    }
+
+    #[test]
+    fn walk_in_clients_get_a_code_but_no_messages() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+
+        let client_code = memory.create_user(User { telegram_id: None, ..user(0) });
+
+        assert_eq!(client_code, client_code::generate(201));
+        assert_eq!(memory.get_segment_ids(&Segment::default()), vec![1]);
+        assert_eq!(memory.count_reachable(), 1);
+    }
+
+    #[test]
+    fn city_segment_matches_users_who_picked_the_city() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+
+        memory.set_city(1, "Ош");
+
+        let segment = Segment { filters: vec![Filter::City("ош".to_string())] };
+
+        assert_eq!(memory.get_segment_ids(&segment), vec![1]);
+    }
+
+    #[test]
+    fn inactive_segment_skips_users_active_lately() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+
+        memory.record_event(2, EventKind::Active);
+
+        let segment = Segment { filters: vec![Filter::InactiveDays(30)] };
+
+        assert_eq!(memory.get_segment_ids(&segment), vec![1]);
+    }
+
+    #[test]
+    fn weight_disputes_keep_their_photos() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+
+        let photo = Attachment { kind: AttachmentKind::Photo, file_id: "photo".to_string(), mime_type: "image/jpeg".to_string(), size: 0 };
+        let ticket_id = memory.open_weight_dispute(1, "MX201", "Вес завышен", std::slice::from_ref(&photo));
+
+        assert_eq!(memory.get_attachments(ticket_id), vec![photo]);
+    }
+
+    #[test]
+    fn sms_codes_wait_for_the_cooldown_and_the_daily_limit() {
+        let mut memory = Memory::default();
+        let limits = SmsLimits { cooldown_seconds: 0, daily_per_phone: 2, daily_per_user: 2 };
+
+        assert!(memory.reserve_sms(1, "996700123456", &limits));
+        assert!(memory.reserve_sms(2, "0700 123 456", &limits));
+        assert!(!memory.reserve_sms(3, "+996700123456", &limits));
+
+        assert!(memory.reserve_sms(1, "996700000001", &limits));
+        assert!(!memory.reserve_sms(1, "996700000002", &limits));
+
+        let limits = SmsLimits { cooldown_seconds: 60, ..SmsLimits::default() };
+
+        assert!(memory.reserve_sms(4, "996555000001", &limits));
+        assert!(!memory.reserve_sms(4, "996555000002", &limits));
+        assert!(!memory.reserve_sms(5, "996555000001", &limits));
+    }
+
+    #[test]
+    fn arrival_goes_through_the_outbox_once() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.save_parcel(1, "YT123", None);
+
+        assert_eq!(memory.mark_track_code_arrived("YT123", notice), 1);
+        assert_eq!(memory.mark_track_code_arrived("YT123", notice), 0);
+
+        let outbox = memory.get_outbox(10, 5, Duration::zero());
+        assert_eq!(outbox.len(), 1);
+
+        memory.mark_outbox_sent(outbox[0].id, 77);
+        assert!(memory.get_outbox(10, 5, Duration::zero()).is_empty());
+    }
+
+    #[test]
+    fn attached_quote_keeps_the_parcel_label() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.save_parcel(1, "YT1", Some("кроссовки".to_string()));
+
+        memory.attach_quote(1, "YT1", 5);
+        memory.attach_quote(1, "YT2", 6);
+
+        let parcels = memory.get_parcels(1);
+        assert_eq!(parcels[0].label.as_deref(), Some("кроссовки"));
+        assert_eq!(parcels[0].quote_id, Some(5));
+        assert_eq!(parcels[1].quote_id, Some(6));
+    }
+
+    #[test]
+    fn city_opens_once_with_a_broadcast_draft() {
+        let mut memory = Memory::default();
+        let city = NewCity {
+            name: "Ош".to_string(),
+            tariffs: Vec::new(),
+            eta_days: (14, 18),
+            pickup_point: "ул. Ленина 1".to_string(),
+            announcement: "Открыли Ош".to_string()
+        };
+
+        assert_eq!(memory.open_city(&city), Some(1));
+        assert_eq!(memory.open_city(&NewCity { name: "ош".to_string(), ..city }), None);
+        assert_eq!(memory.get_planned_etas(), vec![("Ош".to_string(), 14, 18)]);
+        assert_eq!(memory.take_broadcast(1), Some(("city:Ош".to_string(), "Открыли Ош".to_string())));
+    }
+
+    #[test]
+    fn only_the_owner_rates_a_parcel() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+        memory.save_parcel(1, "YT123", None);
+
+        assert!(memory.save_rating(1, "YT123", 5));
+        assert!(!memory.save_rating(2, "YT123", 1));
+        assert!(!memory.save_rating(1, "YT999", 1));
+    }
+
+    #[test]
+    fn quotes_use_the_tariffs_of_the_users_city() {
+        let mut memory = Memory::default();
+        let general = Tariff { min_density: 0.0, price_per_kg: Money::from_cents(350), min_charge: Money::from_cents(500) };
+        let local = Tariff { min_density: 0.0, price_per_kg: Money::from_cents(420), min_charge: Money::from_cents(600) };
+
+        memory.save_tariff(&general);
+        memory.open_city(&NewCity {
+            name: "Ош".to_string(),
+            tariffs: vec![local.clone()],
+            eta_days: (14, 18),
+            pickup_point: "ул. Ленина 1".to_string(),
+            announcement: "Открыли Ош".to_string()
+        });
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+        memory.set_city(1, "ош");
+
+        assert_eq!(memory.get_user_tariffs(1), vec![local]);
+        assert_eq!(memory.get_user_tariffs(2), vec![general]);
+    }
+
+    #[test]
+    fn only_paid_parcels_are_handed_over_when_payment_is_required() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.save_parcel(1, "YT1", None);
+        memory.save_parcel(1, "YT2", None);
+        memory.save_parcel(1, "YT3", None);
+
+        let notice = |invoice: &Invoice| fixtures::notice(invoice.telegram_id, "");
+
+        for track_code in ["YT1", "YT2", "YT3"] {
+            memory.assign_parcel(track_code, "B1", 1.0, Money::from_cents(1000), None, |_, telegram_id| fixtures::notice(telegram_id, ""));
+        }
+
+        assert_eq!(memory.set_invoice("YT1", Money::from_cents(1500), notice), 1);
+        assert_eq!(memory.set_invoice("YT2", Money::from_cents(1500), notice), 1);
+
+        let invoice = memory.record_payment(1, Money::from_cents(1500), Some("charge"), notice).unwrap();
+        assert_eq!(invoice.status(), InvoiceStatus::Paid);
+        assert!(memory.record_payment(1, Money::from_cents(1500), Some("charge"), notice).is_none());
+
+        let invoice = memory.record_payment(2, Money::from_cents(500), None, notice).unwrap();
+        assert_eq!(invoice.status(), InvoiceStatus::PartiallyPaid);
+        assert_eq!(invoice.due(), Money::from_cents(1000));
+        assert!(memory.record_payment(2, Money::from_cents(1001), None, notice).is_none());
+        assert!(memory.record_payment(3, Money::from_cents(500), None, notice).is_none());
+
+        assert_eq!(memory.mark_batch_delivered("B1", true), (1, vec!["YT2".to_string(), "YT3".to_string()]));
+        assert_eq!(memory.mark_batch_delivered("B1", false), (2, Vec::new()));
+    }
+
+    #[test]
+    fn price_overrides_keep_the_original_and_add_up_per_operator() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.save_parcel(1, "YT1", None);
+
+        let notice = |invoice: &Invoice| fixtures::notice(invoice.telegram_id, "");
+        memory.set_invoice("YT1", Money::from_cents(2000), notice);
+
+        let discount = PriceOverride { original: Money::from_cents(2000), final_price: Money::from_cents(1500), reason: OverrideReason::Delay };
+        assert_eq!(memory.override_price(1, &discount, 10, notice).unwrap().amount, Money::from_cents(1500));
+
+        // Overriding a price that changed in between does nothing.
+        assert!(memory.override_price(1, &discount, 10, notice).is_none());
+
+        let penalty = PriceOverride { original: Money::from_cents(1500), final_price: Money::from_cents(1800), reason: OverrideReason::Oversize };
+        memory.override_price(1, &penalty, 10, notice).unwrap();
+
+        assert_eq!(memory.get_override_totals(30), vec![OverrideTotals {
+            operator_id: 10,
+            overrides: 2,
+            discounts: Money::from_cents(500),
+            penalties: Money::from_cents(300)
+        }]);
+    }
+
+    #[test]
+    fn export_permission_is_granted_once_and_revoked() {
+        let mut memory = Memory::default();
+
+        assert!(!memory.has_permission(7, Permission::Export));
+        assert!(memory.grant_permission(7, Permission::Export));
+        assert!(!memory.grant_permission(7, Permission::Export));
+        assert!(memory.has_permission(7, Permission::Export));
+        assert!(!memory.has_permission(8, Permission::Export));
+
+        assert!(memory.revoke_permission(7, Permission::Export));
+        assert!(!memory.revoke_permission(7, Permission::Export));
+        assert_eq!(memory.log_export(7, "clients", 12), 1);
+    }
+
+    #[test]
+    fn purchase_request_leaves_the_queue_once_bought() {
+        let mut memory = Memory::default();
+        memory.create_user(user(7));
+
+        let id = memory.create_purchase_request(7, "taobao.com", "https://item.taobao.com/item.htm?id=1", Some("42, черный"), Money::from_cents(5000));
+        let text = |request: &PurchaseRequest| fixtures::notice(request.telegram_id, request.status.title());
+
+        let quoted = memory.update_purchase_request(id, PurchaseStatus::Quoted, Some(Money::from_cents(4500)), None, text).unwrap();
+        assert_eq!(quoted.quote, Some(Money::from_cents(4500)));
+        assert_eq!(memory.get_open_purchase_requests().len(), 1);
+
+        let purchased = memory.update_purchase_request(id, PurchaseStatus::Purchased, None, Some("заказ 123"), text).unwrap();
+        assert_eq!(purchased.quote, Some(Money::from_cents(4500)));
+        assert_eq!(purchased.comment.as_deref(), Some("заказ 123"));
+        assert!(memory.get_open_purchase_requests().is_empty());
+
+        assert!(memory.update_purchase_request(id, PurchaseStatus::Declined, None, None, text).is_none());
+        assert_eq!(memory.get_outbox(10, 5, Duration::zero()).len(), 2);
+    }
+
+    #[test]
+    fn only_the_latest_box_presets_are_kept() {
+        let mut memory = Memory::default();
+
+        assert!(memory.save_box_preset(7, 40.0, 60.0, 35.0, 2));
+        assert!(!memory.save_box_preset(7, 40.0, 60.0, 35.0, 2));
+        assert!(memory.save_box_preset(7, 30.0, 30.0, 30.0, 2));
+        assert!(memory.save_box_preset(7, 20.0, 20.0, 20.0, 2));
+        assert!(memory.save_box_preset(8, 10.0, 10.0, 10.0, 2));
+
+        let presets = memory.get_box_presets(7);
+        assert_eq!(presets.iter().map(BoxPreset::title).collect::<Vec<_>>(), vec!["Коробка 20×20×20", "Коробка 30×30×30"]);
+
+        assert!(memory.get_box_preset(8, presets[0].id).is_none());
+        assert_eq!(memory.get_box_preset(7, presets[0].id), Some(presets[0].clone()));
+    }
+
+    #[test]
+    fn batch_status_reaches_every_client_once() {
+        let mut memory = Memory::default();
+        memory.create_user(user(1));
+        memory.create_user(user(2));
+        memory.save_parcel(1, "YT1", None);
+        memory.save_parcel(1, "YT2", None);
+        memory.save_parcel(2, "YT3", None);
+
+        assert!(memory.create_batch("B1"));
+        assert!(!memory.create_batch("B1"));
+
+        for track_code in ["YT2", "YT1", "YT3"] {
+            memory.scan_parcel(track_code, "B1");
+        }
+
+        let notice = |telegram_id, track_codes: &[String]| fixtures::notice(telegram_id, track_codes.join(","));
+        assert_eq!(memory.advance_batch("B1", BatchStatus::Forming, BatchStatus::Departed, notice), Some(2));
+        assert_eq!(memory.advance_batch("B1", BatchStatus::Forming, BatchStatus::Departed, notice), None);
+
+        let batch = memory.get_batch("B1").unwrap();
+        assert_eq!((batch.status, batch.parcels), (BatchStatus::Departed, 3));
+
+        let outbox = memory.get_outbox(10, 5, Duration::zero());
+        assert_eq!(outbox.iter().map(|message| (message.telegram_id, message.text.as_str())).collect::<Vec<_>>(), vec![(1, "YT1,YT2"), (2, "YT3")]);
+
+        assert_eq!(memory.get_user_batches(2).len(), 1);
+        assert!(memory.get_user_batches(3).is_empty());
+
+        memory.advance_batch("B1", BatchStatus::Departed, BatchStatus::Arrived, notice);
+        assert!(memory.get_open_batches().is_empty());
+
+        let events = memory.get_batch_events("B1");
+        assert_eq!(events.iter().map(|event| event.status).collect::<Vec<_>>(), vec![BatchStatus::Forming, BatchStatus::Departed, BatchStatus::Arrived]);
+    }
+
+    #[test]
+    fn staff_invite_is_redeemed_once() {
+        let mut memory = Memory::default();
+
+        assert!(memory.seed_staff(&[1]));
+        assert!(!memory.seed_staff(&[2]));
+
+        memory.create_staff_invite("ABCD2345", 1, 24);
+        memory.create_staff_invite("EXPIRED2", 1, -1);
+
+        assert_eq!(memory.redeem_staff_invite("ABCD2345", 7), Some(1));
+        assert_eq!(memory.redeem_staff_invite("ABCD2345", 8), None);
+        assert_eq!(memory.redeem_staff_invite("EXPIRED2", 8), None);
+        assert_eq!(memory.get_staff(), vec![1, 7]);
+
+        memory.grant_permission(7, Permission::Export);
+
+        assert!(memory.remove_staff(7));
+        assert!(!memory.remove_staff(7));
+        assert!(!memory.has_permission(7, Permission::Export));
+    }
+
+    #[test]
+    fn survey_is_sent_and_answered_once() {
+        let mut memory = Memory::default();
+
+        let survey_id = memory.create_survey("Качество");
+        memory.add_survey_question(survey_id, "Как доставка?", &["Отлично".to_string(), "Плохо".to_string()]);
+        assert_eq!(memory.add_survey_question(survey_id, "Что улучшить?", &[]), 2);
+
+        let notice = |telegram_id| fixtures::notice(telegram_id, "");
+
+        assert_eq!(memory.send_survey(survey_id, &[1, 2], notice), Some(2));
+        assert_eq!(memory.send_survey(survey_id, &[1, 2], notice), None);
+        assert!(memory.get_survey(survey_id).unwrap().sent);
+
+        assert!(memory.save_survey_answer(survey_id, 1, 1, Some(0), None));
+        assert!(!memory.save_survey_answer(survey_id, 1, 1, Some(1), None));
+        assert!(memory.save_survey_answer(survey_id, 2, 1, None, Some("Быстрее")));
+        assert!(!memory.save_survey_answer(survey_id, 1, 3, Some(0), None));
+
+        assert_eq!(memory.get_survey_answers(survey_id).len(), 2);
+    }
+}
@@ -0,0 +1,2570 @@
+use chrono::NaiveDate;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{query, query_as, query_scalar, PgConnection, PgExecutor, PgPool, Postgres, QueryBuilder};
+
+use crate::attachments::{Attachment, AttachmentKind};
+use crate::calibration::CalibrationPolicy;
+use crate::catalog::Item;
+use crate::client_code;
+use crate::config::AppEnv;
+use crate::correlation;
+use crate::crypto::{self, PhoneCipher};
+use crate::duplicates;
+use crate::experiments::WelcomeVariant;
+use crate::money::Money;
+use crate::onboarding::OnboardingStep;
+use crate::retention::RetentionPolicy;
+use crate::segment::Segment;
+use crate::sms::SmsLimits;
+use crate::vendor::Source;
+use crate::models::{AccountClaim, ActiveTrackCode, Address, Batch, BatchEvent, BatchStatus, BoxPreset, Calibration, CannedResponse, CannedUsage, Cohort, CohortActivity, Courier, CourierDelivery, DeliveryClosing, DeliveryStatus, DeliveryTask, Discrepancy, DriftKind, ErrorReport, EventKind, ExperimentResult, IdleUser, Invoice, ManifestRow, MonthlyReceipt, MonthlySpending, NewCity, Notice, OnboardingUser, OutboxMessage, OverrideTotals, Parcel, ParcelItem, PendingParcel, Permission, PriceOverride, PurchaseRequest, PurchaseStatus, Quote, QuoteAccuracy, RefundStatus, Registration, ReconciliationRun, RegistrationRequest, RetentionRun, Shipment, StaffNote, Subject, Survey, SurveyAnswer, SurveyQuestion, Tariff, Ticket, Tutorial, Units, User, WarehouseDay, WarehouseLabel, WebhookClaim, MAX_ADDRESSES};
+
+use super::{Store, TARIFF_VERSION_KEY};
+
+const REPORTS_CONNECTIONS: u32 = 2;
+
+/// The store the bot runs on, phone numbers are encrypted before they get here.
+#[derive(Clone)]
+pub(super) struct PgStore {
+    pool: PgPool,
+    // Admin reports only, so a long one never takes the connections handlers wait for.
+    // Anything the notifier, the HTTP server or a client's request reads stays on `pool`.
+    reports: PgPool,
+    cipher: PhoneCipher
+}
+
+impl PgStore {
+    pub(super) async fn connect() -> PgStore {
+        let pg_user = std::env::var("POSTGRES_USER").expect("ERROR: Could not get POSTGRES_USER");
+        let pg_password = std::env::var("POSTGRES_PASSWORD").expect("ERROR: Could not get POSTGRES_PASSWORD");
+        let pg_host = std::env::var("POSTGRES_HOST").expect("ERROR: Could not get POSTGRES_HOST");
+        let pg_port = std::env::var("POSTGRES_PORT").expect("ERROR: Could not get POSTGRES_PORT");
+        // Staging keeps its own database on the same server.
+        let pg_db = AppEnv::current().postgres_db();
+
+        let opt = PgConnectOptions::new()
+            .host(&pg_host)
+            .port(pg_port.parse().expect("ERROR: Could not parse port string into u16"))
+            .database(&pg_db)
+            .username(&pg_user)
+            .password(&pg_password);
+
+        let pool = PgPool::connect_with(opt.clone()).await.expect("ERROR: Could not connect the database");
+
+        sqlx::migrate!().run(&pool).await.expect("ERROR: Could not run migrations");
+
+        // A read replica when there is one, reports may then lag a little behind.
+        let reports_opt = match AppEnv::current().reports_database_url() {
+            Some(url) => url.parse().expect("ERROR: Could not parse REPORTS_DATABASE_URL"),
+            None => opt
+        };
+
+        let reports = PgPoolOptions::new()
+            .max_connections(REPORTS_CONNECTIONS)
+            .connect_with(reports_opt.application_name("max_express_bot_reports"))
+            .await.expect("ERROR: Could not connect the reports database");
+
+        let store = PgStore { pool, reports, cipher: PhoneCipher::from_env() };
+
+        store.encrypt_plaintext_phones().await;
+        store.hash_phones().await;
+        store.import_tutorial_texts().await;
+
+        store
+    }
+
+    async fn insert_user_with(&self, conn: &mut PgConnection, mut new_user: User) -> String {
+        new_user.client_code = Self::next_client_code(conn).await;
+
+        query!("INSERT INTO users (first_name, last_name, phone_number, telegram_id, client_code, username, display_name, phone_hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8);",
+            new_user.first_name, new_user.last_name, self.cipher.encrypt(&new_user.phone_number), new_user.telegram_id, &new_user.client_code, new_user.username, new_user.display_name,
+            self.phone_hash(&new_user.phone_number))
+            .execute(&mut *conn)
+            .await.expect("ERROR: Could not create a user");
+
+        if let Some(telegram_id) = new_user.telegram_id {
+            Self::insert_event(conn, telegram_id, EventKind::Registered).await;
+        }
+
+        new_user.client_code
+    }
+
+    /// A client code nobody else gets, even when two people register at the same moment.
+    async fn next_client_code(conn: &mut PgConnection) -> String {
+        let number: i64 = query_scalar!(r#"SELECT nextval('client_code_numbers') AS "number!";"#)
+            .fetch_one(conn)
+            .await.expect("ERROR: Could not get a client code number");
+
+        client_code::generate(number)
+    }
+
+    fn phone_hash(&self, phone_number: &str) -> String {
+        self.cipher.blind_index(&duplicates::phone_key(phone_number))
+    }
+
+    fn decrypt_user(&self, mut user: User) -> User {
+        user.phone_number = self.decrypt_phone(&user.phone_number);
+        user
+    }
+
+    /// A phone number that does not decrypt is logged and shown as stored, one bad row does not stop a whole list.
+    fn decrypt_phone(&self, stored: &str) -> String {
+        self.cipher.decrypt(stored).unwrap_or_else(|err| {
+            log::error!("ERROR: Could not decrypt a phone number: {}", err);
+            stored.to_string()
+        })
+    }
+
+    /// Encrypts phone numbers stored before encryption was introduced.
+    async fn encrypt_plaintext_phones(&self) {
+        let rows = query!("SELECT id, phone_number FROM users WHERE NOT starts_with(phone_number, $1);", crypto::PREFIX)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get plaintext phone numbers");
+
+        if rows.is_empty() {
+            return;
+        }
+
+        log::info!("Encrypting {} plaintext phone numbers", rows.len());
+
+        for row in rows {
+            query!("UPDATE users SET phone_number = $2 WHERE id = $1;",
+                row.id, self.cipher.encrypt(&row.phone_number))
+                .execute(&self.pool)
+                .await.expect("ERROR: Could not encrypt a phone number");
+        }
+    }
+
+    /// Hashes the phones of users stored before phone hashes were introduced.
+    async fn hash_phones(&self) {
+        let rows = query!("SELECT id, phone_number FROM users WHERE phone_hash IS NULL;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get users without a phone hash");
+
+        if rows.is_empty() {
+            return;
+        }
+
+        log::info!("Hashing {} phone numbers", rows.len());
+
+        for row in rows {
+            let phone_number = match self.cipher.decrypt(&row.phone_number) {
+                Ok(phone_number) => phone_number,
+                Err(err) => {
+                    log::error!("ERROR: Could not hash the phone number of user {}: {}", row.id, err);
+                    continue;
+                }
+            };
+
+            query!("UPDATE users SET phone_hash = $2 WHERE id = $1;",
+                row.id, self.phone_hash(&phone_number))
+                .execute(&self.pool)
+                .await.expect("ERROR: Could not hash a phone number");
+        }
+    }
+
+    /// Fills tutorials that have no text yet from `HELP_<SLUG>` variables.
+    async fn import_tutorial_texts(&self) {
+        let slugs: Vec<String> = query_scalar!("SELECT slug FROM tutorials WHERE text = '';")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get tutorials");
+
+        for slug in slugs {
+            if let Ok(text) = std::env::var(format!("HELP_{}", slug.to_uppercase())) {
+                log::info!("Importing the {} tutorial from the environment", slug);
+
+                query!("UPDATE tutorials SET text = $2 WHERE slug = $1;", &slug, text)
+                    .execute(&self.pool)
+                    .await.expect("ERROR: Could not import a tutorial");
+            }
+        }
+    }
+
+    async fn add_shelf<'e>(executor: impl PgExecutor<'e>, shelf: &str) {
+        query!("INSERT INTO shelves (code) VALUES ($1) ON CONFLICT DO NOTHING;", shelf)
+            .execute(executor)
+            .await.expect("ERROR: Could not add a shelf");
+    }
+
+    /// Writes a message to the outbox within the caller's transaction, the relay delivers it after commit.
+    async fn enqueue(conn: &mut PgConnection, notice: &Notice) {
+        let markup = notice.markup.as_ref()
+            .map(|markup| serde_json::to_string(markup).expect("ERROR: Could not serialize a keyboard"));
+
+        query!("INSERT INTO outbox (telegram_id, text, markup, photo_id, digest, subject, track_code) VALUES ($1, $2, $3, $4, $5, $6, $7);",
+            notice.telegram_id, &notice.text, markup, notice.photo_id.as_deref(), notice.digest.as_deref(),
+            notice.subject.as_ref().map(Subject::kind), notice.subject.as_ref().map(Subject::track_code))
+            .execute(conn)
+            .await.expect("ERROR: Could not write to the outbox");
+    }
+
+    async fn attach(tx: &mut PgConnection, ticket_id: i32, attachments: &[Attachment]) {
+        for attachment in attachments {
+            query!("INSERT INTO attachments (ticket_id, kind, file_id, mime_type, size_bytes) VALUES ($1, $2, $3, $4, $5);",
+                ticket_id, attachment.kind.as_str(), attachment.file_id, attachment.mime_type, attachment.size as i32)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not save an attachment");
+        }
+    }
+
+    /// Records an event in the transaction of the change it is about, so neither is kept without the other.
+    async fn insert_event(conn: &mut PgConnection, telegram_id: i64, kind: EventKind) {
+        query!("INSERT INTO events (telegram_id, kind, correlation_id) VALUES ($1, $2, $3);",
+            telegram_id, kind.as_str(), correlation::current().map(|id| id.to_string()))
+            .execute(conn)
+            .await.expect("ERROR: Could not record an event");
+    }
+
+    /// Gives the account of a claim to the new Telegram account: the client code and parcels go with the user row,
+    /// the history kept by telegram id is moved here and the old account is told about it.
+    ///
+    /// None when the account was deleted or the new Telegram account has registered meanwhile, the caller rolls back.
+    async fn move_account(tx: &mut PgConnection, id: i32, notice: impl FnOnce(i64, &AccountClaim) -> Notice) -> Option<AccountClaim> {
+        let claim = query_as!(AccountClaim, r#"SELECT c.id, c.user_id, u.client_code, u.first_name, u.last_name, u.telegram_id AS old_telegram_id,
+                c.telegram_id, c.username, c.display_name
+            FROM account_claims c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.id = $1 AND u.deleted_at IS NULL
+            FOR UPDATE OF u;"#, id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not get an account claim")?;
+
+        let moved = query!("UPDATE users SET telegram_id = $2, username = $3, display_name = $4, blocked_at = NULL
+            WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM users WHERE telegram_id = $2 AND deleted_at IS NULL);",
+            claim.user_id, claim.telegram_id, claim.username, claim.display_name)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not move an account")
+            .rows_affected();
+
+        if moved == 0 {
+            return None;
+        }
+
+        // A client registered at the counter has no history in the bot.
+        let old_telegram_id = match claim.old_telegram_id {
+            Some(old_telegram_id) => old_telegram_id,
+            None => return Some(claim)
+        };
+
+        let (old, new) = (old_telegram_id, claim.telegram_id);
+
+        // The old chat could be left halfway through a flow, its user is gone now.
+        query!("DELETE FROM dialogues WHERE chat_id = $1;", old)
+            .execute(&mut *tx).await.expect("ERROR: Could not remove the old dialogue");
+
+        query!("UPDATE tickets SET telegram_id = $2 WHERE telegram_id = $1;", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move tickets");
+        query!("UPDATE tickets_archive SET telegram_id = $2 WHERE telegram_id = $1;", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move archived tickets");
+        query!("UPDATE quotes SET telegram_id = $2 WHERE telegram_id = $1;", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move quotes");
+        query!("UPDATE events SET telegram_id = $2 WHERE telegram_id = $1;", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move events");
+        query!("UPDATE outbox SET telegram_id = $2 WHERE telegram_id = $1 AND sent_at IS NULL;", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move pending notices");
+
+        // Rows keyed by telegram id can be there already when the new account used the bot before, those are kept.
+        query!("UPDATE box_presets o SET telegram_id = $2 WHERE o.telegram_id = $1
+            AND NOT EXISTS (SELECT 1 FROM box_presets n WHERE n.telegram_id = $2 AND n.width = o.width AND n.length = o.length AND n.height = o.height);", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move box presets");
+        query!("UPDATE ratings o SET telegram_id = $2 WHERE o.telegram_id = $1
+            AND NOT EXISTS (SELECT 1 FROM ratings n WHERE n.telegram_id = $2 AND n.track_code = o.track_code);", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move ratings");
+        query!("UPDATE monthly_summaries o SET telegram_id = $2 WHERE o.telegram_id = $1
+            AND NOT EXISTS (SELECT 1 FROM monthly_summaries n WHERE n.telegram_id = $2 AND n.month = o.month);", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move monthly summaries");
+        query!("UPDATE survey_answers o SET telegram_id = $2 WHERE o.telegram_id = $1
+            AND NOT EXISTS (SELECT 1 FROM survey_answers n WHERE n.telegram_id = $2 AND n.survey_id = o.survey_id AND n.position = o.position);", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move survey answers");
+        query!("UPDATE survey_recipients o SET telegram_id = $2 WHERE o.telegram_id = $1
+            AND NOT EXISTS (SELECT 1 FROM survey_recipients n WHERE n.telegram_id = $2 AND n.survey_id = o.survey_id);", old, new)
+            .execute(&mut *tx).await.expect("ERROR: Could not move survey recipients");
+
+        Self::enqueue(tx, &notice(old_telegram_id, &claim)).await;
+
+        Some(claim)
+    }
+
+    // Quotes keep the version they were priced with, so a changed price list is told apart from a wrong quote.
+    async fn bump_tariff_version(conn: &mut PgConnection) {
+        query!("INSERT INTO settings (key, value) VALUES ($1, '1')
+            ON CONFLICT (key) DO UPDATE SET value = (settings.value::int + 1)::text;", TARIFF_VERSION_KEY)
+            .execute(conn)
+            .await.expect("ERROR: Could not bump the tariff version");
+    }
+
+    /// Compares a freshly billed parcel with the quote the client attached to it.
+    ///
+    /// A bill within the threshold clears an earlier record, the parcel may have been billed again after a correction.
+    async fn calibrate(conn: &mut PgConnection, policy: &CalibrationPolicy, parcel_id: i32, billed: Money) {
+        let quote = query_as!(Quote, r#"SELECT q.id, q.telegram_id, q.width, q.length, q.height, q.weight_kg, q.price_cents AS "price: Money", q.tariff_version,
+                q.created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM parcels p
+            JOIN quotes q ON q.id = p.quote_id
+            WHERE p.id = $1;"#, parcel_id)
+            .fetch_optional(&mut *conn)
+            .await.expect("ERROR: Could not get a parcel quote");
+
+        let quote = match quote {
+            Some(quote) => quote,
+            None => return
+        };
+
+        match quote.price {
+            Some(quoted) if policy.is_off(quoted, billed) => {
+                log::info!("Calibration: parcel {} quoted {} billed {}", parcel_id, quoted, billed);
+
+                query!("INSERT INTO quote_calibrations (parcel_id, quote_id, tariff_version, quoted_cents, billed_cents, quoted_weight_kg, actual_weight_kg, density)
+                    SELECT id, $2, $3, $4, $5, $6, weight_kg, $7 FROM parcels WHERE id = $1
+                    ON CONFLICT (parcel_id) DO UPDATE
+                    SET billed_cents = EXCLUDED.billed_cents, actual_weight_kg = EXCLUDED.actual_weight_kg, created_at = now();",
+                    parcel_id, quote.id, quote.tariff_version, quoted as Money, billed as Money, quote.weight_kg, quote.density())
+                    .execute(conn)
+                    .await.expect("ERROR: Could not save a calibration");
+            },
+            _ => {
+                query!("DELETE FROM quote_calibrations WHERE parcel_id = $1;", parcel_id)
+                    .execute(conn)
+                    .await.expect("ERROR: Could not delete a calibration");
+            }
+        }
+    }
+}
+
+impl Store for PgStore {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        query!("SELECT 1 AS one;").fetch_one(&self.pool).await.map(|_| ())
+    }
+
+    async fn insert_user(&self, new_user: User) -> String {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let client_code = self.insert_user_with(&mut tx, new_user).await;
+
+        tx.commit().await.expect("ERROR: Could not create a user");
+
+        client_code
+    }
+
+    async fn upsert_by_phone(&self, new_user: User) -> (String, bool) {
+        let phone_hash = self.phone_hash(&new_user.phone_number);
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // Two sign-ups with one phone at once wait for each other here, the second finds the first.
+        query!("SELECT pg_advisory_xact_lock(hashtext($1));", phone_hash)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not lock a phone");
+
+        let existing = query_scalar!("SELECT client_code FROM users WHERE phone_hash = $1 AND deleted_at IS NULL ORDER BY id LIMIT 1;", phone_hash)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not find a user by phone");
+
+        if let Some(client_code) = existing {
+            return (client_code, false);
+        }
+
+        let client_code = self.insert_user_with(&mut tx, new_user).await;
+
+        tx.commit().await.expect("ERROR: Could not create a user");
+
+        (client_code, true)
+    }
+
+    async fn upsert_user(&self, profile: User) -> (User, bool) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // A registration that finds the user already there leaves a gap in the numbers, codes stay unique.
+        let client_code = Self::next_client_code(&mut tx).await;
+
+        // The no-op update makes RETURNING give the existing row, client codes are unique so it tells the two apart.
+        let user = query_as!(User, r#"INSERT INTO users (first_name, last_name, phone_number, telegram_id, client_code, username, display_name, phone_hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (telegram_id) WHERE deleted_at IS NULL DO UPDATE SET telegram_id = EXCLUDED.telegram_id
+            RETURNING id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city;"#,
+            profile.first_name, profile.last_name, self.cipher.encrypt(&profile.phone_number), profile.telegram_id, client_code, profile.username, profile.display_name,
+            self.phone_hash(&profile.phone_number))
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not get or create a user");
+
+        let created = user.client_code == client_code;
+
+        if let (true, Some(telegram_id)) = (created, user.telegram_id) {
+            Self::insert_event(&mut tx, telegram_id, EventKind::Registered).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not get or create a user");
+
+        (self.decrypt_user(user), created)
+    }
+
+    async fn find_user(&self, telegram_id: i64) -> Option<User> {
+        query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users WHERE telegram_id = $1 AND deleted_at IS NULL;"#, telegram_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not find user")
+            .map(|user| self.decrypt_user(user))
+    }
+
+    async fn get_user(&self, telegram_id: i64) -> User {
+        let user = query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users WHERE telegram_id = $1 AND deleted_at IS NULL;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get user")[0].clone();
+
+        self.decrypt_user(user)
+    }
+
+    async fn find_user_by_client_code(&self, client_code: &str) -> Option<User> {
+        query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users WHERE client_code = $1 AND deleted_at IS NULL;"#, client_code)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not find user by client code")
+            .map(|user| self.decrypt_user(user))
+    }
+
+    async fn existing_client_codes(&self, client_codes: &[String]) -> Vec<String> {
+        query_scalar!("SELECT client_code FROM users WHERE client_code = ANY($1) AND deleted_at IS NULL ORDER BY client_code;", client_codes)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not check client codes")
+    }
+
+    async fn update_telegram_profile(&self, telegram_id: i64, username: Option<&str>, display_name: &str) {
+        query!("UPDATE users SET username = $2, display_name = $3, blocked_at = NULL
+            WHERE telegram_id = $1 AND deleted_at IS NULL
+                AND (username IS DISTINCT FROM $2 OR display_name IS DISTINCT FROM $3 OR blocked_at IS NOT NULL);", telegram_id, username, display_name)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update telegram profile");
+    }
+
+    async fn mark_unreachable(&self, telegram_id: i64) {
+        query!("UPDATE users SET blocked_at = now() WHERE telegram_id = $1 AND blocked_at IS NULL;", telegram_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not mark a user unreachable");
+    }
+
+    async fn mark_reachable(&self, telegram_id: i64) {
+        query!("UPDATE users SET blocked_at = NULL WHERE telegram_id = $1 AND blocked_at IS NOT NULL;", telegram_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not mark a user reachable");
+    }
+
+    async fn count_reachable(&self) -> i64 {
+        query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE telegram_id IS NOT NULL AND blocked_at IS NULL AND deleted_at IS NULL;"#)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not count reachable users")
+    }
+
+    async fn count_unreachable(&self) -> i64 {
+        query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE blocked_at IS NOT NULL AND deleted_at IS NULL;"#)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not count unreachable users")
+    }
+
+    async fn set_text_menu(&self, telegram_id: i64, text_menu: bool) {
+        query!("UPDATE users SET text_menu = $2 WHERE telegram_id = $1 AND deleted_at IS NULL;", telegram_id, text_menu)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update the menu mode");
+    }
+
+    async fn set_reminders(&self, telegram_id: i64, reminders: bool) {
+        query!("UPDATE users SET reminders = $2 WHERE telegram_id = $1 AND deleted_at IS NULL;", telegram_id, reminders)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update reminders");
+    }
+
+    async fn set_monthly_summary(&self, telegram_id: i64, monthly_summary: bool) {
+        query!("UPDATE users SET monthly_summary = $2 WHERE telegram_id = $1 AND deleted_at IS NULL;", telegram_id, monthly_summary)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update the monthly summary");
+    }
+
+    async fn set_units(&self, telegram_id: i64, units: Units) {
+        query!("UPDATE users SET units = $2 WHERE telegram_id = $1 AND deleted_at IS NULL;", telegram_id, units.as_str())
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update units");
+    }
+
+    async fn set_city(&self, telegram_id: i64, city: &str) {
+        query!("UPDATE users SET city = $2 WHERE telegram_id = $1 AND deleted_at IS NULL;", telegram_id, city)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not update the city");
+    }
+
+    async fn find_users(&self, search: &str, limit: i64) -> Vec<User> {
+        let search = search.trim().trim_start_matches('@');
+
+        query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users
+            WHERE deleted_at IS NULL AND (lower(username) = lower($1)
+                OR client_code = upper($1)
+                OR first_name ILIKE '%' || $1 || '%'
+                OR last_name ILIKE '%' || $1 || '%'
+                OR display_name ILIKE '%' || $1 || '%')
+            ORDER BY id
+            LIMIT $2;"#, search, limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not find users")
+            .into_iter()
+            .map(|user| self.decrypt_user(user))
+            .collect()
+    }
+
+    async fn get_users(&self) -> Vec<User> {
+        query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users WHERE deleted_at IS NULL ORDER BY id;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get users")
+            .into_iter()
+            .map(|user| self.decrypt_user(user))
+            .collect()
+    }
+
+    async fn merge_users(&self, survivor: &User, duplicate: &User) -> Result<u64, Vec<String>> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // Dropping a parcel cascades to its money and claims, so a duplicate that has any stops the merge.
+        let kept_history = query_scalar!("SELECT d.track_code FROM parcels d
+            JOIN parcels s ON s.user_id = $1 AND s.track_code = d.track_code
+            WHERE d.user_id = $2 AND (d.paid_cents > 0
+                OR EXISTS (SELECT 1 FROM payments WHERE parcel_id = d.id)
+                OR EXISTS (SELECT 1 FROM refunds WHERE parcel_id = d.id)
+                OR EXISTS (SELECT 1 FROM weight_disputes WHERE parcel_id = d.id)
+                OR EXISTS (SELECT 1 FROM price_overrides WHERE parcel_id = d.id)
+                OR EXISTS (SELECT 1 FROM deliveries WHERE parcel_id = d.id)
+                OR EXISTS (SELECT 1 FROM quote_calibrations WHERE parcel_id = d.id))
+            ORDER BY d.track_code
+            FOR UPDATE OF d;", survivor.id, duplicate.id)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not check duplicate parcels");
+
+        if !kept_history.is_empty() {
+            return Err(kept_history);
+        }
+
+        query!("DELETE FROM parcels d
+            USING parcels s
+            WHERE d.user_id = $2 AND s.user_id = $1 AND s.track_code = d.track_code;", survivor.id, duplicate.id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not drop duplicate parcels");
+
+        let moved = query!("UPDATE parcels SET user_id = $1 WHERE user_id = $2;", survivor.id, duplicate.id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not move parcels")
+            .rows_affected();
+
+        query!("UPDATE addresses SET user_id = $1 WHERE user_id = $2;", survivor.id, duplicate.id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not move addresses");
+
+        query!("UPDATE purchase_requests SET user_id = $1 WHERE user_id = $2;", survivor.id, duplicate.id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not move purchase requests");
+
+        query!("UPDATE users SET deleted_at = now(), merged_into = $1 WHERE id = $2;", survivor.id, duplicate.id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not delete the duplicate user");
+
+        tx.commit().await.expect("ERROR: Could not merge users");
+
+        Ok(moved)
+    }
+
+    async fn find_phone_owner(&self, telegram_id: Option<i64>, phone_number: &str) -> Option<User> {
+        query_as!(User, r#"SELECT id, first_name, last_name, phone_number, telegram_id, client_code, username, display_name, text_menu, reminders, monthly_summary, units AS "units: Units", city
+            FROM users WHERE phone_hash = $1 AND deleted_at IS NULL AND telegram_id IS DISTINCT FROM $2
+            ORDER BY id LIMIT 1;"#, self.phone_hash(phone_number), telegram_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not find a user by phone")
+            .map(|user| self.decrypt_user(user))
+    }
+
+    async fn reserve_sms(&self, telegram_id: i64, phone_number: &str, limits: &SmsLimits) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // Two codes requested at once would both pass the counts otherwise.
+        query!("LOCK TABLE sms_sends IN SHARE ROW EXCLUSIVE MODE;")
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not lock SMS sends");
+
+        let reserved = query!("INSERT INTO sms_sends (telegram_id, phone_hash)
+            SELECT $1, $2
+            WHERE NOT EXISTS (SELECT 1 FROM sms_sends WHERE (telegram_id = $1 OR phone_hash = $2) AND sent_at > now() - make_interval(secs => $3))
+                AND (SELECT COUNT(*) FROM sms_sends WHERE phone_hash = $2 AND sent_at > now() - interval '1 day') < $4
+                AND (SELECT COUNT(*) FROM sms_sends WHERE telegram_id = $1 AND sent_at > now() - interval '1 day') < $5;",
+            telegram_id, self.phone_hash(phone_number), limits.cooldown_seconds as f64, limits.daily_per_phone, limits.daily_per_user)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not reserve an SMS")
+            .rows_affected() > 0;
+
+        tx.commit().await.expect("ERROR: Could not reserve an SMS");
+
+        reserved
+    }
+
+    async fn get_tutorials(&self) -> Vec<Tutorial> {
+        query_as!(Tutorial, "SELECT slug, title, text, app_url, web_url FROM tutorials WHERE text <> '' ORDER BY position, slug;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get tutorials")
+    }
+
+    async fn get_tutorial(&self, slug: &str) -> Option<Tutorial> {
+        query_as!(Tutorial, "SELECT slug, title, text, app_url, web_url FROM tutorials WHERE slug = $1 AND text <> '';", slug)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a tutorial")
+    }
+
+    async fn save_tutorial(&self, tutorial: &Tutorial) {
+        query!("INSERT INTO tutorials (slug, title, text, position)
+            VALUES ($1, $2, $3, (SELECT COALESCE(MAX(position), 0) + 1 FROM tutorials))
+            ON CONFLICT (slug) DO UPDATE SET title = EXCLUDED.title, text = EXCLUDED.text;",
+            &tutorial.slug, &tutorial.title, &tutorial.text)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a tutorial");
+    }
+
+    async fn set_tutorial_links(&self, slug: &str, app_url: Option<&str>, web_url: Option<&str>) -> bool {
+        query!("UPDATE tutorials SET app_url = $2, web_url = $3 WHERE slug = $1;", slug, app_url, web_url)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not set tutorial links")
+            .rows_affected() > 0
+    }
+
+    async fn delete_tutorial(&self, slug: &str) -> bool {
+        query!("DELETE FROM tutorials WHERE slug = $1;", slug)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not delete a tutorial")
+            .rows_affected() > 0
+    }
+
+    async fn check_user(&self, telegram_id: i64) -> bool {
+        query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM users WHERE telegram_id = $1 AND deleted_at IS NULL) AS "exists!";"#, telegram_id)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not check the user")
+    }
+
+    async fn get_segment_ids(&self, segment: &Segment) -> Vec<i64> {
+        // Filters differ per segment, so unlike the rest this query is built at runtime.
+        let mut builder = QueryBuilder::<Postgres>::new("SELECT u.telegram_id FROM users u WHERE u.telegram_id IS NOT NULL AND u.deleted_at IS NULL AND u.blocked_at IS NULL");
+        segment.push_conditions(&mut builder);
+
+        builder.build_query_scalar()
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get telegram ids")
+    }
+
+    async fn count_segment(&self, segment: &Segment) -> i64 {
+        let mut builder = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM users u WHERE u.telegram_id IS NOT NULL AND u.deleted_at IS NULL AND u.blocked_at IS NULL");
+        segment.push_conditions(&mut builder);
+
+        builder.build_query_scalar()
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not count a segment")
+    }
+
+    async fn create_broadcast(&self, filters: &str, text: &str) -> i32 {
+        query_scalar!("INSERT INTO broadcasts (filters, text) VALUES ($1, $2) RETURNING id;", filters, text)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not create a broadcast")
+    }
+
+    async fn take_broadcast(&self, id: i32) -> Option<(String, String)> {
+        query!("UPDATE broadcasts SET sent_at = now() WHERE id = $1 AND sent_at IS NULL RETURNING filters, text;", id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not take a broadcast")
+            .map(|row| (row.filters, row.text))
+    }
+
+    async fn create_survey(&self, title: &str, created_by: i64) -> i32 {
+        query_scalar!("INSERT INTO surveys (title, created_by) VALUES ($1, $2) RETURNING id;", title, created_by)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not create a survey")
+    }
+
+    async fn get_survey(&self, id: i32) -> Option<Survey> {
+        let survey = query!("SELECT id, title, sent_at IS NOT NULL AS \"sent!\" FROM surveys WHERE id = $1;", id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a survey")?;
+
+        let questions = query_as!(SurveyQuestion, "SELECT position, text, options FROM survey_questions WHERE survey_id = $1 ORDER BY position;", id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get survey questions");
+
+        Some(Survey { id: survey.id, title: survey.title, sent: survey.sent, questions })
+    }
+
+    async fn add_survey_question(&self, survey_id: i32, text: &str, options: &[String]) -> i16 {
+        query_scalar!(r#"INSERT INTO survey_questions (survey_id, position, text, options)
+            SELECT $1, COALESCE(MAX(position), 0) + 1, $2, $3 FROM survey_questions WHERE survey_id = $1
+            RETURNING position;"#, survey_id, text, options)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not add a survey question")
+    }
+
+    async fn send_survey(&self, survey_id: i32, telegram_ids: &[i64], notice: impl Fn(i64) -> Notice) -> Option<usize> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("UPDATE surveys SET sent_at = now() WHERE id = $1 AND sent_at IS NULL RETURNING id;", survey_id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not send a survey")?;
+
+        query!("INSERT INTO survey_recipients (survey_id, telegram_id) SELECT $1, unnest($2::BIGINT[]) ON CONFLICT DO NOTHING;", survey_id, telegram_ids)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not save survey recipients");
+
+        for telegram_id in telegram_ids {
+            Self::enqueue(&mut tx, &notice(*telegram_id)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not send a survey");
+
+        Some(telegram_ids.len())
+    }
+
+    async fn is_survey_recipient(&self, survey_id: i32, telegram_id: i64) -> bool {
+        query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM survey_recipients WHERE survey_id = $1 AND telegram_id = $2) AS "exists!";"#, survey_id, telegram_id)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not check a survey recipient")
+    }
+
+    async fn save_survey_answer(&self, survey_id: i32, position: i16, telegram_id: i64, option: Option<i16>, text: Option<&str>) -> bool {
+        query!("INSERT INTO survey_answers (survey_id, position, telegram_id, option, text)
+            SELECT survey_id, $2, telegram_id, $4, $5 FROM survey_recipients WHERE survey_id = $1 AND telegram_id = $3
+            ON CONFLICT DO NOTHING;", survey_id, position, telegram_id, option, text)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a survey answer")
+            .rows_affected() > 0
+    }
+
+    async fn get_survey_answers(&self, survey_id: i32) -> Vec<SurveyAnswer> {
+        query_as!(SurveyAnswer, "SELECT position, option, text FROM survey_answers WHERE survey_id = $1 ORDER BY answered_at;", survey_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get survey answers")
+    }
+
+    async fn save_parcel(&self, telegram_id: i64, track_code: &str, label: Option<String>) {
+        query!("INSERT INTO parcels (user_id, track_code, label)
+            SELECT id, $2, $3 FROM users WHERE telegram_id = $1 AND deleted_at IS NULL
+            ON CONFLICT (user_id, track_code) DO UPDATE SET label = EXCLUDED.label;", telegram_id, track_code, label)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a parcel");
+
+        // The first parcel after a reminder counts as its conversion.
+        query!("INSERT INTO events (telegram_id, kind, correlation_id)
+            SELECT telegram_id, $2, $3 FROM users u
+            WHERE u.telegram_id = $1 AND u.reengaged_at IS NOT NULL
+                AND NOT EXISTS (SELECT 1 FROM events e WHERE e.telegram_id = $1 AND e.kind = $2 AND e.created_at >= u.reengaged_at);",
+            telegram_id, EventKind::ReengagedParcel.as_str(), correlation::current().map(|id| id.to_string()))
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not record a re-engagement conversion");
+
+        query!("INSERT INTO events (telegram_id, kind, correlation_id)
+            SELECT $1, $2, $3 WHERE NOT EXISTS (SELECT 1 FROM events WHERE telegram_id = $1 AND kind = $2);",
+            telegram_id, EventKind::Onboarding(OnboardingStep::TrackCode).as_str(), correlation::current().map(|id| id.to_string()))
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not record an onboarding step");
+    }
+
+    async fn attach_quote(&self, telegram_id: i64, track_code: &str, quote_id: i32) {
+        query!("INSERT INTO parcels (user_id, track_code, quote_id)
+            SELECT id, $2, $3 FROM users WHERE telegram_id = $1 AND deleted_at IS NULL
+            ON CONFLICT (user_id, track_code) DO UPDATE SET quote_id = EXCLUDED.quote_id;", telegram_id, track_code, quote_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not attach a quote");
+    }
+
+    async fn save_parcel_item(&self, telegram_id: i64, track_code: &str, item: &Item) {
+        query!("UPDATE parcels p SET item_url = $3, item_title = $4, item_price = $5, item_image = $6
+            FROM users u
+            WHERE u.id = p.user_id AND u.telegram_id = $1 AND p.track_code = $2;",
+            telegram_id, track_code, &item.url, item.title.as_deref(), item.price.as_deref(), item.image.as_deref())
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a parcel item");
+    }
+
+    async fn get_parcel_items(&self, track_code: &str) -> Vec<ParcelItem> {
+        query_as!(ParcelItem, r#"SELECT u.client_code, p.item_url AS "url!", p.item_title AS title, p.item_price AS price
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.track_code = $1 AND p.item_url IS NOT NULL;"#, track_code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get parcel items")
+    }
+
+    async fn get_parcels(&self, telegram_id: i64) -> Vec<Parcel> {
+        query_as!(Parcel, r#"SELECT p.id, p.track_code, p.label, p.arrived, r.status AS "refund: RefundStatus", p.quote_id FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            LEFT JOIN refunds r ON r.parcel_id = p.id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL
+            ORDER BY p.created_at;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get parcels")
+    }
+
+    async fn get_pending_parcels(&self) -> Vec<PendingParcel> {
+        // Parcels are saved from Telegram, so their owners always have a telegram id.
+        query_as!(PendingParcel, r#"SELECT p.id, p.track_code, p.label, u.telegram_id AS "telegram_id!", p.photo_id, u.city FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE NOT p.arrived;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get pending parcels")
+    }
+
+    async fn update_parcel_arrived(&self, parcel_id: i32, notice: &Notice) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("UPDATE parcels SET arrived = TRUE, arrived_at = now() WHERE id = $1;", parcel_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not mark a parcel arrived");
+
+        Self::enqueue(&mut tx, notice).await;
+
+        tx.commit().await.expect("ERROR: Could not mark a parcel arrived");
+    }
+
+    async fn set_parcel_photo(&self, track_code: &str, photo_id: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let updated = query!("UPDATE parcels SET photo_id = $2 WHERE upper(track_code) = upper($1);", track_code, photo_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not attach a parcel photo")
+            .rows_affected();
+
+        let arrived = query_as!(PendingParcel, r#"SELECT p.id, p.track_code, p.label, u.telegram_id AS "telegram_id!", p.photo_id, u.city FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE upper(p.track_code) = upper($1) AND p.arrived;"#, track_code)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not attach a parcel photo");
+
+        for parcel in &arrived {
+            Self::enqueue(&mut tx, &notice(parcel)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not attach a parcel photo");
+
+        updated
+    }
+
+    async fn assign_parcel(&self, track_code: &str, batch_code: &str, weight_kg: f32, declared_value: Money, description: Option<&str>, receipt: impl Fn(i32, i64) -> Notice) -> usize {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let owners = query!(r#"UPDATE parcels p SET batch_code = $2, weight_kg = $3, declared_value_cents = $4,
+                declared_description = COALESCE($5, declared_description)
+            FROM users u
+            WHERE u.id = p.user_id AND p.track_code = $1
+            RETURNING p.id, u.telegram_id AS "telegram_id!";"#,
+            track_code, batch_code, weight_kg, declared_value as Money, description)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not assign a parcel to a batch");
+
+        for owner in &owners {
+            Self::enqueue(&mut tx, &receipt(owner.id, owner.telegram_id)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not assign a parcel to a batch");
+
+        owners.len()
+    }
+
+    async fn get_shipment(&self, telegram_id: i64, track_code: &str) -> Option<Shipment> {
+        query_as!(Shipment, r#"SELECT p.track_code, p.batch_code, p.weight_kg, p.declared_value_cents AS "declared_value: Money",
+                COALESCE(p.declared_description, p.label) AS description
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL AND p.track_code = $2;"#, telegram_id, track_code)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a shipment")
+    }
+
+    async fn get_shipments(&self, telegram_id: i64) -> Vec<Shipment> {
+        query_as!(Shipment, r#"SELECT p.track_code, p.batch_code, p.weight_kg, p.declared_value_cents AS "declared_value: Money",
+                COALESCE(p.declared_description, p.label) AS description
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL
+            ORDER BY p.created_at;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get shipments")
+    }
+
+    async fn open_weight_dispute(&self, telegram_id: i64, track_code: &str, text: &str, attachments: &[Attachment]) -> i32 {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let ticket_id: i32 = query_scalar!("INSERT INTO tickets (telegram_id, text) VALUES ($1, $2) RETURNING id;", telegram_id, text)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not create a ticket");
+
+        query!("INSERT INTO weight_disputes (ticket_id, parcel_id)
+            SELECT $3, p.id FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL AND p.track_code = $2;",
+            telegram_id, track_code, ticket_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not open a weight dispute");
+
+        Self::attach(&mut tx, ticket_id, attachments).await;
+
+        tx.commit().await.expect("ERROR: Could not open a weight dispute");
+
+        ticket_id
+    }
+
+    async fn scan_parcel(&self, track_code: &str, batch_code: &str) -> Vec<String> {
+        query_scalar!("UPDATE parcels p SET batch_code = $2, scanned_at = COALESCE(p.scanned_at, now())
+            FROM users u
+            WHERE u.id = p.user_id AND upper(p.track_code) = upper($1)
+            RETURNING u.client_code;", track_code, batch_code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not scan a parcel")
+    }
+
+    async fn get_manifest(&self, batch_code: &str) -> Vec<ManifestRow> {
+        query_as!(ManifestRow, r#"SELECT p.track_code, u.client_code,
+                COALESCE(p.declared_description, p.label) AS description,
+                p.declared_value_cents AS "declared_value: Money", p.weight_kg
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.batch_code = $1
+            ORDER BY u.client_code, p.track_code;"#, batch_code)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get a manifest")
+    }
+
+    async fn get_warehouse_labels(&self, track_code: &str) -> Vec<WarehouseLabel> {
+        query_as!(WarehouseLabel, "SELECT p.track_code, u.client_code, u.first_name, u.last_name, u.city, p.shelf
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.deleted_at IS NULL AND p.track_code = $1
+            ORDER BY u.client_code;", track_code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get warehouse labels")
+    }
+
+    async fn shelve_parcel(&self, track_code: &str, shelf: &str, notice: impl Fn(&PendingParcel) -> Notice) -> u64 {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        Self::add_shelf(&mut *tx, shelf).await;
+
+        let shelved = query_as!(PendingParcel, r#"UPDATE parcels p SET shelf = $2
+            FROM users u
+            WHERE u.id = p.user_id AND u.deleted_at IS NULL AND upper(p.track_code) = upper($1) AND p.delivered_at IS NULL
+            RETURNING p.id, p.track_code, p.label, u.telegram_id AS "telegram_id!", p.photo_id, u.city;"#, track_code, shelf)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not shelve a parcel");
+
+        for parcel in &shelved {
+            Self::enqueue(&mut tx, &notice(parcel)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not shelve a parcel");
+
+        shelved.len() as u64
+    }
+
+    async fn get_undelivered_parcels(&self, track_code: &str) -> Vec<PendingParcel> {
+        query_as!(PendingParcel, r#"SELECT p.id, p.track_code, p.label, u.telegram_id AS "telegram_id!", p.photo_id, u.city
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.deleted_at IS NULL AND upper(p.track_code) = upper($1) AND p.delivered_at IS NULL
+            ORDER BY p.id;"#, track_code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get undelivered parcels")
+    }
+
+    async fn get_addresses(&self, telegram_id: i64) -> Vec<Address> {
+        query_as!(Address, "SELECT a.id, a.city, a.street, a.entrance, a.comment
+            FROM addresses a
+            JOIN users u ON u.id = a.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL
+            ORDER BY a.id;", telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get addresses")
+    }
+
+    async fn add_address(&self, telegram_id: i64, city: &str, street: &str, entrance: Option<&str>, comment: Option<&str>) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // Locking the user makes two addresses saved at once count each other.
+        let user_id = query_scalar!("SELECT id FROM users WHERE telegram_id = $1 AND deleted_at IS NULL FOR UPDATE;", telegram_id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not get a user");
+
+        let user_id = match user_id {
+            Some(user_id) => user_id,
+            None => return false
+        };
+
+        let added = query!("INSERT INTO addresses (user_id, city, street, entrance, comment)
+            SELECT $1, $2, $3, $4, $5
+            WHERE (SELECT COUNT(*) FROM addresses WHERE user_id = $1) < $6;",
+            user_id, city, street, entrance, comment, MAX_ADDRESSES as i64)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not add an address")
+            .rows_affected() > 0;
+
+        tx.commit().await.expect("ERROR: Could not add an address");
+
+        added
+    }
+
+    async fn delete_address(&self, telegram_id: i64, address_id: i32) -> bool {
+        query!("DELETE FROM addresses a
+            USING users u
+            WHERE u.id = a.user_id AND u.telegram_id = $1 AND a.id = $2;", telegram_id, address_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not delete an address")
+            .rows_affected() > 0
+    }
+
+    async fn queue_delivery(&self, parcel_id: i32, address_id: i32, require_payment: bool, notice: impl Fn(&CourierDelivery) -> Notice) -> Option<CourierDelivery> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let address = query_as!(Address, "SELECT a.id, a.city, a.street, a.entrance, a.comment
+            FROM addresses a
+            JOIN parcels p ON p.user_id = a.user_id
+            WHERE p.id = $1 AND a.id = $2 AND p.arrived_at IS NOT NULL AND p.delivered_at IS NULL
+                AND (NOT $3 OR p.paid_cents >= p.invoice_cents)
+            FOR UPDATE OF p;", parcel_id, address_id, require_payment)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not get a delivery address")?;
+
+        let delivery = query_as!(CourierDelivery, r#"WITH delivery AS (
+                INSERT INTO deliveries (parcel_id, address) VALUES ($1, $2)
+                ON CONFLICT DO NOTHING
+                RETURNING id, parcel_id, address
+            )
+            SELECT d.id, p.track_code, u.telegram_id AS "telegram_id!", d.address
+            FROM delivery d
+            JOIN parcels p ON p.id = d.parcel_id
+            JOIN users u ON u.id = p.user_id;"#, parcel_id, address.line())
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not queue a delivery")?;
+
+        Self::enqueue(&mut tx, &notice(&delivery)).await;
+
+        tx.commit().await.expect("ERROR: Could not queue a delivery");
+
+        Some(delivery)
+    }
+
+    async fn get_couriers(&self) -> Vec<Courier> {
+        query_as!(Courier, r#"SELECT c.telegram_id, c.name, count(d.id) AS "open_tasks!"
+            FROM couriers c
+            LEFT JOIN deliveries d ON d.courier_id = c.telegram_id AND d.status = 'assigned'
+            GROUP BY c.telegram_id
+            ORDER BY c.name;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get couriers")
+    }
+
+    async fn add_courier(&self, telegram_id: i64, name: &str, added_by: i64) {
+        query!("INSERT INTO couriers (telegram_id, name, added_by) VALUES ($1, $2, $3)
+            ON CONFLICT (telegram_id) DO UPDATE SET name = EXCLUDED.name;", telegram_id, name, added_by)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not add a courier");
+    }
+
+    async fn remove_courier(&self, telegram_id: i64) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("UPDATE deliveries SET status = 'queued', courier_id = NULL, updated_at = now()
+            WHERE courier_id = $1 AND status = 'assigned';", telegram_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not return deliveries to the queue");
+
+        let removed = query!("DELETE FROM couriers WHERE telegram_id = $1;", telegram_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not remove a courier")
+            .rows_affected() > 0;
+
+        tx.commit().await.expect("ERROR: Could not remove a courier");
+
+        removed
+    }
+
+    async fn is_courier(&self, telegram_id: i64) -> bool {
+        query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM couriers WHERE telegram_id = $1) AS "exists!";"#, telegram_id)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not check a courier")
+    }
+
+    async fn get_open_deliveries(&self, courier_id: Option<i64>) -> Vec<DeliveryTask> {
+        query_as!(DeliveryTask, r#"SELECT d.id, p.track_code, d.address, d.status AS "status: DeliveryStatus", c.name AS "courier?"
+            FROM deliveries d
+            JOIN parcels p ON p.id = d.parcel_id
+            LEFT JOIN couriers c ON c.telegram_id = d.courier_id
+            WHERE d.status <> 'delivered' AND ($1::bigint IS NULL OR d.courier_id = $1)
+            ORDER BY d.id;"#, courier_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get open deliveries")
+    }
+
+    async fn assign_delivery(&self, delivery_id: i32, courier_id: i64) -> Option<CourierDelivery> {
+        query_as!(CourierDelivery, r#"UPDATE deliveries d SET status = 'assigned', courier_id = c.telegram_id, updated_at = now()
+            FROM couriers c, parcels p, users u
+            WHERE d.id = $1 AND d.status <> 'delivered' AND c.telegram_id = $2 AND p.id = d.parcel_id AND u.id = p.user_id
+            RETURNING d.id, p.track_code, u.telegram_id AS "telegram_id!", d.address;"#, delivery_id, courier_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not assign a delivery")
+    }
+
+    async fn complete_delivery(&self, delivery_id: i32, courier_id: i64, photo_id: Option<&str>, require_payment: bool, notice: impl Fn(&CourierDelivery) -> Notice) -> DeliveryClosing {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // A parcel without an invoice has nothing recorded as paid, so it is held too.
+        let paid = query_scalar!(r#"SELECT (p.paid_cents >= p.invoice_cents) IS TRUE AS "paid!"
+            FROM deliveries d
+            JOIN parcels p ON p.id = d.parcel_id
+            WHERE d.id = $1 AND d.courier_id = $2 AND d.status = 'assigned'
+            FOR UPDATE OF d, p;"#, delivery_id, courier_id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not get a delivery");
+
+        match paid {
+            None => return DeliveryClosing::NotFound,
+            Some(false) if require_payment => return DeliveryClosing::Unpaid,
+            Some(_) => ()
+        }
+
+        let delivery = query_as!(CourierDelivery, r#"UPDATE deliveries d SET status = 'delivered', delivered_at = now(), photo_id = $3, updated_at = now()
+            FROM parcels p, users u
+            WHERE d.id = $1 AND d.courier_id = $2 AND d.status = 'assigned' AND p.id = d.parcel_id AND u.id = p.user_id
+            RETURNING d.id, p.track_code, u.telegram_id AS "telegram_id!", d.address;"#, delivery_id, courier_id, photo_id)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not complete a delivery");
+
+        query!("UPDATE parcels SET delivered_at = now()
+            FROM deliveries d
+            WHERE d.id = $1 AND parcels.id = d.parcel_id AND parcels.delivered_at IS NULL;", delivery_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not hand over a parcel");
+
+        Self::enqueue(&mut tx, &notice(&delivery)).await;
+
+        tx.commit().await.expect("ERROR: Could not complete a delivery");
+
+        DeliveryClosing::Closed(delivery)
+    }
+
+    async fn get_shelf(&self, shelf: &str) -> Vec<WarehouseLabel> {
+        query_as!(WarehouseLabel, "SELECT p.track_code, u.client_code, u.first_name, u.last_name, u.city, p.shelf
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.shelf = $1 AND p.delivered_at IS NULL
+            ORDER BY u.client_code, p.track_code;", shelf)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get a shelf")
+    }
+
+    async fn get_shelves(&self) -> Vec<(String, i64)> {
+        query!(r#"SELECT s.code, COUNT(p.id) AS "parcels!"
+            FROM shelves s
+            LEFT JOIN parcels p ON p.shelf = s.code AND p.delivered_at IS NULL
+            GROUP BY s.code;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get shelves")
+            .into_iter()
+            .map(|row| (row.code, row.parcels))
+            .collect()
+    }
+
+    async fn get_setting(&self, key: &str) -> Option<String> {
+        query_scalar!("SELECT value FROM settings WHERE key = $1;", key)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a setting")
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) {
+        query!("INSERT INTO settings (key, value) VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value;", key, value)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not set a setting");
+    }
+
+    async fn delete_setting(&self, key: &str) {
+        query!("DELETE FROM settings WHERE key = $1;", key)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not delete a setting");
+    }
+
+    async fn update_track_code_arrived(&self, track_code: &str, notice: impl Fn(&PendingParcel) -> Notice) -> usize {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let parcels = query_as!(PendingParcel, r#"UPDATE parcels p SET arrived = TRUE, arrived_at = now()
+            FROM users u
+            WHERE u.id = p.user_id AND p.track_code = $1 AND NOT p.arrived
+            RETURNING p.id, p.track_code, p.label, u.telegram_id AS "telegram_id!", p.photo_id, u.city;"#, track_code)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not mark a track code arrived");
+
+        for parcel in &parcels {
+            Self::enqueue(&mut tx, &notice(parcel)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not mark a track code arrived");
+
+        parcels.len()
+    }
+
+    async fn create_purchase_request(&self, telegram_id: i64, marketplace: &str, link: &str, options: Option<&str>, budget: Money) -> i32 {
+        query_scalar!("INSERT INTO purchase_requests (user_id, marketplace, link, options, budget_cents)
+            SELECT id, $2, $3, $4, $5 FROM users WHERE telegram_id = $1 AND deleted_at IS NULL
+            RETURNING id;", telegram_id, marketplace, link, options, budget as Money)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not create a purchase request")
+    }
+
+    async fn get_open_purchase_requests(&self) -> Vec<PurchaseRequest> {
+        query_as!(PurchaseRequest, r#"SELECT r.id, u.client_code, u.telegram_id AS "telegram_id!", r.marketplace, r.link, r.options,
+                r.budget_cents AS "budget: Money", r.status AS "status: PurchaseStatus", r.quote_cents AS "quote: Money", r.comment
+            FROM purchase_requests r
+            JOIN users u ON u.id = r.user_id
+            WHERE r.status IN ('new', 'quoted') AND u.deleted_at IS NULL
+            ORDER BY r.marketplace, r.created_at;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get purchase requests")
+    }
+
+    async fn get_purchase_request(&self, id: i32) -> Option<PurchaseRequest> {
+        query_as!(PurchaseRequest, r#"SELECT r.id, u.client_code, u.telegram_id AS "telegram_id!", r.marketplace, r.link, r.options,
+                r.budget_cents AS "budget: Money", r.status AS "status: PurchaseStatus", r.quote_cents AS "quote: Money", r.comment
+            FROM purchase_requests r
+            JOIN users u ON u.id = r.user_id
+            WHERE r.id = $1 AND u.deleted_at IS NULL;"#, id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a purchase request")
+    }
+
+    async fn update_purchase_request(&self, id: i32, status: PurchaseStatus, quote: Option<Money>, comment: Option<&str>, notice: impl FnOnce(&PurchaseRequest) -> Notice) -> Option<PurchaseRequest> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let request = query_as!(PurchaseRequest, r#"UPDATE purchase_requests r
+            SET status = $2, quote_cents = COALESCE($3, r.quote_cents), comment = COALESCE($4, r.comment), updated_at = now()
+            FROM users u
+            WHERE r.id = $1 AND u.id = r.user_id AND r.status IN ('new', 'quoted')
+            RETURNING r.id, u.client_code, u.telegram_id AS "telegram_id!", r.marketplace, r.link, r.options,
+                r.budget_cents AS "budget: Money", r.status AS "status: PurchaseStatus", r.quote_cents AS "quote: Money", r.comment;"#,
+            id, status.as_str(), quote as Option<Money>, comment)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not update a purchase request")?;
+
+        Self::enqueue(&mut tx, &notice(&request)).await;
+
+        tx.commit().await.expect("ERROR: Could not update a purchase request");
+
+        Some(request)
+    }
+
+    async fn get_providers(&self) -> Vec<Source> {
+        query_as!(Source, "SELECT name, base_url, requests_per_second FROM providers WHERE enabled ORDER BY position;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get tracking providers")
+    }
+
+    async fn record_vendor_error(&self, source: &str, track_code: &str, error: &str, body: &str) {
+        query!("INSERT INTO vendor_errors (source, track_code, error, body) VALUES ($1, $2, $3, $4);", source, track_code, error, body)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not record a vendor error");
+    }
+
+    async fn get_idle_users(&self, idle_days: i32, cooldown_days: i32, limit: i64) -> Vec<IdleUser> {
+        query_as!(IdleUser, r#"SELECT u.telegram_id AS "telegram_id!", u.first_name FROM users u
+            WHERE u.telegram_id IS NOT NULL AND u.deleted_at IS NULL AND u.blocked_at IS NULL AND u.reminders
+                AND u.created_at < now() - make_interval(days => $1)
+                AND (u.reengaged_at IS NULL OR u.reengaged_at < now() - make_interval(days => $2))
+                AND NOT EXISTS (SELECT 1 FROM parcels p WHERE p.user_id = u.id)
+                AND NOT EXISTS (
+                    SELECT 1 FROM events e
+                    WHERE e.telegram_id = u.telegram_id AND e.kind = $3 AND e.created_at >= now() - make_interval(days => $1)
+                )
+            ORDER BY u.id
+            LIMIT $4;"#,
+            idle_days, cooldown_days, EventKind::Active.as_str(), limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get idle users")
+    }
+
+    async fn reengage(&self, notice: &Notice) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("UPDATE users SET reengaged_at = now() WHERE telegram_id = $1;", notice.telegram_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not mark a user reminded");
+
+        query!("INSERT INTO events (telegram_id, kind) VALUES ($1, $2);",
+            notice.telegram_id, EventKind::Reengaged.as_str())
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not record a reminder");
+
+        Self::enqueue(&mut tx, notice).await;
+
+        tx.commit().await.expect("ERROR: Could not remind a user");
+    }
+
+    async fn get_onboarding_users(&self, window_days: i32) -> Vec<OnboardingUser> {
+        query_as!(OnboardingUser, r#"SELECT u.telegram_id AS "telegram_id!", u.first_name,
+                EXTRACT(EPOCH FROM now() - u.created_at)::FLOAT8 / 86400 AS "age_days!",
+                (SELECT EXTRACT(EPOCH FROM MAX(e.created_at) - u.created_at)::FLOAT8 / 86400 FROM events e
+                    WHERE e.telegram_id = u.telegram_id AND e.kind = $2) AS last_sent_days,
+                ARRAY(SELECT DISTINCT e.kind FROM events e
+                    WHERE e.telegram_id = u.telegram_id AND e.kind LIKE 'onboarding\_%' AND e.kind <> $2) AS "completed!"
+            FROM users u
+            WHERE u.telegram_id IS NOT NULL AND u.deleted_at IS NULL AND u.blocked_at IS NULL
+                AND u.created_at >= now() - make_interval(days => $1)
+            ORDER BY u.id;"#,
+            window_days, EventKind::OnboardingSent.as_str())
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get onboarding users")
+    }
+
+    async fn send_onboarding(&self, notice: &Notice) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("INSERT INTO events (telegram_id, kind) VALUES ($1, $2);",
+            notice.telegram_id, EventKind::OnboardingSent.as_str())
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not record an onboarding checklist");
+
+        Self::enqueue(&mut tx, notice).await;
+
+        tx.commit().await.expect("ERROR: Could not send an onboarding checklist");
+    }
+
+    async fn get_monthly_spending(&self, month: NaiveDate, limit: i64) -> Vec<MonthlySpending> {
+        query_as!(MonthlySpending, r#"SELECT u.telegram_id AS "telegram_id!", COUNT(*) AS "parcels!",
+                COALESCE(SUM(p.weight_kg), 0)::REAL AS "weight_kg!",
+                (SELECT COALESCE(SUM(m.amount_cents), 0) FROM payments m JOIN parcels mp ON mp.id = m.parcel_id
+                    WHERE mp.user_id = u.id
+                        AND m.created_at AT TIME ZONE 'Asia/Bishkek' >= $1::date
+                        AND m.created_at AT TIME ZONE 'Asia/Bishkek' < $1::date + interval '1 month'
+                )::BIGINT AS "paid!: Money"
+            FROM users u
+            JOIN parcels p ON p.user_id = u.id
+            WHERE u.telegram_id IS NOT NULL AND u.deleted_at IS NULL AND u.blocked_at IS NULL AND u.monthly_summary
+                AND p.delivered_at AT TIME ZONE 'Asia/Bishkek' >= $1::date
+                AND p.delivered_at AT TIME ZONE 'Asia/Bishkek' < $1::date + interval '1 month'
+                AND NOT EXISTS (SELECT 1 FROM monthly_summaries s WHERE s.telegram_id = u.telegram_id AND s.month = $1)
+            GROUP BY u.id
+            ORDER BY u.id
+            LIMIT $2;"#,
+            month, limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get monthly spending")
+    }
+
+    async fn send_monthly_summary(&self, month: NaiveDate, notice: &Notice) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let inserted = query!("INSERT INTO monthly_summaries (telegram_id, month) VALUES ($1, $2) ON CONFLICT DO NOTHING;",
+            notice.telegram_id, month)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not record a monthly summary")
+            .rows_affected();
+
+        if inserted == 1 {
+            Self::enqueue(&mut tx, notice).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not send a monthly summary");
+    }
+
+    async fn get_monthly_receipts(&self, telegram_id: i64, month: NaiveDate) -> Vec<MonthlyReceipt> {
+        query_as!(MonthlyReceipt, r#"SELECT p.track_code, p.delivered_at AT TIME ZONE 'Asia/Bishkek' AS "delivered_at!",
+                p.weight_kg, p.invoice_cents AS "amount: Money", p.paid_cents AS "paid: Money"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL
+                AND p.delivered_at AT TIME ZONE 'Asia/Bishkek' >= $2::date
+                AND p.delivered_at AT TIME ZONE 'Asia/Bishkek' < $2::date + interval '1 month'
+            ORDER BY p.delivered_at;"#,
+            telegram_id, month)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get monthly receipts")
+    }
+
+    async fn get_outbox(&self, limit: i64, max_attempts: i32, window: std::time::Duration) -> Vec<OutboxMessage> {
+        query_as!(OutboxMessage, "SELECT o.id, o.telegram_id, o.text, o.markup, o.photo_id, o.digest FROM outbox o
+            WHERE o.sent_at IS NULL AND o.attempts < $2
+                AND NOT EXISTS (SELECT 1 FROM users u WHERE u.telegram_id = o.telegram_id AND u.blocked_at IS NOT NULL)
+                AND (o.digest IS NULL OR NOT EXISTS (
+                    SELECT 1 FROM outbox n
+                    WHERE n.telegram_id = o.telegram_id AND n.digest IS NOT NULL AND n.sent_at IS NULL
+                        AND n.created_at > now() - make_interval(secs => $3)
+                ))
+            ORDER BY o.id
+            LIMIT $1;",
+            limit, max_attempts, window.as_secs_f64())
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not read the outbox")
+    }
+
+    async fn mark_outbox_sent(&self, id: i64, message_id: i32) {
+        query!("UPDATE outbox SET sent_at = now(), message_id = $2 WHERE id = $1;", id, message_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not mark an outbox message sent");
+    }
+
+    async fn get_reacted_subjects(&self, telegram_id: i64, message_id: i32) -> Vec<Subject> {
+        query!("SELECT subject AS \"subject!\", track_code AS \"track_code!\" FROM outbox
+            WHERE telegram_id = $1 AND message_id = $2 AND subject IS NOT NULL AND track_code IS NOT NULL
+            ORDER BY id;",
+            telegram_id, message_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get the subjects of a message")
+            .into_iter()
+            .filter_map(|row| Subject::from_row(&row.subject, row.track_code))
+            .collect()
+    }
+
+    async fn acknowledge_arrival(&self, telegram_id: i64, track_code: &str) {
+        query!("UPDATE parcels p SET arrival_acknowledged_at = now()
+            FROM users u
+            WHERE u.id = p.user_id AND u.telegram_id = $1 AND p.track_code = $2 AND p.arrival_acknowledged_at IS NULL;",
+            telegram_id, track_code)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not acknowledge an arrival");
+    }
+
+    async fn save_rating(&self, telegram_id: i64, track_code: &str, stars: i16) -> bool {
+        query!("INSERT INTO ratings (telegram_id, track_code, stars)
+            SELECT u.telegram_id, p.track_code, $3
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND p.track_code = $2
+            ON CONFLICT (telegram_id, track_code) DO UPDATE SET stars = EXCLUDED.stars, created_at = now();",
+            telegram_id, track_code, stars)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a rating")
+            .rows_affected() > 0
+    }
+
+    async fn mark_outbox_failed(&self, id: i64) {
+        query!("UPDATE outbox SET attempts = attempts + 1 WHERE id = $1;", id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not mark an outbox message failed");
+    }
+
+    async fn get_partner_secret(&self, partner_id: &str) -> Option<String> {
+        query_scalar!("SELECT secret FROM partners WHERE id = $1;", partner_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a partner secret")
+    }
+
+    async fn claim_webhook_key(&self, partner_id: &str, key: &str) -> WebhookClaim {
+        let claimed = query!("INSERT INTO webhook_keys (partner_id, idempotency_key) VALUES ($1, $2)
+            ON CONFLICT (partner_id, idempotency_key) DO UPDATE SET claimed_at = now()
+            WHERE webhook_keys.status IS NULL AND webhook_keys.claimed_at < now() - interval '1 minute'
+            RETURNING partner_id;", partner_id, key)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not claim a webhook key");
+
+        if claimed.is_some() {
+            return WebhookClaim::New;
+        }
+
+        let status = query_scalar!("SELECT status FROM webhook_keys WHERE partner_id = $1 AND idempotency_key = $2;", partner_id, key)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not get a webhook key");
+
+        match status {
+            Some(status) => WebhookClaim::Done(status as u16),
+            None => WebhookClaim::InProgress
+        }
+    }
+
+    async fn finish_webhook_key(&self, partner_id: &str, key: &str, status: u16) {
+        query!("UPDATE webhook_keys SET status = $3 WHERE partner_id = $1 AND idempotency_key = $2;", partner_id, key, status as i16)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not finish a webhook key");
+    }
+
+    async fn create_ticket(&self, telegram_id: i64, text: &str, attachments: &[Attachment]) -> i32 {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let ticket_id: i32 = query_scalar!("INSERT INTO tickets (telegram_id, text) VALUES ($1, $2) RETURNING id;", telegram_id, text)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not create a ticket");
+
+        Self::attach(&mut tx, ticket_id, attachments).await;
+
+        tx.commit().await.expect("ERROR: Could not create a ticket");
+
+        ticket_id
+    }
+
+    async fn get_attachments(&self, ticket_id: i32) -> Vec<Attachment> {
+        query!("SELECT kind, file_id, mime_type, size_bytes FROM attachments WHERE ticket_id = $1 ORDER BY id;", ticket_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get attachments")
+            .into_iter()
+            .map(|row| Attachment {
+                kind: AttachmentKind::try_from(row.kind).expect("ERROR: Could not read an attachment"),
+                file_id: row.file_id,
+                mime_type: row.mime_type,
+                size: row.size_bytes as u32
+            })
+            .collect()
+    }
+
+    async fn request_refund(&self, telegram_id: i64, track_code: &str, reason: &str, text: &str) -> Option<i32> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let ticket_id: i32 = query_scalar!("INSERT INTO tickets (telegram_id, text) VALUES ($1, $2) RETURNING id;", telegram_id, text)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not create a ticket");
+
+        let requested = query!("INSERT INTO refunds (parcel_id, ticket_id, reason)
+            SELECT p.id, $3, $4 FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE u.telegram_id = $1 AND u.deleted_at IS NULL AND p.track_code = $2
+            ON CONFLICT (parcel_id) DO NOTHING;",
+            telegram_id, track_code, ticket_id, reason)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not request a refund")
+            .rows_affected() > 0;
+
+        if !requested {
+            return None;
+        }
+
+        tx.commit().await.expect("ERROR: Could not request a refund");
+
+        Some(ticket_id)
+    }
+
+    async fn set_refund_status(&self, ticket_id: i32, status: RefundStatus, notice: impl FnOnce(i64, &str) -> Notice) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let refund = query!(r#"UPDATE refunds r SET status = $2, updated_at = now()
+            FROM parcels p, users u
+            WHERE r.ticket_id = $1 AND p.id = r.parcel_id AND u.id = p.user_id
+            RETURNING u.telegram_id AS "telegram_id!", p.track_code;"#, ticket_id, status.as_str())
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not update a refund");
+
+        let refund = match refund {
+            Some(refund) => refund,
+            None => return false
+        };
+
+        Self::enqueue(&mut tx, &notice(refund.telegram_id, &refund.track_code)).await;
+
+        tx.commit().await.expect("ERROR: Could not update a refund");
+
+        true
+    }
+
+    async fn get_open_tickets(&self) -> Vec<Ticket> {
+        query_as!(Ticket, r#"SELECT t.id, u.client_code, t.text, t.created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM tickets t
+            JOIN users u ON u.telegram_id = t.telegram_id AND u.deleted_at IS NULL
+            WHERE t.closed_at IS NULL
+            ORDER BY t.created_at;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get open tickets")
+    }
+
+    async fn get_user_tickets(&self, telegram_id: i64) -> Vec<Ticket> {
+        query_as!(Ticket, r#"SELECT t.id, u.client_code, t.text, t.created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM tickets t
+            JOIN users u ON u.telegram_id = t.telegram_id AND u.deleted_at IS NULL
+            WHERE t.telegram_id = $1
+            ORDER BY t.created_at;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get user tickets")
+    }
+
+    async fn close_ticket(&self, ticket_id: i32) -> bool {
+        query!("UPDATE tickets SET closed_at = now() WHERE id = $1 AND closed_at IS NULL;", ticket_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not close a ticket")
+            .rows_affected() > 0
+    }
+
+    async fn record_event(&self, telegram_id: i64, kind: EventKind) {
+        let mut conn = self.pool.acquire().await.expect("ERROR: Could not get a connection");
+
+        Self::insert_event(&mut conn, telegram_id, kind).await;
+    }
+
+    async fn record_audit(&self, admin_id: i64, admin: &str, action: &str, outcome: &str) {
+        query!("INSERT INTO audit_log (admin_id, admin, action, outcome, correlation_id) VALUES ($1, $2, $3, $4, $5);",
+            admin_id, admin, action, outcome, correlation::current().map(|id| id.to_string()))
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not record an audit entry");
+    }
+
+    async fn get_cohorts(&self, weeks: i32) -> Vec<Cohort> {
+        query_as!(Cohort, r#"SELECT date_trunc('week', u.created_at AT TIME ZONE 'Asia/Bishkek')::date AS "week!",
+                COUNT(DISTINCT u.id) AS "users!",
+                COUNT(p.id) AS "parcels!"
+            FROM users u
+            LEFT JOIN parcels p ON p.user_id = u.id
+            WHERE u.created_at >= date_trunc('week', now()) - make_interval(weeks => $1)
+            GROUP BY 1
+            ORDER BY 1;"#, weeks)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get cohorts")
+    }
+
+    async fn get_cohort_activity(&self, weeks: i32) -> Vec<CohortActivity> {
+        query_as!(CohortActivity, r#"WITH cohorts AS (
+                SELECT telegram_id, date_trunc('week', created_at AT TIME ZONE 'Asia/Bishkek')::date AS week
+                FROM users
+                WHERE created_at >= date_trunc('week', now()) - make_interval(weeks => $1)
+            )
+            SELECT c.week AS "week!",
+                ((date_trunc('week', e.created_at AT TIME ZONE 'Asia/Bishkek')::date - c.week) / 7)::int AS "offset!",
+                COUNT(DISTINCT e.telegram_id) AS "users!"
+            FROM events e
+            JOIN cohorts c ON c.telegram_id = e.telegram_id
+            WHERE e.kind = $2
+            GROUP BY 1, 2;"#, weeks, EventKind::Active.as_str())
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get cohort activity")
+    }
+
+    async fn get_welcome_results(&self) -> Vec<ExperimentResult> {
+        query_as!(ExperimentResult, r#"SELECT w.kind AS variant,
+                COUNT(DISTINCT w.telegram_id) AS "shown!",
+                COUNT(DISTINCT r.telegram_id) AS "registered!"
+            FROM events w
+            LEFT JOIN events r ON r.telegram_id = w.telegram_id AND r.kind = $3
+            WHERE w.kind IN ($1, $2)
+            GROUP BY w.kind
+            ORDER BY w.kind;"#,
+            EventKind::Welcome(WelcomeVariant::A).as_str(), EventKind::Welcome(WelcomeVariant::B).as_str(), EventKind::Registered.as_str())
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get welcome experiment results")
+    }
+
+    async fn get_dialogue(&self, chat_id: i64) -> Option<String> {
+        query_scalar!("SELECT state FROM dialogues WHERE chat_id = $1;", chat_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a dialogue")
+    }
+
+    async fn save_dialogue(&self, chat_id: i64, state: &str) {
+        query!("INSERT INTO dialogues (chat_id, state) VALUES ($1, $2)
+            ON CONFLICT (chat_id) DO UPDATE SET state = EXCLUDED.state, updated_at = now();", chat_id, state)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a dialogue");
+    }
+
+    async fn remove_dialogue(&self, chat_id: i64) {
+        query!("DELETE FROM dialogues WHERE chat_id = $1;", chat_id)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not remove a dialogue");
+    }
+
+    async fn save_start_track_code(&self, telegram_id: i64, track_code: &str) {
+        query!("INSERT INTO start_track_codes (telegram_id, track_code) VALUES ($1, $2)
+            ON CONFLICT (telegram_id) DO UPDATE SET track_code = EXCLUDED.track_code, created_at = now();", telegram_id, track_code)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a start track code");
+    }
+
+    async fn take_start_track_code(&self, telegram_id: i64) -> Option<String> {
+        query_scalar!("DELETE FROM start_track_codes WHERE telegram_id = $1 RETURNING track_code;", telegram_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not take a start track code")
+    }
+
+    async fn add_staff_note(&self, author_id: i64, author: &str, text: &str) {
+        query!("INSERT INTO staff_notes (author_id, author, text) VALUES ($1, $2, $3);", author_id, author, text)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not add a staff note");
+    }
+
+    async fn get_staff_notes(&self, hours: i32) -> Vec<StaffNote> {
+        query_as!(StaffNote, r#"SELECT author, text, created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM staff_notes
+            WHERE created_at > now() - make_interval(hours => $1)
+            ORDER BY created_at;"#, hours)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get staff notes")
+    }
+
+    async fn get_pending_disputes(&self) -> Vec<(i32, String)> {
+        query!("SELECT t.id, p.track_code
+            FROM weight_disputes d
+            JOIN tickets t ON t.id = d.ticket_id
+            JOIN parcels p ON p.id = d.parcel_id
+            WHERE t.closed_at IS NULL
+            ORDER BY t.id;")
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get pending disputes")
+            .into_iter()
+            .map(|row| (row.id, row.track_code))
+            .collect()
+    }
+
+    async fn get_unassigned_parcels(&self) -> Vec<(String, String)> {
+        query!("SELECT p.track_code, u.client_code
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.arrived AND p.batch_code IS NULL
+            ORDER BY p.id;")
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get unassigned parcels")
+            .into_iter()
+            .map(|row| (row.track_code, row.client_code))
+            .collect()
+    }
+
+    async fn request_registration(&self, user: &User) {
+        query!("INSERT INTO registration_requests (telegram_id, first_name, last_name, phone_number, username, display_name)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (telegram_id) DO UPDATE SET
+                first_name = EXCLUDED.first_name,
+                last_name = EXCLUDED.last_name,
+                phone_number = EXCLUDED.phone_number,
+                username = EXCLUDED.username,
+                display_name = EXCLUDED.display_name,
+                created_at = now(),
+                decided_at = NULL;",
+            user.telegram_id, &user.first_name, user.last_name.as_deref(), self.cipher.encrypt(&user.phone_number), user.username.as_deref(), user.display_name.as_deref())
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a registration request");
+    }
+
+    async fn get_recent_registrations(&self, limit: i64) -> Vec<Registration> {
+        query_as!(Registration, r#"SELECT client_code, first_name, last_name, username,
+                created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM users
+            WHERE deleted_at IS NULL
+            ORDER BY created_at DESC, id DESC
+            LIMIT $1;"#, limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get recent registrations")
+    }
+
+    async fn get_registration_requests(&self) -> Vec<RegistrationRequest> {
+        query_as!(RegistrationRequest, "SELECT telegram_id, first_name, last_name, phone_number, username, display_name
+            FROM registration_requests
+            WHERE decided_at IS NULL
+            ORDER BY created_at;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get registration requests")
+            .into_iter()
+            .map(|mut request| {
+                request.phone_number = self.decrypt_phone(&request.phone_number);
+                request
+            })
+            .collect()
+    }
+
+    async fn take_registration_request(&self, telegram_id: i64) -> Option<RegistrationRequest> {
+        query_as!(RegistrationRequest, "UPDATE registration_requests SET decided_at = now()
+            WHERE telegram_id = $1 AND decided_at IS NULL
+            RETURNING telegram_id, first_name, last_name, phone_number, username, display_name;", telegram_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not take a registration request")
+            .map(|mut request| {
+                request.phone_number = self.decrypt_phone(&request.phone_number);
+                request
+            })
+    }
+
+    async fn request_account_claim(&self, user_id: i32, profile: &User) -> AccountClaim {
+        let telegram_id = profile.telegram_id.expect("ERROR: Claims come from Telegram");
+
+        query_as!(AccountClaim, r#"WITH claim AS (
+                INSERT INTO account_claims (user_id, telegram_id, username, display_name) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (telegram_id) WHERE decided_at IS NULL DO UPDATE
+                SET user_id = EXCLUDED.user_id, username = EXCLUDED.username, display_name = EXCLUDED.display_name, created_at = now()
+                RETURNING id, user_id, telegram_id, username, display_name
+            )
+            SELECT c.id AS "id!", c.user_id AS "user_id!", u.client_code, u.first_name, u.last_name, u.telegram_id AS old_telegram_id,
+                c.telegram_id AS "telegram_id!", c.username, c.display_name
+            FROM claim c
+            JOIN users u ON u.id = c.user_id;"#,
+            user_id, telegram_id, profile.username, profile.display_name)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not request an account claim")
+    }
+
+    async fn get_account_claims(&self) -> Vec<AccountClaim> {
+        query_as!(AccountClaim, r#"SELECT c.id, c.user_id, u.client_code, u.first_name, u.last_name, u.telegram_id AS old_telegram_id,
+                c.telegram_id, c.username, c.display_name
+            FROM account_claims c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.decided_at IS NULL AND u.deleted_at IS NULL
+            ORDER BY c.id;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get account claims")
+    }
+
+    async fn approve_account_claim(&self, id: i32, operator_id: i64, notice: impl FnOnce(i64, &AccountClaim) -> Notice) -> Option<AccountClaim> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("UPDATE account_claims SET approved = true, decided_by = $2, decided_at = now()
+            WHERE id = $1 AND decided_at IS NULL
+            RETURNING id;", id, operator_id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not approve an account claim")?;
+
+        let claim = Self::move_account(&mut tx, id, notice).await?;
+
+        tx.commit().await.expect("ERROR: Could not approve an account claim");
+
+        Some(claim)
+    }
+
+    async fn reject_account_claim(&self, id: i32, operator_id: i64) -> Option<AccountClaim> {
+        query_as!(AccountClaim, r#"UPDATE account_claims c SET approved = false, decided_by = $2, decided_at = now()
+            FROM users u
+            WHERE c.id = $1 AND c.decided_at IS NULL AND u.id = c.user_id
+            RETURNING c.id, c.user_id, u.client_code, u.first_name, u.last_name, u.telegram_id AS old_telegram_id,
+                c.telegram_id, c.username, c.display_name;"#, id, operator_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not reject an account claim")
+    }
+
+    async fn save_canned_response(&self, slug: &str, text: &str) {
+        query!("INSERT INTO canned_responses (slug, text) VALUES ($1, $2)
+            ON CONFLICT (slug) DO UPDATE SET text = EXCLUDED.text;", slug, text)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not save a canned response");
+    }
+
+    async fn get_canned_responses(&self) -> Vec<CannedResponse> {
+        query_as!(CannedResponse, "SELECT slug, text FROM canned_responses ORDER BY slug;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get canned responses")
+    }
+
+    async fn get_canned_response(&self, slug: &str) -> Option<String> {
+        query_scalar!("SELECT text FROM canned_responses WHERE slug = $1;", slug)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a canned response")
+    }
+
+    async fn record_canned_use(&self, slug: &str, ticket_id: i32, operator_id: i64, operator: &str) {
+        query!("INSERT INTO canned_response_uses (slug, ticket_id, operator_id, operator) VALUES ($1, $2, $3, $4);", slug, ticket_id, operator_id, operator)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not record a canned response use");
+    }
+
+    async fn get_canned_usage(&self) -> Vec<CannedUsage> {
+        query_as!(CannedUsage, r#"SELECT slug, max(operator) AS "operator!", COUNT(*) AS "uses!"
+            FROM canned_response_uses
+            GROUP BY slug, operator_id
+            ORDER BY slug, 3 DESC;"#)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get canned response usage")
+    }
+
+    async fn get_ticket_user(&self, ticket_id: i32) -> Option<User> {
+        query_as!(User, r#"SELECT u.id, u.first_name, u.last_name, u.phone_number, u.telegram_id, u.client_code, u.username, u.display_name, u.text_menu, u.reminders, u.monthly_summary, u.units AS "units: Units", u.city
+            FROM tickets t
+            JOIN users u ON u.telegram_id = t.telegram_id
+            WHERE t.id = $1 AND u.deleted_at IS NULL;"#, ticket_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get the ticket user")
+            .map(|user| self.decrypt_user(user))
+    }
+
+    async fn get_tariffs(&self) -> Vec<Tariff> {
+        query_as!(Tariff, r#"SELECT min_density, price_per_kg_cents AS "price_per_kg: Money", min_charge_cents AS "min_charge: Money" FROM tariffs ORDER BY min_density;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get tariffs")
+    }
+
+    async fn get_user_tariffs(&self, telegram_id: i64) -> Vec<Tariff> {
+        let tariffs = query_as!(Tariff, r#"SELECT t.min_density, t.price_per_kg_cents AS "price_per_kg: Money", t.min_charge_cents AS "min_charge: Money"
+            FROM city_tariffs t
+            JOIN cities c ON c.name = t.city
+            JOIN users u ON lower(u.city) = lower(c.name)
+            WHERE u.telegram_id = $1
+            ORDER BY t.min_density;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get city tariffs");
+
+        match tariffs.is_empty() {
+            true => self.get_tariffs().await,
+            false => tariffs
+        }
+    }
+
+    async fn save_tariff(&self, tariff: &Tariff) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        query!("INSERT INTO tariffs (min_density, price_per_kg_cents, min_charge_cents) VALUES ($1, $2, $3)
+            ON CONFLICT (min_density) DO UPDATE SET price_per_kg_cents = EXCLUDED.price_per_kg_cents, min_charge_cents = EXCLUDED.min_charge_cents;",
+            tariff.min_density, tariff.price_per_kg as Money, tariff.min_charge as Money)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not save a tariff");
+
+        Self::bump_tariff_version(&mut tx).await;
+
+        tx.commit().await.expect("ERROR: Could not commit the transaction");
+    }
+
+    async fn delete_tariff(&self, min_density: f32) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let deleted = query!("DELETE FROM tariffs WHERE min_density = $1;", min_density)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not delete a tariff")
+            .rows_affected() > 0;
+
+        if deleted {
+            Self::bump_tariff_version(&mut tx).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not commit the transaction");
+
+        deleted
+    }
+
+    async fn save_quote(&self, quote: &Quote) -> i32 {
+        query_scalar!("INSERT INTO quotes (telegram_id, width, length, height, weight_kg, price_cents, tariff_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id;",
+            quote.telegram_id, quote.width, quote.length, quote.height, quote.weight_kg, quote.price as Option<Money>, quote.tariff_version)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not save a quote")
+    }
+
+    async fn get_quotes(&self, telegram_id: i64, limit: i64) -> Vec<Quote> {
+        query_as!(Quote, r#"SELECT id, telegram_id, width, length, height, weight_kg, price_cents AS "price: Money", tariff_version,
+                created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM quotes
+            WHERE telegram_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2;"#, telegram_id, limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get quotes")
+    }
+
+    async fn get_quote(&self, id: i32) -> Option<Quote> {
+        query_as!(Quote, r#"SELECT id, telegram_id, width, length, height, weight_kg, price_cents AS "price: Money", tariff_version,
+                created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM quotes
+            WHERE id = $1;"#, id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a quote")
+    }
+
+    async fn get_quote_accuracy(&self, days: i32) -> Vec<QuoteAccuracy> {
+        query_as!(QuoteAccuracy, r#"SELECT q.tariff_version, count(*) AS "billed!",
+                avg(abs(p.invoice_cents - q.price_cents) * 100.0 / q.price_cents)::float8 AS "mean_error_percent!",
+                avg((p.invoice_cents - q.price_cents) * 100.0 / q.price_cents)::float8 AS "bias_percent!",
+                avg(p.weight_kg - q.weight_kg)::float8 AS weight_bias_kg,
+                count(c.id) AS "calibrations!"
+            FROM parcels p
+            JOIN quotes q ON q.id = p.quote_id
+            LEFT JOIN quote_calibrations c ON c.parcel_id = p.id
+            WHERE p.invoice_cents IS NOT NULL AND q.price_cents > 0 AND q.created_at >= now() - make_interval(days => $1)
+            GROUP BY q.tariff_version
+            ORDER BY q.tariff_version;"#, days)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get quote accuracy")
+    }
+
+    async fn get_calibrations(&self, limit: i64) -> Vec<Calibration> {
+        query_as!(Calibration, r#"SELECT p.track_code, c.quoted_cents AS "quoted: Money", c.billed_cents AS "billed: Money",
+                c.quoted_weight_kg, c.actual_weight_kg, c.density
+            FROM quote_calibrations c
+            JOIN parcels p ON p.id = c.parcel_id
+            ORDER BY c.created_at DESC
+            LIMIT $1;"#, limit)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get calibrations")
+    }
+
+    async fn save_box_preset(&self, telegram_id: i64, width: f32, length: f32, height: f32, limit: i64) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let saved = query!("INSERT INTO box_presets (telegram_id, width, length, height)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING;", telegram_id, width, length, height)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not save a box preset")
+            .rows_affected() > 0;
+
+        query!("DELETE FROM box_presets
+            WHERE telegram_id = $1 AND id NOT IN (
+                SELECT id FROM box_presets WHERE telegram_id = $1 ORDER BY created_at DESC, id DESC LIMIT $2
+            );", telegram_id, limit)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not save a box preset");
+
+        tx.commit().await.expect("ERROR: Could not save a box preset");
+
+        saved
+    }
+
+    async fn get_box_presets(&self, telegram_id: i64) -> Vec<BoxPreset> {
+        query_as!(BoxPreset, "SELECT id, width, length, height
+            FROM box_presets
+            WHERE telegram_id = $1
+            ORDER BY created_at DESC, id DESC;", telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get box presets")
+    }
+
+    async fn get_box_preset(&self, telegram_id: i64, id: i32) -> Option<BoxPreset> {
+        query_as!(BoxPreset, "SELECT id, width, length, height
+            FROM box_presets
+            WHERE telegram_id = $1 AND id = $2;", telegram_id, id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a box preset")
+    }
+
+    async fn mark_batch_delivered(&self, batch_code: &str, require_payment: bool) -> (u64, Vec<String>) {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // A parcel without an invoice has nothing recorded as paid, so it is held too.
+        let delivered = query!(r#"UPDATE parcels SET delivered_at = now()
+            WHERE batch_code = $1 AND delivered_at IS NULL AND (NOT $2 OR paid_cents >= invoice_cents);"#, batch_code, require_payment)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not mark a batch delivered")
+            .rows_affected();
+
+        let held = query_scalar!("SELECT track_code FROM parcels WHERE batch_code = $1 AND delivered_at IS NULL ORDER BY track_code;", batch_code)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not get unpaid parcels");
+
+        tx.commit().await.expect("ERROR: Could not mark a batch delivered");
+
+        (delivered, held)
+    }
+
+    async fn create_batch(&self, code: &str) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let created = query!("INSERT INTO batches (code) VALUES ($1) ON CONFLICT DO NOTHING;", code)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not create a batch")
+            .rows_affected() > 0;
+
+        if created {
+            query!("INSERT INTO batch_events (batch_code, status) VALUES ($1, $2);", code, BatchStatus::Forming.as_str())
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not create a batch");
+        }
+
+        tx.commit().await.expect("ERROR: Could not create a batch");
+
+        created
+    }
+
+    async fn get_batch(&self, code: &str) -> Option<Batch> {
+        query_as!(Batch, r#"SELECT b.code, b.status AS "status: BatchStatus",
+                (SELECT COUNT(*) FROM parcels p WHERE p.batch_code = b.code AND p.delivered_at IS NULL) AS "parcels!",
+                b.updated_at AT TIME ZONE 'Asia/Bishkek' AS "updated_at!"
+            FROM batches b
+            WHERE b.code = $1;"#, code)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get a batch")
+    }
+
+    async fn get_open_batches(&self) -> Vec<Batch> {
+        query_as!(Batch, r#"SELECT b.code, b.status AS "status: BatchStatus",
+                (SELECT COUNT(*) FROM parcels p WHERE p.batch_code = b.code AND p.delivered_at IS NULL) AS "parcels!",
+                b.updated_at AT TIME ZONE 'Asia/Bishkek' AS "updated_at!"
+            FROM batches b
+            WHERE b.status <> $1
+            ORDER BY b.created_at, b.code;"#, BatchStatus::Arrived.as_str())
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get open batches")
+    }
+
+    async fn get_user_batches(&self, telegram_id: i64) -> Vec<Batch> {
+        query_as!(Batch, r#"SELECT b.code, b.status AS "status: BatchStatus",
+                (SELECT COUNT(*) FROM parcels p WHERE p.batch_code = b.code AND p.delivered_at IS NULL) AS "parcels!",
+                b.updated_at AT TIME ZONE 'Asia/Bishkek' AS "updated_at!"
+            FROM batches b
+            WHERE EXISTS (
+                SELECT 1 FROM parcels p
+                JOIN users u ON u.id = p.user_id
+                WHERE p.batch_code = b.code AND p.delivered_at IS NULL AND u.telegram_id = $1 AND u.deleted_at IS NULL
+            )
+            ORDER BY b.created_at, b.code;"#, telegram_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get batches of a user")
+    }
+
+    async fn get_batch_events(&self, code: &str) -> Vec<BatchEvent> {
+        query_as!(BatchEvent, r#"SELECT status AS "status: BatchStatus", created_at AT TIME ZONE 'Asia/Bishkek' AS "created_at!"
+            FROM batch_events
+            WHERE batch_code = $1
+            ORDER BY created_at, id;"#, code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get batch events")
+    }
+
+    async fn advance_batch(&self, code: &str, from: BatchStatus, status: BatchStatus, notice: impl Fn(i64, &[String]) -> Notice) -> Option<usize> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let advanced = query!("UPDATE batches SET status = $3, updated_at = now() WHERE code = $1 AND status = $2;", code, from.as_str(), status.as_str())
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not advance a batch")
+            .rows_affected() > 0;
+
+        // Dropping the transaction rolls it back.
+        if !advanced {
+            return None;
+        }
+
+        query!("INSERT INTO batch_events (batch_code, status) VALUES ($1, $2);", code, status.as_str())
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not advance a batch");
+
+        let owners = query!(r#"SELECT u.telegram_id AS "telegram_id!", array_agg(p.track_code ORDER BY p.track_code) AS "track_codes!"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.batch_code = $1 AND p.delivered_at IS NULL AND u.deleted_at IS NULL AND u.telegram_id IS NOT NULL
+            GROUP BY u.telegram_id;"#, code)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not get clients of a batch");
+
+        for owner in &owners {
+            Self::enqueue(&mut tx, &notice(owner.telegram_id, &owner.track_codes)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not advance a batch");
+
+        Some(owners.len())
+    }
+
+    async fn has_permission(&self, telegram_id: i64, permission: Permission) -> bool {
+        query_scalar!(r#"SELECT EXISTS (SELECT 1 FROM roles WHERE telegram_id = $1 AND permission = $2) AS "exists!";"#,
+            telegram_id, permission.as_str())
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not check a permission")
+    }
+
+    async fn grant_permission(&self, telegram_id: i64, permission: Permission, granted_by: i64) -> bool {
+        query!("INSERT INTO roles (telegram_id, permission, granted_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING;",
+            telegram_id, permission.as_str(), granted_by)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not grant a permission")
+            .rows_affected() > 0
+    }
+
+    async fn revoke_permission(&self, telegram_id: i64, permission: Permission) -> bool {
+        query!("DELETE FROM roles WHERE telegram_id = $1 AND permission = $2;", telegram_id, permission.as_str())
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not revoke a permission")
+            .rows_affected() > 0
+    }
+
+    async fn get_staff(&self) -> Vec<i64> {
+        query_scalar!("SELECT telegram_id FROM staff ORDER BY joined_at, telegram_id;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get staff")
+    }
+
+    async fn seed_staff(&self, telegram_ids: &[i64]) -> bool {
+        query!("INSERT INTO staff (telegram_id)
+            SELECT UNNEST($1::BIGINT[])
+            WHERE NOT EXISTS (SELECT 1 FROM staff)
+            ON CONFLICT DO NOTHING;", telegram_ids)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not seed staff")
+            .rows_affected() > 0
+    }
+
+    async fn create_staff_invite(&self, code: &str, created_by: i64, valid_hours: i32) {
+        query!("INSERT INTO staff_invites (code, created_by, expires_at) VALUES ($1, $2, now() + make_interval(hours => $3));",
+            code, created_by, valid_hours)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not create a staff invite");
+    }
+
+    async fn redeem_staff_invite(&self, code: &str, telegram_id: i64) -> Option<i64> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let invited_by = query_scalar!("UPDATE staff_invites SET redeemed_by = $2, redeemed_at = now()
+            WHERE code = $1 AND redeemed_by IS NULL AND expires_at > now()
+            RETURNING created_by;", code, telegram_id)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not redeem a staff invite")?;
+
+        query!("INSERT INTO staff (telegram_id, invited_by) VALUES ($1, $2) ON CONFLICT DO NOTHING;", telegram_id, invited_by)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not add a staff member");
+
+        tx.commit().await.expect("ERROR: Could not redeem a staff invite");
+
+        Some(invited_by)
+    }
+
+    async fn remove_staff(&self, telegram_id: i64) -> bool {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let removed = query!("DELETE FROM staff WHERE telegram_id = $1;", telegram_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not remove a staff member")
+            .rows_affected() > 0;
+
+        query!("DELETE FROM roles WHERE telegram_id = $1;", telegram_id)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not remove a staff member");
+
+        tx.commit().await.expect("ERROR: Could not remove a staff member");
+
+        removed
+    }
+
+    async fn save_error_report(&self, report: &ErrorReport) -> i32 {
+        query_scalar!("INSERT INTO error_reports (telegram_id, correlation_id, state, input, error) VALUES ($1, $2, $3, $4, $5) RETURNING id;",
+            report.telegram_id, report.correlation_id, report.state, report.input, report.error)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not save an error report")
+    }
+
+    async fn log_export(&self, telegram_id: i64, kind: &str, row_count: usize) -> i32 {
+        query_scalar!("INSERT INTO exports (telegram_id, kind, row_count) VALUES ($1, $2, $3) RETURNING id;",
+            telegram_id, kind, row_count as i32)
+            .fetch_one(&self.pool)
+            .await.expect("ERROR: Could not log an export")
+    }
+
+    async fn update_invoices(&self, track_code: &str, amount: Money, calibration: &CalibrationPolicy, notice: impl Fn(&Invoice) -> Notice) -> usize {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let invoices = query_as!(Invoice, r#"UPDATE parcels p SET invoice_cents = $2
+            FROM users u
+            WHERE u.id = p.user_id AND u.deleted_at IS NULL AND p.track_code = $1
+            RETURNING p.id AS parcel_id, p.track_code, u.client_code, u.telegram_id AS "telegram_id!",
+                p.invoice_cents AS "amount!: Money", p.paid_cents AS "paid: Money";"#,
+            track_code, amount as Money)
+            .fetch_all(&mut *tx)
+            .await.expect("ERROR: Could not set an invoice");
+
+        for invoice in &invoices {
+            Self::calibrate(&mut tx, calibration, invoice.parcel_id, invoice.amount).await;
+            Self::enqueue(&mut tx, &notice(invoice)).await;
+        }
+
+        tx.commit().await.expect("ERROR: Could not set an invoice");
+
+        invoices.len()
+    }
+
+    async fn get_invoice(&self, parcel_id: i32) -> Option<Invoice> {
+        query_as!(Invoice, r#"SELECT p.id AS parcel_id, p.track_code, u.client_code, u.telegram_id AS "telegram_id!",
+                p.invoice_cents AS "amount!: Money", p.paid_cents AS "paid: Money"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.id = $1 AND u.deleted_at IS NULL AND p.invoice_cents IS NOT NULL;"#, parcel_id)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get an invoice")
+    }
+
+    async fn get_invoices(&self, track_code: &str) -> Vec<Invoice> {
+        query_as!(Invoice, r#"SELECT p.id AS parcel_id, p.track_code, u.client_code, u.telegram_id AS "telegram_id!",
+                p.invoice_cents AS "amount!: Money", p.paid_cents AS "paid: Money"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.track_code = $1 AND u.deleted_at IS NULL AND p.invoice_cents IS NOT NULL
+            ORDER BY u.client_code;"#, track_code)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get invoices")
+    }
+
+    async fn record_payment(&self, parcel_id: i32, amount: Money, charge_id: Option<&str>, recorded_by: Option<i64>, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let payment = query_scalar!(r#"INSERT INTO payments (parcel_id, amount_cents, charge_id, recorded_by)
+            SELECT id, $2, $3, $4 FROM parcels
+            WHERE id = $1 AND invoice_cents IS NOT NULL AND ($3::text IS NOT NULL OR paid_cents + $2 <= invoice_cents)
+            FOR UPDATE
+            ON CONFLICT (charge_id) DO NOTHING
+            RETURNING id;"#, parcel_id, amount as Money, charge_id, recorded_by)
+            .fetch_optional(&mut *tx)
+            .await.expect("ERROR: Could not record a payment");
+
+        payment?;
+
+        let invoice = query_as!(Invoice, r#"UPDATE parcels p SET paid_cents = p.paid_cents + $2
+            FROM users u
+            WHERE u.id = p.user_id AND p.id = $1
+            RETURNING p.id AS parcel_id, p.track_code, u.client_code, u.telegram_id AS "telegram_id!",
+                p.invoice_cents AS "amount!: Money", p.paid_cents AS "paid: Money";"#,
+            parcel_id, amount as Money)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not record a payment");
+
+        Self::enqueue(&mut tx, &notice(&invoice)).await;
+
+        tx.commit().await.expect("ERROR: Could not record a payment");
+
+        Some(invoice)
+    }
+
+    async fn override_price(&self, parcel_id: i32, change: &PriceOverride, operator_id: i64, notice: impl FnOnce(&Invoice) -> Notice) -> Option<Invoice> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let recorded = query!("INSERT INTO price_overrides (parcel_id, original_cents, final_cents, reason, operator_id)
+            SELECT id, invoice_cents, $2, $3, $4 FROM parcels WHERE id = $1 AND invoice_cents = $5;",
+            parcel_id, change.final_price as Money, change.reason.as_str(), operator_id, change.original as Money)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not record a price override")
+            .rows_affected();
+
+        // The invoice changed since the operator looked at it, or there is none.
+        if recorded == 0 {
+            return None;
+        }
+
+        let invoice = query_as!(Invoice, r#"UPDATE parcels p SET invoice_cents = $2
+            FROM users u
+            WHERE u.id = p.user_id AND p.id = $1
+            RETURNING p.id AS parcel_id, p.track_code, u.client_code, u.telegram_id AS "telegram_id!",
+                p.invoice_cents AS "amount!: Money", p.paid_cents AS "paid: Money";"#,
+            parcel_id, change.final_price as Money)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not override a price");
+
+        Self::enqueue(&mut tx, &notice(&invoice)).await;
+
+        tx.commit().await.expect("ERROR: Could not override a price");
+
+        Some(invoice)
+    }
+
+    async fn get_override_totals(&self, days: i32) -> Vec<OverrideTotals> {
+        query_as!(OverrideTotals, r#"SELECT operator_id, COUNT(*) AS "overrides!",
+                COALESCE(SUM(original_cents - final_cents) FILTER (WHERE final_cents < original_cents), 0)::BIGINT AS "discounts!: Money",
+                COALESCE(SUM(final_cents - original_cents) FILTER (WHERE final_cents > original_cents), 0)::BIGINT AS "penalties!: Money"
+            FROM price_overrides
+            WHERE created_at >= now() - make_interval(days => $1)
+            GROUP BY operator_id
+            ORDER BY COUNT(*) DESC, operator_id;"#, days)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get price overrides")
+    }
+
+    async fn open_city(&self, city: &NewCity) -> Option<i32> {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let opened = query!("INSERT INTO cities (name, eta_min_days, eta_max_days, pickup_point) VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING;", city.name, city.eta_days.0, city.eta_days.1, city.pickup_point)
+            .execute(&mut *tx)
+            .await.expect("ERROR: Could not open a city")
+            .rows_affected() > 0;
+
+        // Dropping the transaction rolls it back.
+        if !opened {
+            return None;
+        }
+
+        for tariff in &city.tariffs {
+            query!("INSERT INTO city_tariffs (city, min_density, price_per_kg_cents, min_charge_cents) VALUES ($1, $2, $3, $4);",
+                city.name, tariff.min_density, tariff.price_per_kg as Money, tariff.min_charge as Money)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not save a city tariff");
+        }
+
+        let broadcast_id = query_scalar!("INSERT INTO broadcasts (filters, text) VALUES ($1, $2) RETURNING id;",
+            format!("city:{}", city.name), city.announcement)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not create a broadcast");
+
+        tx.commit().await.expect("ERROR: Could not commit the transaction");
+
+        Some(broadcast_id)
+    }
+
+    async fn get_cities(&self) -> Vec<(i32, String)> {
+        query!("SELECT id, name FROM cities ORDER BY name;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get cities")
+            .into_iter()
+            .map(|row| (row.id, row.name))
+            .collect()
+    }
+
+    async fn get_pickup_points(&self) -> Vec<(String, String)> {
+        query!("SELECT name, pickup_point FROM cities;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get pickup points")
+            .into_iter()
+            .map(|row| (row.name, row.pickup_point))
+            .collect()
+    }
+
+    async fn get_planned_etas(&self) -> Vec<(String, i32, i32)> {
+        query!("SELECT name, eta_min_days, eta_max_days FROM cities;")
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get planned delivery times")
+            .into_iter()
+            .map(|row| (row.name, row.eta_min_days, row.eta_max_days))
+            .collect()
+    }
+
+    async fn get_delivery_history(&self, days: i32) -> Vec<(Option<String>, i32)> {
+        query!(r#"SELECT u.city, (p.delivered_at::date - p.arrived_at::date)::int AS "days!"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            WHERE p.delivered_at > now() - make_interval(days => $1) AND p.arrived_at IS NOT NULL;"#, days)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get the delivery history")
+            .into_iter()
+            .map(|row| (row.city, row.days))
+            .collect()
+    }
+
+    async fn apply_retention(&self, policy: &RetentionPolicy, dry_run: bool) -> RetentionRun {
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        // The counts and the purge below repeat the same conditions, a change to one has to go to both.
+        // Only whole months are summed up, a month cut in the middle would be counted twice.
+        let events = query_scalar!(r#"SELECT count(*) AS "count!" FROM events
+            WHERE created_at < date_trunc('month', now()) - make_interval(months => $1);"#, policy.events_months)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not count old events");
+
+        let tickets = query_scalar!(r#"SELECT count(*) AS "count!" FROM tickets t
+            WHERE closed_at < now() - make_interval(months => $1)
+                AND NOT EXISTS (SELECT 1 FROM refunds r WHERE r.ticket_id = t.id)
+                AND NOT EXISTS (SELECT 1 FROM weight_disputes w WHERE w.ticket_id = t.id);"#, policy.tickets_months)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not count old tickets");
+
+        let parcels = query_scalar!(r#"SELECT count(*) AS "count!" FROM parcels
+            WHERE delivered_at < now() - make_interval(months => $1)
+                AND (photo_id IS NOT NULL OR declared_description IS NOT NULL OR item_url IS NOT NULL);"#, policy.parcels_months)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not count old parcels");
+
+        if !dry_run {
+            // Distinct users of a month cannot be added up, a month summed up twice keeps the larger count.
+            query!("INSERT INTO event_counts (month, kind, events, users)
+                SELECT date_trunc('month', created_at)::date, kind, count(*), count(DISTINCT telegram_id)
+                FROM events WHERE created_at < date_trunc('month', now()) - make_interval(months => $1)
+                GROUP BY 1, 2
+                ON CONFLICT (month, kind) DO UPDATE
+                SET events = event_counts.events + EXCLUDED.events, users = GREATEST(event_counts.users, EXCLUDED.users);", policy.events_months)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not sum up old events");
+
+            query!("DELETE FROM events WHERE created_at < date_trunc('month', now()) - make_interval(months => $1);", policy.events_months)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not delete old events");
+
+            // Tickets behind a refund or a weight dispute stay, those tables point at them.
+            query!("WITH archived AS (
+                    DELETE FROM tickets t
+                    WHERE closed_at < now() - make_interval(months => $1)
+                        AND NOT EXISTS (SELECT 1 FROM refunds r WHERE r.ticket_id = t.id)
+                        AND NOT EXISTS (SELECT 1 FROM weight_disputes w WHERE w.ticket_id = t.id)
+                    RETURNING id, telegram_id, text, created_at, closed_at
+                )
+                INSERT INTO tickets_archive (id, telegram_id, text, created_at, closed_at)
+                SELECT id, telegram_id, text, created_at, closed_at FROM archived;", policy.tickets_months)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not archive old tickets");
+
+            query!("UPDATE parcels SET photo_id = NULL, declared_description = NULL,
+                    item_url = NULL, item_title = NULL, item_price = NULL, item_image = NULL
+                WHERE delivered_at < now() - make_interval(months => $1)
+                    AND (photo_id IS NOT NULL OR declared_description IS NOT NULL OR item_url IS NOT NULL);", policy.parcels_months)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not compact old parcels");
+
+            // Partners retry within hours, a month of keys is plenty.
+            query!("DELETE FROM webhook_keys WHERE claimed_at < now() - interval '30 days';")
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not delete old webhook keys");
+        }
+
+        let run = query_as!(RetentionRun, r#"INSERT INTO retention_runs (dry_run, events, tickets, parcels)
+            VALUES ($1, $2, $3, $4)
+            RETURNING dry_run, events, tickets, parcels, ran_at AT TIME ZONE 'Asia/Bishkek' AS "ran_at!";"#, dry_run, events, tickets, parcels)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not record a retention run");
+
+        tx.commit().await.expect("ERROR: Could not commit the transaction");
+
+        run
+    }
+
+    async fn get_retention_runs(&self, limit: i64) -> Vec<RetentionRun> {
+        query_as!(RetentionRun, r#"SELECT dry_run, events, tickets, parcels, ran_at AT TIME ZONE 'Asia/Bishkek' AS "ran_at!"
+            FROM retention_runs
+            ORDER BY ran_at DESC
+            LIMIT $1;"#, limit)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get retention runs")
+    }
+
+    async fn get_active_track_codes(&self) -> Vec<ActiveTrackCode> {
+        query_as!(ActiveTrackCode, r#"SELECT p.track_code, bool_and(p.arrived) AS "all_arrived!", bool_or(p.arrived) AS "any_arrived!",
+                bool_or(b.status IN ('departed', 'customs', 'arrived')) IS TRUE AS "departed!"
+            FROM parcels p
+            JOIN users u ON u.id = p.user_id
+            LEFT JOIN batches b ON b.code = p.batch_code
+            WHERE p.delivered_at IS NULL AND u.deleted_at IS NULL
+            GROUP BY p.track_code
+            ORDER BY p.track_code;"#)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get active track codes")
+    }
+
+    async fn save_reconciliation(&self, checked: i32, failed: i32, discrepancies: &[Discrepancy]) {
+        let repaired = discrepancies.iter().filter(|discrepancy| discrepancy.repaired).count() as i32;
+
+        let mut tx = self.pool.begin().await.expect("ERROR: Could not start a transaction");
+
+        let run_id = query_scalar!("INSERT INTO reconciliation_runs (checked, failed, repaired) VALUES ($1, $2, $3) RETURNING id;",
+            checked, failed, repaired)
+            .fetch_one(&mut *tx)
+            .await.expect("ERROR: Could not record a reconciliation run");
+
+        for discrepancy in discrepancies {
+            query!("INSERT INTO reconciliation_discrepancies (run_id, track_code, kind, vendor_status, repaired) VALUES ($1, $2, $3, $4, $5);",
+                run_id, discrepancy.track_code, discrepancy.kind.as_str(), discrepancy.vendor_status, discrepancy.repaired)
+                .execute(&mut *tx)
+                .await.expect("ERROR: Could not record a discrepancy");
+        }
+
+        tx.commit().await.expect("ERROR: Could not record a reconciliation run");
+    }
+
+    async fn get_last_reconciliation(&self) -> Option<ReconciliationRun> {
+        query_as!(ReconciliationRun, r#"SELECT id, checked, failed, repaired, ran_at AT TIME ZONE 'Asia/Bishkek' AS "ran_at!"
+            FROM reconciliation_runs
+            ORDER BY ran_at DESC
+            LIMIT 1;"#)
+            .fetch_optional(&self.pool)
+            .await.expect("ERROR: Could not get the last reconciliation run")
+    }
+
+    async fn get_discrepancies(&self, run_id: i32) -> Vec<Discrepancy> {
+        query_as!(Discrepancy, r#"SELECT track_code, kind AS "kind: DriftKind", vendor_status, repaired
+            FROM reconciliation_discrepancies
+            WHERE run_id = $1
+            ORDER BY id;"#, run_id)
+            .fetch_all(&self.pool)
+            .await.expect("ERROR: Could not get discrepancies")
+    }
+
+    async fn refresh_warehouse_stats(&self, days: i32) -> u64 {
+        query!("INSERT INTO warehouse_stats (day, parcels, scanned, weight_kg)
+            SELECT (COALESCE(scanned_at, arrived_at) AT TIME ZONE 'Asia/Bishkek')::date AS day,
+                count(*), count(scanned_at), COALESCE(sum(weight_kg), 0)
+            FROM parcels
+            WHERE COALESCE(scanned_at, arrived_at) >= ((now() AT TIME ZONE 'Asia/Bishkek')::date - $1::int) AT TIME ZONE 'Asia/Bishkek'
+            GROUP BY 1
+            ON CONFLICT (day) DO UPDATE
+            SET parcels = EXCLUDED.parcels, scanned = EXCLUDED.scanned, weight_kg = EXCLUDED.weight_kg, updated_at = now();", days)
+            .execute(&self.pool)
+            .await.expect("ERROR: Could not refresh warehouse stats")
+            .rows_affected()
+    }
+
+    async fn get_warehouse_stats(&self, days: i32) -> Vec<WarehouseDay> {
+        query_as!(WarehouseDay, "SELECT day, parcels, scanned, weight_kg
+            FROM warehouse_stats
+            WHERE day >= (now() AT TIME ZONE 'Asia/Bishkek')::date - $1::int
+            ORDER BY day;", days)
+            .fetch_all(&self.reports)
+            .await.expect("ERROR: Could not get warehouse stats")
+    }
+}
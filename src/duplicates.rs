@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use crate::models::User;
+
+// Local numbers are written as 0XXX..., +996 XXX... or 996XXX..., the last nine digits match.
+const PHONE_DIGITS: usize = 9;
+
+/// Accounts that probably belong to the same person.
+pub struct Duplicates<'a> {
+    pub users: Vec<&'a User>,
+    pub same_phone: bool
+}
+
+/// Groups users sharing a phone number or a name, ignoring case, `ё` and first/last name order.
+///
+/// Phones are encrypted with a random nonce, so this runs on decrypted users rather than in SQL.
+pub fn find_duplicates(users: &[User]) -> Vec<Duplicates<'_>> {
+    let mut parents: Vec<usize> = (0..users.len()).collect();
+
+    let mut by_phone = HashMap::new();
+    let mut by_name = HashMap::new();
+
+    for (i, user) in users.iter().enumerate() {
+        if let Some(j) = by_phone.insert(phone_key(&user.phone_number), i) {
+            union(&mut parents, i, j);
+        }
+
+        // A first name alone is shared by too many people.
+        if let Some(j) = name_key(user).and_then(|key| by_name.insert(key, i)) {
+            union(&mut parents, i, j);
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<&User>> = HashMap::new();
+
+    for (i, user) in users.iter().enumerate() {
+        groups.entry(find(&mut parents, i)).or_default().push(user);
+    }
+
+    let mut duplicates: Vec<Duplicates> = groups.into_values()
+        .filter(|users| users.len() > 1)
+        .map(|users| {
+            let phone = phone_key(&users[0].phone_number);
+            let same_phone = users.iter().all(|user| phone_key(&user.phone_number) == phone);
+
+            Duplicates { users, same_phone }
+        })
+        .collect();
+
+    duplicates.sort_by_key(|duplicates| duplicates.users[0].id);
+
+    duplicates
+}
+
+/// Whether two numbers are the same phone, however the country code was written.
+pub fn same_phone(a: &str, b: &str) -> bool {
+    phone_key(a) == phone_key(b)
+}
+
+/// The part of a phone number that stays the same across the ways it is written.
+pub fn phone_key(phone_number: &str) -> String {
+    let digits: String = phone_number.chars().filter(char::is_ascii_digit).collect();
+
+    digits[digits.len().saturating_sub(PHONE_DIGITS)..].to_string()
+}
+
+fn name_key(user: &User) -> Option<(String, String)> {
+    let normalize = |name: &str| name.trim().to_lowercase().replace('ё', "е");
+
+    let first = normalize(&user.first_name);
+    let last = normalize(user.last_name.as_deref()?);
+
+    Some(if first <= last { (first, last) } else { (last, first) })
+}
+
+fn find(parents: &mut [usize], i: usize) -> usize {
+    if parents[i] != i {
+        parents[i] = find(parents, parents[i]);
+    }
+
+    parents[i]
+}
+
+fn union(parents: &mut [usize], i: usize, j: usize) {
+    let (i, j) = (find(parents, i), find(parents, j));
+
+    parents[i.max(j)] = i.min(j);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+
+    use super::*;
+
+    fn user(id: i32, first_name: &str, last_name: &str, phone_number: &str) -> User {
+        User {
+            id,
+            first_name: first_name.to_string(),
+            last_name: Some(last_name.to_string()),
+            phone_number: phone_number.to_string(),
+            client_code: format!("MX{}", 200 + id),
+            ..fixtures::user(id as i64)
+        }
+    }
+
+    #[test]
+    fn phone_formats_are_matched() {
+        let users = [
+            user(1, "Азамат", "Осмонов", "+996 700 123 456"),
+            user(2, "Азамат", "Исаев", "0700123456"),
+            user(3, "Бекзат", "Исаев", "0555000111")
+        ];
+
+        let duplicates = find_duplicates(&users);
+
+        assert_eq!(duplicates.len(), 1);
+        assert!(duplicates[0].same_phone);
+        assert_eq!(duplicates[0].users.iter().map(|user| user.id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn names_are_matched_in_any_order() {
+        let users = [
+            user(1, "Алёна", "Ким", "0700000001"),
+            user(2, "ким", "Алена", "0700000002")
+        ];
+
+        let duplicates = find_duplicates(&users);
+
+        assert_eq!(duplicates.len(), 1);
+        assert!(!duplicates[0].same_phone);
+    }
+
+    #[test]
+    fn matches_are_chained_into_one_group() {
+        let users = [
+            user(1, "Айбек", "Токтогулов", "0700000001"),
+            user(2, "Айбек", "Токтогулов", "0700000002"),
+            user(3, "А", "Т", "0700000002")
+        ];
+
+        assert_eq!(find_duplicates(&users)[0].users.len(), 3);
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDate};
+
+// Fewer deliveries to a city than this say little, the estimate then comes from all cities.
+const MIN_SAMPLES: usize = 5;
+
+/// Days from the warehouse in China to the client, learned from recently delivered parcels.
+///
+/// The range is the middle half of past deliveries, so one stuck batch does not stretch it.
+#[derive(Default)]
+pub struct Eta {
+    by_city: HashMap<String, (i32, i32)>,
+    overall: Option<(i32, i32)>
+}
+
+impl Eta {
+    /// Builds the estimate from (destination city, days in transit) of delivered parcels.
+    pub fn from_history(history: &[(Option<String>, i32)]) -> Eta {
+        let mut cities: HashMap<String, Vec<i32>> = HashMap::new();
+
+        for (city, days) in history {
+            if let Some(city) = city {
+                cities.entry(city.to_lowercase()).or_default().push(*days);
+            }
+        }
+
+        Eta {
+            by_city: cities.into_iter()
+                .filter_map(|(city, days)| Some((city, range(days)?)))
+                .collect(),
+            overall: range(history.iter().map(|(_, days)| *days).collect())
+        }
+    }
+
+    /// Adds the days admins planned for new cities, until enough deliveries there tell the real ones.
+    pub fn with_planned(mut self, planned: &[(String, i32, i32)]) -> Eta {
+        for (city, from, to) in planned {
+            self.by_city.entry(city.to_lowercase()).or_insert((*from, *to));
+        }
+
+        self
+    }
+
+    /// Fewest and most days a parcel to this city usually takes.
+    pub fn days(&self, city: Option<&str>) -> Option<(i32, i32)> {
+        city.and_then(|city| self.by_city.get(&city.to_lowercase()).copied())
+            .or(self.overall)
+    }
+
+    pub fn text(&self, city: Option<&str>, arrived: NaiveDate) -> Option<String> {
+        let (from, to) = self.days(city)?;
+        let date = |days: i32| (arrived + Duration::days(days as i64)).format("%d.%m");
+
+        let days = match from == to {
+            true => from.to_string(),
+            false => format!("{}–{}", from, to)
+        };
+
+        Some(format!("Обычно доставляется за {} {}, ориентировочно {}–{}", days, days_word(to), date(from), date(to)))
+    }
+}
+
+fn days_word(days: i32) -> &'static str {
+    match (days % 10, days % 100) {
+        (1, 11) | (2..=4, 12..=14) => "дней",
+        (1, _) => "день",
+        (2..=4, _) => "дня",
+        _ => "дней"
+    }
+}
+
+fn range(mut days: Vec<i32>) -> Option<(i32, i32)> {
+    if days.len() < MIN_SAMPLES {
+        return None;
+    }
+
+    days.sort_unstable();
+
+    // Nearest-rank quartiles.
+    let quartile = |share: usize| days[(days.len() * share).div_ceil(4) - 1];
+
+    Some((quartile(1), quartile(3)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(city: Option<&str>, days: &[i32]) -> Vec<(Option<String>, i32)> {
+        days.iter().map(|days| (city.map(str::to_string), *days)).collect()
+    }
+
+    #[test]
+    fn range_skips_outliers() {
+        let eta = Eta::from_history(&history(Some("Бишкек"), &[10, 11, 12, 12, 13, 14, 40]));
+
+        assert_eq!(eta.days(Some("бишкек")), Some((11, 14)));
+    }
+
+    #[test]
+    fn small_cities_fall_back_to_all_deliveries() {
+        let mut deliveries = history(Some("Бишкек"), &[10, 10, 11, 12, 12, 12]);
+        deliveries.extend(history(Some("Ош"), &[20, 21]));
+
+        let eta = Eta::from_history(&deliveries);
+
+        assert_eq!(eta.days(Some("Ош")), Some((10, 12)));
+        assert_eq!(eta.days(None), Some((10, 12)));
+        assert_eq!(Eta::from_history(&history(None, &[10, 12])).days(None), None);
+    }
+
+    #[test]
+    fn planned_days_wait_for_real_deliveries() {
+        let mut deliveries = history(Some("Бишкек"), &[10, 10, 11, 12, 12, 12]);
+        deliveries.extend(history(Some("Ош"), &[20, 21]));
+
+        let planned = vec![("Бишкек".to_string(), 7, 9), ("Ош".to_string(), 14, 18)];
+        let eta = Eta::from_history(&deliveries).with_planned(&planned);
+
+        assert_eq!(eta.days(Some("бишкек")), Some((10, 12)));
+        assert_eq!(eta.days(Some("Ош")), Some((14, 18)));
+    }
+
+    #[test]
+    fn text_counts_from_the_arrival_date() {
+        let eta = Eta::from_history(&history(None, &[10, 11, 12, 13, 14]));
+        let arrived = NaiveDate::from_ymd_opt(2024, 10, 17).unwrap();
+
+        assert_eq!(eta.text(None, arrived).unwrap(), "Обычно доставляется за 11–13 дней, ориентировочно 28.10–30.10");
+        assert_eq!(days_word(21), "день");
+        assert_eq!(days_word(3), "дня");
+    }
+}
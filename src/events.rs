@@ -0,0 +1,86 @@
+use std::future::Future;
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{database::Db, money::Money};
+
+// A subscriber further behind than this skips the oldest events and logs how many.
+const CAPACITY: usize = 1024;
+
+/// Something that has happened to a client, published once it is committed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DomainEvent {
+    /// Walk-in clients have no telegram id.
+    UserRegistered { telegram_id: Option<i64>, client_code: String },
+    ParcelArrived { parcel_id: i32, telegram_id: i64 },
+    ShipmentBilled { parcel_id: i32, telegram_id: i64, amount: Money }
+}
+
+/// Hands domain events to every subscriber, publishers do not know who listens.
+///
+/// Only for side effects that may be lost: an event published before a restart is never delivered.
+/// Client notices, funnel events and calibration records are written in the transaction of the change instead.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus { sender: broadcast::channel(CAPACITY).0 }
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        // Nobody subscribed yet, e.g. in tests.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+/// Runs `handle` for every event published from now on, in order.
+pub fn spawn_subscriber<F, Fut>(bus: &EventBus, name: &'static str, mut handle: F)
+where
+    F: FnMut(DomainEvent) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send
+{
+    log::info!("Subscribing {} to domain events", name);
+    let mut receiver = bus.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => handle(event).await,
+                Err(RecvError::Lagged(skipped)) => log::warn!("{} skipped {} domain events", name, skipped),
+                Err(RecvError::Closed) => break
+            }
+        }
+    });
+}
+
+/// The subscribers every bot runs, for now a trail of events in the log.
+pub fn subscribe_all(db: &Db) {
+    spawn_subscriber(db.events(), "event log", |event| async move {
+        log::info!("Event: {:?}", event);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn every_subscriber_gets_each_event() {
+        let bus = EventBus::new();
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+
+        let event = DomainEvent::ParcelArrived { parcel_id: 7, telegram_id: 42 };
+        bus.publish(event.clone());
+
+        assert_eq!(first.recv().await.unwrap(), event);
+        assert_eq!(second.recv().await.unwrap(), event);
+    }
+}
@@ -0,0 +1,46 @@
+use sha2::{Digest, Sha256};
+
+/// Welcome message variants compared by registration completion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WelcomeVariant {
+    A,
+    B
+}
+
+impl WelcomeVariant {
+    /// Picks the same variant for a user on every /start, without storing it.
+    ///
+    /// With the experiment off everyone sees the original welcome, variant A.
+    pub fn assign(telegram_id: i64, enabled: bool) -> WelcomeVariant {
+        if !enabled {
+            return WelcomeVariant::A;
+        }
+
+        // A salted hash, so that other experiments do not split users the same way.
+        let hash = Sha256::digest(format!("welcome:{}", telegram_id));
+
+        match hash[0] % 2 {
+            0 => WelcomeVariant::A,
+            _ => WelcomeVariant::B
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignment_is_stable_and_splits_users() {
+        assert_eq!(WelcomeVariant::assign(42, true), WelcomeVariant::assign(42, true));
+
+        let b_count = (0..1000).filter(|id| WelcomeVariant::assign(*id, true) == WelcomeVariant::B).count();
+
+        assert!((400..600).contains(&b_count));
+    }
+
+    #[test]
+    fn disabled_experiment_shows_the_original() {
+        assert!((0..100).all(|id| WelcomeVariant::assign(id, false) == WelcomeVariant::A));
+    }
+}
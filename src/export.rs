@@ -0,0 +1,85 @@
+use serde_json::{json, Value};
+
+use crate::models::{Parcel, Quote, Shipment, Ticket, User};
+
+/// Everything the bot keeps about a user, in one JSON file they can read or take elsewhere.
+pub struct UserData {
+    pub user: User,
+    pub parcels: Vec<Parcel>,
+    pub shipments: Vec<Shipment>,
+    pub quotes: Vec<Quote>,
+    pub tickets: Vec<Ticket>
+}
+
+impl UserData {
+    pub fn to_json(&self) -> Vec<u8> {
+        let user = &self.user;
+
+        let data = json!({
+            "profile": {
+                "client_code": user.client_code,
+                "first_name": user.first_name,
+                "last_name": user.last_name,
+                "phone_number": user.phone_number,
+                "telegram_id": user.telegram_id,
+                "username": user.username,
+                "text_menu": user.text_menu,
+                "reminders": user.reminders
+            },
+            "parcels": self.parcels.iter().map(|parcel| json!({
+                "track_code": parcel.track_code,
+                "label": parcel.label,
+                "arrived": parcel.arrived,
+                "refund": parcel.refund.map(|refund| refund.title()),
+                "quote_id": parcel.quote_id
+            })).collect::<Vec<Value>>(),
+            "shipments": self.shipments.iter().map(|shipment| json!({
+                "track_code": shipment.track_code,
+                "batch_code": shipment.batch_code,
+                "weight_kg": shipment.weight_kg,
+                "declared_value": shipment.declared_value.map(|value| value.to_string()),
+                "description": shipment.description
+            })).collect::<Vec<Value>>(),
+            "quotes": self.quotes.iter().map(|quote| json!({
+                "id": quote.id,
+                "width_cm": quote.width,
+                "length_cm": quote.length,
+                "height_cm": quote.height,
+                "weight_kg": quote.weight_kg,
+                "price": quote.price.map(|price| price.to_string()),
+                "created_at": quote.created_at.to_string()
+            })).collect::<Vec<Value>>(),
+            "tickets": self.tickets.iter().map(|ticket| json!({
+                "id": ticket.id,
+                "text": ticket.text,
+                "created_at": ticket.created_at.to_string()
+            })).collect::<Vec<Value>>()
+        });
+
+        serde_json::to_vec_pretty(&data).expect("ERROR: Could not serialize user data")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn export_lists_every_section() {
+        let data = UserData {
+            user: User { id: 1, last_name: None, client_code: "MX201".to_string(), ..fixtures::user(7) },
+            parcels: vec![Parcel { id: 1, track_code: "YT1".to_string(), label: None, arrived: true, refund: None, quote_id: None }],
+            shipments: Vec::new(),
+            quotes: Vec::new(),
+            tickets: Vec::new()
+        };
+
+        let json: Value = serde_json::from_slice(&data.to_json()).unwrap();
+
+        assert_eq!(json["profile"]["client_code"], "MX201");
+        assert_eq!(json["profile"]["last_name"], Value::Null);
+        assert_eq!(json["parcels"][0]["track_code"], "YT1");
+        assert_eq!(json["tickets"], json!([]));
+    }
+}
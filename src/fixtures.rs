@@ -0,0 +1,25 @@
+use crate::models::{Notice, Units, User};
+
+/// A registered client with the default settings, tests override the fields they look at.
+pub fn user(telegram_id: i64) -> User {
+    User {
+        id: 0,
+        first_name: "Азамат".to_string(),
+        last_name: Some("Осмонов".to_string()),
+        phone_number: "996555123456".to_string(),
+        telegram_id: Some(telegram_id),
+        client_code: String::new(),
+        username: None,
+        display_name: None,
+        text_menu: false,
+        reminders: true,
+        monthly_summary: false,
+        units: Units::Metric,
+        city: None
+    }
+}
+
+/// A plain text notice, what tests check is who gets it and what it says.
+pub fn notice(telegram_id: i64, text: impl Into<String>) -> Notice {
+    Notice { telegram_id, text: text.into(), markup: None, photo_id: None, digest: None, subject: None }
+}
@@ -0,0 +1,130 @@
+use std::io::Cursor;
+
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect};
+use qrcode::{Color, QrCode};
+
+use crate::{config::env_opt, models::{full_name, WarehouseLabel}};
+
+// The 58×40 mm roll of desktop thermal printers.
+const WIDTH_MM: f32 = 58.0;
+const HEIGHT_MM: f32 = 40.0;
+const MARGIN_MM: f32 = 2.0;
+const QR_MM: f32 = 24.0;
+
+// Longer names run off the sticker.
+const NAME_CHARS: usize = 18;
+
+// Installed with fonts-dejavu-core, the builtin PDF fonts have no Cyrillic.
+const FONT_PATH: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+/// Reads the font of the labels from LABEL_FONT_PATH, or the DejaVu font when it is not set.
+///
+/// A LABEL_FONT_PATH that cannot be read or is not a font stops the bot at startup instead of failing the first /label.
+/// None when the default font is missing too, the labels then print in a builtin font.
+pub fn load_font() -> Option<Vec<u8>> {
+    let path = match env_opt("LABEL_FONT_PATH") {
+        Some(path) => path,
+        None => return match std::fs::read(FONT_PATH) {
+            Ok(font) if is_font(&font) => Some(font),
+            _ => {
+                log::warn!("Could not read the label font {}, names print without Cyrillic", FONT_PATH);
+                None
+            }
+        }
+    };
+
+    let font = std::fs::read(&path).unwrap_or_else(|err| panic!("ERROR: Could not read LABEL_FONT_PATH {}: {}", path, err));
+
+    if !is_font(&font) {
+        panic!("ERROR: LABEL_FONT_PATH {} is not a TrueType font", path);
+    }
+
+    Some(font)
+}
+
+fn is_font(font: &[u8]) -> bool {
+    PdfDocument::empty("Font check").add_external_font(Cursor::new(font)).is_ok()
+}
+
+/// Renders one page per label: a QR code of the client code, the client, the city and the shelf.
+pub fn render_pdf(labels: &[WarehouseLabel], font: Option<&[u8]>) -> Vec<u8> {
+    let (doc, page, layer) = PdfDocument::new("Labels", Mm(WIDTH_MM), Mm(HEIGHT_MM), "Label");
+
+    let font = match font {
+        Some(font) => doc.add_external_font(Cursor::new(font)).expect("ERROR: Could not load the label font"),
+        None => doc.add_builtin_font(BuiltinFont::Helvetica).expect("ERROR: Could not load the builtin font")
+    };
+
+    for (i, label) in labels.iter().enumerate() {
+        let layer = match i {
+            0 => doc.get_page(page).get_layer(layer),
+            _ => {
+                let (page, layer) = doc.add_page(Mm(WIDTH_MM), Mm(HEIGHT_MM), "Label");
+
+                doc.get_page(page).get_layer(layer)
+            }
+        };
+
+        draw_label(&layer, &font, label);
+    }
+
+    doc.save_to_bytes().expect("ERROR: Could not render labels")
+}
+
+fn draw_label(layer: &PdfLayerReference, font: &IndirectFontRef, label: &WarehouseLabel) {
+    draw_qr(layer, &label.client_code, MARGIN_MM, HEIGHT_MM - MARGIN_MM - QR_MM);
+
+    let x = Mm(MARGIN_MM * 2.0 + QR_MM);
+    let name: String = full_name(&label.first_name, label.last_name.as_deref()).chars().take(NAME_CHARS).collect();
+
+    layer.use_text(&label.client_code, 16.0, x, Mm(31.0), font);
+    layer.use_text(name, 7.0, x, Mm(25.0), font);
+    layer.use_text(label.city.as_deref().unwrap_or("—"), 7.0, x, Mm(21.0), font);
+    layer.use_text(format!("Полка: {}", label.shelf.as_deref().unwrap_or("—")), 9.0, x, Mm(15.0), font);
+
+    layer.use_text(&label.track_code, 9.0, Mm(MARGIN_MM), Mm(MARGIN_MM + 2.0), font);
+}
+
+/// Draws the code as filled squares with the lower left corner at (`x`, `y`).
+fn draw_qr(layer: &PdfLayerReference, text: &str, x: f32, y: f32) {
+    let code = QrCode::new(text.as_bytes()).expect("ERROR: Could not encode a QR code");
+    let modules = code.width();
+    let size = QR_MM / modules as f32;
+
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+
+        // Rows go from the top, the page grows from the bottom.
+        let left = x + (i % modules) as f32 * size;
+        let top = y + QR_MM - (i / modules) as f32 * size;
+
+        layer.add_rect(Rect::new(Mm(left), Mm(top - size), Mm(left + size), Mm(top)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(client_code: &str) -> WarehouseLabel {
+        WarehouseLabel {
+            track_code: "YT7412345678901".to_string(),
+            client_code: client_code.to_string(),
+            first_name: "Айбек".to_string(),
+            last_name: Some("Осмонов".to_string()),
+            city: Some("Ош".to_string()),
+            shelf: Some("B-4".to_string())
+        }
+    }
+
+    #[test]
+    fn every_label_gets_a_page() {
+        let pdf = render_pdf(&[label("MX201"), label("MX202")], None);
+        let text = String::from_utf8_lossy(&pdf);
+
+        assert!(pdf.starts_with(b"%PDF"));
+        assert_eq!(text.matches("/Type/Page/").count(), 2);
+    }
+}
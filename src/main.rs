@@ -1,19 +1,84 @@
+use std::io::Write;
+
 use bot::BotService;
+use config::AppEnv;
+use database::Db;
 
 mod models;
 mod vendor;
 mod database;
 mod bot;
+mod api;
+mod attachments;
+mod calibration;
+mod capacity;
+mod carrier;
+mod catalog;
+mod china_address;
+mod client_code;
+mod client_list;
+mod config;
+mod correlation;
+mod crypto;
+mod dashboard;
+mod duplicates;
+mod eta;
+mod events;
+mod experiments;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod label;
+mod maintenance;
+mod manifest;
+mod money;
+mod notifier;
+mod onboarding;
+mod outbox;
+mod payments;
+mod phone_policy;
+mod reactions;
+mod reconciliation;
+mod reengagement;
+mod report;
+mod retention;
+mod retry;
+mod scheduler;
+mod segment;
+mod sender;
+mod server;
+mod shutdown;
+mod sms;
+mod staff;
+mod summary;
+mod support;
+mod translation;
+mod translit;
+mod watchdog;
 
 #[tokio::main]
 async fn main() -> Result<(), sqlx::Error> {
-    env_logger::init();
+    dotenv::dotenv().ok();
 
-    log::info!("Starting max_express_bot");
+    let app_env = AppEnv::current();
 
-    dotenv::dotenv().ok();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(app_env.log_filter()))
+        .format(|buf, record| {
+            let id = correlation::current().map_or(String::new(), |id| format!(" {}", id));
+
+            writeln!(buf, "[{} {:<5} {}{}] {}", buf.timestamp(), record.level(), record.target(), id, record.args())
+        })
+        .init();
+
+    log::info!("Starting max_express_bot, profile {:?}", app_env);
+
+    // `--no-db` keeps everything in memory, so the bot runs with only TELOXIDE_TOKEN set.
+    let db = match std::env::args().any(|arg| arg == "--no-db") {
+        true => Db::in_memory(),
+        false => Db::new().await
+    };
 
-    let bot = BotService::new().await;
+    let bot = BotService::new(db).await;
 
     bot.dispatch().await;
 
@@ -0,0 +1,78 @@
+use std::sync::{Arc, RwLock};
+
+use crate::database::Db;
+
+const SETTING_KEY: &str = "maintenance_until";
+
+/// Maintenance flag shared between handlers, persisted in the settings table.
+#[derive(Clone)]
+pub struct Maintenance {
+    until: Arc<RwLock<Option<String>>>,
+    template: String
+}
+
+impl Maintenance {
+    /// Restores the flag from the database, falling back to `MAINTENANCE_UNTIL`.
+    pub async fn load(db: &Db, template: String) -> Maintenance {
+        let until = match db.get_setting(SETTING_KEY).await {
+            Some(until) => Some(until),
+            None => std::env::var("MAINTENANCE_UNTIL").ok().filter(|until| !until.trim().is_empty())
+        };
+
+        if let Some(until) = &until {
+            log::info!("Maintenance mode is on until {}", until);
+        }
+
+        Maintenance { until: Arc::new(RwLock::new(until)), template }
+    }
+
+    pub fn message(&self) -> Option<String> {
+        self.until.read().unwrap()
+            .as_ref()
+            .map(|until| self.template.replace("{until}", until))
+    }
+
+    pub async fn enable(&self, db: &Db, until: String) {
+        log::info!("Maintenance mode enabled until {}", until);
+        db.set_setting(SETTING_KEY, &until).await;
+        *self.until.write().unwrap() = Some(until);
+    }
+
+    pub async fn disable(&self, db: &Db) {
+        log::info!("Maintenance mode disabled");
+        db.delete_setting(SETTING_KEY).await;
+        *self.until.write().unwrap() = None;
+    }
+}
+
+/// Checks that `time` looks like HH:MM.
+pub fn parse_time(time: &str) -> Option<String> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+
+    let hours: u8 = hours.parse().ok()?;
+    let minutes: u8 = minutes.parse().ok()?;
+
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+
+    Some(format!("{:02}:{:02}", hours, minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_normalizes_hours() {
+        assert_eq!(parse_time("9:05"), Some("09:05".to_string()));
+        assert_eq!(parse_time(" 18:30 "), Some("18:30".to_string()));
+    }
+
+    #[test]
+    fn parse_time_rejects_invalid_input() {
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("12:60"), None);
+        assert_eq!(parse_time("завтра"), None);
+    }
+}
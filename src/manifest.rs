@@ -0,0 +1,76 @@
+use crate::models::ManifestRow;
+
+const HEADER: [&str; 5] = ["Track code", "Client code", "Description", "Declared value, USD", "Weight, kg"];
+
+/// Renders the customs manifest of a batch as CSV.
+///
+/// Starts with a BOM so that Excel opens the Cyrillic descriptions correctly.
+pub fn render_csv(rows: &[ManifestRow]) -> String {
+    let mut lines = vec![HEADER.iter().map(|cell| escape(cell)).collect::<Vec<_>>().join(",")];
+
+    for row in rows {
+        lines.push([
+            escape(&row.track_code),
+            escape(&row.client_code),
+            escape(row.description.as_deref().unwrap_or_default()),
+            row.declared_value.map_or(String::new(), |value| value.amount().to_string()),
+            row.weight_kg.map_or(String::new(), |weight| format!("{:.2}", weight))
+        ].join(","));
+    }
+
+    format!("\u{feff}{}\r\n", lines.join("\r\n"))
+}
+
+/// Appends who downloaded the file as its last line, so a leaked copy points to the export it came from.
+pub fn watermark(csv: String, line: &str) -> String {
+    format!("{}{}\r\n", csv, escape(line))
+}
+
+pub fn escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::money::Money;
+
+    use super::*;
+
+    fn row(description: Option<&str>) -> ManifestRow {
+        ManifestRow {
+            track_code: "YT7412345678901".to_string(),
+            client_code: "MX201".to_string(),
+            description: description.map(str::to_string),
+            declared_value: Some(Money::from_cents(1250)),
+            weight_kg: Some(1.234)
+        }
+    }
+
+    #[test]
+    fn rows_follow_the_header() {
+        let csv = render_csv(&[row(Some("Кроссовки"))]);
+        let lines: Vec<&str> = csv.trim_start_matches('\u{feff}').lines().collect();
+
+        assert_eq!(lines[0], "Track code,Client code,Description,\"Declared value, USD\",\"Weight, kg\"");
+        assert_eq!(lines[1], "YT7412345678901,MX201,Кроссовки,12.50,1.23");
+    }
+
+    #[test]
+    fn descriptions_are_quoted_when_needed() {
+        let csv = render_csv(&[row(Some("Кроссовки, \"Nike\"")), row(None)]);
+
+        assert!(csv.contains(",\"Кроссовки, \"\"Nike\"\"\","));
+        assert!(csv.contains("MX201,,12.50"));
+    }
+
+    #[test]
+    fn watermark_is_the_last_line() {
+        let csv = watermark(render_csv(&[row(None)]), "Выгрузка №3, 42");
+
+        assert!(csv.ends_with("\r\n\"Выгрузка №3, 42\"\r\n"));
+    }
+}
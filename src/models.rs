@@ -1,18 +1,1170 @@
-use serde::Deserialize;
-use sqlx::prelude::FromRow;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use sqlx::{error::BoxDynError, postgres::{PgTypeInfo, PgValueRef}, prelude::FromRow, Decode, Postgres, Type};
+use teloxide::types::InlineKeyboardMarkup;
+
+use crate::{experiments::WelcomeVariant, money::Money, onboarding::OnboardingStep};
 
 #[derive(FromRow, Clone)]
 pub struct User {
     pub id: i32,
     pub first_name: String,
-    pub last_name: String,
+    /// Empty for users who skipped it at registration.
+    pub last_name: Option<String>,
     pub phone_number: String,
-    pub telegram_id: i64,
-    pub client_code: String
+    /// Empty for walk-in clients an operator registered at the counter.
+    pub telegram_id: Option<i64>,
+    pub client_code: String,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub text_menu: bool,
+    /// Off when the user asked not to be reminded about the bot.
+    pub reminders: bool,
+    /// On when the user asked for a summary of last month's shipments on the 1st.
+    pub monthly_summary: bool,
+    #[sqlx(try_from = "String")]
+    pub units: Units,
+    /// Picked in the settings, empty until the user does so.
+    pub city: Option<String>
 }
 
-#[derive(Deserialize)]
+impl User {
+    pub fn full_name(&self) -> String {
+        full_name(&self.first_name, self.last_name.as_deref())
+    }
+}
+
+/// The first name followed by the last one, when there is one.
+pub fn full_name(first_name: &str, last_name: Option<&str>) -> String {
+    match last_name {
+        Some(last_name) => format!("{} {}", first_name, last_name),
+        None => first_name.to_string()
+    }
+}
+
+/// Measurement system used in the price calculator prompts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Units {
+    Metric,
+    Imperial
+}
+
+impl Units {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Units::Metric => "metric",
+            Units::Imperial => "imperial"
+        }
+    }
+
+    pub fn length_unit(&self) -> &'static str {
+        match self {
+            Units::Metric => "см",
+            Units::Imperial => "дюймы"
+        }
+    }
+
+    pub fn weight_unit(&self) -> &'static str {
+        match self {
+            Units::Metric => "кг",
+            Units::Imperial => "фунты"
+        }
+    }
+
+    pub fn to_centimeters(self, length: f32) -> f32 {
+        match self {
+            Units::Metric => length,
+            Units::Imperial => length * 2.54
+        }
+    }
+
+    pub fn to_kilograms(self, weight: f32) -> f32 {
+        match self {
+            Units::Metric => weight,
+            Units::Imperial => weight * 0.453_592_37
+        }
+    }
+}
+
+impl TryFrom<String> for Units {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Units, String> {
+        match value.as_str() {
+            "metric" => Ok(Units::Metric),
+            "imperial" => Ok(Units::Imperial),
+            _ => Err(format!("unknown units {}", value))
+        }
+    }
+}
+
+// Decoded by hand so that the checked queries can name it as the column type.
+impl Type<Postgres> for Units {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for Units {
+    fn decode(value: PgValueRef<'r>) -> Result<Units, BoxDynError> {
+        Ok(Units::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// The warehouse answer for a track code.
+///
+/// Read leniently so that the API may grow: unknown fields are ignored, missing ones are empty
+/// and `code` may come as a number.
+#[derive(Deserialize, Default)]
+#[serde(default)]
 pub struct ProductStatus {
+    #[serde(deserialize_with = "status_code")]
     pub code: String,
     pub msg: String
-}
\ No newline at end of file
+}
+
+/// `"0000"` or `0`, a number is padded to the four digits of the documented codes.
+fn status_code<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(code) => Ok(code),
+        Value::Number(code) => Ok(format!("{:0>4}", code)),
+        Value::Null => Ok(String::new()),
+        other => Err(de::Error::custom(format!("unexpected status code {}", other)))
+    }
+}
+
+impl ProductStatus {
+    pub fn ready(&self) -> bool {
+        self.code == "0000"
+    }
+
+    /// The service has never heard of the track code, another one may have.
+    pub fn not_found(&self) -> bool {
+        self.code == "1002"
+    }
+}
+
+#[derive(FromRow, Clone)]
+pub struct Parcel {
+    pub id: i32,
+    pub track_code: String,
+    pub label: Option<String>,
+    pub arrived: bool,
+    pub refund: Option<RefundStatus>,
+    pub quote_id: Option<i32>
+}
+
+// Home, work and a couple more, a longer list makes the courier buttons hard to read.
+pub const MAX_ADDRESSES: usize = 5;
+
+/// Where a courier brings the parcels of a user.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct Address {
+    pub id: i32,
+    pub city: String,
+    pub street: String,
+    pub entrance: Option<String>,
+    pub comment: Option<String>
+}
+
+impl Address {
+    /// The address on one line, as couriers and the address book show it.
+    pub fn line(&self) -> String {
+        let mut line = format!("{}, {}", self.city, self.street);
+
+        if let Some(entrance) = &self.entrance {
+            line.push_str(&format!(", подъезд {}", entrance));
+        }
+
+        if let Some(comment) = &self.comment {
+            line.push_str(&format!(" ({})", comment));
+        }
+
+        line
+    }
+}
+
+/// A parcel an operator handed to the courier queue, `address` as it was at that moment.
+#[derive(FromRow, Clone)]
+pub struct CourierDelivery {
+    pub id: i32,
+    pub track_code: String,
+    pub telegram_id: i64,
+    pub address: String
+}
+
+/// How a courier's attempt to close a delivery ended.
+pub enum DeliveryClosing {
+    Closed(CourierDelivery),
+    /// Payment is required before delivery and the parcel is not paid in full, it stays with the courier.
+    Unpaid,
+    NotFound
+}
+
+/// Where a parcel sent out with a courier stands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeliveryStatus {
+    Queued,
+    Assigned,
+    Delivered
+}
+
+impl DeliveryStatus {
+    pub const ALL: [DeliveryStatus; 3] = [DeliveryStatus::Queued, DeliveryStatus::Assigned, DeliveryStatus::Delivered];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "queued",
+            DeliveryStatus::Assigned => "assigned",
+            DeliveryStatus::Delivered => "delivered"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Queued => "ждет курьера",
+            DeliveryStatus::Assigned => "у курьера",
+            DeliveryStatus::Delivered => "доставлена"
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<DeliveryStatus, String> {
+        DeliveryStatus::ALL.into_iter()
+            .find(|status| status.as_str() == value)
+            .ok_or_else(|| format!("unknown delivery status {}", value))
+    }
+}
+
+impl Type<Postgres> for DeliveryStatus {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for DeliveryStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<DeliveryStatus, BoxDynError> {
+        Ok(DeliveryStatus::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// A delivery as dispatchers and couriers list it, `courier` is the name of the one who has it.
+#[derive(FromRow, Clone)]
+pub struct DeliveryTask {
+    pub id: i32,
+    pub track_code: String,
+    pub address: String,
+    pub status: DeliveryStatus,
+    pub courier: Option<String>
+}
+
+#[derive(FromRow, Clone)]
+pub struct Courier {
+    pub telegram_id: i64,
+    pub name: String,
+    pub open_tasks: i64
+}
+
+/// What the warehouse prints on the sticker of a parcel, `shelf` is where it was put.
+#[derive(FromRow, Clone)]
+pub struct WarehouseLabel {
+    pub track_code: String,
+    pub client_code: String,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub city: Option<String>,
+    pub shelf: Option<String>
+}
+
+/// A parcel of an outbound batch as declared to customs.
+///
+/// `description` is the declared one, or the client's label when nothing was declared.
+#[derive(FromRow, Clone)]
+pub struct ManifestRow {
+    pub track_code: String,
+    pub client_code: String,
+    pub description: Option<String>,
+    pub declared_value: Option<Money>,
+    pub weight_kg: Option<f32>
+}
+
+/// A message to a user written to the outbox in the same transaction as the change it reports.
+pub struct Notice {
+    pub telegram_id: i64,
+    pub text: String,
+    pub markup: Option<InlineKeyboardMarkup>,
+    pub photo_id: Option<String>,
+    /// The line standing for this notice when several of them for a user are sent as one message.
+    pub digest: Option<String>,
+    pub subject: Option<Subject>
+}
+
+/// The parcel event a notice reports, a reaction to the message acts on it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subject {
+    Arrival(String),
+    Receipt(String)
+}
+
+impl Subject {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Subject::Arrival(_) => "arrival",
+            Subject::Receipt(_) => "receipt"
+        }
+    }
+
+    pub fn track_code(&self) -> &str {
+        match self {
+            Subject::Arrival(track_code) | Subject::Receipt(track_code) => track_code
+        }
+    }
+
+    pub fn from_row(kind: &str, track_code: String) -> Option<Subject> {
+        match kind {
+            "arrival" => Some(Subject::Arrival(track_code)),
+            "receipt" => Some(Subject::Receipt(track_code)),
+            _ => None
+        }
+    }
+}
+
+/// An outbox row the relay has not delivered yet, `markup` is kept as JSON.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub telegram_id: i64,
+    pub text: String,
+    pub markup: Option<String>,
+    pub photo_id: Option<String>,
+    pub digest: Option<String>
+}
+
+/// What the warehouse recorded about a parcel when it was put into a batch.
+#[derive(FromRow, Clone)]
+pub struct Shipment {
+    pub track_code: String,
+    pub batch_code: Option<String>,
+    pub weight_kg: Option<f32>,
+    pub declared_value: Option<Money>,
+    pub description: Option<String>
+}
+
+/// What a client got delivered in a month and paid for, the summary on the 1st reports it.
+#[derive(FromRow, Clone, Debug)]
+pub struct MonthlySpending {
+    pub telegram_id: i64,
+    pub parcels: i64,
+    pub weight_kg: f32,
+    pub paid: Money
+}
+
+/// A parcel delivered in the month, a line of the receipt list.
+#[derive(FromRow, Clone, Debug)]
+pub struct MonthlyReceipt {
+    pub track_code: String,
+    pub delivered_at: NaiveDateTime,
+    pub weight_kg: Option<f32>,
+    pub amount: Option<Money>,
+    pub paid: Money
+}
+
+/// The bill for shipping a parcel and how much of it the client has paid.
+#[derive(FromRow, Clone, Debug)]
+pub struct Invoice {
+    pub parcel_id: i32,
+    pub track_code: String,
+    pub client_code: String,
+    pub telegram_id: i64,
+    pub amount: Money,
+    pub paid: Money
+}
+
+impl Invoice {
+    pub fn status(&self) -> InvoiceStatus {
+        match self.paid {
+            paid if paid >= self.amount => InvoiceStatus::Paid,
+            paid if paid == Money::default() => InvoiceStatus::Unpaid,
+            _ => InvoiceStatus::PartiallyPaid
+        }
+    }
+
+    /// What is left to pay, never below zero.
+    pub fn due(&self) -> Money {
+        Money::from_cents((self.amount.cents() - self.paid.cents()).max(0))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvoiceStatus {
+    Unpaid,
+    PartiallyPaid,
+    Paid
+}
+
+impl InvoiceStatus {
+    pub fn title(&self) -> &'static str {
+        match self {
+            InvoiceStatus::Unpaid => "не оплачен",
+            InvoiceStatus::PartiallyPaid => "оплачен частично",
+            InvoiceStatus::Paid => "оплачен"
+        }
+    }
+}
+
+/// Why an operator changed a billed price, `/adjust` takes the code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverrideReason {
+    Loyalty,
+    Damage,
+    Delay,
+    Oversize,
+    Correction
+}
+
+impl OverrideReason {
+    pub const ALL: [OverrideReason; 5] = [
+        OverrideReason::Loyalty,
+        OverrideReason::Damage,
+        OverrideReason::Delay,
+        OverrideReason::Oversize,
+        OverrideReason::Correction
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverrideReason::Loyalty => "loyalty",
+            OverrideReason::Damage => "damage",
+            OverrideReason::Delay => "delay",
+            OverrideReason::Oversize => "oversize",
+            OverrideReason::Correction => "correction"
+        }
+    }
+
+    /// What the client reads in the receipt.
+    pub fn title(&self) -> &'static str {
+        match self {
+            OverrideReason::Loyalty => "скидка постоянному клиенту",
+            OverrideReason::Damage => "повреждение при доставке",
+            OverrideReason::Delay => "задержка доставки",
+            OverrideReason::Oversize => "негабаритный груз",
+            OverrideReason::Correction => "исправление ошибки в счете"
+        }
+    }
+}
+
+impl TryFrom<String> for OverrideReason {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<OverrideReason, String> {
+        OverrideReason::ALL.into_iter()
+            .find(|reason| reason.as_str() == value)
+            .ok_or_else(|| format!("unknown override reason {}", value))
+    }
+}
+
+/// A billed price an operator changed, the invoice then asks for `final_price`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceOverride {
+    pub original: Money,
+    pub final_price: Money,
+    pub reason: OverrideReason
+}
+
+impl PriceOverride {
+    /// Below zero for a discount, above for a penalty.
+    pub fn delta(&self) -> Money {
+        Money::from_cents(self.final_price.cents() - self.original.cents())
+    }
+}
+
+/// The overrides of one operator over a period, for `/overrides`.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct OverrideTotals {
+    pub operator_id: i64,
+    pub overrides: i64,
+    /// What the discounts took off, a positive amount.
+    pub discounts: Money,
+    pub penalties: Money
+}
+
+/// What an admin may do beyond the admin commands, granted by the owner with `/grant`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    /// Downloading files with clients' personal data.
+    Export
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Export => "export"
+        }
+    }
+}
+
+impl TryFrom<String> for Permission {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Permission, String> {
+        match value.as_str() {
+            "export" => Ok(Permission::Export),
+            _ => Err(format!("unknown permission {}", value))
+        }
+    }
+}
+
+/// Where a refund requested by cancelling an order stands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RefundStatus {
+    Requested,
+    Approved,
+    Paid
+}
+
+impl RefundStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundStatus::Requested => "requested",
+            RefundStatus::Approved => "approved",
+            RefundStatus::Paid => "paid"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            RefundStatus::Requested => "запрошен",
+            RefundStatus::Approved => "одобрен",
+            RefundStatus::Paid => "выплачен"
+        }
+    }
+}
+
+impl TryFrom<String> for RefundStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<RefundStatus, String> {
+        match value.as_str() {
+            "requested" => Ok(RefundStatus::Requested),
+            "approved" => Ok(RefundStatus::Approved),
+            "paid" => Ok(RefundStatus::Paid),
+            _ => Err(format!("unknown refund status {}", value))
+        }
+    }
+}
+
+// Decoded by hand so that it also works as a nullable column from a LEFT JOIN.
+impl Type<Postgres> for RefundStatus {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for RefundStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<RefundStatus, BoxDynError> {
+        Ok(RefundStatus::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// A client's request to have an item bought for them on a marketplace.
+///
+/// `marketplace` is the domain of the link, `options` the size, color and quantity as the client typed them,
+/// `quote` and `comment` what the operator answered.
+#[derive(FromRow, Clone, Debug)]
+pub struct PurchaseRequest {
+    pub id: i32,
+    pub client_code: String,
+    pub telegram_id: i64,
+    pub marketplace: String,
+    pub link: String,
+    pub options: Option<String>,
+    pub budget: Money,
+    #[sqlx(try_from = "String")]
+    pub status: PurchaseStatus,
+    pub quote: Option<Money>,
+    pub comment: Option<String>
+}
+
+/// Where a purchase request stands, new and quoted ones are still in the operators' queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PurchaseStatus {
+    New,
+    Quoted,
+    Purchased,
+    Declined
+}
+
+impl PurchaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PurchaseStatus::New => "new",
+            PurchaseStatus::Quoted => "quoted",
+            PurchaseStatus::Purchased => "purchased",
+            PurchaseStatus::Declined => "declined"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            PurchaseStatus::New => "новая",
+            PurchaseStatus::Quoted => "цена предложена",
+            PurchaseStatus::Purchased => "выкуплено",
+            PurchaseStatus::Declined => "отклонена"
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self, PurchaseStatus::New | PurchaseStatus::Quoted)
+    }
+}
+
+impl TryFrom<String> for PurchaseStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<PurchaseStatus, String> {
+        match value.as_str() {
+            "new" => Ok(PurchaseStatus::New),
+            "quoted" => Ok(PurchaseStatus::Quoted),
+            "purchased" => Ok(PurchaseStatus::Purchased),
+            "declined" => Ok(PurchaseStatus::Declined),
+            _ => Err(format!("unknown purchase status {}", value))
+        }
+    }
+}
+
+impl Type<Postgres> for PurchaseStatus {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for PurchaseStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<PurchaseStatus, BoxDynError> {
+        Ok(PurchaseStatus::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// An outbound batch, `parcels` counts those not handed over yet and `updated_at` is in Bishkek time.
+#[derive(FromRow, Clone, Debug)]
+pub struct Batch {
+    pub code: String,
+    #[sqlx(try_from = "String")]
+    pub status: BatchStatus,
+    pub parcels: i64,
+    pub updated_at: NaiveDateTime
+}
+
+/// A survey with its questions in order, `sent` once it went out to clients.
+#[derive(Clone, Debug)]
+pub struct Survey {
+    pub id: i32,
+    pub title: String,
+    pub sent: bool,
+    pub questions: Vec<SurveyQuestion>
+}
+
+/// A question of a survey, positions start at 1. Without options the answer is typed.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct SurveyQuestion {
+    pub position: i16,
+    pub text: String,
+    pub options: Vec<String>
+}
+
+/// One client's answer, the index of the option picked or the text typed.
+#[derive(FromRow, Clone, Debug)]
+pub struct SurveyAnswer {
+    pub position: i16,
+    pub option: Option<i16>,
+    pub text: Option<String>
+}
+
+/// A status a batch reached, `created_at` in Bishkek time.
+#[derive(FromRow, Clone, Debug)]
+pub struct BatchEvent {
+    #[sqlx(try_from = "String")]
+    pub status: BatchStatus,
+    pub created_at: NaiveDateTime
+}
+
+/// Where a batch is on its way from the warehouse in China, in the order it goes through them.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum BatchStatus {
+    Forming,
+    Loaded,
+    Departed,
+    Customs,
+    Arrived
+}
+
+impl BatchStatus {
+    pub const ALL: [BatchStatus; 5] = [BatchStatus::Forming, BatchStatus::Loaded, BatchStatus::Departed, BatchStatus::Customs, BatchStatus::Arrived];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchStatus::Forming => "forming",
+            BatchStatus::Loaded => "loaded",
+            BatchStatus::Departed => "departed",
+            BatchStatus::Customs => "customs",
+            BatchStatus::Arrived => "arrived"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            BatchStatus::Forming => "формируется на складе",
+            BatchStatus::Loaded => "загружена",
+            BatchStatus::Departed => "в пути",
+            BatchStatus::Customs => "на таможне",
+            BatchStatus::Arrived => "прибыла в Кыргызстан"
+        }
+    }
+}
+
+impl TryFrom<String> for BatchStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<BatchStatus, String> {
+        BatchStatus::ALL.into_iter()
+            .find(|status| status.as_str() == value)
+            .ok_or_else(|| format!("unknown batch status {}", value))
+    }
+}
+
+impl Type<Postgres> for BatchStatus {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for BatchStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<BatchStatus, BoxDynError> {
+        Ok(BatchStatus::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// A user the re-engagement job reminds about the bot.
+#[derive(FromRow, Clone)]
+pub struct IdleUser {
+    pub telegram_id: i64,
+    pub first_name: String
+}
+
+/// A recent registration the onboarding job may send the checklist to, ages in days.
+#[derive(FromRow, Clone)]
+pub struct OnboardingUser {
+    pub telegram_id: i64,
+    pub first_name: String,
+    pub age_days: f64,
+    /// How many days after registration the checklist was last sent.
+    pub last_sent_days: Option<f64>,
+    /// Kinds of the onboarding events recorded for the user.
+    pub completed: Vec<String>
+}
+
+/// A parcel still waiting for the warehouse, with the chat to notify and the city it goes to.
+#[derive(FromRow, Clone)]
+pub struct PendingParcel {
+    pub id: i32,
+    pub track_code: String,
+    pub label: Option<String>,
+    pub telegram_id: i64,
+    pub photo_id: Option<String>,
+    pub city: Option<String>
+}
+
+/// Status update posted by a partner warehouse.
+#[derive(Deserialize)]
+pub struct PartnerStatus {
+    pub track_code: String,
+    pub status: String
+}
+
+/// What is known about an idempotency key of a partner post.
+#[derive(Debug, PartialEq)]
+pub enum WebhookClaim {
+    /// Seen for the first time, this post applies the update.
+    New,
+    /// The first post with this key has not been answered yet.
+    InProgress,
+    /// Already applied, the status code the first post was answered with.
+    Done(u16)
+}
+
+/// Kinds of rows written to the events table.
+#[derive(Clone, Copy)]
+pub enum EventKind {
+    Active,
+    Registered,
+    Welcome(WelcomeVariant),
+    Reengaged,
+    ReengagedParcel,
+    Onboarding(OnboardingStep),
+    OnboardingSent
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Active => "active",
+            EventKind::Registered => "registered",
+            EventKind::Welcome(WelcomeVariant::A) => "welcome_a",
+            EventKind::Welcome(WelcomeVariant::B) => "welcome_b",
+            EventKind::Reengaged => "reengaged",
+            EventKind::ReengagedParcel => "reengaged_parcel",
+            EventKind::Onboarding(OnboardingStep::Address) => "onboarding_address",
+            EventKind::Onboarding(OnboardingStep::TrackCode) => "onboarding_track_code",
+            EventKind::Onboarding(OnboardingStep::Rules) => "onboarding_rules",
+            EventKind::OnboardingSent => "onboarding_sent"
+        }
+    }
+}
+
+/// Marketplace how-to shown from the "Инструкция" menu, `slug` is used in callback data.
+#[derive(FromRow, Clone)]
+pub struct Tutorial {
+    pub slug: String,
+    pub title: String,
+    pub text: String,
+    /// Opens the marketplace app where it is installed, shown as a button under the text.
+    pub app_url: Option<String>,
+    pub web_url: Option<String>
+}
+
+/// A density band of the price list, it lasts until the `min_density` of the next one.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct Tariff {
+    pub min_density: f32,
+    pub price_per_kg: Money,
+    pub min_charge: Money
+}
+
+/// A destination city an admin opens, with its own price list, planned days in transit and pickup point.
+///
+/// `announcement` becomes a broadcast draft to the users of the city.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewCity {
+    pub name: String,
+    pub tariffs: Vec<Tariff>,
+    pub eta_days: (i32, i32),
+    pub pickup_point: String,
+    pub announcement: String
+}
+
+/// Box sides a user saved after a quote to price the same box again with one tap, in centimeters.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct BoxPreset {
+    pub id: i32,
+    pub width: f32,
+    pub length: f32,
+    pub height: f32
+}
+
+impl BoxPreset {
+    pub fn title(&self) -> String {
+        format!("Коробка {}×{}×{}", self.width, self.length, self.height)
+    }
+}
+
+/// A price the calculator gave, dimensions in centimeters, `created_at` in Bishkek time.
+///
+/// `price` is empty when no tariffs were published, `tariff_version` tells which price list it came from.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub id: i32,
+    pub telegram_id: i64,
+    pub width: f32,
+    pub length: f32,
+    pub height: f32,
+    pub weight_kg: f32,
+    pub price: Option<Money>,
+    pub tariff_version: i32,
+    pub created_at: NaiveDateTime
+}
+
+impl Quote {
+    /// Kilograms per cubic meter.
+    pub fn density(&self) -> f32 {
+        self.weight_kg / (self.width * self.length * self.height * 0.000001)
+    }
+}
+
+/// How close the quotes of one tariff version came to the bills, in percent of the quote.
+#[derive(FromRow, Clone)]
+pub struct QuoteAccuracy {
+    pub tariff_version: i32,
+    pub billed: i64,
+    pub mean_error_percent: f64,
+    pub bias_percent: f64,
+    /// Billed minus quoted weight, None while no billed parcel was weighed.
+    pub weight_bias_kg: Option<f64>,
+    pub calibrations: i64
+}
+
+/// A billed parcel further from its quote than the calibration threshold.
+#[derive(FromRow, Clone)]
+pub struct Calibration {
+    pub track_code: String,
+    pub quoted: Money,
+    pub billed: Money,
+    pub quoted_weight_kg: f32,
+    pub actual_weight_kg: Option<f32>,
+    pub density: f32
+}
+
+/// A failed update as `error_reports` keeps it, `state` and `input` without personal data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorReport {
+    pub telegram_id: Option<i64>,
+    pub correlation_id: String,
+    /// None when the chat had no dialogue yet.
+    pub state: Option<String>,
+    pub input: String,
+    pub error: String
+}
+
+/// A request to move an account to the Telegram account that registered with its phone number.
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct AccountClaim {
+    pub id: i32,
+    pub user_id: i32,
+    pub client_code: String,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    /// Where the account was before the move, None for a client registered at the counter.
+    pub old_telegram_id: Option<i64>,
+    pub telegram_id: i64,
+    pub username: Option<String>,
+    pub display_name: Option<String>
+}
+
+/// A support request waiting for an operator, `created_at` in Bishkek time.
+#[derive(FromRow, Clone)]
+pub struct Ticket {
+    pub id: i32,
+    pub client_code: String,
+    pub text: String,
+    pub created_at: NaiveDateTime
+}
+
+/// A marketplace item a client saved with a parcel.
+#[derive(FromRow, Clone)]
+pub struct ParcelItem {
+    pub client_code: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub price: Option<String>
+}
+
+/// What one pass of the retention policy purged, or would have in a dry run; `ran_at` in Bishkek time.
+#[derive(FromRow, Clone)]
+pub struct RetentionRun {
+    pub dry_run: bool,
+    pub events: i64,
+    pub tickets: i64,
+    pub parcels: i64,
+    pub ran_at: NaiveDateTime
+}
+
+/// How a saved parcel disagreed with the warehouse when they were reconciled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriftKind {
+    /// The warehouse has it but the arrival was never recorded, e.g. a webhook got lost.
+    MissedArrival,
+    /// Saved as arrived while the warehouse does not have it.
+    NotInWarehouse
+}
+
+impl DriftKind {
+    pub const ALL: [DriftKind; 2] = [DriftKind::MissedArrival, DriftKind::NotInWarehouse];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DriftKind::MissedArrival => "missed_arrival",
+            DriftKind::NotInWarehouse => "not_in_warehouse"
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            DriftKind::MissedArrival => "прибытие не было отмечено",
+            DriftKind::NotInWarehouse => "отмечена прибывшей, но склад ее не знает"
+        }
+    }
+}
+
+impl TryFrom<String> for DriftKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<DriftKind, String> {
+        DriftKind::ALL.into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("unknown drift kind {}", value))
+    }
+}
+
+impl Type<Postgres> for DriftKind {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for DriftKind {
+    fn decode(value: PgValueRef<'r>) -> Result<DriftKind, BoxDynError> {
+        Ok(DriftKind::try_from(<String as Decode<Postgres>>::decode(value)?)?)
+    }
+}
+
+/// A track code of parcels not delivered yet, several clients may have saved the same one.
+#[derive(FromRow, Clone)]
+pub struct ActiveTrackCode {
+    pub track_code: String,
+    pub all_arrived: bool,
+    pub any_arrived: bool,
+    /// In a batch that has left the warehouse, the warehouse no longer has it by design.
+    pub departed: bool
+}
+
+#[derive(FromRow, Clone, Debug, PartialEq)]
+pub struct Discrepancy {
+    pub track_code: String,
+    pub kind: DriftKind,
+    pub vendor_status: String,
+    pub repaired: bool
+}
+
+/// Intake of one Bishkek day, `scanned` of `parcels` went through an operator scan.
+#[derive(FromRow, Clone)]
+pub struct WarehouseDay {
+    pub day: NaiveDate,
+    pub parcels: i32,
+    pub scanned: i32,
+    pub weight_kg: f32
+}
+
+/// One nightly pass over the active parcels, `ran_at` in Bishkek time.
+#[derive(FromRow, Clone)]
+pub struct ReconciliationRun {
+    pub id: i32,
+    pub checked: i32,
+    pub failed: i32,
+    pub repaired: i32,
+    pub ran_at: NaiveDateTime
+}
+
+/// A template operators send as a ticket reply, `{name}` is replaced with the client's first name.
+#[derive(FromRow, Clone)]
+pub struct CannedResponse {
+    pub slug: String,
+    pub text: String
+}
+
+/// How many times an operator sent a canned response.
+#[derive(FromRow, Clone)]
+pub struct CannedUsage {
+    pub slug: String,
+    pub operator: String,
+    pub uses: i64
+}
+
+/// A note one shift leaves for the next, `created_at` in Bishkek time.
+#[derive(FromRow, Clone)]
+pub struct StaffNote {
+    pub author: String,
+    pub text: String,
+    pub created_at: NaiveDateTime
+}
+
+/// A client who got a code, `created_at` in Bishkek time.
+#[derive(FromRow, Clone)]
+pub struct Registration {
+    pub client_code: String,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub created_at: NaiveDateTime
+}
+
+/// A registration waiting for an operator because the phone prefix needs a manual check.
+#[derive(FromRow, Clone)]
+pub struct RegistrationRequest {
+    pub telegram_id: i64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub phone_number: String,
+    pub username: Option<String>,
+    pub display_name: Option<String>
+}
+
+/// How many users saw a welcome variant and how many of them registered.
+#[derive(FromRow, Clone)]
+pub struct ExperimentResult {
+    pub variant: String,
+    pub shown: i64,
+    pub registered: i64
+}
+
+/// Users registered in the same week, with their parcel count.
+#[derive(FromRow, Clone)]
+pub struct Cohort {
+    pub week: NaiveDate,
+    pub users: i64,
+    pub parcels: i64
+}
+
+/// Users of a cohort active `offset` weeks after registering.
+#[derive(FromRow, Clone)]
+pub struct CohortActivity {
+    pub week: NaiveDate,
+    pub offset: i32,
+    pub users: i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn product_status_survives_api_changes() {
+        let status: ProductStatus = serde_json::from_str(r#"{"code":"0000","msg":"已入库","weight":1.2,"data":{"shelf":"A1"}}"#).unwrap();
+        assert!(status.ready());
+
+        let status: ProductStatus = serde_json::from_str(r#"{"code":1002}"#).unwrap();
+        assert!(status.not_found());
+        assert_eq!(status.msg, "");
+
+        let status: ProductStatus = serde_json::from_str(r#"{"msg":"未入库","code":null}"#).unwrap();
+        assert!(!status.ready() && !status.not_found());
+
+        assert!(serde_json::from_str::<ProductStatus>(r#"{"code":["0000"]}"#).is_err());
+        assert!(serde_json::from_str::<ProductStatus>("<html>502 Bad Gateway</html>").is_err());
+    }
+}
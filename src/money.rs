@@ -0,0 +1,102 @@
+use std::fmt;
+
+use rust_decimal::{prelude::{FromPrimitive, ToPrimitive}, Decimal, RoundingStrategy};
+
+const CURRENCY: &str = "$";
+
+/// An amount in dollars, exact to the cent and stored as integer cents.
+///
+/// Multiplying goes through [`Decimal`] and rounds once, half away from zero, so a price per kg
+/// times a weight never drifts the way f32 prices did.
+#[derive(sqlx::Type, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(transparent)]
+pub struct Money(i64);
+
+impl Money {
+    pub fn from_cents(cents: i64) -> Money {
+        Money(cents)
+    }
+
+    pub fn cents(self) -> i64 {
+        self.0
+    }
+
+    /// The amount as a plain number with a dot, for CSV and the audit trail.
+    pub fn amount(self) -> Decimal {
+        Decimal::new(self.0, 2)
+    }
+
+    /// Reads a typed amount like `3,2`, `1 234.50` or `5`; negative and sub-cent amounts are rejected.
+    pub fn parse(text: &str) -> Option<Money> {
+        let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let amount: Decimal = text.replace(',', ".").parse().ok()?;
+
+        if amount.is_sign_negative() || amount.scale() > 2 {
+            return None;
+        }
+
+        (amount * Decimal::ONE_HUNDRED).to_i64().map(Money::from_cents)
+    }
+
+    /// The price of `quantity` units at this price per unit, e.g. kilograms at a price per kg.
+    ///
+    /// None when the quantity is not a number or the total does not fit.
+    pub fn times(self, quantity: f32) -> Option<Money> {
+        let quantity = Decimal::from_f32(quantity)?;
+        let total = self.amount().checked_mul(quantity)?.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+
+        total.checked_mul(Decimal::ONE_HUNDRED)?.to_i64().map(Money)
+    }
+}
+
+/// Russian style: a space between thousands and a comma before the cents, `1 234,50 $`.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let digits: Vec<char> = (self.0 / 100).unsigned_abs().to_string().chars().collect();
+        let cents = (self.0 % 100).unsigned_abs();
+
+        let groups: Vec<String> = digits.rchunks(3).rev().map(|group| group.iter().collect()).collect();
+
+        write!(f, "{}{},{:02} {}", sign, groups.join(" "), cents, CURRENCY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_with_grouped_thousands() {
+        assert_eq!(Money::from_cents(123450).to_string(), "1 234,50 $");
+        assert_eq!(Money::from_cents(123456789).to_string(), "1 234 567,89 $");
+        assert_eq!(Money::from_cents(500).to_string(), "5,00 $");
+        assert_eq!(Money::from_cents(7).to_string(), "0,07 $");
+        assert_eq!(Money::from_cents(-1050).to_string(), "-10,50 $");
+    }
+
+    #[test]
+    fn parses_typed_amounts() {
+        assert_eq!(Money::parse("3,2"), Some(Money::from_cents(320)));
+        assert_eq!(Money::parse("1 234.50"), Some(Money::from_cents(123450)));
+        assert_eq!(Money::parse("5"), Some(Money::from_cents(500)));
+        assert_eq!(Money::parse("-5"), None);
+        assert_eq!(Money::parse("0.125"), None);
+        assert_eq!(Money::parse("пять"), None);
+    }
+
+    #[test]
+    fn multiplies_without_drift() {
+        assert_eq!(Money::from_cents(280).times(10.3), Some(Money::from_cents(2884)));
+        assert_eq!(Money::from_cents(320).times(10.0), Some(Money::from_cents(3200)));
+        assert_eq!(Money::from_cents(5).times(0.5), Some(Money::from_cents(3)));
+    }
+
+    #[test]
+    fn huge_or_broken_quantities_have_no_price() {
+        assert_eq!(Money::from_cents(i64::MAX).times(1e30), None);
+        assert_eq!(Money::from_cents(100).times(f32::MAX), None);
+        assert_eq!(Money::from_cents(100).times(f32::NAN), None);
+        assert_eq!(Money::from_cents(100).times(f32::INFINITY), None);
+    }
+}
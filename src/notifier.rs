@@ -0,0 +1,206 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{correlation, database::Db, eta::Eta, models::{AccountClaim, CourierDelivery, Notice, PendingParcel, Subject}, scheduler::PollScheduler, support::bishkek_now, vendor::{self, product_ready}};
+
+// Deliveries of the last quarter make up the estimate, older ones follow a different schedule.
+pub const ETA_HISTORY_DAYS: i32 = 90;
+
+/// Telegram refuses longer messages, it counts UTF-16 code units.
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+pub struct Notifier {
+    db: Db,
+    interval: Duration,
+    scheduler: PollScheduler
+}
+
+impl Notifier {
+    pub fn spawn(db: Db, interval: Duration, scheduler: PollScheduler) {
+        log::info!("Starting the arrival notifier");
+        tokio::spawn(Notifier { db, interval, scheduler }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+
+        loop {
+            ticker.tick().await;
+            self.poll().await;
+        }
+    }
+
+    async fn poll(&self) {
+        let parcels = self.db.get_pending_parcels().await;
+        log::info!("Notifier: checking {} parcels", parcels.len());
+
+        let db = self.db.clone();
+        let sources = Arc::new(vendor::chain(self.db.get_providers().await));
+        let eta = Arc::new(Eta::from_history(&self.db.get_delivery_history(ETA_HISTORY_DAYS).await)
+            .with_planned(&self.db.get_planned_etas().await));
+
+        self.scheduler.run(self.interval, parcels, move |parcel: PendingParcel| {
+            let db = db.clone();
+            let eta = eta.clone();
+            let sources = sources.clone();
+
+            correlation::scope(async move {
+                match product_ready(&db, &sources, &parcel.track_code).await {
+                    Ok(true) => db.mark_parcel_arrived(parcel.id, &arrival_notice(&parcel, &eta)).await,
+                    Ok(false) => {},
+                    Err(err) => {
+                        log::error!("ERROR: Could not check parcel {}: {}", parcel.track_code, err);
+
+                        return Err(err);
+                    }
+                }
+
+                Ok(())
+            })
+        }).await;
+    }
+}
+
+/// The warehouse photo goes along when there is one, so the owner can check it is the right item.
+pub fn arrival_notice(parcel: &PendingParcel, eta: &Eta) -> Notice {
+    let mut text = match &parcel.label {
+        Some(label) => format!("📦 Посылка {} ({}) прибыла на склад", parcel.track_code, label),
+        None => format!("📦 Посылка {} прибыла на склад", parcel.track_code)
+    };
+
+    if let Some(eta) = eta.text(parcel.city.as_deref(), bishkek_now().date()) {
+        text = format!("{}\n{}", text, eta);
+    }
+
+    let digest = match &parcel.label {
+        Some(label) => format!("{} ({})", parcel.track_code, label),
+        None => parcel.track_code.clone()
+    };
+
+    Notice {
+        telegram_id: parcel.telegram_id,
+        text,
+        markup: None,
+        photo_id: parcel.photo_id.clone(),
+        digest: Some(digest),
+        subject: Some(Subject::Arrival(parcel.track_code.clone()))
+    }
+}
+
+pub fn message_length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Splits a long text into messages Telegram accepts, at line breaks. A line too long for a message is cut.
+pub fn split_message(text: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines() {
+        let line = match message_length(line) > MAX_MESSAGE_LENGTH {
+            true => {
+                let mut length = message_length("…");
+                let mut cut: String = line.chars().take_while(|c| { length += c.len_utf16(); length <= MAX_MESSAGE_LENGTH }).collect();
+                cut.push('…');
+                cut
+            },
+            false => line.to_string()
+        };
+
+        if !current.is_empty() && message_length(&current) + 1 + message_length(&line) > MAX_MESSAGE_LENGTH {
+            messages.push(std::mem::take(&mut current));
+        }
+
+        if !current.is_empty() {
+            current.push('\n');
+        }
+
+        current.push_str(&line);
+    }
+
+    if !current.is_empty() || messages.is_empty() {
+        messages.push(current);
+    }
+
+    messages
+}
+
+/// Sent when the parcel is put on a shelf of the pickup point, the shelf makes the handover quick.
+/// Names the pickup point of the user's city when the city has one.
+pub fn pickup_notice(parcel: &PendingParcel, shelf: &str, pickup_point: Option<&str>) -> Notice {
+    let mut text = match &parcel.label {
+        Some(label) => format!("✅ Посылка {} ({}) готова к выдаче, полка {}", parcel.track_code, label, shelf),
+        None => format!("✅ Посылка {} готова к выдаче, полка {}", parcel.track_code, shelf)
+    };
+
+    if let Some(pickup_point) = pickup_point {
+        text.push_str(&format!("\nПункт выдачи: {}", pickup_point));
+    }
+
+    Notice { telegram_id: parcel.telegram_id, text, markup: None, photo_id: None, digest: None, subject: None }
+}
+
+/// Sent to the Telegram account an account was moved away from, in case it was not the client's doing.
+pub fn account_moved_notice(telegram_id: i64, claim: &AccountClaim) -> Notice {
+    Notice {
+        telegram_id,
+        text: format!(
+            "Ваш аккаунт {} перенесен на другой аккаунт Telegram по номеру телефона. Если это были не Вы, срочно напишите в поддержку.",
+            claim.client_code
+        ),
+        markup: None,
+        photo_id: None,
+        digest: None,
+        subject: None
+    }
+}
+
+pub fn courier_notice(delivery: &CourierDelivery) -> Notice {
+    Notice {
+        telegram_id: delivery.telegram_id,
+        text: format!("🚚 Посылка {} передана в доставку курьером по адресу: {}", delivery.track_code, delivery.address),
+        markup: None,
+        photo_id: None,
+        digest: None,
+        subject: None
+    }
+}
+
+pub fn delivered_notice(delivery: &CourierDelivery, photo_id: Option<&str>) -> Notice {
+    Notice {
+        telegram_id: delivery.telegram_id,
+        text: format!("✅ Посылка {} доставлена по адресу: {}. Спасибо, что выбрали нас!", delivery.track_code, delivery.address),
+        markup: None,
+        photo_id: photo_id.map(str::to_string),
+        digest: None,
+        subject: Some(Subject::Receipt(delivery.track_code.clone()))
+    }
+}
+
+pub fn photo_notice(parcel: &PendingParcel) -> Notice {
+    Notice {
+        telegram_id: parcel.telegram_id,
+        text: format!("📷 Фото посылки {} на складе. Проверьте, что это Ваш товар", parcel.track_code),
+        markup: None,
+        photo_id: parcel.photo_id.clone(),
+        digest: None,
+        subject: None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_texts_are_split_at_line_breaks() {
+        assert_eq!(split_message("короткий\nтекст"), vec!["короткий\nтекст".to_string()]);
+
+        let line = "ы".repeat(3000);
+        let messages = split_message(&format!("{}\n{}\n{}", line, line, "ы".repeat(5000)));
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0], line);
+        assert!(messages[2].ends_with('…'));
+        assert!(messages.iter().all(|message| message_length(message) <= MAX_MESSAGE_LENGTH));
+    }
+}
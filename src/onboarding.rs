@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use crate::{config::{env_opt, env_or}, database::Db, models::{EventKind, Notice, OnboardingUser}};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What a new client should do first, each step is done once its event is recorded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnboardingStep {
+    Address,
+    TrackCode,
+    Rules
+}
+
+impl OnboardingStep {
+    pub const ALL: [OnboardingStep; 3] = [OnboardingStep::Address, OnboardingStep::TrackCode, OnboardingStep::Rules];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            OnboardingStep::Address => "Сохраните адрес склада",
+            OnboardingStep::TrackCode => "Добавьте первый трек-код",
+            OnboardingStep::Rules => "Прочитайте правила"
+        }
+    }
+
+    /// Where in the menu the step is done.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            OnboardingStep::Address => "кнопка «Адрес» в меню",
+            OnboardingStep::TrackCode => "кнопка «Отслеживание товара»",
+            OnboardingStep::Rules => "кнопка «Инструкция»"
+        }
+    }
+}
+
+/// When new clients get the checklist, in days after registration.
+///
+/// ONBOARDING_DAYS=1,3,6 sends it three times; a client who did every step gets no more.
+#[derive(Clone)]
+pub struct OnboardingPolicy {
+    pub enabled: bool,
+    pub days: Vec<u32>
+}
+
+impl OnboardingPolicy {
+    pub fn from_env() -> OnboardingPolicy {
+        let days = env_opt("ONBOARDING_DAYS").map_or(vec![1, 3, 6], |days| {
+            let mut days: Vec<u32> = days.split(',')
+                .map(|day| day.trim().parse().expect("ERROR: Could not parse ONBOARDING_DAYS"))
+                .collect();
+
+            days.sort();
+            days.dedup();
+            days
+        });
+
+        OnboardingPolicy { enabled: env_or("ONBOARDING_ENABLED", false), days }
+    }
+
+    /// How far back registrations are looked at, the day after the last send.
+    pub fn window_days(&self) -> i32 {
+        self.days.last().map_or(0, |day| *day as i32 + 1)
+    }
+
+    pub fn remaining(&self, user: &OnboardingUser) -> Vec<OnboardingStep> {
+        OnboardingStep::ALL.into_iter()
+            .filter(|step| !user.completed.iter().any(|kind| kind == EventKind::Onboarding(*step).as_str()))
+            .collect()
+    }
+
+    /// Once per send day, a send the bot missed while down is not made up twice.
+    pub fn is_due(&self, user: &OnboardingUser) -> bool {
+        let send_day = match self.days.iter().rev().find(|day| **day as f64 <= user.age_days) {
+            Some(day) => *day as f64,
+            None => return false
+        };
+
+        !matches!(user.last_sent_days, Some(sent) if sent >= send_day) && !self.remaining(user).is_empty()
+    }
+
+    pub fn notice(&self, user: &OnboardingUser) -> Notice {
+        let steps: Vec<String> = self.remaining(user).iter()
+            .map(|step| format!("☐ {} — {}", step.title(), step.hint()))
+            .collect();
+
+        Notice {
+            telegram_id: user.telegram_id,
+            text: format!("{}, несколько шагов, чтобы начать пользоваться MAX EXPRESS:\n\n{}", user.first_name, steps.join("\n")),
+            markup: None,
+            photo_id: None,
+            digest: None,
+            subject: None
+        }
+    }
+}
+
+/// Sends new clients the steps they have not done yet on the days of the policy.
+pub struct Onboarding {
+    db: Db,
+    policy: OnboardingPolicy
+}
+
+impl Onboarding {
+    pub fn spawn(db: Db, policy: OnboardingPolicy) {
+        if !policy.enabled || policy.days.is_empty() {
+            return;
+        }
+
+        log::info!("Starting the onboarding job");
+        tokio::spawn(Onboarding { db, policy }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let users: Vec<OnboardingUser> = self.db.get_onboarding_users(self.policy.window_days()).await.into_iter()
+                .filter(|user| self.policy.is_due(user))
+                .collect();
+
+            for user in &users {
+                self.db.send_onboarding(&self.policy.notice(user)).await;
+            }
+
+            log::info!("Onboarding: checklist sent to {} users", users.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(age_days: f64, last_sent_days: Option<f64>, completed: &[OnboardingStep]) -> OnboardingUser {
+        OnboardingUser {
+            telegram_id: 7,
+            first_name: "Айбек".to_string(),
+            age_days,
+            last_sent_days,
+            completed: completed.iter().map(|step| EventKind::Onboarding(*step).as_str().to_string()).collect()
+        }
+    }
+
+    #[test]
+    fn checklist_goes_once_per_day_until_everything_is_done() {
+        let policy = OnboardingPolicy { enabled: true, days: vec![1, 3, 6] };
+
+        assert!(!policy.is_due(&user(0.5, None, &[])));
+        assert!(policy.is_due(&user(1.2, None, &[])));
+        assert!(!policy.is_due(&user(1.5, Some(1.2), &[])));
+        assert!(policy.is_due(&user(3.1, Some(1.2), &[])));
+
+        // Down on day 1, sent on day 4 once and not again for day 3.
+        assert!(policy.is_due(&user(4.0, None, &[])));
+        assert!(!policy.is_due(&user(4.5, Some(4.0), &[])));
+
+        assert!(!policy.is_due(&user(3.1, Some(1.2), &OnboardingStep::ALL)));
+
+        let notice = policy.notice(&user(1.2, None, &[OnboardingStep::Address]));
+        assert!(!notice.text.contains("Сохраните адрес склада"));
+        assert!(notice.text.contains("Добавьте первый трек-код"));
+        assert!(notice.text.contains("Прочитайте правила"));
+    }
+}
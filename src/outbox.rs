@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use teloxide::types::ChatId;
+
+use crate::{database::Db, models::OutboxMessage, notifier::{message_length, MAX_MESSAGE_LENGTH}, sender::{Priority, SendQueue}};
+
+const RELAY_INTERVAL: Duration = Duration::from_secs(2);
+
+const BATCH_SIZE: i64 = 100;
+
+// A user who blocked the bot would otherwise be retried forever.
+const MAX_ATTEMPTS: i32 = 5;
+
+// A warehouse import marks parcels arrived one request at a time, a user's arrivals are
+// held until it has been quiet for this long and then sent as one message.
+const DIGEST_WINDOW: Duration = Duration::from_secs(2 * 60);
+
+/// Delivers messages committed to the outbox and marks them sent once Telegram accepts them.
+pub struct Relay {
+    db: Db,
+    queue: SendQueue
+}
+
+impl Relay {
+    pub fn spawn(db: Db, queue: SendQueue) {
+        log::info!("Starting the outbox relay");
+        tokio::spawn(Relay { db, queue }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RELAY_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+            self.relay().await;
+        }
+    }
+
+    async fn relay(&self) {
+        for (ids, message) in combine(self.db.get_outbox(BATCH_SIZE, MAX_ATTEMPTS, DIGEST_WINDOW).await) {
+            let markup = message.markup.as_deref().and_then(|markup| serde_json::from_str(markup).ok());
+
+            let sent = self.queue.deliver(Priority::Bulk, ChatId(message.telegram_id), message.text, markup, message.photo_id).await;
+
+            for id in ids {
+                match sent {
+                    Some(message_id) => self.db.mark_outbox_sent(id, message_id.0).await,
+                    None => self.db.mark_outbox_failed(id).await
+                }
+            }
+        }
+    }
+}
+
+/// Merges the digest messages of each user into one listing their lines, with the ids it stands for.
+///
+/// The merged message takes the place of the user's first one, a single digest message goes as it is.
+/// A digest that would not fit in a Telegram message goes on in another one.
+fn combine(messages: Vec<OutboxMessage>) -> Vec<(Vec<i64>, OutboxMessage)> {
+    let mut combined: Vec<(Vec<i64>, OutboxMessage)> = Vec::new();
+
+    for message in messages {
+        let merged = match &message.digest {
+            Some(digest) => combined.iter_mut().find(|(ids, first)| {
+                first.telegram_id == message.telegram_id && matches!(&first.digest, Some(lines)
+                    if message_length(&digest_text(ids.len() + 1, &format!("{}\n{}", lines, digest))) <= MAX_MESSAGE_LENGTH)
+            }),
+            None => None
+        };
+
+        match merged {
+            Some((ids, first)) => {
+                ids.push(message.id);
+                first.digest = Some(format!("{}\n{}", first.digest.take().unwrap_or_default(), message.digest.unwrap_or_default()));
+            },
+            None => combined.push((vec![message.id], message))
+        }
+    }
+
+    for (ids, message) in combined.iter_mut().filter(|(ids, _)| ids.len() > 1) {
+        message.text = digest_text(ids.len(), message.digest.as_deref().unwrap_or_default());
+        message.markup = None;
+        message.photo_id = None;
+    }
+
+    combined
+}
+
+fn digest_text(count: usize, digest: &str) -> String {
+    let lines: Vec<String> = digest.lines().map(|line| format!("▫️ {}", line)).collect();
+
+    format!("📦 На склад прибыли посылки ({}):\n{}", count, lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i64, telegram_id: i64, digest: Option<&str>) -> OutboxMessage {
+        OutboxMessage {
+            id,
+            telegram_id,
+            text: format!("Сообщение {}", id),
+            markup: None,
+            photo_id: Some("photo".to_string()),
+            digest: digest.map(str::to_string)
+        }
+    }
+
+    #[test]
+    fn arrivals_of_a_user_go_as_one_message() {
+        let combined = combine(vec![
+            message(1, 10, Some("YT1 (кроссовки)")),
+            message(2, 10, None),
+            message(3, 20, Some("YT2")),
+            message(4, 10, Some("YT3"))
+        ]);
+
+        assert_eq!(combined.len(), 3);
+        assert_eq!(combined[0].0, vec![1, 4]);
+        assert_eq!(combined[0].1.text, "📦 На склад прибыли посылки (2):\n▫️ YT1 (кроссовки)\n▫️ YT3");
+        assert_eq!(combined[0].1.photo_id, None);
+        assert_eq!(combined[1].1, message(2, 10, None));
+        assert_eq!(combined[2].1, message(3, 20, Some("YT2")));
+    }
+
+    #[test]
+    fn a_long_digest_is_split_into_messages_telegram_accepts() {
+        let line = "ы".repeat(100);
+        let combined = combine((1..=100).map(|id| message(id, 10, Some(&line))).collect());
+
+        assert!(combined.len() > 1);
+        assert_eq!(combined.iter().map(|(ids, _)| ids.len()).sum::<usize>(), 100);
+        assert!(combined.iter().all(|(_, message)| message_length(&message.text) <= MAX_MESSAGE_LENGTH));
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{config::{env_or, AppEnv}, money::Money};
+
+/// Prices are kept in dollars, so invoices are issued in dollar cents.
+pub const CURRENCY: &str = "USD";
+
+/// How shipments are paid for.
+///
+/// Without a provider token clients pay in cash or by transfer and an admin records it with `/paid`.
+/// With `require_before_delivery` a batch is handed over only for the parcels paid in full.
+#[derive(Clone)]
+pub struct PaymentPolicy {
+    pub provider_token: Option<String>,
+    pub require_before_delivery: bool
+}
+
+impl PaymentPolicy {
+    pub fn from_env() -> PaymentPolicy {
+        PaymentPolicy {
+            provider_token: AppEnv::current().payment_provider_token(),
+            require_before_delivery: env_or("DELIVERY_REQUIRES_PAYMENT", false)
+        }
+    }
+}
+
+/// The invoice payload Telegram hands back with the pre-checkout query and the payment.
+pub fn payload(parcel_id: i32) -> String {
+    format!("parcel:{}", parcel_id)
+}
+
+pub fn parcel_id(payload: &str) -> Option<i32> {
+    payload.strip_prefix("parcel:")?.parse().ok()
+}
+
+/// What is left to pay in the smallest units Telegram takes, None when it is more than an invoice can carry.
+pub fn telegram_amount(due: Money) -> Option<i32> {
+    i32::try_from(due.cents()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_carries_the_parcel() {
+        assert_eq!(parcel_id(&payload(42)), Some(42));
+        assert_eq!(parcel_id("parcel:"), None);
+        assert_eq!(parcel_id("quote:42"), None);
+    }
+
+    #[test]
+    fn amounts_past_an_i32_cannot_be_invoiced() {
+        assert_eq!(telegram_amount(Money::from_cents(1500)), Some(1500));
+        assert_eq!(telegram_amount(Money::from_cents(i64::from(i32::MAX) + 1)), None);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::config::env_opt;
+
+/// What registration does with a phone number.
+#[derive(Debug, PartialEq)]
+pub enum PhoneDecision {
+    Allowed,
+    Review,
+    Denied
+}
+
+/// Country codes that register right away, that are refused, and everything else going to manual approval.
+#[derive(Clone)]
+pub struct PhonePolicy {
+    allowed: Vec<String>,
+    denied: Vec<String>
+}
+
+impl PhonePolicy {
+    pub fn from_env() -> PhonePolicy {
+        PhonePolicy {
+            allowed: prefixes(&env_opt("PHONE_ALLOWED_PREFIXES").unwrap_or("996,7".to_string())),
+            denied: prefixes(&env_opt("PHONE_DENIED_PREFIXES").unwrap_or_default())
+        }
+    }
+
+    /// The longest matching prefix decides, so `7` can be allowed while `79` is denied.
+    pub fn check(&self, phone_number: &str) -> PhoneDecision {
+        let longest = |prefixes: &[String]| prefixes.iter()
+            .filter(|prefix| phone_number.starts_with(prefix.as_str()))
+            .map(String::len)
+            .max();
+
+        match (longest(&self.allowed), longest(&self.denied)) {
+            (Some(allowed), Some(denied)) if allowed > denied => PhoneDecision::Allowed,
+            (_, Some(_)) => PhoneDecision::Denied,
+            (Some(_), None) => PhoneDecision::Allowed,
+            (None, None) => PhoneDecision::Review
+        }
+    }
+
+    pub fn allowed_text(&self) -> String {
+        self.allowed.iter().map(|prefix| format!("+{}", prefix)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Digits of an international number, without `+` or `00`; None if it is too short or too long.
+///
+/// Numbers dialed inside the country are rewritten to the international form before the policy sees them:
+/// Kyrgyz `0555 12 34 56` becomes `996555123456`, Russian and Kazakh `8 900 123 45 67` becomes `79001234567`.
+pub fn normalize(input: &str) -> Option<String> {
+    if input.chars().any(|c| !(c.is_ascii_digit() || " +-()".contains(c))) {
+        return None;
+    }
+
+    let digits: String = input.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = match digits.strip_prefix("00") {
+        Some(international) => international.to_string(),
+        None if digits.len() == 10 && digits.starts_with('0') => format!("996{}", &digits[1..]),
+        None if digits.len() == 11 && digits.starts_with("89") => format!("7{}", &digits[1..]),
+        None => digits
+    };
+
+    (10..=15).contains(&digits.len()).then_some(digits)
+}
+
+fn prefixes(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|prefix| prefix.trim().trim_start_matches('+').to_string())
+        .filter(|prefix| !prefix.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allowed: &str, denied: &str) -> PhonePolicy {
+        PhonePolicy { allowed: prefixes(allowed), denied: prefixes(denied) }
+    }
+
+    #[test]
+    fn typed_numbers_are_normalized() {
+        assert_eq!(normalize("+996 (555) 12-34-56"), Some("996555123456".to_string()));
+        assert_eq!(normalize("00996555123456"), Some("996555123456".to_string()));
+        assert_eq!(normalize("0555 12-34-56"), Some("996555123456".to_string()));
+        assert_eq!(normalize("8 (900) 123-45-67"), Some("79001234567".to_string()));
+        assert_eq!(normalize("555 12 34"), None);
+        assert_eq!(normalize("996555123456 звоните вечером"), None);
+    }
+
+    #[test]
+    fn unknown_countries_go_to_review() {
+        let policy = policy("996, +7", "");
+
+        assert_eq!(policy.check("996555123456"), PhoneDecision::Allowed);
+        assert_eq!(policy.check("79001234567"), PhoneDecision::Allowed);
+        assert_eq!(policy.check("8613812345678"), PhoneDecision::Review);
+        assert_eq!(policy.allowed_text(), "+996, +7");
+    }
+
+    #[test]
+    fn longest_prefix_decides() {
+        let policy = policy("7", "79,380");
+
+        assert_eq!(policy.check("77011234567"), PhoneDecision::Allowed);
+        assert_eq!(policy.check("79001234567"), PhoneDecision::Denied);
+        assert_eq!(policy.check("380501234567"), PhoneDecision::Denied);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{future, pin::Pin, time::Duration};
+
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::Value;
+use teloxide::{requests::{Payload, Request}, stop::StopToken, types::{AllowedUpdate, ChatId, MessageId, True, Update, UpdateKind}, update_listeners::{AsUpdateStream, UpdateListener}};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{config::Webhook, retry::Bot};
+
+// Telegram sends reactions only when asked for them, and teloxide has no name for that kind of update yet.
+const ALLOWED_UPDATES: &[&str] = &[
+    "message",
+    "edited_message",
+    "callback_query",
+    "inline_query",
+    "pre_checkout_query",
+    "my_chat_member",
+    "message_reaction"
+];
+
+/// A client changed their reaction to a message in the chat with the bot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reaction {
+    pub chat_id: ChatId,
+    pub message_id: MessageId,
+    pub telegram_id: i64,
+    /// Emoji on the message after the change, custom emoji are left out.
+    pub emoji: Vec<String>
+}
+
+impl Reaction {
+    /// Reads a `message_reaction` update, which teloxide passes on as raw JSON.
+    pub fn parse(update: &Value) -> Option<Reaction> {
+        let reaction = update.get("message_reaction")?;
+
+        Some(Reaction {
+            chat_id: ChatId(reaction["chat"]["id"].as_i64()?),
+            message_id: MessageId(reaction["message_id"].as_i64()?.try_into().ok()?),
+            // Anonymous group admins react as the chat, there is no client to act for.
+            telegram_id: reaction["user"]["id"].as_i64()?,
+            emoji: reaction["new_reaction"].as_array()?.iter()
+                .filter_map(|reaction| reaction["emoji"].as_str())
+                .map(str::to_string)
+                .collect()
+        })
+    }
+}
+
+/// Takes reactions out of the updates of `listener`, they arrive at the receiver instead.
+///
+/// The dispatcher drops every update teloxide cannot parse, so a handler would never see them.
+pub fn split<L>(listener: L) -> (ReactionListener<L>, UnboundedReceiver<Reaction>) {
+    let (reactions, receiver) = mpsc::unbounded_channel();
+
+    (ReactionListener { inner: listener, reactions }, receiver)
+}
+
+pub struct ReactionListener<L> {
+    inner: L,
+    reactions: UnboundedSender<Reaction>
+}
+
+impl<L> UpdateListener for ReactionListener<L>
+where
+    L: UpdateListener,
+    Self: for<'a> AsUpdateStream<'a, StreamErr = L::Err>
+{
+    type Err = L::Err;
+
+    fn stop_token(&mut self) -> StopToken {
+        self.inner.stop_token()
+    }
+
+    // The hint has no reactions in it, Telegram keeps the list it got from `allow_on_polling`.
+    fn hint_allowed_updates(&mut self, _hint: &mut dyn Iterator<Item = AllowedUpdate>) {}
+
+    fn timeout_hint(&self) -> Option<Duration> {
+        self.inner.timeout_hint()
+    }
+}
+
+impl<'a, L> AsUpdateStream<'a> for ReactionListener<L>
+where
+    L: AsUpdateStream<'a>,
+    L::Stream: Send + 'a,
+    L::StreamErr: Send + 'a
+{
+    type StreamErr = L::StreamErr;
+    type Stream = Pin<Box<dyn Stream<Item = Result<Update, L::StreamErr>> + Send + 'a>>;
+
+    fn as_stream(&'a mut self) -> Self::Stream {
+        let reactions = self.reactions.clone();
+
+        self.inner.as_stream()
+            .filter(move |update| {
+                let reaction = match update {
+                    Ok(Update { kind: UpdateKind::Error(value), .. }) => Reaction::parse(value),
+                    _ => None
+                };
+
+                let keep = match reaction {
+                    Some(reaction) => {
+                        let _ = reactions.send(reaction);
+
+                        false
+                    },
+                    None => true
+                };
+
+                future::ready(keep)
+            })
+            .boxed()
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct GetUpdates {
+    allowed_updates: &'static [&'static str],
+    limit: u8,
+    timeout: u32
+}
+
+impl Payload for GetUpdates {
+    type Output = Vec<Update>;
+
+    const NAME: &'static str = "GetUpdates";
+}
+
+#[derive(Clone, Serialize)]
+struct SetWebhook {
+    url: String,
+    secret_token: String,
+    allowed_updates: &'static [&'static str]
+}
+
+impl Payload for SetWebhook {
+    type Output = True;
+
+    const NAME: &'static str = "SetWebhook";
+}
+
+/// Asks for reactions along with the other updates, long polling then gets them without naming the kinds again.
+///
+/// Call it once the webhook is deleted, getUpdates fails while one is set.
+pub async fn allow_on_polling(bot: &Bot) {
+    // Without an offset nothing is confirmed, the update comes again with the first poll.
+    bot.json(GetUpdates { allowed_updates: ALLOWED_UPDATES, limit: 1, timeout: 0 })
+        .send()
+        .await.expect("ERROR: Could not ask for message reactions");
+}
+
+/// The same for a webhook, it is set again with the full list.
+pub async fn allow_on_webhook(bot: &Bot, webhook: &Webhook) {
+    let payload = SetWebhook {
+        url: webhook.url.to_string(),
+        secret_token: webhook.secret.clone(),
+        allowed_updates: ALLOWED_UPDATES
+    };
+
+    bot.json(payload)
+        .send()
+        .await.expect("ERROR: Could not ask for message reactions");
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reactions_are_read_from_raw_updates() {
+        let update = json!({
+            "update_id": 10,
+            "message_reaction": {
+                "chat": { "id": 42, "type": "private" },
+                "message_id": 7,
+                "user": { "id": 42, "is_bot": false, "first_name": "Айбек" },
+                "date": 1717200000,
+                "old_reaction": [],
+                "new_reaction": [{ "type": "emoji", "emoji": "👍" }, { "type": "custom_emoji", "custom_emoji_id": "1" }]
+            }
+        });
+
+        assert_eq!(Reaction::parse(&update), Some(Reaction {
+            chat_id: ChatId(42),
+            message_id: MessageId(7),
+            telegram_id: 42,
+            emoji: vec!["👍".to_string()]
+        }));
+
+        assert_eq!(Reaction::parse(&json!({ "update_id": 11, "message_reaction_count": {} })), None);
+    }
+}
@@ -0,0 +1,213 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use chrono::Timelike;
+use teloxide::{requests::Requester};
+
+use crate::{config::Config, correlation, database::Db, eta::Eta, models::{ActiveTrackCode, Discrepancy, DriftKind, ProductStatus, ReconciliationRun}, notifier::{self, ETA_HISTORY_DAYS}, retry::Bot, scheduler::PollScheduler, staff::Staff, support::bishkek_now, vendor};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// At night in Bishkek, repaired arrivals then reach clients before the pickup points open.
+const RUN_HOUR: u32 = 3;
+
+// Lookups are spread over the window, they share the vendor limits with the arrival notifier.
+const WINDOW: Duration = Duration::from_secs(30 * 60);
+
+// Longer reports are cut, the rest is in /reconciliation.
+const MAX_REPORT_LINES: usize = 50;
+
+/// Compares every parcel that is not delivered yet with a fresh warehouse lookup once a night.
+///
+/// Missed arrivals are marked like the notifier would, with the notice and a `ParcelArrived` event;
+/// parcels the warehouse no longer knows are only reported, an operator has to look at them.
+pub struct Reconciliation {
+    bot: Bot,
+    db: Db,
+    config: Config,
+    staff: Staff,
+    scheduler: PollScheduler
+}
+
+impl Reconciliation {
+    pub fn spawn(bot: Bot, db: Db, config: Config, staff: Staff, scheduler: PollScheduler) {
+        log::info!("Starting the reconciliation job");
+        tokio::spawn(Reconciliation { bot, db, config, staff, scheduler }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let now = bishkek_now();
+
+            if now.hour() != RUN_HOUR {
+                continue;
+            }
+
+            // A restart within the hour must not run it twice.
+            if self.db.get_last_reconciliation().await.is_some_and(|run| run.ran_at.date() == now.date()) {
+                continue;
+            }
+
+            self.reconcile().await;
+        }
+    }
+
+    async fn reconcile(&self) {
+        let track_codes = self.db.get_active_track_codes().await;
+        let checked = track_codes.len() as i32;
+        log::info!("Reconciliation: checking {} track codes", checked);
+
+        let db = self.db.clone();
+        let sources = Arc::new(vendor::chain(self.db.get_providers().await));
+        let eta = Arc::new(Eta::from_history(&self.db.get_delivery_history(ETA_HISTORY_DAYS).await)
+            .with_planned(&self.db.get_planned_etas().await));
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let failed = Arc::new(Mutex::new(0));
+
+        let (found_by_polls, failed_by_polls) = (found.clone(), failed.clone());
+
+        self.scheduler.run(WINDOW, track_codes, move |active: ActiveTrackCode| {
+            let db = db.clone();
+            let eta = eta.clone();
+            let sources = sources.clone();
+            let found = found_by_polls.clone();
+            let failed = failed_by_polls.clone();
+
+            correlation::scope(async move {
+                let lookup = match vendor::lookup(&db, &sources, &active.track_code).await {
+                    Ok(lookup) => lookup,
+                    Err(err) => {
+                        log::error!("ERROR: Could not reconcile parcel {}: {}", active.track_code, err);
+                        *failed.lock().unwrap() += 1;
+
+                        return Err(err);
+                    }
+                };
+
+                if let Some(kind) = drift(&active, &lookup.status) {
+                    let repaired = match kind {
+                        DriftKind::MissedArrival => db.mark_track_code_arrived(&active.track_code, |parcel| notifier::arrival_notice(parcel, &eta)).await > 0,
+                        DriftKind::NotInWarehouse => false
+                    };
+
+                    log::warn!("Reconciliation: {} {}", active.track_code, kind.as_str());
+
+                    found.lock().unwrap().push(Discrepancy {
+                        track_code: active.track_code,
+                        kind,
+                        vendor_status: lookup.status.msg,
+                        repaired
+                    });
+                }
+
+                Ok(())
+            })
+        }).await;
+
+        let discrepancies = std::mem::take(&mut *found.lock().unwrap());
+        let failed = *failed.lock().unwrap();
+
+        self.db.save_reconciliation(checked, failed, &discrepancies).await;
+
+        log::info!("Reconciliation: {} checked, {} failed, {} discrepancies", checked, failed, discrepancies.len());
+
+        if discrepancies.is_empty() {
+            return;
+        }
+
+        if let Some(run) = self.db.get_last_reconciliation().await {
+            let report = render_report(&run, &discrepancies);
+
+            for chat_id in self.config.alert_chats(&self.staff.ids()) {
+                if let Err(err) = self.bot.send_message(chat_id, &report).await {
+                    log::error!("ERROR: Could not send the reconciliation report to {}: {}", chat_id, err);
+                }
+            }
+        }
+    }
+}
+
+/// How the saved parcels of a track code disagree with the warehouse answer, if they do.
+pub fn drift(active: &ActiveTrackCode, status: &ProductStatus) -> Option<DriftKind> {
+    match status.ready() {
+        true if !active.all_arrived => Some(DriftKind::MissedArrival),
+        false if active.any_arrived && !active.departed => Some(DriftKind::NotInWarehouse),
+        _ => None
+    }
+}
+
+/// The run with what it found, for the admins and /reconciliation.
+pub fn render_report(run: &ReconciliationRun, discrepancies: &[Discrepancy]) -> String {
+    let mut lines = vec![
+        format!("🔎 Сверка со складом {}", run.ran_at.format("%d.%m.%Y %H:%M")),
+        format!("Проверено трек-кодов: {}, не удалось проверить: {}", run.checked, run.failed),
+        format!("Расхождений: {}, исправлено: {}", discrepancies.len(), run.repaired)
+    ];
+
+    if !discrepancies.is_empty() {
+        lines.push(String::new());
+    }
+
+    for discrepancy in discrepancies.iter().take(MAX_REPORT_LINES) {
+        lines.push(format!(
+            "{} {}: {} (склад: {})",
+            if discrepancy.repaired { "✅" } else { "⚠️" },
+            discrepancy.track_code,
+            discrepancy.kind.title(),
+            if discrepancy.vendor_status.is_empty() { "—" } else { &discrepancy.vendor_status }
+        ));
+    }
+
+    if discrepancies.len() > MAX_REPORT_LINES {
+        lines.push(format!("…и еще {}", discrepancies.len() - MAX_REPORT_LINES));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn status(code: &str) -> ProductStatus {
+        ProductStatus { code: code.to_string(), msg: "已入库".to_string() }
+    }
+
+    fn active(all_arrived: bool, any_arrived: bool) -> ActiveTrackCode {
+        ActiveTrackCode { track_code: "YT123".to_string(), all_arrived, any_arrived, departed: false }
+    }
+
+    #[test]
+    fn saved_status_is_compared_with_the_warehouse() {
+        assert_eq!(drift(&active(false, false), &status("0000")), Some(DriftKind::MissedArrival));
+        // One of two clients with the same track code was not notified.
+        assert_eq!(drift(&active(false, true), &status("0000")), Some(DriftKind::MissedArrival));
+        assert_eq!(drift(&active(true, true), &status("0000")), None);
+
+        assert_eq!(drift(&active(true, true), &status("1002")), Some(DriftKind::NotInWarehouse));
+        assert_eq!(drift(&active(false, false), &status("1002")), None);
+        assert_eq!(drift(&ActiveTrackCode { departed: true, ..active(true, true) }, &status("1002")), None);
+
+        let run = ReconciliationRun {
+            id: 1,
+            checked: 40,
+            failed: 2,
+            repaired: 1,
+            ran_at: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap().and_hms_opt(3, 10, 0).unwrap()
+        };
+
+        let report = render_report(&run, &[
+            Discrepancy { track_code: "YT123".to_string(), kind: DriftKind::MissedArrival, vendor_status: "已入库".to_string(), repaired: true },
+            Discrepancy { track_code: "YT456".to_string(), kind: DriftKind::NotInWarehouse, vendor_status: String::new(), repaired: false }
+        ]);
+
+        assert!(report.contains("Проверено трек-кодов: 40, не удалось проверить: 2"));
+        assert!(report.contains("✅ YT123: прибытие не было отмечено (склад: 已入库)"));
+        assert!(report.contains("⚠️ YT456: отмечена прибывшей, но склад ее не знает (склад: —)"));
+    }
+}
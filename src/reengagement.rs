@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{config::env_or, database::Db, models::{IdleUser, Notice}};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Spread over days, so a first run on an old user base does not flood the outbox.
+const MAX_PER_RUN: i64 = 200;
+
+/// Who is reminded about the bot and how often.
+///
+/// A user is idle when nothing came from them for `idle_days` and they never saved a parcel.
+/// The template may use `{name}` for the first name.
+#[derive(Clone)]
+pub struct ReengagementPolicy {
+    pub enabled: bool,
+    pub idle_days: i32,
+    pub cooldown_days: i32,
+    pub template: String
+}
+
+impl ReengagementPolicy {
+    pub fn from_env() -> ReengagementPolicy {
+        ReengagementPolicy {
+            enabled: env_or("REENGAGE_ENABLED", false),
+            idle_days: env_or("REENGAGE_IDLE_DAYS", 30),
+            cooldown_days: env_or("REENGAGE_COOLDOWN_DAYS", 90),
+            template: env_or(
+                "REENGAGE_TEMPLATE",
+                "{name}, здравствуйте! Ваш адрес склада в Китае всё ещё ждёт первую посылку. \
+                Откройте /start, чтобы посмотреть адрес и тарифы.".to_string()
+            )
+        }
+    }
+
+    pub fn notice(&self, user: &IdleUser) -> Notice {
+        let markup = InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback("Больше не напоминать", "reminders_off")
+        ]]);
+
+        Notice {
+            telegram_id: user.telegram_id,
+            text: self.template.replace("{name}", &user.first_name),
+            markup: Some(markup),
+            photo_id: None,
+            digest: None,
+            subject: None
+        }
+    }
+}
+
+/// Reminds idle users about the bot once a day, at most once per `cooldown_days` each.
+pub struct Reengagement {
+    db: Db,
+    policy: ReengagementPolicy
+}
+
+impl Reengagement {
+    pub fn spawn(db: Db, policy: ReengagementPolicy) {
+        if !policy.enabled {
+            return;
+        }
+
+        log::info!("Starting the re-engagement job");
+        tokio::spawn(Reengagement { db, policy }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let users = self.db.get_idle_users(self.policy.idle_days, self.policy.cooldown_days, MAX_PER_RUN).await;
+
+            for user in &users {
+                self.db.reengage(&self.policy.notice(user)).await;
+            }
+
+            log::info!("Re-engagement: {} idle users reminded", users.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_gets_the_first_name() {
+        let policy = ReengagementPolicy {
+            enabled: true,
+            idle_days: 30,
+            cooldown_days: 90,
+            template: "{name}, мы скучаем".to_string()
+        };
+
+        let notice = policy.notice(&IdleUser { telegram_id: 7, first_name: "Айбек".to_string() });
+
+        assert_eq!(notice.telegram_id, 7);
+        assert_eq!(notice.text, "Айбек, мы скучаем");
+        assert!(notice.markup.is_some());
+    }
+}
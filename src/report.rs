@@ -0,0 +1,201 @@
+use crate::models::{Cohort, CohortActivity, ExperimentResult, OverrideTotals, StaffNote, Ticket};
+
+// Longer lists are cut, the shift sees the rest with /tickets or in the manifest.
+const HANDOVER_LIMIT: usize = 20;
+
+/// Renders the weekly retention table shown by /report.
+pub fn render_cohorts(cohorts: &[Cohort], activity: &[CohortActivity]) -> String {
+    if cohorts.is_empty() {
+        return "Нет регистраций за выбранный период".to_string();
+    }
+
+    let columns = cohorts.len();
+
+    let mut lines = vec![format!(
+        "{:<10} {:>5} {:>5} {}",
+        "Неделя",
+        "Польз",
+        "Пос/п",
+        (0..columns).map(|offset| format!("{:>4}", format!("W{}", offset))).collect::<Vec<_>>().join(" ")
+    )];
+
+    for (i, cohort) in cohorts.iter().enumerate() {
+        // Later cohorts have fewer weeks behind them.
+        let cells: Vec<String> = (0..columns - i)
+            .map(|offset| {
+                let active = activity.iter()
+                    .find(|row| row.week == cohort.week && row.offset == offset as i32)
+                    .map_or(0, |row| row.users);
+
+                format!("{:>3}%", active * 100 / cohort.users.max(1))
+            })
+            .collect();
+
+        lines.push(format!(
+            "{:<10} {:>5} {:>5.1} {}",
+            cohort.week.format("%Y-%m-%d"),
+            cohort.users,
+            cohort.parcels as f32 / cohort.users.max(1) as f32,
+            cells.join(" ")
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders the welcome experiment table shown by /stats.
+pub fn render_welcome(results: &[ExperimentResult]) -> String {
+    if results.is_empty() {
+        return "Эксперимент еще не показывался".to_string();
+    }
+
+    let mut lines = vec![format!("{:<8} {:>6} {:>6} {:>6}", "Вариант", "Показ", "Рег", "Конв")];
+
+    for result in results {
+        lines.push(format!(
+            "{:<8} {:>6} {:>6} {:>5.1}%",
+            result.variant.trim_start_matches("welcome_").to_uppercase(),
+            result.shown,
+            result.registered,
+            result.registered as f32 * 100.0 / result.shown.max(1) as f32
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders the /overrides table, discounts and penalties in dollars per operator.
+pub fn render_overrides(totals: &[OverrideTotals]) -> String {
+    if totals.is_empty() {
+        return "Цены не менялись".to_string();
+    }
+
+    let mut lines = vec![format!("{:<12} {:>5} {:>10} {:>10}", "Оператор", "Изм", "Скидки", "Надбавки")];
+
+    for total in totals {
+        lines.push(format!(
+            "{:<12} {:>5} {:>10} {:>10}",
+            total.operator_id,
+            total.overrides,
+            total.discounts.amount(),
+            total.penalties.amount()
+        ));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders the /handover summary for the incoming shift.
+pub fn render_handover(notes: &[StaffNote], tickets: &[Ticket], disputes: &[(i32, String)], unassigned: &[(String, String)]) -> String {
+    let mut sections = Vec::new();
+
+    sections.push(section(
+        "📝 Заметки смены",
+        notes.iter().map(|note| format!("{} {}: {}", note.created_at.format("%d.%m %H:%M"), note.author, note.text))
+    ));
+
+    sections.push(section(
+        &format!("📨 Открытые обращения ({})", tickets.len()),
+        tickets.iter().map(|ticket| format!("#{} {}: {}", ticket.id, ticket.client_code, first_line(&ticket.text)))
+    ));
+
+    sections.push(section(
+        &format!("⚖️ Споры по весу ({})", disputes.len()),
+        disputes.iter().map(|(ticket_id, track_code)| format!("#{} {}", ticket_id, track_code))
+    ));
+
+    sections.push(section(
+        &format!("📦 Посылки без партии ({})", unassigned.len()),
+        unassigned.iter().map(|(track_code, client_code)| format!("{} — {}", track_code, client_code))
+    ));
+
+    sections.join("\n\n")
+}
+
+fn section(title: &str, lines: impl ExactSizeIterator<Item = String>) -> String {
+    let total = lines.len();
+
+    if total == 0 {
+        return format!("{}\nнет", title);
+    }
+
+    let mut lines: Vec<String> = lines.take(HANDOVER_LIMIT).collect();
+
+    if total > HANDOVER_LIMIT {
+        lines.push(format!("и еще {}", total - HANDOVER_LIMIT));
+    }
+
+    format!("{}\n{}", title, lines.join("\n"))
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn week(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap()
+    }
+
+    #[test]
+    fn retention_is_relative_to_cohort_size() {
+        let cohorts = [
+            Cohort { week: week(3), users: 4, parcels: 6 },
+            Cohort { week: week(10), users: 2, parcels: 0 }
+        ];
+        let activity = [
+            CohortActivity { week: week(3), offset: 0, users: 4 },
+            CohortActivity { week: week(3), offset: 1, users: 1 },
+            CohortActivity { week: week(10), offset: 0, users: 1 }
+        ];
+
+        let table = render_cohorts(&cohorts, &activity);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("2024-06-03"));
+        assert!(lines[1].contains("1.5"));
+        assert!(lines[1].ends_with("100%  25%"));
+        assert!(lines[2].ends_with(" 50%"));
+    }
+
+    #[test]
+    fn empty_period_is_reported() {
+        assert_eq!(render_cohorts(&[], &[]), "Нет регистраций за выбранный период");
+    }
+
+    #[test]
+    fn welcome_conversion_is_per_variant() {
+        let results = [
+            ExperimentResult { variant: "welcome_a".to_string(), shown: 200, registered: 50 },
+            ExperimentResult { variant: "welcome_b".to_string(), shown: 180, registered: 63 }
+        ];
+
+        let table = render_welcome(&results);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(lines[1].starts_with("A ") && lines[1].ends_with("25.0%"));
+        assert!(lines[2].starts_with("B ") && lines[2].ends_with("35.0%"));
+    }
+
+    #[test]
+    fn handover_lists_every_section() {
+        let created_at = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap().and_hms_opt(8, 30, 0).unwrap();
+        let notes = [StaffNote { author: "Айбек".to_string(), text: "Весы на 2 стойке барахлят".to_string(), created_at }];
+        let tickets = [Ticket { id: 7, client_code: "MX201H".to_string(), text: "Где посылка?\nЖду неделю".to_string(), created_at }];
+        let unassigned: Vec<(String, String)> = (0..25).map(|i| (format!("YT{}", i), "MX201H".to_string())).collect();
+
+        let text = render_handover(&notes, &tickets, &[], &unassigned);
+
+        assert!(text.contains("03.06 08:30 Айбек: Весы на 2 стойке барахлят"));
+        assert!(text.contains("#7 MX201H: Где посылка?\n"));
+        assert!(text.contains("Споры по весу (0)\nнет"));
+        assert!(text.contains("Посылки без партии (25)"));
+        assert!(text.ends_with("и еще 5"));
+    }
+}
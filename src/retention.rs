@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use crate::{config::env_or, database::Db, models::RetentionRun};
+
+const RUN_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many months each kind of data is kept in full.
+///
+/// Events are summed up per month and kind before they go, closed tickets move to an archive
+/// table with their attachments left in place, delivered parcels keep their track code and dates
+/// but drop photos and item details.
+#[derive(Clone)]
+pub struct RetentionPolicy {
+    pub events_months: i32,
+    pub tickets_months: i32,
+    pub parcels_months: i32,
+    pub dry_run: bool
+}
+
+impl RetentionPolicy {
+    pub fn from_env() -> RetentionPolicy {
+        RetentionPolicy {
+            events_months: env_or("RETENTION_EVENTS_MONTHS", 12),
+            tickets_months: env_or("RETENTION_TICKETS_MONTHS", 6),
+            parcels_months: env_or("RETENTION_PARCELS_MONTHS", 6),
+            dry_run: env_or("RETENTION_DRY_RUN", false)
+        }
+    }
+}
+
+/// Applies the retention policy once a day.
+pub struct Retention {
+    db: Db,
+    policy: RetentionPolicy
+}
+
+impl Retention {
+    pub fn spawn(db: Db, policy: RetentionPolicy) {
+        log::info!("Starting the retention job");
+        tokio::spawn(Retention { db, policy }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let run = self.db.apply_retention(&self.policy, self.policy.dry_run).await;
+
+            log::info!(
+                "Retention: {} events, {} tickets, {} parcels{}",
+                run.events,
+                run.tickets,
+                run.parcels,
+                if run.dry_run { " (dry run)" } else { "" }
+            );
+        }
+    }
+}
+
+/// The policy and the latest runs, for /retention.
+pub fn render_report(policy: &RetentionPolicy, runs: &[RetentionRun]) -> String {
+    let mut lines = vec![
+        format!("События хранятся {} мес., затем сводятся по месяцам", policy.events_months),
+        format!("Закрытые обращения уходят в архив через {} мес.", policy.tickets_months),
+        format!("Доставленные посылки очищаются от фото и ссылок через {} мес.", policy.parcels_months)
+    ];
+
+    if policy.dry_run {
+        lines.push("Включен пробный режим: данные только подсчитываются".to_string());
+    }
+
+    lines.push(String::new());
+
+    if runs.is_empty() {
+        lines.push("Очистка еще не запускалась".to_string());
+    }
+
+    for run in runs {
+        lines.push(format!(
+            "{}{}: событий {}, обращений {}, посылок {}",
+            run.ran_at.format("%d.%m.%Y %H:%M"),
+            if run.dry_run { " (пробно)" } else { "" },
+            run.events,
+            run.tickets,
+            run.parcels
+        ));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn report_lists_dry_runs() {
+        let policy = RetentionPolicy { events_months: 12, tickets_months: 6, parcels_months: 6, dry_run: true };
+        let run = RetentionRun {
+            dry_run: true,
+            events: 1200,
+            tickets: 35,
+            parcels: 410,
+            ran_at: NaiveDate::from_ymd_opt(2024, 9, 1).unwrap().and_hms_opt(3, 0, 0).unwrap()
+        };
+
+        let text = render_report(&policy, &[run]);
+
+        assert!(text.contains("Включен пробный режим"));
+        assert!(text.contains("01.09.2024 03:00 (пробно): событий 1200, обращений 35, посылок 410"));
+        assert!(render_report(&policy, &[]).contains("еще не запускалась"));
+    }
+}
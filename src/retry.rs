@@ -0,0 +1,304 @@
+use std::{future::{Future, IntoFuture}, pin::Pin, time::Duration};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use reqwest::Url;
+use serde::Serialize;
+use teloxide::{requests::{HasPayload, JsonRequest, Output, Payload, Request, Requester}, types::*, RequestError};
+
+// One try and three retries, the last wait is about four seconds.
+const MAX_ATTEMPTS: u32 = 4;
+
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// The Telegram client of the bot, every request it makes is sent with [`send`].
+///
+/// Handlers get it from the dispatcher, so none of them can send a message without the retries.
+#[derive(Clone, Debug)]
+pub struct Bot(teloxide::Bot);
+
+impl Bot {
+    pub fn new(token: impl Into<String>) -> Bot {
+        Bot(teloxide::Bot::new(token))
+    }
+
+    /// A request teloxide has no method for, sent with the same retries.
+    pub fn json<P>(&self, payload: P) -> Retried<JsonRequest<P>>
+    where
+        P: Payload + Serialize
+    {
+        Retried(JsonRequest::new(self.0.clone(), payload))
+    }
+}
+
+/// A request of [`Bot`], setters reach the payload as on the plain request.
+#[derive(Clone)]
+pub struct Retried<R>(R);
+
+impl<R: HasPayload> HasPayload for Retried<R> {
+    type Payload = R::Payload;
+
+    fn payload_mut(&mut self) -> &mut Self::Payload {
+        self.0.payload_mut()
+    }
+
+    fn payload_ref(&self) -> &Self::Payload {
+        self.0.payload_ref()
+    }
+}
+
+type RetriedFuture<R> = Pin<Box<dyn Future<Output = Result<Output<R>, RequestError>> + Send>>;
+
+impl<R> Request for Retried<R>
+where
+    R: Request<Err = RequestError> + Clone + Send + Sync + 'static,
+    Output<R>: Send
+{
+    type Err = RequestError;
+    type Send = RetriedFuture<R>;
+    type SendRef = RetriedFuture<R>;
+
+    fn send(self) -> Self::Send {
+        Box::pin(send(self.0))
+    }
+
+    fn send_ref(&self) -> Self::SendRef {
+        Box::pin(send(self.0.clone()))
+    }
+}
+
+impl<R> IntoFuture for Retried<R>
+where
+    R: Request<Err = RequestError> + Clone + Send + Sync + 'static,
+    Output<R>: Send
+{
+    type Output = Result<Output<R>, RequestError>;
+    type IntoFuture = RetriedFuture<R>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Request::send(self)
+    }
+}
+
+/// Every method of [`Requester`], given as `Request method<Generics>(args) where bounds;`.
+macro_rules! retried {
+    ($($request:ident $method:ident $(<$($generic:ident),*>)? ($($arg:ident: $ty:ty),*) $(where $($bounded:ident: $bound:path),*)?;)*) => {
+        $(
+            type $request = Retried<<teloxide::Bot as Requester>::$request>;
+
+            fn $method $(<$($generic),*>)? (&self, $($arg: $ty),*) -> Self::$request
+            $(where $($bounded: $bound),*)?
+            {
+                Retried(self.0.$method($($arg),*))
+            }
+        )*
+    };
+}
+
+impl Requester for Bot {
+    type Err = RequestError;
+
+    retried! {
+        GetUpdates get_updates();
+        SetWebhook set_webhook(url: Url);
+        DeleteWebhook delete_webhook();
+        GetWebhookInfo get_webhook_info();
+        GetMe get_me();
+        LogOut log_out();
+        Close close();
+        SendMessage send_message<C, T>(chat_id: C, text: T) where C: Into<Recipient>, T: Into<String>;
+        ForwardMessage forward_message<C, F>(chat_id: C, from_chat_id: F, message_id: MessageId) where C: Into<Recipient>, F: Into<Recipient>;
+        CopyMessage copy_message<C, F>(chat_id: C, from_chat_id: F, message_id: MessageId) where C: Into<Recipient>, F: Into<Recipient>;
+        SendPhoto send_photo<C>(chat_id: C, photo: InputFile) where C: Into<Recipient>;
+        SendAudio send_audio<C>(chat_id: C, audio: InputFile) where C: Into<Recipient>;
+        SendDocument send_document<C>(chat_id: C, document: InputFile) where C: Into<Recipient>;
+        SendVideo send_video<C>(chat_id: C, video: InputFile) where C: Into<Recipient>;
+        SendAnimation send_animation<C>(chat_id: C, animation: InputFile) where C: Into<Recipient>;
+        SendVoice send_voice<C>(chat_id: C, voice: InputFile) where C: Into<Recipient>;
+        SendVideoNote send_video_note<C>(chat_id: C, video_note: InputFile) where C: Into<Recipient>;
+        SendMediaGroup send_media_group<C, M>(chat_id: C, media: M) where C: Into<Recipient>, M: IntoIterator<Item = InputMedia>;
+        SendLocation send_location<C>(chat_id: C, latitude: f64, longitude: f64) where C: Into<Recipient>;
+        EditMessageLiveLocation edit_message_live_location<C>(chat_id: C, message_id: MessageId, latitude: f64, longitude: f64) where C: Into<Recipient>;
+        EditMessageLiveLocationInline edit_message_live_location_inline<I>(inline_message_id: I, latitude: f64, longitude: f64) where I: Into<String>;
+        StopMessageLiveLocation stop_message_live_location<C>(chat_id: C, message_id: MessageId, latitude: f64, longitude: f64) where C: Into<Recipient>;
+        StopMessageLiveLocationInline stop_message_live_location_inline<I>(inline_message_id: I, latitude: f64, longitude: f64) where I: Into<String>;
+        SendVenue send_venue<C, T, A>(chat_id: C, latitude: f64, longitude: f64, title: T, address: A) where C: Into<Recipient>, T: Into<String>, A: Into<String>;
+        SendContact send_contact<C, P, F>(chat_id: C, phone_number: P, first_name: F) where C: Into<Recipient>, P: Into<String>, F: Into<String>;
+        SendPoll send_poll<C, Q, O>(chat_id: C, question: Q, options: O) where C: Into<Recipient>, Q: Into<String>, O: IntoIterator<Item = String>;
+        SendDice send_dice<C>(chat_id: C) where C: Into<Recipient>;
+        SendChatAction send_chat_action<C>(chat_id: C, action: ChatAction) where C: Into<Recipient>;
+        GetUserProfilePhotos get_user_profile_photos(user_id: UserId);
+        GetFile get_file<F>(file_id: F) where F: Into<String>;
+        BanChatMember ban_chat_member<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        KickChatMember kick_chat_member<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        UnbanChatMember unban_chat_member<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        RestrictChatMember restrict_chat_member<C>(chat_id: C, user_id: UserId, permissions: ChatPermissions) where C: Into<Recipient>;
+        PromoteChatMember promote_chat_member<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        SetChatAdministratorCustomTitle set_chat_administrator_custom_title<Ch, C>(chat_id: Ch, user_id: UserId, custom_title: C) where Ch: Into<Recipient>, C: Into<String>;
+        BanChatSenderChat ban_chat_sender_chat<C, S>(chat_id: C, sender_chat_id: S) where C: Into<Recipient>, S: Into<ChatId>;
+        UnbanChatSenderChat unban_chat_sender_chat<C, S>(chat_id: C, sender_chat_id: S) where C: Into<Recipient>, S: Into<ChatId>;
+        SetChatPermissions set_chat_permissions<C>(chat_id: C, permissions: ChatPermissions) where C: Into<Recipient>;
+        ExportChatInviteLink export_chat_invite_link<C>(chat_id: C) where C: Into<Recipient>;
+        CreateChatInviteLink create_chat_invite_link<C>(chat_id: C) where C: Into<Recipient>;
+        EditChatInviteLink edit_chat_invite_link<C, I>(chat_id: C, invite_link: I) where C: Into<Recipient>, I: Into<String>;
+        RevokeChatInviteLink revoke_chat_invite_link<C, I>(chat_id: C, invite_link: I) where C: Into<Recipient>, I: Into<String>;
+        ApproveChatJoinRequest approve_chat_join_request<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        DeclineChatJoinRequest decline_chat_join_request<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        SetChatPhoto set_chat_photo<C>(chat_id: C, photo: InputFile) where C: Into<Recipient>;
+        DeleteChatPhoto delete_chat_photo<C>(chat_id: C) where C: Into<Recipient>;
+        SetChatTitle set_chat_title<C, T>(chat_id: C, title: T) where C: Into<Recipient>, T: Into<String>;
+        SetChatDescription set_chat_description<C>(chat_id: C) where C: Into<Recipient>;
+        PinChatMessage pin_chat_message<C>(chat_id: C, message_id: MessageId) where C: Into<Recipient>;
+        UnpinChatMessage unpin_chat_message<C>(chat_id: C) where C: Into<Recipient>;
+        UnpinAllChatMessages unpin_all_chat_messages<C>(chat_id: C) where C: Into<Recipient>;
+        LeaveChat leave_chat<C>(chat_id: C) where C: Into<Recipient>;
+        GetChat get_chat<C>(chat_id: C) where C: Into<Recipient>;
+        GetChatAdministrators get_chat_administrators<C>(chat_id: C) where C: Into<Recipient>;
+        GetChatMemberCount get_chat_member_count<C>(chat_id: C) where C: Into<Recipient>;
+        GetChatMembersCount get_chat_members_count<C>(chat_id: C) where C: Into<Recipient>;
+        GetChatMember get_chat_member<C>(chat_id: C, user_id: UserId) where C: Into<Recipient>;
+        SetChatStickerSet set_chat_sticker_set<C, S>(chat_id: C, sticker_set_name: S) where C: Into<Recipient>, S: Into<String>;
+        DeleteChatStickerSet delete_chat_sticker_set<C>(chat_id: C) where C: Into<Recipient>;
+        GetForumTopicIconStickers get_forum_topic_icon_stickers();
+        CreateForumTopic create_forum_topic<C, N, I>(chat_id: C, name: N, icon_color: u32, icon_custom_emoji_id: I) where C: Into<Recipient>, N: Into<String>, I: Into<String>;
+        EditForumTopic edit_forum_topic<C>(chat_id: C, message_thread_id: i32) where C: Into<Recipient>;
+        CloseForumTopic close_forum_topic<C>(chat_id: C, message_thread_id: i32) where C: Into<Recipient>;
+        ReopenForumTopic reopen_forum_topic<C>(chat_id: C, message_thread_id: i32) where C: Into<Recipient>;
+        DeleteForumTopic delete_forum_topic<C>(chat_id: C, message_thread_id: i32) where C: Into<Recipient>;
+        UnpinAllForumTopicMessages unpin_all_forum_topic_messages<C>(chat_id: C, message_thread_id: i32) where C: Into<Recipient>;
+        EditGeneralForumTopic edit_general_forum_topic<C, N>(chat_id: C, name: N) where C: Into<Recipient>, N: Into<String>;
+        CloseGeneralForumTopic close_general_forum_topic<C>(chat_id: C) where C: Into<Recipient>;
+        ReopenGeneralForumTopic reopen_general_forum_topic<C>(chat_id: C) where C: Into<Recipient>;
+        HideGeneralForumTopic hide_general_forum_topic<C>(chat_id: C) where C: Into<Recipient>;
+        UnhideGeneralForumTopic unhide_general_forum_topic<C>(chat_id: C) where C: Into<Recipient>;
+        AnswerCallbackQuery answer_callback_query<C>(callback_query_id: C) where C: Into<String>;
+        SetMyCommands set_my_commands<C>(commands: C) where C: IntoIterator<Item = BotCommand>;
+        GetMyCommands get_my_commands();
+        SetChatMenuButton set_chat_menu_button();
+        GetChatMenuButton get_chat_menu_button();
+        SetMyDefaultAdministratorRights set_my_default_administrator_rights();
+        GetMyDefaultAdministratorRights get_my_default_administrator_rights();
+        DeleteMyCommands delete_my_commands();
+        AnswerInlineQuery answer_inline_query<I, R>(inline_query_id: I, results: R) where I: Into<String>, R: IntoIterator<Item = InlineQueryResult>;
+        AnswerWebAppQuery answer_web_app_query<W>(web_app_query_id: W, result: InlineQueryResult) where W: Into<String>;
+        EditMessageText edit_message_text<C, T>(chat_id: C, message_id: MessageId, text: T) where C: Into<Recipient>, T: Into<String>;
+        EditMessageTextInline edit_message_text_inline<I, T>(inline_message_id: I, text: T) where I: Into<String>, T: Into<String>;
+        EditMessageCaption edit_message_caption<C>(chat_id: C, message_id: MessageId) where C: Into<Recipient>;
+        EditMessageCaptionInline edit_message_caption_inline<I>(inline_message_id: I) where I: Into<String>;
+        EditMessageMedia edit_message_media<C>(chat_id: C, message_id: MessageId, media: InputMedia) where C: Into<Recipient>;
+        EditMessageMediaInline edit_message_media_inline<I>(inline_message_id: I, media: InputMedia) where I: Into<String>;
+        EditMessageReplyMarkup edit_message_reply_markup<C>(chat_id: C, message_id: MessageId) where C: Into<Recipient>;
+        EditMessageReplyMarkupInline edit_message_reply_markup_inline<I>(inline_message_id: I) where I: Into<String>;
+        StopPoll stop_poll<C>(chat_id: C, message_id: MessageId) where C: Into<Recipient>;
+        DeleteMessage delete_message<C>(chat_id: C, message_id: MessageId) where C: Into<Recipient>;
+        SendSticker send_sticker<C>(chat_id: C, sticker: InputFile) where C: Into<Recipient>;
+        GetStickerSet get_sticker_set<N>(name: N) where N: Into<String>;
+        GetCustomEmojiStickers get_custom_emoji_stickers<C>(custom_emoji_ids: C) where C: IntoIterator<Item = String>;
+        UploadStickerFile upload_sticker_file(user_id: UserId, png_sticker: InputFile);
+        CreateNewStickerSet create_new_sticker_set<N, T, E>(user_id: UserId, name: N, title: T, sticker: InputSticker, emojis: E) where N: Into<String>, T: Into<String>, E: Into<String>;
+        AddStickerToSet add_sticker_to_set<N, E>(user_id: UserId, name: N, sticker: InputSticker, emojis: E) where N: Into<String>, E: Into<String>;
+        SetStickerPositionInSet set_sticker_position_in_set<S>(sticker: S, position: u32) where S: Into<String>;
+        DeleteStickerFromSet delete_sticker_from_set<S>(sticker: S) where S: Into<String>;
+        SetStickerSetThumb set_sticker_set_thumb<N>(name: N, user_id: UserId) where N: Into<String>;
+        SendInvoice send_invoice<Ch, T, D, Pa, P, C, Pri>(chat_id: Ch, title: T, description: D, payload: Pa, provider_token: P, currency: C, prices: Pri) where Ch: Into<Recipient>, T: Into<String>, D: Into<String>, Pa: Into<String>, P: Into<String>, C: Into<String>, Pri: IntoIterator<Item = LabeledPrice>;
+        CreateInvoiceLink create_invoice_link<T, D, Pa, P, C, Pri>(title: T, description: D, payload: Pa, provider_token: P, currency: C, prices: Pri) where T: Into<String>, D: Into<String>, Pa: Into<String>, P: Into<String>, C: Into<String>, Pri: IntoIterator<Item = LabeledPrice>;
+        AnswerShippingQuery answer_shipping_query<S>(shipping_query_id: S, ok: bool) where S: Into<String>;
+        AnswerPreCheckoutQuery answer_pre_checkout_query<P>(pre_checkout_query_id: P, ok: bool) where P: Into<String>;
+        SetPassportDataErrors set_passport_data_errors<E>(user_id: UserId, errors: E) where E: IntoIterator<Item = PassportElementError>;
+        SendGame send_game<G>(chat_id: u32, game_short_name: G) where G: Into<String>;
+        SetGameScore set_game_score(user_id: UserId, score: u64, chat_id: u32, message_id: MessageId);
+        SetGameScoreInline set_game_score_inline<I>(user_id: UserId, score: u64, inline_message_id: I) where I: Into<String>;
+        GetGameHighScores get_game_high_scores<T>(user_id: UserId, target: T) where T: Into<TargetMessage>;
+    }
+}
+
+/// Sends the request, retrying flood control waits and connections that failed before anything was sent.
+///
+/// Waits grow exponentially with a random spread, so chats throttled at once do not retry at once.
+async fn send<R>(request: R) -> Result<Output<R>, RequestError>
+where
+    R: Request<Err = RequestError>
+{
+    let mut attempt = 0;
+
+    loop {
+        let err = match request.send_ref().await {
+            Ok(output) => return Ok(output),
+            Err(err) => err
+        };
+
+        attempt += 1;
+
+        if attempt >= MAX_ATTEMPTS || !retryable(&err) {
+            return Err(err);
+        }
+
+        let delay = match &err {
+            // Telegram names the wait, the spread only keeps the retries apart.
+            RequestError::RetryAfter(after) => *after + backoff(0, OsRng.next_u32()),
+            _ => backoff(attempt - 1, OsRng.next_u32())
+        };
+
+        log::warn!("Telegram request failed ({}), retrying in {:?}", err, delay);
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn retryable(err: &RequestError) -> bool {
+    match err {
+        RequestError::RetryAfter(_) => true,
+        // Only a connection that never opened is sure not to have delivered the message,
+        // after a timeout or a 5xx it may have been sent and a retry would send it twice.
+        RequestError::Network(err) => err.is_connect(),
+        _ => false
+    }
+}
+
+/// Between half and all of the exponential delay for this attempt, `random` picks the point.
+fn backoff(attempt: u32, random: u32) -> Duration {
+    let full = BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)).min(MAX_DELAY);
+    let half = full / 2;
+
+    half + half.mul_f64(random as f64 / u32::MAX as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::{payloads::SendMessageSetters, ApiError};
+
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_with_spread_and_cap() {
+        assert_eq!(backoff(0, 0), Duration::from_millis(250));
+        assert_eq!(backoff(0, u32::MAX), Duration::from_millis(500));
+        assert_eq!(backoff(2, u32::MAX), Duration::from_secs(2));
+
+        let spread = backoff(1, u32::MAX / 2);
+        assert!(spread > Duration::from_millis(500) && spread < Duration::from_secs(1));
+
+        assert_eq!(backoff(30, u32::MAX), MAX_DELAY);
+    }
+
+    #[test]
+    fn setters_reach_the_payload_of_a_retried_request() {
+        let request = Bot::new("token").send_message(ChatId(1), "Привет").disable_notification(true);
+
+        assert_eq!(request.payload_ref().text, "Привет");
+        assert_eq!(request.payload_ref().disable_notification, Some(true));
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        assert!(retryable(&RequestError::RetryAfter(Duration::from_secs(3))));
+
+        assert!(!retryable(&RequestError::Api(ApiError::Unknown("Bad Gateway".into()))));
+        assert!(!retryable(&RequestError::Io(std::io::Error::from(std::io::ErrorKind::TimedOut))));
+
+        assert!(!retryable(&RequestError::Api(ApiError::BotBlocked)));
+        assert!(!retryable(&RequestError::Api(ApiError::MessageNotModified)));
+        assert!(!retryable(&RequestError::MigrateToChatId(1)));
+    }
+}
@@ -0,0 +1,175 @@
+use std::{future::Future, sync::{Arc, Mutex}, time::Duration};
+
+use tokio::{sync::Semaphore, task::JoinSet, time::Instant};
+
+use crate::vendor::{self, VendorResult};
+
+const MAX_CONCURRENT_POLLS: usize = 4;
+
+// Server errors in a row after which every provider is left alone for a while.
+const ERRORS_BEFORE_BACKOFF: u32 = 3;
+
+const MIN_BACKOFF: Duration = Duration::from_secs(30);
+
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Spreads vendor polls over a time window instead of firing them all at once.
+///
+/// Clones share the poll permits and the backoff, every job that polls vendors has to use one.
+/// Request budgets are per source, see [`vendor::lookup`].
+#[derive(Clone)]
+pub struct PollScheduler {
+    permits: Arc<Semaphore>,
+    backoff: Arc<Mutex<Backoff>>
+}
+
+impl PollScheduler {
+    pub fn new() -> PollScheduler {
+        PollScheduler {
+            permits: Arc::new(Semaphore::new(MAX_CONCURRENT_POLLS)),
+            backoff: Arc::new(Mutex::new(Backoff::default()))
+        }
+    }
+
+    /// Starts the polls evenly over `window` and waits for all of them to finish.
+    pub async fn run<T, F, Fut>(&self, window: Duration, jobs: Vec<T>, poll: F)
+    where
+        T: Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = VendorResult<()>> + Send + 'static
+    {
+        let spacing = spacing(window, jobs.len());
+        let start = Instant::now();
+        let poll = Arc::new(poll);
+        let mut tasks = JoinSet::new();
+
+        for (i, job) in jobs.into_iter().enumerate() {
+            tokio::time::sleep_until(start + spacing * i as u32).await;
+
+            let paused_until = self.backoff.lock().unwrap().paused_until(Instant::now());
+
+            if let Some(until) = paused_until {
+                log::warn!("Scheduler: vendors keep failing, polls paused for {:?}", until - Instant::now());
+                tokio::time::sleep_until(until).await;
+            }
+
+            let permit = self.permits.clone().acquire_owned().await.expect("ERROR: Poll permits are closed");
+
+            let poll = poll.clone();
+            let backoff = self.backoff.clone();
+
+            tasks.spawn(async move {
+                let result = poll(job).await;
+                drop(permit);
+
+                let mut backoff = backoff.lock().unwrap();
+
+                match result {
+                    Ok(()) => backoff.record_success(),
+                    Err(err) if vendor::is_server_error(err.as_ref()) => backoff.record_server_error(Instant::now()),
+                    Err(_) => {}
+                }
+            });
+        }
+
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+fn spacing(window: Duration, jobs: usize) -> Duration {
+    match jobs {
+        0 => Duration::ZERO,
+        jobs => window / jobs as u32
+    }
+}
+
+/// Doubles the pause each time vendors fail again right after one, up to `MAX_BACKOFF`.
+struct Backoff {
+    failures: u32,
+    delay: Duration,
+    until: Option<Instant>
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff { failures: 0, delay: MIN_BACKOFF, until: None }
+    }
+}
+
+impl Backoff {
+    fn record_success(&mut self) {
+        self.failures = 0;
+        self.delay = MIN_BACKOFF;
+    }
+
+    fn record_server_error(&mut self, now: Instant) {
+        self.failures += 1;
+
+        if self.failures >= ERRORS_BEFORE_BACKOFF {
+            self.until = Some(now + self.delay);
+            self.delay = (self.delay * 2).min(MAX_BACKOFF);
+            self.failures = 0;
+        }
+    }
+
+    fn paused_until(&self, now: Instant) -> Option<Instant> {
+        self.until.filter(|until| *until > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polls_are_spread_over_the_window() {
+        assert_eq!(spacing(Duration::from_secs(1800), 600), Duration::from_secs(3));
+        assert_eq!(spacing(Duration::from_secs(1800), 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn repeated_server_errors_pause_polls() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+
+        backoff.record_server_error(now);
+        backoff.record_server_error(now);
+        assert_eq!(backoff.paused_until(now), None);
+
+        backoff.record_server_error(now);
+        assert_eq!(backoff.paused_until(now), Some(now + MIN_BACKOFF));
+        assert_eq!(backoff.paused_until(now + MIN_BACKOFF), None);
+    }
+
+    #[test]
+    fn pause_grows_until_a_poll_succeeds() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+
+        for _ in 0..ERRORS_BEFORE_BACKOFF * 2 {
+            backoff.record_server_error(now);
+        }
+
+        assert_eq!(backoff.paused_until(now), Some(now + MIN_BACKOFF * 2));
+
+        backoff.record_success();
+        for _ in 0..ERRORS_BEFORE_BACKOFF {
+            backoff.record_server_error(now);
+        }
+
+        assert_eq!(backoff.paused_until(now), Some(now + MIN_BACKOFF));
+    }
+
+    #[test]
+    fn success_in_between_resets_the_count() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+
+        backoff.record_server_error(now);
+        backoff.record_server_error(now);
+        backoff.record_success();
+        backoff.record_server_error(now);
+
+        assert_eq!(backoff.paused_until(now), None);
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::NaiveDate;
+use sqlx::{Postgres, QueryBuilder};
+
+/// A condition on the users a broadcast goes to, typed as `key:value` before the text.
+#[derive(Debug, PartialEq)]
+pub enum Filter {
+    RegisteredAfter(NaiveDate),
+    AtWarehouse,
+    City(String),
+    InactiveDays(i32)
+}
+
+impl Filter {
+    /// None when the token is not a filter at all, i.e. the broadcast text starts here.
+    fn parse(token: &str) -> Option<Result<Filter, String>> {
+        let (key, value) = token.split_once(':')?;
+
+        let filter = match key {
+            "after" => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(Filter::RegisteredAfter)
+                .map_err(|_| format!("Дата {} должна быть в формате ГГГГ-ММ-ДД", value)),
+            "parcels" if value == "warehouse" => Ok(Filter::AtWarehouse),
+            "parcels" => Err(format!("Неизвестный фильтр посылок {}, поддерживается parcels:warehouse", value)),
+            "city" if !value.is_empty() => Ok(Filter::City(value.to_string())),
+            "city" => Err("Укажите город, например city:Ош".to_string()),
+            "inactive" => match value.parse::<i32>() {
+                Ok(days) if days > 0 => Ok(Filter::InactiveDays(days)),
+                _ => Err(format!("Число дней {} должно быть положительным", value))
+            },
+            _ => return None
+        };
+
+        Some(filter)
+    }
+
+    fn to_arg(&self) -> String {
+        match self {
+            Filter::RegisteredAfter(date) => format!("after:{}", date.format("%Y-%m-%d")),
+            Filter::AtWarehouse => "parcels:warehouse".to_string(),
+            Filter::City(city) => format!("city:{}", city),
+            Filter::InactiveDays(days) => format!("inactive:{}", days)
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Filter::RegisteredAfter(date) => format!("зарегистрированы с {}", date.format("%d.%m.%Y")),
+            Filter::AtWarehouse => "есть посылки на складе".to_string(),
+            Filter::City(city) => format!("город {}", city),
+            Filter::InactiveDays(days) => format!("не заходили {}+ дней", days)
+        }
+    }
+}
+
+/// Users matching all filters, every active user when there are none.
+#[derive(Debug, Default, PartialEq)]
+pub struct Segment {
+    pub filters: Vec<Filter>
+}
+
+impl Segment {
+    /// Splits `/broadcast` arguments into the leading filters and the text.
+    pub fn parse(args: &str) -> Result<(Segment, String), String> {
+        let mut filters = Vec::new();
+        let mut rest = args.trim_start();
+
+        while let Some(token) = rest.split_whitespace().next() {
+            match Filter::parse(token) {
+                Some(filter) => filters.push(filter?),
+                None => break
+            }
+
+            rest = rest[token.len()..].trim_start();
+        }
+
+        Ok((Segment { filters }, rest.trim_end().to_string()))
+    }
+
+    /// The filters in the form they were typed, to keep a pending broadcast.
+    pub fn to_args(&self) -> String {
+        self.filters.iter().map(Filter::to_arg).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn describe(&self) -> String {
+        if self.filters.is_empty() {
+            return "все пользователи".to_string();
+        }
+
+        self.filters.iter().map(Filter::describe).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Appends the conditions to a query over `users u`.
+    pub fn push_conditions(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        for filter in &self.filters {
+            builder.push(" AND ");
+
+            match filter {
+                Filter::RegisteredAfter(date) => {
+                    builder.push("(u.created_at AT TIME ZONE 'Asia/Bishkek')::date >= ").push_bind(*date);
+                },
+                Filter::AtWarehouse => {
+                    builder.push("EXISTS (SELECT 1 FROM parcels p WHERE p.user_id = u.id AND p.arrived AND p.batch_code IS NULL)");
+                },
+                Filter::City(city) => {
+                    builder.push("lower(u.city) = lower(").push_bind(city.clone()).push(")");
+                },
+                Filter::InactiveDays(days) => {
+                    builder.push("NOT EXISTS (SELECT 1 FROM events e WHERE e.telegram_id = u.telegram_id AND e.kind = 'active'
+                        AND e.created_at > now() - make_interval(days => ").push_bind(*days).push("))");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leading_filters_are_split_from_the_text() {
+        let (segment, text) = Segment::parse("city:Ош inactive:30 Скидка 10%\nтолько сегодня").unwrap();
+
+        assert_eq!(segment.filters, vec![Filter::City("Ош".to_string()), Filter::InactiveDays(30)]);
+        assert_eq!(text, "Скидка 10%\nтолько сегодня");
+
+        let (segment, text) = Segment::parse("Всем привет").unwrap();
+
+        assert_eq!(segment, Segment::default());
+        assert_eq!(text, "Всем привет");
+    }
+
+    #[test]
+    fn invalid_filter_values_are_reported() {
+        assert!(Segment::parse("after:01.05.2024 текст").is_err());
+        assert!(Segment::parse("inactive:-3 текст").is_err());
+        assert!(Segment::parse("parcels:home текст").is_err());
+    }
+
+    #[test]
+    fn filters_survive_a_round_trip() {
+        let (segment, _) = Segment::parse("after:2024-05-01 parcels:warehouse city:Ош текст").unwrap();
+        let (restored, text) = Segment::parse(&segment.to_args()).unwrap();
+
+        assert_eq!(restored, segment);
+        assert!(text.is_empty());
+    }
+
+    #[test]
+    fn filters_compile_to_bound_conditions() {
+        let (segment, _) = Segment::parse("after:2024-05-01 city:Ош inactive:30 текст").unwrap();
+        let mut builder = QueryBuilder::new("SELECT u.telegram_id FROM users u WHERE u.deleted_at IS NULL");
+
+        segment.push_conditions(&mut builder);
+
+        let sql = builder.sql();
+
+        assert!(sql.contains("::date >= $1"));
+        assert!(sql.contains("lower(u.city) = lower($2)"));
+        assert!(sql.contains("days => $3"));
+        assert!(!sql.contains("Ош"));
+    }
+}
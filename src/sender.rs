@@ -0,0 +1,127 @@
+use std::{num::NonZeroU32, sync::Arc};
+
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use teloxide::{payloads::{SendMessageSetters, SendPhotoSetters}, requests::Requester, types::{ChatId, InlineKeyboardMarkup, InputFile, MessageId}, ApiError, RequestError};
+use tokio::sync::{mpsc::{self, UnboundedReceiver, UnboundedSender}, oneshot};
+
+use crate::{config, database::Db, retry::Bot};
+
+// Telegram allows about 30 messages per second across all chats.
+const MESSAGES_PER_SECOND: u32 = 30;
+
+#[derive(Clone, Copy)]
+pub enum Priority {
+    Interactive,
+    Bulk
+}
+
+struct Outgoing {
+    chat_id: ChatId,
+    text: String,
+    markup: Option<InlineKeyboardMarkup>,
+    // Telegram file id, the text then goes as its caption.
+    photo: Option<String>,
+    delivered: Option<oneshot::Sender<Option<MessageId>>>
+}
+
+#[derive(Clone)]
+pub struct SendQueue {
+    interactive: UnboundedSender<Outgoing>,
+    bulk: UnboundedSender<Outgoing>
+}
+
+impl SendQueue {
+    pub fn spawn(bot: Bot, db: Db) -> SendQueue {
+        log::info!("Starting the send queue");
+        let (interactive, interactive_rx) = mpsc::unbounded_channel();
+        let (bulk, bulk_rx) = mpsc::unbounded_channel();
+
+        Self::run(bot, db, interactive_rx, bulk_rx);
+
+        SendQueue { interactive, bulk }
+    }
+
+    pub fn push(&self, priority: Priority, chat_id: ChatId, text: String) {
+        self.send(priority, Outgoing { chat_id, text, markup: None, photo: None, delivered: None });
+    }
+
+    /// Sends through the same lanes and waits until Telegram accepts or rejects the message.
+    ///
+    /// Returns the id of the message in the chat, None when it was not sent.
+    pub async fn deliver(&self, priority: Priority, chat_id: ChatId, text: String, markup: Option<InlineKeyboardMarkup>, photo: Option<String>) -> Option<MessageId> {
+        let (delivered, result) = oneshot::channel();
+
+        self.send(priority, Outgoing { chat_id, text, markup, photo, delivered: Some(delivered) });
+
+        result.await.unwrap_or(None)
+    }
+
+    fn send(&self, priority: Priority, outgoing: Outgoing) {
+        let lane = match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Bulk => &self.bulk
+        };
+
+        let chat_id = outgoing.chat_id;
+
+        if lane.send(outgoing).is_err() {
+            log::error!("ERROR: Send queue is closed, dropping message to {}", chat_id);
+        }
+    }
+
+    /// Each lane runs on its own task, so a message waiting out a retry holds up only its lane.
+    ///
+    /// The lanes share one rate limit.
+    fn run(bot: Bot, db: Db, interactive: UnboundedReceiver<Outgoing>, bulk: UnboundedReceiver<Outgoing>) {
+        let quota = Quota::per_second(NonZeroU32::new(MESSAGES_PER_SECOND).unwrap());
+        let limiter = Arc::new(RateLimiter::direct(quota));
+
+        tokio::spawn(Self::run_lane(bot.clone(), db.clone(), limiter.clone(), interactive));
+        tokio::spawn(Self::run_lane(bot, db, limiter, bulk));
+    }
+
+    async fn run_lane(bot: Bot, db: Db, limiter: Arc<DefaultDirectRateLimiter>, mut lane: UnboundedReceiver<Outgoing>) {
+        while let Some(outgoing) = lane.recv().await {
+            limiter.until_ready().await;
+
+            // Every notification and broadcast passes here, so staging marks them all.
+            let text = config::banner(outgoing.text);
+
+            let sent = match outgoing.photo {
+                Some(photo) => {
+                    let mut request = bot.send_photo(outgoing.chat_id, InputFile::file_id(photo)).caption(text);
+
+                    if let Some(markup) = outgoing.markup {
+                        request = request.reply_markup(markup);
+                    }
+
+                    request.await
+                },
+                None => {
+                    let mut request = bot.send_message(outgoing.chat_id, text);
+
+                    if let Some(markup) = outgoing.markup {
+                        request = request.reply_markup(markup);
+                    }
+
+                    request.await
+                }
+            };
+
+            match &sent {
+                Err(RequestError::Api(ApiError::BotBlocked | ApiError::UserDeactivated)) => {
+                    log::info!("Sender: {} is unreachable, pausing their notifications", outgoing.chat_id);
+                    db.mark_unreachable(outgoing.chat_id.0).await;
+                },
+                Err(err) => log::error!("ERROR: Could not send a queued message to {}: {}", outgoing.chat_id, err),
+                Ok(_) => {}
+            }
+
+            if let Some(delivered) = outgoing.delivered {
+                let _ = delivered.send(sent.ok().map(|message| message.id));
+            }
+        }
+
+        log::info!("Send queue lane stopped");
+    }
+}
@@ -0,0 +1,192 @@
+use std::{future::Future, net::SocketAddr};
+
+use axum::{body::Bytes, extract::State, http::{header::AUTHORIZATION, HeaderMap, StatusCode}, routing::post, Router};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use crate::{api, config::Config, dashboard, database::Db, eta::Eta, models::{PartnerStatus, WebhookClaim}, notifier::{arrival_notice, ETA_HISTORY_DAYS}};
+
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Clone)]
+struct ServerState {
+    db: Db
+}
+
+pub fn router(db: Db, config: &Config) -> Router {
+    let mut router = Router::new()
+        .route("/partner/status", post(partner_status))
+        .with_state(ServerState { db: db.clone() });
+
+    if let Some(token) = &config.dashboard_token {
+        router = router.merge(dashboard::router(db.clone(), token.clone()));
+    }
+
+    if !config.api_keys.is_empty() {
+        router = router.merge(api::router(db, config.clone()));
+    }
+
+    router
+}
+
+pub async fn serve(address: SocketAddr, router: Router, shutdown: impl Future<Output = ()>) {
+    log::info!("Starting HTTP server on {}", address);
+
+    axum::Server::bind(&address)
+        .serve(router.into_make_service())
+        .with_graceful_shutdown(shutdown)
+        .await
+        .expect("ERROR: HTTP server failed");
+}
+
+async fn partner_status(State(state): State<ServerState>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    let (partner_id, signature) = match (header("X-Partner-Id"), header("X-Signature")) {
+        (Some(partner_id), Some(signature)) => (partner_id, signature),
+        _ => return StatusCode::UNAUTHORIZED
+    };
+
+    let secret = match state.db.get_partner_secret(partner_id).await {
+        Some(secret) => secret,
+        None => {
+            log::warn!("Server: unknown partner {}", partner_id);
+            return StatusCode::UNAUTHORIZED;
+        }
+    };
+
+    if !verify_signature(&secret, &body, signature) {
+        log::warn!("Server: invalid signature from partner {}", partner_id);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let key = match idempotency_key(&headers) {
+        Some(key) => key,
+        None => return StatusCode::BAD_REQUEST
+    };
+
+    match state.db.claim_webhook_key(partner_id, key).await {
+        WebhookClaim::New => (),
+        // The ERP retries until it gets an answer, the first post will give it.
+        WebhookClaim::InProgress => return StatusCode::CONFLICT,
+        WebhookClaim::Done(status) => {
+            log::info!("Server: partner {} repeated key {}", partner_id, key);
+            return StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+        }
+    }
+
+    let status = apply_status(&state.db, partner_id, &body).await;
+
+    state.db.finish_webhook_key(partner_id, key, status.as_u16()).await;
+
+    status
+}
+
+async fn apply_status(db: &Db, partner_id: &str, body: &[u8]) -> StatusCode {
+    let update: PartnerStatus = match serde_json::from_slice(body) {
+        Ok(update) => update,
+        Err(_) => return StatusCode::BAD_REQUEST
+    };
+
+    log::info!("Server: partner {} reported {} for {}", partner_id, update.status, update.track_code);
+
+    if update.status == "arrived" {
+        let eta = Eta::from_history(&db.get_delivery_history(ETA_HISTORY_DAYS).await)
+            .with_planned(&db.get_planned_etas().await);
+
+        db.mark_track_code_arrived(&update.track_code, |parcel| arrival_notice(parcel, &eta)).await;
+    }
+
+    StatusCode::OK
+}
+
+/// The `Idempotency-Key` header, every post must have one and retries repeat it.
+fn idempotency_key(headers: &HeaderMap) -> Option<&str> {
+    headers.get("Idempotency-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LENGTH)
+}
+
+/// Checks a hex HMAC-SHA256 of the body, optionally prefixed with `sha256=`.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim_start_matches("sha256=")) {
+        Ok(signature) => signature,
+        Err(_) => return false
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Checks `Authorization: Bearer <token>` against the accepted tokens, in time independent of where they differ.
+pub fn authorized(headers: &HeaderMap, tokens: &[String]) -> bool {
+    let given = headers.get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    given.is_some_and(|given| tokens.iter().any(|token| {
+        given.len() == token.len() && given.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn valid_signature_is_accepted() {
+        let body = br#"{"track_code":"YT1","status":"arrived"}"#;
+
+        assert!(verify_signature("key", body, &sign("key", body)));
+        assert!(verify_signature("key", body, &format!("sha256={}", sign("key", body))));
+    }
+
+    #[test]
+    fn tampered_body_is_rejected() {
+        let signature = sign("key", br#"{"track_code":"YT1","status":"arrived"}"#);
+
+        assert!(!verify_signature("key", br#"{"track_code":"YT2","status":"arrived"}"#, &signature));
+        assert!(!verify_signature("other", br#"{"track_code":"YT1","status":"arrived"}"#, &signature));
+        assert!(!verify_signature("key", b"", "not hex"));
+    }
+
+    #[test]
+    fn idempotency_key_is_required() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("Idempotency-Key", value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(idempotency_key(&headers(" 7f3a-01 ")), Some("7f3a-01"));
+        assert_eq!(idempotency_key(&headers("  ")), None);
+        assert_eq!(idempotency_key(&headers(&"k".repeat(MAX_KEY_LENGTH + 1))), None);
+        assert_eq!(idempotency_key(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn only_a_known_bearer_token_is_let_in() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+        let tokens = ["s3cret".to_string(), "app-key".to_string()];
+
+        assert!(authorized(&headers("Bearer s3cret"), &tokens));
+        assert!(authorized(&headers("Bearer app-key"), &tokens));
+        assert!(!authorized(&headers("Bearer s3cre"), &tokens));
+        assert!(!authorized(&headers("Bearer s3creT"), &tokens));
+        assert!(!authorized(&headers("s3cret"), &tokens));
+        assert!(!authorized(&HeaderMap::new(), &tokens));
+        assert!(!authorized(&headers("Bearer s3cret"), &[]));
+    }
+}
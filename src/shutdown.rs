@@ -0,0 +1,38 @@
+use teloxide::dispatching::ShutdownToken;
+
+/// Stops the dispatcher on SIGTERM the way Ctrl+C does, so `docker stop` and systemd let the handlers in flight finish.
+pub fn on_terminate(token: ShutdownToken) {
+    tokio::spawn(async move {
+        terminated().await;
+
+        log::info!("SIGTERM received, finishing the updates in progress");
+
+        match token.shutdown() {
+            Ok(finished) => finished.await,
+            // The dispatcher has not started yet or has already stopped, so nothing is left to finish.
+            Err(_) => std::process::exit(0)
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        },
+        Err(err) => {
+            log::error!("ERROR: Could not listen for SIGTERM, only Ctrl+C stops the bot gracefully: {}", err);
+
+            std::future::pending::<()>().await
+        }
+    }
+}
+
+// Elsewhere stop requests arrive as Ctrl+C, which the dispatcher handles itself.
+#[cfg(not(unix))]
+async fn terminated() {
+    std::future::pending::<()>().await
+}
@@ -0,0 +1,187 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::{env_opt, env_or};
+
+const SMS_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Wrong codes a user may type before the number has to be entered again.
+pub const MAX_CODE_ATTEMPTS: u8 = 3;
+
+pub type Delivery<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// A service that delivers text messages to phone numbers.
+pub trait SmsGateway: Send + Sync {
+    fn send<'a>(&'a self, phone_number: &'a str, text: &'a str) -> Delivery<'a>;
+}
+
+/// How often codes may go out, counted in the database so restarts do not reset it.
+#[derive(Clone)]
+pub struct SmsLimits {
+    pub cooldown_seconds: i32,
+    pub daily_per_phone: i64,
+    pub daily_per_user: i64
+}
+
+impl Default for SmsLimits {
+    fn default() -> SmsLimits {
+        SmsLimits { cooldown_seconds: 60, daily_per_phone: 5, daily_per_user: 5 }
+    }
+}
+
+/// Checking the phone number of a registration with a code sent by SMS.
+///
+/// Off unless SMS_VERIFICATION is set. Without SMS_GATEWAY_URL codes only go to the log,
+/// which is enough to click through registration in development.
+#[derive(Clone, Default)]
+pub struct PhoneVerification {
+    gateway: Option<Arc<dyn SmsGateway>>,
+    pub limits: SmsLimits
+}
+
+impl PhoneVerification {
+    pub fn from_env() -> PhoneVerification {
+        if !env_or("SMS_VERIFICATION", false) {
+            return PhoneVerification::default();
+        }
+
+        let gateway = match env_opt("SMS_GATEWAY_URL") {
+            Some(url) => Arc::new(HttpGateway::new(url, env_opt("SMS_GATEWAY_API_KEY"))) as Arc<dyn SmsGateway>,
+            None => {
+                log::warn!("SMS_GATEWAY_URL is not set, verification codes are only logged");
+
+                Arc::new(LogGateway) as Arc<dyn SmsGateway>
+            }
+        };
+
+        let default = SmsLimits::default();
+
+        let limits = SmsLimits {
+            cooldown_seconds: env_or("SMS_COOLDOWN_SECONDS", default.cooldown_seconds),
+            daily_per_phone: env_or("SMS_DAILY_LIMIT_PER_PHONE", default.daily_per_phone),
+            daily_per_user: env_or("SMS_DAILY_LIMIT_PER_USER", default.daily_per_user)
+        };
+
+        PhoneVerification { gateway: Some(gateway), limits }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.gateway.is_some()
+    }
+
+    /// Sends a fresh code to the number and returns its [`hash_code`], None when verification is off or the SMS failed.
+    ///
+    /// The caller checks [`SmsLimits`] first.
+    pub async fn send_code(&self, phone_number: &str) -> Option<String> {
+        let gateway = self.gateway.as_ref()?;
+        let code = generate_code(OsRng.next_u32());
+
+        match gateway.send(phone_number, &format!("MAX EXPRESS: код подтверждения {}", code)).await {
+            Ok(()) => Some(hash_code(&code, &format!("{:08x}", OsRng.next_u32()))),
+            Err(err) => {
+                log::error!("ERROR: Could not send a verification code: {}", err);
+
+                None
+            }
+        }
+    }
+}
+
+fn generate_code(random: u32) -> String {
+    format!("{:04}", random % 10_000)
+}
+
+/// What the dialogue keeps instead of the code, as `salt$sha256`.
+pub fn hash_code(code: &str, salt: &str) -> String {
+    format!("{}${}", salt, hex::encode(Sha256::digest(format!("{}{}", salt, code))))
+}
+
+/// The code as the user typed it, spaces and dashes between digits do not matter.
+pub fn matches(code_hash: &str, text: &str) -> bool {
+    let typed: String = text.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+
+    match code_hash.split_once('$') {
+        Some((salt, _)) => hash_code(&typed, salt) == code_hash,
+        None => false
+    }
+}
+
+/// Writes the code to the log instead of sending it.
+struct LogGateway;
+
+impl SmsGateway for LogGateway {
+    fn send<'a>(&'a self, phone_number: &'a str, text: &'a str) -> Delivery<'a> {
+        Box::pin(async move {
+            log::info!("SMS to {}: {}", phone_number, text);
+
+            Ok(())
+        })
+    }
+}
+
+/// A gateway taking `{"to": ..., "text": ...}` as JSON, with the API key as a Bearer token.
+struct HttpGateway {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>
+}
+
+impl HttpGateway {
+    fn new(url: String, api_key: Option<String>) -> HttpGateway {
+        let client = reqwest::Client::builder()
+            .timeout(SMS_TIMEOUT)
+            .build()
+            .expect("ERROR: Could not build the SMS client");
+
+        HttpGateway { client, url, api_key }
+    }
+}
+
+impl SmsGateway for HttpGateway {
+    fn send<'a>(&'a self, phone_number: &'a str, text: &'a str) -> Delivery<'a> {
+        Box::pin(async move {
+            let mut request = self.client
+                .post(&self.url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(json!({ "to": format!("+{}", phone_number), "text": text }).to_string());
+
+            if let Some(api_key) = &self.api_key {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", api_key));
+            }
+
+            let response = request.send().await.map_err(|err| err.to_string())?;
+
+            match response.status().is_success() {
+                true => Ok(()),
+                false => Err(format!("the gateway answered {}", response.status()))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_have_four_digits() {
+        assert_eq!(generate_code(7), "0007");
+        assert_eq!(generate_code(123_456), "3456");
+    }
+
+    #[test]
+    fn typed_code_may_have_spaces() {
+        let code_hash = hash_code("0427", "5eed");
+
+        assert!(!code_hash.contains("0427"));
+        assert!(matches(&code_hash, "04 27"));
+        assert!(matches(&code_hash, " 04-27\n"));
+        assert!(!matches(&code_hash, "0428"));
+        assert!(!matches(&code_hash, ""));
+        assert!(!matches("0427", "0427"));
+    }
+}
@@ -0,0 +1,91 @@
+use std::{collections::HashSet, sync::{Arc, RwLock}};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+use crate::database::Db;
+
+// An invite nobody redeemed within a day has to be issued again.
+pub const INVITE_HOURS: i32 = 24;
+
+// No 0, O, 1 and I, the code is read off one screen and typed on another.
+const INVITE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+const INVITE_LENGTH: usize = 8;
+
+/// Who may run admin commands, shared between handlers and kept in step with the staff table.
+///
+/// Joining and dismissal change the set at once, no restart is needed.
+#[derive(Clone, Default)]
+pub struct Staff {
+    members: Arc<RwLock<HashSet<i64>>>
+}
+
+impl Staff {
+    /// Reads the staff table, `admin_ids` become its first members while it is empty.
+    pub async fn load(db: &Db, admin_ids: &[i64]) -> Staff {
+        if db.seed_staff(admin_ids).await {
+            log::info!("Staff table seeded from ADMIN_IDS: {} members", admin_ids.len());
+        }
+
+        let members = db.get_staff().await.into_iter().collect();
+
+        Staff { members: Arc::new(RwLock::new(members)) }
+    }
+
+    pub fn contains(&self, telegram_id: i64) -> bool {
+        self.members.read().unwrap().contains(&telegram_id)
+    }
+
+    pub fn ids(&self) -> Vec<i64> {
+        let mut ids: Vec<i64> = self.members.read().unwrap().iter().copied().collect();
+
+        ids.sort();
+        ids
+    }
+
+    /// A one-time code for a new operator, valid for [`INVITE_HOURS`].
+    pub async fn invite(&self, db: &Db, created_by: i64) -> String {
+        let code = invite_code(|| OsRng.next_u32());
+
+        db.create_staff_invite(&code, created_by, INVITE_HOURS).await;
+
+        code
+    }
+
+    /// Adds the user when the code is valid, returns who issued it.
+    pub async fn join(&self, db: &Db, code: &str, telegram_id: i64) -> Option<i64> {
+        let invited_by = db.redeem_staff_invite(&code.trim().to_uppercase(), telegram_id).await?;
+
+        self.members.write().unwrap().insert(telegram_id);
+
+        Some(invited_by)
+    }
+
+    /// Takes away admin commands and permissions, false when the user was not staff.
+    pub async fn dismiss(&self, db: &Db, telegram_id: i64) -> bool {
+        self.members.write().unwrap().remove(&telegram_id);
+
+        db.remove_staff(telegram_id).await
+    }
+}
+
+fn invite_code(mut random: impl FnMut() -> u32) -> String {
+    (0..INVITE_LENGTH)
+        .map(|_| INVITE_ALPHABET[random() as usize % INVITE_ALPHABET.len()] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invite_codes_avoid_lookalike_characters() {
+        let mut next = 0;
+        let code = invite_code(|| { next += 7; next });
+
+        assert_eq!(code.len(), INVITE_LENGTH);
+        assert!(code.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+        assert!(!code.contains(['0', 'O', '1', 'I']));
+    }
+}
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::{database::Db, manifest::escape, models::{MonthlyReceipt, MonthlySpending, Notice}, support::bishkek_now};
+
+// Hourly, so a restart on the 1st only delays the summaries.
+const RUN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const MAX_PER_RUN: i64 = 500;
+
+const MONTHS: [&str; 12] = [
+    "январь", "февраль", "март", "апрель", "май", "июнь",
+    "июль", "август", "сентябрь", "октябрь", "ноябрь", "декабрь"
+];
+
+const HEADER: [&str; 5] = ["Track code", "Delivered", "Weight, kg", "Invoice, USD", "Paid, USD"];
+
+/// Sends clients who asked for it what they got delivered last month, on the 1st.
+pub struct MonthlySummary {
+    db: Db
+}
+
+impl MonthlySummary {
+    pub fn spawn(db: Db) {
+        log::info!("Starting the monthly summary job");
+        tokio::spawn(MonthlySummary { db }.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(RUN_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let today = bishkek_now().date();
+
+            if today.day() != 1 {
+                continue;
+            }
+
+            let month = previous_month(today);
+            let summaries = self.db.get_monthly_spending(month, MAX_PER_RUN).await;
+
+            for spending in &summaries {
+                self.db.send_monthly_summary(month, &notice(month, spending)).await;
+            }
+
+            log::info!("Monthly summary: {} summaries for {} sent", summaries.len(), month.format("%Y-%m"));
+        }
+    }
+}
+
+/// The first day of the month before the one `today` is in.
+pub fn previous_month(today: NaiveDate) -> NaiveDate {
+    let first = today.with_day(1).unwrap();
+
+    (first - chrono::Duration::days(1)).with_day(1).unwrap()
+}
+
+pub fn notice(month: NaiveDate, spending: &MonthlySpending) -> Notice {
+    let markup = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("Скачать список квитанций", format!("summary:{}", month.format("%Y-%m")))
+    ]]);
+
+    let text = format!(
+        "📊 Итоги за {} {}\n\nПолучено посылок: {}\nОбщий вес: {:.2} кг\nОплачено: {}",
+        MONTHS[month.month0() as usize], month.year(), spending.parcels, spending.weight_kg, spending.paid
+    );
+
+    Notice {
+        telegram_id: spending.telegram_id,
+        text,
+        markup: Some(markup),
+        photo_id: None,
+        digest: None,
+        subject: None
+    }
+}
+
+/// The month of a download button, `summary:2024-05`.
+pub fn parse_callback(data: Option<&str>) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(&format!("{}-01", data?.strip_prefix("summary:")?), "%Y-%m-%d").ok()
+}
+
+pub fn file_name(month: NaiveDate) -> String {
+    format!("max-express-{}.csv", month.format("%Y-%m"))
+}
+
+/// The receipts of the month as CSV, with a BOM like the other exports.
+pub fn render_csv(receipts: &[MonthlyReceipt]) -> String {
+    let mut lines = vec![HEADER.iter().map(|cell| escape(cell)).collect::<Vec<_>>().join(",")];
+
+    for receipt in receipts {
+        lines.push([
+            escape(&receipt.track_code),
+            receipt.delivered_at.format("%Y-%m-%d").to_string(),
+            receipt.weight_kg.map_or(String::new(), |weight| format!("{:.2}", weight)),
+            receipt.amount.map_or(String::new(), |amount| amount.amount().to_string()),
+            receipt.paid.amount().to_string()
+        ].join(","));
+    }
+
+    format!("\u{feff}{}\r\n", lines.join("\r\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::money::Money;
+
+    use super::*;
+
+    #[test]
+    fn summary_covers_the_previous_month() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(previous_month(date(2024, 6, 1)), date(2024, 5, 1));
+        assert_eq!(previous_month(date(2024, 1, 1)), date(2023, 12, 1));
+
+        let spending = MonthlySpending { telegram_id: 7, parcels: 3, weight_kg: 4.5, paid: Money::from_cents(2599) };
+        let notice = notice(date(2024, 5, 1), &spending);
+
+        assert_eq!(notice.telegram_id, 7);
+        assert!(notice.text.contains("Итоги за май 2024"));
+        assert!(notice.text.contains(&format!("Оплачено: {}", Money::from_cents(2599))));
+        assert!(!notice.text.contains("баллов"));
+
+        assert_eq!(parse_callback(Some("summary:2024-05")), Some(date(2024, 5, 1)));
+        assert_eq!(parse_callback(Some("summary:2024-13")), None);
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use teloxide::types::ChatId;
+
+use crate::{config::env_opt, database::Db};
+
+// Settings key prefix mapping an upgraded group to its supergroup.
+const MIGRATION_KEY: &str = "chat_migrated";
+
+// Asia/Bishkek has stayed at UTC+6 without daylight saving since 2005.
+const BISHKEK_OFFSET_SECONDS: i32 = 6 * 60 * 60;
+
+/// Operator working hours in Bishkek time and the chat that gets pinged about new tickets.
+///
+/// The chat id changes when Telegram upgrades the group to a supergroup, the new id is shared
+/// between clones and kept in the settings table keyed by the old one.
+#[derive(Clone)]
+pub struct SupportDesk {
+    open: NaiveTime,
+    close: NaiveTime,
+    days: Vec<Weekday>,
+    holidays: Vec<NaiveDate>,
+    operator_chat: Arc<RwLock<Option<ChatId>>>
+}
+
+impl SupportDesk {
+    pub fn from_env() -> SupportDesk {
+        let hours = env_opt("SUPPORT_HOURS").unwrap_or("09:00-18:00".to_string());
+        let (open, close) = hours.split_once('-').expect("ERROR: Could not parse SUPPORT_HOURS");
+
+        SupportDesk {
+            open: parse_time(open).expect("ERROR: Could not parse SUPPORT_HOURS"),
+            close: parse_time(close).expect("ERROR: Could not parse SUPPORT_HOURS"),
+            days: env_opt("SUPPORT_DAYS").unwrap_or("mon,tue,wed,thu,fri,sat".to_string())
+                .split(',')
+                .map(|day| day.trim().parse().expect("ERROR: Could not parse SUPPORT_DAYS"))
+                .collect(),
+            holidays: env_opt("SUPPORT_HOLIDAYS").unwrap_or_default()
+                .split(',')
+                .filter(|date| !date.trim().is_empty())
+                .map(|date| date.trim().parse().expect("ERROR: Could not parse SUPPORT_HOLIDAYS"))
+                .collect(),
+            operator_chat: Arc::new(RwLock::new(env_opt("SUPPORT_CHAT_ID")
+                .map(|id| ChatId(id.parse().expect("ERROR: Could not parse SUPPORT_CHAT_ID")))))
+        }
+    }
+
+    pub fn operator_chat(&self) -> Option<ChatId> {
+        *self.operator_chat.read().unwrap()
+    }
+
+    /// Follows a migration saved while SUPPORT_CHAT_ID still pointed at the old group.
+    pub async fn load_migrations(&self, db: &Db) {
+        let chat_id = match self.operator_chat() {
+            Some(chat_id) => chat_id,
+            None => return
+        };
+
+        if let Some(migrated) = migrated_chat(db, chat_id).await {
+            log::info!("Operator chat {} was migrated to {}, update SUPPORT_CHAT_ID", chat_id, migrated);
+            *self.operator_chat.write().unwrap() = Some(migrated);
+        }
+    }
+
+    /// Remembers that a group became a supergroup and switches the operator chat if it was that group.
+    pub async fn migrate(&self, db: &Db, from: ChatId, to: ChatId) {
+        db.set_setting(&format!("{}:{}", MIGRATION_KEY, from), &to.to_string()).await;
+
+        let mut operator_chat = self.operator_chat.write().unwrap();
+
+        if *operator_chat == Some(from) {
+            log::info!("Operator chat migrated from {} to {}", from, to);
+            *operator_chat = Some(to);
+        }
+    }
+
+    pub fn is_open(&self, at: NaiveDateTime) -> bool {
+        self.is_working_day(at.date()) && at.time() >= self.open && at.time() < self.close
+    }
+
+    /// The moment operators are next at work, `at` itself when they already are.
+    pub fn next_opening(&self, at: NaiveDateTime) -> NaiveDateTime {
+        if self.is_open(at) {
+            return at;
+        }
+
+        let mut date = at.date();
+
+        if at.time() >= self.open {
+            date += Duration::days(1);
+        }
+
+        // A year is plenty even for a long holiday list; past that the schedule is broken.
+        for _ in 0..366 {
+            if self.is_working_day(date) {
+                return date.and_time(self.open);
+            }
+
+            date += Duration::days(1);
+        }
+
+        panic!("ERROR: Support desk has no working days");
+    }
+
+    pub fn schedule_text(&self) -> String {
+        let days = self.days.iter().map(|day| weekday_name(*day)).collect::<Vec<_>>().join(", ");
+
+        format!("{}–{} ({}), время бишкекское", self.open.format("%H:%M"), self.close.format("%H:%M"), days)
+    }
+
+    fn is_working_day(&self, date: NaiveDate) -> bool {
+        self.days.contains(&date.weekday()) && !self.holidays.contains(&date)
+    }
+}
+
+async fn migrated_chat(db: &Db, chat_id: ChatId) -> Option<ChatId> {
+    db.get_setting(&format!("{}:{}", MIGRATION_KEY, chat_id)).await
+        .map(|id| ChatId(id.parse().expect("ERROR: Could not parse a migrated chat id")))
+}
+
+pub fn bishkek_now() -> NaiveDateTime {
+    let offset = FixedOffset::east_opt(BISHKEK_OFFSET_SECONDS).unwrap();
+
+    Utc::now().with_timezone(&offset).naive_local()
+}
+
+fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "пн",
+        Weekday::Tue => "вт",
+        Weekday::Wed => "ср",
+        Weekday::Thu => "чт",
+        Weekday::Fri => "пт",
+        Weekday::Sat => "сб",
+        Weekday::Sun => "вс"
+    }
+}
+
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desk() -> SupportDesk {
+        SupportDesk {
+            open: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            close: NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            days: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat],
+            holidays: vec![NaiveDate::from_ymd_opt(2024, 3, 8).unwrap()],
+            operator_chat: Arc::default()
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn open_within_hours_on_working_days() {
+        // 2024-03-06 is a Wednesday.
+        assert!(desk().is_open(at(6, 9, 0)));
+        assert!(desk().is_open(at(6, 17, 59)));
+        assert!(!desk().is_open(at(6, 18, 0)));
+        assert!(!desk().is_open(at(6, 8, 59)));
+    }
+
+    #[test]
+    fn closed_on_days_off_and_holidays() {
+        assert!(!desk().is_open(at(10, 12, 0)));
+        assert!(!desk().is_open(at(8, 12, 0)));
+    }
+
+    #[tokio::test]
+    async fn migration_outlives_a_restart() {
+        let db = Db::in_memory();
+        let running = desk();
+
+        *running.operator_chat.write().unwrap() = Some(ChatId(-100));
+
+        // Some other group the bot is in.
+        running.migrate(&db, ChatId(-200), ChatId(-1002)).await;
+        assert_eq!(running.operator_chat(), Some(ChatId(-100)));
+
+        running.migrate(&db, ChatId(-100), ChatId(-1001)).await;
+        assert_eq!(running.operator_chat(), Some(ChatId(-1001)));
+
+        // SUPPORT_CHAT_ID still names the old group after a restart.
+        let restarted = desk();
+
+        *restarted.operator_chat.write().unwrap() = Some(ChatId(-100));
+        restarted.load_migrations(&db).await;
+
+        assert_eq!(restarted.operator_chat(), Some(ChatId(-1001)));
+    }
+
+    #[test]
+    fn next_opening_skips_holidays_and_sundays() {
+        assert_eq!(desk().next_opening(at(6, 12, 0)), at(6, 12, 0));
+        assert_eq!(desk().next_opening(at(6, 7, 30)), at(6, 9, 0));
+        assert_eq!(desk().next_opening(at(7, 19, 0)), at(9, 9, 0));
+        assert_eq!(desk().next_opening(at(9, 18, 30)), at(11, 9, 0));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use reqwest::header::CONTENT_TYPE;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{config::env_opt, vendor::VendorResult};
+
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Statuses the warehouse is known to send, longer phrases first so they win over their parts.
+const PHRASES: &[(&str, &str)] = &[
+    ("单号不存在", "трек-код не найден"),
+    ("已出库", "отправлен со склада"),
+    ("待入库", "ожидается на складе"),
+    ("已签收", "получен"),
+    ("已揽收", "принят перевозчиком"),
+    ("已发货", "отправлен продавцом"),
+    ("运输中", "в пути"),
+    ("派送中", "доставляется на склад"),
+    ("问题件", "проблема с посылкой, обратитесь в поддержку"),
+    ("已退回", "возвращен продавцу"),
+    ("拒收", "склад отказался принять посылку"),
+    ("货架", "полка"),
+    ("重量", "вес"),
+    ("公斤", " кг")
+];
+
+// Messages that only repeat what the status code already says.
+const PLAIN: &[&str] = &["已入库", "未入库"];
+
+pub type Translation<'a> = Pin<Box<dyn Future<Output = VendorResult<String>> + Send + 'a>>;
+
+/// A machine translation service for what the dictionary does not know.
+pub trait MachineTranslator: Send + Sync {
+    fn translate<'a>(&'a self, text: &'a str) -> Translation<'a>;
+}
+
+/// Turns the Chinese `msg` of a warehouse status into a Russian line for users.
+///
+/// Known phrases come from a dictionary, the rest goes to a machine translator when one is set up.
+#[derive(Clone, Default)]
+pub struct StatusTranslator {
+    machine: Option<Arc<dyn MachineTranslator>>
+}
+
+impl StatusTranslator {
+    pub fn from_env() -> StatusTranslator {
+        let machine = env_opt("TRANSLATE_URL").map(|url| {
+            Arc::new(LibreTranslate { url, api_key: env_opt("TRANSLATE_API_KEY") }) as Arc<dyn MachineTranslator>
+        });
+
+        StatusTranslator { machine }
+    }
+
+    /// None when the message says nothing beyond the status code or could not be translated.
+    pub async fn translate(&self, msg: &str) -> Option<String> {
+        let msg = msg.trim();
+
+        if msg.is_empty() || PLAIN.contains(&msg) {
+            return None;
+        }
+
+        if let Some(text) = from_dictionary(msg) {
+            return Some(text);
+        }
+
+        match self.machine.as_ref()?.translate(msg).await {
+            Ok(text) => Some(text.trim().to_string()).filter(|text| !text.is_empty()),
+            Err(err) => {
+                log::error!("ERROR: Could not translate a warehouse status: {}", err);
+
+                None
+            }
+        }
+    }
+}
+
+/// None when anything Chinese is left after replacing the known phrases.
+fn from_dictionary(msg: &str) -> Option<String> {
+    let mut text = msg.replace('，', ", ").replace('：', ": ").replace('。', "");
+
+    for (chinese, russian) in PHRASES {
+        text = text.replace(chinese, russian);
+    }
+
+    if text.chars().any(is_chinese) {
+        return None;
+    }
+
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+fn is_chinese(c: char) -> bool {
+    ('\u{4e00}'..='\u{9fff}').contains(&c) || ('\u{3000}'..='\u{303f}').contains(&c) || ('\u{ff00}'..='\u{ffef}').contains(&c)
+}
+
+/// A LibreTranslate compatible API, self-hosted or a paid mirror.
+struct LibreTranslate {
+    url: String,
+    api_key: Option<String>
+}
+
+#[derive(Deserialize)]
+struct Translated {
+    #[serde(rename = "translatedText")]
+    translated_text: String
+}
+
+impl MachineTranslator for LibreTranslate {
+    fn translate<'a>(&'a self, text: &'a str) -> Translation<'a> {
+        Box::pin(async move {
+            let body = json!({ "q": text, "source": "zh", "target": "ru", "format": "text", "api_key": self.api_key });
+
+            let response = reqwest::Client::new()
+                .post(format!("{}/translate", self.url.trim_end_matches('/')))
+                .timeout(TRANSLATE_TIMEOUT)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+
+            let translated: Translated = serde_json::from_str(&response)?;
+
+            Ok(translated.translated_text)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl MachineTranslator for Echo {
+        fn translate<'a>(&'a self, text: &'a str) -> Translation<'a> {
+            Box::pin(async move { Ok(format!("перевод: {}", text)) })
+        }
+    }
+
+    #[test]
+    fn dictionary_translates_known_phrases() {
+        assert_eq!(from_dictionary("已出库，重量：2.5公斤").as_deref(), Some("отправлен со склада, вес: 2.5 кг"));
+        assert_eq!(from_dictionary("问题件 A3").as_deref(), Some("проблема с посылкой, обратитесь в поддержку A3"));
+        assert_eq!(from_dictionary("包裹破损"), None);
+    }
+
+    #[tokio::test]
+    async fn plain_and_unknown_messages_are_not_shown() {
+        let translator = StatusTranslator::default();
+
+        assert_eq!(translator.translate("已入库").await, None);
+        assert_eq!(translator.translate("包裹破损").await, None);
+        assert_eq!(translator.translate("已签收").await.as_deref(), Some("получен"));
+    }
+
+    #[tokio::test]
+    async fn unknown_messages_go_to_the_machine() {
+        let translator = StatusTranslator { machine: Some(Arc::new(Echo)) };
+
+        assert_eq!(translator.translate("包裹破损").await.as_deref(), Some("перевод: 包裹破损"));
+        assert_eq!(translator.translate("已签收").await.as_deref(), Some("получен"));
+    }
+}
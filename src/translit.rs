@@ -0,0 +1,70 @@
+// Close to the passport romanization of Kyrgyzstan, with no letters a Chinese waybill form rejects.
+fn latin_letter(c: char) -> Option<&'static str> {
+    let latin = match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'е' => "e", 'ё' => "yo",
+        'ж' => "zh", 'з' => "z", 'и' => "i", 'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m",
+        'н' => "n", 'ң' => "ng", 'о' => "o", 'ө' => "o", 'п' => "p", 'р' => "r", 'с' => "s",
+        'т' => "t", 'у' => "u", 'ү' => "u", 'ф' => "f", 'х' => "kh", 'ц' => "ts", 'ч' => "ch",
+        'ш' => "sh", 'щ' => "shch", 'ъ' => "", 'ы' => "y", 'ь' => "", 'э' => "e", 'ю' => "yu",
+        'я' => "ya",
+        _ => return None
+    };
+
+    Some(latin)
+}
+
+/// Spells a Russian or Kyrgyz name in plain ASCII letters, e.g. `Айбек Өмүрбеков` as `Aybek Omurbekov`.
+///
+/// Latin letters are kept, anything else but spaces and hyphens is dropped.
+pub fn latin(name: &str) -> String {
+    let mut words = Vec::new();
+
+    for word in name.split_whitespace() {
+        let mut latin = String::new();
+
+        for c in word.chars() {
+            let lower = c.to_lowercase().next().unwrap_or(c);
+
+            let letters = match latin_letter(lower) {
+                Some(letters) => letters.to_string(),
+                None if c.is_ascii_alphabetic() || c == '-' => c.to_string(),
+                None => continue
+            };
+
+            // Only the first letter of a word or a hyphenated part is capital.
+            if c.is_uppercase() && !latin.chars().last().is_some_and(|c| c.is_ascii_alphabetic()) {
+                let mut letters = letters.chars();
+
+                latin.extend(letters.next().map(|c| c.to_ascii_uppercase()));
+                latin.extend(letters);
+            } else {
+                latin.push_str(&letters);
+            }
+        }
+
+        if !latin.is_empty() {
+            words.push(latin);
+        }
+    }
+
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn russian_and_kyrgyz_names() {
+        assert_eq!(latin("Айбек Өмүрбеков"), "Aybek Omurbekov");
+        assert_eq!(latin("Жаңыл Мамытова"), "Zhangyl Mamytova");
+        assert_eq!(latin("Юлия Щукина-Эргешова"), "Yuliya Shchukina-Ergeshova");
+        assert_eq!(latin("ШАХЗОД"), "Shakhzod");
+    }
+
+    #[test]
+    fn latin_is_kept_and_the_rest_dropped() {
+        assert_eq!(latin("John  Smith"), "John Smith");
+        assert_eq!(latin("Айбек 🙂 Ъ"), "Aybek");
+    }
+}
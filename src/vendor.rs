@@ -1,17 +1,149 @@
-use crate::models::ProductStatus;
+use std::{collections::HashMap, num::NonZeroU32, sync::{Arc, Mutex, OnceLock}};
 
-pub async fn product_ready(track_code: &str) -> bool {
-    let url: String = "http://www.107kapro.cn/index/index/search?no=".to_string() + track_code;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
 
-    let response: String = reqwest::get(url)
-        .await.expect("ERROR: Could not reach an api")
-        .text()
-        .await.expect("ERROR: Could not get the text from an api");
+use crate::{config::AppEnv, correlation, database::Db, models::ProductStatus, watchdog};
+
+// Enough of an unreadable answer to see what changed, an HTML error page can be much longer.
+const MAX_ERROR_BODY: usize = 4000;
+
+// The warehouse budget, for VENDOR_BASE_URL while the providers table is empty.
+const DEFAULT_REQUESTS_PER_SECOND: i32 = 5;
+
+pub type VendorResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+static BASE_URL: OnceLock<String> = OnceLock::new();
+
+// One limiter per source name, shared by the notifier, reconciliation and lookups from chats.
+static LIMITERS: OnceLock<Mutex<HashMap<String, Budget>>> = OnceLock::new();
+
+struct Budget {
+    requests_per_second: i32,
+    limiter: Arc<DefaultDirectRateLimiter>
+}
+
+/// A tracking service answering like the warehouse API, see the providers table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Source {
+    pub name: String,
+    pub base_url: String,
+    pub requests_per_second: i32
+}
+
+impl Source {
+    /// The limiter for this source, a new one when its budget was changed in the providers table.
+    fn limiter(&self) -> Arc<DefaultDirectRateLimiter> {
+        let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap();
+
+        match limiters.get(&self.name) {
+            Some(budget) if budget.requests_per_second == self.requests_per_second => budget.limiter.clone(),
+            _ => {
+                let rate = NonZeroU32::new(self.requests_per_second as u32).unwrap();
+                let limiter = Arc::new(RateLimiter::direct(Quota::per_second(rate)));
+
+                limiters.insert(self.name.clone(), Budget { requests_per_second: self.requests_per_second, limiter: limiter.clone() });
+
+                limiter
+            }
+        }
+    }
+}
+
+/// The warehouse answer, `fallback` when it did not come from the first source of the chain.
+pub struct Lookup {
+    pub status: ProductStatus,
+    pub source: String,
+    pub fallback: bool
+}
+
+/// The sources to try in order, only VENDOR_BASE_URL while the providers table is empty.
+pub fn chain(configured: Vec<Source>) -> Vec<Source> {
+    if !configured.is_empty() {
+        return configured;
+    }
+
+    vec![Source {
+        name: "107kapro".to_string(),
+        base_url: BASE_URL.get_or_init(|| AppEnv::current().vendor_base_url()).clone(),
+        requests_per_second: DEFAULT_REQUESTS_PER_SECOND
+    }]
+}
+
+pub async fn product_ready(db: &Db, sources: &[Source], track_code: &str) -> VendorResult<bool> {
+    Ok(lookup(db, sources, track_code).await?.status.ready())
+}
 
-    let product_status: ProductStatus = serde_json::from_str(&response).expect("ERROR: Could not deserialize an object");
+/// Asks the sources in turn until one knows the track code, `msg` of the answer is a Chinese status line.
+/// Every request waits for the budget of its source, fallbacks included.
+///
+/// When none does, a "not found" answer wins over an error, so a failing source does not hide it.
+/// Answers that cannot be read at all are kept in the vendor_errors table.
+pub async fn lookup(db: &Db, sources: &[Source], track_code: &str) -> VendorResult<Lookup> {
+    let mut outcome: Option<VendorResult<Lookup>> = None;
 
-    match product_status.code.as_str() {
-        "0000" => true,
-        _ => false
+    for (i, source) in sources.iter().enumerate() {
+        let result = request_status(db, source, track_code).await;
+
+        watchdog::record_vendor_call(result.is_ok());
+
+        match result {
+            Ok(status) if !status.not_found() => return Ok(Lookup { status, source: source.name.clone(), fallback: i > 0 }),
+            Ok(status) => {
+                log::info!("Vendor: {} is unknown to {}", track_code, source.name);
+
+                if !matches!(outcome, Some(Ok(_))) {
+                    outcome = Some(Ok(Lookup { status, source: source.name.clone(), fallback: i > 0 }));
+                }
+            },
+            Err(err) => {
+                log::warn!("Vendor: {} failed for {}: {}", source.name, track_code, err);
+
+                if outcome.is_none() {
+                    outcome = Some(Err(err));
+                }
+            }
+        }
+    }
+
+    outcome.unwrap_or_else(|| Err("no tracking providers".into()))
+}
+
+async fn request_status(db: &Db, source: &Source, track_code: &str) -> VendorResult<ProductStatus> {
+    let url = format!("{}/index/index/search?no={}", source.base_url.trim_end_matches('/'), track_code);
+
+    source.limiter().until_ready().await;
+
+    let mut request = reqwest::Client::new().get(url);
+
+    if let Some(id) = correlation::current() {
+        request = request.header("X-Request-Id", id.to_string());
     }
-}
\ No newline at end of file
+
+    let response: String = request.send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let product_status: ProductStatus = match serde_json::from_str(&response) {
+        Ok(product_status) => product_status,
+        Err(err) => {
+            let body: String = response.chars().take(MAX_ERROR_BODY).collect();
+
+            db.record_vendor_error(&source.name, track_code, &err.to_string(), &body).await;
+
+            return Err(err.into());
+        }
+    };
+
+    log::info!("Vendor: {} -> {} ({})", track_code, product_status.code, product_status.msg);
+
+    Ok(product_status)
+}
+
+/// Whether the provider itself is failing, as opposed to a bad track code or a network hiccup.
+pub fn is_server_error(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+        .is_some_and(|status| status.is_server_error())
+}
@@ -0,0 +1,219 @@
+use std::{collections::HashMap, sync::{atomic::{AtomicU32, Ordering}, Mutex}, time::Duration};
+
+use teloxide::{requests::Requester};
+use tokio::time::Instant;
+
+use crate::{config::{env_or, Config}, database::Db, retry::Bot, staff::Staff};
+
+// Counted since the previous check, so each check sees the error rate of its own interval.
+static VENDOR_CALLS: AtomicU32 = AtomicU32::new(0);
+static VENDOR_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+static LAST_UPDATE: Mutex<Option<Instant>> = Mutex::new(None);
+
+const DB_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// When the watchdog checks the bot and what counts as a failure.
+///
+/// The vendor is failing when at least `vendor_error_percent` of `vendor_min_calls` or more calls
+/// failed since the previous check. The dispatcher is stalled when Telegram holds updates for the bot
+/// and none was handled for `stall_minutes`.
+#[derive(Clone)]
+pub struct WatchdogPolicy {
+    pub interval: Duration,
+    pub vendor_error_percent: u32,
+    pub vendor_min_calls: u32,
+    pub stall_minutes: u64
+}
+
+impl WatchdogPolicy {
+    pub fn from_env() -> WatchdogPolicy {
+        WatchdogPolicy {
+            interval: Duration::from_secs(env_or("WATCHDOG_INTERVAL_MINUTES", 5) * 60),
+            vendor_error_percent: env_or("WATCHDOG_VENDOR_ERROR_PERCENT", 50),
+            vendor_min_calls: env_or("WATCHDOG_VENDOR_MIN_CALLS", 10),
+            stall_minutes: env_or("WATCHDOG_STALL_MINUTES", 10)
+        }
+    }
+
+    /// The diagnostics when too many vendor calls failed.
+    pub fn vendor_problem(&self, calls: u32, errors: u32) -> Option<String> {
+        if calls < self.vendor_min_calls || errors * 100 < calls * self.vendor_error_percent {
+            return None;
+        }
+
+        Some(format!("{} из {} запросов к складу завершились ошибкой", errors, calls))
+    }
+
+    /// The diagnostics when updates wait in Telegram but the dispatcher does not take them.
+    pub fn dispatcher_problem(&self, pending: u32, idle: Duration) -> Option<String> {
+        let idle_minutes = idle.as_secs() / 60;
+
+        if pending == 0 || idle_minutes < self.stall_minutes {
+            return None;
+        }
+
+        Some(format!("{} обновлений ждут в Telegram, последнее обработано {} мин назад", pending, idle_minutes))
+    }
+}
+
+/// Counts a warehouse request for the error rate.
+pub fn record_vendor_call(ok: bool) {
+    VENDOR_CALLS.fetch_add(1, Ordering::Relaxed);
+
+    if !ok {
+        VENDOR_ERRORS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Marks that the dispatcher has just handled an update.
+pub fn record_update() {
+    *LAST_UPDATE.lock().unwrap() = Some(Instant::now());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Database,
+    Vendor,
+    Dispatcher
+}
+
+impl Subsystem {
+    fn title(&self) -> &'static str {
+        match self {
+            Subsystem::Database => "База данных",
+            Subsystem::Vendor => "Склад в Китае",
+            Subsystem::Dispatcher => "Обработка сообщений"
+        }
+    }
+}
+
+/// Remembers which subsystems are failing, so admins hear once when one breaks and once when it recovers.
+#[derive(Default)]
+pub struct Alarms {
+    failing: HashMap<Subsystem, String>
+}
+
+impl Alarms {
+    /// The alert to send, if the subsystem has just started failing or has recovered.
+    pub fn update(&mut self, subsystem: Subsystem, problem: Option<String>) -> Option<String> {
+        match problem {
+            Some(problem) => match self.failing.insert(subsystem, problem.clone()) {
+                Some(_) => None,
+                None => Some(format!("🚨 {}: {}", subsystem.title(), problem))
+            },
+            None => self.failing.remove(&subsystem)
+                .map(|_| format!("✅ {}: снова работает", subsystem.title()))
+        }
+    }
+}
+
+/// Checks the database, the vendor and the dispatcher every `interval` and alerts admins.
+///
+/// Alerts go straight through the bot, not the outbox, so they get out while the database is down.
+pub struct Watchdog {
+    bot: Bot,
+    db: Db,
+    policy: WatchdogPolicy,
+    config: Config,
+    staff: Staff,
+    started: Instant,
+    alarms: Alarms
+}
+
+impl Watchdog {
+    /// Recipients are looked up for every alert, so staff who joined or left since the start are counted.
+    pub fn spawn(bot: Bot, db: Db, config: Config, staff: Staff) {
+        log::info!("Starting the watchdog");
+        let policy = config.watchdog.clone();
+
+        tokio::spawn(Watchdog { bot, db, policy, config, staff, started: Instant::now(), alarms: Alarms::default() }.run());
+    }
+
+    async fn run(mut self) {
+        let mut ticker = tokio::time::interval(self.policy.interval);
+
+        loop {
+            ticker.tick().await;
+            self.check().await;
+        }
+    }
+
+    async fn check(&mut self) {
+        let database = match tokio::time::timeout(DB_TIMEOUT, self.db.ping()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(err)) => Some(format!("нет соединения: {}", err)),
+            Err(_) => Some(format!("нет ответа за {} с", DB_TIMEOUT.as_secs()))
+        };
+
+        let vendor = self.policy.vendor_problem(VENDOR_CALLS.swap(0, Ordering::Relaxed), VENDOR_ERRORS.swap(0, Ordering::Relaxed));
+
+        let dispatcher = match self.bot.get_webhook_info().await {
+            Ok(info) => {
+                let last_update = LAST_UPDATE.lock().unwrap().unwrap_or(self.started);
+
+                self.policy.dispatcher_problem(info.pending_update_count, last_update.elapsed())
+            },
+            Err(err) => Some(format!("Telegram API недоступен: {}", err))
+        };
+
+        for (subsystem, problem) in [(Subsystem::Database, database), (Subsystem::Vendor, vendor), (Subsystem::Dispatcher, dispatcher)] {
+            if let Some(problem) = &problem {
+                log::warn!("Watchdog: {:?} is failing: {}", subsystem, problem);
+            }
+
+            if let Some(alert) = self.alarms.update(subsystem, problem) {
+                self.alert(&alert).await;
+            }
+        }
+    }
+
+    async fn alert(&self, text: &str) {
+        let chats = self.config.alert_chats(&self.staff.ids());
+
+        if chats.is_empty() {
+            log::warn!("Watchdog: ALERT_CHAT_ID is not set and there is no staff, nobody hears the alert: {}", text);
+        }
+
+        for chat_id in chats {
+            if let Err(err) = self.bot.send_message(chat_id, text).await {
+                log::error!("ERROR: Could not send a watchdog alert to {}: {}", chat_id, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> WatchdogPolicy {
+        WatchdogPolicy { interval: Duration::from_secs(300), vendor_error_percent: 50, vendor_min_calls: 10, stall_minutes: 10 }
+    }
+
+    #[test]
+    fn vendor_fails_past_the_error_rate() {
+        assert_eq!(policy().vendor_problem(5, 5), None);
+        assert_eq!(policy().vendor_problem(20, 9), None);
+        assert_eq!(policy().vendor_problem(20, 10).as_deref(), Some("10 из 20 запросов к складу завершились ошибкой"));
+    }
+
+    #[test]
+    fn dispatcher_stalls_only_with_pending_updates() {
+        assert_eq!(policy().dispatcher_problem(0, Duration::from_secs(3600)), None);
+        assert_eq!(policy().dispatcher_problem(3, Duration::from_secs(120)), None);
+        assert!(policy().dispatcher_problem(3, Duration::from_secs(900)).is_some());
+    }
+
+    #[test]
+    fn alarms_fire_once_and_on_recovery() {
+        let mut alarms = Alarms::default();
+
+        assert_eq!(alarms.update(Subsystem::Database, None), None);
+        assert_eq!(alarms.update(Subsystem::Database, Some("нет ответа".to_string())).as_deref(), Some("🚨 База данных: нет ответа"));
+        assert_eq!(alarms.update(Subsystem::Database, Some("нет ответа".to_string())), None);
+        assert_eq!(alarms.update(Subsystem::Vendor, None), None);
+        assert_eq!(alarms.update(Subsystem::Database, None).as_deref(), Some("✅ База данных: снова работает"));
+        assert_eq!(alarms.update(Subsystem::Database, None), None);
+    }
+}